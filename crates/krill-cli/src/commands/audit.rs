@@ -0,0 +1,71 @@
+// krill audit - Show the control commands clients sent to the daemon
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{AuditOutcome, ClientMessage, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct AuditArgs {
+    /// Only show commands from this long ago until now (e.g. 30s, 10m, 2h)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub since: Option<Duration>,
+
+    /// Only show commands targeting this service
+    #[arg(long, value_name = "SERVICE")]
+    pub service: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: AuditArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let request = ClientMessage::GetAuditLog {
+        since_secs: args.since.map(|since| since.as_secs()),
+        service: args.service,
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::AuditLog { records } => {
+            if records.is_empty() {
+                println!("No commands");
+            }
+            for record in records {
+                let outcome = match record.outcome {
+                    AuditOutcome::Accepted => "accepted".to_string(),
+                    AuditOutcome::Rejected { code, message } => {
+                        format!("rejected ({}): {}", code, message)
+                    }
+                    AuditOutcome::Scheduled { at } => {
+                        format!("scheduled for {}", at.format("%Y-%m-%d %H:%M:%S"))
+                    }
+                };
+                println!(
+                    "{}  {:<18} {:<24} {:<32} {}",
+                    record.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    format!("{:?}", record.action),
+                    record.target.as_deref().unwrap_or("-"),
+                    record.client.to_string(),
+                    outcome
+                );
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => {
+            Err(anyhow!("Failed to get audit log: {}", message))
+        }
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
@@ -1,45 +1,168 @@
 // krill daemon - Run the daemon directly (used internally)
 
 use anyhow::Result;
-use krill_common::KrillConfig;
+use krill_common::{KrillConfig, KrillError, SOCKET_ENV};
+use krill_daemon::audit::{self, AuditLog};
+use krill_daemon::bundle::{self, SessionMetadata};
+use krill_daemon::daemon_log::{self, DaemonLogOptions};
+#[cfg(unix)]
+use krill_daemon::daemonize::{self, PidFile};
+use krill_daemon::events::{self, EventJournal};
+use krill_daemon::gateway::{self, HttpGateway};
+use krill_daemon::handoff;
+use krill_daemon::ipc_server::{ReloadRequest, SNAPSHOT_DELTA_INTERVAL};
+use krill_daemon::logging::BufferLimits;
+use krill_daemon::metrics::METRICS_INTERVAL;
+use krill_daemon::orchestrator;
+use krill_daemon::reaper;
+use krill_daemon::scheduler;
+use krill_daemon::watch;
+use krill_daemon::watchdog::{self, SystemdNotifier};
 use krill_daemon::{
-    ErrorCategory, IpcServer, LogStore, Orchestrator, StartupError, StartupMessage,
+    AccessPolicy, ErrorCategory, IpcServer, Liveness, LogStore, Orchestrator, PrometheusExporter,
+    Scheduler, StartupError, StartupMessage, StateFile, Workspaces,
 };
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 
+use super::upgrade::{self, UpgradeArgs};
+
 #[derive(clap::Args, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct DaemonArgs {
+    #[command(subcommand)]
+    pub command: Option<DaemonCommand>,
+
     /// Path to configuration file
-    #[arg(short, long, value_name = "FILE")]
-    pub config: PathBuf,
+    #[arg(short, long, value_name = "FILE", required = true)]
+    pub config: Option<PathBuf>,
 
     /// Log directory (overrides config)
     #[arg(long, value_name = "DIR")]
     pub log_dir: Option<PathBuf>,
 
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    pub socket: PathBuf,
-
-    // File descriptor for startup error communication
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// recipe's `socket`, else one named after the workspace)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+
+    /// Also accept IPC clients over TCP (e.g. 127.0.0.1:7777)
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<SocketAddr>,
+
+    /// Only start this service group (and its dependencies)
+    #[arg(long, value_name = "GROUP")]
+    pub group: Option<String>,
+
+    /// Serve the HTTP/JSON gateway on this address (e.g. 0.0.0.0:8080)
+    #[arg(long, value_name = "ADDR", requires = "http_token_file")]
+    pub http: Option<SocketAddr>,
+
+    /// File holding the bearer token required by the HTTP gateway
+    #[arg(long, value_name = "FILE")]
+    pub http_token_file: Option<PathBuf>,
+
+    /// Also host the workspace of this recipe; its services are addressed as
+    /// `workspace/service` (repeatable)
+    #[arg(long = "workspace", value_name = "FILE")]
+    pub workspaces: Vec<PathBuf>,
+
+    /// Reload the configuration whenever its file, an included file or a
+    /// service's env file or file secret changes
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Detach from the terminal into a session of its own, with output in
+    /// the session's krill.log and a locked PID file (the default stays in
+    /// the foreground, as systemd expects)
+    #[arg(long)]
+    pub daemonize: bool,
+
+    /// Take over the services of the daemon handing them over on this socket
+    /// (started by `upgrade_daemon`)
+    #[arg(long, hide = true, value_name = "SOCKET")]
+    pub handoff: Option<PathBuf>,
+
+    // File descriptor (handle on Windows) for startup error communication
     #[arg(long, hide = true)]
-    pub startup_pipe_fd: Option<i32>,
+    pub startup_pipe_fd: Option<i64>,
+
+    /// Connection to systemd, read before the runtime starts
+    #[arg(skip)]
+    pub notifier: SystemdNotifier,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum DaemonCommand {
+    /// Replace the running daemon with a new binary, handing the services
+    /// over without restarting them
+    Upgrade(UpgradeArgs),
+}
+
+/// With `--daemonize`, leave the session of the terminal that started the
+/// daemon. Runs before the tokio runtime is built, as forking is only safe
+/// while the process is single-threaded.
+pub fn detach(args: &DaemonArgs) -> Result<()> {
+    // A daemon taking over in a handoff already runs in a session of its own
+    if !args.daemonize || args.handoff.is_some() || args.command.is_some() {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use anyhow::Context;
+        krill_daemon::daemonize::detach().context("Failed to detach from the terminal")
+    }
+
+    #[cfg(not(unix))]
+    anyhow::bail!("--daemonize is only supported on Unix")
+}
+
+/// Tell the services, and the SDK clients they embed, where the daemon
+/// listens through KRILL_SOCKET. Runs before the tokio runtime is built, as
+/// the environment may only change while the process is single-threaded; a
+/// recipe that does not load is reported by `execute`.
+pub fn export_socket(args: &DaemonArgs) {
+    if args.command.is_some() {
+        return;
+    }
+    let socket = match (&args.socket, &args.config) {
+        (Some(socket), _) => Some(socket.clone()),
+        (None, Some(config)) => KrillConfig::from_file(config)
+            .and_then(|config| config.socket_path())
+            .ok(),
+        (None, None) => None,
+    };
+    if let Some(socket) = socket {
+        std::env::set_var(SOCKET_ENV, socket);
+    }
 }
 
 pub async fn execute(args: DaemonArgs) -> Result<()> {
-    info!("Pre-flight krill-daemon checks");
+    if let Some(DaemonCommand::Upgrade(args)) = args.command {
+        return upgrade::execute(args).await;
+    }
+    let config_path = args.config.clone().expect("clap requires --config");
+    let notifier = args.notifier.clone();
+
     use std::io::Write;
-    use std::os::fd::FromRawFd;
 
     // Open startup pipe if provided
-    let mut startup_pipe = args
-        .startup_pipe_fd
-        .map(|fd| unsafe { std::fs::File::from_raw_fd(fd) });
+    #[cfg(unix)]
+    let mut startup_pipe = args.startup_pipe_fd.map(|fd| {
+        use std::os::fd::FromRawFd;
+        unsafe { std::fs::File::from_raw_fd(fd as i32) }
+    });
+    #[cfg(windows)]
+    let mut startup_pipe = args.startup_pipe_fd.map(|handle| {
+        use std::os::windows::io::FromRawHandle;
+        unsafe { std::fs::File::from_raw_handle(handle as std::os::windows::io::RawHandle) }
+    });
 
     // Helper to send error and exit
     let send_error = |pipe: &mut Option<std::fs::File>, category, message, path, hint| {
@@ -60,16 +183,33 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
         std::process::exit(1);
     };
 
+    // Tracing comes first, so loading the recipe is logged; krill.log and
+    // the recipe's filter follow once the session directory is known
+    let daemon_log = match daemon_log::init(DaemonLogOptions::default()) {
+        Ok(daemon_log) => daemon_log,
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::LogStore,
+                format!("Failed to initialize tracing: {}", e),
+                None,
+                "Check that RUST_LOG holds a valid filter".to_string(),
+            );
+            unreachable!();
+        }
+    };
+    info!("Pre-flight krill-daemon checks");
+
     // Load configuration
-    info!("Loading configuration from {:?}", args.config);
-    let config = match KrillConfig::from_file(&args.config) {
+    info!("Loading configuration from {:?}", config_path);
+    let config = match KrillConfig::from_file(&config_path) {
         Ok(c) => c,
         Err(e) => {
             send_error(
                 &mut startup_pipe,
                 ErrorCategory::Config,
                 format!("Failed to load configuration: {}", e),
-                Some(args.config.clone()),
+                Some(config_path.clone()),
                 "Check that the file exists, is valid Yaml, and you have read permissions"
                     .to_string(),
             );
@@ -80,9 +220,99 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
     info!("Loaded workspace: {}", config.name);
     info!("Services: {}", config.services.len());
 
+    // Workspaces hosted next to this one
+    let mut hosted_configs = Vec::new();
+    for path in &args.workspaces {
+        match KrillConfig::from_file(path) {
+            Ok(hosted) => hosted_configs.push((path.clone(), hosted)),
+            Err(e) => {
+                send_error(
+                    &mut startup_pipe,
+                    ErrorCategory::Config,
+                    format!("Failed to load workspace: {}", e),
+                    Some(path.clone()),
+                    "Check that the file exists, is valid Yaml, and you have read permissions"
+                        .to_string(),
+                );
+                unreachable!();
+            }
+        }
+    }
+
+    let socket = match args
+        .socket
+        .clone()
+        .map(Ok)
+        .unwrap_or_else(|| config.socket_path())
+    {
+        Ok(socket) => socket,
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Config,
+                format!("Failed to load configuration: {}", e),
+                Some(config_path.clone()),
+                "Rename the workspace, or give its socket with 'socket:' or --socket".to_string(),
+            );
+            unreachable!();
+        }
+    };
+
+    if let Some(ref group) = args.group {
+        if !config.groups.contains_key(group) {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Config,
+                format!("Unknown service group '{}'", group),
+                Some(config_path.clone()),
+                "Define the group under 'groups:' in the recipe".to_string(),
+            );
+            unreachable!();
+        }
+    }
+
+    // A second daemon of the workspace fails here; one taking over in a
+    // handoff gets the PID file once the previous daemon exited, below
+    #[cfg(unix)]
+    let pid_path = match PidFile::default_path(&config.name) {
+        Ok(path) => path,
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Config,
+                format!("Failed to load configuration: {}", e),
+                Some(config_path.clone()),
+                "Rename the workspace".to_string(),
+            );
+            unreachable!();
+        }
+    };
+    #[cfg(unix)]
+    let pid_file = if args.daemonize && args.handoff.is_none() {
+        match PidFile::acquire(&pid_path) {
+            Ok(pid_file) => Some(pid_file),
+            Err(e) => {
+                send_error(
+                    &mut startup_pipe,
+                    ErrorCategory::Orchestrator,
+                    e.to_string(),
+                    Some(pid_path.clone()),
+                    "Attach with 'krill ps', or stop it with 'krill down'".to_string(),
+                );
+                unreachable!();
+            }
+        }
+    } else {
+        None
+    };
+
     // Initialize log store
     let log_dir = args.log_dir.or(config.log_dir.clone());
-    let log_store = match LogStore::new(log_dir.clone()) {
+    let log_store = match LogStore::with_format(
+        log_dir.clone(),
+        config.log_rotation.clone(),
+        config.log_format,
+    ) {
         Ok(ls) => ls,
         Err(e) => {
             send_error(
@@ -98,19 +328,63 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
 
     info!("Logs directory: {:?}", log_store.session_dir());
 
-    if let Err(e) = init_daemon_tracing(&log_store) {
-        send_error(
-            &mut startup_pipe,
-            ErrorCategory::LogStore,
-            format!("Failed to initialize tracing: {}", e),
-            Some(log_store.session_dir().join("krill.log")),
-            "Check that the log directory is writable".to_string(),
-        );
-        unreachable!();
+    // Keep the recipe and who ran it with the logs, for `krill logs --export`
+    let metadata = SessionMetadata::current(&config.name, &config_path);
+    if let Err(e) = bundle::record_session(log_store.session_dir(), &metadata) {
+        warn!("Failed to record session metadata: {}", e);
     }
+    log_store
+        .set_buffer_limits(BufferLimits::from_config(&config))
+        .await;
+
+    let log_filter = match daemon_log.open(&config.daemon_log, log_store.session_dir()) {
+        Ok(handle) => Arc::new(handle),
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::LogStore,
+                format!("Failed to initialize tracing: {}", e),
+                Some(log_store.session_dir().join("krill.log")),
+                "Check that the log directory is writable and `daemon_log` is valid".to_string(),
+            );
+            unreachable!();
+        }
+    };
     info!("Krill daemon starting");
     info!("Workspace: {}", config.name);
 
+    // Panics and anything else printed land in krill.log, not a closed terminal
+    #[cfg(unix)]
+    if args.daemonize {
+        if let Err(e) = daemonize::redirect_output(&log_store.session_dir().join("krill.log")) {
+            warn!("Failed to redirect output to krill.log: {}", e);
+        }
+    }
+
+    // Record status changes for `krill events`
+    let journal_path = EventJournal::session_path(log_store.session_dir());
+    let event_journal = Arc::new(
+        EventJournal::with_file(events::MAX_EVENTS, &journal_path).unwrap_or_else(|e| {
+            warn!("Failed to open event journal {:?}: {}", journal_path, e);
+            EventJournal::new(events::MAX_EVENTS)
+        }),
+    );
+
+    // Record control commands and who sent them for `krill audit`
+    let audit_path = AuditLog::session_path(log_store.session_dir());
+    let audit_log = Arc::new(
+        AuditLog::with_file(audit::MAX_RECORDS, &audit_path).unwrap_or_else(|e| {
+            warn!("Failed to open audit log {:?}: {}", audit_path, e);
+            AuditLog::new(audit::MAX_RECORDS)
+        }),
+    );
+
+    // Processes orphaned inside a service are reparented to the daemon,
+    // which ends them along with the service and reaps those it does not track
+    if reaper::enable_subreaper() {
+        tokio::spawn(reaper::reap_orphans());
+    }
+
     // Create event channel
     let (event_tx, mut event_rx) = mpsc::unbounded_channel();
 
@@ -128,9 +402,68 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
     // Create heartbeat channel
     let (heartbeat_tx, mut heartbeat_rx) = mpsc::unbounded_channel();
 
+    // Create config reload request channel
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel::<ReloadRequest>();
+
+    let prometheus = config.prometheus.clone();
+    let log_throttle = config.log_throttle.clone();
+    let access = match AccessPolicy::from_config(&config.access) {
+        Ok(access) => access,
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Config,
+                e.to_string(),
+                Some(config_path.clone()),
+                "Check that the users and groups under `access` exist on this machine".to_string(),
+            );
+            unreachable!();
+        }
+    };
+    let workspace_name = config.name.clone();
+
+    // Processes left behind by a crashed daemon of this workspace
+    let state_file = StateFile::new(StateFile::default_path(&config.name));
+    let previous_state = state_file.load().unwrap_or_else(|e| {
+        warn!(
+            "Ignoring unreadable state file {:?}: {}",
+            state_file.path(),
+            e
+        );
+        None
+    });
+
+    // Fail now rather than let services crash-loop on ports held elsewhere;
+    // handed over services keep theirs
+    if args.handoff.is_none() {
+        if let Some((service, conflict)) = orchestrator::startup_port_conflict(
+            &config,
+            args.group.as_deref(),
+            previous_state.as_ref(),
+        ) {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Orchestrator,
+                format!("Service '{}' cannot start: required {}", service, conflict),
+                Some(config_path.clone()),
+                "Stop the process holding the port, or change the service's requires_ports"
+                    .to_string(),
+            );
+            unreachable!();
+        }
+    }
+
+    // Maintenance actions: the recipes' `schedule:` and one-shot requests
+    let (maintenance_tx, mut maintenance_rx) = mpsc::unbounded_channel();
+    let scheduler = Arc::new(Scheduler::new(maintenance_tx));
+    scheduler.set_recipe(None, &config);
+
+    // Restart storms pausing the automatic restarts of a workspace
+    let (storm_tx, mut storm_rx) = mpsc::unbounded_channel();
+
     // Create orchestrator with log channel
-    let orchestrator = Arc::new(
-        match Orchestrator::with_log_tx(config, event_tx.clone(), Some(log_tx)) {
+    let mut orchestrator =
+        match Orchestrator::with_log_tx(config, event_tx.clone(), Some(log_tx.clone())) {
             Ok(o) => o,
             Err(e) => {
                 send_error(
@@ -142,31 +475,157 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
                 );
                 unreachable!();
             }
+        };
+    orchestrator.set_state_file(state_file);
+    orchestrator.set_storm_tx(storm_tx.clone());
+    let orchestrator = Arc::new(orchestrator);
+
+    let mut workspaces = Workspaces::new(workspace_name, Arc::clone(&orchestrator));
+    workspaces.set_scheduler(Arc::clone(&scheduler));
+    workspaces.set_storm_tx(storm_tx);
+    for (path, hosted) in hosted_configs {
+        if let Err(e) = workspaces.add(hosted, event_tx.clone(), Some(log_tx.clone())) {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::Orchestrator,
+                format!("Failed to host workspace: {}", e),
+                Some(path),
+                "Give every hosted workspace a distinct name".to_string(),
+            );
+            unreachable!();
+        }
+    }
+    let workspaces = Arc::new(workspaces);
+
+    // Services handed over by the daemon this one replaces; it exits once
+    // they are, freeing the socket and ports bound below
+    let handoff = match args.handoff {
+        Some(ref path) => match handoff::receive(path).await {
+            Ok(table) => Some(table),
+            Err(e) => {
+                error!("Failed to take over from the previous daemon: {}", e);
+                send_error(
+                    &mut startup_pipe,
+                    ErrorCategory::IpcServer,
+                    format!("Failed to take over from the previous daemon: {}", e),
+                    Some(path.clone()),
+                    "The previous daemon keeps running; see its krill.log".to_string(),
+                );
+                unreachable!();
+            }
         },
-    );
+        None => None,
+    };
+
+    // The previous daemon released the PID file as it exited; held until this one does
+    #[cfg(unix)]
+    let _pid_file = match pid_file {
+        None if args.daemonize => PidFile::take_over(&pid_path).await,
+        pid_file => pid_file,
+    };
+
+    let gateway_command_tx = command_tx.clone();
+    let prometheus_snapshot_req_tx = snapshot_req_tx.clone();
+    let gateway_snapshot_req_tx = snapshot_req_tx.clone();
+    let watchdog_snapshot_req_tx = snapshot_req_tx.clone();
+
+    // Self-heartbeat for the systemd watchdog and the /healthz endpoints
+    let liveness = Arc::new(Liveness::new(watchdog::heartbeat_interval(&notifier)));
 
     // Create IPC server with heartbeat channel and log store
-    let ipc_server = Arc::new(
-        match IpcServer::with_heartbeat_tx(
-            args.socket.clone(),
-            command_tx,
-            snapshot_req_tx,
-            Some(heartbeat_tx),
-            Some(Arc::clone(&log_store)),
-        ) {
-            Ok(is) => is,
+    let mut ipc_server = match IpcServer::with_heartbeat_tx(
+        socket.clone(),
+        command_tx,
+        snapshot_req_tx,
+        Some(heartbeat_tx),
+        Some(Arc::clone(&log_store)),
+    ) {
+        Ok(is) => is,
+        Err(e) => {
+            send_error(
+                &mut startup_pipe,
+                ErrorCategory::IpcServer,
+                format!("Failed to initialize IPC server: {}", e),
+                None,
+                "Check if you have permission to create IPC Server".to_string(),
+            );
+            unreachable!();
+        }
+    };
+    ipc_server.set_reload_tx(reload_tx.clone());
+    ipc_server.set_event_journal(Arc::clone(&event_journal));
+    ipc_server.set_audit_log(Arc::clone(&audit_log));
+    ipc_server.set_log_filter(log_filter);
+    ipc_server.set_access_policy(access);
+    ipc_server.set_scheduler(Arc::clone(&scheduler));
+    ipc_server.log_throttle().set_config(log_throttle);
+    if let Some(addr) = args.listen {
+        ipc_server.set_tcp_listen(addr);
+    }
+    let ipc_server = Arc::new(ipc_server);
+
+    // Bind the HTTP gateway before reporting success so address errors reach `krill up`
+    let gateway = match (args.http, args.http_token_file.as_deref()) {
+        (Some(addr), Some(token_file)) => {
+            let token = match gateway::load_token(token_file) {
+                Ok(t) => t,
+                Err(e) => {
+                    send_error(
+                        &mut startup_pipe,
+                        ErrorCategory::Gateway,
+                        format!("Failed to load HTTP gateway token: {}", e),
+                        Some(token_file.to_path_buf()),
+                        "Write a non-empty token to the file and make it readable".to_string(),
+                    );
+                    unreachable!();
+                }
+            };
+            let listener = match tokio::net::TcpListener::bind(addr).await {
+                Ok(l) => l,
+                Err(e) => {
+                    send_error(
+                        &mut startup_pipe,
+                        ErrorCategory::Gateway,
+                        format!("Failed to bind HTTP gateway on {}: {}", addr, e),
+                        None,
+                        "Check that the address is free and you may bind it".to_string(),
+                    );
+                    unreachable!();
+                }
+            };
+            let mut gateway = HttpGateway::new(
+                token,
+                gateway_command_tx,
+                gateway_snapshot_req_tx,
+                Some(Arc::clone(&log_store)),
+            );
+            gateway.set_liveness(Arc::clone(&liveness));
+            Some((Arc::new(gateway), listener))
+        }
+        _ => None,
+    };
+
+    // Bind the Prometheus endpoint before reporting success, like the gateway
+    let prometheus_listener = if prometheus.enabled {
+        match tokio::net::TcpListener::bind(prometheus.listen).await {
+            Ok(l) => Some(l),
             Err(e) => {
                 send_error(
                     &mut startup_pipe,
-                    ErrorCategory::IpcServer,
-                    format!("Failed to initialize IPC server: {}", e),
+                    ErrorCategory::Metrics,
+                    format!(
+                        "Failed to bind Prometheus endpoint on {}: {}",
+                        prometheus.listen, e
+                    ),
                     None,
-                    "Check if you have permission to create IPC Server".to_string(),
+                    "Check that prometheus.listen is free and you may bind it".to_string(),
                 );
                 unreachable!();
             }
-        },
-    );
+        }
+    } else {
+        None
+    };
 
     // Send success message - daemon infrastructure is ready
     // (Service startup happens asynchronously and may take time)
@@ -184,29 +643,113 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
         }
     });
 
+    // Spawn Prometheus exporter task
+    let prometheus_handle = prometheus_listener.map(|listener| {
+        let mut exporter = PrometheusExporter::new(prometheus_snapshot_req_tx);
+        exporter.set_heartbeat_limiter(ipc_server.heartbeat_limiter());
+        exporter.set_fanout(ipc_server.fanout());
+        exporter.set_liveness(Arc::clone(&liveness));
+        tokio::spawn(async move {
+            if let Err(e) = exporter.serve(listener).await {
+                error!("Prometheus exporter error: {}", e);
+            }
+        })
+    });
+
+    // Spawn HTTP gateway task
+    let gateway_handle = gateway.map(|(gateway, listener)| {
+        tokio::spawn(async move {
+            if let Err(e) = gateway.serve(listener).await {
+                error!("HTTP gateway error: {}", e);
+            }
+        })
+    });
+
     // Spawn event forwarding task
     let ipc_server_clone = Arc::clone(&ipc_server);
+    let orchestrator_clone = Arc::clone(&orchestrator);
     let event_handle = tokio::spawn(async move {
         while let Some((service, status)) = event_rx.recv().await {
             info!("Event: {} -> {:?}", service, status);
+            event_journal.record(&service, status.clone());
             ipc_server_clone.broadcast_event(service, status);
+            orchestrator_clone.state_changed();
+        }
+    });
+
+    // Spawn maintenance forwarding task - tells clients about scheduled actions
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let maintenance_handle = tokio::spawn(async move {
+        while let Some((service, event, maintenance)) = maintenance_rx.recv().await {
+            info!(
+                "Maintenance: {:?} of {} at {} {:?}",
+                maintenance.action, service, maintenance.at, event
+            );
+            ipc_server_clone.broadcast_maintenance(service, event, maintenance);
         }
     });
 
+    // Spawn restart storm forwarding task - tells clients the system is degraded
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let storm_handle = tokio::spawn(async move {
+        while let Some(tripped) = storm_rx.recv().await {
+            ipc_server_clone.broadcast_system_degraded(tripped).await;
+        }
+    });
+
+    // Spawn scheduler task - applies maintenance actions as they come due
+    let scheduler_handle = tokio::spawn(scheduler::run(
+        Arc::clone(&scheduler),
+        Arc::clone(&workspaces),
+        Arc::clone(&audit_log),
+    ));
+
+    // Spawn state persistence task - records service processes for crash recovery
+    let orchestrator_clone = Arc::clone(&orchestrator);
+    let state_handle = tokio::spawn(async move {
+        orchestrator_clone.persist_state_loop().await;
+    });
+
     // Spawn log forwarding task - writes to log store and broadcasts to clients
     let ipc_server_clone = Arc::clone(&ipc_server);
     let log_store_clone = Arc::clone(&log_store);
     let log_handle = tokio::spawn(async move {
-        while let Some((service, line)) = log_rx.recv().await {
+        while let Some(output) = log_rx.recv().await {
             // Write to log store (file + memory)
-            log_store_clone.add_log(&service, line.clone()).await;
+            log_store_clone.add_output(&output).await;
             // Broadcast to connected clients
-            ipc_server_clone.broadcast_log(service, line);
+            ipc_server_clone.broadcast_log(output);
+        }
+    });
+
+    // Spawn metrics sampling task - broadcasts per-service resource usage
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let metrics_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(METRICS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let metrics = workspaces_clone.sample_metrics().await;
+            ipc_server_clone.broadcast_metrics(metrics);
+        }
+    });
+
+    // Spawn snapshot publishing task - sends snapshot deltas to subscribed clients
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let delta_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SNAPSHOT_DELTA_INTERVAL);
+        loop {
+            interval.tick().await;
+            let snapshot = workspaces_clone.get_snapshot().await;
+            ipc_server_clone.publish_snapshot(snapshot).await;
         }
     });
 
     // Spawn command handling task
-    let orchestrator_clone = Arc::clone(&orchestrator);
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let socket_clone = socket.clone();
     let command_handle = tokio::spawn(async move {
         while let Some((action, target)) = command_rx.recv().await {
             info!("Command: {:?} for {:?}", action, target);
@@ -215,53 +758,57 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
             match action {
                 CommandAction::StopDaemon => {
                     info!("Received stop daemon command");
-                    if let Err(e) = orchestrator_clone.shutdown().await {
-                        error!("Shutdown error: {}", e);
-                    }
+                    let report = workspaces_clone.shutdown().await;
+                    ipc_server_clone.broadcast_shutdown_report(report);
+                    ipc_server_clone.flush().await;
                     break;
                 }
-                CommandAction::Stop => {
-                    if let Some(service) = target {
-                        if let Err(e) = orchestrator_clone.stop_service(&service).await {
-                            error!("Failed to stop service '{}': {}", service, e);
-                        }
-                    } else {
-                        warn!("Stop command requires a target service");
-                    }
-                }
-                CommandAction::Restart => {
-                    if let Some(service) = target {
-                        if let Err(e) = orchestrator_clone.restart_service(&service).await {
-                            error!("Failed to restart service '{}': {}", service, e);
-                        }
-                    } else {
-                        warn!("Restart command requires a target service");
+                CommandAction::UpgradeDaemon => {
+                    info!("Received upgrade daemon command");
+                    let binary = target.map(PathBuf::from);
+                    let handed_over =
+                        handoff::upgrade(&workspaces_clone, &socket_clone, binary, &mut command_rx)
+                            .await;
+                    if handed_over.is_some() {
+                        return handed_over;
                     }
+                    ipc_server_clone.upgrade_aborted();
                 }
-                CommandAction::Start => {
-                    warn!("Start command not implemented - services start automatically");
+                CommandAction::EmergencyStop => {
+                    warn!("Received emergency stop command");
+                    workspaces_clone.emergency_stop().await;
                 }
-                CommandAction::Kill => {
-                    warn!("Kill command not implemented - use Stop instead");
+                _ => {
+                    if let Err(e) = workspaces_clone.execute(action, target).await {
+                        error!("Failed to apply {:?}: {}", action, e);
+                    }
                 }
             }
         }
+        None
     });
 
     // Spawn snapshot request handling task
-    let orchestrator_clone = Arc::clone(&orchestrator);
+    let workspaces_clone = Arc::clone(&workspaces);
     tokio::spawn(async move {
         while let Some(response_tx) = snapshot_req_rx.recv().await {
-            let snapshot = orchestrator_clone.get_snapshot().await;
+            let snapshot = workspaces_clone.get_snapshot().await;
             let _ = response_tx.send(snapshot);
         }
     });
 
+    // Spawn self-heartbeat task
+    let watchdog_handle = tokio::spawn(watchdog::run(
+        liveness,
+        watchdog_snapshot_req_tx,
+        notifier.clone(),
+    ));
+
     // Spawn heartbeat handling task
-    let orchestrator_clone = Arc::clone(&orchestrator);
+    let workspaces_clone = Arc::clone(&workspaces);
     tokio::spawn(async move {
         while let Some((service, status, metadata)) = heartbeat_rx.recv().await {
-            if let Err(e) = orchestrator_clone
+            if let Err(e) = workspaces_clone
                 .process_heartbeat(&service, status, metadata)
                 .await
             {
@@ -270,16 +817,83 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
         }
     });
 
-    // Start all services
-    info!("Starting all services...");
-    if let Err(e) = orchestrator.start_all().await {
-        error!("Failed to start services: {}", e);
+    // Spawn config reload handling task
+    let orchestrator_clone = Arc::clone(&orchestrator);
+    let log_store_clone = Arc::clone(&log_store);
+    let log_throttle = ipc_server.log_throttle();
+    let reload_path = config_path.clone();
+    tokio::spawn(async move {
+        while let Some(request) = reload_rx.recv().await {
+            let result = match KrillConfig::from_file(&reload_path) {
+                Ok(config) if request.dry_run => orchestrator_clone
+                    .plan_reload(&config)
+                    .await
+                    .map_err(KrillError::from),
+                Ok(config) => {
+                    info!("Reloading configuration from {:?}", reload_path);
+                    let limits = BufferLimits::from_config(&config);
+                    let result = orchestrator_clone
+                        .reload_config(config.clone())
+                        .await
+                        .map_err(KrillError::from);
+                    if result.is_ok() {
+                        scheduler.set_recipe(None, &config);
+                        log_store_clone.set_buffer_limits(limits).await;
+                        log_throttle.set_config(config.log_throttle.clone());
+                        let session_dir = log_store_clone.session_dir();
+                        if let Err(e) = bundle::record_config(session_dir, &reload_path) {
+                            warn!("Failed to record reloaded config: {}", e);
+                        }
+                    }
+                    result
+                }
+                Err(e) => Err(KrillError::from(e)),
+            };
+            if let Err(ref e) = result {
+                error!("Config reload failed: {}", e);
+            }
+            let _ = request.reply.send(result);
+        }
+    });
+
+    // Reload on edits of the config file
+    if args.watch {
+        tokio::spawn(watch::watch_config(config_path.clone(), reload_tx));
+    }
+
+    if let Some(handoff) = handoff {
+        // The services keep running across the upgrade; nothing is started
+        info!("Taking over the services of daemon {}", handoff.daemon_pid);
+        workspaces.take_over(handoff.services).await;
+    } else {
+        // Take over services that survived a crash of the previous daemon
+        if let Some(ref previous) = previous_state {
+            orchestrator.recover(previous).await;
+        }
+
+        // Start all services, or only the requested group
+        if let Some(ref group) = args.group {
+            info!("Starting service group '{}'...", group);
+            if let Err(e) = orchestrator.start_group(group).await {
+                error!("Failed to start group '{}': {}", group, e);
+            }
+        } else {
+            info!("Starting all services...");
+            if let Err(e) = orchestrator.start_all().await {
+                error!("Failed to start services: {}", e);
+            }
+        }
+
+        workspaces.start_hosted().await;
     }
 
     info!("All services initialization complete");
     info!("Daemon running. Press Ctrl+C to stop.");
+    notifier.ready();
 
-    // Wait for shutdown signal
+    // Wait for shutdown signal; after an upgrade, the connection to the new
+    // daemon, held until this one exits
+    let mut handed_over = None;
     tokio::select! {
         result = signal::ctrl_c() => {
             match result {
@@ -287,7 +901,8 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
                 Err(e) => error!("Failed to listen for Ctrl+C: {}", e),
             }
         }
-        _ = command_handle => {
+        result = command_handle => {
+            handed_over = result.ok().flatten();
             info!("Command handler stopped, initiating shutdown");
         }
         _ = ipc_handle => {
@@ -296,41 +911,36 @@ pub async fn execute(args: DaemonArgs) -> Result<()> {
     }
 
     // Shutdown
-    info!("Shutting down daemon...");
-
-    if let Err(e) = orchestrator.shutdown().await {
-        error!("Error during shutdown: {}", e);
+    notifier.notify("STOPPING=1");
+    if handed_over.is_some() {
+        info!("Services handed over to the new daemon; exiting without stopping them");
+        ipc_server.release_socket();
+    } else {
+        info!("Shutting down daemon...");
+
+        workspaces.shutdown().await;
     }
 
     ipc_server.shutdown().await;
 
     // Cancel event and log forwarding tasks
     event_handle.abort();
+    state_handle.abort();
     log_handle.abort();
+    metrics_handle.abort();
+    delta_handle.abort();
+    maintenance_handle.abort();
+    storm_handle.abort();
+    scheduler_handle.abort();
+    watchdog_handle.abort();
+    if let Some(handle) = gateway_handle {
+        handle.abort();
+    }
+    if let Some(handle) = prometheus_handle {
+        handle.abort();
+    }
 
     info!("Daemon stopped");
-    Ok(())
-}
-
-fn init_daemon_tracing(log_store: &LogStore) -> Result<()> {
-    use tracing_subscriber::{fmt, EnvFilter};
-
-    let daemon_log_path = log_store.session_dir().join("krill.log");
-    let file = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(daemon_log_path)?;
-
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    let subscriber = fmt()
-        .with_writer(file)
-        .with_target(false)
-        .with_ansi(false)
-        .with_env_filter(filter)
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber)?;
-
+    drop(handed_over);
     Ok(())
 }
@@ -0,0 +1,133 @@
+// krill doctor - Diagnose the environment and suggest fixes
+
+use crate::doctor::{self, Finding};
+use crate::{config_discovery, validate};
+use anyhow::{bail, Result};
+use krill_common::{socket_dir, KrillConfig, LEGACY_SOCKET_PATH};
+use krill_daemon::logging::log_base_dir;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct DoctorArgs {
+    /// Configuration file (defaults to ./krill.yaml)
+    pub config: Option<PathBuf>,
+
+    /// Remove stale sockets and orphaned state files
+    #[arg(long)]
+    pub fix: bool,
+}
+
+pub async fn execute(args: DoctorArgs) -> Result<()> {
+    let explicit = args.config.is_some();
+    let mut findings = Vec::new();
+    let config = match config_discovery::discover_config(args.config) {
+        Ok(path) => match load_config(path) {
+            Ok(config) => Some(config),
+            Err(finding) => {
+                findings.push(finding);
+                None
+            }
+        },
+        Err(e) if explicit => return Err(e),
+        Err(_) => {
+            println!("[--] No krill.yaml found, skipping the checks of a recipe");
+            None
+        }
+    };
+
+    let mut sockets = config_discovery::workspace_sockets(&socket_dir());
+    sockets.push(PathBuf::from(LEGACY_SOCKET_PATH));
+    report(
+        "Sockets",
+        doctor::check_sockets(&sockets).await,
+        &mut findings,
+    );
+    report(
+        "State files",
+        doctor::check_state_files(&doctor::state_dir()),
+        &mut findings,
+    );
+
+    let log_dir = log_base_dir(config.as_ref().and_then(|config| config.log_dir.as_deref()));
+    report(
+        "Log directory",
+        doctor::check_log_dir(&log_dir).into_iter().collect(),
+        &mut findings,
+    );
+    if let Some(config) = &config {
+        report(
+            "Executables",
+            doctor::check_executables(config),
+            &mut findings,
+        );
+    }
+
+    if findings.is_empty() {
+        println!("\nNo problems found");
+        return Ok(());
+    }
+
+    let mut fixed = 0;
+    if args.fix {
+        for finding in &findings {
+            let Some(path) = &finding.stale_file else {
+                continue;
+            };
+            match std::fs::remove_file(path) {
+                Ok(()) => {
+                    println!("Removed {}", path.display());
+                    fixed += 1;
+                }
+                Err(e) => eprintln!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+    } else if findings.iter().any(|finding| finding.stale_file.is_some()) {
+        println!("\nRun 'krill doctor --fix' to remove the stale files");
+    }
+
+    let remaining = findings.len() - fixed;
+    if remaining > 0 {
+        bail!("{} problem(s) found", remaining);
+    }
+    Ok(())
+}
+
+/// Parse the recipe at `path`, reporting whether it is valid
+fn load_config(path: PathBuf) -> Result<KrillConfig, Finding> {
+    let checked = std::fs::read_to_string(&path)
+        .map_err(|e| vec![e.to_string()])
+        .and_then(|content| {
+            validate::check(&content, path.parent()).map_err(|diagnostics| {
+                diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.to_string())
+                    .collect()
+            })
+        });
+    match checked {
+        Ok(config) => {
+            println!(
+                "[ok] Configuration: {} ({} services)",
+                path.display(),
+                config.services.len()
+            );
+            Ok(config)
+        }
+        Err(problems) => {
+            let finding = doctor::config_finding(&path, &problems);
+            println!("[!!] Configuration: {}", finding.error);
+            Err(finding)
+        }
+    }
+}
+
+/// Print the outcome of one check and collect its problems
+fn report(check: &str, problems: Vec<Finding>, findings: &mut Vec<Finding>) {
+    if problems.is_empty() {
+        println!("[ok] {}", check);
+    }
+    for problem in problems {
+        println!("[!!] {}: {}", check, problem.error);
+        findings.push(problem);
+    }
+}
@@ -1,24 +1,93 @@
-// krill down - Stop all services and the daemon
+// krill down - Stop all services and the daemon, and report how each stopped
 
-use crate::daemon_manager;
+use crate::status::{self, DaemonNotRunning, OutputFormat};
+use crate::{config_discovery, daemon_manager};
 use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use krill_common::KrillConfig;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for the daemon to stop when the recipe cannot tell
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Allowance on top of the services' stop timeouts for their hooks and for
+/// the daemon to report and exit
+const STOP_MARGIN: Duration = Duration::from_secs(10);
 
 #[derive(clap::Args, Debug)]
 pub struct DownArgs {
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    pub socket: PathBuf,
+    /// Format of the shutdown report
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+
+    /// Give up waiting for the daemon to stop after this long (defaults to
+    /// the stop timeouts of the recipe's services)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
 }
 
 pub async fn execute(args: DownArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
     // Check if daemon is running
-    if !daemon_manager::is_daemon_running(&args.socket).await {
-        return Err(anyhow!("Daemon is not running"));
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    if args.output == OutputFormat::Text {
+        println!("Stopping all services and daemon...");
     }
+    let timeout = args
+        .timeout
+        .unwrap_or_else(|| recipe_stop_timeout(&socket).unwrap_or(DEFAULT_STOP_TIMEOUT));
+    let report = daemon_manager::stop_daemon(&socket, timeout).await?;
 
-    println!("Stopping all services and daemon...");
-    daemon_manager::stop_daemon(&args.socket).await?;
-    println!("Daemon stopped successfully");
+    match (args.output, report) {
+        (OutputFormat::Text, Some(services)) => {
+            print!("{}", status::render_shutdown_report(&services));
+            println!("Daemon stopped successfully");
+        }
+        (OutputFormat::Text, None) => {
+            println!("Daemon stopped successfully (it did not report how the services stopped)")
+        }
+        (OutputFormat::Json, Some(services)) => println!(
+            "{}",
+            serde_json::to_string_pretty(&status::shutdown_json(&services))?
+        ),
+        (OutputFormat::Json, None) => {
+            return Err(anyhow!(
+                "The daemon stopped without reporting how the services stopped"
+            ))
+        }
+    }
     Ok(())
 }
+
+/// Longest the daemon behind `socket` may take to stop, if it runs the
+/// discovered recipe: every service going through all its stop steps, one
+/// after another
+fn recipe_stop_timeout(socket: &Path) -> Option<Duration> {
+    let path = config_discovery::discover_config(None).ok()?;
+    let config = KrillConfig::from_file(&path).ok()?;
+    if config.socket_path().ok()? != socket {
+        return None;
+    }
+    Some(total_stop_timeout(&config))
+}
+
+/// Time to stop every service of `config` one after another, stop command
+/// included
+fn total_stop_timeout(config: &KrillConfig) -> Duration {
+    config
+        .services
+        .values()
+        .flat_map(|service| service.policy.stop_steps(true))
+        .map(|step| step.timeout)
+        .sum::<Duration>()
+        + STOP_MARGIN
+}
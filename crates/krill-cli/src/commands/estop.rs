@@ -0,0 +1,57 @@
+// krill estop - Emergency stop all services, or clear a service's safety stop
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, CommandAction, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct EstopArgs {
+    /// Clear the safety stop of a service instead of triggering one
+    #[arg(long, value_name = "SERVICE")]
+    pub clear: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: EstopArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let request = match &args.clear {
+        Some(service) => ClientMessage::Command {
+            action: CommandAction::ClearSafetyStop,
+            target: Some(service.clone()),
+            force: false,
+        },
+        None => ClientMessage::Command {
+            action: CommandAction::EmergencyStop,
+            target: None,
+            force: false,
+        },
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            match &args.clear {
+                Some(service) => println!("Safety stop clear requested for '{}'", service),
+                None => println!("Emergency stop triggered"),
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => {
+            Err(anyhow!("Emergency stop request failed: {}", message))
+        }
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
@@ -0,0 +1,58 @@
+// krill events - Show the history of service status changes
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct EventsArgs {
+    /// Only show events from this long ago until now (e.g. 30s, 10m, 2h)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub since: Option<Duration>,
+
+    /// Only show events of this service
+    #[arg(long, value_name = "SERVICE")]
+    pub service: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: EventsArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let request = ClientMessage::GetEvents {
+        since_secs: args.since.map(|since| since.as_secs()),
+        service: args.service,
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::EventHistory { events } => {
+            if events.is_empty() {
+                println!("No events");
+            }
+            for event in events {
+                println!(
+                    "{}  {:<24} {:?}",
+                    event.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    event.service,
+                    event.status
+                );
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Failed to get events: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
@@ -0,0 +1,57 @@
+// krill exec - Run a one-off command in a service's environment
+
+use crate::{config_discovery, exec};
+use anyhow::{Context, Result};
+use krill_common::KrillConfig;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(clap::Args, Debug)]
+pub struct ExecArgs {
+    /// Service whose environment to use
+    pub service: String,
+
+    /// Command to run, after `--`
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+
+    /// Configuration file (defaults to ./krill.yaml)
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+}
+
+pub async fn execute(args: ExecArgs) -> Result<()> {
+    let config_path = config_discovery::discover_config(args.config)?;
+    let config = KrillConfig::from_file(&config_path)
+        .with_context(|| format!("Failed to load configuration {:?}", config_path))?;
+
+    let prepared = exec::prepare(
+        &config,
+        &args.service,
+        &args.command,
+        std::io::stdin().is_terminal(),
+    )?;
+
+    let mut command = Command::new(&prepared.program);
+    command.args(&prepared.args).envs(&prepared.env);
+    if let Some(ref dir) = prepared.working_dir {
+        command.current_dir(dir);
+    }
+
+    // Hand the terminal over to the command for good
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let err = command.exec();
+        Err(err).with_context(|| format!("Failed to run '{}'", prepared.program))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run '{}'", prepared.program))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
@@ -0,0 +1,39 @@
+// krill graph - Render the service dependency graph
+
+use crate::{config_discovery, graph};
+use anyhow::{Context, Result};
+use krill_common::KrillConfig;
+use std::path::PathBuf;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum GraphFormat {
+    /// ASCII tree from root services down to their dependents
+    #[default]
+    Tree,
+    /// Graphviz DOT (pipe into `dot -Tsvg`)
+    Dot,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GraphArgs {
+    /// Configuration file (defaults to ./krill.yaml)
+    pub config: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: GraphFormat,
+}
+
+pub async fn execute(args: GraphArgs) -> Result<()> {
+    let config_path = config_discovery::discover_config(args.config)?;
+    let config = KrillConfig::from_file(&config_path)
+        .with_context(|| format!("Failed to load configuration {:?}", config_path))?;
+
+    let rendered = match args.format {
+        GraphFormat::Tree => graph::render_tree(&config)?,
+        GraphFormat::Dot => graph::render_dot(&config)?,
+    };
+    print!("{}", rendered);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+// krill log-level - Change the daemon's log filter while it runs
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct LogLevelArgs {
+    /// Level or RUST_LOG style filter, e.g. `debug` or `info,krill_daemon::health=trace`
+    pub filter: String,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: LogLevelArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let request = ClientMessage::SetLogFilter {
+        filter: args.filter.clone(),
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            println!("Daemon log filter set to {}", args.filter);
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => {
+            Err(anyhow!("Failed to set the log filter: {}", message))
+        }
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
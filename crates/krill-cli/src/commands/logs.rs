@@ -1,34 +1,100 @@
 // krill logs - View logs
 
-use crate::daemon_manager;
-use anyhow::{anyhow, Result};
-use krill_common::{ClientMessage, ServerMessage};
-use std::path::PathBuf;
+use crate::status::{DaemonNotRunning, OutputFormat};
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use krill_common::{ClientMessage, KrillConfig, LogLevel, ServerMessage};
+use krill_daemon::{bundle, logging};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+
+/// How long the daemon may take to send the next part of a log bundle
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Minimum severity accepted by `--level`
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum LevelFilter {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LevelFilter> for LogLevel {
+    fn from(level: LevelFilter) -> Self {
+        match level {
+            LevelFilter::Trace => LogLevel::Trace,
+            LevelFilter::Debug => LogLevel::Debug,
+            LevelFilter::Info => LogLevel::Info,
+            LevelFilter::Warn => LogLevel::Warn,
+            LevelFilter::Error => LogLevel::Error,
+        }
+    }
+}
 
 #[derive(clap::Args, Debug)]
 pub struct LogsArgs {
     /// Service name (omit for daemon logs)
     pub service: Option<String>,
 
-    /// Follow log output
+    /// Follow log output; with a service, lines are printed raw for piping
     #[arg(short, long)]
     pub follow: bool,
 
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    pub socket: PathBuf,
+    /// Apply the daemon's `log_throttle` while following, replacing floods
+    /// and repeated lines with markers as the TUI does
+    #[arg(long, requires = "follow")]
+    pub throttle: bool,
+
+    /// Only show lines at this severity or above (lines without a
+    /// recognizable level are hidden)
+    #[arg(short, long, value_enum)]
+    pub level: Option<LevelFilter>,
+
+    /// Output format; `json` prints one JSON object per line
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+
+    /// Write the logs, event journal, recipe and daemon metadata of a
+    /// session to this .tar.gz file, e.g. for a bug report
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["service", "follow", "level", "output"])]
+    pub export: Option<PathBuf>,
+
+    /// Session to export, e.g. 20261016-081500 (defaults to the running
+    /// daemon's session, else the newest one)
+    #[arg(long, requires = "export")]
+    pub session: Option<String>,
+
+    /// Recipe whose log directory holds the sessions, used to export
+    /// without a running daemon (defaults to ./krill.yaml)
+    #[arg(long, value_name = "FILE", requires = "export")]
+    pub config: Option<PathBuf>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
 }
 
 pub async fn execute(args: LogsArgs) -> Result<()> {
+    if let Some(ref out) = args.export {
+        return export(&args, out).await;
+    }
+
+    let min_level = args.level.map(LogLevel::from);
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
     // Check if daemon is running
-    if !daemon_manager::is_daemon_running(&args.socket).await {
-        return Err(anyhow!("Daemon is not running. Start it with 'krill up'"));
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
     }
 
     // Connect to daemon
-    let stream = UnixStream::connect(&args.socket).await?;
+    let stream = daemon_manager::connect(&socket).await?;
     let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
 
@@ -36,6 +102,7 @@ pub async fn execute(args: LogsArgs) -> Result<()> {
     let subscribe_msg = ClientMessage::Subscribe {
         events: false,
         logs: args.service.clone(),
+        raw_logs: !args.throttle,
     };
 
     let json = serde_json::to_string(&subscribe_msg)?;
@@ -45,18 +112,26 @@ pub async fn execute(args: LogsArgs) -> Result<()> {
     if !args.follow {
         let get_logs_msg = ClientMessage::GetLogs {
             service: args.service.clone(),
+            level: min_level,
         };
         let json = serde_json::to_string(&get_logs_msg)?;
         writer.write_all(format!("{}\n", json).as_bytes()).await?;
     }
 
-    // Print header
-    if let Some(ref service) = args.service {
-        println!("=== Logs for service: {} ===", service);
-    } else {
-        println!("=== Daemon logs ===");
+    // Raw follow output and JSON are meant for pipes, so they get no
+    // header or prefix
+    let json = args.output == OutputFormat::Json;
+    let raw = args.follow && args.service.is_some();
+    if !raw && !json {
+        if let Some(ref service) = args.service {
+            println!("=== Logs for service: {} ===", service);
+        } else {
+            println!("=== Daemon logs ===");
+        }
+        println!();
     }
-    println!();
+
+    let mut stdout = std::io::stdout();
 
     // Read and print logs
     let mut line = String::new();
@@ -65,26 +140,59 @@ pub async fn execute(args: LogsArgs) -> Result<()> {
         match reader.read_line(&mut line).await {
             Ok(0) => {
                 // Connection closed
+                if args.follow {
+                    eprintln!("Daemon closed the connection");
+                }
                 break;
             }
             Ok(_) => {
-                if let Ok(msg) = serde_json::from_str::<ServerMessage>(line.trim()) {
-                    match msg {
-                        ServerMessage::LogLine { service, line } => {
-                            if args.service.is_none() || args.service.as_ref() == Some(&service) {
-                                println!("[{}] {}", service, line);
-                            }
-                        }
-                        ServerMessage::LogHistory { lines, .. } => {
-                            for log_line in lines {
+                let Ok(msg) = serde_json::from_str::<ServerMessage>(line.trim()) else {
+                    continue;
+                };
+
+                let written = match msg {
+                    ServerMessage::LogLine {
+                        service,
+                        line,
+                        level,
+                        timestamp,
+                        stream,
+                    } if args.follow => match args.service {
+                        Some(ref wanted) if *wanted != service => continue,
+                        _ if min_level.is_some_and(|min| level.is_none_or(|l| l < min)) => continue,
+                        _ if json => writeln!(
+                            stdout,
+                            "{}",
+                            serde_json::json!({
+                                "service": service,
+                                "line": line,
+                                "level": level,
+                                "timestamp": timestamp,
+                                "stream": stream,
+                            })
+                        ),
+                        Some(_) => writeln!(stdout, "{}", line),
+                        None => writeln!(stdout, "[{}] {}", service, line),
+                    },
+                    ServerMessage::LogHistory { service, lines } if !args.follow => {
+                        for log_line in lines {
+                            if json {
+                                println!(
+                                    "{}",
+                                    serde_json::json!({ "service": service, "line": log_line })
+                                );
+                            } else {
                                 println!("{}", log_line);
                             }
-                            if !args.follow {
-                                break;
-                            }
                         }
-                        _ => {}
+                        break;
                     }
+                    _ => continue,
+                };
+
+                // Stop quietly when the reader goes away (e.g. `| head`)
+                if written.and_then(|_| stdout.flush()).is_err() {
+                    break;
                 }
             }
             Err(e) => {
@@ -92,12 +200,73 @@ pub async fn execute(args: LogsArgs) -> Result<()> {
                 break;
             }
         }
+    }
+
+    Ok(())
+}
 
-        // If not following and we got some output, exit
-        if !args.follow {
-            break;
+/// Write a bundle of a log session to `out`: through the daemon when it
+/// runs, which adds a snapshot of the services, else straight from the log
+/// directory of the recipe
+async fn export(args: &LogsArgs, out: &Path) -> Result<()> {
+    let socket = args.socket.clone().or_else(|| {
+        args.config
+            .as_deref()
+            .and_then(config_discovery::config_socket)
+    });
+    let socket = config_discovery::resolve_socket(socket, None)?;
+
+    if daemon_manager::is_daemon_running(&socket).await {
+        let request = ClientMessage::ExportLogs {
+            session: args.session.clone(),
+        };
+        let mut file =
+            std::fs::File::create(out).with_context(|| format!("Failed to create {:?}", out))?;
+        let reply =
+            daemon_manager::send_request_with_parts(&socket, request, EXPORT_TIMEOUT, |message| {
+                match message {
+                    ServerMessage::LogBundleChunk { data } => {
+                        let chunk = base64::engine::general_purpose::STANDARD
+                            .decode(data)
+                            .context("Invalid log bundle chunk from daemon")?;
+                        file.write_all(&chunk)
+                            .with_context(|| format!("Failed to write {:?}", out))?;
+                        Ok(None)
+                    }
+                    other => Ok(Some(other)),
+                }
+            })
+            .await;
+
+        let result = match reply {
+            Ok(ServerMessage::LogsExported { session, .. }) => {
+                println!("Exported log session {} to {}", session, out.display());
+                Ok(())
+            }
+            Ok(ServerMessage::Error { message, .. }) => {
+                Err(anyhow!("Failed to export logs: {}", message))
+            }
+            Ok(other) => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            let _ = std::fs::remove_file(out);
         }
+        return result;
     }
 
+    let config_path = config_discovery::discover_config(args.config.clone())?;
+    let config = KrillConfig::from_file(&config_path)
+        .with_context(|| format!("Failed to load {:?}", config_path))?;
+    let log_dir = logging::log_base_dir(config.log_dir.as_deref());
+    let session_dir = bundle::find_session(&log_dir, args.session.as_deref())?;
+    let file = std::fs::File::create(out).with_context(|| format!("Failed to create {:?}", out))?;
+    bundle::export_session(&session_dir, file, &[])?;
+
+    let session = session_dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    println!("Exported log session {} to {}", session, out.display());
     Ok(())
 }
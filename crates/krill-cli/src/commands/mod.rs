@@ -1,13 +1,53 @@
 // Command modules
 
+pub mod audit;
 pub mod daemon;
+pub mod doctor;
 pub mod down;
+pub mod estop;
+pub mod events;
+pub mod exec;
+pub mod graph;
+pub mod kill;
+pub mod log_level;
 pub mod logs;
+pub mod pause;
+pub mod plan;
 pub mod ps;
+pub mod reload;
+pub mod replay;
+pub mod restart;
+pub mod resume;
+pub mod start;
+pub mod stats;
+pub mod status;
+pub mod targets;
+pub mod top;
 pub mod up;
+pub mod upgrade;
+pub mod validate;
 
+pub use audit::{execute as audit, AuditArgs};
 pub use daemon::{execute as daemon, DaemonArgs};
+pub use doctor::{execute as doctor, DoctorArgs};
 pub use down::{execute as down, DownArgs};
+pub use estop::{execute as estop, EstopArgs};
+pub use events::{execute as events, EventsArgs};
+pub use exec::{execute as exec, ExecArgs};
+pub use graph::{execute as graph, GraphArgs};
+pub use kill::{execute as kill, KillArgs};
+pub use log_level::{execute as log_level, LogLevelArgs};
 pub use logs::{execute as logs, LogsArgs};
+pub use pause::{execute as pause, PauseArgs};
+pub use plan::{execute as plan, PlanArgs};
 pub use ps::{execute as ps, PsArgs};
+pub use reload::{execute as reload, ReloadArgs};
+pub use replay::{execute as replay, ReplayArgs};
+pub use restart::{execute as restart, RestartArgs};
+pub use resume::{execute as resume, ResumeArgs};
+pub use start::{execute as start, StartArgs};
+pub use stats::{execute as stats, StatsArgs};
+pub use status::{execute as status, StatusArgs};
+pub use top::{execute as top, TopArgs};
 pub use up::{execute as up, UpArgs};
+pub use validate::{execute as validate, ValidateArgs};
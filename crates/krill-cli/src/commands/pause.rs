@@ -0,0 +1,48 @@
+// krill pause - Freeze services with SIGSTOP, keeping their state
+
+use super::targets::{self, Targets};
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{CommandAction, ServerMessage};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct PauseArgs {
+    #[command(flatten)]
+    pub targets: Targets,
+
+    /// Pause protected services without typing their names to confirm
+    #[arg(long)]
+    pub force: bool,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: PauseArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let services = args.targets.resolve(&socket).await?;
+    let response =
+        targets::send_action(&socket, CommandAction::Pause, &services, args.force).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            println!(
+                "Paused {}; 'krill resume' continues them",
+                targets::describe(&services)
+            );
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Pause failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
@@ -0,0 +1,22 @@
+// krill plan - Preview the staged startup order without starting anything
+
+use crate::{config_discovery, plan};
+use anyhow::{Context, Result};
+use krill_common::KrillConfig;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct PlanArgs {
+    /// Configuration file (defaults to ./krill.yaml)
+    pub config: Option<PathBuf>,
+}
+
+pub async fn execute(args: PlanArgs) -> Result<()> {
+    let config_path = config_discovery::discover_config(args.config)?;
+    let config = KrillConfig::from_file(&config_path)
+        .with_context(|| format!("Failed to load configuration {:?}", config_path))?;
+
+    print!("{}", plan::render_plan(&config)?);
+
+    Ok(())
+}
@@ -1,26 +1,93 @@
-// krill ps - Attach TUI to running daemon
+// krill ps - Attach TUI to running daemon, here or over SSH, or print the services as JSON
 
-use crate::daemon_manager;
-use anyhow::{anyhow, Result};
+use crate::remote::{self, RemoteTarget};
+use crate::status::{self, DaemonNotRunning, OutputFormat};
+use crate::{config_discovery, daemon_manager};
+use anyhow::Result;
 use std::path::PathBuf;
 use tracing::info;
 
 #[derive(clap::Args, Debug)]
 pub struct PsArgs {
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    pub socket: PathBuf,
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long, conflicts_with = "workspace")]
+    pub socket: Option<PathBuf>,
+
+    /// Attach to the daemon running this workspace
+    #[arg(short, long, value_name = "NAME")]
+    pub workspace: Option<String>,
+
+    /// Connect to a daemon over TCP instead (e.g. 192.168.1.10:7777)
+    #[arg(long, value_name = "HOST:PORT")]
+    pub connect: Option<String>,
+
+    /// Attach to the daemon of another machine over SSH (e.g. user@robot);
+    /// --socket and --workspace then pick the daemon there
+    #[arg(long, value_name = "[USER@]HOST", conflicts_with_all = ["connect", "output"])]
+    pub remote: Option<String>,
+
+    /// krill executable on the remote machine, when it is not on the PATH
+    /// of SSH sessions there (default: krill); quote a leading `~/` so the
+    /// remote shell expands it
+    #[arg(long, value_name = "PATH", requires = "remote")]
+    pub remote_krill: Option<String>,
+
+    /// Relay the daemon connection over stdin and stdout (run by --remote)
+    #[arg(long, hide = true, conflicts_with_all = ["connect", "remote", "output"])]
+    pub stdio: bool,
+
+    /// Print every service's snapshot as JSON instead of attaching the TUI
+    #[arg(short, long, value_enum, default_value_t, conflicts_with = "connect")]
+    pub output: OutputFormat,
 }
 
 pub async fn execute(args: PsArgs) -> Result<()> {
-    // Check if daemon is running
-    if !daemon_manager::is_daemon_running(&args.socket).await {
-        return Err(anyhow!("Daemon is not running. Start it with 'krill up'"));
+    // The socket and workspace name the daemon on the remote machine
+    if let Some(ref host) = args.remote {
+        info!("Attaching TUI to the daemon on {}...", host);
+        let target = RemoteTarget {
+            host,
+            krill: args.remote_krill.as_deref(),
+            socket: args.socket.as_deref(),
+            workspace: args.workspace.as_deref(),
+        };
+        let tui_config = krill_tui::TuiConfig {
+            socket: PathBuf::new(),
+            connect: None,
+            tunnel: Some(remote::ssh_command(&target)),
+        };
+        return krill_tui::run(tui_config).await;
+    }
+
+    let socket = config_discovery::resolve_socket(args.socket, args.workspace.as_deref())?;
+
+    // Check if daemon is running (a TCP connection failure is reported by the TUI)
+    if args.connect.is_none() && !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning {
+            workspace: args.workspace,
+        }
+        .into());
+    }
+
+    if args.stdio {
+        return remote::relay_stdio(&socket).await;
+    }
+
+    if args.output == OutputFormat::Json {
+        let services = daemon_manager::list_services(&socket, None).await?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status::services_json(&services))?
+        );
+        return Ok(());
     }
 
     info!("Attaching TUI to daemon...");
     let tui_config = krill_tui::TuiConfig {
-        socket: args.socket,
+        socket,
+        connect: args.connect,
+        tunnel: None,
     };
 
     krill_tui::run(tui_config).await?;
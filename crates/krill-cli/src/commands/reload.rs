@@ -0,0 +1,125 @@
+// krill reload - Apply configuration changes without restarting the daemon,
+// or signal a service to reload its own configuration
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, CommandAction, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct ReloadArgs {
+    /// Send this service its `reload_signal` (default SIGHUP) instead of
+    /// reloading the recipe
+    pub service: Option<String>,
+
+    /// Print which services the reload would start, stop, restart or update,
+    /// without changing anything
+    #[arg(long, conflicts_with = "service")]
+    pub dry_run: bool,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: ReloadArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    if let Some(service) = args.service {
+        return reload_service(&socket, service).await;
+    }
+
+    if !args.dry_run {
+        println!("Reloading configuration...");
+    }
+    let response = daemon_manager::send_request(
+        &socket,
+        ClientMessage::ReloadConfig {
+            dry_run: args.dry_run,
+        },
+        Duration::from_secs(90),
+    )
+    .await?;
+
+    match response {
+        ServerMessage::ConfigReloaded {
+            added,
+            removed,
+            restarted,
+            updated,
+            changes,
+            dry_run,
+        } => {
+            let done = |action: &str, planned: &str| {
+                if dry_run {
+                    format!("would {}", planned)
+                } else {
+                    action.to_string()
+                }
+            };
+            let changed = |name: &String| {
+                changes
+                    .get(name)
+                    .map(|settings| format!(": {}", settings.join(", ")))
+                    .unwrap_or_default()
+            };
+
+            if dry_run {
+                println!("Reload plan (nothing changed yet):");
+            }
+            if [&added, &removed, &restarted, &updated]
+                .iter()
+                .all(|names| names.is_empty())
+            {
+                println!("No service changes");
+            }
+            for name in &added {
+                println!("  + {} ({})", name, done("started", "start"));
+            }
+            for name in &removed {
+                println!("  - {} ({})", name, done("stopped", "stop"));
+            }
+            for name in &restarted {
+                let action = done("restarted", "restart");
+                println!("  ~ {} ({}{})", name, action, changed(name));
+            }
+            for name in &updated {
+                let action = done("updated in place", "update in place");
+                println!("  * {} ({}{})", name, action, changed(name));
+            }
+            if !dry_run {
+                println!("Configuration reloaded successfully");
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Reload failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Ask a running service to reload its configuration in place
+async fn reload_service(socket: &std::path::Path, service: String) -> Result<()> {
+    let request = ClientMessage::Command {
+        action: CommandAction::Reload,
+        target: Some(service.clone()),
+        force: false,
+    };
+    let response = daemon_manager::send_request(socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            println!("Reload signal sent to '{}'", service);
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Reload failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
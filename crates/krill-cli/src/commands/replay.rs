@@ -0,0 +1,31 @@
+// krill replay - Review a recorded session in the TUI
+
+use anyhow::Result;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// Log session directory, or its events.jsonl or timeline.jsonl
+    pub path: PathBuf,
+
+    /// Playback speed, 1 being real time (`<`/`>` halve and double it)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    let speed: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if !speed.is_finite() || speed <= 0.0 {
+        return Err("must be a positive number".to_string());
+    }
+    Ok(speed)
+}
+
+pub async fn execute(args: ReplayArgs) -> Result<()> {
+    let replay_config = krill_tui::replay::ReplayConfig {
+        path: args.path,
+        speed: args.speed,
+    };
+
+    krill_tui::replay::run(replay_config).await
+}
@@ -0,0 +1,71 @@
+// krill restart - Restart services
+
+use super::targets::{self, Targets};
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use krill_common::{CommandAction, ServerMessage};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct RestartArgs {
+    #[command(flatten)]
+    pub targets: Targets,
+
+    /// Restart protected services without typing their names to confirm
+    #[arg(long)]
+    pub force: bool,
+
+    /// Restart later instead of now: at a time of day (02:00, the next one),
+    /// a date and time (2026-10-17 02:00), or after a delay (+30m)
+    #[arg(long, value_name = "TIME")]
+    pub at: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: RestartArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let at = match args.at {
+        Some(ref at) => Some(krill_common::parse_time(at, &Local::now()).map_err(|e| anyhow!(e))?),
+        None => None,
+    };
+
+    let services = args.targets.resolve(&socket).await?;
+    let response = match at {
+        Some(at) => {
+            let at = at.with_timezone(&Utc);
+            targets::schedule_action(&socket, CommandAction::Restart, &services, at, args.force)
+                .await?
+        }
+        None => {
+            targets::send_action(&socket, CommandAction::Restart, &services, args.force).await?
+        }
+    };
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            match at {
+                Some(at) => println!(
+                    "Restart of {} scheduled for {}",
+                    targets::describe(&services),
+                    at.format("%Y-%m-%d %H:%M:%S %Z")
+                ),
+                None => println!("Restart requested for {}", targets::describe(&services)),
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Restart failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
@@ -0,0 +1,86 @@
+// krill resume - Continue paused services, or resume the automatic restarts
+// a restart storm paused
+
+use super::targets::{self, Targets};
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, CommandAction, ServerMessage};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct ResumeArgs {
+    /// Paused services to continue; without any, automatic restarts are
+    /// resumed instead
+    #[arg(value_name = "SERVICE", conflicts_with = "selector")]
+    pub services: Vec<String>,
+
+    /// Continue the paused services whose labels match, e.g. tier=mapping
+    #[arg(short = 'l', long, value_name = "SELECTOR")]
+    pub selector: Option<String>,
+
+    /// Only resume the automatic restarts of this workspace (defaults to all of them)
+    #[arg(
+        long,
+        value_name = "WORKSPACE",
+        conflicts_with_all = ["services", "selector"]
+    )]
+    pub workspace: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: ResumeArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    if !args.services.is_empty() || args.selector.is_some() {
+        let targets = Targets {
+            services: args.services,
+            selector: args.selector,
+        };
+        return resume_services(&socket, &targets).await;
+    }
+
+    let request = ClientMessage::Command {
+        action: CommandAction::ResumeRestarts,
+        target: args.workspace.clone(),
+        force: false,
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            match &args.workspace {
+                Some(workspace) => println!("Automatic restarts of '{}' resumed", workspace),
+                None => println!("Automatic restarts resumed"),
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Resume request failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Continue paused services with SIGCONT
+async fn resume_services(socket: &Path, targets: &Targets) -> Result<()> {
+    let services = targets.resolve(socket).await?;
+    let response = targets::send_action(socket, CommandAction::Resume, &services, false).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            println!("Resumed {}", targets::describe(&services));
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Resume failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
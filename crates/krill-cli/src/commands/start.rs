@@ -0,0 +1,40 @@
+// krill start - Start stopped services
+
+use super::targets::{self, Targets};
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{CommandAction, ServerMessage};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct StartArgs {
+    #[command(flatten)]
+    pub targets: Targets,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: StartArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let services = args.targets.resolve(&socket).await?;
+    let response = targets::send_action(&socket, CommandAction::Start, &services, false).await?;
+
+    match response {
+        ServerMessage::Ack { .. } => {
+            println!("Start requested for {}", targets::describe(&services));
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Start failed: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
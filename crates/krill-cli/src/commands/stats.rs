@@ -0,0 +1,73 @@
+// krill stats - Show how reliably each service ran since the daemon started
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{anyhow, Result};
+use krill_common::{ClientMessage, ServerMessage};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+    /// Only show this service
+    #[arg(long, value_name = "SERVICE")]
+    pub service: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: StatsArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    let request = ClientMessage::GetStats {
+        service: args.service,
+    };
+    let response = daemon_manager::send_request(&socket, request, Duration::from_secs(5)).await?;
+
+    match response {
+        ServerMessage::Stats { services } => {
+            let mut services: Vec<_> = services.into_iter().collect();
+            services.sort_by(|a, b| a.0.cmp(&b.0));
+
+            println!(
+                "{:<24} {:>12} {:>12} {:>13} {:>9} {:>12}",
+                "SERVICE", "UPTIME", "DOWNTIME", "AVAILABILITY", "FAILURES", "MTBF"
+            );
+            for (name, stats) in services {
+                let availability = stats
+                    .availability()
+                    .map(|fraction| format!("{:.2}%", fraction * 100.0))
+                    .unwrap_or_else(|| "-".to_string());
+                let mtbf = stats
+                    .mtbf
+                    .map(format_secs)
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "{:<24} {:>12} {:>12} {:>13} {:>9} {:>12}",
+                    name,
+                    format_secs(stats.uptime),
+                    format_secs(stats.downtime),
+                    availability,
+                    stats.failures,
+                    mtbf
+                );
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message, .. } => Err(anyhow!("Failed to get stats: {}", message)),
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Duration rounded to whole seconds, e.g. `2h 5m 3s`
+fn format_secs(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
@@ -0,0 +1,51 @@
+// krill status - Print the services' status and exit with their overall health
+
+use crate::status::{self, DaemonNotRunning, Exit, Health, OutputFormat};
+use crate::{config_discovery, daemon_manager};
+use anyhow::Result;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+
+    /// Only consider the services whose labels match, e.g. tier=perception
+    #[arg(short = 'l', long, value_name = "SELECTOR")]
+    pub selector: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long, conflicts_with = "workspace")]
+    pub socket: Option<PathBuf>,
+
+    /// Check the daemon running this workspace
+    #[arg(short, long, value_name = "NAME")]
+    pub workspace: Option<String>,
+}
+
+pub async fn execute(args: StatusArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket, args.workspace.as_deref())?;
+
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning {
+            workspace: args.workspace,
+        }
+        .into());
+    }
+
+    let services = daemon_manager::list_services(&socket, args.selector.as_deref()).await?;
+    match args.output {
+        OutputFormat::Text => print!("{}", status::render_status(&services)),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&status::status_json(&services))?
+        ),
+    }
+
+    match Health::of(&services).exit_code() {
+        status::EXIT_OK => Ok(()),
+        code => Err(Exit(code).into()),
+    }
+}
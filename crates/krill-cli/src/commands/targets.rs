@@ -0,0 +1,149 @@
+// Targets - Services a command acts on, by name or by label selector
+
+use crate::daemon_manager;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use krill_common::{ClientMessage, CommandAction, ServerMessage, ERROR_CONFIRMATION_REQUIRED};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Services named on the command line, or picked by their labels
+#[derive(clap::Args, Debug)]
+pub struct Targets {
+    /// Services to act on
+    #[arg(
+        value_name = "SERVICE",
+        required_unless_present = "selector",
+        conflicts_with = "selector"
+    )]
+    pub services: Vec<String>,
+
+    /// Act on the services whose labels match, e.g. tier=perception,robot!=r2
+    #[arg(short = 'l', long, value_name = "SELECTOR")]
+    pub selector: Option<String>,
+}
+
+impl Targets {
+    /// Names of the services to act on; a selector matching none is an error
+    pub async fn resolve(&self, socket: &Path) -> Result<Vec<String>> {
+        let Some(ref selector) = self.selector else {
+            return Ok(self.services.clone());
+        };
+        let mut services: Vec<String> = daemon_manager::list_services(socket, Some(selector))
+            .await?
+            .into_keys()
+            .collect();
+        if services.is_empty() {
+            return Err(anyhow!("No service matches '{}'", selector));
+        }
+        services.sort();
+        Ok(services)
+    }
+}
+
+/// Services as `'a', 'b'` for messages
+pub fn describe(services: &[String]) -> String {
+    services
+        .iter()
+        .map(|service| format!("'{}'", service))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Apply `action` to the services. Protected services need `force`, or on
+/// a terminal the service's name (`all` for several) typed back to confirm.
+pub async fn send_action(
+    socket: &Path,
+    action: CommandAction,
+    services: &[String],
+    force: bool,
+) -> Result<ServerMessage> {
+    let request = |force| ClientMessage::BatchCommand {
+        action,
+        targets: services.to_vec(),
+        force,
+    };
+    send_confirmed(socket, action, services, force, request).await
+}
+
+/// Have the daemon apply `action` to the services at `at`, confirmed as
+/// for [`send_action`]
+pub async fn schedule_action(
+    socket: &Path,
+    action: CommandAction,
+    services: &[String],
+    at: DateTime<Utc>,
+    force: bool,
+) -> Result<ServerMessage> {
+    let request = |force| ClientMessage::ScheduleCommand {
+        action,
+        targets: services.to_vec(),
+        at,
+        force,
+    };
+    send_confirmed(socket, action, services, force, request).await
+}
+
+async fn send_confirmed(
+    socket: &Path,
+    action: CommandAction,
+    services: &[String],
+    force: bool,
+    request: impl Fn(bool) -> ClientMessage,
+) -> Result<ServerMessage> {
+    let response =
+        daemon_manager::send_request(socket, request(force), Duration::from_secs(5)).await?;
+    let ServerMessage::Error {
+        code: Some(ERROR_CONFIRMATION_REQUIRED),
+        ..
+    } = response
+    else {
+        return Ok(response);
+    };
+
+    let snapshots = daemon_manager::list_services(socket, None).await?;
+    let protected: Vec<String> = services
+        .iter()
+        .filter(|service| snapshots.get(*service).is_some_and(|s| s.protected))
+        .cloned()
+        .collect();
+    let verb = format!("{:?}", action).to_lowercase();
+    if !std::io::stdin().is_terminal() {
+        return Err(match protected.as_slice() {
+            [service] => anyhow!("'{}' is protected; pass --force to {} it", service, verb),
+            several => anyhow!(
+                "{} are protected; pass --force to {} them",
+                describe(several),
+                verb
+            ),
+        });
+    }
+
+    let (phrase, prompt) = match protected.as_slice() {
+        [service] => (
+            service.clone(),
+            format!("'{}' is protected. Type its name to {} it: ", service, verb),
+        ),
+        several => (
+            "all".to_string(),
+            format!(
+                "{} are protected. Type 'all' to {} them: ",
+                describe(several),
+                verb
+            ),
+        ),
+    };
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() != phrase {
+        return Err(anyhow!(
+            "{:?} of {} not confirmed",
+            action,
+            describe(services)
+        ));
+    }
+    daemon_manager::send_request(socket, request(true), Duration::from_secs(5)).await
+}
@@ -0,0 +1,68 @@
+// krill top - Refresh a table of services and their resource usage in place
+
+use crate::status::DaemonNotRunning;
+use crate::top::{parse_interval, render};
+use crate::{config_discovery, daemon_manager};
+use anyhow::Result;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct TopArgs {
+    /// Time between refreshes (e.g. 1s, 500ms)
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_interval)]
+    pub interval: Duration,
+
+    /// Print the table once and exit
+    #[arg(long)]
+    pub once: bool,
+
+    /// Only show the services whose labels match, e.g. tier=perception
+    #[arg(short = 'l', long, value_name = "SELECTOR")]
+    pub selector: Option<String>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: TopArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket.clone(), None)?;
+
+    // Check if daemon is running
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    // On a terminal each table overwrites the last one; piped output gets
+    // one table after the other
+    let redraw = std::io::stdout().is_terminal();
+    let mut printed_lines = 0;
+    loop {
+        let services = daemon_manager::list_services(&socket, args.selector.as_deref()).await?;
+
+        let lines = render(&services);
+        let mut stdout = std::io::stdout().lock();
+        if redraw && printed_lines > 0 {
+            // Move to the start of the previous table and clear below it
+            write!(stdout, "\x1b[{}F\x1b[J", printed_lines)?;
+        } else if printed_lines > 0 {
+            writeln!(stdout)?;
+        }
+        for line in &lines {
+            writeln!(stdout, "{}", line)?;
+        }
+        stdout.flush()?;
+        printed_lines = lines.len();
+
+        if args.once {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(args.interval) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
@@ -1,7 +1,9 @@
 // krill up - Start daemon and optionally attach TUI
 
 use crate::{config_discovery, daemon_manager};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use krill_common::{ClientMessage, CommandAction, LEGACY_SOCKET_PATH};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 use tracing::info;
@@ -11,13 +13,40 @@ pub struct UpArgs {
     /// Configuration file (defaults to ./krill.yaml)
     pub config: Option<PathBuf>,
 
-    /// Run daemon without attaching TUI
-    #[arg(short, long)]
-    pub detached: bool,
+    /// Don't attach the TUI, and run the daemon in a session of its own so it
+    /// survives the terminal or SSH session closing
+    #[arg(short, long, alias = "detached")]
+    pub detach: bool,
 
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    pub socket: PathBuf,
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// recipe's `socket`, else one named after the workspace)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+
+    /// Also accept IPC clients over TCP (e.g. 127.0.0.1:7777)
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<SocketAddr>,
+
+    /// Serve the HTTP/JSON gateway on this address (e.g. 0.0.0.0:8080)
+    #[arg(long, value_name = "ADDR", requires = "http_token_file")]
+    pub http: Option<SocketAddr>,
+
+    /// File holding the bearer token required by the HTTP gateway
+    #[arg(long, value_name = "FILE")]
+    pub http_token_file: Option<PathBuf>,
+
+    /// Only start this service group (and its dependencies)
+    #[arg(long, value_name = "GROUP")]
+    pub group: Option<String>,
+
+    /// Also host the workspace of this recipe in the daemon; its services are
+    /// addressed as `workspace/service` (repeatable)
+    #[arg(long = "workspace", value_name = "FILE")]
+    pub workspaces: Vec<PathBuf>,
+
+    /// Reload the configuration whenever its file changes
+    #[arg(long)]
+    pub watch: bool,
 }
 
 pub async fn execute(args: UpArgs) -> Result<()> {
@@ -25,28 +54,75 @@ pub async fn execute(args: UpArgs) -> Result<()> {
     let config_path = config_discovery::discover_config(args.config)?;
     info!("Using configuration: {:?}", config_path);
 
+    // Each workspace gets its own daemon socket
+    let socket = args
+        .socket
+        .or_else(|| config_discovery::config_socket(&config_path))
+        .unwrap_or_else(|| PathBuf::from(LEGACY_SOCKET_PATH));
+
+    // The daemon resolves hosted recipes from its own working directory
+    let workspaces = args
+        .workspaces
+        .iter()
+        .map(|path| {
+            std::fs::canonicalize(path)
+                .with_context(|| format!("Workspace recipe {:?} not found", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
     // Check if daemon is already running
-    let daemon_running = daemon_manager::is_daemon_running(&args.socket).await;
+    let daemon_running = daemon_manager::is_daemon_running(&socket).await;
 
     if !daemon_running {
         info!("Starting daemon...");
 
         // Start daemon in background
-        daemon_manager::start_daemon_background(&config_path, &args.socket, None).await?;
+        daemon_manager::start_daemon_background(
+            &config_path,
+            &socket,
+            None,
+            args.listen,
+            args.http.zip(args.http_token_file.as_deref()),
+            args.group.as_deref(),
+            &workspaces,
+            args.watch,
+            // Windows daemons are started as detached processes already
+            cfg!(unix) && args.detach,
+        )
+        .await?;
 
         // Wait for daemon to be ready
-        daemon_manager::wait_for_socket(&args.socket, Duration::from_secs(10)).await?;
+        daemon_manager::wait_for_socket(&socket, Duration::from_secs(10)).await?;
 
         println!("Daemon started successfully");
+    } else if let Some(ref group) = args.group {
+        // Daemon is already up - start the group on it
+        let command = ClientMessage::Command {
+            action: CommandAction::StartGroup,
+            target: Some(group.clone()),
+            force: false,
+        };
+        daemon_manager::send_command(&socket, command).await?;
+        println!("Daemon already running, starting group '{}'", group);
     } else {
         println!("Daemon already running");
     }
 
+    if daemon_running && !workspaces.is_empty() {
+        println!("--workspace only applies when starting the daemon");
+    }
+
+    if daemon_running && args.watch {
+        println!("--watch only applies when starting the daemon; use 'krill reload' instead");
+    }
+
     // Launch TUI unless detached mode
-    if !args.detached {
+    if !args.detach {
         info!("Launching TUI...");
         let tui_config = krill_tui::TuiConfig {
-            socket: args.socket,
+            socket,
+            connect: None,
+            tunnel: None,
         };
 
         krill_tui::run(tui_config).await?;
@@ -0,0 +1,42 @@
+// krill daemon upgrade - Replace the daemon without restarting its services
+
+use crate::status::DaemonNotRunning;
+use crate::{config_discovery, daemon_manager};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct UpgradeArgs {
+    /// New daemon binary (defaults to the running daemon's executable, as
+    /// replaced by a package upgrade)
+    #[arg(long, value_name = "FILE")]
+    pub binary: Option<PathBuf>,
+
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// current workspace's daemon)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+}
+
+pub async fn execute(args: UpgradeArgs) -> Result<()> {
+    let socket = config_discovery::resolve_socket(args.socket, None)?;
+
+    if !daemon_manager::is_daemon_running(&socket).await {
+        return Err(DaemonNotRunning::default().into());
+    }
+
+    // The daemon requires an absolute path, as its working directory differs
+    let binary = args
+        .binary
+        .map(|binary| {
+            binary
+                .canonicalize()
+                .with_context(|| format!("Binary not found: {}", binary.display()))
+        })
+        .transpose()?;
+
+    println!("Upgrading daemon...");
+    daemon_manager::upgrade_daemon(&socket, binary.as_deref()).await?;
+    println!("Daemon upgraded; services kept running");
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// krill validate - Check a recipe without starting anything
+
+use crate::{config_discovery, validate};
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Configuration file (defaults to ./krill.yaml)
+    pub config: Option<PathBuf>,
+}
+
+pub async fn execute(args: ValidateArgs) -> Result<()> {
+    let config_path = config_discovery::discover_config(args.config)?;
+    let content = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read configuration {:?}", config_path))?;
+
+    match validate::check(&content, config_path.parent()) {
+        Ok(config) => {
+            println!(
+                "{}: valid ({} services)",
+                config_path.display(),
+                config.services.len()
+            );
+            Ok(())
+        }
+        Err(diagnostics) => {
+            // One `file:line:column: message` line per problem, as compilers do
+            for diagnostic in &diagnostics {
+                match diagnostic.file {
+                    Some(_) => eprintln!("{}", diagnostic),
+                    None => eprintln!("{}:{}", config_path.display(), diagnostic),
+                }
+            }
+            bail!(
+                "{} problem(s) found in {}",
+                diagnostics.len(),
+                config_path.display()
+            )
+        }
+    }
+}
@@ -1,7 +1,10 @@
 // Configuration file discovery
 
 use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use krill_common::{
+    socket_dir, workspace_socket_path, KrillConfig, LEGACY_SOCKET_PATH, SOCKET_ENV,
+};
+use std::path::{Path, PathBuf};
 use tracing::debug;
 
 /// Discover configuration file using priority order:
@@ -63,3 +66,88 @@ pub fn discover_config(cli_config: Option<PathBuf>) -> Result<PathBuf> {
          Please create a krill.yaml configuration file or specify --config <path>"
     ))
 }
+
+/// Socket of the daemon for the workspace defined in `config_path`, if the
+/// file can be loaded
+pub fn config_socket(config_path: &Path) -> Option<PathBuf> {
+    match KrillConfig::from_file(&config_path.to_path_buf()).and_then(|c| c.socket_path()) {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            debug!(
+                "Could not read workspace name from {:?}: {}",
+                config_path, e
+            );
+            None
+        }
+    }
+}
+
+/// Per-workspace daemon sockets (`krill-<name>.sock`) present in `dir`
+pub fn workspace_sockets(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sockets: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("krill-") && name.ends_with(".sock"))
+        })
+        .collect();
+    sockets.sort();
+    sockets
+}
+
+/// Resolve the daemon socket using priority order:
+/// 1. --socket CLI argument (if provided)
+/// 2. --workspace CLI argument (if provided)
+/// 3. KRILL_SOCKET environment variable (set by the daemon for its services)
+/// 4. Workspace of the discovered configuration file
+/// 5. The only workspace socket in the socket directory
+/// 6. /tmp/krill.sock (daemons predating per-workspace sockets)
+pub fn resolve_socket(cli_socket: Option<PathBuf>, workspace: Option<&str>) -> Result<PathBuf> {
+    // 1. CLI argument takes highest priority
+    if let Some(path) = cli_socket {
+        return Ok(path);
+    }
+
+    // 2. Explicit workspace name
+    if let Some(name) = workspace {
+        return Ok(workspace_socket_path(name)?);
+    }
+
+    // 3. Environment variable
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        debug!("Using socket from {} env var: {:?}", SOCKET_ENV, path);
+        return Ok(PathBuf::from(path));
+    }
+
+    // 4. Workspace of the local configuration
+    if let Some(socket) = discover_config(None)
+        .ok()
+        .and_then(|path| config_socket(&path))
+    {
+        debug!("Using socket of configured workspace: {:?}", socket);
+        return Ok(socket);
+    }
+
+    // 5. A single daemon running anywhere
+    let sockets = workspace_sockets(&socket_dir());
+    match sockets.len() {
+        0 => Ok(PathBuf::from(LEGACY_SOCKET_PATH)),
+        1 => Ok(sockets.into_iter().next().unwrap()),
+        _ => {
+            let names: Vec<String> = sockets
+                .iter()
+                .filter_map(|path| path.file_stem()?.to_str()?.strip_prefix("krill-"))
+                .map(String::from)
+                .collect();
+            Err(anyhow!(
+                "Several krill daemons are running ({}). Pick one with --workspace <name>",
+                names.join(", ")
+            ))
+        }
+    }
+}
@@ -2,22 +2,56 @@
 
 use anyhow::{anyhow, Context, Result};
 use krill_daemon::StartupMessage;
-use std::os::fd::{FromRawFd, RawFd};
-use std::path::Path;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tracing::{debug, info, warn};
 
+/// Connection to the daemon's local endpoint
+pub trait DaemonStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> DaemonStream for T {}
+
+/// Connect to the daemon's Unix socket, or to its named pipe on Windows
+pub async fn connect(socket_path: &Path) -> std::io::Result<Box<dyn DaemonStream>> {
+    #[cfg(unix)]
+    {
+        Ok(Box::new(tokio::net::UnixStream::from_std(
+            krill_common::connect_unix_socket(socket_path)?,
+        )?))
+    }
+
+    #[cfg(windows)]
+    {
+        use tokio::net::windows::named_pipe::ClientOptions;
+        let pipe = krill_common::pipe_name(socket_path);
+        Ok(Box::new(ClientOptions::new().open(pipe)?))
+    }
+}
+
+/// Whether the local endpoint has a file that stays behind when the daemon
+/// dies; named pipes and abstract sockets vanish with it
+fn has_socket_file(socket_path: &Path) -> bool {
+    !cfg!(windows) && krill_common::abstract_socket_name(socket_path).is_none()
+}
+
+/// Whether the local endpoint may exist; only socket files can be looked for
+fn endpoint_exists(socket_path: &Path) -> bool {
+    !has_socket_file(socket_path) || socket_path.exists()
+}
+
 /// Check if daemon is running by attempting to connect to socket
 pub async fn is_daemon_running(socket_path: &Path) -> bool {
-    if !socket_path.exists() {
+    if !endpoint_exists(socket_path) {
         return false;
     }
 
     // Try to connect
-    match UnixStream::connect(socket_path).await {
+    match connect(socket_path).await {
         Ok(_) => true,
+        Err(_) if !has_socket_file(socket_path) => false,
         Err(_) => {
             // Socket exists but can't connect - stale socket
             warn!("Stale socket detected at {:?}, will clean up", socket_path);
@@ -28,19 +62,33 @@ pub async fn is_daemon_running(socket_path: &Path) -> bool {
 }
 
 /// Start daemon in background
+#[allow(clippy::too_many_arguments)]
 pub async fn start_daemon_background(
     config_path: &Path,
     socket_path: &Path,
     log_dir: Option<&Path>,
+    listen: Option<SocketAddr>,
+    http: Option<(SocketAddr, &Path)>,
+    group: Option<&str>,
+    workspaces: &[PathBuf],
+    watch: bool,
+    daemonize: bool,
 ) -> Result<()> {
     info!("Starting daemon in background...");
 
-    use std::os::fd::AsRawFd;
-
     // Create pipe for startup communicaiton
     let (read_fd, write_fd) = os_pipe::pipe().context("Failed to create pipe")?;
 
-    let write_fd_raw = write_fd.as_raw_fd();
+    #[cfg(unix)]
+    let write_fd_raw = {
+        use std::os::fd::AsRawFd;
+        write_fd.as_raw_fd()
+    };
+    #[cfg(windows)]
+    let write_fd_raw = {
+        use std::os::windows::io::AsRawHandle;
+        write_fd.as_raw_handle() as i64
+    };
 
     // Get current executable path
     let current_exe = std::env::current_exe()?;
@@ -59,6 +107,33 @@ pub async fn start_daemon_background(
         cmd.arg("--log-dir").arg(log_dir);
     }
 
+    if let Some(addr) = listen {
+        cmd.arg("--listen").arg(addr.to_string());
+    }
+
+    if let Some((addr, token_file)) = http {
+        cmd.arg("--http")
+            .arg(addr.to_string())
+            .arg("--http-token-file")
+            .arg(token_file);
+    }
+
+    if let Some(group) = group {
+        cmd.arg("--group").arg(group);
+    }
+
+    for workspace in workspaces {
+        cmd.arg("--workspace").arg(workspace);
+    }
+
+    if watch {
+        cmd.arg("--watch");
+    }
+
+    if daemonize {
+        cmd.arg("--daemonize");
+    }
+
     // Inherit PATH from parent so daemon can find pixi, ros2, etc.
     if let Ok(path) = std::env::var("PATH") {
         cmd.env("PATH", path);
@@ -86,6 +161,27 @@ pub async fn start_daemon_background(
         }
     }
 
+    // Windows: let the child inherit the write handle and run without our console
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{SetHandleInformation, HANDLE_FLAG_INHERIT};
+
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+        let ok = unsafe {
+            SetHandleInformation(
+                write_fd_raw as windows_sys::Win32::Foundation::HANDLE,
+                HANDLE_FLAG_INHERIT,
+                HANDLE_FLAG_INHERIT,
+            )
+        };
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to share startup pipe");
+        }
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+
     let child = cmd.spawn().context("Failed to spawn daemon process")?;
 
     // Detach - don't wait for child
@@ -94,11 +190,7 @@ pub async fn start_daemon_background(
     // Close write end in parent (daemon holds it)
     drop(write_fd);
 
-    // Extract raw FD and forget the PipeReader to avoid double-close
-    let read_fd_raw = read_fd.as_raw_fd();
-    std::mem::forget(read_fd);
-
-    let result = read_startup_result(read_fd_raw).await?;
+    let result = read_startup_result(read_fd).await?;
 
     match result {
         StartupMessage::Success => {
@@ -109,9 +201,13 @@ pub async fn start_daemon_background(
     }
 }
 
-pub async fn read_startup_result(read_fd: RawFd) -> Result<StartupMessage> {
-    let file = unsafe { tokio::fs::File::from_raw_fd(read_fd) };
-    let mut reader = tokio::io::BufReader::new(file);
+pub async fn read_startup_result(read_fd: os_pipe::PipeReader) -> Result<StartupMessage> {
+    #[cfg(unix)]
+    let file = std::fs::File::from(std::os::fd::OwnedFd::from(read_fd));
+    #[cfg(windows)]
+    let file = std::fs::File::from(std::os::windows::io::OwnedHandle::from(read_fd));
+
+    let mut reader = tokio::io::BufReader::new(tokio::fs::File::from_std(file));
     let mut line = String::new();
 
     match tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut line)).await {
@@ -131,8 +227,8 @@ pub async fn wait_for_socket(socket_path: &Path, timeout: Duration) -> Result<()
     debug!("Waiting for socket at {:?}", socket_path);
 
     while start.elapsed() < timeout {
-        if socket_path.exists() {
-            match UnixStream::connect(socket_path).await {
+        if endpoint_exists(socket_path) {
+            match connect(socket_path).await {
                 Ok(_) => {
                     info!("Socket ready at {:?}", socket_path);
                     return Ok(());
@@ -158,7 +254,7 @@ pub async fn send_command(
     socket_path: &Path,
     command: krill_common::ClientMessage,
 ) -> Result<krill_common::ServerMessage> {
-    let stream = UnixStream::connect(socket_path)
+    let stream = connect(socket_path)
         .await
         .context("Failed to connect to daemon")?;
 
@@ -185,28 +281,216 @@ pub async fn send_command(
     Ok(response)
 }
 
-/// Stop the daemon gracefully
-pub async fn stop_daemon(socket_path: &Path) -> Result<()> {
+/// Send a request to the daemon and wait for its reply, skipping broadcast events
+pub async fn send_request(
+    socket_path: &Path,
+    request: krill_common::ClientMessage,
+    timeout: Duration,
+) -> Result<krill_common::ServerMessage> {
+    send_request_with_parts(socket_path, request, timeout, |message| Ok(Some(message))).await
+}
+
+/// Send a request whose reply comes after parts of it, such as the chunks
+/// of a log bundle. Each message goes to `part`, which hands back the one
+/// that ends the reply and consumes the others. `timeout` bounds the wait
+/// for each of them.
+pub async fn send_request_with_parts<F>(
+    socket_path: &Path,
+    request: krill_common::ClientMessage,
+    timeout: Duration,
+    mut part: F,
+) -> Result<krill_common::ServerMessage>
+where
+    F: FnMut(krill_common::ServerMessage) -> Result<Option<krill_common::ServerMessage>>,
+{
+    use krill_common::ServerMessage;
+
+    let stream = connect(socket_path)
+        .await
+        .context("Failed to connect to daemon")?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let json = serde_json::to_string(&request)?;
+    writer
+        .write_all(format!("{}\n", json).as_bytes())
+        .await
+        .context("Failed to send request")?;
+
+    let mut deadline = tokio::time::Instant::now() + timeout;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = tokio::time::timeout_at(deadline, reader.read_line(&mut line))
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for daemon response"))??;
+        if read == 0 {
+            return Err(anyhow!("Daemon closed the connection"));
+        }
+
+        match serde_json::from_str::<ServerMessage>(line.trim()) {
+            Ok(ServerMessage::StatusUpdate { .. }) | Ok(ServerMessage::LogLine { .. }) => {}
+            Ok(message) => match part(message)? {
+                Some(reply) => return Ok(reply),
+                None => deadline = tokio::time::Instant::now() + timeout,
+            },
+            Err(e) => debug!("Ignoring unparseable message from daemon: {}", e),
+        }
+    }
+}
+
+/// Snapshots of the services matching a label selector, or of all services
+pub async fn list_services(
+    socket_path: &Path,
+    selector: Option<&str>,
+) -> Result<HashMap<String, krill_common::ServiceSnapshot>> {
+    use krill_common::{ClientMessage, ServerMessage};
+
+    let request = ClientMessage::ListServices {
+        selector: selector.map(String::from),
+    };
+    match send_request(socket_path, request, Duration::from_secs(5)).await? {
+        ServerMessage::Snapshot { services, .. } => Ok(services),
+        ServerMessage::Error { message, .. } => {
+            Err(anyhow!("Failed to list services: {}", message))
+        }
+        other => Err(anyhow!("Unexpected response from daemon: {:?}", other)),
+    }
+}
+
+/// Stop the daemon gracefully, returning how it stopped each service;
+/// `None` if it exited without reporting that. Fails if the report does not
+/// arrive within `timeout`.
+pub async fn stop_daemon(
+    socket_path: &Path,
+    timeout: Duration,
+) -> Result<Option<Vec<krill_common::ServiceShutdown>>> {
+    use krill_common::ServerMessage;
+
     info!("Stopping daemon...");
 
+    let stream = connect(socket_path)
+        .await
+        .context("Failed to connect to daemon")?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
     let command = krill_common::ClientMessage::Command {
         action: krill_common::CommandAction::StopDaemon,
         target: None,
+        force: false,
     };
+    let json = serde_json::to_string(&command)?;
+    writer
+        .write_all(format!("{}\n", json).as_bytes())
+        .await
+        .context("Failed to send command")?;
 
-    send_command(socket_path, command).await?;
-
-    // Wait for socket to be removed
+    // The daemon acknowledges the command, stops the services and reports
+    // how each one stopped before it closes the connection. One that
+    // predates the report just closes it.
+    let read_report = async {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) | Err(_) => return Ok(None),
+                Ok(_) => {}
+            }
+            match serde_json::from_str::<ServerMessage>(line.trim()) {
+                Ok(ServerMessage::ShutdownReport { services }) => return Ok(Some(services)),
+                Ok(ServerMessage::Error { message, .. }) => {
+                    return Err(anyhow!("Stop refused: {}", message))
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Ignoring unparseable message from daemon: {}", e),
+            }
+        }
+    };
+    let report = tokio::time::timeout(timeout, read_report)
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "Daemon did not stop within {}",
+                humantime::format_duration(timeout)
+            )
+        })??;
+
+    // Wait for socket to be removed (for a named pipe, to stop accepting)
     let start = Instant::now();
-    while socket_path.exists() && start.elapsed() < Duration::from_secs(5) {
+    while start.elapsed() < Duration::from_secs(5) {
+        let gone = if has_socket_file(socket_path) {
+            !socket_path.exists()
+        } else {
+            connect(socket_path).await.is_err()
+        };
+        if gone {
+            break;
+        }
         tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
-    if socket_path.exists() {
+    if has_socket_file(socket_path) && socket_path.exists() {
         warn!("Socket still exists after shutdown, removing manually");
         std::fs::remove_file(socket_path)?;
     }
 
     info!("Daemon stopped successfully");
-    Ok(())
+    Ok(report)
+}
+
+/// Have the daemon hand its services over to a new daemon binary, and wait
+/// until the new daemon answers on the socket
+pub async fn upgrade_daemon(socket_path: &Path, binary: Option<&Path>) -> Result<()> {
+    use krill_common::ServerMessage;
+    use krill_daemon::handoff::HANDOFF_TIMEOUT;
+
+    let stream = connect(socket_path)
+        .await
+        .context("Failed to connect to daemon")?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let command = krill_common::ClientMessage::Command {
+        action: krill_common::CommandAction::UpgradeDaemon,
+        target: binary.map(|binary| binary.display().to_string()),
+        force: false,
+    };
+    let json = serde_json::to_string(&command)?;
+    writer
+        .write_all(format!("{}\n", json).as_bytes())
+        .await
+        .context("Failed to send request")?;
+
+    // The old daemon acknowledges the command and closes the connection
+    // when it exits after the handoff
+    let handed_over = async {
+        let mut line = String::new();
+        let mut acknowledged = false;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return match acknowledged {
+                    true => Ok(()),
+                    false => Err(anyhow!("Daemon closed the connection")),
+                };
+            }
+            match serde_json::from_str::<ServerMessage>(line.trim()) {
+                Ok(ServerMessage::Ack { .. }) => acknowledged = true,
+                Ok(ServerMessage::Error { message, .. }) => {
+                    return Err(anyhow!("Upgrade refused: {}", message))
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Ignoring unparseable message from daemon: {}", e),
+            }
+        }
+    };
+    tokio::time::timeout(HANDOFF_TIMEOUT * 2, handed_over)
+        .await
+        .map_err(|_| {
+            anyhow!("The handoff did not complete; the old daemon keeps running, see its krill.log")
+        })??;
+
+    wait_for_socket(socket_path, HANDOFF_TIMEOUT).await
 }
@@ -0,0 +1,280 @@
+// Doctor - Diagnose the environment a daemon runs in
+
+use crate::daemon_manager;
+use krill_common::{build_command, find_executable, get_working_dir, ExecuteConfig, KrillConfig};
+use krill_daemon::state::StateFile;
+use krill_daemon::{ErrorCategory, StartupError};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Shell builtins and keywords that are not looked up in PATH
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "cd", "source", "export", "set", "unset", "eval", "if", "for", "while", "case",
+    "test", "[", "echo", "true", "false", "(", "{",
+];
+
+/// A problem found in the environment
+pub struct Finding {
+    pub error: StartupError,
+    /// Leftover file whose removal fixes the problem
+    pub stale_file: Option<PathBuf>,
+}
+
+impl Finding {
+    fn new(category: ErrorCategory, message: String, path: Option<PathBuf>, hint: String) -> Self {
+        Self {
+            error: StartupError {
+                category,
+                message,
+                path,
+                hint,
+            },
+            stale_file: None,
+        }
+    }
+
+    fn stale(mut self, path: PathBuf) -> Self {
+        self.stale_file = Some(path);
+        self
+    }
+}
+
+/// A recipe that does not validate
+pub fn config_finding(path: &Path, problems: &[String]) -> Finding {
+    Finding::new(
+        ErrorCategory::Config,
+        format!("Invalid configuration: {}", problems.join("; ")),
+        Some(path.to_path_buf()),
+        "Run 'krill validate' to see where each problem is".to_string(),
+    )
+}
+
+/// Sockets left behind by daemons that no longer listen on them
+pub async fn check_sockets(sockets: &[PathBuf]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for socket in sockets {
+        if cfg!(windows) || krill_common::abstract_socket_name(socket).is_some() || !socket.exists()
+        {
+            continue;
+        }
+        // Not `is_daemon_running`, which removes stale sockets on its own
+        if daemon_manager::connect(socket).await.is_err() {
+            findings.push(
+                Finding::new(
+                    ErrorCategory::IpcServer,
+                    "Stale socket: no daemon is listening on it".to_string(),
+                    Some(socket.clone()),
+                    "Remove it with 'krill doctor --fix'; 'krill up' also replaces it".to_string(),
+                )
+                .stale(socket.clone()),
+            );
+        }
+    }
+    findings
+}
+
+/// Directory holding the state files of the workspaces
+pub fn state_dir() -> PathBuf {
+    StateFile::default_path("krill")
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// State files of daemons that are gone. A file whose services still run
+/// is kept for `krill up` to adopt them.
+pub fn check_state_files(dir: &Path) -> Vec<Finding> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut findings = Vec::new();
+    for path in paths {
+        let workspace = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let state = match StateFile::new(path.clone()).load() {
+            Ok(Some(state)) => state,
+            Ok(None) => continue,
+            Err(e) => {
+                findings.push(
+                    Finding::new(
+                        ErrorCategory::Orchestrator,
+                        format!("Unreadable state file of workspace '{}': {}", workspace, e),
+                        Some(path.clone()),
+                        "Remove it with 'krill doctor --fix'".to_string(),
+                    )
+                    .stale(path),
+                );
+                continue;
+            }
+        };
+        if state.daemon_alive() {
+            continue;
+        }
+
+        let live = state.live_services();
+        if live.is_empty() {
+            findings.push(
+                Finding::new(
+                    ErrorCategory::Orchestrator,
+                    format!(
+                        "Orphaned state file of workspace '{}': daemon PID {} is gone",
+                        workspace, state.daemon_pid
+                    ),
+                    Some(path.clone()),
+                    "Remove it with 'krill doctor --fix'".to_string(),
+                )
+                .stale(path),
+            );
+        } else {
+            let pids: Vec<String> = live
+                .iter()
+                .map(|(name, record)| format!("{} (PID {})", name, record.pid))
+                .collect();
+            findings.push(Finding::new(
+                ErrorCategory::Orchestrator,
+                format!(
+                    "Daemon PID {} of workspace '{}' is gone but its services still run: {}",
+                    state.daemon_pid,
+                    workspace,
+                    pids.join(", ")
+                ),
+                Some(path),
+                "Run 'krill up' for this workspace to adopt and manage them again".to_string(),
+            ));
+        }
+    }
+    findings
+}
+
+/// Program a shell command starts, unless it begins with a builtin, a
+/// variable or a quote that cannot be resolved without running a shell
+pub fn shell_program(command: &str) -> Option<&str> {
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|word| word.contains('=') && !word.starts_with('='));
+    let mut program = words.next()?;
+    if program == "exec" {
+        program = words.next()?;
+    }
+    if SHELL_BUILTINS.contains(&program) || program.starts_with(['$', '"', '\'', '`']) {
+        return None;
+    }
+    Some(program)
+}
+
+/// Executables the services of `config` start that cannot be found
+pub fn check_executables(config: &KrillConfig) -> Vec<Finding> {
+    let mut names: Vec<&String> = config.services.keys().collect();
+    names.sort();
+
+    let mut findings = Vec::new();
+    for name in names {
+        let execute = &config.services[name].execute;
+        let (program, hint) = match execute {
+            ExecuteConfig::Pixi { .. } => (
+                "pixi".to_string(),
+                "Install pixi (https://pixi.sh) and make sure it is in PATH",
+            ),
+            ExecuteConfig::Ros2 { .. } => (
+                "ros2".to_string(),
+                "Source your ROS 2 setup (e.g. /opt/ros/<distro>/setup.bash) before 'krill up'",
+            ),
+            ExecuteConfig::Docker { .. } => (
+                "docker".to_string(),
+                "Install Docker and make sure the 'docker' CLI is in PATH",
+            ),
+            ExecuteConfig::Podman { .. } => (
+                "podman".to_string(),
+                "Install Podman (https://podman.io) and make sure it is in PATH",
+            ),
+            ExecuteConfig::Compose { engine, .. } => (
+                engine.program().to_string(),
+                "Install the container engine and its compose plugin and make sure it is in PATH",
+            ),
+            ExecuteConfig::Shell { command, .. } => match shell_program(command) {
+                Some(program) => (
+                    program.to_string(),
+                    "Install the program or fix the command of the service",
+                ),
+                None => continue,
+            },
+            // Unregistered types are reported by config validation
+            ExecuteConfig::Custom(_) => match build_command(execute, &HashMap::new()) {
+                Ok(argv) if !argv.is_empty() => (
+                    argv[0].clone(),
+                    "Install the program the executor runs and make sure it is in PATH",
+                ),
+                _ => continue,
+            },
+        };
+        let working_dir = get_working_dir(execute);
+
+        if let Some(reason) = missing_executable(&program, working_dir.as_deref()) {
+            findings.push(Finding::new(
+                ErrorCategory::Config,
+                format!("Service '{}': {}", name, reason),
+                None,
+                hint.to_string(),
+            ));
+        }
+    }
+    findings
+}
+
+/// What keeps `program` from running: it is not in PATH, or its path is
+/// missing, a directory or not executable. Relative paths are resolved
+/// against `working_dir`.
+fn missing_executable(program: &str, working_dir: Option<&Path>) -> Option<String> {
+    if !program.contains('/') {
+        return find_executable(program)
+            .err()
+            .map(|_| format!("'{}' not found in PATH", program));
+    }
+
+    let path = match working_dir {
+        Some(dir) if Path::new(program).is_relative() => dir.join(program),
+        _ => PathBuf::from(program),
+    };
+    let Ok(metadata) = fs::metadata(&path) else {
+        return Some(format!("'{}' does not exist", path.display()));
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.is_file() && metadata.permissions().mode() & 0o111 == 0 {
+            return Some(format!("'{}' is not executable", path.display()));
+        }
+    }
+    if metadata.is_dir() {
+        return Some(format!("'{}' is a directory", path.display()));
+    }
+    None
+}
+
+/// A log directory the daemon cannot create or write its sessions in, found
+/// by writing a probe file to `dir`
+pub fn check_log_dir(dir: &Path) -> Option<Finding> {
+    let probe = dir.join(format!(".krill-doctor-{}", std::process::id()));
+    let result = fs::create_dir_all(dir).and_then(|()| fs::write(&probe, b""));
+    let _ = fs::remove_file(&probe);
+
+    result.err().map(|e| {
+        Finding::new(
+            ErrorCategory::LogStore,
+            format!("Log directory is not writable: {}", e),
+            Some(dir.to_path_buf()),
+            "Fix its permissions or set 'log_dir' in krill.yaml to a writable directory"
+                .to_string(),
+        )
+    })
+}
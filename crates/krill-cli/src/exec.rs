@@ -0,0 +1,118 @@
+// Command preparation for `krill exec`
+
+use anyhow::{bail, Context, Result};
+use krill_common::{container_name, get_working_dir, ExecuteConfig, KrillConfig};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A command set up to run in the environment of a service
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Variables set on top of the inherited environment
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Prepare `command` to run the way the daemon runs `service`: in its
+/// working directory, with its env file, the workspace `env`, its own `env`
+/// and its secrets applied, and inside its pixi environment. For container services the command is
+/// run in the service's running container with `docker exec` (or
+/// `podman exec`, or `docker compose exec` in the first compose service),
+/// allocating a terminal if `tty` is set.
+pub fn prepare(
+    config: &KrillConfig,
+    service: &str,
+    command: &[String],
+    tty: bool,
+) -> Result<ExecCommand> {
+    let Some(service_config) = config.services.get(service) else {
+        bail!(
+            "Unknown service '{}' in workspace '{}'",
+            service,
+            config.name
+        );
+    };
+    if command.is_empty() {
+        bail!("No command given, e.g. `krill exec {} -- bash`", service);
+    }
+
+    let mut env = service_config
+        .environment(&config.env)
+        .with_context(|| format!("Failed to load env_file of '{}'", service))?;
+    env.extend(krill_common::read_secrets(&service_config.secrets)?);
+    env.insert("KRILL_SERVICE_NAME".to_string(), service.to_string());
+
+    let mut argv: Vec<String> = match &service_config.execute {
+        ExecuteConfig::Pixi { environment, .. } => {
+            let mut argv = vec!["pixi".to_string(), "run".to_string()];
+            if let Some(environment) = environment {
+                argv.push("-e".to_string());
+                argv.push(environment.clone());
+            }
+            argv.extend(command.iter().cloned());
+            argv
+        }
+        ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. } => {
+            // The container already has the service's environment
+            let engine = service_config
+                .execute
+                .container_engine()
+                .unwrap_or_default();
+            let mut argv = vec![
+                engine.program().to_string(),
+                "exec".to_string(),
+                "-i".to_string(),
+            ];
+            if tty {
+                argv.push("-t".to_string());
+            }
+            argv.push(container_name(&config.name, service));
+            argv.extend(command.iter().cloned());
+            env.clear();
+            argv
+        }
+        ExecuteConfig::Compose {
+            file,
+            services,
+            exit_code_from,
+            engine,
+            ..
+        } => {
+            let Some(target) = exit_code_from.as_ref().or(services.first()) else {
+                bail!(
+                    "Service '{}' does not name a compose service to enter; set execute.services",
+                    service
+                );
+            };
+            let mut argv = vec![
+                engine.program().to_string(),
+                "compose".to_string(),
+                "-f".to_string(),
+                file.display().to_string(),
+                "-p".to_string(),
+                container_name(&config.name, service),
+                "exec".to_string(),
+            ];
+            if !tty {
+                argv.push("-T".to_string());
+            }
+            argv.push(target.clone());
+            argv.extend(command.iter().cloned());
+            env.clear();
+            argv
+        }
+        ExecuteConfig::Ros2 { .. } | ExecuteConfig::Shell { .. } | ExecuteConfig::Custom(_) => {
+            command.to_vec()
+        }
+    };
+
+    let program = argv.remove(0);
+    Ok(ExecCommand {
+        program,
+        args: argv,
+        env,
+        working_dir: get_working_dir(&service_config.execute),
+    })
+}
@@ -0,0 +1,143 @@
+// Dependency graph rendering for `krill graph`
+
+use krill_common::{DagError, DependencyCondition, DependencyGraph, KrillConfig};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+fn condition_label(condition: DependencyCondition) -> &'static str {
+    match condition {
+        DependencyCondition::Started => "started",
+        DependencyCondition::Healthy => "healthy",
+        DependencyCondition::Completed => "completed",
+        DependencyCondition::Failed => "failed",
+    }
+}
+
+/// Condition `dependent` waits for on `service`
+fn edge_condition(graph: &DependencyGraph, dependent: &str, service: &str) -> DependencyCondition {
+    graph
+        .dependencies(dependent)
+        .iter()
+        .find(|dep| dep.service_name() == service)
+        .map_or(DependencyCondition::Started, |dep| dep.condition())
+}
+
+fn is_critical(config: &KrillConfig, service: &str) -> bool {
+    config.services.get(service).is_some_and(|s| s.critical)
+}
+
+fn sorted_services(config: &KrillConfig) -> Vec<&str> {
+    let mut names: Vec<&str> = config.services.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    names
+}
+
+/// Render the graph in Graphviz DOT format, with edges pointing from a
+/// dependency to the services that wait on it
+pub fn render_dot(config: &KrillConfig) -> Result<String, DagError> {
+    let graph = config.dependency_graph()?;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "digraph \"{}\" {{", config.name);
+    let _ = writeln!(out, "    rankdir=LR;");
+    let _ = writeln!(out, "    node [shape=box];");
+
+    let services = sorted_services(config);
+    for service in &services {
+        if is_critical(config, service) {
+            let _ = writeln!(
+                out,
+                "    \"{}\" [label=\"{} (critical)\", color=red, penwidth=2];",
+                service, service
+            );
+        } else {
+            let _ = writeln!(out, "    \"{}\";", service);
+        }
+    }
+
+    for service in &services {
+        for dependent in graph.dependents(service) {
+            let condition = edge_condition(&graph, dependent, service);
+            let style = match condition {
+                DependencyCondition::Started => "dashed",
+                DependencyCondition::Healthy => "solid",
+                DependencyCondition::Completed => "dotted",
+                DependencyCondition::Failed => "bold",
+            };
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [label=\"{}\", style={}];",
+                service,
+                dependent,
+                condition_label(condition),
+                style
+            );
+        }
+    }
+
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// Render the graph as an ASCII tree rooted at services without
+/// dependencies; children are the services that wait on their parent.
+/// Subtrees already printed are shown once and marked `(*)`
+pub fn render_tree(config: &KrillConfig) -> Result<String, DagError> {
+    let graph = config.dependency_graph()?;
+    let mut out = String::new();
+    let mut expanded = HashSet::new();
+
+    let roots = sorted_services(config)
+        .into_iter()
+        .filter(|service| graph.dependencies(service).is_empty());
+
+    for root in roots {
+        out.push_str(root);
+        if is_critical(config, root) {
+            out.push_str(" [critical]");
+        }
+        out.push('\n');
+        expanded.insert(root.to_string());
+        write_children(config, &graph, root, "", &mut expanded, &mut out);
+    }
+
+    Ok(out)
+}
+
+fn write_children(
+    config: &KrillConfig,
+    graph: &DependencyGraph,
+    service: &str,
+    prefix: &str,
+    expanded: &mut HashSet<String>,
+    out: &mut String,
+) {
+    let dependents = graph.dependents(service);
+    let count = dependents.len();
+
+    for (i, dependent) in dependents.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let condition = edge_condition(graph, dependent, service);
+
+        let _ = write!(
+            out,
+            "{}{}{} ({})",
+            prefix,
+            if last { "└── " } else { "├── " },
+            dependent,
+            condition_label(condition)
+        );
+        if is_critical(config, dependent) {
+            out.push_str(" [critical]");
+        }
+
+        if !expanded.insert(dependent.to_string()) {
+            out.push_str(" (*)\n");
+            continue;
+        }
+        out.push('\n');
+
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        write_children(config, graph, dependent, &child_prefix, expanded, out);
+    }
+}
@@ -1,2 +1,10 @@
 pub mod config_discovery;
 pub mod daemon_manager;
+pub mod doctor;
+pub mod exec;
+pub mod graph;
+pub mod plan;
+pub mod remote;
+pub mod status;
+pub mod top;
+pub mod validate;
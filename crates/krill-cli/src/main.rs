@@ -3,11 +3,20 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::io;
+use std::process::ExitCode;
 use tracing_subscriber::{fmt, EnvFilter};
 
 mod commands;
 mod config_discovery;
 mod daemon_manager;
+mod doctor;
+mod exec;
+mod graph;
+mod plan;
+mod remote;
+mod status;
+mod top;
+mod validate;
 
 #[derive(Parser, Debug)]
 #[command(name = "krill")]
@@ -30,23 +39,103 @@ enum Commands {
     /// Stop all services and the daemon
     Down(commands::DownArgs),
 
-    /// Attach TUI to running daemon
+    /// Attach TUI to running daemon, or print the services as JSON
     Ps(commands::PsArgs),
 
+    /// Print each service's status; the exit code tells whether any (critical) service failed
+    Status(commands::StatusArgs),
+
     /// View logs
     Logs(commands::LogsArgs),
 
+    /// Reload the configuration without restarting the daemon, or signal a service to reload
+    Reload(commands::ReloadArgs),
+
+    /// Start stopped services, by name or label selector
+    Start(commands::StartArgs),
+
+    /// Restart services, by name or label selector
+    Restart(commands::RestartArgs),
+
+    /// Kill services with SIGKILL, skipping their stop command and grace period
+    Kill(commands::KillArgs),
+
+    /// Emergency stop all services, or clear a service's safety stop
+    Estop(commands::EstopArgs),
+
+    /// Freeze services with SIGSTOP without losing their state, e.g. a mapping node during teleop
+    Pause(commands::PauseArgs),
+
+    /// Continue paused services, or without any resume the automatic restarts a restart storm paused
+    Resume(commands::ResumeArgs),
+
+    /// Show the history of service status changes
+    Events(commands::EventsArgs),
+
+    /// Show each service's uptime, downtime, failures and MTBF since the daemon started
+    Stats(commands::StatsArgs),
+
+    /// Show services, their status, CPU, memory and restarts, refreshed in place
+    Top(commands::TopArgs),
+
+    /// Change the daemon's log level or per-module filter until it exits
+    LogLevel(commands::LogLevelArgs),
+
+    /// Review a recorded log session in the TUI, with play/pause and seeking
+    Replay(commands::ReplayArgs),
+
+    /// Show the control commands clients sent, who sent them, and the outcome
+    Audit(commands::AuditArgs),
+
+    /// Check for stale sockets, orphaned state, missing executables and log dir permissions
+    Doctor(commands::DoctorArgs),
+
+    /// Run a command in a service's environment (working dir, env, pixi environment)
+    Exec(commands::ExecArgs),
+
+    /// Show the service dependency graph as a tree or Graphviz DOT
+    Graph(commands::GraphArgs),
+
+    /// Preview the staged startup order without starting anything
+    Plan(commands::PlanArgs),
+
+    /// Check a recipe for errors without starting anything
+    Validate(commands::ValidateArgs),
+
     /// Run daemon directly (internal use)
     #[command(hide = true)]
     Daemon(commands::DaemonArgs),
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    match start(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // A command that already printed its result only sets the code
+            if e.downcast_ref::<status::Exit>().is_none() {
+                eprintln!("Error: {:?}", e);
+            }
+            ExitCode::from(status::exit_code(&e))
+        }
+    }
+}
+
+fn start(mut cli: Cli) -> Result<()> {
+    // The daemon leaves the terminal's session before the runtime starts its
+    // threads, the only point where forking is safe
+    if let Some(Commands::Daemon(args)) = &mut cli.command {
+        args.notifier = krill_daemon::watchdog::SystemdNotifier::from_env();
+        commands::daemon::detach(args)?;
+        commands::daemon::export_socket(args);
+    }
+
+    tokio::runtime::Runtime::new()?.block_on(run(cli))
+}
 
+async fn run(cli: Cli) -> Result<()> {
     // Initialize tracing for CLI commands only (daemon initializes its own)
-    let is_daemon_command = matches!(cli.command, Some(Commands::Daemon(_)));
+    let is_daemon_command =
+        matches!(&cli.command, Some(Commands::Daemon(args)) if args.command.is_none());
 
     if !is_daemon_command {
         let filter = if cli.verbose {
@@ -67,10 +156,22 @@ async fn main() -> Result<()> {
         Some(cmd) => cmd,
         None => {
             // Check if daemon is running
-            let socket = std::path::PathBuf::from("/tmp/krill.sock");
-            if crate::daemon_manager::is_daemon_running(&socket).await {
+            let socket = crate::config_discovery::resolve_socket(None, None).ok();
+            let running = match socket {
+                Some(ref socket) => crate::daemon_manager::is_daemon_running(socket).await,
+                None => false,
+            };
+            if running {
                 // Attach to running daemon
-                Commands::Ps(commands::PsArgs { socket })
+                Commands::Ps(commands::PsArgs {
+                    socket,
+                    workspace: None,
+                    connect: None,
+                    remote: None,
+                    remote_krill: None,
+                    stdio: false,
+                    output: Default::default(),
+                })
             } else {
                 // No daemon running, show help
                 use clap::CommandFactory;
@@ -85,7 +186,26 @@ async fn main() -> Result<()> {
         Commands::Up(args) => commands::up(args).await,
         Commands::Down(args) => commands::down(args).await,
         Commands::Ps(args) => commands::ps(args).await,
+        Commands::Status(args) => commands::status(args).await,
         Commands::Logs(args) => commands::logs(args).await,
+        Commands::Reload(args) => commands::reload(args).await,
+        Commands::Start(args) => commands::start(args).await,
+        Commands::Restart(args) => commands::restart(args).await,
+        Commands::Kill(args) => commands::kill(args).await,
+        Commands::Estop(args) => commands::estop(args).await,
+        Commands::Pause(args) => commands::pause(args).await,
+        Commands::Resume(args) => commands::resume(args).await,
+        Commands::Validate(args) => commands::validate(args).await,
+        Commands::Events(args) => commands::events(args).await,
+        Commands::Stats(args) => commands::stats(args).await,
+        Commands::Top(args) => commands::top(args).await,
+        Commands::LogLevel(args) => commands::log_level(args).await,
+        Commands::Replay(args) => commands::replay(args).await,
+        Commands::Audit(args) => commands::audit(args).await,
+        Commands::Doctor(args) => commands::doctor(args).await,
+        Commands::Exec(args) => commands::exec(args).await,
+        Commands::Graph(args) => commands::graph(args).await,
+        Commands::Plan(args) => commands::plan(args).await,
         Commands::Daemon(args) => commands::daemon(args).await,
     }
 }
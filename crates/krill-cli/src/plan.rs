@@ -0,0 +1,180 @@
+// Startup plan rendering for `krill plan`
+
+use krill_common::{DagError, DependencyCondition, HealthChecker, KrillConfig, ServiceConfig};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Group services into startup stages. A service lands one stage after its
+/// latest dependency, so the services of a stage can start in parallel.
+pub fn stages(config: &KrillConfig) -> Result<Vec<Vec<String>>, DagError> {
+    let graph = config.dependency_graph()?;
+    let mut level: HashMap<String, usize> = HashMap::new();
+    let mut stages: Vec<Vec<String>> = Vec::new();
+
+    for service in graph.startup_order()? {
+        let stage = graph
+            .dependencies(&service)
+            .iter()
+            .filter_map(|dep| level.get(dep.service_name()))
+            .map(|l| l + 1)
+            .max()
+            .unwrap_or(0);
+
+        if stages.len() <= stage {
+            stages.resize_with(stage + 1, Vec::new);
+        }
+        stages[stage].push(service.clone());
+        level.insert(service, stage);
+    }
+
+    for stage in &mut stages {
+        stage.sort_unstable();
+    }
+    Ok(stages)
+}
+
+fn condition_label(condition: DependencyCondition) -> &'static str {
+    match condition {
+        DependencyCondition::Started => "started",
+        DependencyCondition::Healthy => "healthy",
+        DependencyCondition::Completed => "completed",
+        DependencyCondition::Failed => "failed",
+    }
+}
+
+fn checker_label(checker: &HealthChecker) -> String {
+    match checker {
+        HealthChecker::Heartbeat { timeout, .. } => {
+            format!("heartbeat within {}", humantime::format_duration(*timeout))
+        }
+        HealthChecker::Tcp { port, .. } => format!("tcp port {}", port),
+        HealthChecker::Http { port, path, .. } => format!("http :{}{}", port, path),
+        HealthChecker::Script { command, .. } => format!("script `{}`", command),
+        HealthChecker::Lifecycle { node, .. } => format!("lifecycle node {} active", node),
+    }
+}
+
+/// What a `healthy` dependent of the service waits for
+fn health_gate(service: &ServiceConfig) -> String {
+    if service.oneshot {
+        "exits successfully".to_string()
+    } else if let Some(readiness) = &service.readiness {
+        format!("readiness {}", checker_label(readiness))
+    } else if let Some(health_check) = &service.health_check {
+        format!("health {}", checker_label(health_check))
+    } else {
+        "process running".to_string()
+    }
+}
+
+/// Longest a service can take to become ready: its start delay plus its
+/// startup timeout. `None` when it has no `start_timeout_sec`.
+fn readiness_bound(service: &ServiceConfig) -> Option<Duration> {
+    let timeout = Duration::from_secs(service.start_timeout_sec?);
+    Some(service.start_delay.unwrap_or_default() + timeout)
+}
+
+/// Render the staged startup plan: each stage with its services, what they
+/// wait for, and how they become ready, followed by a worst-case estimate of
+/// the time to bring everything up
+pub fn render_plan(config: &KrillConfig) -> Result<String, DagError> {
+    let graph = config.dependency_graph()?;
+    let stages = stages(config)?;
+    let standby = graph.standby_services();
+    let mut out = String::new();
+    let mut total = Some(Duration::ZERO);
+
+    let _ = writeln!(
+        out,
+        "Plan for '{}': {} service(s) in {} stage(s)",
+        config.name,
+        config.services.len(),
+        stages.len()
+    );
+
+    for (i, stage) in stages.iter().enumerate() {
+        let _ = write!(out, "\nStage {}", i + 1);
+        if stage.len() > 1 {
+            let _ = write!(out, " ({} in parallel)", stage.len());
+        }
+        out.push('\n');
+
+        let mut stage_bound = Some(Duration::ZERO);
+        for name in stage {
+            let Some(service) = config.services.get(name) else {
+                continue;
+            };
+
+            let _ = write!(out, "  + {}", name);
+            if service.critical {
+                out.push_str(" [critical]");
+            }
+            if service.oneshot {
+                out.push_str(" [oneshot]");
+            }
+            if standby.contains(name) {
+                out.push_str(" [fallback]");
+            }
+            out.push('\n');
+
+            let dependencies = graph.dependencies(name);
+            if !dependencies.is_empty() {
+                let waits: Vec<String> = dependencies
+                    .iter()
+                    .map(|dep| {
+                        format!(
+                            "{} ({})",
+                            dep.service_name(),
+                            condition_label(dep.condition())
+                        )
+                    })
+                    .collect();
+                let _ = writeln!(out, "      waits for: {}", waits.join(", "));
+            }
+            if let Some(delay) = service.start_delay {
+                let _ = writeln!(
+                    out,
+                    "      start delay: {}",
+                    humantime::format_duration(delay)
+                );
+            }
+            let _ = write!(out, "      ready when: {}", health_gate(service));
+            if let Some(timeout) = service.start_timeout_sec {
+                let _ = write!(out, " (timeout {}s)", timeout);
+            }
+            out.push('\n');
+
+            // Fallbacks only start when a dependency fails
+            if standby.contains(name) {
+                continue;
+            }
+            stage_bound = match (stage_bound, readiness_bound(service)) {
+                (Some(bound), Some(service_bound)) => Some(bound.max(service_bound)),
+                _ => None,
+            };
+        }
+
+        total = total.zip(stage_bound).map(|(total, bound)| total + bound);
+    }
+
+    out.push('\n');
+    match total {
+        Some(total) => {
+            let _ = writeln!(
+                out,
+                "Worst case to ready: {}",
+                humantime::format_duration(total)
+            );
+        }
+        None => {
+            let _ = writeln!(
+                out,
+                "Worst case to ready: unbounded (not every service sets start_timeout_sec)"
+            );
+        }
+    }
+    out.push_str("Nothing was started.\n");
+
+    Ok(out)
+}
@@ -0,0 +1,107 @@
+// Remote access for `krill ps --remote`, relaying a robot's daemon over SSH
+
+use crate::daemon_manager;
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Where `krill ps --remote` finds the daemon on the remote machine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RemoteTarget<'a> {
+    /// `[user@]host`, or a host alias from `~/.ssh/config`
+    pub host: &'a str,
+    /// krill executable on the remote machine, `krill` by default
+    pub krill: Option<&'a str>,
+    pub socket: Option<&'a Path>,
+    pub workspace: Option<&'a str>,
+}
+
+/// The `ssh` command running `krill ps --stdio` on the remote machine. Its
+/// stdin and stdout then carry the IPC connection to the daemon there, so no
+/// socket path has to be known or forwarded locally. Authentication, host
+/// keys and options come from the user's SSH setup.
+pub fn ssh_command(target: &RemoteTarget) -> Vec<String> {
+    let mut remote = vec![target.krill.unwrap_or("krill").to_string()];
+    remote.extend(["ps", "--stdio"].map(String::from));
+    if let Some(socket) = target.socket {
+        remote.push("--socket".to_string());
+        remote.push(socket.display().to_string());
+    }
+    if let Some(workspace) = target.workspace {
+        remote.push("--workspace".to_string());
+        remote.push(workspace.to_string());
+    }
+
+    // ssh hands the command to the remote user's shell as one line
+    let remote = remote
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    [
+        "ssh",
+        "-T",
+        "-o",
+        "ServerAliveInterval=15",
+        target.host,
+        "--",
+    ]
+    .into_iter()
+    .map(String::from)
+    .chain([remote])
+    .collect()
+}
+
+/// Quote `arg` for a POSIX shell, unless it only has characters that need
+/// none. A leading `~/` stays unquoted, so the remote shell expands it to
+/// the home directory (e.g. `--remote-krill ~/.pixi/bin/krill`).
+fn shell_quote(arg: &str) -> String {
+    if let Some(path) = arg.strip_prefix("~/") {
+        return match path {
+            "" => "~/".to_string(),
+            path => format!("~/{}", shell_quote(path)),
+        };
+    }
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Relay the connection to the daemon on `socket` over stdin and stdout,
+/// until either side closes it
+pub async fn relay_stdio(socket: &Path) -> Result<()> {
+    relay(socket, tokio::io::stdin(), tokio::io::stdout()).await
+}
+
+/// Relay the connection to the daemon on `socket` over `input` and
+/// `output`, until either side closes it
+pub async fn relay<R, W>(socket: &Path, mut input: R, mut output: W) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let daemon = daemon_manager::connect(socket)
+        .await
+        .context("Failed to connect to daemon")?;
+    let (mut from_daemon, mut to_daemon) = tokio::io::split(daemon);
+
+    // The daemon keeps a connection open after the client's requests end,
+    // so the relay stops as soon as either direction does
+    let relayed = tokio::select! {
+        sent = tokio::io::copy(&mut input, &mut to_daemon) => sent,
+        received = tokio::io::copy(&mut from_daemon, &mut output) => received,
+    };
+    match relayed {
+        // The SSH session ended while the daemon was talking
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        relayed => relayed
+            .map(drop)
+            .context("Relaying the daemon connection failed"),
+    }
+}
@@ -0,0 +1,248 @@
+// Service status summaries and exit codes, for scripts gating on the orchestrator
+
+use krill_common::{ServiceShutdown, ServiceSnapshot, ServiceStatus};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+/// Everything is up, or stopped on purpose
+pub const EXIT_OK: u8 = 0;
+/// The command failed (2 is left to argument errors)
+pub const EXIT_FAILURE: u8 = 1;
+/// No daemon runs for the workspace
+pub const EXIT_DAEMON_NOT_RUNNING: u8 = 3;
+/// A service is failed, blocked or degraded
+pub const EXIT_UNHEALTHY: u8 = 4;
+/// A critical service is failed or blocked
+pub const EXIT_CRITICAL_FAILED: u8 = 5;
+
+/// How a command prints its result
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON for scripts
+    Json,
+}
+
+/// No daemon answers on the workspace's socket
+#[derive(Debug, Default)]
+pub struct DaemonNotRunning {
+    /// Workspace the daemon was looked up by, if one was named
+    pub workspace: Option<String>,
+}
+
+impl fmt::Display for DaemonNotRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.workspace {
+            Some(ref name) => write!(f, "No daemon is running workspace '{}'", name),
+            None => write!(f, "Daemon is not running. Start it with 'krill up'"),
+        }
+    }
+}
+
+impl std::error::Error for DaemonNotRunning {}
+
+/// Ends the command with an exit code and no further message, after it
+/// printed its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit(pub u8);
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit code {}", self.0)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// Exit code for a command that failed with `err`
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    if let Some(Exit(code)) = err.downcast_ref::<Exit>() {
+        *code
+    } else if err.downcast_ref::<DaemonNotRunning>().is_some() {
+        EXIT_DAEMON_NOT_RUNNING
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// Overall health of the services
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Ok,
+    /// A service is failed, blocked or degraded
+    Unhealthy,
+    /// A critical service is failed or blocked
+    CriticalFailed,
+}
+
+impl Health {
+    pub fn of(services: &HashMap<String, ServiceSnapshot>) -> Self {
+        let down = |s: &ServiceSnapshot| {
+            matches!(s.status, ServiceStatus::Failed | ServiceStatus::Blocked)
+        };
+        if services.values().any(|s| s.config.critical && down(s)) {
+            Health::CriticalFailed
+        } else if services
+            .values()
+            .any(|s| down(s) || s.status == ServiceStatus::Degraded)
+        {
+            Health::Unhealthy
+        } else {
+            Health::Ok
+        }
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Health::Ok => EXIT_OK,
+            Health::Unhealthy => EXIT_UNHEALTHY,
+            Health::CriticalFailed => EXIT_CRITICAL_FAILED,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Health::Ok => "ok",
+            Health::Unhealthy => "unhealthy",
+            Health::CriticalFailed => "critical_failed",
+        }
+    }
+}
+
+/// Services as a JSON object keyed by name, in name order
+pub fn services_json(services: &HashMap<String, ServiceSnapshot>) -> serde_json::Value {
+    let sorted: BTreeMap<&String, &ServiceSnapshot> = services.iter().collect();
+    serde_json::to_value(sorted).unwrap_or_default()
+}
+
+/// `krill status --output json`: the overall health, its exit code, and
+/// each service's snapshot
+pub fn status_json(services: &HashMap<String, ServiceSnapshot>) -> serde_json::Value {
+    let health = Health::of(services);
+    serde_json::json!({
+        "health": health.as_str(),
+        "exit_code": health.exit_code(),
+        "services": services_json(services),
+    })
+}
+
+/// `krill status`: one row per service, sorted by name, and the health
+pub fn render_status(services: &HashMap<String, ServiceSnapshot>) -> String {
+    let mut sorted: Vec<_> = services.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut out = format!(
+        "{:<24} {:<10} {:>8} {:>8} {:>10}  ERROR\n",
+        "SERVICE", "STATUS", "PID", "RESTARTS", "UPTIME"
+    );
+    for (name, service) in sorted {
+        let name = if service.config.critical {
+            format!("{}*", name)
+        } else {
+            name.clone()
+        };
+        let row = format!(
+            "{:<24} {:<10} {:>8} {:>8} {:>10}  {}",
+            name,
+            format!("{:?}", service.status),
+            service
+                .pid
+                .map(|pid| pid.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            service.restart_count,
+            service
+                .uptime
+                .map(|uptime| {
+                    humantime::format_duration(Duration::from_secs(uptime.as_secs())).to_string()
+                })
+                .unwrap_or_else(|| "-".to_string()),
+            service.last_error.as_deref().unwrap_or("")
+        );
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+
+    let summary = match Health::of(services) {
+        Health::Ok => "All services ok",
+        Health::Unhealthy => "Some services are failed, blocked or degraded",
+        Health::CriticalFailed => "A critical service (*) is failed",
+    };
+    out.push_str(&format!("\n{}\n", summary));
+
+    let paused: BTreeSet<&str> = services
+        .values()
+        .filter(|service| service.restarts_paused)
+        .map(|service| service.namespace.as_str())
+        .collect();
+    for workspace in paused {
+        out.push_str(&format!(
+            "Automatic restarts of '{0}' are paused by a restart storm; 'krill resume --workspace {0}' resumes them\n",
+            workspace
+        ));
+    }
+    out
+}
+
+/// `krill down --output json`: how each service was stopped, in the order
+/// they stopped, with durations in milliseconds
+pub fn shutdown_json(services: &[ServiceShutdown]) -> serde_json::Value {
+    let services: Vec<serde_json::Value> = services
+        .iter()
+        .map(|service| {
+            serde_json::json!({
+                "service": service.service,
+                "method": service.method,
+                "duration_ms": service.duration.as_millis() as u64,
+                "exit_code": service.exit_code,
+                "signal": service.signal,
+                "errors": service.errors,
+            })
+        })
+        .collect();
+    serde_json::json!({ "services": services })
+}
+
+/// `krill down`: one row per stopped service in the order they stopped,
+/// and the one that held up the shutdown longest
+pub fn render_shutdown_report(services: &[ServiceShutdown]) -> String {
+    if services.is_empty() {
+        return "No services were running\n".to_string();
+    }
+
+    let mut out = format!(
+        "{:<24} {:<14} {:>8} {:>10}  ERRORS\n",
+        "SERVICE", "METHOD", "TIME", "EXIT"
+    );
+    for service in services {
+        let exit = match (service.exit_code, service.signal) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(signal)) => format!("signal {}", signal),
+            (None, None) => "-".to_string(),
+        };
+        let row = format!(
+            "{:<24} {:<14} {:>8} {:>10}  {}",
+            service.service,
+            service
+                .method
+                .map(|method| method.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format!("{:.2}s", service.duration.as_secs_f64()),
+            exit,
+            service.errors.join("; ")
+        );
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+
+    if let Some(slowest) = services.iter().max_by_key(|service| service.duration) {
+        out.push_str(&format!(
+            "\nSlowest to stop: {} ({:.2}s)\n",
+            slowest.service,
+            slowest.duration.as_secs_f64()
+        ));
+    }
+    out
+}
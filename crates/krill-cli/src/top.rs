@@ -0,0 +1,77 @@
+// Resource table rendering for `krill top`
+
+use krill_common::{format_memory_size, ServiceSnapshot};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Parse a refresh interval such as `2s` or `500ms`; zero would refresh in
+/// a busy loop
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let interval = humantime::parse_duration(value).map_err(|e| e.to_string())?;
+    if interval.is_zero() {
+        return Err("must be longer than zero".to_string());
+    }
+    Ok(interval)
+}
+
+/// Summary line and one row per service, sorted by name
+pub fn render(services: &HashMap<String, ServiceSnapshot>) -> Vec<String> {
+    let mut services: Vec<_> = services.iter().collect();
+    services.sort_by(|a, b| a.0.cmp(b.0));
+
+    let running = services.iter().filter(|(_, s)| s.pid.is_some()).count();
+    let cpu: f32 = services
+        .iter()
+        .filter_map(|(_, s)| s.metrics.as_ref())
+        .map(|m| m.cpu_percent)
+        .sum();
+    let memory: u64 = services
+        .iter()
+        .filter_map(|(_, s)| s.metrics.as_ref())
+        .map(|m| m.memory_bytes)
+        .sum();
+
+    let mut lines = vec![
+        format!(
+            "krill top - {}  {} services, {} running  CPU {:.1}%  MEM {}",
+            chrono::Local::now().format("%H:%M:%S"),
+            services.len(),
+            running,
+            cpu,
+            format_memory_size(memory)
+        ),
+        String::new(),
+        format!(
+            "{:<24} {:<10} {:>8} {:>7} {:>11} {:>8} {:>10}",
+            "SERVICE", "STATUS", "PID", "CPU%", "MEM", "RESTARTS", "UPTIME"
+        ),
+    ];
+    for (name, service) in services {
+        let (cpu, memory) = match service.metrics {
+            Some(ref metrics) => (
+                format!("{:.1}", metrics.cpu_percent),
+                format_memory_size(metrics.memory_bytes),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        lines.push(format!(
+            "{:<24} {:<10} {:>8} {:>7} {:>11} {:>8} {:>10}",
+            name,
+            format!("{:?}", service.status),
+            service
+                .pid
+                .map(|pid| pid.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            cpu,
+            memory,
+            service.restart_count,
+            service
+                .uptime
+                .map(|uptime| {
+                    humantime::format_duration(Duration::from_secs(uptime.as_secs())).to_string()
+                })
+                .unwrap_or_else(|| "-".to_string())
+        ));
+    }
+    lines
+}
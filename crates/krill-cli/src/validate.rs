@@ -0,0 +1,174 @@
+// Recipe linting for `krill validate`
+
+use krill_common::{DagError, KrillConfig};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A problem found in a recipe, positioned in the source when known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Included file the problem is in; `None` for the recipe itself
+    pub file: Option<PathBuf>,
+    /// 1-based line and column, in `file` if set
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Run the checks `krill up` would: parsing, field validation, shell command
+/// safety, dependency targets, and dependency cycles. Returns the config if it
+/// is valid, or every problem found
+pub fn check(content: &str, base_dir: Option<&Path>) -> Result<KrillConfig, Vec<Diagnostic>> {
+    let config = match KrillConfig::parse(content, base_dir) {
+        Ok(config) => config,
+        Err(e) => {
+            // The position is reported separately, drop it from the message
+            let message = e.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(index) if e.location().is_some() => message[..index].to_string(),
+                _ => message,
+            };
+            return Err(vec![Diagnostic {
+                file: None,
+                position: e.location().map(|l| (l.line(), l.column())),
+                message,
+            }]);
+        }
+    };
+
+    let sources = Sources::new(content, &config.included);
+    let mut diagnostics: Vec<Diagnostic> = config
+        .issues()
+        .into_iter()
+        .map(|issue| {
+            let path: Vec<&str> = issue.path.iter().map(String::as_str).collect();
+            let (file, position) = sources.locate(&path);
+            Diagnostic {
+                file,
+                position,
+                message: issue.error.to_string(),
+            }
+        })
+        .collect();
+
+    // Cycles are only meaningful once every dependency target exists
+    if diagnostics.is_empty() {
+        if let Err(e) = config.dependency_graph() {
+            let (file, position) = match e {
+                DagError::CircularDependency(ref cycle) => match cycle.split(" -> ").next() {
+                    Some(service) => sources.locate(&["services", service, "dependencies"]),
+                    None => (None, None),
+                },
+                _ => (None, None),
+            };
+            diagnostics.push(Diagnostic {
+                file,
+                position,
+                message: e.to_string(),
+            });
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(config)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// The recipe and the files it includes, to find which one a setting of
+/// the merged recipe came from
+struct Sources<'a> {
+    content: &'a str,
+    /// Included files and their content, in merge order
+    included: Vec<(PathBuf, String)>,
+}
+
+impl<'a> Sources<'a> {
+    fn new(content: &'a str, included: &[PathBuf]) -> Self {
+        let included = included
+            .iter()
+            .filter_map(|file| Some((file.clone(), std::fs::read_to_string(file).ok()?)))
+            .collect();
+        Self { content, included }
+    }
+
+    /// File and position of the deepest key of `path`. Files merged later
+    /// override earlier ones, and the recipe overrides them all, so on equal
+    /// depth the later file wins.
+    fn locate(&self, path: &[&str]) -> (Option<PathBuf>, Option<(usize, usize)>) {
+        if self.included.is_empty() {
+            return (None, locate(self.content, path));
+        }
+        let mut best = (None, locate_depth(self.content, path));
+        for (file, content) in self.included.iter().rev() {
+            let found = locate_depth(content, path);
+            let depth =
+                |found: Option<((usize, usize), usize)>| found.map_or(0, |(_, depth)| depth);
+            if depth(found) > depth(best.1) {
+                best = (Some(file.clone()), found);
+            }
+        }
+        (best.0, best.1.map(|(position, _)| position))
+    }
+}
+
+/// Position of the deepest key of `path` found in block-style YAML, e.g.
+/// `["services", "lidar", "dependencies"]`
+pub fn locate(content: &str, path: &[&str]) -> Option<(usize, usize)> {
+    locate_depth(content, path).map(|(position, _)| position)
+}
+
+/// Like [`locate`], also returning how many keys of `path` were found
+fn locate_depth(content: &str, path: &[&str]) -> Option<((usize, usize), usize)> {
+    let mut found = None;
+    let mut depth = 0;
+    let mut parent_indent: Option<usize> = None;
+    // Indentation of the keys directly under the current parent
+    let mut child_indent: Option<usize> = None;
+    let mut keys = path.iter();
+    let mut key = keys.next()?;
+
+    for (number, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+
+        // Left the parent's block without finding the key
+        if parent_indent.is_some_and(|parent| indent <= parent) {
+            break;
+        }
+        if *child_indent.get_or_insert(indent) != indent {
+            continue;
+        }
+
+        let name = trimmed
+            .split_once(':')
+            .map(|(name, _)| name.trim().trim_matches(|c| c == '"' || c == '\''));
+        if name == Some(*key) {
+            found = Some((number + 1, indent + 1));
+            depth += 1;
+            match keys.next() {
+                Some(next) => key = next,
+                None => break,
+            }
+            parent_indent = Some(indent);
+            child_indent = None;
+        }
+    }
+
+    found.map(|position| (position, depth))
+}
@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 use tempfile::NamedTempFile;
 
-use krill_cli::config_discovery::discover_config;
+use krill_cli::config_discovery::{discover_config, resolve_socket, workspace_sockets};
+use krill_common::workspace_socket_path;
 
 #[test]
 fn cli_argument_with_existing_file_returns_ok() {
@@ -137,3 +138,43 @@ fn cli_argument_takes_priority_over_env_var() {
         "CLI argument should take priority over KRILL_CONFIG env var"
     );
 }
+
+#[test]
+fn socket_argument_takes_priority_over_workspace() {
+    let socket = PathBuf::from("/tmp/krill_test_explicit.sock");
+    let result = resolve_socket(Some(socket.clone()), Some("robot")).unwrap();
+    assert_eq!(result, socket);
+}
+
+#[test]
+fn workspace_argument_selects_workspace_socket() {
+    let result = resolve_socket(None, Some("robot")).unwrap();
+    assert_eq!(result, workspace_socket_path("robot").unwrap());
+}
+
+#[test]
+fn workspace_argument_must_be_a_workspace_name() {
+    assert!(resolve_socket(None, Some("../robot")).is_err());
+}
+
+#[test]
+fn workspace_sockets_lists_only_krill_sockets() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    for name in [
+        "krill-robot.sock",
+        "krill-sim.sock",
+        "other.sock",
+        "krill.log",
+    ] {
+        std::fs::write(dir.path().join(name), "").unwrap();
+    }
+
+    let sockets = workspace_sockets(dir.path());
+    assert_eq!(
+        sockets,
+        vec![
+            dir.path().join("krill-robot.sock"),
+            dir.path().join("krill-sim.sock"),
+        ]
+    );
+}
@@ -1,5 +1,4 @@
 use std::path::PathBuf;
-use tempfile::TempDir;
 
 use krill_cli::daemon_manager::is_daemon_running;
 
@@ -14,8 +13,11 @@ async fn is_daemon_running_with_nonexistent_socket_returns_false() {
     assert!(!running, "expected false for non-existent socket");
 }
 
+#[cfg(unix)]
 #[tokio::test]
 async fn is_daemon_running_with_stale_socket_returns_false_and_cleans_up() {
+    use tempfile::TempDir;
+
     let tmp_dir = TempDir::new().expect("failed to create temp dir");
     let socket_path = tmp_dir.path().join("stale.sock");
 
@@ -32,3 +34,92 @@ async fn is_daemon_running_with_stale_socket_returns_false_and_cleans_up() {
         "stale socket file should have been cleaned up"
     );
 }
+
+/// A daemon's IPC server on `socket_path`, with the receiver of the commands
+/// sent to it
+#[cfg(unix)]
+fn start_ipc_server(
+    socket_path: &std::path::Path,
+) -> (
+    std::sync::Arc<krill_daemon::ipc_server::IpcServer>,
+    tokio::sync::mpsc::UnboundedReceiver<krill_daemon::ipc_server::CommandRequest>,
+) {
+    use krill_daemon::ipc_server::IpcServer;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    let (command_tx, command_rx) = mpsc::unbounded_channel();
+    let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+    let server =
+        Arc::new(IpcServer::new(socket_path.to_path_buf(), command_tx, snapshot_req_tx).unwrap());
+    let server_clone = Arc::clone(&server);
+    tokio::spawn(async move { server_clone.start().await });
+    (server, command_rx)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn stop_daemon_returns_the_shutdown_report() {
+    use krill_cli::daemon_manager::{stop_daemon, wait_for_socket};
+    use krill_common::{CommandAction, ServiceShutdown, StopMethod};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    let tmp_dir = TempDir::new().unwrap();
+    let socket_path = tmp_dir.path().join("krill.sock");
+    let (server, mut command_rx) = start_ipc_server(&socket_path);
+    wait_for_socket(&socket_path, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    let report = vec![ServiceShutdown {
+        service: "planner".to_string(),
+        method: Some(StopMethod::Sigterm),
+        duration: Duration::from_millis(120),
+        exit_code: Some(0),
+        signal: None,
+        errors: Vec::new(),
+    }];
+    let sent = report.clone();
+    tokio::spawn(async move {
+        while let Some((action, _)) = command_rx.recv().await {
+            if action == CommandAction::StopDaemon {
+                server.broadcast_shutdown_report(sent);
+                server.flush().await;
+                server.shutdown().await;
+                break;
+            }
+        }
+    });
+
+    let received = stop_daemon(&socket_path, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(received, Some(report));
+    assert!(!socket_path.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn stop_daemon_gives_up_on_a_wedged_shutdown() {
+    use krill_cli::daemon_manager::{stop_daemon, wait_for_socket};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    let tmp_dir = TempDir::new().unwrap();
+    let socket_path = tmp_dir.path().join("krill.sock");
+    // The commands are received but never acted upon
+    let (_server, _command_rx) = start_ipc_server(&socket_path);
+    wait_for_socket(&socket_path, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        stop_daemon(&socket_path, Duration::from_millis(300)),
+    )
+    .await
+    .expect("stop_daemon did not give up");
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("did not stop within 300ms"), "{}", error);
+}
@@ -0,0 +1,97 @@
+use krill_cli::doctor::{
+    check_executables, check_log_dir, check_sockets, check_state_files, shell_program,
+};
+use krill_common::KrillConfig;
+
+#[test]
+fn shell_program_skips_assignments_and_builtins() {
+    assert_eq!(
+        shell_program("camera_driver --fps 30"),
+        Some("camera_driver")
+    );
+    assert_eq!(
+        shell_program("RUST_LOG=debug ./bin/planner"),
+        Some("./bin/planner")
+    );
+    assert_eq!(shell_program("exec python3 node.py"), Some("python3"));
+    assert_eq!(shell_program("cd src && make run"), None);
+    assert_eq!(shell_program("$HOME/bin/run"), None);
+}
+
+#[test]
+fn missing_executables_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("not_executable.sh"), "#!/bin/sh\n").unwrap();
+    let yaml = r#"version: "1"
+name: robot
+services:
+  ok:
+    execute:
+      type: shell
+      command: "sleep 10"
+  missing:
+    execute:
+      type: shell
+      command: "krill-doctor-no-such-program --flag"
+  script:
+    execute:
+      type: shell
+      command: "./not_executable.sh"
+      working_dir: WORKDIR
+"#
+    .replace("WORKDIR", &dir.path().display().to_string());
+    let config = KrillConfig::parse(&yaml, None).unwrap();
+
+    let findings = check_executables(&config);
+    let messages: Vec<&str> = findings.iter().map(|f| f.error.message.as_str()).collect();
+    assert_eq!(findings.len(), 2, "{:?}", messages);
+    assert!(messages[0].starts_with("Service 'missing'"));
+    assert!(messages[1].starts_with("Service 'script'"));
+    assert!(messages[1].ends_with("is not executable"));
+    assert!(findings.iter().all(|f| !f.error.hint.is_empty()));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn stale_sockets_are_found_but_left_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let stale = dir.path().join("krill-gone.sock");
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+    let live = dir.path().join("krill-live.sock");
+    let _listener = tokio::net::UnixListener::bind(&live).unwrap();
+
+    let findings = check_sockets(&[stale.clone(), live, dir.path().join("krill-none.sock")]).await;
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].stale_file.as_deref(), Some(stale.as_path()));
+    assert!(stale.exists());
+}
+
+#[test]
+fn orphaned_state_files_are_found() {
+    let dir = tempfile::tempdir().unwrap();
+    // No process runs with a PID this large
+    let state = r#"{"session_id": "s", "daemon_pid": 4194000, "services": {}}"#;
+    std::fs::write(dir.path().join("robot.json"), state).unwrap();
+    std::fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+    let findings = check_state_files(dir.path());
+    assert_eq!(findings.len(), 2);
+    assert!(findings[0].error.message.contains("'broken'"));
+    assert!(findings[1].error.message.contains("Orphaned"));
+    assert!(findings.iter().all(|f| f.stale_file.is_some()));
+}
+
+#[test]
+fn unwritable_log_dir_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(check_log_dir(&dir.path().join("logs")).is_none());
+
+    // A regular file in the way cannot be created over, even by root
+    let file = dir.path().join("file");
+    std::fs::write(&file, "").unwrap();
+    let finding = check_log_dir(&file.join("logs")).unwrap();
+    assert!(finding
+        .error
+        .message
+        .starts_with("Log directory is not writable"));
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use tempfile::TempDir;
+
+use krill_cli::exec::prepare;
+use krill_common::KrillConfig;
+
+fn command(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn workspace() -> (TempDir, KrillConfig) {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("lidar.env"),
+        "LIDAR_PORT=/dev/ttyUSB0\nROS_DOMAIN_ID=7\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("krill.yaml"),
+        r#"
+version: "1"
+name: robot
+env:
+  ROS_DOMAIN_ID: "42"
+services:
+  lidar:
+    env_file: lidar.env
+    env:
+      LIDAR_RATE: "10"
+    secrets:
+      LIDAR_KEY:
+        command: echo k3y
+    execute:
+      type: shell
+      command: "lidar_driver"
+      working_dir: drivers
+  planner:
+    execute:
+      type: pixi
+      task: planner
+      environment: cuda
+  web:
+    execute:
+      type: docker
+      image: nginx:latest
+  nav:
+    execute:
+      type: podman
+      image: ros:humble
+  stack:
+    execute:
+      type: compose
+      file: compose.yaml
+      services: [nav, map]
+"#,
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("drivers")).unwrap();
+
+    let config = KrillConfig::from_file(&dir.path().join("krill.yaml")).unwrap();
+    (dir, config)
+}
+
+#[test]
+fn test_exec_uses_service_environment() {
+    let (dir, config) = workspace();
+    let prepared = prepare(&config, "lidar", &command(&["env"]), false).unwrap();
+
+    assert_eq!(prepared.program, "env");
+    assert!(prepared.args.is_empty());
+    assert_eq!(prepared.working_dir, Some(dir.path().join("drivers")));
+    assert_eq!(prepared.env["LIDAR_PORT"], "/dev/ttyUSB0");
+    // Workspace env wins over the env file, as in the daemon
+    assert_eq!(prepared.env["ROS_DOMAIN_ID"], "42");
+    assert_eq!(prepared.env["LIDAR_RATE"], "10");
+    assert_eq!(prepared.env["LIDAR_KEY"], "k3y");
+    assert_eq!(prepared.env["KRILL_SERVICE_NAME"], "lidar");
+}
+
+#[test]
+fn test_exec_runs_in_pixi_environment() {
+    let (_dir, config) = workspace();
+    let prepared = prepare(&config, "planner", &command(&["python", "-V"]), false).unwrap();
+
+    assert_eq!(prepared.program, "pixi");
+    assert_eq!(
+        prepared.args,
+        command(&["run", "-e", "cuda", "python", "-V"])
+    );
+}
+
+#[test]
+fn test_exec_enters_docker_container() {
+    let (_dir, config) = workspace();
+    let prepared = prepare(&config, "web", &command(&["sh"]), true).unwrap();
+
+    assert_eq!(prepared.program, "docker");
+    assert_eq!(
+        prepared.args,
+        command(&["exec", "-i", "-t", "krill-robot-web", "sh"])
+    );
+    assert!(prepared.env.is_empty());
+}
+
+#[test]
+fn test_exec_enters_podman_and_compose_containers() {
+    let (dir, config) = workspace();
+    let prepared = prepare(&config, "nav", &command(&["sh"]), false).unwrap();
+    assert_eq!(prepared.program, "podman");
+    assert_eq!(
+        prepared.args,
+        command(&["exec", "-i", "krill-robot-nav", "sh"])
+    );
+
+    let prepared = prepare(&config, "stack", &command(&["sh"]), false).unwrap();
+    let file = dir.path().join("compose.yaml");
+    assert_eq!(prepared.program, "docker");
+    assert_eq!(
+        prepared.args,
+        command(&[
+            "compose",
+            "-f",
+            file.to_str().unwrap(),
+            "-p",
+            "krill-robot-stack",
+            "exec",
+            "-T",
+            "nav",
+            "sh"
+        ])
+    );
+}
+
+#[test]
+fn test_exec_rejects_unknown_service() {
+    let (_dir, config) = workspace();
+    let err = prepare(&config, "camera", &command(&["sh"]), false).unwrap_err();
+    assert!(err.to_string().contains("Unknown service 'camera'"));
+}
@@ -0,0 +1,63 @@
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+use krill_cli::graph::{render_dot, render_tree};
+use krill_common::KrillConfig;
+
+/// lidar <- perception (healthy) <- planner, and lidar <- planner (started)
+fn diamond_config() -> KrillConfig {
+    let mut file = NamedTempFile::new().expect("failed to create temp file");
+    write!(
+        file,
+        r#"
+version: "1"
+name: robot
+services:
+  lidar:
+    critical: true
+    execute:
+      type: shell
+      command: "sleep 10"
+  perception:
+    dependencies:
+      - lidar healthy
+    execute:
+      type: shell
+      command: "sleep 10"
+  planner:
+    dependencies:
+      - lidar
+      - perception
+    execute:
+      type: shell
+      command: "sleep 10"
+"#
+    )
+    .unwrap();
+    KrillConfig::from_file(&file.path().to_path_buf()).expect("config should parse")
+}
+
+#[test]
+fn tree_annotates_conditions_and_critical_services() {
+    let tree = render_tree(&diamond_config()).unwrap();
+
+    assert_eq!(
+        tree,
+        "lidar [critical]\n\
+         ├── perception (healthy)\n\
+         │   └── planner (started)\n\
+         └── planner (started) (*)\n"
+    );
+}
+
+#[test]
+fn dot_contains_edges_with_condition_labels() {
+    let dot = render_dot(&diamond_config()).unwrap();
+
+    assert!(dot.starts_with("digraph \"robot\" {"));
+    assert!(dot.contains("\"lidar\" [label=\"lidar (critical)\", color=red, penwidth=2];"));
+    assert!(dot.contains("\"lidar\" -> \"perception\" [label=\"healthy\", style=solid];"));
+    assert!(dot.contains("\"lidar\" -> \"planner\" [label=\"started\", style=dashed];"));
+    assert!(dot.contains("\"perception\" -> \"planner\" [label=\"started\", style=dashed];"));
+    assert!(dot.trim_end().ends_with('}'));
+}
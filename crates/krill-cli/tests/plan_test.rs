@@ -0,0 +1,128 @@
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+use krill_cli::plan::{render_plan, stages};
+use krill_common::KrillConfig;
+
+fn load(yaml: &str) -> KrillConfig {
+    let mut file = NamedTempFile::new().expect("failed to create temp file");
+    write!(file, "{}", yaml).unwrap();
+    KrillConfig::from_file(&file.path().to_path_buf()).expect("config should parse")
+}
+
+/// lidar and imu start together; perception waits on both, planner on perception
+fn robot_config() -> KrillConfig {
+    load(
+        r#"
+version: "1"
+name: robot
+services:
+  lidar:
+    critical: true
+    start_timeout_sec: 10
+    readiness:
+      type: tcp
+      port: 9000
+      timeout: 1s
+    execute:
+      type: shell
+      command: "sleep 10"
+  imu:
+    start_timeout_sec: 5
+    start_delay: 2s
+    execute:
+      type: shell
+      command: "sleep 10"
+  perception:
+    start_timeout_sec: 20
+    dependencies:
+      - lidar healthy
+      - imu
+    execute:
+      type: shell
+      command: "sleep 10"
+  planner:
+    start_timeout_sec: 5
+    dependencies:
+      - perception healthy
+    execute:
+      type: shell
+      command: "sleep 10"
+"#,
+    )
+}
+
+#[test]
+fn test_stages_group_parallel_services() {
+    let stages = stages(&robot_config()).unwrap();
+    assert_eq!(
+        stages,
+        vec![
+            vec!["imu".to_string(), "lidar".to_string()],
+            vec!["perception".to_string()],
+            vec!["planner".to_string()],
+        ]
+    );
+}
+
+#[test]
+fn test_plan_lists_conditions_and_gates() {
+    let plan = render_plan(&robot_config()).unwrap();
+
+    assert!(plan.contains("Plan for 'robot': 4 service(s) in 3 stage(s)"));
+    assert!(plan.contains("Stage 1 (2 in parallel)"));
+    assert!(plan
+        .contains("  + lidar [critical]\n      ready when: readiness tcp port 9000 (timeout 10s)"));
+    assert!(plan.contains("      start delay: 2s"));
+    assert!(plan.contains("      waits for: lidar (healthy), imu (started)"));
+    // Stage bounds: max(10s, 2s + 5s) + 20s + 5s
+    assert!(plan.contains("Worst case to ready: 35s"));
+}
+
+#[test]
+fn test_plan_without_timeouts_is_unbounded() {
+    let config = load(
+        r#"
+version: "1"
+name: minimal
+services:
+  setup:
+    oneshot: true
+    execute:
+      type: shell
+      command: "true"
+"#,
+    );
+    let plan = render_plan(&config).unwrap();
+
+    assert!(plan.contains("  + setup [oneshot]\n      ready when: exits successfully\n"));
+    assert!(plan.contains("Worst case to ready: unbounded"));
+}
+
+#[test]
+fn test_plan_leaves_fallbacks_out_of_the_estimate() {
+    let config = load(
+        r#"
+version: "1"
+name: rover
+services:
+  gps-rtk:
+    start_timeout_sec: 10
+    policy:
+      max_restarts: 3
+    execute:
+      type: shell
+      command: "sleep 10"
+  gps-basic:
+    dependencies:
+      - gps-rtk failed
+    execute:
+      type: shell
+      command: "sleep 10"
+"#,
+    );
+    let plan = render_plan(&config).unwrap();
+
+    assert!(plan.contains("  + gps-basic [fallback]\n      waits for: gps-rtk (failed)\n"));
+    assert!(plan.contains("Worst case to ready: 10s"));
+}
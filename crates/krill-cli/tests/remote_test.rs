@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use krill_cli::remote::{ssh_command, RemoteTarget};
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+#[test]
+fn ssh_command_runs_the_stdio_relay_remotely() {
+    let command = ssh_command(&RemoteTarget {
+        host: "ops@robot-7",
+        ..Default::default()
+    });
+    assert_eq!(
+        command,
+        [
+            "ssh",
+            "-T",
+            "-o",
+            "ServerAliveInterval=15",
+            "ops@robot-7",
+            "--",
+            "krill ps --stdio",
+        ]
+    );
+}
+
+#[test]
+fn ssh_command_quotes_the_remote_arguments() {
+    let command = ssh_command(&RemoteTarget {
+        host: "robot",
+        krill: Some("/opt/krill tools/krill"),
+        socket: Some(Path::new("/run/user/1000/krill-arm.sock")),
+        workspace: Some("it's-mine"),
+    });
+    assert_eq!(
+        command.last().unwrap(),
+        r"'/opt/krill tools/krill' ps --stdio --socket /run/user/1000/krill-arm.sock --workspace 'it'\''s-mine'"
+    );
+}
+
+#[test]
+fn ssh_command_lets_the_remote_shell_expand_the_home_directory() {
+    let command = ssh_command(&RemoteTarget {
+        host: "robot",
+        krill: Some("~/.pixi/bin/krill"),
+        ..Default::default()
+    });
+    assert_eq!(command.last().unwrap(), "~/.pixi/bin/krill ps --stdio");
+
+    let command = ssh_command(&RemoteTarget {
+        host: "robot",
+        krill: Some("~/krill tools/krill"),
+        ..Default::default()
+    });
+    assert_eq!(command.last().unwrap(), "~/'krill tools/krill' ps --stdio");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn relay_carries_both_directions_until_the_daemon_closes() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("krill-test.sock");
+    let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+    let daemon = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await.unwrap();
+        writer
+            .write_all(format!("echo {}", line).as_bytes())
+            .await
+            .unwrap();
+    });
+
+    let (mut client_in, relay_in) = tokio::io::duplex(1024);
+    let (relay_out, client_out) = tokio::io::duplex(1024);
+    let relay = tokio::spawn({
+        let socket = socket.clone();
+        async move { krill_cli::remote::relay(&socket, relay_in, relay_out).await }
+    });
+
+    client_in.write_all(b"hello\n").await.unwrap();
+    let mut reply = String::new();
+    BufReader::new(client_out)
+        .read_line(&mut reply)
+        .await
+        .unwrap();
+    assert_eq!(reply, "echo hello\n");
+
+    daemon.await.unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), relay)
+        .await
+        .expect("relay did not end with the daemon connection")
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use krill_cli::status::{
+    exit_code, render_shutdown_report, render_status, shutdown_json, status_json, DaemonNotRunning,
+    Exit, Health, EXIT_CRITICAL_FAILED, EXIT_DAEMON_NOT_RUNNING, EXIT_FAILURE, EXIT_OK,
+    EXIT_UNHEALTHY,
+};
+use krill_common::{ServiceShutdown, ServiceSnapshot, ServiceStatus, StopMethod};
+use std::time::Duration;
+
+fn snapshot(status: ServiceStatus, critical: bool) -> ServiceSnapshot {
+    let json = serde_json::json!({
+        "status": status,
+        "pid": null,
+        "uid": "abc1234",
+        "uptime": null,
+        "restart_count": 0,
+        "last_error": null,
+        "namespace": "robot",
+        "config": {
+            "executor": "shell",
+            "critical": critical,
+        },
+    });
+    serde_json::from_value(json).expect("snapshot should deserialize")
+}
+
+fn services(entries: &[(&str, ServiceStatus, bool)]) -> HashMap<String, ServiceSnapshot> {
+    entries
+        .iter()
+        .map(|(name, status, critical)| (name.to_string(), snapshot(status.clone(), *critical)))
+        .collect()
+}
+
+#[test]
+fn test_health_gates_on_failed_services() {
+    let ok = services(&[
+        ("lidar", ServiceStatus::Healthy, true),
+        ("calibrate", ServiceStatus::Completed, false),
+        ("recorder", ServiceStatus::Stopped, false),
+    ]);
+    assert_eq!(Health::of(&ok), Health::Ok);
+    assert_eq!(Health::of(&ok).exit_code(), EXIT_OK);
+
+    let degraded = services(&[
+        ("lidar", ServiceStatus::Healthy, true),
+        ("planner", ServiceStatus::Degraded, false),
+    ]);
+    assert_eq!(Health::of(&degraded).exit_code(), EXIT_UNHEALTHY);
+
+    let failed = services(&[
+        ("lidar", ServiceStatus::Failed, true),
+        ("planner", ServiceStatus::Degraded, false),
+    ]);
+    assert_eq!(Health::of(&failed).exit_code(), EXIT_CRITICAL_FAILED);
+
+    let json = status_json(&failed);
+    assert_eq!(json["health"], "critical_failed");
+    assert_eq!(json["exit_code"], EXIT_CRITICAL_FAILED);
+    assert_eq!(json["services"]["lidar"]["status"], "failed");
+
+    let text = render_status(&failed);
+    assert!(text.starts_with("SERVICE"));
+    assert!(text.contains("lidar*"));
+    assert!(text.ends_with("A critical service (*) is failed\n"));
+}
+
+#[test]
+fn test_status_reports_paused_restarts() {
+    let mut services = services(&[
+        ("lidar", ServiceStatus::Failed, false),
+        ("planner", ServiceStatus::Failed, false),
+    ]);
+    assert!(!render_status(&services).contains("restart storm"));
+
+    for service in services.values_mut() {
+        service.restarts_paused = true;
+    }
+    let text = render_status(&services);
+    assert_eq!(text.matches("restart storm").count(), 1);
+    assert!(text.ends_with(
+        "Automatic restarts of 'robot' are paused by a restart storm; 'krill resume --workspace robot' resumes them\n"
+    ));
+    assert_eq!(
+        status_json(&services)["services"]["lidar"]["restarts_paused"],
+        true
+    );
+}
+
+#[test]
+fn test_exit_code_of_errors() {
+    assert_eq!(exit_code(&Exit(EXIT_UNHEALTHY).into()), EXIT_UNHEALTHY);
+    assert_eq!(
+        exit_code(&DaemonNotRunning::default().into()),
+        EXIT_DAEMON_NOT_RUNNING
+    );
+    assert_eq!(exit_code(&anyhow::anyhow!("Restart failed")), EXIT_FAILURE);
+}
+
+#[test]
+fn test_shutdown_report() {
+    let report = [
+        ServiceShutdown {
+            service: "planner".to_string(),
+            method: Some(StopMethod::Sigterm),
+            duration: Duration::from_millis(120),
+            exit_code: Some(0),
+            signal: None,
+            errors: Vec::new(),
+        },
+        ServiceShutdown {
+            service: "camera".to_string(),
+            method: Some(StopMethod::Sigkill),
+            duration: Duration::from_millis(10_040),
+            exit_code: None,
+            signal: Some(9),
+            errors: vec!["post_stop hook failed: exit status: 1".to_string()],
+        },
+    ];
+
+    let text = render_shutdown_report(&report);
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("SERVICE"));
+    assert!(lines[1].starts_with("planner"));
+    assert!(lines[1].contains("SIGTERM") && lines[1].contains("0.12s"));
+    assert!(lines[2].contains("SIGKILL") && lines[2].contains("signal 9"));
+    assert!(lines[2].ends_with("post_stop hook failed: exit status: 1"));
+    assert!(text.ends_with("Slowest to stop: camera (10.04s)\n"));
+    assert_eq!(render_shutdown_report(&[]), "No services were running\n");
+
+    let json = shutdown_json(&report);
+    assert_eq!(json["services"][0]["method"], "sigterm");
+    assert_eq!(json["services"][1]["service"], "camera");
+    assert_eq!(json["services"][1]["duration_ms"], 10_040);
+    assert_eq!(json["services"][1]["exit_code"], serde_json::Value::Null);
+    assert_eq!(json["services"][1]["signal"], 9);
+}
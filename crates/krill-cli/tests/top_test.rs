@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use krill_cli::top::{parse_interval, render};
+use krill_common::{ServiceMetrics, ServiceSnapshot};
+
+fn snapshot(pid: Option<u32>, metrics: Option<(f32, u64)>) -> ServiceSnapshot {
+    let json = serde_json::json!({
+        "status": if pid.is_some() { "running" } else { "stopped" },
+        "pid": pid,
+        "uid": "abc1234",
+        "uptime": pid.map(|_| Duration::from_secs(90)),
+        "restart_count": 2,
+        "last_error": null,
+        "namespace": "robot",
+        "config": {
+            "executor": "shell",
+            "critical": false,
+        },
+    });
+    let mut snapshot: ServiceSnapshot =
+        serde_json::from_value(json).expect("snapshot should deserialize");
+    snapshot.metrics = metrics.map(|(cpu_percent, memory_bytes)| ServiceMetrics {
+        cpu_percent,
+        memory_bytes,
+        ..Default::default()
+    });
+    snapshot
+}
+
+#[test]
+fn zero_interval_is_rejected() {
+    assert_eq!(parse_interval("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_interval("2s"), Ok(Duration::from_secs(2)));
+    assert!(parse_interval("0s").is_err());
+    assert!(parse_interval("0").is_err());
+    assert!(parse_interval("soon").is_err());
+}
+
+#[test]
+fn table_sums_usage_and_sorts_services() {
+    let services = HashMap::from([
+        (
+            "planner".to_string(),
+            snapshot(Some(42), Some((12.5, 1024))),
+        ),
+        ("lidar".to_string(), snapshot(Some(7), Some((3.0, 2048)))),
+        ("recorder".to_string(), snapshot(None, None)),
+    ]);
+
+    let lines = render(&services);
+    assert!(
+        lines[0].contains("3 services, 2 running  CPU 15.5%"),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[2].starts_with("SERVICE"));
+
+    let names: Vec<&str> = lines[3..]
+        .iter()
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(names, ["lidar", "planner", "recorder"]);
+
+    let planner: Vec<&str> = lines[4].split_whitespace().collect();
+    assert_eq!(&planner[..4], ["planner", "Running", "42", "12.5"]);
+    assert!(lines[4].ends_with(" 1m 30s"), "{}", lines[4]);
+    let recorder: Vec<&str> = lines[5].split_whitespace().collect();
+    assert_eq!(recorder, ["recorder", "Stopped", "-", "-", "-", "2", "-"]);
+}
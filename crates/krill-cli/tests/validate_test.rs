@@ -0,0 +1,127 @@
+use krill_cli::validate::{check, locate};
+
+const RECIPE: &str = r#"version: "1"
+name: robot
+services:
+  lidar:
+    execute:
+      type: shell
+      command: "sleep 10"
+  planner:
+    dependencies:
+      - lidar
+      - perception
+    execute:
+      type: shell
+      command: "sleep 10"
+"#;
+
+#[test]
+fn valid_recipe_passes() {
+    let recipe = RECIPE.replace("      - perception\n", "");
+    let config = check(&recipe, None).expect("recipe should be valid");
+    assert_eq!(config.services.len(), 2);
+}
+
+#[test]
+fn unknown_dependency_is_positioned_at_dependencies_key() {
+    let diagnostics = check(RECIPE, None).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].position, Some((9, 5)));
+    assert!(diagnostics[0]
+        .message
+        .contains("unknown service 'perception'"));
+}
+
+#[test]
+fn every_problem_is_reported() {
+    let recipe = RECIPE.replace("version: \"1\"", "version: \"2\"").replace(
+        "command: \"sleep 10\"\n  planner",
+        "command: \"sleep 10; rm -rf /\"\n  planner",
+    );
+    let diagnostics = check(&recipe, None).unwrap_err();
+    let positions: Vec<_> = diagnostics.iter().map(|d| d.position).collect();
+    assert_eq!(positions, vec![Some((1, 1)), Some((7, 7)), Some((9, 5))]);
+}
+
+#[test]
+fn parse_error_carries_yaml_position() {
+    let recipe = RECIPE.replace(
+        "      type: shell\n      command: \"sleep 10\"\n  planner",
+        "      type: shell\n      comand: \"sleep 10\"\n  planner",
+    );
+    let diagnostics = check(&recipe, None).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    let (line, _) = diagnostics[0]
+        .position
+        .expect("parse errors have a position");
+    assert_eq!(line, 5);
+    assert!(!diagnostics[0].message.contains(" at line "));
+}
+
+#[test]
+fn problems_in_included_files_are_located_there() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let common = dir.path().join("common.yaml");
+    std::fs::write(
+        &common,
+        r#"version: "1"
+services:
+  planner:
+    dependencies:
+      - perception
+    execute:
+      type: shell
+      command: "sleep 10"
+"#,
+    )
+    .unwrap();
+    let recipe = r#"include: [common.yaml]
+name: robot
+services:
+  planner:
+    critical: true
+"#;
+
+    let diagnostics = check(recipe, Some(dir.path())).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].file, Some(common.clone()));
+    assert_eq!(diagnostics[0].position, Some((4, 5)));
+    assert!(diagnostics[0]
+        .to_string()
+        .starts_with(&format!("{}:4:5: ", common.display())));
+
+    // Overridden in the recipe, the problem is the recipe's
+    let recipe = format!("{}    dependencies: [perception]\n", recipe);
+    let diagnostics = check(&recipe, Some(dir.path())).unwrap_err();
+    assert_eq!(diagnostics[0].file, None);
+    assert_eq!(diagnostics[0].position, Some((6, 5)));
+}
+
+#[test]
+fn dependency_cycle_is_reported() {
+    let recipe = RECIPE.replace(
+        "  lidar:\n    execute",
+        "  lidar:\n    dependencies:\n      - planner\n    execute",
+    );
+    let recipe = recipe.replace("      - perception\n", "");
+    let diagnostics = check(&recipe, None).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("Circular dependency"));
+    assert!(diagnostics[0].position.is_some());
+}
+
+#[test]
+fn locate_only_matches_direct_children() {
+    // `command` exists under both services; the path picks planner's
+    assert_eq!(
+        locate(RECIPE, &["services", "planner", "execute", "command"]),
+        Some((14, 7))
+    );
+    // Missing keys fall back to the deepest key found
+    assert_eq!(
+        locate(RECIPE, &["services", "planner", "readiness"]),
+        Some((8, 3))
+    );
+    assert_eq!(locate(RECIPE, &["groups"]), None);
+}
@@ -0,0 +1,177 @@
+// Access Control - Who may connect to the daemon's socket and send control commands
+
+use crate::{CommandAction, ConfigError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Permission bits of the daemon socket unless `socket_mode` says otherwise
+pub const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// A user or group, by name or numeric id
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Principal {
+    Id(u32),
+    Name(String),
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Principal::Id(id) => write!(f, "{}", id),
+            Principal::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// `access:` of a recipe
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConfig {
+    /// Permission bits of the Unix socket in octal, e.g. "0660" to let the
+    /// members of `socket_group` connect
+    #[serde(default = "default_socket_mode")]
+    pub socket_mode: String,
+
+    /// Group owning the Unix socket
+    #[serde(default)]
+    pub socket_group: Option<Principal>,
+
+    /// Commands only root, the daemon's own user, `allow_users`, and members
+    /// of `allow_groups` may send; status queries stay open to every client.
+    /// `upgrade_daemon` and `set_log_filter` are restricted even if unlisted.
+    #[serde(default = "default_restricted_commands")]
+    pub restricted_commands: Vec<CommandAction>,
+
+    #[serde(default)]
+    pub allow_users: Vec<Principal>,
+
+    #[serde(default)]
+    pub allow_groups: Vec<Principal>,
+
+    /// Executables `krill daemon upgrade --binary` may start besides the
+    /// daemon's own
+    #[serde(default)]
+    pub upgrade_binaries: Vec<PathBuf>,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            socket_mode: default_socket_mode(),
+            socket_group: None,
+            restricted_commands: default_restricted_commands(),
+            allow_users: Vec::new(),
+            allow_groups: Vec::new(),
+            upgrade_binaries: Vec::new(),
+        }
+    }
+}
+
+impl AccessConfig {
+    /// `socket_mode` as permission bits
+    pub fn socket_mode_bits(&self) -> Result<u32, ConfigError> {
+        let digits = self.socket_mode.trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(mode),
+            _ => Err(ConfigError::InvalidAccess(self.socket_mode_problem())),
+        }
+    }
+
+    fn socket_mode_problem(&self) -> String {
+        format!(
+            "socket_mode '{}' is not an octal permission mode like \"0660\"",
+            self.socket_mode
+        )
+    }
+
+    /// Reject a socket mode that is not octal, empty user and group names
+    /// and relative upgrade binaries
+    pub fn check(&self) -> Option<String> {
+        if self.socket_mode_bits().is_err() {
+            return Some(self.socket_mode_problem());
+        }
+        let names = self
+            .socket_group
+            .iter()
+            .chain(&self.allow_users)
+            .chain(&self.allow_groups);
+        for principal in names {
+            if matches!(principal, Principal::Name(name) if name.is_empty()) {
+                return Some("user and group names cannot be empty".to_string());
+            }
+        }
+        if let Some(binary) = self.upgrade_binaries.iter().find(|b| !b.is_absolute()) {
+            return Some(format!(
+                "upgrade binary {:?} must be an absolute path",
+                binary
+            ));
+        }
+        None
+    }
+}
+
+fn default_socket_mode() -> String {
+    format!("{:04o}", DEFAULT_SOCKET_MODE)
+}
+
+fn default_restricted_commands() -> Vec<CommandAction> {
+    vec![
+        CommandAction::StopDaemon,
+        CommandAction::EmergencyStop,
+        CommandAction::UpgradeDaemon,
+        CommandAction::SetLogFilter,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_access() {
+        let access: AccessConfig = serde_yaml::from_str(
+            "socket_mode: \"0660\"\nsocket_group: robots\nallow_users: [1000, alice]\n",
+        )
+        .unwrap();
+        assert_eq!(access.socket_mode_bits().unwrap(), 0o660);
+        assert_eq!(
+            access.socket_group,
+            Some(Principal::Name("robots".to_string()))
+        );
+        assert_eq!(
+            access.allow_users,
+            vec![Principal::Id(1000), Principal::Name("alice".to_string())]
+        );
+        assert_eq!(
+            access.restricted_commands,
+            vec![
+                CommandAction::StopDaemon,
+                CommandAction::EmergencyStop,
+                CommandAction::UpgradeDaemon,
+                CommandAction::SetLogFilter
+            ]
+        );
+        assert_eq!(access.check(), None);
+
+        let defaults = AccessConfig::default();
+        assert_eq!(defaults.socket_mode_bits().unwrap(), DEFAULT_SOCKET_MODE);
+
+        let access = AccessConfig {
+            socket_mode: "0999".to_string(),
+            ..Default::default()
+        };
+        assert!(access.check().is_some());
+        let access = AccessConfig {
+            socket_mode: "1777".to_string(),
+            ..Default::default()
+        };
+        assert!(access.check().is_some());
+        let access = AccessConfig {
+            upgrade_binaries: vec![PathBuf::from("bin/krill-daemon")],
+            ..Default::default()
+        };
+        assert!(access.check().is_some());
+    }
+}
@@ -1,9 +1,16 @@
 // Configuration file types
 
-use crate::{Dependency, ExecuteConfig, HealthChecker, PolicyConfig};
+use crate::emergency::default_on_emergency;
+use crate::{
+    AccessConfig, DagError, Dependency, DependencyCondition, DependencyGraph, EmergencyAction,
+    ExecuteConfig, HealthChecker, HealthDebounce, MaintenanceSchedule, PathVars, PolicyConfig,
+    PortSpec, Precondition, ResourceLimits, RestartPolicy, RestartStormConfig, SchedulerConfig,
+    SecretSource, StopAction, DEFAULT_EMERGENCY_COMMAND_TIMEOUT,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -13,8 +20,60 @@ pub struct KrillConfig {
     #[serde(default)]
     pub log_dir: Option<PathBuf>,
     #[serde(default)]
+    pub log_rotation: LogRotationConfig,
+    /// Format of the per-service log files
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Lines of output kept in memory per service, unless the service sets
+    /// its own `log_buffer_lines`
+    #[serde(default = "default_log_buffer_lines")]
+    pub log_buffer_lines: usize,
+    /// Cap on the memory of all in-memory log buffers together (in MB); the
+    /// largest buffers lose their oldest lines first
+    #[serde(default = "default_log_buffer_max_mb")]
+    pub log_buffer_max_mb: u64,
+    #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Named subsets of services, e.g. `perception: [camera, lidar]`
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// Prometheus `/metrics` endpoint, disabled by default
+    #[serde(default)]
+    pub prometheus: PrometheusConfig,
+    /// Socket permissions and who may send restricted commands
+    #[serde(default)]
+    pub access: AccessConfig,
+    /// Socket the daemon listens on instead of `krill-<name>.sock` in the
+    /// socket directory; `@name` selects an abstract socket (Linux only)
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    /// Command run first on an emergency stop, e.g. one halting the motors,
+    /// before the `on_emergency` actions
+    #[serde(default)]
+    pub emergency_stop_cmd: Option<String>,
+    /// Steps run in order on an emergency stop; by default all services are stopped
+    #[serde(default = "default_on_emergency")]
+    pub on_emergency: Vec<EmergencyAction>,
+    /// Circuit breaker pausing automatic restarts after too many failures
+    /// across the workspace; disabled by default
+    #[serde(default)]
+    pub restart_storm: Option<RestartStormConfig>,
+    /// Services launched at once; a further launch waits until one of them
+    /// finished starting. Unlimited by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_starts: Option<usize>,
+    /// Format and filters of the daemon's own log, `krill.log` in the session
+    /// directory
+    #[serde(default)]
+    pub daemon_log: DaemonLogConfig,
+    /// Rate limiting and deduplication of the service output streamed to
+    /// clients such as the TUI
+    #[serde(default)]
+    pub log_throttle: LogThrottleConfig,
     pub services: HashMap<String, ServiceConfig>,
+    /// Files merged into the recipe by `include:`, in merge order
+    #[serde(skip)]
+    pub included: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -25,206 +84,2168 @@ pub struct ServiceConfig {
     pub dependencies: Vec<Dependency>,
     #[serde(default)]
     pub critical: bool,
+    /// Stopping, restarting or killing the service needs an explicit
+    /// confirmation, e.g. for a motor controller
+    #[serde(default)]
+    pub protect: bool,
     #[serde(default)]
     pub gpu: bool,
+    /// Degrade the service while `nvidia-smi` reports a GPU throttling or
+    /// uncorrected ECC errors; needs `gpu`
+    #[serde(default)]
+    pub gpu_health: bool,
+    /// Free-form `key: value` pairs that label selectors such as
+    /// `tier=perception` pick the service by
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Runs to completion: exit code 0 marks it completed instead of failed,
+    /// and dependents can wait for that with the `completed` condition
+    #[serde(default)]
+    pub oneshot: bool,
     #[serde(default)]
     pub health_check: Option<HealthChecker>,
+    /// Probe that must pass once before `healthy` dependencies consider the
+    /// service ready; liveness (`health_check`) still governs restarts
+    #[serde(default)]
+    pub readiness: Option<HealthChecker>,
+    /// Checks a health verdict must persist for, and flapping detection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_debounce: Option<HealthDebounce>,
+    /// `.env` file read at spawn time; values from `env` take precedence
+    #[serde(default)]
+    pub env_file: Option<PathBuf>,
+    /// Variables of this service, overriding the workspace `env`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Variables read from a file or command each time the service starts,
+    /// overriding `env`; their values never reach the recipe, logs or snapshots
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secrets: BTreeMap<String, SecretSource>,
+    /// Seconds a started service gets to become ready (readiness probe, else
+    /// health check, else running) before it is failed
+    #[serde(default)]
+    pub start_timeout_sec: Option<u64>,
+    /// Time to wait after the dependencies are satisfied before launching,
+    /// e.g. for hardware that needs to settle after power-up
+    #[serde(default, with = "humantime_serde")]
+    pub start_delay: Option<std::time::Duration>,
+    /// Time the service waits for its dependencies before it is blocked
+    /// instead of started; unlimited if unset
+    #[serde(default, with = "humantime_serde")]
+    pub dependency_timeout: Option<std::time::Duration>,
+    /// Conditions outside the recipe checked before the service is spawned,
+    /// e.g. `- device: /dev/ttyUSB0`; it stays pending until all of them hold
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wait_for: Vec<Precondition>,
+    /// Time the service waits for its `wait_for` conditions before it is
+    /// blocked instead of started; unlimited if unset
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub wait_for_timeout: Option<std::time::Duration>,
+    /// Lines of output kept in memory, overriding the workspace's `log_buffer_lines`
+    #[serde(default)]
+    pub log_buffer_lines: Option<usize>,
+    /// CPU, memory and priority caps of the service's processes
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    /// CPUs the service's processes may run on, e.g. isolated cores
+    #[serde(default)]
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Scheduling policy and realtime priority of the service's processes
+    #[serde(default)]
+    pub scheduler: Option<SchedulerConfig>,
+    /// Ports the service binds, e.g. `[8080, 9090/udp]`; it is not started
+    /// while another process holds one of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires_ports: Vec<PortSpec>,
+    /// Commands run before and after the service's process starts and stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<ServiceHooks>,
+    /// Cron expressions of maintenance actions, e.g. `restart: "0 3 * * *"`
+    #[serde(default, skip_serializing_if = "MaintenanceSchedule::is_empty")]
+    pub schedule: MaintenanceSchedule,
     #[serde(default)]
     pub policy: PolicyConfig,
 }
 
+/// Point in a service's lifecycle where a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreStart,
+    PostStart,
+    PreStop,
+    PostStop,
+}
+
+impl Hook {
+    pub const ALL: [Hook; 4] = [
+        Hook::PreStart,
+        Hook::PostStart,
+        Hook::PreStop,
+        Hook::PostStop,
+    ];
+
+    /// Key of the hook under `hooks:`
+    pub fn key(self) -> &'static str {
+        match self {
+            Hook::PreStart => "pre_start",
+            Hook::PostStart => "post_start",
+            Hook::PreStop => "pre_stop",
+            Hook::PostStop => "post_stop",
+        }
+    }
+}
+
+impl std::fmt::Display for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// `hooks:` of a service: shell commands run with the service's environment
+/// and working directory. A failing `pre_start` fails the start; failures of
+/// the other hooks are only logged.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceHooks {
+    #[serde(default)]
+    pub pre_start: Option<String>,
+    #[serde(default)]
+    pub post_start: Option<String>,
+    #[serde(default)]
+    pub pre_stop: Option<String>,
+    /// Also runs after the process exited on its own
+    #[serde(default)]
+    pub post_stop: Option<String>,
+    /// How long each hook may run before it is killed
+    #[serde(default = "default_hook_timeout", with = "humantime_serde")]
+    pub timeout: std::time::Duration,
+}
+
+impl Default for ServiceHooks {
+    fn default() -> Self {
+        Self {
+            pre_start: None,
+            post_start: None,
+            pre_stop: None,
+            post_stop: None,
+            timeout: default_hook_timeout(),
+        }
+    }
+}
+
+impl ServiceHooks {
+    pub fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreStart => self.pre_start.as_deref(),
+            Hook::PostStart => self.post_start.as_deref(),
+            Hook::PreStop => self.pre_stop.as_deref(),
+            Hook::PostStop => self.post_stop.as_deref(),
+        }
+    }
+}
+
+/// How service output is written to the log files of a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[timestamp] line` in `<service>.log`
+    #[default]
+    Text,
+    /// One JSON object per line in `<service>.jsonl`, with the timestamp,
+    /// stream, service and level next to the line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogRotationConfig {
+    /// Rotate a service log file once it grows past this size (in MB)
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Number of rotated files to keep per service
+    #[serde(default = "default_log_max_files")]
+    pub max_files: u32,
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size_mb: default_log_max_size_mb(),
+            max_files: default_log_max_files(),
+        }
+    }
+}
+
+impl LogRotationConfig {
+    pub fn max_size_bytes(&self) -> u64 {
+        self.max_size_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+/// `log_throttle:` of a recipe. A service printing faster than `lines_per_sec`
+/// (after a `burst`) or repeating a line more than `max_repeats` times in a
+/// row has the excess withheld from clients, which are told how many lines
+/// were suppressed instead. Log files and log history keep every line.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogThrottleConfig {
+    #[serde(default = "default_log_throttle_enabled")]
+    pub enabled: bool,
+
+    /// Lines per second a service may sustain
+    #[serde(default = "default_log_throttle_lines_per_sec")]
+    pub lines_per_sec: u32,
+
+    /// Lines a service may print at once before it is limited
+    #[serde(default = "default_log_throttle_burst")]
+    pub burst: u32,
+
+    /// Identical lines in a row forwarded before the rest of the run is
+    /// suppressed
+    #[serde(default = "default_log_throttle_max_repeats")]
+    pub max_repeats: u32,
+}
+
+impl Default for LogThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lines_per_sec: default_log_throttle_lines_per_sec(),
+            burst: default_log_throttle_burst(),
+            max_repeats: default_log_throttle_max_repeats(),
+        }
+    }
+}
+
+impl LogThrottleConfig {
+    /// The first limit set to zero, which would withhold every line of a
+    /// service, as a validation message
+    pub fn check(&self) -> Option<String> {
+        if self.lines_per_sec == 0 {
+            Some("lines_per_sec must be at least 1".to_string())
+        } else if self.burst == 0 {
+            Some("burst must be at least 1".to_string())
+        } else if self.max_repeats == 0 {
+            Some("max_repeats must be at least 1".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Address serving `GET /metrics`
+    #[serde(default = "default_prometheus_listen")]
+    pub listen: SocketAddr,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_prometheus_listen(),
+        }
+    }
+}
+
+/// Levels accepted by `daemon_log`, as in `RUST_LOG`
+const DAEMON_LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error", "off"];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonLogConfig {
+    /// Layout of the lines written to `krill.log`
+    #[serde(default)]
+    pub format: DaemonLogFormat,
+
+    /// Level of the daemon's messages without a module override
+    #[serde(default = "default_daemon_log_level")]
+    pub level: String,
+
+    /// Levels of individual modules, e.g. `krill_daemon::health: debug`
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+impl Default for DaemonLogConfig {
+    fn default() -> Self {
+        Self {
+            format: DaemonLogFormat::default(),
+            level: default_daemon_log_level(),
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+impl DaemonLogConfig {
+    /// The level and module overrides as a `RUST_LOG` style filter, e.g.
+    /// `info,krill_daemon::health=debug`
+    pub fn filter(&self) -> String {
+        let mut filter = self.level.clone();
+        for (module, level) in &self.modules {
+            filter.push_str(&format!(",{}={}", module, level));
+        }
+        filter
+    }
+
+    fn check(&self, issues: &mut Vec<ConfigIssue>) {
+        if !is_daemon_log_level(&self.level) {
+            issues.push(ConfigIssue::new(
+                &["daemon_log", "level"],
+                ConfigError::InvalidDaemonLog(format!("unknown level '{}'", self.level)),
+            ));
+        }
+        for (module, level) in &self.modules {
+            let path = ["daemon_log", "modules", module.as_str()];
+            if module.is_empty() || module.contains([',', '=', ' ']) {
+                issues.push(ConfigIssue::new(
+                    &path,
+                    ConfigError::InvalidDaemonLog(format!("invalid module name '{}'", module)),
+                ));
+            } else if !is_daemon_log_level(level) {
+                issues.push(ConfigIssue::new(
+                    &path,
+                    ConfigError::InvalidDaemonLog(format!(
+                        "unknown level '{}' for module '{}'",
+                        level, module
+                    )),
+                ));
+            }
+        }
+    }
+}
+
+fn is_daemon_log_level(level: &str) -> bool {
+    DAEMON_LOG_LEVELS.contains(&level.to_ascii_lowercase().as_str())
+}
+
+fn default_daemon_log_level() -> String {
+    "info".to_string()
+}
+
+/// Layout of the daemon's log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DaemonLogFormat {
+    /// One human-readable line per message
+    #[default]
+    Compact,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+fn default_prometheus_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 9464))
+}
+
+fn default_log_max_size_mb() -> u64 {
+    10
+}
+
+fn default_log_max_files() -> u32 {
+    5
+}
+
+fn default_log_throttle_enabled() -> bool {
+    true
+}
+
+fn default_log_throttle_lines_per_sec() -> u32 {
+    200
+}
+
+fn default_log_throttle_burst() -> u32 {
+    500
+}
+
+fn default_log_throttle_max_repeats() -> u32 {
+    3
+}
+
+fn default_hook_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(30)
+}
+
+fn default_log_buffer_lines() -> usize {
+    5000
+}
+
+fn default_log_buffer_max_mb() -> u64 {
+    64
+}
+
 impl KrillConfig {
     pub fn from_file(path: &PathBuf) -> Result<Self, ConfigError> {
         let content = std::fs::read_to_string(path)
             .map_err(|e| ConfigError::FileRead(path.clone(), e.to_string()))?;
 
-        let mut config: KrillConfig =
-            serde_yaml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let config =
+            Self::parse(&content, path.parent()).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
 
-        // Resolve relative paths against the config file's directory
-        if let Some(parent) = path.parent() {
-            config.resolve_paths(parent);
-        }
+        Ok(config)
+    }
 
-        config.validate()?;
+    /// Socket the daemon for this recipe listens on
+    pub fn socket_path(&self) -> Result<PathBuf, ConfigError> {
+        match &self.socket {
+            Some(socket) => Ok(socket.clone()),
+            None => crate::workspace_socket_path(&self.name),
+        }
+    }
 
+    /// Parse a recipe without validating it, merging in its `include:`
+    /// files, expanding service templates and resolving relative paths
+    /// against `base_dir` (the config file's directory)
+    pub fn parse(content: &str, base_dir: Option<&Path>) -> Result<Self, serde_yaml::Error> {
+        let mut doc: serde_yaml::Value = serde_yaml::from_str(content)?;
+        let has_includes = doc.get("include").is_some();
+        let included = crate::resolve_includes(&mut doc, base_dir)
+            .map_err(<serde_yaml::Error as serde::de::Error>::custom)?;
+        let mut config: KrillConfig = if has_includes || doc.get("templates").is_some() {
+            crate::expand_templates(&mut doc)
+                .map_err(<serde_yaml::Error as serde::de::Error>::custom)?;
+            serde_yaml::from_value(doc)?
+        } else {
+            // Parsing the text directly keeps error positions
+            serde_yaml::from_str(content)?
+        };
+        config.included = included;
+        config
+            .resolve_paths(base_dir)
+            .map_err(<serde_yaml::Error as serde::de::Error>::custom)?;
         Ok(config)
     }
 
-    /// Resolve relative paths in the config against a base directory
-    fn resolve_paths(&mut self, base_dir: &std::path::Path) {
-        for service in self.services.values_mut() {
-            service.execute.resolve_working_dir(base_dir);
+    /// Expand variables in the services' paths and resolve relative ones
+    /// against a base directory, which is also `${WORKSPACE_DIR}` (the
+    /// current directory without one)
+    fn resolve_paths(&mut self, base_dir: Option<&Path>) -> Result<(), ConfigError> {
+        let workspace_dir = base_dir
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+        let vars = PathVars::new(&workspace_dir, &self.env);
+
+        if let Some(ref mut socket) = self.socket {
+            if !socket.to_string_lossy().starts_with('@') {
+                vars.resolve(socket, base_dir)
+                    .map_err(|e| ConfigError::InvalidSocket(e.to_string()))?;
+            }
+        }
+        for (name, service) in self.services.iter_mut() {
+            let invalid = |field: String, error: ConfigError| ConfigError::InvalidServicePath {
+                service: name.clone(),
+                field,
+                error: Box::new(error),
+            };
+            service.execute.resolve_paths(name, &vars, base_dir)?;
+            if let Some(ref mut env_file) = service.env_file {
+                vars.resolve(env_file, base_dir)
+                    .map_err(|e| invalid("env_file".to_string(), e))?;
+            }
+            for (secret, source) in service.secrets.iter_mut() {
+                if let SecretSource::File(path) = source {
+                    vars.resolve(path, base_dir)
+                        .map_err(|e| invalid(format!("secrets.{}", secret), e))?;
+                }
+            }
+            for condition in service.wait_for.iter_mut() {
+                if let Precondition::File(path) | Precondition::Device(path) = condition {
+                    vars.resolve(path, base_dir)
+                        .map_err(|e| invalid("wait_for".to_string(), e))?;
+                }
+            }
         }
+        Ok(())
     }
 
     pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.issues().into_iter().next() {
+            Some(issue) => Err(issue.error),
+            None => Ok(()),
+        }
+    }
+
+    /// Every validation problem in the recipe, in document order where possible
+    pub fn issues(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+
         // Check version
         if self.version != "1" {
-            return Err(ConfigError::UnsupportedVersion(self.version.clone()));
+            issues.push(ConfigIssue::new(
+                &["version"],
+                ConfigError::UnsupportedVersion(self.version.clone()),
+            ));
+        }
+
+        if let Err(e) = check_workspace_name(&self.name) {
+            issues.push(ConfigIssue::new(&["name"], e));
         }
 
-        // Validate workspace name
-        if self.name.is_empty() {
-            return Err(ConfigError::InvalidWorkspaceName(
-                "Workspace name cannot be empty".to_string(),
+        if self.max_concurrent_starts == Some(0) {
+            issues.push(ConfigIssue::new(
+                &["max_concurrent_starts"],
+                ConfigError::InvalidConcurrentStarts,
             ));
         }
 
-        if !self
-            .name
-            .chars()
-            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-        {
-            return Err(ConfigError::InvalidWorkspaceName(format!(
-                "Workspace name '{}' contains invalid characters",
-                self.name
-            )));
+        if let Some(reason) = self.restart_storm.as_ref().and_then(|storm| storm.check()) {
+            issues.push(ConfigIssue::new(
+                &["restart_storm"],
+                ConfigError::InvalidRestartStorm(reason),
+            ));
+        }
+
+        if let Some(reason) = self.log_throttle.check() {
+            issues.push(ConfigIssue::new(
+                &["log_throttle"],
+                ConfigError::InvalidLogThrottle(reason),
+            ));
         }
 
+        self.daemon_log.check(&mut issues);
+
         // Validate services exist
         if self.services.is_empty() {
-            return Err(ConfigError::NoServices);
+            issues.push(ConfigIssue::new(&["services"], ConfigError::NoServices));
         }
 
         // Validate each service
-        for (name, service) in &self.services {
-            service.validate(name)?;
+        let mut names: Vec<&String> = self.services.keys().collect();
+        names.sort();
+        for name in names {
+            let service = &self.services[name];
+            service.check(name, &mut issues);
 
             // Check that dependencies reference valid services
             for dep in &service.dependencies {
                 let dep_name = dep.service_name();
-                if !self.services.contains_key(dep_name) {
-                    return Err(ConfigError::UnknownDependency {
-                        service: name.clone(),
-                        dependency: dep_name.to_string(),
-                    });
+                let path = ["services", name, "dependencies"];
+                let Some(dep_service) = self.services.get(dep_name) else {
+                    issues.push(ConfigIssue::new(
+                        &path,
+                        ConfigError::UnknownDependency {
+                            service: name.clone(),
+                            dependency: dep_name.to_string(),
+                        },
+                    ));
+                    continue;
+                };
+                if dep.condition() == DependencyCondition::Completed && !dep_service.oneshot {
+                    issues.push(ConfigIssue::new(
+                        &path,
+                        ConfigError::NotOneshot {
+                            service: name.clone(),
+                            dependency: dep_name.to_string(),
+                        },
+                    ));
+                }
+                if dep.condition() == DependencyCondition::Failed
+                    && dep_service.policy.restart != RestartPolicy::Never
+                    && dep_service.policy.max_restarts == 0
+                {
+                    issues.push(ConfigIssue::new(
+                        &path,
+                        ConfigError::RestartedWithoutLimit {
+                            service: name.clone(),
+                            dependency: dep_name.to_string(),
+                        },
+                    ));
                 }
             }
         }
 
-        Ok(())
-    }
-}
+        // Validate emergency actions
+        if let Some(command) = &self.emergency_stop_cmd {
+            if let Err(e) = crate::validation::validate_shell_command(command) {
+                issues.push(ConfigIssue::new(&["emergency_stop_cmd"], e.into()));
+            }
+        }
+        for (i, action) in self.on_emergency.iter().enumerate() {
+            if let EmergencyAction::Command { command, .. } = action {
+                if let Err(e) = crate::validation::validate_shell_command(command) {
+                    let index = i.to_string();
+                    issues.push(ConfigIssue::new(&["on_emergency", &index], e.into()));
+                }
+            }
+        }
 
-impl ServiceConfig {
-    fn validate(&self, service_name: &str) -> Result<(), ConfigError> {
-        // Validate service name
-        if service_name.is_empty() {
-            return Err(ConfigError::InvalidServiceName(
-                "Service name cannot be empty".to_string(),
+        if let Some(reason) = self.access.check() {
+            issues.push(ConfigIssue::new(
+                &["access"],
+                ConfigError::InvalidAccess(reason),
             ));
         }
 
-        if !service_name
-            .chars()
-            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-        {
-            return Err(ConfigError::InvalidServiceName(format!(
-                "Service name '{}' contains invalid characters",
-                service_name
-            )));
+        if let Some(socket) = &self.socket {
+            let reason = if socket.as_os_str().is_empty() || socket.as_os_str() == "@" {
+                Some("the socket needs a path, or a name after '@'")
+            } else if crate::abstract_socket_name(socket).is_some() && !cfg!(target_os = "linux") {
+                Some("abstract '@' sockets need Linux; use a socket path")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                issues.push(ConfigIssue::new(
+                    &["socket"],
+                    ConfigError::InvalidSocket(reason.to_string()),
+                ));
+            }
         }
 
-        // Validate shell commands
-        if let ExecuteConfig::Shell {
-            command,
-            stop_command,
-            ..
-        } = &self.execute
-        {
-            crate::validation::validate_shell_command(command)?;
-            if let Some(stop_cmd) = stop_command {
-                crate::validation::validate_shell_command(stop_cmd)?;
+        // Validate groups
+        let mut groups: Vec<(&String, &Vec<String>)> = self.groups.iter().collect();
+        groups.sort();
+        for (group, members) in groups {
+            if group.is_empty()
+                || !group
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            {
+                issues.push(ConfigIssue::new(
+                    &["groups", group],
+                    ConfigError::InvalidGroupName(group.clone()),
+                ));
+            }
+
+            for member in members {
+                if !self.services.contains_key(member) {
+                    issues.push(ConfigIssue::new(
+                        &["groups", group],
+                        ConfigError::UnknownGroupMember {
+                            group: group.clone(),
+                            service: member.clone(),
+                        },
+                    ));
+                }
             }
         }
 
-        Ok(())
+        issues
     }
-}
-
-#[derive(Debug, thiserror::Error)]
-pub enum ConfigError {
-    #[error("Failed to read config file {0}: {1}")]
-    FileRead(PathBuf, String),
-
-    #[error("Failed to parse config: {0}")]
-    Parse(String),
 
-    #[error("Unsupported config version: {0} (expected '1')")]
-    UnsupportedVersion(String),
+    /// Build the dependency graph of all services
+    pub fn dependency_graph(&self) -> Result<DependencyGraph, DagError> {
+        let deps_map: HashMap<String, Vec<Dependency>> = self
+            .services
+            .iter()
+            .map(|(name, svc)| (name.clone(), svc.dependencies.clone()))
+            .collect();
 
-    #[error("Invalid workspace name: {0}")]
-    InvalidWorkspaceName(String),
+        DependencyGraph::new(&deps_map)
+    }
 
-    #[error("Invalid service name: {0}")]
-    InvalidServiceName(String),
+    /// Actions of an emergency stop: `emergency_stop_cmd` (if set), then `on_emergency`
+    pub fn emergency_actions(&self) -> Vec<EmergencyAction> {
+        let stop_cmd = self
+            .emergency_stop_cmd
+            .iter()
+            .map(|command| EmergencyAction::Command {
+                command: command.clone(),
+                timeout: DEFAULT_EMERGENCY_COMMAND_TIMEOUT,
+            });
+        stop_cmd.chain(self.on_emergency.iter().cloned()).collect()
+    }
 
-    #[error("No services defined in configuration")]
-    NoServices,
+    /// Services of a group plus everything they transitively depend on,
+    /// or `None` if the group does not exist
+    pub fn group_with_dependencies(&self, group: &str) -> Option<HashSet<String>> {
+        let mut selected = HashSet::new();
+        let mut pending: Vec<&str> = self.groups.get(group)?.iter().map(String::as_str).collect();
 
-    #[error("Service '{service}' depends on unknown service '{dependency}'")]
-    UnknownDependency { service: String, dependency: String },
+        while let Some(name) = pending.pop() {
+            if !selected.insert(name.to_string()) {
+                continue;
+            }
+            if let Some(service) = self.services.get(name) {
+                pending.extend(service.dependencies.iter().map(|d| d.service_name()));
+            }
+        }
 
-    #[error("Unsafe shell command: {0}")]
-    UnsafeShellCommand(String),
+        Some(selected)
+    }
 }
 
-// Bridge validation error
-impl From<crate::validation::ValidationError> for ConfigError {
-    fn from(err: crate::validation::ValidationError) -> Self {
-        ConfigError::UnsafeShellCommand(err.to_string())
+/// Check that `name` can name a workspace. It becomes part of the names of
+/// the workspace's socket and PID file, so only letters, digits, `-` and `_`
+/// are allowed.
+pub fn check_workspace_name(name: &str) -> Result<(), ConfigError> {
+    if name.is_empty() {
+        return Err(ConfigError::InvalidWorkspaceName(
+            "Workspace name cannot be empty".to_string(),
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ConfigError::InvalidWorkspaceName(format!(
+            "Workspace name '{}' contains invalid characters",
+            name
+        )));
     }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+/// Settings of a service that only take effect in a new process, so a
+/// reload restarts the service when one of them changes
+pub const SPAWN_SETTINGS: &[&str] = &[
+    "execute",
+    "env",
+    "env_file",
+    "secrets",
+    "gpu",
+    "limits",
+    "cpu_affinity",
+    "scheduler",
+];
 
-    #[test]
-    fn test_load_valid_config() {
-        let yaml = r#"
-version: "1"
-name: test-workspace
-services:
-  service1:
-    execute:
-      type: pixi
-      task: test-task
-"#;
+/// Settings of a service that differ between two recipes, by their key under
+/// the service, e.g. `["execute", "labels"]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceChanges(pub Vec<String>);
 
-        let mut file = NamedTempFile::new().unwrap();
-        file.write_all(yaml.as_bytes()).unwrap();
+impl ServiceChanges {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 
-        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
-        assert_eq!(config.name, "test-workspace");
-        assert_eq!(config.services.len(), 1);
+    /// Whether a changed setting needs a new process; the others (labels,
+    /// policy, health checks, ...) apply to the running one
+    pub fn needs_restart(&self) -> bool {
+        self.0
+            .iter()
+            .any(|setting| SPAWN_SETTINGS.contains(&setting.as_str()))
     }
+}
 
-    #[test]
-    fn test_invalid_version() {
-        let yaml = r#"
-version: "2"
-name: test
-services:
-  service1:
-    execute:
-      type: pixi
-      task: test
-"#;
+impl ServiceConfig {
+    /// Settings that differ in `new`. `env` compares the variables the
+    /// service ends up with, its own over the workspace's (`old_env` and
+    /// `new_env`), so a workspace change it overrides is no change.
+    pub fn changes(
+        &self,
+        old_env: &HashMap<String, String>,
+        new: &ServiceConfig,
+        new_env: &HashMap<String, String>,
+    ) -> ServiceChanges {
+        let settings = |config: &ServiceConfig| match serde_json::to_value(config) {
+            Ok(serde_json::Value::Object(settings)) => settings,
+            _ => serde_json::Map::new(),
+        };
+        let (old_settings, new_settings) = (settings(self), settings(new));
+        let keys: BTreeSet<&String> = old_settings.keys().chain(new_settings.keys()).collect();
+        let mut changed: Vec<String> = keys
+            .into_iter()
+            .filter(|key| key.as_str() != "env" && old_settings.get(*key) != new_settings.get(*key))
+            .cloned()
+            .collect();
+
+        let effective_env = |workspace_env: &HashMap<String, String>, config: &ServiceConfig| {
+            let mut env = workspace_env.clone();
+            env.extend(config.env.clone());
+            env
+        };
+        if effective_env(old_env, self) != effective_env(new_env, new) {
+            changed.push("env".to_string());
+            changed.sort();
+        }
+        ServiceChanges(changed)
+    }
+
+    /// Environment of the service's processes, secrets aside: its env file,
+    /// overridden by the workspace `env`, overridden by its own `env`
+    pub fn environment(
+        &self,
+        workspace_env: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, crate::EnvFileError> {
+        let mut env = match &self.env_file {
+            Some(path) => crate::load_env_file(path)?,
+            None => HashMap::new(),
+        };
+        env.extend(workspace_env.clone());
+        env.extend(self.env.clone());
+        Ok(env)
+    }
+
+    /// Record this service's validation problems in `issues`
+    fn check(&self, service_name: &str, issues: &mut Vec<ConfigIssue>) {
+        let mut push = |key: &[&str], error: ConfigError| {
+            let mut path = vec!["services", service_name];
+            path.extend_from_slice(key);
+            issues.push(ConfigIssue::new(&path, error));
+        };
+
+        // Validate service name
+        if service_name.is_empty() {
+            push(
+                &[],
+                ConfigError::InvalidServiceName("Service name cannot be empty".to_string()),
+            );
+        } else if !service_name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            push(
+                &[],
+                ConfigError::InvalidServiceName(format!(
+                    "Service name '{}' contains invalid characters",
+                    service_name
+                )),
+            );
+        }
+
+        for (key, value) in &self.labels {
+            if let Some(reason) = crate::check_label(key, value) {
+                push(
+                    &["labels", key],
+                    ConfigError::InvalidLabel {
+                        service: service_name.to_string(),
+                        reason,
+                    },
+                );
+            }
+        }
+
+        for (key, checker) in [
+            ("health_check", &self.health_check),
+            ("readiness", &self.readiness),
+        ] {
+            if let Some(reason) = checker.as_ref().and_then(HealthChecker::check) {
+                push(
+                    &[key, "headers"],
+                    ConfigError::InvalidHealthCheck {
+                        service: service_name.to_string(),
+                        check: key,
+                        reason,
+                    },
+                );
+            }
+        }
+
+        // Readiness is probed by the daemon; heartbeats only report liveness
+        if let Some(HealthChecker::Heartbeat { .. }) = self.readiness {
+            push(
+                &["readiness"],
+                ConfigError::InvalidReadiness(service_name.to_string()),
+            );
+        }
+
+        // Lifecycle nodes only exist in ROS2 launches
+        if !matches!(self.execute, ExecuteConfig::Ros2 { .. }) {
+            for (key, checker) in [
+                ("health_check", &self.health_check),
+                ("readiness", &self.readiness),
+            ] {
+                if let Some(HealthChecker::Lifecycle { .. }) = checker {
+                    push(
+                        &[key],
+                        ConfigError::LifecycleWithoutRos2(service_name.to_string()),
+                    );
+                }
+            }
+        }
+
+        if self.gpu_health && !self.gpu {
+            push(
+                &["gpu_health"],
+                ConfigError::GpuHealthWithoutGpu(service_name.to_string()),
+            );
+        }
+
+        let is_compose = matches!(self.execute, ExecuteConfig::Compose { .. });
+        let compose_limits = (is_compose && self.limits.is_some()).then(|| {
+            "limits are not supported for compose services; set them in the compose file"
+                .to_string()
+        });
+        if let Some(reason) = self
+            .limits
+            .as_ref()
+            .and_then(ResourceLimits::check)
+            .or(compose_limits)
+        {
+            push(
+                &["limits"],
+                ConfigError::InvalidLimits {
+                    service: service_name.to_string(),
+                    reason,
+                },
+            );
+        }
+
+        let scheduling_issues = [
+            (
+                "cpu_affinity",
+                self.cpu_affinity
+                    .as_deref()
+                    .and_then(crate::check_cpu_affinity),
+            ),
+            (
+                "scheduler",
+                self.scheduler.as_ref().and_then(SchedulerConfig::check),
+            ),
+            (
+                "scheduler",
+                (self.scheduler.is_some() && self.execute.container_engine().is_some()).then(|| {
+                    format!(
+                        "scheduler is not supported for {} services",
+                        self.execute.executor_type()
+                    )
+                }),
+            ),
+            (
+                "cpu_affinity",
+                (self.cpu_affinity.is_some() && is_compose).then(|| {
+                    "cpu_affinity is not supported for compose services; set cpuset in the compose file"
+                        .to_string()
+                }),
+            ),
+        ];
+        for (key, reason) in scheduling_issues {
+            if let Some(reason) = reason {
+                push(
+                    &[key],
+                    ConfigError::InvalidScheduling {
+                        service: service_name.to_string(),
+                        reason,
+                    },
+                );
+            }
+        }
+
+        if let Some(reason) = self
+            .health_debounce
+            .as_ref()
+            .and_then(HealthDebounce::check)
+        {
+            push(
+                &["health_debounce"],
+                ConfigError::InvalidHealthDebounce {
+                    service: service_name.to_string(),
+                    reason,
+                },
+            );
+        }
+
+        if self.start_timeout_sec == Some(0) {
+            push(
+                &["start_timeout_sec"],
+                ConfigError::InvalidStartTimeout(service_name.to_string()),
+            );
+        }
+
+        if self
+            .dependency_timeout
+            .is_some_and(|timeout| timeout.is_zero())
+        {
+            push(
+                &["dependency_timeout"],
+                ConfigError::InvalidDependencyTimeout(service_name.to_string()),
+            );
+        }
+
+        for (index, condition) in self.wait_for.iter().enumerate() {
+            if let Some(reason) = condition.check() {
+                push(
+                    &["wait_for", &index.to_string()],
+                    ConfigError::InvalidWaitFor {
+                        service: service_name.to_string(),
+                        reason,
+                    },
+                );
+            }
+        }
+        if self
+            .wait_for_timeout
+            .is_some_and(|timeout| timeout.is_zero())
+        {
+            push(
+                &["wait_for_timeout"],
+                ConfigError::InvalidWaitFor {
+                    service: service_name.to_string(),
+                    reason: "wait_for_timeout is 0; omit it to wait indefinitely".to_string(),
+                },
+            );
+        }
+
+        if let Err(e) = self.validate_stop_sequence(service_name) {
+            push(&["policy", "stop_sequence"], e);
+        }
+
+        // Relative directories are only checked once resolved against the recipe
+        if let Some(dir) = crate::get_working_dir(&self.execute) {
+            if dir.is_absolute() && !dir.is_dir() {
+                push(
+                    &["execute", "working_dir"],
+                    ConfigError::MissingWorkingDir {
+                        service: service_name.to_string(),
+                        path: dir,
+                    },
+                );
+            }
+        }
+
+        if let Some(hooks) = &self.hooks {
+            for hook in Hook::ALL {
+                if let Some(command) = hooks.command(hook) {
+                    if let Err(e) = crate::validation::validate_shell_command(command) {
+                        push(&["hooks", hook.key()], e.into());
+                    }
+                }
+            }
+        }
+
+        // Validate shell commands
+        if let ExecuteConfig::Shell {
+            command,
+            stop_command,
+            ..
+        } = &self.execute
+        {
+            if let Err(e) = crate::validation::validate_shell_command(command) {
+                push(&["execute", "command"], e.into());
+            }
+            if let Some(stop_cmd) = stop_command {
+                if let Err(e) = crate::validation::validate_shell_command(stop_cmd) {
+                    push(&["execute", "stop_command"], e.into());
+                }
+            }
+        }
+    }
+
+    fn validate_stop_sequence(&self, service_name: &str) -> Result<(), ConfigError> {
+        let sequence = &self.policy.stop_sequence;
+        if sequence.is_empty() {
+            return Ok(());
+        }
+
+        let invalid = |reason: &str| ConfigError::InvalidStopSequence {
+            service: service_name.to_string(),
+            reason: reason.to_string(),
+        };
+
+        // Containers are stopped with `docker stop`, which sends the image's STOPSIGNAL
+        match &self.execute {
+            ExecuteConfig::Docker { .. } => {
+                return Err(invalid("docker services are stopped with 'docker stop'"))
+            }
+            ExecuteConfig::Podman { .. } => {
+                return Err(invalid("podman services are stopped with 'podman stop'"))
+            }
+            ExecuteConfig::Compose { .. } => {
+                return Err(invalid("compose services are stopped with 'compose down'"))
+            }
+            _ => {}
+        }
+
+        if sequence.iter().any(|s| s.action == StopAction::StopCommand)
+            && crate::get_stop_command(&self.execute).is_none()
+        {
+            return Err(invalid(
+                "'stop-command' step requires a stop_command or stop_task",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A validation problem and the YAML keys leading to it, e.g.
+/// `["services", "lidar", "dependencies"]`
+#[derive(Debug)]
+pub struct ConfigIssue {
+    pub path: Vec<String>,
+    pub error: ConfigError,
+}
+
+impl ConfigIssue {
+    fn new(path: &[&str], error: ConfigError) -> Self {
+        Self {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    FileRead(PathBuf, String),
+
+    #[error("Failed to parse config: {0}")]
+    Parse(String),
+
+    #[error("Unsupported config version: {0} (expected '1')")]
+    UnsupportedVersion(String),
+
+    #[error("Invalid workspace name: {0}")]
+    InvalidWorkspaceName(String),
+
+    #[error("Invalid service name: {0}")]
+    InvalidServiceName(String),
+
+    #[error("No services defined in configuration")]
+    NoServices,
+
+    #[error("max_concurrent_starts is 0; omit it to start services without a limit")]
+    InvalidConcurrentStarts,
+
+    #[error("Service '{service}' depends on unknown service '{dependency}'")]
+    UnknownDependency { service: String, dependency: String },
+
+    #[error("Unsafe shell command: {0}")]
+    UnsafeShellCommand(String),
+
+    #[error("Invalid access settings: {0}")]
+    InvalidAccess(String),
+
+    #[error("Invalid restart_storm: {0}")]
+    InvalidRestartStorm(String),
+
+    #[error("Invalid log_throttle: {0}")]
+    InvalidLogThrottle(String),
+
+    #[error("Invalid socket: {0}")]
+    InvalidSocket(String),
+
+    #[error("Invalid group name: '{0}'")]
+    InvalidGroupName(String),
+
+    #[error("Group '{group}' references unknown service '{service}'")]
+    UnknownGroupMember { group: String, service: String },
+
+    #[error("Service '{0}' uses a heartbeat readiness check; use tcp, http, or script")]
+    InvalidReadiness(String),
+
+    #[error("Service '{0}' uses a lifecycle check, which requires a ros2 execute type")]
+    LifecycleWithoutRos2(String),
+
+    #[error("Service '{0}' sets gpu_health, which requires gpu: true")]
+    GpuHealthWithoutGpu(String),
+
+    #[error("Service '{service}' has invalid limits: {reason}")]
+    InvalidLimits { service: String, reason: String },
+
+    #[error("Service '{service}' has invalid scheduling: {reason}")]
+    InvalidScheduling { service: String, reason: String },
+
+    #[error("Service '{service}' has an invalid label: {reason}")]
+    InvalidLabel { service: String, reason: String },
+
+    #[error("Service '{service}' has invalid health_debounce: {reason}")]
+    InvalidHealthDebounce { service: String, reason: String },
+
+    #[error("Service '{service}' has an invalid {check}: {reason}")]
+    InvalidHealthCheck {
+        service: String,
+        check: &'static str,
+        reason: String,
+    },
+
+    #[error("Service '{0}' has start_timeout_sec 0; omit it to wait indefinitely")]
+    InvalidStartTimeout(String),
+
+    #[error("Service '{0}' has dependency_timeout 0; omit it to wait indefinitely")]
+    InvalidDependencyTimeout(String),
+
+    #[error("Service '{service}' has an invalid wait_for: {reason}")]
+    InvalidWaitFor { service: String, reason: String },
+
+    #[error("Invalid daemon_log settings: {0}")]
+    InvalidDaemonLog(String),
+
+    #[error(
+        "Service '{service}' waits for '{dependency}' to complete, but it is not a oneshot service"
+    )]
+    NotOneshot { service: String, dependency: String },
+
+    #[error(
+        "Service '{service}' waits for '{dependency}' to fail, but it is restarted without limit; set its policy.max_restarts"
+    )]
+    RestartedWithoutLimit { service: String, dependency: String },
+
+    #[error("Service '{service}' has an invalid stop_sequence: {reason}")]
+    InvalidStopSequence { service: String, reason: String },
+
+    #[error("Service '{service}' has working_dir '{}', which is not a directory", path.display())]
+    MissingWorkingDir { service: String, path: PathBuf },
+
+    #[error("undefined variable '{variable}' in '{}'", path.display())]
+    UndefinedVariable { variable: String, path: PathBuf },
+
+    #[error("cannot expand '~' in '{}': HOME is not set", .0.display())]
+    HomeNotSet(PathBuf),
+
+    #[error("invalid path '{}': {reason}", path.display())]
+    InvalidPath { path: PathBuf, reason: String },
+
+    #[error("Service '{service}': {field}: {error}")]
+    InvalidServicePath {
+        service: String,
+        field: String,
+        error: Box<ConfigError>,
+    },
+}
+
+// Bridge validation error
+impl From<crate::validation::ValidationError> for ConfigError {
+    fn from(err: crate::validation::ValidationError) -> Self {
+        ConfigError::UnsafeShellCommand(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_load_valid_config() {
+        let yaml = r#"
+version: "1"
+name: test-workspace
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test-task
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
+        assert_eq!(config.name, "test-workspace");
+        assert_eq!(config.services.len(), 1);
+    }
+
+    #[test]
+    fn test_env_file_resolved_against_config_dir() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  nav:
+    execute:
+      type: pixi
+      task: nav
+    env_file: robots/krill01.env
+"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::write(&path, yaml).unwrap();
+
+        let config = KrillConfig::from_file(&path).unwrap();
+        assert_eq!(
+            config.services["nav"].env_file,
+            Some(dir.path().join("robots/krill01.env"))
+        );
+    }
+
+    #[test]
+    fn test_service_env_layering_and_secrets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("uplink.env"),
+            "UPLINK_HOST=file\nROS_DOMAIN_ID=1\nLOG=file\n",
+        )
+        .unwrap();
+        let yaml = r#"
+version: "1"
+name: test
+env:
+  ROS_DOMAIN_ID: "42"
+  LOG: info
+services:
+  uplink:
+    execute:
+      type: pixi
+      task: uplink
+    env_file: uplink.env
+    env:
+      LOG: debug
+    secrets:
+      UPLINK_TOKEN:
+        file: secrets/token
+      UPLINK_PASSWORD:
+        command: pass show uplink
+"#;
+
+        let config = KrillConfig::parse(yaml, Some(dir.path())).unwrap();
+        config.validate().unwrap();
+        let uplink = &config.services["uplink"];
+        let env = uplink.environment(&config.env).unwrap();
+        assert_eq!(env["UPLINK_HOST"], "file");
+        assert_eq!(env["ROS_DOMAIN_ID"], "42");
+        assert_eq!(env["LOG"], "debug");
+        assert!(!env.contains_key("UPLINK_TOKEN"));
+
+        assert_eq!(
+            uplink.secrets["UPLINK_TOKEN"],
+            SecretSource::File(dir.path().join("secrets/token"))
+        );
+        assert_eq!(
+            uplink.secrets["UPLINK_PASSWORD"],
+            SecretSource::Command("pass show uplink".to_string())
+        );
+    }
+
+    #[test]
+    fn test_service_changes() {
+        let yaml = r#"
+version: "1"
+name: test
+env:
+  ROS_DOMAIN_ID: "42"
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar_driver
+    env:
+      LOG: info
+"#;
+        let old = KrillConfig::parse(yaml, None).unwrap();
+        let changes = |new: &KrillConfig| {
+            old.services["lidar"].changes(&old.env, &new.services["lidar"], &new.env)
+        };
+        assert!(changes(&old).is_empty());
+
+        // Metadata and runtime settings apply without a restart
+        let mut new = old.clone();
+        let lidar = new.services.get_mut("lidar").unwrap();
+        lidar
+            .labels
+            .insert("tier".to_string(), "perception".to_string());
+        lidar.policy.max_restarts = 9;
+        assert_eq!(changes(&new).0, ["labels", "policy"]);
+        assert!(!changes(&new).needs_restart());
+
+        // The workspace env counts where the service does not override it
+        let mut new = old.clone();
+        new.env.insert("LOG".to_string(), "debug".to_string());
+        assert!(changes(&new).is_empty());
+        new.env.insert("ROS_DOMAIN_ID".to_string(), "7".to_string());
+        assert_eq!(changes(&new).0, ["env"]);
+        assert!(changes(&new).needs_restart());
+
+        let mut new = old.clone();
+        new.services.get_mut("lidar").unwrap().execute = ExecuteConfig::Shell {
+            command: "lidar_driver --fast".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        assert_eq!(changes(&new).0, ["execute"]);
+        assert!(changes(&new).needs_restart());
+    }
+
+    #[test]
+    fn test_working_dir_variables_expanded() {
+        let yaml = r#"
+version: "1"
+name: test
+env:
+  ROBOT: rover
+services:
+  nav:
+    execute:
+      type: shell
+      command: ./nav
+      working_dir: ${WORKSPACE_DIR}/${ROBOT}
+    env_file: ${ROBOT}.env
+  lidar:
+    execute:
+      type: shell
+      command: ./lidar
+      working_dir: drivers
+"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::write(&path, yaml).unwrap();
+
+        // Directories must exist once resolved
+        let err = KrillConfig::from_file(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::MissingWorkingDir { ref service, ref path }
+                if service == "lidar" && *path == dir.path().join("drivers")
+        ));
+
+        std::fs::create_dir(dir.path().join("rover")).unwrap();
+        std::fs::create_dir(dir.path().join("drivers")).unwrap();
+        let config = KrillConfig::from_file(&path).unwrap();
+        assert_eq!(
+            crate::get_working_dir(&config.services["nav"].execute),
+            Some(dir.path().join("rover"))
+        );
+        assert_eq!(
+            config.services["nav"].env_file,
+            Some(dir.path().join("rover.env"))
+        );
+
+        let err = KrillConfig::parse(
+            &yaml.replace("${ROBOT}.env", "${KRILL_TEST_UNDEFINED}.env"),
+            Some(dir.path()),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Service 'nav': env_file: undefined variable 'KRILL_TEST_UNDEFINED'"));
+    }
+
+    #[test]
+    fn test_readiness_check() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  map_server:
+    execute:
+      type: pixi
+      task: map
+    readiness:
+      type: tcp
+      port: 9000
+      timeout: 1s
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        assert!(matches!(
+            config.services["map_server"].readiness,
+            Some(HealthChecker::Tcp { port: 9000, .. })
+        ));
+
+        let yaml = yaml.replace("type: tcp\n      port: 9000", "type: heartbeat");
+        let config: KrillConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidReadiness(name)) if name == "map_server"
+        ));
+    }
+
+    #[test]
+    fn test_lifecycle_check_requires_ros2() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  lidar:
+    execute:
+      type: ros2
+      package: lidar_driver
+      launch_file: lidar.launch.py
+    health_check:
+      type: lifecycle
+      node: /lidar_driver
+      timeout: 5s
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+
+        let yaml = yaml.replace(
+            "type: ros2\n      package: lidar_driver\n      launch_file: lidar.launch.py",
+            "type: shell\n      command: lidar",
+        );
+        let config: KrillConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::LifecycleWithoutRos2(name)) if name == "lidar"
+        ));
+    }
+
+    #[test]
+    fn test_gpu_health_requires_gpu() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  detector:
+    execute:
+      type: shell
+      command: detector
+    gpu: true
+    gpu_health: true
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+
+        let yaml = yaml.replace("    gpu: true\n", "");
+        let config: KrillConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::GpuHealthWithoutGpu(name)) if name == "detector"
+        ));
+    }
+
+    #[test]
+    fn test_log_format() {
+        let yaml = r#"
+version: "1"
+name: test
+log_format: jsonl
+services:
+  a:
+    execute:
+      type: shell
+      command: echo a
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.log_format, LogFormat::Jsonl);
+
+        let config: KrillConfig =
+            serde_yaml::from_str(&yaml.replace("log_format: jsonl\n", "")).unwrap();
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert!(serde_yaml::from_str::<KrillConfig>(&yaml.replace("jsonl", "xml")).is_err());
+    }
+
+    #[test]
+    fn test_restart_storm() {
+        let yaml = r#"
+version: "1"
+name: test
+restart_storm:
+  max_failures: 5
+  window: 30s
+services:
+  a:
+    execute:
+      type: shell
+      command: echo a
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.restart_storm,
+            Some(RestartStormConfig {
+                max_failures: 5,
+                window: std::time::Duration::from_secs(30),
+            })
+        );
+        assert!(config.validate().is_ok());
+
+        let config: KrillConfig =
+            serde_yaml::from_str(&yaml.replace("  window: 30s\n", "")).unwrap();
+        assert_eq!(
+            config.restart_storm.unwrap().window,
+            std::time::Duration::from_secs(60)
+        );
+
+        let config: KrillConfig =
+            serde_yaml::from_str(&yaml.replace("max_failures: 5", "max_failures: 0")).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidRestartStorm(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_rotation_config() {
+        let yaml = r#"
+version: "1"
+name: test
+log_rotation:
+  max_size_mb: 50
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
+        assert_eq!(config.log_rotation.max_size_mb, 50);
+        assert_eq!(config.log_rotation.max_files, 5);
+        assert_eq!(config.log_rotation.max_size_bytes(), 50 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_log_throttle_config() {
+        let yaml = r#"
+version: "1"
+name: test
+log_throttle:
+  lines_per_sec: 50
+  max_repeats: 1
+services:
+  a:
+    execute:
+      type: shell
+      command: echo a
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.log_throttle,
+            LogThrottleConfig {
+                enabled: true,
+                lines_per_sec: 50,
+                burst: 500,
+                max_repeats: 1,
+            }
+        );
+        assert!(config.validate().is_ok());
+
+        let config: KrillConfig =
+            serde_yaml::from_str(&yaml.replace("max_repeats: 1", "max_repeats: 0")).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidLogThrottle(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_buffer_config() {
+        let yaml = r#"
+version: "1"
+name: test
+log_buffer_lines: 1000
+services:
+  quiet:
+    execute:
+      type: pixi
+      task: quiet
+  pointcloud:
+    log_buffer_lines: 20000
+    execute:
+      type: pixi
+      task: pointcloud
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.log_buffer_lines, 1000);
+        assert_eq!(config.log_buffer_max_mb, 64);
+        assert_eq!(config.services["quiet"].log_buffer_lines, None);
+        assert_eq!(config.services["pointcloud"].log_buffer_lines, Some(20000));
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        let yaml = r#"
+version: "2"
+name: test
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unsupported"));
+    }
+
+    #[test]
+    fn test_unknown_dependency() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+    dependencies:
+      - nonexistent
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("unknown service"));
+    }
+
+    #[test]
+    fn test_docker_config_loads() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: docker
+      image: ubuntu:latest
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
+        assert_eq!(config.services.len(), 1);
+        assert!(matches!(
+            config.services.get("service1").unwrap().execute,
+            ExecuteConfig::Docker { .. }
+        ));
+    }
+
+    #[test]
+    fn test_unsafe_shell_command() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: shell
+      command: "ls | grep foo"
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unsafe"));
+    }
+
+    #[test]
+    fn test_groups_include_transitive_dependencies() {
+        let yaml = r#"
+version: "1"
+name: test
+groups:
+  perception: [detector]
+services:
+  driver:
+    execute:
+      type: pixi
+      task: driver
+  camera:
+    execute:
+      type: pixi
+      task: camera
+    dependencies:
+      - driver
+  detector:
+    execute:
+      type: pixi
+      task: detector
+    dependencies:
+      - camera: healthy
+  sim:
+    execute:
+      type: pixi
+      task: sim
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
+        let selected = config.group_with_dependencies("perception").unwrap();
+        let expected: HashSet<String> = ["detector", "camera", "driver"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(selected, expected);
+        assert!(config.group_with_dependencies("missing").is_none());
+    }
+
+    #[test]
+    fn test_group_with_unknown_service() {
+        let yaml = r#"
+version: "1"
+name: test
+groups:
+  sim: [gazebo]
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnknownGroupMember { ref group, ref service })
+                if group == "sim" && service == "gazebo"
+        ));
+    }
+
+    #[test]
+    fn test_zero_start_timeout_rejected() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    start_timeout_sec: 0
+    execute:
+      type: pixi
+      task: test
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(matches!(result, Err(ConfigError::InvalidStartTimeout(_))));
+    }
+
+    #[test]
+    fn test_http_header_with_line_break_rejected() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+    health_check:
+      type: http
+      port: 8080
+      path: /healthz
+      headers:
+        X-Robot: "krill01\r\nX-Admin: yes"
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidHealthCheck {
+                check: "health_check",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_zero_dependency_timeout_rejected() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    dependency_timeout: 0s
+    execute:
+      type: pixi
+      task: test
+"#;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidDependencyTimeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_included_recipes_validated_as_a_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("common.yaml"),
+            r#"
+version: "1"
+services:
+  planner:
+    execute:
+      type: pixi
+      task: planner
+    dependencies: [lidar]
+"#,
+        )
+        .unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::write(
+            &path,
+            r#"
+include: [common.yaml]
+name: krill01
+services:
+  lidar:
+    execute:
+      type: pixi
+      task: lidar
+"#,
+        )
+        .unwrap();
+
+        let config = KrillConfig::from_file(&path).unwrap();
+        assert_eq!(config.name, "krill01");
+        assert_eq!(config.services.len(), 2);
+        assert_eq!(config.included, [dir.path().join("common.yaml")]);
+
+        // The dependency is only satisfied by the including recipe
+        std::fs::write(
+            &path,
+            "include: [common.yaml]\nname: krill01\nservices: {}\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            KrillConfig::from_file(&path),
+            Err(ConfigError::UnknownDependency { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wait_for_resolved_and_validated() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  lidar:
+    execute:
+      type: pixi
+      task: lidar
+    wait_for:
+      - device: /dev/ttyUSB0
+      - file: calibration/done
+      - tcp: 192.168.1.201:2368
+    wait_for_timeout: 30s
+"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::write(&path, yaml).unwrap();
+
+        let config = KrillConfig::from_file(&path).unwrap();
+        let lidar = &config.services["lidar"];
+        assert_eq!(
+            lidar.wait_for[1],
+            Precondition::File(dir.path().join("calibration/done"))
+        );
+        assert_eq!(
+            lidar.wait_for_timeout,
+            Some(std::time::Duration::from_secs(30))
+        );
+
+        std::fs::write(&path, yaml.replace("2368", "lidar")).unwrap();
+        let issues = KrillConfig::parse(&std::fs::read_to_string(&path).unwrap(), None)
+            .unwrap()
+            .issues();
+        assert_eq!(issues[0].path, ["services", "lidar", "wait_for", "2"]);
+        assert!(matches!(
+            issues[0].error,
+            ConfigError::InvalidWaitFor { .. }
+        ));
+    }
+
+    #[test]
+    fn test_start_delay_parses_duration() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  power:
+    execute:
+      type: pixi
+      task: power
+  can_driver:
+    start_delay: 5s
+    dependencies:
+      - power: healthy
+    execute:
+      type: pixi
+      task: can
+"#;
+
+        let config = KrillConfig::parse(yaml, None).unwrap();
+        assert_eq!(
+            config.services["can_driver"].start_delay,
+            Some(std::time::Duration::from_secs(5))
+        );
+        assert_eq!(config.services["power"].start_delay, None);
+    }
+
+    #[test]
+    fn test_scheduling_config() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  control:
+    cpu_affinity: [2, 3]
+    scheduler:
+      policy: fifo
+      priority: 50
+    execute:
+      type: shell
+      command: ./control_loop
+"#;
+
+        let config = KrillConfig::parse(yaml, None).unwrap();
+        config.validate().unwrap();
+        let control = &config.services["control"];
+        assert_eq!(control.cpu_affinity, Some(vec![2, 3]));
+        assert_eq!(
+            control.scheduler,
+            Some(SchedulerConfig {
+                policy: crate::SchedulerPolicy::Fifo,
+                priority: 50,
+            })
+        );
+
+        let config =
+            KrillConfig::parse(&yaml.replace("priority: 50", "priority: 0"), None).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidScheduling { .. })
+        ));
+        let config = KrillConfig::parse(&yaml.replace("[2, 3]", "[]"), None).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidScheduling { .. })
+        ));
+    }
+
+    #[test]
+    fn test_services_from_template() {
+        let yaml = r#"
+version: "1"
+name: test
+templates:
+  camera:
+    execute:
+      type: shell
+      command: camera_driver --device ${device}
+    critical: true
+services:
+  cam-front:
+    template: camera
+    params:
+      device: /dev/video0
+  cam-rear:
+    template: camera
+    params:
+      device: /dev/video1
+    critical: false
+"#;
+
+        let config = KrillConfig::parse(yaml, None).unwrap();
+        config.validate().unwrap();
+        match &config.services["cam-rear"].execute {
+            ExecuteConfig::Shell { command, .. } => {
+                assert_eq!(command, "camera_driver --device /dev/video1")
+            }
+            other => panic!("unexpected executor {:?}", other),
+        }
+        assert!(config.services["cam-front"].critical);
+        assert!(!config.services["cam-rear"].critical);
+
+        let err = KrillConfig::parse(&yaml.replace("device: /dev/video1", "dev: x"), None);
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("undefined parameter 'device'"));
+    }
+
+    #[test]
+    fn test_stop_command_step_requires_stop_command() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: shell
+      command: python node.py
+    policy:
+      stop_sequence:
+        - action: stop-command
+          timeout: 5s
+"#;
 
         let mut file = NamedTempFile::new().unwrap();
         file.write_all(yaml.as_bytes()).unwrap();
 
         let result = KrillConfig::from_file(&file.path().to_path_buf());
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Unsupported"));
+        assert!(matches!(
+            result,
+            Err(ConfigError::InvalidStopSequence { ref service, .. }) if service == "service1"
+        ));
     }
 
     #[test]
-    fn test_unknown_dependency() {
+    fn test_invalid_access_socket_mode() {
+        let yaml = r#"
+version: "1"
+name: test
+access:
+  socket_mode: "rw-rw----"
+services:
+  a:
+    execute:
+      type: shell
+      command: echo a
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidAccess(_))
+        ));
+    }
+
+    #[test]
+    fn test_socket_setting() {
+        let yaml = r#"
+version: "1"
+name: test
+socket: run/krill.sock
+services:
+  a:
+    execute:
+      type: shell
+      command: echo a
+"#;
+        let config = KrillConfig::parse(yaml, Some(Path::new("/opt/robot"))).unwrap();
+        assert_eq!(
+            config.socket_path().unwrap(),
+            Path::new("/opt/robot/run/krill.sock")
+        );
+
+        let config = KrillConfig::parse(
+            &yaml.replace("run/krill.sock", "\"@krill-test\""),
+            Some(Path::new("/opt/robot")),
+        )
+        .unwrap();
+        assert_eq!(config.socket_path().unwrap(), Path::new("@krill-test"));
+        assert_eq!(config.validate().is_ok(), cfg!(target_os = "linux"));
+
+        let config = KrillConfig::parse(&yaml.replace("run/krill.sock", "\"@\""), None).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidSocket(_))
+        ));
+
+        let config =
+            KrillConfig::parse(&yaml.replace("socket: run/krill.sock\n", ""), None).unwrap();
+        assert_eq!(
+            config.socket_path().unwrap(),
+            crate::workspace_socket_path("test").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_prometheus_disabled_by_default() {
         let yaml = r#"
 version: "1"
 name: test
@@ -233,58 +2254,421 @@ services:
     execute:
       type: pixi
       task: test
-    dependencies:
-      - nonexistent
 "#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(!config.prometheus.enabled);
+        assert!(!PrometheusConfig::default().enabled);
+    }
+
+    #[test]
+    fn test_prometheus_enabled_on_default_port() {
+        let yaml = r#"
+version: "1"
+name: test
+prometheus:
+  enabled: true
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.prometheus.enabled);
+        assert_eq!(config.prometheus.listen.port(), 9464);
+    }
+
+    #[test]
+    fn test_on_emergency_actions() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.on_emergency, vec![EmergencyAction::StopServices]);
+
+        let yaml = r#"
+version: "1"
+name: test
+on_emergency:
+  - action: command
+    command: /opt/robot/engage_brakes.sh
+  - action: stop-services
+  - action: command
+    command: "wall estop | logger"
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.on_emergency.len(), 3);
+
+        let issues = config.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, vec!["on_emergency", "2"]);
+        assert!(matches!(
+            issues[0].error,
+            ConfigError::UnsafeShellCommand(_)
+        ));
+    }
+
+    #[test]
+    fn test_emergency_stop_cmd_runs_first() {
+        let yaml = r#"
+version: "1"
+name: test
+emergency_stop_cmd: /opt/robot/halt_motors.sh
+on_emergency:
+  - action: stop-services
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
+        let config: KrillConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.issues().is_empty());
+        assert_eq!(
+            config.emergency_actions(),
+            vec![
+                EmergencyAction::Command {
+                    command: "/opt/robot/halt_motors.sh".to_string(),
+                    timeout: DEFAULT_EMERGENCY_COMMAND_TIMEOUT,
+                },
+                EmergencyAction::StopServices,
+            ]
+        );
 
+        let yaml = r#"
+version: "1"
+name: test
+emergency_stop_cmd: "halt; reboot"
+services:
+  service1:
+    execute:
+      type: pixi
+      task: test
+"#;
         let mut file = NamedTempFile::new().unwrap();
         file.write_all(yaml.as_bytes()).unwrap();
-
         let result = KrillConfig::from_file(&file.path().to_path_buf());
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("unknown service"));
+        assert!(matches!(result, Err(ConfigError::UnsafeShellCommand(_))));
     }
 
     #[test]
-    fn test_docker_config_loads() {
+    fn test_completed_dependency_requires_oneshot() {
         let yaml = r#"
 version: "1"
 name: test
 services:
-  service1:
+  calibrate:
     execute:
-      type: docker
-      image: ubuntu:latest
+      type: shell
+      command: python calibrate.py
+  driver:
+    execute:
+      type: shell
+      command: python driver.py
+    dependencies:
+      - calibrate: completed
 "#;
 
         let mut file = NamedTempFile::new().unwrap();
         file.write_all(yaml.as_bytes()).unwrap();
+        let result = KrillConfig::from_file(&file.path().to_path_buf());
+        assert!(matches!(
+            result,
+            Err(ConfigError::NotOneshot { ref dependency, .. }) if dependency == "calibrate"
+        ));
 
-        let config = KrillConfig::from_file(&file.path().to_path_buf()).unwrap();
-        assert_eq!(config.services.len(), 1);
+        let yaml = yaml.replace("  calibrate:\n", "  calibrate:\n    oneshot: true\n");
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        assert!(KrillConfig::from_file(&file.path().to_path_buf()).is_ok());
+    }
+
+    #[test]
+    fn test_failed_dependency_requires_restart_limit() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  gps-rtk:
+    execute:
+      type: shell
+      command: rtk_driver
+  gps-basic:
+    execute:
+      type: shell
+      command: nmea_driver
+    dependencies:
+      - gps-rtk: failed
+"#;
+
+        let load = |yaml: &str| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(yaml.as_bytes()).unwrap();
+            KrillConfig::from_file(&file.path().to_path_buf())
+        };
         assert!(matches!(
-            config.services.get("service1").unwrap().execute,
-            ExecuteConfig::Docker { .. }
+            load(yaml),
+            Err(ConfigError::RestartedWithoutLimit { ref dependency, .. }) if dependency == "gps-rtk"
         ));
+
+        let limited = yaml.replace(
+            "  gps-rtk:\n",
+            "  gps-rtk:\n    policy:\n      max_restarts: 3\n",
+        );
+        assert!(load(&limited).is_ok());
+        let never = yaml.replace(
+            "  gps-rtk:\n",
+            "  gps-rtk:\n    policy:\n      restart: never\n",
+        );
+        assert!(load(&never).is_ok());
     }
 
     #[test]
-    fn test_unsafe_shell_command() {
+    fn test_service_hooks() {
+        let yaml = r#"
+version: "1"
+name: test
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar_driver
+    hooks:
+      pre_start: usbreset 0bda:8153
+      post_stop: logger lidar stopped
+"#;
+
+        let load = |yaml: &str| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(yaml.as_bytes()).unwrap();
+            KrillConfig::from_file(&file.path().to_path_buf())
+        };
+        let config = load(yaml).unwrap();
+        let hooks = config.services["lidar"].hooks.as_ref().unwrap();
+        assert_eq!(hooks.command(Hook::PreStart), Some("usbreset 0bda:8153"));
+        assert_eq!(hooks.command(Hook::PostStart), None);
+        assert_eq!(hooks.command(Hook::PostStop), Some("logger lidar stopped"));
+        assert_eq!(hooks.timeout, std::time::Duration::from_secs(30));
+
+        let with_timeout = yaml.replace("      post_stop:", "      timeout: 5s\n      post_stop:");
+        let config = load(&with_timeout).unwrap();
+        assert_eq!(
+            config.services["lidar"].hooks.as_ref().unwrap().timeout,
+            std::time::Duration::from_secs(5)
+        );
+
+        assert!(load(&yaml.replace("usbreset 0bda:8153", "\"\"")).is_err());
+    }
+
+    #[test]
+    fn test_zero_max_concurrent_starts_rejected() {
+        let yaml = r#"
+version: "1"
+name: test
+max_concurrent_starts: 0
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar_driver
+"#;
+
+        let load = |yaml: &str| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(yaml.as_bytes()).unwrap();
+            KrillConfig::from_file(&file.path().to_path_buf())
+        };
+        assert!(matches!(
+            load(yaml),
+            Err(ConfigError::InvalidConcurrentStarts)
+        ));
+
+        let config = load(&yaml.replace("starts: 0", "starts: 2")).unwrap();
+        assert_eq!(config.max_concurrent_starts, Some(2));
+    }
+
+    #[test]
+    fn test_daemon_log_filter() {
         let yaml = r#"
 version: "1"
 name: test
+daemon_log:
+  format: json
+  level: warn
+  modules:
+    krill_daemon::health: debug
+    krill_daemon::ipc_server: trace
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar_driver
+"#;
+
+        let load = |yaml: &str| {
+            let mut file = NamedTempFile::new().unwrap();
+            file.write_all(yaml.as_bytes()).unwrap();
+            KrillConfig::from_file(&file.path().to_path_buf())
+        };
+        let config = load(yaml).unwrap();
+        assert_eq!(config.daemon_log.format, DaemonLogFormat::Json);
+        assert_eq!(
+            config.daemon_log.filter(),
+            "warn,krill_daemon::health=debug,krill_daemon::ipc_server=trace"
+        );
+
+        assert!(matches!(
+            load(&yaml.replace("health: debug", "health: loud")),
+            Err(ConfigError::InvalidDaemonLog(_))
+        ));
+        assert_eq!(DaemonLogConfig::default().filter(), "info");
+    }
+
+    #[test]
+    fn test_issues_reports_every_problem_with_its_path() {
+        let yaml = r#"
+version: "2"
+name: test-workspace
 services:
   service1:
     execute:
       type: shell
-      command: "ls | grep foo"
+      command: echo hi; rm -rf /
+    start_timeout_sec: 0
+  service2:
+    execute:
+      type: pixi
+      task: test-task
+    dependencies:
+      - missing
 "#;
 
-        let mut file = NamedTempFile::new().unwrap();
-        file.write_all(yaml.as_bytes()).unwrap();
+        let config = KrillConfig::parse(yaml, None).unwrap();
+        let issues = config.issues();
+        let paths: Vec<String> = issues.iter().map(|i| i.path.join(".")).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "version",
+                "services.service1.start_timeout_sec",
+                "services.service1.execute.command",
+                "services.service2.dependencies",
+            ]
+        );
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::UnsupportedVersion(_))
+        ));
+    }
 
-        let result = KrillConfig::from_file(&file.path().to_path_buf());
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Unsafe"));
+    #[test]
+    fn test_custom_executor_must_be_registered() {
+        struct Planner;
+        impl crate::Executor for Planner {
+            fn validate(&self, options: &crate::ExecuteOptions) -> Result<(), String> {
+                match options.get("map") {
+                    Some(_) => Ok(()),
+                    None => Err("missing 'map'".to_string()),
+                }
+            }
+
+            fn build_command(
+                &self,
+                _options: &crate::ExecuteOptions,
+                _env_vars: &HashMap<String, String>,
+            ) -> Result<Vec<String>, crate::ProcessError> {
+                Ok(vec!["planner".to_string()])
+            }
+        }
+
+        let yaml = r#"
+version: "1"
+name: test-workspace
+services:
+  planner:
+    execute:
+      type: planner-test
+"#;
+        // Both are parse errors, positioned at the execute block
+        let err = KrillConfig::parse(yaml, None).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `planner-test`"));
+        assert_eq!(err.location().map(|l| l.line()), Some(6));
+
+        crate::register_executor("planner-test", std::sync::Arc::new(Planner)).unwrap();
+        let err = KrillConfig::parse(yaml, None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("invalid planner-test execute: missing 'map'"));
+        assert_eq!(err.location().map(|l| l.line()), Some(6));
+
+        let config = KrillConfig::parse(&format!("{}      map: office\n", yaml), None).unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_container_services_reject_host_settings() {
+        let yaml = r#"
+version: "1"
+name: test-workspace
+services:
+  nav:
+    execute:
+      type: podman
+      image: ros:humble
+      userns: keep-id
+    scheduler:
+      policy: fifo
+      priority: 10
+  stack:
+    execute:
+      type: compose
+      file: compose.yaml
+      services: [nav]
+    limits:
+      memory: 1G
+    policy:
+      stop_sequence:
+        - action: sigterm
+          timeout: 5s
+"#;
+        let config = KrillConfig::parse(yaml, Some(Path::new("/robot"))).unwrap();
+        match &config.services["stack"].execute {
+            ExecuteConfig::Compose { file, engine, .. } => {
+                assert_eq!(file, Path::new("/robot/compose.yaml"));
+                assert_eq!(*engine, crate::ContainerEngine::Docker);
+            }
+            _ => panic!("Expected Compose variant"),
+        }
+
+        let mut paths: Vec<String> = config.issues().iter().map(|i| i.path.join(".")).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "services.nav.scheduler",
+                "services.stack.limits",
+                "services.stack.policy.stop_sequence",
+            ]
+        );
+
+        let yaml = yaml.replace(
+            "services: [nav]",
+            "services: [nav]\n      exit_code_from: map",
+        );
+        let err = KrillConfig::parse(&yaml, None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("exit_code_from 'map' is not in services"));
     }
 }
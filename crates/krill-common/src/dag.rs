@@ -167,7 +167,55 @@ impl DependencyGraph {
         Ok(order)
     }
 
-    /// Get services that should be stopped when a service fails (cascade failure)
+    /// Shutdown order grouped into levels: a service lands one level after
+    /// its latest-stopping dependent, so the services of a level can be
+    /// stopped in parallel. Levels are sorted by name.
+    pub fn shutdown_levels(&self) -> Result<Vec<Vec<String>>, DagError> {
+        let mut level: HashMap<String, usize> = HashMap::new();
+        let mut levels: Vec<Vec<String>> = Vec::new();
+
+        for service in self.shutdown_order()? {
+            let index = self
+                .edges
+                .get(&service)
+                .into_iter()
+                .flatten()
+                .filter_map(|dependent| level.get(dependent))
+                .map(|l| l + 1)
+                .max()
+                .unwrap_or(0);
+
+            if levels.len() <= index {
+                levels.resize_with(index + 1, Vec::new);
+            }
+            levels[index].push(service.clone());
+            level.insert(service, index);
+        }
+
+        for services in &mut levels {
+            services.sort_unstable();
+        }
+        Ok(levels)
+    }
+
+    /// Services this service depends on, in declaration order
+    pub fn dependencies(&self, service: &str) -> &[Dependency] {
+        self.reverse_edges.get(service).map_or(&[], Vec::as_slice)
+    }
+
+    /// Services that directly depend on this service, sorted by name
+    pub fn dependents(&self, service: &str) -> Vec<&str> {
+        let mut dependents: Vec<&str> = self
+            .edges
+            .get(service)
+            .map(|set| set.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        dependents.sort_unstable();
+        dependents
+    }
+
+    /// Get services that should be stopped when a service fails (cascade failure).
+    /// Fallbacks waiting for the failure, and their dependents, are spared.
     pub fn cascade_failure(&self, failed_service: &str) -> HashSet<String> {
         let mut to_stop = HashSet::new();
         let mut queue = VecDeque::new();
@@ -177,6 +225,9 @@ impl DependencyGraph {
         while let Some(service) = queue.pop_front() {
             if let Some(dependents) = self.edges.get(&service) {
                 for dependent in dependents {
+                    if self.waits_for_failure(dependent, &service) {
+                        continue;
+                    }
                     if !to_stop.contains(dependent) {
                         to_stop.insert(dependent.clone());
                         queue.push_back(dependent.clone());
@@ -188,7 +239,40 @@ impl DependencyGraph {
         to_stop
     }
 
-    /// Check if dependencies are satisfied for a service
+    /// Whether `dependent` has a `failed` dependency on `service`
+    fn waits_for_failure(&self, dependent: &str, service: &str) -> bool {
+        self.dependencies(dependent).iter().any(|dep| {
+            dep.service_name() == service && dep.condition() == DependencyCondition::Failed
+        })
+    }
+
+    /// Services that only start once a dependency failed: those with a
+    /// `failed` dependency and everything depending on them
+    pub fn standby_services(&self) -> HashSet<String> {
+        let mut standby = HashSet::new();
+        let mut queue: VecDeque<String> = self
+            .reverse_edges
+            .iter()
+            .filter(|(_, deps)| {
+                deps.iter()
+                    .any(|dep| dep.condition() == DependencyCondition::Failed)
+            })
+            .map(|(service, _)| service.clone())
+            .collect();
+
+        while let Some(service) = queue.pop_front() {
+            if standby.insert(service.clone()) {
+                if let Some(dependents) = self.edges.get(&service) {
+                    queue.extend(dependents.iter().cloned());
+                }
+            }
+        }
+        standby
+    }
+
+    /// Check if dependencies are satisfied for a service. A `failed`
+    /// dependency is met by any failed status, whether or not it is going
+    /// to be restarted.
     pub fn dependencies_satisfied<F>(&self, service: &str, get_status: F) -> bool
     where
         F: Fn(&str) -> ServiceStatus,
@@ -205,6 +289,7 @@ impl DependencyGraph {
                             ServiceStatus::Running
                                 | ServiceStatus::Healthy
                                 | ServiceStatus::Degraded
+                                | ServiceStatus::Completed
                         ) {
                             return false;
                         }
@@ -214,6 +299,16 @@ impl DependencyGraph {
                             return false;
                         }
                     }
+                    DependencyCondition::Completed => {
+                        if status != ServiceStatus::Completed {
+                            return false;
+                        }
+                    }
+                    DependencyCondition::Failed => {
+                        if status != ServiceStatus::Failed {
+                            return false;
+                        }
+                    }
                 }
             }
         }
@@ -272,6 +367,29 @@ mod tests {
         assert_eq!(order, vec!["c", "b", "a"]);
     }
 
+    #[test]
+    fn test_shutdown_levels() {
+        let mut services: HashMap<String, Vec<Dependency>> = HashMap::new();
+        services.insert("driver".to_string(), vec![]);
+        services.insert("camera".to_string(), vec![simple_dep("driver")]);
+        services.insert("lidar".to_string(), vec![simple_dep("driver")]);
+        services.insert(
+            "planner".to_string(),
+            vec![simple_dep("camera"), simple_dep("driver")],
+        );
+        services.insert("logger".to_string(), vec![]);
+
+        let graph = DependencyGraph::new(&services).unwrap();
+        assert_eq!(
+            graph.shutdown_levels().unwrap(),
+            vec![
+                vec!["lidar", "logger", "planner"],
+                vec!["camera"],
+                vec!["driver"],
+            ]
+        );
+    }
+
     #[test]
     fn test_cascade_failure() {
         let mut services: HashMap<String, Vec<Dependency>> = HashMap::new();
@@ -318,6 +436,34 @@ mod tests {
         assert!(!not_satisfied);
     }
 
+    #[test]
+    fn test_fallback_services() {
+        let failed_dep = |name: &str| Dependency::WithCondition {
+            service: name.to_string(),
+            condition: DependencyCondition::Failed,
+        };
+        let mut services: HashMap<String, Vec<Dependency>> = HashMap::new();
+        services.insert("gps-rtk".to_string(), vec![]);
+        services.insert("localizer".to_string(), vec![simple_dep("gps-rtk")]);
+        services.insert("gps-basic".to_string(), vec![failed_dep("gps-rtk")]);
+        services.insert("odometry".to_string(), vec![simple_dep("gps-basic")]);
+
+        let graph = DependencyGraph::new(&services).unwrap();
+
+        let standby = graph.standby_services();
+        assert_eq!(
+            standby,
+            HashSet::from(["gps-basic".to_string(), "odometry".to_string()])
+        );
+
+        // The failure stops what needs the primary, not its fallback
+        let affected = graph.cascade_failure("gps-rtk");
+        assert_eq!(affected, HashSet::from(["localizer".to_string()]));
+
+        assert!(graph.dependencies_satisfied("gps-basic", |_| ServiceStatus::Failed));
+        assert!(!graph.dependencies_satisfied("gps-basic", |_| ServiceStatus::Healthy));
+    }
+
     #[test]
     fn test_unknown_service_dependency() {
         let mut services: HashMap<String, Vec<Dependency>> = HashMap::new();
@@ -326,4 +472,18 @@ mod tests {
         let result = DependencyGraph::new(&services);
         assert!(matches!(result, Err(DagError::UnknownService(_))));
     }
+
+    #[test]
+    fn test_dependencies_and_dependents() {
+        let mut services: HashMap<String, Vec<Dependency>> = HashMap::new();
+        services.insert("a".to_string(), vec![]);
+        services.insert("c".to_string(), vec![healthy_dep("a")]);
+        services.insert("b".to_string(), vec![simple_dep("a")]);
+
+        let graph = DependencyGraph::new(&services).unwrap();
+
+        assert_eq!(graph.dependents("a"), vec!["b", "c"]);
+        assert!(graph.dependencies("a").is_empty());
+        assert_eq!(graph.dependencies("c"), &[healthy_dep("a")]);
+    }
 }
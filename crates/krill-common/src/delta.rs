@@ -0,0 +1,162 @@
+// Snapshot Deltas - Changed fields between two snapshots, for `snapshot_delta` messages
+
+use crate::ipc::ServiceSnapshot;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Changed fields of a service snapshot by name; `null` resets a field to
+/// its default
+pub type SnapshotFields = Map<String, Value>;
+
+/// Changes turning `old` into `new`: the changed fields of each changed
+/// service (all fields of an added one), and the removed services
+pub fn diff_snapshots(
+    old: &HashMap<String, ServiceSnapshot>,
+    new: &HashMap<String, ServiceSnapshot>,
+) -> (HashMap<String, SnapshotFields>, Vec<String>) {
+    let mut changed = HashMap::new();
+    for (name, snapshot) in new {
+        let new_fields = to_fields(snapshot);
+        let Some(old_fields) = old.get(name).map(to_fields) else {
+            changed.insert(name.clone(), new_fields);
+            continue;
+        };
+
+        let mut fields: SnapshotFields = new_fields
+            .iter()
+            .filter(|(key, value)| old_fields.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        for key in old_fields.keys() {
+            if !new_fields.contains_key(key) {
+                fields.insert(key.clone(), Value::Null);
+            }
+        }
+        if !fields.is_empty() {
+            changed.insert(name.clone(), fields);
+        }
+    }
+
+    let mut removed: Vec<String> = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .cloned()
+        .collect();
+    removed.sort();
+    (changed, removed)
+}
+
+/// Apply the changes of a `snapshot_delta` message to `services`
+pub fn apply_snapshot_delta(
+    services: &mut HashMap<String, ServiceSnapshot>,
+    changed: &HashMap<String, SnapshotFields>,
+    removed: &[String],
+) -> Result<(), serde_json::Error> {
+    for name in removed {
+        services.remove(name);
+    }
+    for (name, fields) in changed {
+        let mut merged = services.get(name).map(to_fields).unwrap_or_default();
+        for (key, value) in fields {
+            if value.is_null() {
+                merged.remove(key);
+            } else {
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+        let snapshot = serde_json::from_value(Value::Object(merged))?;
+        services.insert(name.clone(), snapshot);
+    }
+    Ok(())
+}
+
+fn to_fields(snapshot: &ServiceSnapshot) -> SnapshotFields {
+    match serde_json::to_value(snapshot) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::{ServiceConfigSummary, ServiceStatus};
+    use crate::RestartPolicy;
+    use std::time::Duration;
+
+    fn snapshot(status: ServiceStatus, pid: Option<u32>) -> ServiceSnapshot {
+        ServiceSnapshot {
+            status,
+            pid,
+            uid: "abc1234".to_string(),
+            uptime: pid.map(|_| Duration::from_secs(5)),
+            restart_count: 0,
+            last_error: None,
+            namespace: "robot".to_string(),
+            config: ServiceConfigSummary {
+                executor: "shell".to_string(),
+                restart_policy: RestartPolicy::Never,
+                ..Default::default()
+            },
+            protected: false,
+            legacy_config: Default::default(),
+            labels: Default::default(),
+            safety_stopped: false,
+            restarts_paused: false,
+            metrics: None,
+            heartbeat_metadata: HashMap::new(),
+            heartbeat_age: None,
+            missed_heartbeats: 0,
+            total_restarts: 0,
+            last_exit_code: None,
+            state_history: vec![],
+            state_age: None,
+            availability: Default::default(),
+            maintenance: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_diff_holds_only_changed_fields() {
+        let old = HashMap::from([
+            (
+                "lidar".to_string(),
+                snapshot(ServiceStatus::Running, Some(10)),
+            ),
+            (
+                "camera".to_string(),
+                snapshot(ServiceStatus::Healthy, Some(11)),
+            ),
+            (
+                "planner".to_string(),
+                snapshot(ServiceStatus::Healthy, Some(12)),
+            ),
+        ]);
+        let mut new = old.clone();
+        new.insert(
+            "lidar".to_string(),
+            snapshot(ServiceStatus::Healthy, Some(10)),
+        );
+        new.insert("camera".to_string(), snapshot(ServiceStatus::Failed, None));
+        new.get_mut("camera").unwrap().last_error = Some("exit 1".to_string());
+        new.remove("planner");
+        new.insert("arm".to_string(), snapshot(ServiceStatus::Starting, None));
+
+        let (changed, removed) = diff_snapshots(&old, &new);
+        assert_eq!(removed, vec!["planner"]);
+        assert_eq!(
+            changed["lidar"],
+            serde_json::from_str::<SnapshotFields>(r#"{"status":"healthy"}"#).unwrap()
+        );
+        let mut camera: Vec<_> = changed["camera"].keys().cloned().collect();
+        camera.sort();
+        assert_eq!(camera, vec!["last_error", "pid", "status", "uptime"]);
+        assert!(changed["arm"].contains_key("namespace"));
+
+        let mut applied = old.clone();
+        apply_snapshot_delta(&mut applied, &changed, &removed).unwrap();
+        assert_eq!(applied, new);
+
+        assert_eq!(diff_snapshots(&new, &new), (HashMap::new(), vec![]));
+    }
+}
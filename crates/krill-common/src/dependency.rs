@@ -16,6 +16,10 @@ pub enum Dependency {
 pub enum DependencyCondition {
     Started,
     Healthy,
+    /// The dependency is a oneshot service that exited successfully
+    Completed,
+    /// The dependency failed and will not be restarted, for fallbacks
+    Failed,
 }
 
 impl Dependency {
@@ -71,6 +75,14 @@ impl<'de> Deserialize<'de> for Dependency {
                         service: service.to_string(),
                         condition: DependencyCondition::Healthy,
                     }),
+                    [service, "completed"] => Ok(Dependency::WithCondition {
+                        service: service.to_string(),
+                        condition: DependencyCondition::Completed,
+                    }),
+                    [service, "failed"] => Ok(Dependency::WithCondition {
+                        service: service.to_string(),
+                        condition: DependencyCondition::Failed,
+                    }),
                     _ => Err(E::custom(format!(
                         "Invalid dependency format: '{}'. Expected 'service' or 'service condition'",
                         value
@@ -87,9 +99,12 @@ impl<'de> Deserialize<'de> for Dependency {
                     let cond = match condition.as_str() {
                         "started" => DependencyCondition::Started,
                         "healthy" => DependencyCondition::Healthy,
+                        "completed" => DependencyCondition::Completed,
+                        "failed" => DependencyCondition::Failed,
                         _ => {
                             return Err(M::Error::custom(format!(
-                                "Invalid condition '{}'. Expected 'started' or 'healthy'",
+                                "Invalid condition '{}'. Expected 'started', 'healthy', \
+                                 'completed', or 'failed'",
                                 condition
                             )))
                         }
@@ -123,6 +138,8 @@ impl Serialize for Dependency {
                     match condition {
                         DependencyCondition::Started => "started",
                         DependencyCondition::Healthy => "healthy",
+                        DependencyCondition::Completed => "completed",
+                        DependencyCondition::Failed => "failed",
                     }
                 )
             }
@@ -184,6 +201,29 @@ mod tests {
         assert_eq!(dep.condition(), DependencyCondition::Started);
     }
 
+    #[test]
+    fn test_deserialize_completed() {
+        let dep: Dependency = serde_yaml::from_str(r#"map-loader: completed"#).unwrap();
+        assert_eq!(dep.service_name(), "map-loader");
+        assert_eq!(dep.condition(), DependencyCondition::Completed);
+
+        let dep: Dependency = serde_yaml::from_str(r#""calibrate completed""#).unwrap();
+        assert_eq!(dep.condition(), DependencyCondition::Completed);
+    }
+
+    #[test]
+    fn test_deserialize_failed() {
+        let dep: Dependency = serde_yaml::from_str(r#"gps-rtk: failed"#).unwrap();
+        assert_eq!(dep.condition(), DependencyCondition::Failed);
+
+        let dep: Dependency = serde_yaml::from_str(r#""gps-rtk failed""#).unwrap();
+        assert_eq!(dep.service_name(), "gps-rtk");
+        assert_eq!(
+            serde_yaml::to_string(&dep).unwrap().trim(),
+            "gps-rtk failed"
+        );
+    }
+
     #[test]
     fn test_serialize_simple() {
         let dep = Dependency::Simple("lidar".to_string());
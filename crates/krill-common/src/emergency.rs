@@ -0,0 +1,106 @@
+// Emergency Actions - What the daemon does when an emergency stop is triggered
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Default time an emergency `command` may run before it is killed
+pub const DEFAULT_EMERGENCY_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One step of the workspace's `on_emergency:` list, run in order when a
+/// critical service fails or an emergency stop is requested
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum EmergencyAction {
+    /// Stop every service and latch it until its safety stop is cleared
+    StopServices,
+    /// Run a shell command, e.g. a script engaging the brakes
+    Command {
+        command: String,
+        #[serde(
+            default = "default_command_timeout",
+            with = "humantime_serde",
+            skip_serializing_if = "is_default_timeout"
+        )]
+        timeout: Duration,
+    },
+    /// Reboot the host
+    Reboot,
+    /// Power off the host
+    Poweroff,
+}
+
+impl EmergencyAction {
+    /// Command line carrying out a host action (`reboot` or `poweroff`)
+    pub fn host_command(&self) -> Option<Vec<String>> {
+        let argv: &[&str] = match self {
+            EmergencyAction::Reboot if cfg!(windows) => &["shutdown", "/r", "/t", "0"],
+            EmergencyAction::Reboot => &["systemctl", "reboot"],
+            EmergencyAction::Poweroff if cfg!(windows) => &["shutdown", "/s", "/t", "0"],
+            EmergencyAction::Poweroff => &["systemctl", "poweroff"],
+            _ => return None,
+        };
+        Some(argv.iter().map(|arg| arg.to_string()).collect())
+    }
+}
+
+/// Actions used without an `on_emergency:` list: stop all services
+pub fn default_on_emergency() -> Vec<EmergencyAction> {
+    vec![EmergencyAction::StopServices]
+}
+
+fn default_command_timeout() -> Duration {
+    DEFAULT_EMERGENCY_COMMAND_TIMEOUT
+}
+
+fn is_default_timeout(timeout: &Duration) -> bool {
+    *timeout == DEFAULT_EMERGENCY_COMMAND_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_actions() {
+        let yaml = r#"
+- action: command
+  command: /opt/robot/engage_brakes.sh
+  timeout: 2s
+- action: stop-services
+- action: command
+  command: notify-ops
+- action: poweroff
+"#;
+        let actions: Vec<EmergencyAction> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                EmergencyAction::Command {
+                    command: "/opt/robot/engage_brakes.sh".to_string(),
+                    timeout: Duration::from_secs(2),
+                },
+                EmergencyAction::StopServices,
+                EmergencyAction::Command {
+                    command: "notify-ops".to_string(),
+                    timeout: DEFAULT_EMERGENCY_COMMAND_TIMEOUT,
+                },
+                EmergencyAction::Poweroff,
+            ]
+        );
+
+        assert!(serde_yaml::from_str::<EmergencyAction>("action: explode\n").is_err());
+        assert!(serde_yaml::from_str::<EmergencyAction>("action: command\n").is_err());
+    }
+
+    #[test]
+    fn test_host_commands() {
+        assert_eq!(EmergencyAction::StopServices.host_command(), None);
+        assert!(EmergencyAction::Reboot.host_command().is_some());
+        if cfg!(unix) {
+            assert_eq!(
+                EmergencyAction::Poweroff.host_command().unwrap(),
+                vec!["systemctl", "poweroff"]
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Environment files - Parse `.env` files referenced by `env_file`
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EnvFileError {
+    #[error("Failed to read env file {0}: {1}")]
+    Read(PathBuf, String),
+
+    #[error("{path}:{line}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+/// Read and parse a `.env` file
+pub fn load_env_file(path: &Path) -> Result<HashMap<String, String>, EnvFileError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| EnvFileError::Read(path.to_path_buf(), e.to_string()))?;
+
+    parse_env(&content).map_err(|(line, message)| EnvFileError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    })
+}
+
+/// Parse `KEY=value` lines. Supports `#` comments, an optional `export`
+/// prefix, and single- or double-quoted values (the latter with `\n`, `\t`,
+/// `\"` and `\\` escapes). Errors carry the 1-based line number.
+pub fn parse_env(content: &str) -> Result<HashMap<String, String>, (usize, String)> {
+    let mut vars = HashMap::new();
+
+    for (index, raw) in content.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| (line_no, format!("expected KEY=value, got '{}'", line)))?;
+
+        let key = key.trim();
+        if !is_valid_key(key) {
+            return Err((line_no, format!("invalid variable name '{}'", key)));
+        }
+
+        let value = parse_value(value.trim()).map_err(|message| (line_no, message))?;
+        vars.insert(key.to_string(), value);
+    }
+
+    Ok(vars)
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "unterminated single quote".to_string())?;
+        return Ok(rest[..end].to_string());
+    }
+
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(result),
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some(other) => result.push(other),
+                    None => break,
+                },
+                c => result.push(c),
+            }
+        }
+        return Err("unterminated double quote".to_string());
+    }
+
+    // Unquoted: a ` #` starts a trailing comment
+    let value = match value.find(" #") {
+        Some(pos) => &value[..pos],
+        None => value,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env() {
+        let content = r#"
+# Robot settings
+ROS_DOMAIN_ID=42
+export RMW_IMPLEMENTATION=rmw_cyclonedds_cpp
+ROBOT_NAME = "krill 01"  # quoted
+GREETING="line1\nline2"
+RAW='no $expansion\n'
+EMPTY=
+TRAILING=value # comment
+"#;
+
+        let vars = parse_env(content).unwrap();
+        assert_eq!(vars["ROS_DOMAIN_ID"], "42");
+        assert_eq!(vars["RMW_IMPLEMENTATION"], "rmw_cyclonedds_cpp");
+        assert_eq!(vars["ROBOT_NAME"], "krill 01");
+        assert_eq!(vars["GREETING"], "line1\nline2");
+        assert_eq!(vars["RAW"], r"no $expansion\n");
+        assert_eq!(vars["EMPTY"], "");
+        assert_eq!(vars["TRAILING"], "value");
+    }
+
+    #[test]
+    fn test_parse_env_errors() {
+        assert_eq!(parse_env("A=1\nnot a pair").unwrap_err().0, 2);
+        assert_eq!(parse_env("1BAD=x").unwrap_err().0, 1);
+        assert!(parse_env("A=\"open").is_err());
+    }
+
+    #[test]
+    fn test_load_missing_env_file() {
+        let err = load_env_file(Path::new("/nonexistent/krill.env")).unwrap_err();
+        assert!(matches!(err, EnvFileError::Read(..)));
+    }
+}
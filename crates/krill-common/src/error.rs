@@ -0,0 +1,152 @@
+// Errors - Failures the daemon reports to clients, with stable error codes
+
+use crate::ipc::{ProtocolVersion, ServerMessage};
+use thiserror::Error;
+
+// Codes sent in `ServerMessage::Error`, modelled on HTTP status codes
+/// The request is malformed or misses a target
+pub const ERROR_INVALID_REQUEST: i32 = 400;
+/// The operating system refused the operation
+pub const ERROR_PERMISSION_DENIED: i32 = 403;
+/// The named service or group does not exist
+pub const ERROR_NOT_FOUND: i32 = 404;
+/// The service is in a state that does not allow the operation
+pub const ERROR_CONFLICT: i32 = 409;
+/// The recipe failed to load or validate
+pub const ERROR_INVALID_CONFIG: i32 = 422;
+/// The service is held by an emergency stop
+pub const ERROR_SAFETY_STOPPED: i32 = 423;
+/// The service is protected and the command was not confirmed with `force`
+pub const ERROR_CONFIRMATION_REQUIRED: i32 = 428;
+/// The client's protocol is rejected
+pub const ERROR_INCOMPATIBLE_PROTOCOL: i32 = 426;
+/// The client read messages too slowly and is disconnected
+pub const ERROR_SLOW_CLIENT: i32 = 429;
+/// An unexpected failure inside the daemon
+pub const ERROR_INTERNAL: i32 = 500;
+/// The daemon was started without the requested feature
+pub const ERROR_UNSUPPORTED: i32 = 501;
+/// A service process could not be spawned
+pub const ERROR_SPAWN_FAILED: i32 = 502;
+/// The daemon is shutting down or being upgraded
+pub const ERROR_SHUTTING_DOWN: i32 = 503;
+/// The daemon did not finish the request in time
+pub const ERROR_TIMEOUT: i32 = 504;
+
+/// A failure reported to clients; `code()` lets them tell failures apart
+/// without parsing the message
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum KrillError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Service '{0}' not found")]
+    ServiceNotFound(String),
+
+    #[error("Group '{0}' not found")]
+    GroupNotFound(String),
+
+    #[error("Service '{service}' {reason}")]
+    InvalidState { service: String, reason: String },
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Service '{0}' is held by an emergency stop; clear it first")]
+    SafetyStopped(String),
+
+    #[error("Service '{0}' is protected; the command must be confirmed")]
+    Protected(String),
+
+    #[error("Incompatible protocol version {client} (daemon speaks {server})")]
+    IncompatibleProtocol {
+        client: ProtocolVersion,
+        server: ProtocolVersion,
+    },
+
+    #[error("Client fell too far behind on messages and is disconnected")]
+    SlowClient,
+
+    #[error("Failed to spawn process: {0}")]
+    SpawnFailed(String),
+
+    #[error("{0} not supported by this daemon")]
+    Unsupported(String),
+
+    #[error("Daemon is shutting down")]
+    ShuttingDown,
+
+    #[error("Daemon is being upgraded; try again once the new daemon took over")]
+    Upgrading,
+
+    #[error("Timeout waiting for {0}")]
+    Timeout(String),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl KrillError {
+    /// Error code sent with the error
+    pub fn code(&self) -> i32 {
+        match self {
+            KrillError::InvalidRequest(_) => ERROR_INVALID_REQUEST,
+            KrillError::PermissionDenied(_) => ERROR_PERMISSION_DENIED,
+            KrillError::ServiceNotFound(_) | KrillError::GroupNotFound(_) => ERROR_NOT_FOUND,
+            KrillError::InvalidState { .. } => ERROR_CONFLICT,
+            KrillError::InvalidConfig(_) => ERROR_INVALID_CONFIG,
+            KrillError::SafetyStopped(_) => ERROR_SAFETY_STOPPED,
+            KrillError::Protected(_) => ERROR_CONFIRMATION_REQUIRED,
+            KrillError::IncompatibleProtocol { .. } => ERROR_INCOMPATIBLE_PROTOCOL,
+            KrillError::SlowClient => ERROR_SLOW_CLIENT,
+            KrillError::SpawnFailed(_) => ERROR_SPAWN_FAILED,
+            KrillError::Unsupported(_) => ERROR_UNSUPPORTED,
+            KrillError::ShuttingDown | KrillError::Upgrading => ERROR_SHUTTING_DOWN,
+            KrillError::Timeout(_) => ERROR_TIMEOUT,
+            KrillError::Internal(_) => ERROR_INTERNAL,
+        }
+    }
+}
+
+impl From<KrillError> for ServerMessage {
+    fn from(err: KrillError) -> Self {
+        ServerMessage::Error {
+            message: err.to_string(),
+            code: Some(err.code()),
+        }
+    }
+}
+
+impl From<crate::ConfigError> for KrillError {
+    fn from(err: crate::ConfigError) -> Self {
+        KrillError::InvalidConfig(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_message_carries_code() {
+        let message = ServerMessage::from(KrillError::ServiceNotFound("lidar".to_string()));
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"type":"error","message":"Service 'lidar' not found","code":404}"#
+        );
+
+        let message = ServerMessage::from(KrillError::SpawnFailed(
+            "No such file or directory".to_string(),
+        ));
+        assert!(matches!(
+            message,
+            ServerMessage::Error {
+                code: Some(ERROR_SPAWN_FAILED),
+                ..
+            }
+        ));
+    }
+}
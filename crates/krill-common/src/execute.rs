@@ -1,8 +1,13 @@
-use serde::{Deserialize, Serialize};
+use crate::executor::{self, ExecuteOptions, BUILTIN_EXECUTORS};
+use crate::paths::PathVars;
+use crate::ConfigError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 #[serde(tag = "type", rename_all = "lowercase")]
 #[serde(deny_unknown_fields)]
 pub enum ExecuteConfig {
@@ -43,6 +48,134 @@ pub enum ExecuteConfig {
         #[serde(default)]
         network: Option<String>,
     },
+    Podman {
+        image: String,
+        #[serde(default)]
+        volumes: Vec<VolumeMount>,
+        #[serde(default)]
+        ports: Vec<PortMapping>,
+        #[serde(default)]
+        privileged: bool,
+        #[serde(default)]
+        network: Option<String>,
+        /// User namespace mode, e.g. `keep-id` for rootless bind mounts
+        #[serde(default)]
+        userns: Option<String>,
+    },
+    Compose {
+        file: PathBuf,
+        /// Compose services to start; all of the file's if empty
+        #[serde(default)]
+        services: Vec<String>,
+        /// Compose service whose exit code becomes the service's exit code
+        #[serde(default)]
+        exit_code_from: Option<String>,
+        #[serde(default)]
+        engine: ContainerEngine,
+        #[serde(default)]
+        working_dir: Option<PathBuf>,
+    },
+    /// Any other `type`, handled by an executor from the registry
+    #[serde(skip)]
+    Custom(CustomExecute),
+}
+
+/// CLI running the containers of a container service
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerEngine {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl ContainerEngine {
+    pub fn program(self) -> &'static str {
+        match self {
+            ContainerEngine::Docker => "docker",
+            ContainerEngine::Podman => "podman",
+        }
+    }
+}
+
+/// `execute` block of a type registered with `register_executor`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomExecute {
+    pub kind: String,
+    pub options: ExecuteOptions,
+}
+
+impl<'de> Deserialize<'de> for ExecuteConfig {
+    /// Errors in the block, including unknown types and options a
+    /// registered executor rejects, are parse errors so they keep their
+    /// position in the recipe
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let value = Value::deserialize(deserializer)?;
+        match value.get("type").and_then(Value::as_str) {
+            Some(kind) if !BUILTIN_EXECUTORS.contains(&kind) => {
+                let Some(executor) = executor::executor(kind) else {
+                    let expected: Vec<String> = BUILTIN_EXECUTORS
+                        .iter()
+                        .map(|name| name.to_string())
+                        .chain(executor::registered_executors())
+                        .map(|name| format!("`{}`", name))
+                        .collect();
+                    return Err(Error::custom(format!(
+                        "unknown variant `{}`, expected one of {}",
+                        kind,
+                        expected.join(", ")
+                    )));
+                };
+                let kind = kind.to_string();
+                let Value::Object(mut options) = value else {
+                    unreachable!("only objects have a type")
+                };
+                options.remove("type");
+                executor.validate(&options).map_err(|reason| {
+                    Error::custom(format!("invalid {} execute: {}", kind, reason))
+                })?;
+                Ok(ExecuteConfig::Custom(CustomExecute { kind, options }))
+            }
+            _ => {
+                let config = ExecuteConfig::deserialize(value).map_err(Error::custom)?;
+                if let ExecuteConfig::Compose {
+                    services,
+                    exit_code_from: Some(service),
+                    ..
+                } = &config
+                {
+                    if !services.is_empty() && !services.contains(service) {
+                        return Err(Error::custom(format!(
+                            "exit_code_from '{}' is not in services",
+                            service
+                        )));
+                    }
+                }
+                Ok(config)
+            }
+        }
+    }
+}
+
+impl Serialize for ExecuteConfig {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ExecuteConfig::Custom(custom) => {
+                let mut map = custom.options.clone();
+                map.insert("type".to_string(), Value::String(custom.kind.clone()));
+                map.serialize(serializer)
+            }
+            builtin => ExecuteConfig::serialize(builtin, serializer),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -146,13 +279,79 @@ impl<'de> Deserialize<'de> for PortMapping {
 }
 
 impl ExecuteConfig {
-    pub fn executor_type(&self) -> &'static str {
+    pub fn executor_type(&self) -> &str {
         match self {
             ExecuteConfig::Pixi { .. } => "pixi",
             ExecuteConfig::Ros2 { .. } => "ros2",
             ExecuteConfig::Shell { .. } => "shell",
             ExecuteConfig::Docker { .. } => "docker",
+            ExecuteConfig::Podman { .. } => "podman",
+            ExecuteConfig::Compose { .. } => "compose",
+            ExecuteConfig::Custom(custom) => &custom.kind,
+        }
+    }
+
+    /// Engine running the service's containers, for container services
+    pub fn container_engine(&self) -> Option<ContainerEngine> {
+        match self {
+            ExecuteConfig::Docker { .. } => Some(ContainerEngine::Docker),
+            ExecuteConfig::Podman { .. } => Some(ContainerEngine::Podman),
+            ExecuteConfig::Compose { engine, .. } => Some(*engine),
+            _ => None,
+        }
+    }
+
+    /// Expand variables in working_dir, a compose file and volume host paths
+    /// of `service`, then resolve relative working_dir and compose paths
+    /// against `base_dir` if given
+    pub fn resolve_paths(
+        &mut self,
+        service: &str,
+        vars: &PathVars,
+        base_dir: Option<&Path>,
+    ) -> Result<(), ConfigError> {
+        let invalid = |field: &str, error: ConfigError| ConfigError::InvalidServicePath {
+            service: service.to_string(),
+            field: field.to_string(),
+            error: Box::new(error),
+        };
+        let expand = |path: &mut PathBuf| {
+            vars.expand(path)
+                .map(|expanded| *path = expanded)
+                .map_err(|e| invalid("working_dir", e))
+        };
+
+        match self {
+            ExecuteConfig::Pixi { working_dir, .. }
+            | ExecuteConfig::Ros2 { working_dir, .. }
+            | ExecuteConfig::Shell { working_dir, .. } => {
+                working_dir.as_mut().map(expand).transpose()?;
+            }
+            ExecuteConfig::Compose {
+                file, working_dir, ..
+            } => {
+                *file = vars.expand(file).map_err(|e| invalid("file", e))?;
+                working_dir.as_mut().map(expand).transpose()?;
+            }
+            // Containers don't have a working_dir, but bind-mount host paths
+            ExecuteConfig::Docker { volumes, .. } | ExecuteConfig::Podman { volumes, .. } => {
+                for VolumeMount::Detailed { host, .. } in volumes {
+                    *host = vars.expand(host).map_err(|e| invalid("volumes", e))?;
+                }
+            }
+            ExecuteConfig::Custom(custom) => {
+                if let Some(Value::String(dir)) = custom.options.get_mut("working_dir") {
+                    let mut path = PathBuf::from(&*dir);
+                    expand(&mut path)?;
+                    *dir = path.to_string_lossy().into_owned();
+                }
+            }
+        }
+
+        if let Some(base_dir) = base_dir {
+            self.resolve_working_dir(base_dir);
         }
+        Ok(())
     }
 
     /// Resolve relative working_dir paths against a base directory
@@ -169,7 +368,21 @@ impl ExecuteConfig {
             ExecuteConfig::Pixi { working_dir, .. } => resolve(working_dir),
             ExecuteConfig::Ros2 { working_dir, .. } => resolve(working_dir),
             ExecuteConfig::Shell { working_dir, .. } => resolve(working_dir),
-            ExecuteConfig::Docker { .. } => {} // Docker doesn't have working_dir
+            ExecuteConfig::Compose {
+                file, working_dir, ..
+            } => {
+                if file.is_relative() {
+                    *file = base_dir.join(&file);
+                }
+                resolve(working_dir);
+            }
+            // Containers don't have a working_dir
+            ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. } => {}
+            ExecuteConfig::Custom(custom) => {
+                if let Some(executor) = executor::executor(&custom.kind) {
+                    executor.resolve(&mut custom.options, base_dir);
+                }
+            }
         }
     }
 }
@@ -285,4 +498,51 @@ ports:
             _ => panic!("Expected Docker variant"),
         }
     }
+
+    #[test]
+    fn test_custom_type_round_trip() {
+        struct Nerdctl;
+        impl crate::Executor for Nerdctl {
+            fn build_command(
+                &self,
+                _options: &ExecuteOptions,
+                _env_vars: &HashMap<String, String>,
+            ) -> Result<Vec<String>, crate::ProcessError> {
+                Ok(vec!["nerdctl".to_string()])
+            }
+        }
+        crate::register_executor("nerdctl", std::sync::Arc::new(Nerdctl)).unwrap();
+
+        let yaml = r#"
+type: nerdctl
+image: ros:humble
+args: ["--rm"]
+"#;
+        let config: ExecuteConfig = serde_yaml::from_str(yaml).unwrap();
+        match &config {
+            ExecuteConfig::Custom(custom) => {
+                assert_eq!(custom.kind, "nerdctl");
+                assert_eq!(custom.options["image"], "ros:humble");
+                assert!(!custom.options.contains_key("type"));
+            }
+            _ => panic!("Expected Custom variant"),
+        }
+        assert_eq!(config.executor_type(), "nerdctl");
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("type: nerdctl"));
+        assert_eq!(
+            serde_yaml::from_str::<ExecuteConfig>(&yaml).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_builtin_type_errors_are_kept() {
+        let err = serde_yaml::from_str::<ExecuteConfig>("type: shell\ncommand: hi\nbogus: 1\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown field `bogus`"));
+        let err = serde_yaml::from_str::<ExecuteConfig>("type: shell\n").unwrap_err();
+        assert!(err.to_string().contains("missing field `command`"));
+    }
 }
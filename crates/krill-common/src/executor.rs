@@ -0,0 +1,212 @@
+// Executor registry - execute types beyond the built-in ones
+
+use crate::process::ProcessError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use thiserror::Error;
+
+/// Execute types handled by `ExecuteConfig` itself; they cannot be registered
+pub const BUILTIN_EXECUTORS: &[&str] = &["pixi", "ros2", "shell", "docker", "podman", "compose"];
+
+/// Fields of an `execute` block of a registered type, without `type`
+pub type ExecuteOptions = serde_json::Map<String, Value>;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExecutorError {
+    #[error("Executor name '{0}' is reserved for a built-in execute type")]
+    Reserved(String),
+
+    #[error("Executor '{0}' is already registered")]
+    AlreadyRegistered(String),
+}
+
+/// Turns the options of an `execute` block into the commands krill runs.
+///
+/// Register an implementation with [`register_executor`] before the recipe is
+/// loaded; services then select it with `execute.type: <name>`.
+pub trait Executor: Send + Sync {
+    /// Check the options when the recipe is validated
+    fn validate(&self, _options: &ExecuteOptions) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Command that starts the service
+    fn build_command(
+        &self,
+        options: &ExecuteOptions,
+        env_vars: &HashMap<String, String>,
+    ) -> Result<Vec<String>, ProcessError>;
+
+    /// Command that stops the service gracefully, if there is one
+    fn stop_command(&self, _options: &ExecuteOptions) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Resolve paths relative to the recipe directory. By default a relative
+    /// `working_dir` option is joined onto `base_dir`.
+    fn resolve(&self, options: &mut ExecuteOptions, base_dir: &Path) {
+        if let Some(Value::String(dir)) = options.get_mut("working_dir") {
+            if Path::new(dir.as_str()).is_relative() {
+                *dir = base_dir.join(&*dir).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    /// Directory the service runs in. By default the `working_dir` option.
+    fn working_dir(&self, options: &ExecuteOptions) -> Option<PathBuf> {
+        options
+            .get("working_dir")
+            .and_then(Value::as_str)
+            .map(PathBuf::from)
+    }
+}
+
+type Registry = RwLock<HashMap<String, Arc<dyn Executor>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        #[allow(unused_mut)]
+        let mut executors: HashMap<String, Arc<dyn Executor>> = HashMap::new();
+        #[cfg(feature = "nix-executor")]
+        executors.insert("nix".to_string(), Arc::new(NixExecutor));
+        RwLock::new(executors)
+    })
+}
+
+/// Make `executor` available as `execute.type: <name>`
+pub fn register_executor(
+    name: impl Into<String>,
+    executor: Arc<dyn Executor>,
+) -> Result<(), ExecutorError> {
+    let name = name.into();
+    if BUILTIN_EXECUTORS.contains(&name.as_str()) {
+        return Err(ExecutorError::Reserved(name));
+    }
+    let mut executors = registry().write().unwrap();
+    if executors.contains_key(&name) {
+        return Err(ExecutorError::AlreadyRegistered(name));
+    }
+    executors.insert(name, executor);
+    Ok(())
+}
+
+/// The executor registered under `name`
+pub fn executor(name: &str) -> Option<Arc<dyn Executor>> {
+    registry().read().unwrap().get(name).cloned()
+}
+
+/// Names of all registered executors, sorted
+pub fn registered_executors() -> Vec<String> {
+    let mut names: Vec<String> = registry().read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Runs a flake app: `nix run <flake> -- <args>`
+#[cfg(feature = "nix-executor")]
+pub struct NixExecutor;
+
+#[cfg(feature = "nix-executor")]
+impl Executor for NixExecutor {
+    fn validate(&self, options: &ExecuteOptions) -> Result<(), String> {
+        match options.get("flake") {
+            Some(Value::String(_)) => {}
+            Some(_) => return Err("'flake' must be a string".to_string()),
+            None => return Err("missing 'flake'".to_string()),
+        }
+        match options.get("args") {
+            None => Ok(()),
+            Some(Value::Array(args)) if args.iter().all(Value::is_string) => Ok(()),
+            Some(_) => Err("'args' must be a list of strings".to_string()),
+        }
+    }
+
+    fn build_command(
+        &self,
+        options: &ExecuteOptions,
+        _env_vars: &HashMap<String, String>,
+    ) -> Result<Vec<String>, ProcessError> {
+        let flake = options
+            .get("flake")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ProcessError::BuildFailed("nix executor needs 'flake'".to_string()))?;
+
+        let mut cmd = vec!["nix".to_string(), "run".to_string(), flake.to_string()];
+        if let Some(Value::Array(args)) = options.get("args") {
+            cmd.push("--".to_string());
+            cmd.extend(args.iter().filter_map(Value::as_str).map(str::to_string));
+        }
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl Executor for Echo {
+        fn build_command(
+            &self,
+            options: &ExecuteOptions,
+            _env_vars: &HashMap<String, String>,
+        ) -> Result<Vec<String>, ProcessError> {
+            let text = options.get("text").and_then(Value::as_str).unwrap_or("");
+            Ok(vec!["echo".to_string(), text.to_string()])
+        }
+    }
+
+    #[test]
+    fn test_register_executor() {
+        register_executor("echo-test", Arc::new(Echo)).unwrap();
+        assert!(registered_executors().contains(&"echo-test".to_string()));
+        assert_eq!(
+            register_executor("echo-test", Arc::new(Echo)),
+            Err(ExecutorError::AlreadyRegistered("echo-test".to_string()))
+        );
+        assert_eq!(
+            register_executor("shell", Arc::new(Echo)),
+            Err(ExecutorError::Reserved("shell".to_string()))
+        );
+
+        let mut options = ExecuteOptions::new();
+        options.insert("text".to_string(), Value::from("hi"));
+        let cmd = executor("echo-test")
+            .unwrap()
+            .build_command(&options, &HashMap::new())
+            .unwrap();
+        assert_eq!(cmd, vec!["echo", "hi"]);
+        assert!(executor("missing").is_none());
+    }
+
+    #[test]
+    fn test_default_resolve_joins_relative_working_dir() {
+        let mut options = ExecuteOptions::new();
+        options.insert("working_dir".to_string(), Value::from("sub"));
+        Echo.resolve(&mut options, Path::new("/base"));
+        assert_eq!(Echo.working_dir(&options), Some(PathBuf::from("/base/sub")));
+
+        options.insert("working_dir".to_string(), Value::from("/abs"));
+        Echo.resolve(&mut options, Path::new("/base"));
+        assert_eq!(Echo.working_dir(&options), Some(PathBuf::from("/abs")));
+    }
+
+    #[cfg(feature = "nix-executor")]
+    #[test]
+    fn test_nix_executor() {
+        let nix = executor("nix").unwrap();
+        let mut options = ExecuteOptions::new();
+        assert!(nix.validate(&options).is_err());
+        options.insert("flake".to_string(), Value::from(".#planner"));
+        options.insert("args".to_string(), serde_json::json!(["--fast"]));
+        assert!(nix.validate(&options).is_ok());
+        assert_eq!(
+            nix.build_command(&options, &HashMap::new()).unwrap(),
+            vec!["nix", "run", ".#planner", "--", "--fast"]
+        );
+    }
+}
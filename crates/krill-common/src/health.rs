@@ -1,4 +1,6 @@
+use crate::ipc::ServiceStatus;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
@@ -10,6 +12,10 @@ pub enum HealthError {
     #[error("Timeout exceeded")]
     Timeout,
 
+    /// A check command ran past its timeout and was killed
+    #[error("Health check '{command}' timed out after {}s and was killed", timeout.as_secs_f64())]
+    CommandTimeout { command: String, timeout: Duration },
+
     #[error("GPU not available: {0}")]
     GpuUnavailable(String),
 }
@@ -22,6 +28,18 @@ pub enum HealthChecker {
         last_seen: Option<SystemTime>,
         #[serde(with = "humantime_serde")]
         timeout: Duration,
+        /// Consecutive missed beats tolerated before the service is unhealthy
+        #[serde(default, skip_serializing_if = "is_zero")]
+        misses_allowed: u32,
+        /// Time after a start during which missed beats are not counted
+        #[serde(
+            default,
+            with = "humantime_serde",
+            skip_serializing_if = "Option::is_none"
+        )]
+        grace_period_on_start: Option<Duration>,
+        #[serde(skip)]
+        started: Option<SystemTime>,
     },
     Tcp {
         port: u16,
@@ -33,9 +51,19 @@ pub enum HealthChecker {
         path: String,
         #[serde(default = "default_http_status")]
         expected_status: u16,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
     },
     Script {
         command: String,
+        /// Time the command may run before it and its children are killed
+        #[serde(with = "humantime_serde", alias = "check_timeout")]
+        timeout: Duration,
+    },
+    /// State of a ROS2 managed (lifecycle) node, queried with
+    /// `ros2 lifecycle get`; for nodes that cannot send heartbeats
+    Lifecycle {
+        node: String,
         #[serde(with = "humantime_serde")]
         timeout: Duration,
     },
@@ -45,7 +73,38 @@ fn default_http_status() -> u16 {
     200
 }
 
+/// Whether `name` is a token (RFC 9110), as header names must be
+fn is_http_token(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
 impl HealthChecker {
+    /// Reject HTTP headers that would not reach the service as written:
+    /// names that are not tokens and values that break the line
+    pub fn check(&self) -> Option<String> {
+        let HealthChecker::Http { headers, .. } = self else {
+            return None;
+        };
+        let mut names: Vec<&String> = headers.keys().collect();
+        names.sort();
+        for name in names {
+            if !is_http_token(name) {
+                return Some(format!("header name {:?} is not a valid HTTP token", name));
+            }
+            if headers[name].contains(['\r', '\n']) {
+                return Some(format!("header '{}' has a line break in its value", name));
+            }
+        }
+        None
+    }
+
     /// Update the last seen time for heartbeat checks
     pub fn record_heartbeat(&mut self) -> Result<(), HealthError> {
         match self {
@@ -59,16 +118,48 @@ impl HealthChecker {
         }
     }
 
-    /// Check if the health check has timed out (for heartbeat type)
+    /// Forget heartbeats of a previous run and open the start grace period
+    pub fn reset_heartbeat(&mut self) {
+        if let HealthChecker::Heartbeat {
+            last_seen, started, ..
+        } = self
+        {
+            *last_seen = None;
+            *started = Some(SystemTime::now());
+        }
+    }
+
+    /// Whole heartbeat timeouts elapsed since the last heartbeat, not
+    /// counting the start grace period; 0 before the first heartbeat
+    pub fn missed_beats(&self) -> u32 {
+        let HealthChecker::Heartbeat {
+            last_seen: Some(last_seen),
+            timeout,
+            grace_period_on_start,
+            started,
+            ..
+        } = self
+        else {
+            return 0;
+        };
+
+        let grace_end = match (started, grace_period_on_start) {
+            (Some(started), Some(grace)) => *started + *grace,
+            _ => *last_seen,
+        };
+        let since = (*last_seen).max(grace_end);
+        let elapsed = since.elapsed().unwrap_or(Duration::ZERO);
+        let missed = elapsed.as_nanos() / timeout.as_nanos().max(1);
+        missed.min(u32::MAX as u128) as u32
+    }
+
+    /// Check if the health check has timed out (for heartbeat type): more
+    /// beats were missed in a row than `misses_allowed`
     pub fn is_timed_out(&self) -> bool {
         match self {
-            HealthChecker::Heartbeat {
-                last_seen: Some(time),
-                timeout,
-            } => time.elapsed().unwrap_or(Duration::from_secs(0)) > *timeout,
-            HealthChecker::Heartbeat {
-                last_seen: None, ..
-            } => false, // Not started yet
+            HealthChecker::Heartbeat { misses_allowed, .. } => {
+                self.missed_beats() > *misses_allowed
+            }
             _ => false,
         }
     }
@@ -79,11 +170,115 @@ impl HealthChecker {
             HealthChecker::Heartbeat { timeout, .. } => Some(*timeout),
             HealthChecker::Tcp { timeout, .. } => Some(*timeout),
             HealthChecker::Script { timeout, .. } => Some(*timeout),
+            HealthChecker::Lifecycle { timeout, .. } => Some(*timeout),
             HealthChecker::Http { .. } => None,
         }
     }
 }
 
+/// State of a ROS2 lifecycle node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleState {
+    Unconfigured,
+    Inactive,
+    Active,
+    Finalized,
+    /// Between primary states: configuring, activating, deactivating,
+    /// cleaningup, shuttingdown, or errorprocessing
+    Transitioning,
+}
+
+impl LifecycleState {
+    /// Status of a service whose node is in this state: `Healthy` once
+    /// active, `Stopped` once finalized, and `Starting` otherwise
+    pub fn status(self) -> ServiceStatus {
+        match self {
+            LifecycleState::Active => ServiceStatus::Healthy,
+            LifecycleState::Finalized => ServiceStatus::Stopped,
+            LifecycleState::Unconfigured
+            | LifecycleState::Inactive
+            | LifecycleState::Transitioning => ServiceStatus::Starting,
+        }
+    }
+}
+
+/// `ros2 lifecycle get <node>`, printing the node's state, e.g. `active [3]`
+pub fn lifecycle_get_command(node: &str) -> Vec<String> {
+    vec![
+        "ros2".to_string(),
+        "lifecycle".to_string(),
+        "get".to_string(),
+        node.to_string(),
+    ]
+}
+
+/// Interpret `lifecycle_get_command` output, or `None` if it names no state
+pub fn parse_lifecycle_state(output: &str) -> Option<LifecycleState> {
+    let label = output.split_whitespace().next()?.to_ascii_lowercase();
+    match label.as_str() {
+        "unconfigured" => Some(LifecycleState::Unconfigured),
+        "inactive" => Some(LifecycleState::Inactive),
+        "active" => Some(LifecycleState::Active),
+        "finalized" => Some(LifecycleState::Finalized),
+        "configuring" | "activating" | "deactivating" | "cleaningup" | "shuttingdown"
+        | "errorprocessing" => Some(LifecycleState::Transitioning),
+        _ => None,
+    }
+}
+
+/// `health_debounce:` of a service: how many checks a verdict needs before
+/// the service's health changes, and how many changes make it flapping
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthDebounce {
+    /// Consecutive checks with the same verdict before the status changes
+    #[serde(default = "default_debounce_checks")]
+    pub checks: u32,
+    /// Changes between healthy and degraded within `flapping_window` that
+    /// keep the service degraded as flapping; not detected if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flapping_threshold: Option<u32>,
+    #[serde(default = "default_flapping_window", with = "humantime_serde")]
+    pub flapping_window: Duration,
+}
+
+impl Default for HealthDebounce {
+    fn default() -> Self {
+        Self {
+            checks: default_debounce_checks(),
+            flapping_threshold: None,
+            flapping_window: default_flapping_window(),
+        }
+    }
+}
+
+impl HealthDebounce {
+    /// Reject zero `checks`, a `flapping_threshold` below 2 and an empty
+    /// `flapping_window`
+    pub fn check(&self) -> Option<String> {
+        if self.checks == 0 {
+            Some("checks must be at least 1".to_string())
+        } else if self
+            .flapping_threshold
+            .is_some_and(|threshold| threshold < 2)
+        {
+            Some("flapping_threshold must be at least 2".to_string())
+        } else if self.flapping_window.is_zero() {
+            Some("flapping_window must be longer than 0s".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+fn default_debounce_checks() -> u32 {
+    1
+}
+
+fn default_flapping_window() -> Duration {
+    Duration::from_secs(60)
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GpuRequirement {
     #[serde(default)]
@@ -177,6 +372,9 @@ mod tests {
         let mut checker = HealthChecker::Heartbeat {
             last_seen: None,
             timeout: Duration::from_secs(5),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
         };
 
         assert!(!checker.is_timed_out());
@@ -185,6 +383,42 @@ mod tests {
         assert!(!checker.is_timed_out());
     }
 
+    #[test]
+    fn test_heartbeat_misses_allowed_and_grace_period() {
+        let yaml = "type: heartbeat\ntimeout: 1s\nmisses_allowed: 2\ngrace_period_on_start: 30s\n";
+        let mut checker: HealthChecker = serde_yaml::from_str(yaml).unwrap();
+        checker.reset_heartbeat();
+
+        let set_last_seen = |checker: &mut HealthChecker, ago: u64| {
+            if let HealthChecker::Heartbeat { last_seen, .. } = checker {
+                *last_seen = Some(SystemTime::now() - Duration::from_secs(ago));
+            }
+        };
+
+        // Silence within the grace period is not counted
+        set_last_seen(&mut checker, 20);
+        assert_eq!(checker.missed_beats(), 0);
+        assert!(!checker.is_timed_out());
+
+        // Past the grace period: two misses are tolerated, a third is not
+        if let HealthChecker::Heartbeat { started, .. } = &mut checker {
+            *started = Some(SystemTime::now() - Duration::from_secs(60));
+        }
+        set_last_seen(&mut checker, 2);
+        assert_eq!(checker.missed_beats(), 2);
+        assert!(!checker.is_timed_out());
+        set_last_seen(&mut checker, 3);
+        assert_eq!(checker.missed_beats(), 3);
+        assert!(checker.is_timed_out());
+
+        checker.record_heartbeat().unwrap();
+        assert_eq!(checker.missed_beats(), 0);
+
+        // A restart forgets heartbeats of the previous run
+        checker.reset_heartbeat();
+        assert_eq!(checker.missed_beats(), 0);
+    }
+
     #[test]
     fn test_tcp_checker() {
         let checker = HealthChecker::Tcp {
@@ -201,11 +435,59 @@ mod tests {
             port: 3000,
             path: "/health".to_string(),
             expected_status: 200,
+            headers: HashMap::new(),
         };
 
         assert_eq!(checker.timeout(), None);
     }
 
+    #[test]
+    fn test_http_checker_with_headers() {
+        let yaml = r#"
+type: http
+port: 8080
+path: /healthz
+expected_status: 204
+headers:
+  Authorization: Bearer token
+"#;
+        let checker: HealthChecker = serde_yaml::from_str(yaml).unwrap();
+        match checker {
+            HealthChecker::Http {
+                port,
+                path,
+                expected_status,
+                headers,
+            } => {
+                assert_eq!(port, 8080);
+                assert_eq!(path, "/healthz");
+                assert_eq!(expected_status, 204);
+                assert_eq!(headers.get("Authorization").unwrap(), "Bearer token");
+            }
+            _ => panic!("Expected Http variant"),
+        }
+    }
+
+    #[test]
+    fn test_http_headers_cannot_split_the_request() {
+        let checker = |name: &str, value: &str| HealthChecker::Http {
+            port: 8080,
+            path: "/healthz".to_string(),
+            expected_status: 200,
+            headers: HashMap::from([(name.to_string(), value.to_string())]),
+        };
+
+        assert_eq!(checker("Authorization", "Bearer token").check(), None);
+        assert_eq!(checker("X-Robot_ID", "krill01").check(), None);
+        assert!(checker("X-Robot: 1\r\nHost", "a").check().is_some());
+        assert!(checker("X Robot", "a").check().is_some());
+        assert!(checker("", "a").check().is_some());
+        assert!(checker("X-Robot", "krill01\r\nX-Admin: 1")
+            .check()
+            .is_some());
+        assert!(checker("X-Robot", "krill01\n").check().is_some());
+    }
+
     #[test]
     fn test_script_checker() {
         let checker = HealthChecker::Script {
@@ -216,11 +498,30 @@ mod tests {
         assert_eq!(checker.timeout(), Some(Duration::from_secs(10)));
     }
 
+    #[test]
+    fn test_script_check_timeout_alias() {
+        let yaml = "type: script\ncommand: check.sh\ncheck_timeout: 3s\n";
+        let checker: HealthChecker = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(checker.timeout(), Some(Duration::from_secs(3)));
+
+        let err = HealthError::CommandTimeout {
+            command: "check.sh".to_string(),
+            timeout: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Health check 'check.sh' timed out after 1.5s and was killed"
+        );
+    }
+
     #[test]
     fn test_health_checker_serialize() {
         let checker = HealthChecker::Heartbeat {
             last_seen: None,
             timeout: Duration::from_secs(2),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
         };
 
         let yaml = serde_yaml::to_string(&checker).unwrap();
@@ -228,6 +529,45 @@ mod tests {
         assert!(yaml.contains("timeout:"));
     }
 
+    #[test]
+    fn test_lifecycle_checker_deserialization() {
+        let yaml = "type: lifecycle\nnode: /lidar_driver\ntimeout: 5s\n";
+        let checker: HealthChecker = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            checker,
+            HealthChecker::Lifecycle {
+                node: "/lidar_driver".to_string(),
+                timeout: Duration::from_secs(5),
+            }
+        );
+        assert_eq!(checker.timeout(), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_parse_lifecycle_state() {
+        assert_eq!(
+            parse_lifecycle_state("active [3]\n"),
+            Some(LifecycleState::Active)
+        );
+        assert_eq!(
+            parse_lifecycle_state("configuring [10]"),
+            Some(LifecycleState::Transitioning)
+        );
+        assert_eq!(
+            parse_lifecycle_state("finalized [4]"),
+            Some(LifecycleState::Finalized)
+        );
+        assert_eq!(parse_lifecycle_state("Node not found"), None);
+        assert_eq!(parse_lifecycle_state(""), None);
+
+        assert_eq!(LifecycleState::Active.status(), ServiceStatus::Healthy);
+        assert_eq!(
+            LifecycleState::Transitioning.status(),
+            ServiceStatus::Starting
+        );
+        assert_eq!(LifecycleState::Finalized.status(), ServiceStatus::Stopped);
+    }
+
     #[test]
     fn test_gpu_not_required() {
         let req = GpuRequirement {
@@ -251,4 +591,24 @@ mod tests {
         let deserialized: GpuRequirement = serde_yaml::from_str(&yaml).unwrap();
         assert_eq!(req, deserialized);
     }
+
+    #[test]
+    fn test_health_debounce_settings() {
+        let debounce: HealthDebounce = serde_yaml::from_str("flapping_threshold: 5").unwrap();
+        assert_eq!(debounce.checks, 1);
+        assert_eq!(debounce.flapping_window, Duration::from_secs(60));
+        assert_eq!(debounce.check(), None);
+
+        let debounce: HealthDebounce =
+            serde_yaml::from_str("checks: 3\nflapping_window: 30s").unwrap();
+        assert_eq!(debounce.checks, 3);
+        assert_eq!(debounce.flapping_threshold, None);
+        assert_eq!(debounce.flapping_window, Duration::from_secs(30));
+
+        let zero: HealthDebounce = serde_yaml::from_str("checks: 0").unwrap();
+        assert!(zero.check().is_some());
+        let single: HealthDebounce = serde_yaml::from_str("flapping_threshold: 1").unwrap();
+        assert!(single.check().is_some());
+        assert!(serde_yaml::from_str::<HealthDebounce>("check: 3").is_err());
+    }
 }
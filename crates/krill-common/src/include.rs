@@ -0,0 +1,193 @@
+// Includes - Recipes assembled from several files with `include:`
+
+use crate::template::merge;
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+/// Replace the `include:` list of a recipe document by the files it names.
+/// They are merged in order, then the document itself on top: mappings
+/// (such as `services`, each service, or `env`) merge key by key, so
+/// services are merged by name, and any other value, lists included, is
+/// replaced by the later file's. Included files may include further files;
+/// their names are relative to the including file's directory, `base_dir`
+/// for the document. Returns every file read, in the order they were
+/// merged.
+pub fn resolve_includes(doc: &mut Value, base_dir: Option<&Path>) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut stack = Vec::new();
+    resolve(doc, base_dir, &mut stack, &mut files)?;
+    Ok(files)
+}
+
+fn resolve(
+    doc: &mut Value,
+    base_dir: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let Some(root) = doc.as_mapping_mut() else {
+        return Ok(());
+    };
+    let names = match root.remove("include") {
+        None | Some(Value::Null) => return Ok(()),
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Sequence(names)) => names
+            .into_iter()
+            .map(|name| match name {
+                Value::String(name) => Ok(name),
+                _ => Err("include must list file names".to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("include must list file names".to_string()),
+    };
+
+    let mut merged = Value::Mapping(Default::default());
+    for name in names {
+        let path = match base_dir {
+            Some(dir) => dir.join(&name),
+            None => PathBuf::from(&name),
+        };
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("include '{}': {}", path.display(), e))?;
+        if stack.contains(&canonical) {
+            return Err(format!(
+                "include '{}' includes itself through {}",
+                path.display(),
+                stack
+                    .iter()
+                    .map(|file| format!("'{}'", file.display()))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ));
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("include '{}': {}", path.display(), e))?;
+        let mut included: Value = serde_yaml::from_str(&content)
+            .map_err(|e| format!("include '{}': {}", path.display(), e))?;
+        stack.push(canonical);
+        resolve(&mut included, path.parent(), stack, files)?;
+        stack.pop();
+
+        files.push(path);
+        merge(&mut merged, included);
+    }
+
+    merge(&mut merged, std::mem::take(doc));
+    *doc = merged;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_merge_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("base")).unwrap();
+        std::fs::write(
+            dir.path().join("base/common.yaml"),
+            r#"
+include: [sensors.yaml]
+version: "1"
+env:
+  ROS_DOMAIN_ID: "0"
+  RMW_IMPLEMENTATION: rmw_cyclonedds_cpp
+services:
+  planner:
+    execute:
+      type: shell
+      command: planner
+    dependencies: [lidar]
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("base/sensors.yaml"),
+            r#"
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar --port /dev/ttyUSB0
+    critical: true
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("overrides.yaml"),
+            r#"
+env:
+  ROS_DOMAIN_ID: "7"
+services:
+  lidar:
+    execute:
+      command: lidar --port /dev/ttyACM0
+"#,
+        )
+        .unwrap();
+
+        let mut doc: Value = serde_yaml::from_str(
+            r#"
+include: [base/common.yaml, overrides.yaml]
+name: krill01
+services:
+  planner:
+    dependencies: []
+"#,
+        )
+        .unwrap();
+        let files = resolve_includes(&mut doc, Some(dir.path())).unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.path().join("base/sensors.yaml"),
+                dir.path().join("base/common.yaml"),
+                dir.path().join("overrides.yaml"),
+            ]
+        );
+
+        let expected: Value = serde_yaml::from_str(
+            r#"
+services:
+  lidar:
+    execute:
+      type: shell
+      command: lidar --port /dev/ttyACM0
+    critical: true
+  planner:
+    execute:
+      type: shell
+      command: planner
+    dependencies: []
+version: "1"
+env:
+  ROS_DOMAIN_ID: "7"
+  RMW_IMPLEMENTATION: rmw_cyclonedds_cpp
+name: krill01
+"#,
+        )
+        .unwrap();
+        assert_eq!(doc, expected);
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.yaml"), "include: b.yaml\n").unwrap();
+        std::fs::write(dir.path().join("b.yaml"), "include: [a.yaml]\n").unwrap();
+
+        let mut doc: Value = serde_yaml::from_str("include: [a.yaml]").unwrap();
+        let err = resolve_includes(&mut doc, Some(dir.path())).unwrap_err();
+        assert!(err.contains("includes itself"), "{}", err);
+
+        let mut doc: Value = serde_yaml::from_str("include: [missing.yaml]").unwrap();
+        let err = resolve_includes(&mut doc, Some(dir.path())).unwrap_err();
+        assert!(err.contains("missing.yaml"), "{}", err);
+
+        let mut doc: Value = serde_yaml::from_str("include: {file: a.yaml}").unwrap();
+        assert!(resolve_includes(&mut doc, Some(dir.path())).is_err());
+    }
+}
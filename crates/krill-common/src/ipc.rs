@@ -1,9 +1,129 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// IPC protocol version. Peers with the same major version can talk to each
+/// other; the lower minor version is used for the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    pub const CURRENT: ProtocolVersion = ProtocolVersion { major: 1, minor: 1 };
+
+    pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+
+    /// Version both peers understand, or `None` if the major versions differ
+    pub fn negotiate(&self, other: &ProtocolVersion) -> Option<ProtocolVersion> {
+        self.is_compatible_with(other).then(|| (*self).min(*other))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Environment variable through which the daemon tells its services (and
+/// their SDK clients) where its socket is
+pub const SOCKET_ENV: &str = "KRILL_SOCKET";
+
+/// Socket path used before sockets were namespaced per workspace
+pub const LEGACY_SOCKET_PATH: &str = "/tmp/krill.sock";
+
+/// Directory holding daemon sockets: `$XDG_RUNTIME_DIR` if set, else the temp dir
+pub fn socket_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Default socket of the daemon running `workspace`, e.g. `/tmp/krill-robot.sock`.
+/// A name that is no valid workspace name (e.g. `../robot`) is refused.
+pub fn workspace_socket_path(workspace: &str) -> Result<PathBuf, crate::ConfigError> {
+    crate::check_workspace_name(workspace)?;
+    Ok(socket_dir().join(format!("krill-{}.sock", workspace)))
+}
+
+/// Name of the abstract-namespace socket `socket_path` selects, if it starts
+/// with `@` (e.g. `@krill-robot`). Abstract sockets (Linux only) have no
+/// file, so none is left behind to clean up or to block the next start.
+pub fn abstract_socket_name(socket_path: &Path) -> Option<&str> {
+    socket_path
+        .to_str()?
+        .strip_prefix('@')
+        .filter(|name| !name.is_empty())
+}
+
+/// Bind the Unix socket at `socket_path`, in the abstract namespace for an
+/// `@name`. The listener is non-blocking, ready for an async runtime.
+#[cfg(unix)]
+pub fn bind_unix_socket(socket_path: &Path) -> std::io::Result<std::os::unix::net::UnixListener> {
+    let listener = match abstract_socket_name(socket_path) {
+        Some(name) => std::os::unix::net::UnixListener::bind_addr(&abstract_address(name)?)?,
+        None => std::os::unix::net::UnixListener::bind(socket_path)?,
+    };
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Connect to the Unix socket at `socket_path`, in the abstract namespace
+/// for an `@name`. The stream is non-blocking, ready for an async runtime.
+#[cfg(unix)]
+pub fn connect_unix_socket(socket_path: &Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+    let stream = match abstract_socket_name(socket_path) {
+        Some(name) => std::os::unix::net::UnixStream::connect_addr(&abstract_address(name)?)?,
+        None => std::os::unix::net::UnixStream::connect(socket_path)?,
+    };
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_address(name: &str) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn abstract_address(name: &str) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "abstract socket '@{}' needs Linux; use a socket path instead",
+            name
+        ),
+    ))
+}
+
+/// Named pipe that stands in for the Unix socket at `socket_path` on Windows,
+/// e.g. `/tmp/krill.sock` becomes `\\.\pipe\krill-tmp-krill-sock`
+pub fn pipe_name(socket_path: &Path) -> String {
+    let path = socket_path.to_string_lossy();
+    let sanitized: Vec<&str> = path
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|part| !part.is_empty())
+        .collect();
+    format!(r"\\.\pipe\krill-{}", sanitized.join("-"))
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientMessage {
+    /// First message of a session, announcing the client's protocol version
+    Hello {
+        protocol_version: ProtocolVersion,
+        #[serde(default)]
+        client: Option<String>,
+    },
     Heartbeat {
         service: String,
         status: ServiceStatus,
@@ -14,17 +134,128 @@ pub enum ClientMessage {
         action: CommandAction,
         #[serde(default)]
         target: Option<String>,
+        /// Confirms stopping, restarting or killing a protected service
+        #[serde(default, skip_serializing_if = "is_false")]
+        force: bool,
+    },
+    /// Apply one action to several services, queued together in order
+    BatchCommand {
+        action: CommandAction,
+        targets: Vec<String>,
+        #[serde(default, skip_serializing_if = "is_false")]
+        force: bool,
+    },
+    /// Apply one action to each of `targets` at `at` instead of now, e.g.
+    /// a restart in the next maintenance window
+    ScheduleCommand {
+        action: CommandAction,
+        targets: Vec<String>,
+        at: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "is_false")]
+        force: bool,
     },
     Subscribe {
         events: bool,
         logs: Option<String>,
+        /// Every line of service output, bypassing the daemon's
+        /// `log_throttle`
+        #[serde(default, skip_serializing_if = "is_false")]
+        raw_logs: bool,
     },
     GetSnapshot,
+    /// A snapshot of the services matching a label selector such as
+    /// `tier=perception,robot!=r2` (see `LabelSelector`), or of all services
+    ListServices {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        selector: Option<String>,
+    },
+    /// Receive a snapshot now and `snapshot_delta` messages as services
+    /// change; send again to resync after a gap in their sequence numbers.
+    /// Since protocol 1.1.
+    SubscribeSnapshots,
     GetLogs {
         service: Option<String>,
+        /// Only lines classified at this severity or above
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<LogLevel>,
+    },
+    /// A page of a service's output, walking back from the newest line.
+    /// Follow up with the returned `cursor` as `before` for older lines.
+    GetLogsPage {
+        service: String,
+        /// Only lines older than this cursor; `None` for the newest page
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<u64>,
+        #[serde(default = "default_log_page_lines")]
+        lines: usize,
+        /// Only lines classified at this severity or above
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<LogLevel>,
+    },
+    /// Recorded status changes, optionally limited to the last `since_secs`
+    /// seconds and to one service
+    GetEvents {
+        #[serde(default)]
+        since_secs: Option<u64>,
+        #[serde(default)]
+        service: Option<String>,
+    },
+    /// Recorded control commands, optionally limited to the last
+    /// `since_secs` seconds and to one target
+    GetAuditLog {
+        #[serde(default)]
+        since_secs: Option<u64>,
+        #[serde(default)]
+        service: Option<String>,
+    },
+    /// Re-read the recipe and apply the changes: start added services, stop
+    /// removed ones, restart those whose spawn settings changed and update
+    /// the others in place. A dry run only reports what would happen.
+    ReloadConfig {
+        #[serde(default, skip_serializing_if = "is_false")]
+        dry_run: bool,
+    },
+    /// Bundle the logs, event journal, recipe and daemon metadata of a log
+    /// session (the running one by default) into an archive, sent back in
+    /// `log_bundle_chunk`s
+    ExportLogs {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+    },
+    /// Availability of the services since the daemon started, optionally
+    /// of one service
+    GetStats {
+        #[serde(default)]
+        service: Option<String>,
+    },
+    /// Replace the daemon's log filter until it exits, e.g.
+    /// `info,krill_daemon::health=debug`
+    SetLogFilter {
+        filter: String,
     },
 }
 
+/// A client message tagged with an id the daemon copies into each reply to
+/// it, so replies can be told apart from the messages it pushes meanwhile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+/// Lines in a `get_logs_page` reply unless the client asks for another amount
+pub const DEFAULT_LOG_PAGE_LINES: usize = 500;
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn default_log_page_lines() -> usize {
+    DEFAULT_LOG_PAGE_LINES
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CommandAction {
@@ -33,6 +264,31 @@ pub enum CommandAction {
     Restart,
     Kill,
     StopDaemon,
+    /// Start every service of the group named by `target`, plus dependencies
+    StartGroup,
+    /// Stop every service of the group named by `target`
+    StopGroup,
+    /// Stop all services and latch them until their safety stop is cleared
+    EmergencyStop,
+    /// Release the emergency stop latch of the service named by `target`
+    ClearSafetyStop,
+    /// Replace the daemon with the binary at `target` (by default its own
+    /// executable), handing the running services over without restarting them
+    UpgradeDaemon,
+    /// Send the service's `reload_signal` so it reloads its configuration
+    /// without restarting
+    Reload,
+    /// Resume the automatic restarts a restart storm paused, in the
+    /// workspace named by `target` or in all of them
+    ResumeRestarts,
+    /// Freeze the processes of a running service with SIGSTOP, keeping their state
+    Pause,
+    /// Continue a paused service with SIGCONT
+    Resume,
+    /// Change the daemon's log filter to `target`. Sent as a
+    /// `set_log_filter` message; named here for the access policy and
+    /// the audit log.
+    SetLogFilter,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -45,11 +301,21 @@ pub enum ServiceStatus {
     Stopping,
     Stopped,
     Failed,
+    /// A oneshot service that exited successfully
+    Completed,
+    /// Not started because a dependency was not ready within `dependency_timeout`
+    Blocked,
+    /// Frozen by a `pause` command until resumed; not health checked meanwhile
+    Paused,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerMessage {
+    /// Accepted handshake, carrying the negotiated protocol version
+    Welcome {
+        protocol_version: ProtocolVersion,
+    },
     Ack {
         request_id: Option<String>,
     },
@@ -64,14 +330,100 @@ pub enum ServerMessage {
     LogLine {
         service: String,
         line: String,
+        /// Severity parsed from the line, if it has one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<LogLevel>,
+        /// When the daemon captured the line
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<DateTime<Utc>>,
+        /// Stream the line was written to; `None` for lines the daemon
+        /// logs on behalf of a service
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stream: Option<LogStream>,
     },
     Snapshot {
         services: HashMap<String, ServiceSnapshot>,
+        /// Sequence number of the last delta the snapshot includes; set in
+        /// reply to `subscribe_snapshots`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+    },
+    /// Changes since the previous snapshot or delta, numbered consecutively
+    SnapshotDelta {
+        seq: u64,
+        /// Changed fields of changed or added services
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        changed: HashMap<String, crate::SnapshotFields>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        removed: Vec<String>,
     },
     LogHistory {
         service: Option<String>,
         lines: Vec<String>,
     },
+    /// Reply to `get_logs_page`, lines oldest first
+    LogPage {
+        service: String,
+        lines: Vec<String>,
+        /// `before` of the next older page; `None` once the oldest line was sent
+        cursor: Option<u64>,
+    },
+    EventHistory {
+        events: Vec<EventRecord>,
+    },
+    /// Reply to `get_audit_log`, oldest first
+    AuditLog {
+        records: Vec<AuditRecord>,
+    },
+    ConfigReloaded {
+        added: Vec<String>,
+        removed: Vec<String>,
+        restarted: Vec<String>,
+        /// Services whose changes apply without a restart
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        updated: Vec<String>,
+        /// Changed settings of the restarted and updated services
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        changes: BTreeMap<String, Vec<String>>,
+        /// Nothing was applied: the reply to a dry run
+        #[serde(default, skip_serializing_if = "is_false")]
+        dry_run: bool,
+    },
+    /// Next part of the archive requested by `export_logs`, base64
+    /// encoded; the parts come in order, before `logs_exported`
+    LogBundleChunk {
+        data: String,
+    },
+    /// Reply to `export_logs`, once all of the archive was sent
+    LogsExported {
+        session: String,
+        size: u64,
+    },
+    /// Reply to `get_stats`
+    Stats {
+        services: HashMap<String, AvailabilityStats>,
+    },
+    ServiceMetrics {
+        services: HashMap<String, ServiceMetrics>,
+    },
+    /// A maintenance action of a service was scheduled, or is due
+    Maintenance {
+        service: String,
+        event: crate::MaintenanceEvent,
+        maintenance: crate::MaintenanceAction,
+    },
+    /// More than `max_failures` services failed within `window`, so the
+    /// workspace's automatic restarts are paused until `resume_restarts`
+    SystemDegraded {
+        workspace: String,
+        failures: u32,
+        window: std::time::Duration,
+    },
+    /// The daemon stopped these services, in the order they stopped, and
+    /// is exiting
+    ShutdownReport {
+        services: Vec<ServiceShutdown>,
+    },
     SystemStats {
         cpu_usage: f32,
         memory_used_mb: u64,
@@ -81,6 +433,32 @@ pub enum ServerMessage {
     },
 }
 
+/// A reply to a [`ClientRequest`], carrying its `request_id`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerReply {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+impl ServerReply {
+    /// Reply `message` to the request `request_id`. An `ack` carries the id
+    /// in its own field, which reads back into `request_id`.
+    pub fn new(request_id: Option<String>, message: ServerMessage) -> Self {
+        match message {
+            ServerMessage::Ack { request_id: None } => Self {
+                request_id: None,
+                message: ServerMessage::Ack { request_id },
+            },
+            message => Self {
+                request_id,
+                message,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceSnapshot {
     pub status: ServiceStatus,
@@ -91,20 +469,349 @@ pub struct ServiceSnapshot {
     pub restart_count: u32,
     pub last_error: Option<String>,
     pub namespace: String,
+    /// What the recipe says about the service
+    #[serde(default)]
+    pub config: ServiceConfigSummary,
+    /// The same as `config`, in the top-level fields older clients read
+    #[serde(flatten)]
+    pub legacy_config: LegacyConfigFields,
+    /// Stop, restart and kill commands need `force`
+    #[serde(default)]
+    pub protected: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Held down by an emergency stop until cleared
+    #[serde(default)]
+    pub safety_stopped: bool,
+    /// A restart storm paused the automatic restarts of the service's
+    /// workspace until `resume_restarts`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub restarts_paused: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ServiceMetrics>,
+    /// Metadata attached to the service's last heartbeat, e.g. a `reason`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub heartbeat_metadata: HashMap<String, String>,
+    /// Time since the service's last heartbeat, if it sent one since starting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_age: Option<std::time::Duration>,
+    /// Heartbeats missed in a row, counted against `misses_allowed`
+    #[serde(default)]
+    pub missed_heartbeats: u32,
+    /// Restarts since the daemon started, carried over by `krill daemon
+    /// upgrade`; unlike `restart_count`, never reset by a healthy stretch
+    #[serde(default)]
+    pub total_restarts: u32,
+    /// Exit code of the service's last process, if it exited with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit_code: Option<i32>,
+    /// Most recent status changes, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub state_history: Vec<StateTransition>,
+    /// Time since the service entered its current status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_age: Option<std::time::Duration>,
+    /// Uptime, downtime and failures since the daemon started
+    #[serde(default)]
+    pub availability: AvailabilityStats,
+    /// Upcoming maintenance actions, soonest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintenance: Vec<crate::MaintenanceAction>,
+}
+
+/// The parts of a service's recipe clients show next to its runtime state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceConfigSummary {
+    /// Executor type, e.g. `pixi` or `docker`
+    pub executor: String,
+    /// Services it waits for, in recipe order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<DependencySummary>,
+    pub restart_policy: crate::RestartPolicy,
+    /// 0 for unlimited
+    pub max_restarts: u32,
+    pub critical: bool,
+    pub uses_gpu: bool,
+    /// Recipe groups the service is a member of, sorted by name
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+/// A dependency of a service and the condition the service waits for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencySummary {
+    pub service: String,
+    pub condition: crate::DependencyCondition,
+}
+
+impl From<&crate::ServiceConfig> for ServiceConfigSummary {
+    fn from(config: &crate::ServiceConfig) -> Self {
+        Self {
+            executor: config.execute.executor_type().to_string(),
+            dependencies: config
+                .dependencies
+                .iter()
+                .map(|dependency| DependencySummary {
+                    service: dependency.service_name().to_string(),
+                    condition: dependency.condition(),
+                })
+                .collect(),
+            restart_policy: config.policy.restart,
+            max_restarts: config.policy.max_restarts,
+            critical: config.critical,
+            uses_gpu: config.gpu,
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl Default for ServiceConfigSummary {
+    fn default() -> Self {
+        Self {
+            executor: String::new(),
+            dependencies: Vec::new(),
+            restart_policy: crate::PolicyConfig::default().restart,
+            max_restarts: 0,
+            critical: false,
+            uses_gpu: false,
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl ServiceConfigSummary {
+    /// Names of the services it waits for
+    pub fn dependency_names(&self) -> impl Iterator<Item = &str> {
+        self.dependencies
+            .iter()
+            .map(|dependency| dependency.service.as_str())
+    }
+}
+
+/// The recipe fields snapshots carried before they grouped them under
+/// `config`. Deprecated: they are still sent for older clients, but new
+/// code reads `config`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LegacyConfigFields {
+    #[serde(default)]
     pub executor_type: String,
     #[serde(default)]
     pub dependencies: Vec<String>,
+    /// Condition the service waits for on each of its dependencies
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub dependency_conditions: HashMap<String, crate::DependencyCondition>,
     #[serde(default)]
     pub uses_gpu: bool,
     #[serde(default)]
     pub critical: bool,
+    /// The policy's variant name, e.g. `OnFailure`
     #[serde(default)]
     pub restart_policy: String,
     #[serde(default)]
     pub max_restarts: u32,
 }
 
+impl From<&ServiceConfigSummary> for LegacyConfigFields {
+    fn from(config: &ServiceConfigSummary) -> Self {
+        Self {
+            executor_type: config.executor.clone(),
+            dependencies: config.dependency_names().map(String::from).collect(),
+            dependency_conditions: config
+                .dependencies
+                .iter()
+                .map(|dependency| (dependency.service.clone(), dependency.condition))
+                .collect(),
+            uses_gpu: config.uses_gpu,
+            critical: config.critical,
+            restart_policy: format!("{:?}", config.restart_policy),
+            max_restarts: config.max_restarts,
+        }
+    }
+}
+
+/// How reliably a service ran since the daemon started. Stopped, completed
+/// and not yet started services are neither up nor down.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AvailabilityStats {
+    /// Time spent running, healthy or degraded
+    pub uptime: std::time::Duration,
+    /// Time spent starting, failed or blocked
+    pub downtime: std::time::Duration,
+    /// Times the service failed
+    pub failures: u32,
+    /// Mean time between failures: uptime per failure, once it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtbf: Option<std::time::Duration>,
+}
+
+impl AvailabilityStats {
+    /// Share of the time the service was up out of the time it was up or
+    /// down, as a fraction; `None` before either
+    pub fn availability(&self) -> Option<f64> {
+        let total = (self.uptime + self.downtime).as_secs_f64();
+        (total > 0.0).then(|| self.uptime.as_secs_f64() / total)
+    }
+}
+
+/// What ended a service's process when the daemon stopped it
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopMethod {
+    /// Its `stop_command` or `stop_task`
+    StopCommand,
+    Sigint,
+    Sigterm,
+    Sighup,
+    /// `docker stop`, `podman stop` or `docker compose down`
+    ContainerStop,
+    /// Killed once the graceful steps timed out (on Windows, by
+    /// terminating its job object)
+    Sigkill,
+}
+
+impl From<crate::policy::StopAction> for StopMethod {
+    fn from(action: crate::policy::StopAction) -> Self {
+        use crate::policy::StopAction;
+        match action {
+            StopAction::StopCommand => StopMethod::StopCommand,
+            StopAction::Sigint => StopMethod::Sigint,
+            StopAction::Sigterm => StopMethod::Sigterm,
+            StopAction::Sighup => StopMethod::Sighup,
+        }
+    }
+}
+
+impl fmt::Display for StopMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopMethod::StopCommand => "stop_command",
+            StopMethod::Sigint => "SIGINT",
+            StopMethod::Sigterm => "SIGTERM",
+            StopMethod::Sighup => "SIGHUP",
+            StopMethod::ContainerStop => "container stop",
+            StopMethod::Sigkill => "SIGKILL",
+        })
+    }
+}
+
+/// How a service was stopped when the daemon shut down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceShutdown {
+    pub service: String,
+    /// `None` if the stop failed before the process ended
+    pub method: Option<StopMethod>,
+    /// From the stop request until the process was gone, hooks included
+    pub duration: std::time::Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Signal that ended the process, on Unix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// Failed hooks and stop commands, and why the stop failed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// A status change of a service, kept in its snapshot's `state_history`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub timestamp: DateTime<Utc>,
+    pub status: ServiceStatus,
+}
+
+/// A service status change recorded by the daemon's event journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub timestamp: DateTime<Utc>,
+    pub service: String,
+    pub status: ServiceStatus,
+}
+
+/// Who sent a request to the daemon
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientIdentity {
+    /// User of a local client, from its socket's peer credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// Primary group of a local client, from its socket's peer credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    /// Process of a local client, from its socket's peer credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    /// Remote address of a TCP client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Name the client gave in its `hello`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(uid) = self.uid {
+            parts.push(format!("uid={}", uid));
+        }
+        if let Some(gid) = self.gid {
+            parts.push(format!("gid={}", gid));
+        }
+        if let Some(pid) = self.pid {
+            parts.push(format!("pid={}", pid));
+        }
+        if let Some(ref address) = self.address {
+            parts.push(format!("addr={}", address));
+        }
+        if let Some(ref client) = self.client {
+            parts.push(format!("client={}", client));
+        }
+        if parts.is_empty() {
+            write!(f, "unknown")
+        } else {
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+}
+
+/// Whether the daemon took on an audited command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Accepted,
+    Rejected {
+        code: i32,
+        message: String,
+    },
+    /// Planned for `at`; recorded again with how it went once applied
+    Scheduled {
+        at: DateTime<Utc>,
+    },
+}
+
+/// A control command recorded by the daemon's audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub action: CommandAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub client: ClientIdentity,
+    pub outcome: AuditOutcome,
+}
+
+/// Resource usage of a service's process group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServiceMetrics {
+    /// CPU usage in percent of one core (may exceed 100 for multi-threaded services)
+    pub cpu_percent: f32,
+    /// Resident set size in bytes
+    pub memory_bytes: u64,
+    pub threads: u32,
+}
+
+/// Severity of a log line, ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Trace,
@@ -114,6 +821,14 @@ pub enum LogLevel {
     Error,
 }
 
+/// Output stream of a service process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +854,7 @@ mod tests {
         let msg = ClientMessage::Command {
             action: CommandAction::Start,
             target: Some("navigator".to_string()),
+            force: false,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
@@ -146,6 +862,301 @@ mod tests {
         assert!(json.contains("\"action\":\"start\""));
     }
 
+    #[test]
+    fn test_batch_command_message() {
+        let json = r#"{"type":"batch_command","action":"restart","targets":["camera","lidar"]}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::BatchCommand {
+                action: CommandAction::Restart,
+                targets: vec!["camera".to_string(), "lidar".to_string()],
+                force: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_log_page_messages() {
+        let json = r#"{"type":"get_logs_page","service":"lidar"}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::GetLogsPage {
+                service: "lidar".to_string(),
+                before: None,
+                lines: DEFAULT_LOG_PAGE_LINES,
+                level: None,
+            }
+        );
+
+        let msg = ServerMessage::LogPage {
+            service: "lidar".to_string(),
+            lines: vec!["scan 1".to_string()],
+            cursor: Some(1200),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"log_page","service":"lidar","lines":["scan 1"],"cursor":1200}"#
+        );
+    }
+
+    #[test]
+    fn test_emergency_stop_actions() {
+        let msg = ClientMessage::Command {
+            action: CommandAction::EmergencyStop,
+            target: None,
+            force: false,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""action":"emergency_stop""#));
+
+        let json = r#"{"type":"command","action":"clear_safety_stop","target":"lidar"}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::Command {
+                action: CommandAction::ClearSafetyStop,
+                target: Some("lidar".to_string()),
+                force: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_workspace_socket_path() {
+        let path = workspace_socket_path("robot").unwrap();
+        assert_eq!(path.file_name().unwrap(), "krill-robot.sock");
+        assert_eq!(path.parent().unwrap(), socket_dir());
+        assert_ne!(path, workspace_socket_path("sim").unwrap());
+
+        for name in ["", "../robot", "robot/sim", "robot sim"] {
+            assert!(workspace_socket_path(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_abstract_socket_name() {
+        assert_eq!(
+            abstract_socket_name(Path::new("@krill-robot")),
+            Some("krill-robot")
+        );
+        assert_eq!(abstract_socket_name(Path::new("/tmp/krill.sock")), None);
+        assert_eq!(abstract_socket_name(Path::new("@")), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_abstract_socket_round_trip() {
+        use std::io::{Read, Write};
+
+        let path = PathBuf::from(format!("@krill-test-{}", std::process::id()));
+        let listener = bind_unix_socket(&path).unwrap();
+        let mut client = connect_unix_socket(&path).unwrap();
+        client.set_nonblocking(false).unwrap();
+        client.write_all(b"ping").unwrap();
+
+        listener.set_nonblocking(false).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Nothing on disk to clean up, and the name is free once closed
+        assert!(!path.exists());
+        drop((listener, server));
+        assert!(connect_unix_socket(&path).is_err());
+        drop(bind_unix_socket(&path).unwrap());
+    }
+
+    #[test]
+    fn test_pipe_name() {
+        assert_eq!(
+            pipe_name(Path::new("/tmp/krill.sock")),
+            r"\\.\pipe\krill-tmp-krill-sock"
+        );
+        assert_eq!(
+            pipe_name(Path::new(r"C:\Users\me\krill.sock")),
+            r"\\.\pipe\krill-C-Users-me-krill-sock"
+        );
+    }
+
+    #[test]
+    fn test_protocol_negotiation() {
+        let v1_0 = ProtocolVersion { major: 1, minor: 0 };
+        let v1_3 = ProtocolVersion { major: 1, minor: 3 };
+        let v2_0 = ProtocolVersion { major: 2, minor: 0 };
+
+        assert_eq!(v1_3.negotiate(&v1_0), Some(v1_0));
+        assert_eq!(v1_0.negotiate(&v1_3), Some(v1_0));
+        assert_eq!(v1_0.negotiate(&v2_0), None);
+        assert_eq!(v1_3.to_string(), "1.3");
+    }
+
+    #[test]
+    fn test_hello_message() {
+        let msg = ClientMessage::Hello {
+            protocol_version: ProtocolVersion::CURRENT,
+            client: Some("krill-tui".to_string()),
+        };
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""type":"hello""#));
+        assert!(json.contains(r#""protocol_version":{"major":1,"minor":1}"#));
+        let deserialized: ClientMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, deserialized);
+    }
+
+    #[test]
+    fn test_request_ids() {
+        let request: ClientRequest =
+            serde_json::from_str(r#"{"type":"get_snapshot","request_id":"7"}"#).unwrap();
+        assert_eq!(request.request_id.as_deref(), Some("7"));
+        assert_eq!(request.message, ClientMessage::GetSnapshot);
+
+        // Untagged messages still parse, and serialize as before
+        let request: ClientRequest = serde_json::from_str(r#"{"type":"get_snapshot"}"#).unwrap();
+        assert_eq!(request.request_id, None);
+        let reply = ServerReply::new(
+            None,
+            ServerMessage::Welcome {
+                protocol_version: ProtocolVersion::CURRENT,
+            },
+        );
+        assert_eq!(
+            serde_json::to_value(&reply).unwrap(),
+            serde_json::to_value(&reply.message).unwrap()
+        );
+
+        let reply = ServerReply::new(
+            Some("7".to_string()),
+            ServerMessage::Snapshot {
+                services: HashMap::new(),
+                seq: None,
+            },
+        );
+        let json = serde_json::to_string(&reply).unwrap();
+        assert!(json.contains(r#""request_id":"7""#));
+        assert_eq!(serde_json::from_str::<ServerReply>(&json).unwrap(), reply);
+
+        let json = serde_json::to_string(&ServerReply::new(
+            Some("8".to_string()),
+            ServerMessage::Ack { request_id: None },
+        ))
+        .unwrap();
+        assert_eq!(json, r#"{"type":"ack","request_id":"8"}"#);
+        let reply: ServerReply = serde_json::from_str(&json).unwrap();
+        assert_eq!(reply.request_id.as_deref(), Some("8"));
+    }
+
+    #[test]
+    fn test_get_events_defaults() {
+        let msg: ClientMessage = serde_json::from_str(r#"{"type":"get_events"}"#).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::GetEvents {
+                since_secs: None,
+                service: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_audit_record_json() {
+        let record = AuditRecord {
+            timestamp: "2026-03-01T12:00:00Z".parse().unwrap(),
+            action: CommandAction::Restart,
+            target: Some("motor-controller".to_string()),
+            client: ClientIdentity {
+                uid: Some(1000),
+                gid: None,
+                pid: Some(4242),
+                address: None,
+                client: Some("krill-cli".to_string()),
+            },
+            outcome: AuditOutcome::Rejected {
+                code: 423,
+                message: "held".to_string(),
+            },
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"timestamp":"2026-03-01T12:00:00Z","action":"restart","target":"motor-controller","client":{"uid":1000,"pid":4242,"client":"krill-cli"},"outcome":{"rejected":{"code":423,"message":"held"}}}"#
+        );
+        assert_eq!(serde_json::from_str::<AuditRecord>(&json).unwrap(), record);
+        assert_eq!(
+            record.client.to_string(),
+            "uid=1000 pid=4242 client=krill-cli"
+        );
+    }
+
+    #[test]
+    fn test_reload_messages() {
+        let json = serde_json::to_string(&ClientMessage::ReloadConfig { dry_run: false }).unwrap();
+        assert_eq!(json, r#"{"type":"reload_config"}"#);
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"type":"reload_config","dry_run":true}"#).unwrap();
+        assert_eq!(msg, ClientMessage::ReloadConfig { dry_run: true });
+
+        let msg = ServerMessage::ConfigReloaded {
+            added: vec!["camera".to_string()],
+            removed: vec![],
+            restarted: vec!["lidar".to_string()],
+            updated: vec![],
+            changes: BTreeMap::from([("lidar".to_string(), vec!["execute".to_string()])]),
+            dry_run: false,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"type\":\"config_reloaded\""));
+        let deserialized: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, deserialized);
+    }
+
+    #[test]
+    fn test_availability() {
+        let stats = AvailabilityStats {
+            uptime: std::time::Duration::from_secs(95),
+            downtime: std::time::Duration::from_secs(5),
+            failures: 2,
+            mtbf: None,
+        };
+        assert_eq!(stats.availability(), Some(0.95));
+        assert_eq!(AvailabilityStats::default().availability(), None);
+
+        let msg: ClientMessage = serde_json::from_str(r#"{"type":"get_stats"}"#).unwrap();
+        assert_eq!(msg, ClientMessage::GetStats { service: None });
+    }
+
+    #[test]
+    fn test_set_log_filter() {
+        let msg = ClientMessage::SetLogFilter {
+            filter: "info,krill_daemon::health=debug".to_string(),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"set_log_filter","filter":"info,krill_daemon::health=debug"}"#
+        );
+    }
+
+    #[test]
+    fn test_schedule_command() {
+        let json = r#"{"type":"schedule_command","action":"restart","targets":["lidar"],"at":"2026-10-17T02:00:00Z"}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::ScheduleCommand {
+                action: CommandAction::Restart,
+                targets: vec!["lidar".to_string()],
+                at: "2026-10-17T02:00:00Z".parse().unwrap(),
+                force: false,
+            }
+        );
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+    }
+
     #[test]
     fn test_server_error() {
         let msg = ServerMessage::Error {
@@ -171,17 +1182,119 @@ mod tests {
                 restart_count: 0,
                 last_error: None,
                 namespace: "test-workspace".to_string(),
-                executor_type: "pixi".to_string(),
-                dependencies: vec![],
-                uses_gpu: false,
-                critical: false,
-                restart_policy: "Always".to_string(),
-                max_restarts: 0,
+                config: ServiceConfigSummary {
+                    executor: "pixi".to_string(),
+                    dependencies: vec![DependencySummary {
+                        service: "camera".to_string(),
+                        condition: crate::DependencyCondition::Healthy,
+                    }],
+                    restart_policy: crate::RestartPolicy::Always,
+                    max_restarts: 0,
+                    critical: false,
+                    uses_gpu: false,
+                    groups: vec!["perception".to_string()],
+                },
+                protected: false,
+                legacy_config: Default::default(),
+                labels: Default::default(),
+                safety_stopped: false,
+                restarts_paused: false,
+                metrics: Some(ServiceMetrics {
+                    cpu_percent: 12.5,
+                    memory_bytes: 64 * 1024 * 1024,
+                    threads: 4,
+                }),
+                heartbeat_metadata: HashMap::from([(
+                    "reason".to_string(),
+                    "high temperature".to_string(),
+                )]),
+                heartbeat_age: Some(std::time::Duration::from_millis(250)),
+                missed_heartbeats: 1,
+                total_restarts: 3,
+                last_exit_code: Some(1),
+                state_history: vec![StateTransition {
+                    timestamp: Utc::now(),
+                    status: ServiceStatus::Running,
+                }],
+                state_age: Some(std::time::Duration::from_secs(300)),
+                availability: AvailabilityStats {
+                    uptime: std::time::Duration::from_secs(290),
+                    downtime: std::time::Duration::from_secs(10),
+                    failures: 1,
+                    mtbf: Some(std::time::Duration::from_secs(290)),
+                },
+                maintenance: vec![crate::MaintenanceAction {
+                    action: CommandAction::Restart,
+                    at: Utc::now(),
+                    cron: Some("0 3 * * *".to_string()),
+                }],
             },
         );
 
-        let msg = ServerMessage::Snapshot { services };
+        let msg = ServerMessage::Snapshot {
+            services,
+            seq: None,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        let deserialized: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, deserialized);
+    }
+
+    #[test]
+    fn test_snapshot_keeps_legacy_config_fields() {
+        let mut snapshot: ServiceSnapshot = serde_json::from_value(serde_json::json!({
+            "status": "running",
+            "pid": 1234,
+            "uptime": null,
+            "restart_count": 0,
+            "last_error": null,
+            "namespace": "robot",
+            "config": {
+                "executor": "pixi",
+                "dependencies": [{"service": "camera", "condition": "healthy"}],
+                "restart_policy": "on-failure",
+                "max_restarts": 3,
+            },
+        }))
+        .unwrap();
+        snapshot.legacy_config = (&snapshot.config).into();
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["executor_type"], "pixi");
+        assert_eq!(json["dependencies"], serde_json::json!(["camera"]));
+        assert_eq!(json["dependency_conditions"]["camera"], "healthy");
+        assert_eq!(json["restart_policy"], "OnFailure");
+        assert_eq!(json["max_restarts"], 3);
+        assert_eq!(json["config"]["executor"], "pixi");
+    }
+
+    #[test]
+    fn test_snapshot_delta_message() {
+        let msg = ServerMessage::SnapshotDelta {
+            seq: 7,
+            changed: HashMap::from([(
+                "lidar".to_string(),
+                serde_json::from_str(r#"{"status":"degraded","pid":null}"#).unwrap(),
+            )]),
+            removed: vec![],
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"snapshot_delta","seq":7,"changed":{"lidar":{"pid":null,"status":"degraded"}}}"#
+        );
+        let deserialized: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, deserialized);
+    }
+
+    #[test]
+    fn test_service_metrics_message() {
+        let mut services = HashMap::new();
+        services.insert("lidar".to_string(), ServiceMetrics::default());
+
+        let msg = ServerMessage::ServiceMetrics { services };
         let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""type":"service_metrics""#));
         let deserialized: ServerMessage = serde_json::from_str(&json).unwrap();
         assert_eq!(msg, deserialized);
     }
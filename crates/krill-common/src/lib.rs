@@ -1,24 +1,94 @@
+pub mod access;
 pub mod config;
 pub mod dag;
+pub mod delta;
 pub mod dependency;
+pub mod emergency;
+pub mod env_file;
+pub mod error;
 pub mod execute;
+pub mod executor;
 pub mod health;
+pub mod include;
 pub mod ipc;
+pub mod limits;
+pub mod log_parser;
+pub mod maintenance;
+pub mod paths;
 pub mod policy;
+pub mod ports;
+pub mod precondition;
 pub mod process;
+pub mod scheduling;
+pub mod secrets;
+pub mod selector;
+pub mod template;
 pub mod validation;
 
-pub use config::{ConfigError, KrillConfig, ServiceConfig};
+pub use access::{AccessConfig, Principal, DEFAULT_SOCKET_MODE};
+pub use config::{
+    check_workspace_name, ConfigError, ConfigIssue, DaemonLogConfig, DaemonLogFormat, Hook,
+    KrillConfig, LogFormat, LogRotationConfig, LogThrottleConfig, PrometheusConfig, ServiceChanges,
+    ServiceConfig, ServiceHooks, SPAWN_SETTINGS,
+};
 pub use dag::{DagError, DependencyGraph};
+pub use delta::{apply_snapshot_delta, diff_snapshots, SnapshotFields};
 pub use dependency::{Dependency, DependencyCondition};
-pub use execute::ExecuteConfig;
-pub use health::{validate_gpu_available, GpuRequirement, HealthChecker, HealthError};
-pub use ipc::{ClientMessage, CommandAction, ServerMessage, ServiceSnapshot, ServiceStatus};
-pub use policy::{PolicyConfig, RestartPolicy};
+pub use emergency::{EmergencyAction, DEFAULT_EMERGENCY_COMMAND_TIMEOUT};
+pub use env_file::{load_env_file, EnvFileError};
+pub use error::{
+    KrillError, ERROR_CONFIRMATION_REQUIRED, ERROR_CONFLICT, ERROR_INCOMPATIBLE_PROTOCOL,
+    ERROR_INTERNAL, ERROR_INVALID_CONFIG, ERROR_INVALID_REQUEST, ERROR_NOT_FOUND,
+    ERROR_PERMISSION_DENIED, ERROR_SAFETY_STOPPED, ERROR_SHUTTING_DOWN, ERROR_SLOW_CLIENT,
+    ERROR_SPAWN_FAILED, ERROR_TIMEOUT, ERROR_UNSUPPORTED,
+};
+pub use execute::{ContainerEngine, CustomExecute, ExecuteConfig};
+pub use executor::{
+    register_executor, registered_executors, ExecuteOptions, Executor, ExecutorError,
+};
+pub use health::{
+    lifecycle_get_command, parse_lifecycle_state, validate_gpu_available, GpuRequirement,
+    HealthChecker, HealthDebounce, HealthError, LifecycleState,
+};
+pub use include::resolve_includes;
+pub use ipc::{
+    abstract_socket_name, pipe_name, socket_dir, workspace_socket_path, AuditOutcome, AuditRecord,
+    AvailabilityStats, ClientIdentity, ClientMessage, ClientRequest, CommandAction,
+    DependencySummary, EventRecord, LegacyConfigFields, LogLevel, LogStream, ProtocolVersion,
+    ServerMessage, ServerReply, ServiceConfigSummary, ServiceMetrics, ServiceShutdown,
+    ServiceSnapshot, ServiceStatus, StateTransition, StopMethod, DEFAULT_LOG_PAGE_LINES,
+    LEGACY_SOCKET_PATH, SOCKET_ENV,
+};
+#[cfg(unix)]
+pub use ipc::{bind_unix_socket, connect_unix_socket};
+pub use limits::{format_memory_size, parse_memory_size, ResourceLimits};
+pub use log_parser::{parse_log_level, ProcessOutputLine};
+pub use maintenance::{
+    parse_time, CronSchedule, MaintenanceAction, MaintenanceEvent, MaintenanceSchedule,
+};
+pub use paths::{PathVars, WORKSPACE_DIR_VAR};
+pub use policy::{
+    BackoffStrategy, PolicyConfig, ReloadSignal, RestartPolicy, RestartStormConfig, StopAction,
+    StopStep,
+};
+pub use ports::{
+    find_busy_port, find_port_conflict, PortConflict, PortOwner, PortProtocol, PortSpec,
+};
+pub use precondition::{Precondition, TCP_PRECONDITION_TIMEOUT};
+#[cfg(windows)]
+pub use process::JobObject;
 pub use process::{
-    build_command, find_executable, generate_process_name, get_process_group, get_stop_command,
-    get_working_dir, kill_process_group, setup_process_group, ProcessError,
+    build_command, build_container_command, compose_down_command, container_exit_reason,
+    container_name, container_pause_command, find_executable, generate_process_name,
+    get_process_group, get_stop_command, get_working_dir, kill_process_group,
+    parse_container_health, setup_process_group, shell_command, ProcessError,
+};
+pub use scheduling::{
+    check_cpu_affinity, cpu_affinity_docker_args, SchedulerConfig, SchedulerPolicy, MAX_CPU_INDEX,
 };
+pub use secrets::{read_secrets, SecretError, SecretSource};
+pub use selector::{check_label, LabelRequirement, LabelSelector};
+pub use template::expand_templates;
 pub use validation::validate_shell_command;
 
 // Re-export commonly used types
@@ -0,0 +1,199 @@
+// Resource Limits - CPU, memory and scheduling priority caps of a service
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// `limits:` of a service
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// CPU cores the service may use, e.g. `1.5`
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Memory in bytes, written as `512M`, `2G`, or a plain byte count
+    #[serde(default, deserialize_with = "deserialize_memory")]
+    pub memory: Option<u64>,
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// Without a cgroup, enforce `memory` as an address space rlimit. Off
+    /// by default: reserved but unused address space counts against it.
+    #[serde(default)]
+    pub memory_rlimit: bool,
+}
+
+impl ResourceLimits {
+    /// Reject limits no process could run within and a `nice` value outside
+    /// -20 to 19
+    pub fn check(&self) -> Option<String> {
+        if let Some(cpu) = self.cpu {
+            if !cpu.is_finite() || cpu <= 0.0 {
+                return Some(format!(
+                    "cpu must be a positive number of cores, got {}",
+                    cpu
+                ));
+            }
+        }
+        if self.memory == Some(0) {
+            return Some("memory must be greater than 0".to_string());
+        }
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Some(format!("nice must be between -20 and 19, got {}", nice));
+            }
+        }
+        None
+    }
+
+    /// `docker run` options enforcing the CPU and memory limits
+    pub fn docker_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(cpu) = self.cpu {
+            args.push("--cpus".to_string());
+            args.push(cpu.to_string());
+        }
+        if let Some(memory) = self.memory {
+            args.push("--memory".to_string());
+            args.push(memory.to_string());
+        }
+        args
+    }
+}
+
+/// Parse a memory size such as `512M`, `2G`, `1.5Gi` or `1048576`. Suffixes
+/// are binary (`K` = 1024 bytes), with or without a trailing `i`/`iB`/`B`.
+pub fn parse_memory_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit.trim_end_matches('B').trim_end_matches('I');
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+
+    let bytes = number * multiplier as f64;
+    (bytes.is_finite() && bytes >= 0.0 && bytes <= u64::MAX as f64).then_some(bytes as u64)
+}
+
+/// Human readable memory size, e.g. `512.0 MiB`
+pub fn format_memory_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+fn deserialize_memory<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Memory {
+        Bytes(u64),
+        Size(String),
+    }
+
+    match Option::<Memory>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Memory::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Memory::Size(size)) => parse_memory_size(&size).map(Some).ok_or_else(|| {
+            D::Error::custom(format!(
+                "invalid memory size '{}', expected e.g. '512M' or '2G'",
+                size
+            ))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("1048576"), Some(1 << 20));
+        assert_eq!(parse_memory_size("512M"), Some(512 << 20));
+        assert_eq!(parse_memory_size("512Mi"), Some(512 << 20));
+        assert_eq!(parse_memory_size("2GB"), Some(2 << 30));
+        assert_eq!(parse_memory_size("1.5g"), Some(3 << 29));
+        assert_eq!(parse_memory_size("64 KiB"), Some(64 << 10));
+        assert_eq!(parse_memory_size("lots"), None);
+        assert_eq!(parse_memory_size("5X"), None);
+    }
+
+    #[test]
+    fn test_deserialize_limits() {
+        let limits: ResourceLimits =
+            serde_yaml::from_str("cpu: 2.0\nmemory: 512M\nnice: 10\n").unwrap();
+        assert_eq!(
+            limits,
+            ResourceLimits {
+                cpu: Some(2.0),
+                memory: Some(512 << 20),
+                nice: Some(10),
+                memory_rlimit: false,
+            }
+        );
+        assert_eq!(limits.check(), None);
+        assert_eq!(
+            limits.docker_args(),
+            vec!["--cpus", "2", "--memory", "536870912"]
+        );
+
+        let limits: ResourceLimits = serde_yaml::from_str("memory: 4096\n").unwrap();
+        assert_eq!(limits.memory, Some(4096));
+
+        assert!(serde_yaml::from_str::<ResourceLimits>("memory: huge\n").is_err());
+        assert!(serde_yaml::from_str::<ResourceLimits>("swap: 1G\n").is_err());
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let invalid = |limits: ResourceLimits| limits.check().is_some();
+        assert!(invalid(ResourceLimits {
+            cpu: Some(0.0),
+            ..Default::default()
+        }));
+        assert!(invalid(ResourceLimits {
+            memory: Some(0),
+            ..Default::default()
+        }));
+        assert!(invalid(ResourceLimits {
+            nice: Some(20),
+            ..Default::default()
+        }));
+        assert!(!invalid(ResourceLimits {
+            nice: Some(-5),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_format_memory_size() {
+        assert_eq!(format_memory_size(512), "512 B");
+        assert_eq!(format_memory_size(512 << 20), "512.0 MiB");
+        assert_eq!(format_memory_size(3 << 29), "1.5 GiB");
+    }
+}
@@ -0,0 +1,213 @@
+// Log Parser - Severity classification of captured service output
+
+use crate::ipc::{LogLevel, LogStream};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A line of service output captured by the daemon, with its severity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessOutputLine {
+    pub service: String,
+    pub line: String,
+    /// `None` when the line carries no recognizable level
+    pub level: Option<LogLevel>,
+    pub timestamp: DateTime<Utc>,
+    /// `None` for lines not read from the process
+    pub stream: Option<LogStream>,
+}
+
+impl ProcessOutputLine {
+    /// Capture `line` now, classifying it with [`parse_log_level`]
+    pub fn new(service: impl Into<String>, line: impl Into<String>) -> Self {
+        let line = line.into();
+        Self {
+            service: service.into(),
+            level: parse_log_level(&line),
+            line,
+            timestamp: Utc::now(),
+            stream: None,
+        }
+    }
+
+    /// Record the stream the line was read from
+    pub fn with_stream(mut self, stream: LogStream) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+}
+
+/// Severity of a line of output, recognizing in order:
+///
+/// - the ROS2 console format, `[WARN] [1700000000.123] [node]: message`
+/// - JSON logs with a `level`, `severity` or `levelname` field (names, or
+///   numeric bunyan/pino levels)
+/// - glog prefixes such as `E0102 15:04:05.000000 ...`
+/// - level keywords: an upper case word such as `ERROR` or `WARNING`
+///   anywhere, or a lower case one opening the line (`warning: ...`)
+pub fn parse_log_level(line: &str) -> Option<LogLevel> {
+    let line = strip_ansi(line);
+    let line = line.trim_start();
+
+    if let Some(caps) = patterns().ros2.captures(line) {
+        return level_from_name(&caps[1]);
+    }
+    if line.starts_with('{') {
+        if let Some(level) = json_level(line) {
+            return Some(level);
+        }
+    }
+    if let Some(caps) = patterns().glog.captures(line) {
+        return level_from_name(&caps[1]);
+    }
+    if let Some(caps) = patterns().prefix.captures(line) {
+        return level_from_name(&caps[1]);
+    }
+    patterns()
+        .keyword
+        .captures(line)
+        .and_then(|caps| level_from_name(&caps[1]))
+}
+
+struct Patterns {
+    ansi: Regex,
+    ros2: Regex,
+    glog: Regex,
+    prefix: Regex,
+    keyword: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        ansi: Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap(),
+        ros2: Regex::new(r"^\[(DEBUG|INFO|WARN|ERROR|FATAL)\]\s*\[\d+\.\d+\]").unwrap(),
+        glog: Regex::new(r"^([IWEF])\d{4} \d{2}:\d{2}:\d{2}").unwrap(),
+        prefix: Regex::new(r"^(?i)(trace|debug|info|warn|warning|error|fatal|critical)\s*[:\]]")
+            .unwrap(),
+        keyword: Regex::new(
+            r"\b(TRACE|DEBUG|INFO|WARN|WARNING|ERROR|ERR|FATAL|CRITICAL|CRIT|PANIC)\b",
+        )
+        .unwrap(),
+    })
+}
+
+fn strip_ansi(line: &str) -> std::borrow::Cow<'_, str> {
+    if line.contains('\x1b') {
+        patterns().ansi.replace_all(line, "")
+    } else {
+        std::borrow::Cow::Borrowed(line)
+    }
+}
+
+/// Map a level name (any case) or glog letter to a level; fatal and
+/// critical count as errors
+fn level_from_name(name: &str) -> Option<LogLevel> {
+    match name.to_ascii_lowercase().as_str() {
+        "trace" => Some(LogLevel::Trace),
+        "debug" | "d" => Some(LogLevel::Debug),
+        "info" | "i" | "notice" => Some(LogLevel::Info),
+        "warn" | "warning" | "w" => Some(LogLevel::Warn),
+        "error" | "err" | "e" | "fatal" | "f" | "critical" | "crit" | "panic" => {
+            Some(LogLevel::Error)
+        }
+        _ => None,
+    }
+}
+
+fn json_level(line: &str) -> Option<LogLevel> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let field = ["level", "severity", "levelname", "lvl"]
+        .iter()
+        .find_map(|key| value.get(key))?;
+
+    match field {
+        serde_json::Value::String(name) => level_from_name(name),
+        // bunyan/pino: 10 trace, 20 debug, 30 info, 40 warn, 50 error, 60 fatal
+        serde_json::Value::Number(n) => match n.as_u64()? {
+            0..=19 => Some(LogLevel::Trace),
+            20..=29 => Some(LogLevel::Debug),
+            30..=39 => Some(LogLevel::Info),
+            40..=49 => Some(LogLevel::Warn),
+            _ => Some(LogLevel::Error),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ros2_format() {
+        let line = "[WARN] [1700000000.123456789] [lidar_driver]: packet dropped";
+        assert_eq!(parse_log_level(line), Some(LogLevel::Warn));
+
+        let colored = "\x1b[31m[ERROR] [1700000000.1] [planner]: no path\x1b[0m";
+        assert_eq!(parse_log_level(colored), Some(LogLevel::Error));
+
+        let fatal = "[FATAL] [1700000000.1] [planner]: abort";
+        assert_eq!(parse_log_level(fatal), Some(LogLevel::Error));
+    }
+
+    #[test]
+    fn test_parse_json_logs() {
+        assert_eq!(
+            parse_log_level(r#"{"level":"debug","msg":"tick"}"#),
+            Some(LogLevel::Debug)
+        );
+        assert_eq!(
+            parse_log_level(r#"{"severity":"WARNING","message":"hot"}"#),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            parse_log_level(r#"{"level":50,"msg":"boom"}"#),
+            Some(LogLevel::Error)
+        );
+        assert_eq!(parse_log_level(r#"{"msg":"no level"}"#), None);
+    }
+
+    #[test]
+    fn test_parse_keywords_and_prefixes() {
+        assert_eq!(
+            parse_log_level("E0102 15:04:05.000000  1234 main.cc:10] failed"),
+            Some(LogLevel::Error)
+        );
+        assert_eq!(
+            parse_log_level("2024-01-01 12:00:00,000 - nav - WARNING - slow"),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            parse_log_level("[2024-01-01T00:00:00Z INFO  my_crate] ready"),
+            Some(LogLevel::Info)
+        );
+        assert_eq!(
+            parse_log_level("warning: unused variable"),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            parse_log_level("error: build failed"),
+            Some(LogLevel::Error)
+        );
+
+        // Lower case words in running text do not count
+        assert_eq!(parse_log_level("finished without error"), None);
+        assert_eq!(parse_log_level("INFORMATION"), None);
+    }
+
+    #[test]
+    fn test_output_line_is_classified() {
+        let output = ProcessOutputLine::new("camera", "[INFO] [1.5] [camera]: streaming");
+        assert_eq!(output.service, "camera");
+        assert_eq!(output.level, Some(LogLevel::Info));
+    }
+
+    #[test]
+    fn test_levels_are_ordered_by_severity() {
+        assert!(LogLevel::Trace < LogLevel::Debug);
+        assert!(LogLevel::Info < LogLevel::Warn);
+        assert!(LogLevel::Warn < LogLevel::Error);
+    }
+}
@@ -0,0 +1,356 @@
+// Maintenance - Cron schedules of a service's maintenance actions, such as
+// nightly restarts of a leaky driver
+
+use crate::ipc::CommandAction;
+use chrono::{
+    DateTime, Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Years searched for the next time a schedule matches, which covers
+/// `0 0 29 2 *` (leap days)
+const SEARCH_YEARS: u64 = 8;
+
+/// A cron expression: minute, hour, day of month, month and day of week,
+/// e.g. `0 3 * * *` for 03:00 every day, or `@daily`, `@hourly`, `@weekly`,
+/// `@monthly`. Fields take `*`, values, ranges (`1-5`), steps (`*/15`) and
+/// lists (`1,15`); days of week run from 0 (Sunday) to 7 (Sunday again).
+/// As in cron, a day matches when either the day of month or the day of
+/// week matches, if both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// The expression as written
+    pub fn as_str(&self) -> &str {
+        &self.expression
+    }
+
+    /// First time after `after`, to the minute, that the schedule matches,
+    /// in the time zone of `after`. Times skipped by a DST change are skipped.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let local = after.naive_local();
+        let start = local.date();
+        // The minute after `after`, as a start within the first day
+        let first = local.hour() * 60 + local.minute() + 1;
+
+        for offset in 0..SEARCH_YEARS * 366 {
+            let date = start.checked_add_days(Days::new(offset))?;
+            if !self.matches_day(date) {
+                continue;
+            }
+            let from = if offset == 0 { first } else { 0 };
+            for minute_of_day in from..24 * 60 {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & (1 << hour) == 0 || self.minutes & (1 << minute) == 0 {
+                    continue;
+                }
+                let Some(time) = date.and_hms_opt(hour, minute, 0) else {
+                    continue;
+                };
+                if let Some(at) = zone.from_local_datetime(&time).earliest() {
+                    if at > *after {
+                        return Some(at);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+/// Bits of the values a cron field selects, and whether it was `*`
+fn parse_field(field: &str, name: &str, min: u32, max: u32) -> Result<(u64, bool), String> {
+    let invalid = || format!("invalid {} '{}', expected {}-{}", name, field, min, max);
+    let value = |text: &str| match text.parse::<u32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(invalid()),
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(invalid()),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (value(first)?, value(last)?),
+                // `5/10` runs from 5 to the end
+                None if part.contains('/') => (value(range)?, max),
+                None => {
+                    let single = value(range)?;
+                    (single, single)
+                }
+            },
+        };
+        if first > last {
+            return Err(invalid());
+        }
+        for selected in (first..=last).step_by(step as usize) {
+            bits |= 1 << selected;
+        }
+    }
+    Ok((bits, field == "*"))
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let expanded = match value.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "invalid cron expression '{}', expected 5 fields (minute hour day month weekday)",
+                value
+            ));
+        };
+
+        let (minutes, _) = parse_field(minute, "minute", 0, 59)?;
+        let (hours, _) = parse_field(hour, "hour", 0, 23)?;
+        let (days, any_day) = parse_field(day, "day of month", 1, 31)?;
+        let (months, _) = parse_field(month, "month", 1, 12)?;
+        let (weekdays, any_weekday) = parse_field(weekday, "day of week", 0, 7)?;
+        // 7 is another Sunday
+        let weekdays = (weekdays | (weekdays >> 7)) & 0x7f;
+
+        Ok(Self {
+            expression: value.trim().to_string(),
+            minutes,
+            hours: hours as u32,
+            days: days as u32,
+            months: months as u16,
+            weekdays: weekdays as u8,
+            any_day,
+            any_weekday,
+        })
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl Serialize for CronSchedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// `schedule:` of a service: cron expressions of the maintenance actions
+/// the daemon applies to it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceSchedule {
+    /// Restart the service, e.g. `0 3 * * *` for every night at 03:00
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<CronSchedule>,
+}
+
+impl MaintenanceSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.restart.is_none()
+    }
+
+    /// Each scheduled action with its schedule
+    pub fn actions(&self) -> Vec<(CommandAction, &CronSchedule)> {
+        self.restart
+            .iter()
+            .map(|cron| (CommandAction::Restart, cron))
+            .collect()
+    }
+}
+
+/// When to apply a maintenance action, relative to `now`: a time of day
+/// (`02:00`, `02:00:30`), its next occurrence; a date and time
+/// (`2026-10-17 02:00`); an RFC 3339 timestamp; or a delay (`+30m`)
+pub fn parse_time<Tz: TimeZone>(value: &str, now: &DateTime<Tz>) -> Result<DateTime<Tz>, String> {
+    let value = value.trim();
+    let zone = now.timezone();
+    let local = |time: NaiveDateTime| {
+        zone.from_local_datetime(&time)
+            .earliest()
+            .ok_or_else(|| format!("'{}' does not exist in the local time zone", value))
+    };
+
+    if let Some(delay) = value.strip_prefix('+') {
+        let delay = humantime_serde::re::humantime::parse_duration(delay)
+            .map_err(|e| format!("invalid delay '{}': {}", value, e))?;
+        let delay = chrono::Duration::from_std(delay)
+            .map_err(|_| format!("delay '{}' is too long", value))?;
+        return Ok(now.clone() + delay);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&zone));
+    }
+    for format in [
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+    ] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return local(time);
+        }
+    }
+    for format in ["%H:%M", "%H:%M:%S"] {
+        if let Ok(time) = NaiveTime::parse_from_str(value, format) {
+            let today = now.naive_local().date();
+            let at = local(today.and_time(time))?;
+            if at > *now {
+                return Ok(at);
+            }
+            let tomorrow = today
+                .succ_opt()
+                .ok_or_else(|| format!("'{}' is out of range", value))?;
+            return local(tomorrow.and_time(time));
+        }
+    }
+    Err(format!(
+        "invalid time '{}', expected HH:MM, YYYY-MM-DD HH:MM, an RFC 3339 timestamp or +DURATION",
+        value
+    ))
+}
+
+/// A maintenance action planned for a service: once (`krill restart --at`)
+/// or the next run of a `schedule:` cron expression
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceAction {
+    pub action: CommandAction,
+    pub at: DateTime<Utc>,
+    /// The cron expression the action repeats on, if it does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+}
+
+/// What happened to a maintenance action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceEvent {
+    /// The action is planned for its time
+    Scheduled,
+    /// The action's time came and it is being applied
+    Due,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn next(cron: &str, after: &str) -> String {
+        let cron: CronSchedule = cron.parse().unwrap();
+        cron.next_after(&at(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn test_next_run_of_cron_expressions() {
+        assert_eq!(next("0 3 * * *", "2026-10-16 02:59"), "2026-10-16 03:00");
+        assert_eq!(next("0 3 * * *", "2026-10-16 03:00"), "2026-10-17 03:00");
+        assert_eq!(next("*/15 * * * *", "2026-10-16 10:07"), "2026-10-16 10:15");
+        assert_eq!(next("30 2 * * 1-5", "2026-10-16 12:00"), "2026-10-19 02:30");
+        assert_eq!(next("0 0 29 2 *", "2026-03-01 00:00"), "2028-02-29 00:00");
+        assert_eq!(next("@weekly", "2026-10-16 12:00"), "2026-10-18 00:00");
+        // Restricted day of month and day of week: either matches
+        assert_eq!(next("0 4 1 * 7", "2026-10-16 12:00"), "2026-10-18 04:00");
+        assert_eq!(
+            next("5,10 8-9 * * *", "2026-10-16 08:06"),
+            "2026-10-16 08:10"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = at("2026-10-16 12:00");
+        let parse = |value| {
+            parse_time(value, &now)
+                .unwrap()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+        assert_eq!(parse("14:30"), "2026-10-16 14:30:00");
+        assert_eq!(parse("02:00"), "2026-10-17 02:00:00");
+        assert_eq!(parse("12:00"), "2026-10-17 12:00:00");
+        assert_eq!(parse("2026-11-01 03:15"), "2026-11-01 03:15:00");
+        assert_eq!(parse("2026-11-01T03:15:30Z"), "2026-11-01 03:15:30");
+        assert_eq!(parse("+90m"), "2026-10-16 13:30:00");
+        assert!(parse_time("tonight", &now).is_err());
+        assert!(parse_time("25:00", &now).is_err());
+    }
+
+    #[test]
+    fn test_invalid_cron_expressions() {
+        for invalid in [
+            "0 3 * *",
+            "60 * * * *",
+            "0 24 * * *",
+            "0 0 0 * *",
+            "*/0 * * * *",
+        ] {
+            assert!(invalid.parse::<CronSchedule>().is_err(), "{}", invalid);
+        }
+        assert!("5-1 * * * *".parse::<CronSchedule>().is_err());
+
+        let schedule: MaintenanceSchedule = serde_yaml::from_str("restart: '0 3 * * *'").unwrap();
+        assert_eq!(schedule.actions()[0].1.as_str(), "0 3 * * *");
+        assert!(serde_yaml::from_str::<MaintenanceSchedule>("restart: 'nightly'").is_err());
+        assert!(serde_yaml::from_str::<MaintenanceSchedule>("stop: '0 3 * * *'").is_err());
+    }
+}
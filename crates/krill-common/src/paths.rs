@@ -0,0 +1,144 @@
+// Paths - Variables in the directories and files a recipe names
+
+use crate::ConfigError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Variable naming the directory of the recipe file
+pub const WORKSPACE_DIR_VAR: &str = "WORKSPACE_DIR";
+
+/// Expands `~` and `${name}` in recipe paths. A name is looked up as
+/// `WORKSPACE_DIR` (the recipe's directory), then in the workspace `env`,
+/// then in the daemon's environment, which provides `${HOME}`; `$${` stays a
+/// literal `${`.
+pub struct PathVars<'a> {
+    workspace_dir: &'a Path,
+    env: &'a HashMap<String, String>,
+}
+
+impl<'a> PathVars<'a> {
+    pub fn new(workspace_dir: &'a Path, env: &'a HashMap<String, String>) -> Self {
+        Self { workspace_dir, env }
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        if name == WORKSPACE_DIR_VAR {
+            return Some(self.workspace_dir.to_string_lossy().into_owned());
+        }
+        self.env
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    /// The path with its variables replaced; an undefined variable is an error
+    pub fn expand(&self, path: &Path) -> Result<PathBuf, ConfigError> {
+        let Some(text) = path.to_str() else {
+            return Ok(path.to_path_buf());
+        };
+        if !text.contains('$') && !text.starts_with('~') {
+            return Ok(path.to_path_buf());
+        }
+
+        let text = match text.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                let home = self
+                    .lookup("HOME")
+                    .ok_or_else(|| ConfigError::HomeNotSet(path.to_path_buf()))?;
+                format!("{}{}", home, rest)
+            }
+            _ => text.to_string(),
+        };
+        let mut undefined = None;
+        crate::template::substitute_str(&text, &mut |name| {
+            self.lookup(name).ok_or_else(|| {
+                undefined = Some(name.to_string());
+                String::new()
+            })
+        })
+        .map(PathBuf::from)
+        .map_err(|reason| match undefined {
+            Some(variable) => ConfigError::UndefinedVariable {
+                variable,
+                path: path.to_path_buf(),
+            },
+            None => ConfigError::InvalidPath {
+                path: path.to_path_buf(),
+                reason,
+            },
+        })
+    }
+
+    /// Expand the path in place, then join it onto `base_dir` if relative
+    pub fn resolve(&self, path: &mut PathBuf, base_dir: Option<&Path>) -> Result<(), ConfigError> {
+        *path = self.expand(path)?;
+        if let Some(base_dir) = base_dir.filter(|_| path.is_relative()) {
+            *path = base_dir.join(&path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_variables() {
+        let env = HashMap::from([("ROBOT".to_string(), "rover".to_string())]);
+        let vars = PathVars::new(Path::new("/robot/recipes"), &env);
+        let home = std::env::var("HOME").unwrap();
+
+        assert_eq!(
+            vars.expand(Path::new("${WORKSPACE_DIR}/../${ROBOT}"))
+                .unwrap(),
+            PathBuf::from("/robot/recipes/../rover")
+        );
+        assert_eq!(
+            vars.expand(Path::new("${HOME}/maps")).unwrap(),
+            Path::new(&home).join("maps")
+        );
+        assert_eq!(
+            vars.expand(Path::new("~/maps")).unwrap(),
+            Path::new(&home).join("maps")
+        );
+        assert_eq!(
+            vars.expand(Path::new("~maps/$${ROBOT}")).unwrap(),
+            PathBuf::from("~maps/${ROBOT}")
+        );
+
+        let err = vars
+            .expand(Path::new("/opt/${KRILL_TEST_UNDEFINED}"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::UndefinedVariable { ref variable, .. } if variable == "KRILL_TEST_UNDEFINED"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "undefined variable 'KRILL_TEST_UNDEFINED' in '/opt/${KRILL_TEST_UNDEFINED}'"
+        );
+
+        let err = vars.expand(Path::new("/opt/${ROBOT")).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidPath { .. }), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_relative_to_base_dir() {
+        let env = HashMap::new();
+        let vars = PathVars::new(Path::new("/robot"), &env);
+
+        let mut path = PathBuf::from("drivers");
+        vars.resolve(&mut path, Some(Path::new("/robot"))).unwrap();
+        assert_eq!(path, PathBuf::from("/robot/drivers"));
+
+        let mut path = PathBuf::from("${WORKSPACE_DIR}/drivers");
+        vars.resolve(&mut path, Some(Path::new("/elsewhere")))
+            .unwrap();
+        assert_eq!(path, PathBuf::from("/robot/drivers"));
+
+        let mut path = PathBuf::from("drivers");
+        vars.resolve(&mut path, None).unwrap();
+        assert_eq!(path, PathBuf::from("drivers"));
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::hash::{BuildHasher, Hasher};
 use std::time::Duration;
 
+/// How long the default stop sequence waits after running the stop command
+const DEFAULT_STOP_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct PolicyConfig {
@@ -12,13 +16,79 @@ pub struct PolicyConfig {
     #[serde(default)]
     pub max_restarts: u32,
 
-    /// Delay between restart attempts
+    /// Delay between restart attempts (base delay for exponential backoff)
     #[serde(with = "humantime_serde", default = "default_restart_delay")]
     pub restart_delay: Duration,
 
+    /// How the restart delay grows with consecutive failures
+    #[serde(default)]
+    pub backoff: BackoffStrategy,
+
+    /// Factor applied to the delay after each consecutive failure
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: f64,
+
+    /// Upper bound for the backoff delay
+    #[serde(with = "humantime_serde", default = "default_max_restart_delay")]
+    pub max_restart_delay: Duration,
+
+    /// Random spread applied to each delay, as a fraction (0.1 = ±10%)
+    #[serde(default)]
+    pub restart_jitter: f64,
+
+    /// Uptime after which a run counts as stable: a failure after it starts
+    /// the restart count, and with it the backoff, over
+    #[serde(with = "humantime_serde", default = "default_stable_after")]
+    pub stable_after: Duration,
+
     /// Timeout for graceful stop before SIGKILL
     #[serde(with = "humantime_serde", default = "default_stop_timeout")]
     pub stop_timeout: Duration,
+
+    /// Ordered graceful stop steps, replacing the default stop command + SIGTERM;
+    /// SIGKILL follows if the service is still running after the last step
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequence: Vec<StopStep>,
+
+    /// Signal sent to the service's main process by a `reload` command
+    #[serde(default)]
+    pub reload_signal: ReloadSignal,
+
+    /// Send `reload_signal` to the service's whole process group instead,
+    /// for services whose workers each reload on their own
+    #[serde(default)]
+    pub reload_group: bool,
+}
+
+/// Signal asking a service to reload its configuration in place
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReloadSignal {
+    #[default]
+    Sighup,
+    Sigusr1,
+    Sigusr2,
+}
+
+/// One step of a stop sequence: perform `action`, then wait up to `timeout`
+/// for the service to exit before moving on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopStep {
+    pub action: StopAction,
+
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopAction {
+    /// Run the service's `stop_command` or `stop_task`
+    StopCommand,
+    Sigint,
+    Sigterm,
+    Sighup,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -29,17 +99,122 @@ pub enum RestartPolicy {
     Never,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackoffStrategy {
+    /// Always wait `restart_delay`
+    #[default]
+    Fixed,
+    /// Multiply `restart_delay` by `backoff_multiplier` per consecutive failure
+    Exponential,
+}
+
 impl Default for PolicyConfig {
     fn default() -> Self {
         Self {
             restart: default_restart(),
             max_restarts: 0,
             restart_delay: default_restart_delay(),
+            backoff: BackoffStrategy::default(),
+            backoff_multiplier: default_backoff_multiplier(),
+            max_restart_delay: default_max_restart_delay(),
+            restart_jitter: 0.0,
+            stable_after: default_stable_after(),
             stop_timeout: default_stop_timeout(),
+            stop_sequence: Vec::new(),
+            reload_signal: ReloadSignal::default(),
+            reload_group: false,
+        }
+    }
+}
+
+impl PolicyConfig {
+    /// Steps used to stop the service gracefully. Without a configured
+    /// `stop_sequence` this runs the stop command (if any) for up to 5s,
+    /// then sends SIGTERM and waits `stop_timeout`
+    pub fn stop_steps(&self, has_stop_command: bool) -> Vec<StopStep> {
+        if !self.stop_sequence.is_empty() {
+            return self.stop_sequence.clone();
+        }
+
+        let mut steps = Vec::new();
+        if has_stop_command {
+            steps.push(StopStep {
+                action: StopAction::StopCommand,
+                timeout: DEFAULT_STOP_COMMAND_TIMEOUT,
+            });
+        }
+        steps.push(StopStep {
+            action: StopAction::Sigterm,
+            timeout: self.stop_timeout,
+        });
+        steps
+    }
+
+    /// Delay before the restart following `failures` consecutive failures, without jitter
+    pub fn backoff_delay(&self, failures: u32) -> Duration {
+        match self.backoff {
+            BackoffStrategy::Fixed => self.restart_delay,
+            BackoffStrategy::Exponential => {
+                let exponent = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+                let factor = self.backoff_multiplier.max(1.0).powi(exponent);
+                let secs = self.restart_delay.as_secs_f64() * factor;
+                let max = self.max_restart_delay.max(self.restart_delay);
+                if secs.is_finite() && secs < max.as_secs_f64() {
+                    Duration::from_secs_f64(secs)
+                } else {
+                    max
+                }
+            }
+        }
+    }
+
+    /// Delay before the restart following `failures` consecutive failures, with jitter applied
+    pub fn restart_delay_for(&self, failures: u32) -> Duration {
+        let delay = self.backoff_delay(failures);
+        let jitter = self.restart_jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+
+        // Uniform in [-1, 1); RandomState is seeded randomly per instance
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let spread = (random as f64 / u64::MAX as f64) * 2.0 - 1.0;
+        delay.mul_f64((1.0 + spread * jitter).max(0.0))
+    }
+}
+
+/// `restart_storm:` of a recipe, a circuit breaker over the whole workspace:
+/// more than `max_failures` service failures within `window` pause all
+/// automatic restarts until an operator resumes them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RestartStormConfig {
+    pub max_failures: u32,
+    #[serde(with = "humantime_serde", default = "default_storm_window")]
+    pub window: Duration,
+}
+
+impl RestartStormConfig {
+    /// Reject a zero `max_failures`, which would pause restarts on the first
+    /// failure, and an empty `window`
+    pub fn check(&self) -> Option<String> {
+        if self.max_failures == 0 {
+            Some("max_failures must be at least 1".to_string())
+        } else if self.window.is_zero() {
+            Some("window must be longer than 0s".to_string())
+        } else {
+            None
         }
     }
 }
 
+fn default_storm_window() -> Duration {
+    Duration::from_secs(60)
+}
+
 fn default_restart() -> RestartPolicy {
     RestartPolicy::OnFailure
 }
@@ -48,6 +223,18 @@ fn default_restart_delay() -> Duration {
     Duration::from_secs(5)
 }
 
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_restart_delay() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_stable_after() -> Duration {
+    Duration::from_secs(60)
+}
+
 fn default_stop_timeout() -> Duration {
     Duration::from_secs(10)
 }
@@ -77,6 +264,7 @@ stop_timeout: 30s
         assert_eq!(policy.max_restarts, 3);
         assert_eq!(policy.restart_delay, Duration::from_secs(10));
         assert_eq!(policy.stop_timeout, Duration::from_secs(30));
+        assert_eq!(policy.reload_signal, ReloadSignal::Sighup);
     }
 
     #[test]
@@ -85,11 +273,103 @@ stop_timeout: 30s
             restart: RestartPolicy::Never,
             max_restarts: 5,
             restart_delay: Duration::from_secs(15),
+            backoff: BackoffStrategy::Exponential,
+            backoff_multiplier: 1.5,
+            max_restart_delay: Duration::from_secs(120),
+            restart_jitter: 0.2,
+            stable_after: Duration::from_secs(300),
             stop_timeout: Duration::from_secs(20),
+            stop_sequence: vec![StopStep {
+                action: StopAction::Sigint,
+                timeout: Duration::from_secs(3),
+            }],
+            reload_signal: ReloadSignal::Sigusr1,
+            reload_group: true,
         };
 
         let yaml = serde_yaml::to_string(&policy).unwrap();
         let deserialized: PolicyConfig = serde_yaml::from_str(&yaml).unwrap();
         assert_eq!(policy, deserialized);
     }
+
+    #[test]
+    fn test_fixed_backoff_ignores_failures() {
+        let policy = PolicyConfig::default();
+        assert_eq!(policy.backoff, BackoffStrategy::Fixed);
+        assert_eq!(policy.restart_delay_for(1), Duration::from_secs(5));
+        assert_eq!(policy.restart_delay_for(10), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let yaml = r#"
+restart: always
+restart_delay: 1s
+backoff: exponential
+backoff_multiplier: 2.0
+max_restart_delay: 30s
+"#;
+        let policy: PolicyConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(policy.backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(policy.backoff_delay(6), Duration::from_secs(30));
+        assert_eq!(policy.backoff_delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_restart_jitter_stays_in_bounds() {
+        let policy = PolicyConfig {
+            restart_delay: Duration::from_secs(10),
+            restart_jitter: 0.5,
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.restart_delay_for(1);
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(15));
+        }
+    }
+
+    #[test]
+    fn test_default_stop_steps() {
+        let policy = PolicyConfig {
+            stop_timeout: Duration::from_secs(20),
+            ..Default::default()
+        };
+
+        let steps = policy.stop_steps(true);
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].action, StopAction::StopCommand);
+        assert_eq!(steps[1].action, StopAction::Sigterm);
+        assert_eq!(steps[1].timeout, Duration::from_secs(20));
+
+        let steps = policy.stop_steps(false);
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].action, StopAction::Sigterm);
+    }
+
+    #[test]
+    fn test_custom_stop_sequence() {
+        let yaml = r#"
+stop_sequence:
+  - action: sigint
+    timeout: 5s
+  - action: stop-command
+    timeout: 10s
+  - action: sigterm
+    timeout: 2s
+"#;
+        let policy: PolicyConfig = serde_yaml::from_str(yaml).unwrap();
+        let steps = policy.stop_steps(true);
+        assert_eq!(
+            steps.iter().map(|s| s.action).collect::<Vec<_>>(),
+            vec![
+                StopAction::Sigint,
+                StopAction::StopCommand,
+                StopAction::Sigterm
+            ]
+        );
+        assert_eq!(steps[1].timeout, Duration::from_secs(10));
+    }
 }
@@ -0,0 +1,242 @@
+// Ports - Ports a service binds, checked before it is spawned
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::net::{Ipv4Addr, TcpListener, UdpSocket};
+use std::str::FromStr;
+
+/// Transport protocol of a port
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for PortProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortProtocol::Tcp => write!(f, "tcp"),
+            PortProtocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// An entry of `requires_ports:`, written as `8080`, `8080/tcp` or `9090/udp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PortSpec {
+    pub port: u16,
+    pub protocol: PortProtocol,
+}
+
+impl fmt::Display for PortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.port, self.protocol)
+    }
+}
+
+impl FromStr for PortSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (port, protocol) = match value.split_once('/') {
+            Some((port, protocol)) => (port, protocol),
+            None => (value, "tcp"),
+        };
+        let protocol = match protocol.to_ascii_lowercase().as_str() {
+            "tcp" => PortProtocol::Tcp,
+            "udp" => PortProtocol::Udp,
+            other => return Err(format!("unknown protocol '{}' in port '{}'", other, value)),
+        };
+        match port.trim().parse::<u16>() {
+            Ok(port) if port > 0 => Ok(Self { port, protocol }),
+            _ => Err(format!("invalid port '{}', expected 1-65535", value)),
+        }
+    }
+}
+
+impl Serialize for PortSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.protocol {
+            PortProtocol::Tcp => serializer.serialize_u16(self.port),
+            PortProtocol::Udp => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PortSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        let value = match Raw::deserialize(deserializer)? {
+            Raw::Number(port) => port.to_string(),
+            Raw::Text(value) => value,
+        };
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Process holding a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// A required port that is already bound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortConflict {
+    pub port: PortSpec,
+    /// Known when the process is visible to the daemon (Linux, same user or root)
+    pub owner: Option<PortOwner>,
+}
+
+impl fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port {} is already in use", self.port)?;
+        if let Some(ref owner) = self.owner {
+            write!(f, " by PID {} ({})", owner.pid, owner.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// The first of `ports` that another socket is bound to, if any, with the
+/// process holding it
+pub fn find_port_conflict(ports: &[PortSpec]) -> Option<PortConflict> {
+    find_busy_port(ports).map(|port| PortConflict {
+        port,
+        owner: port_owner(&port),
+    })
+}
+
+/// The first of `ports` that another socket is bound to, if any. Unlike
+/// [`find_port_conflict`] this does not walk /proc for its owner.
+pub fn find_busy_port(ports: &[PortSpec]) -> Option<PortSpec> {
+    ports.iter().find(|port| !is_port_free(port)).copied()
+}
+
+/// Whether the port can be bound on all interfaces; the probe socket is
+/// closed again right away
+fn is_port_free(port: &PortSpec) -> bool {
+    let addr = (Ipv4Addr::UNSPECIFIED, port.port);
+    match port.protocol {
+        PortProtocol::Tcp => TcpListener::bind(addr).is_ok(),
+        PortProtocol::Udp => UdpSocket::bind(addr).is_ok(),
+    }
+}
+
+/// Look the socket up in /proc/net, then find the process holding it
+#[cfg(target_os = "linux")]
+fn port_owner(port: &PortSpec) -> Option<PortOwner> {
+    let tables: &[&str] = match port.protocol {
+        PortProtocol::Tcp => &["/proc/net/tcp", "/proc/net/tcp6"],
+        PortProtocol::Udp => &["/proc/net/udp", "/proc/net/udp6"],
+    };
+    let inode = tables.iter().find_map(|table| {
+        let contents = std::fs::read_to_string(table).ok()?;
+        socket_inode(&contents, port)
+    })?;
+    let target = format!("socket:[{}]", inode);
+
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let holds_socket = fds.flatten().any(|fd| {
+            std::fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == target.as_str())
+        });
+        if holds_socket {
+            let name = std::fs::read_to_string(entry.path().join("comm"))
+                .map(|comm| comm.trim().to_string())
+                .unwrap_or_default();
+            return Some(PortOwner { pid, name });
+        }
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn port_owner(_port: &PortSpec) -> Option<PortOwner> {
+    None
+}
+
+/// Inode of the socket bound to the port in a /proc/net table; TCP sockets
+/// only count while listening
+#[cfg(target_os = "linux")]
+fn socket_inode(table: &str, port: &PortSpec) -> Option<u64> {
+    const TCP_LISTEN: &str = "0A";
+
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let local_port = fields.get(1)?.rsplit_once(':')?.1;
+        if u16::from_str_radix(local_port, 16).ok()? != port.port {
+            return None;
+        }
+        if port.protocol == PortProtocol::Tcp && *fields.get(3)? != TCP_LISTEN {
+            return None;
+        }
+        fields.get(9)?.parse().ok().filter(|inode| *inode != 0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_spec() {
+        let ports: Vec<PortSpec> = serde_yaml::from_str("[8080, 9090/udp, 443/TCP]").unwrap();
+        assert_eq!(
+            ports,
+            vec![
+                PortSpec {
+                    port: 8080,
+                    protocol: PortProtocol::Tcp
+                },
+                PortSpec {
+                    port: 9090,
+                    protocol: PortProtocol::Udp
+                },
+                PortSpec {
+                    port: 443,
+                    protocol: PortProtocol::Tcp
+                },
+            ]
+        );
+        assert_eq!(serde_yaml::to_string(&ports[1]).unwrap().trim(), "9090/udp");
+
+        assert!(serde_yaml::from_str::<PortSpec>("0").is_err());
+        assert!(serde_yaml::from_str::<PortSpec>("70000").is_err());
+        assert!(serde_yaml::from_str::<PortSpec>("80/sctp").is_err());
+    }
+
+    #[test]
+    fn test_find_port_conflict() {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = PortSpec {
+            port: listener.local_addr().unwrap().port(),
+            protocol: PortProtocol::Tcp,
+        };
+
+        let conflict = find_port_conflict(&[port]).unwrap();
+        assert_eq!(conflict.port, port);
+        #[cfg(target_os = "linux")]
+        assert_eq!(conflict.owner.unwrap().pid, std::process::id());
+
+        drop(listener);
+        assert_eq!(find_port_conflict(&[port]), None);
+    }
+}
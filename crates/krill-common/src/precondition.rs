@@ -0,0 +1,193 @@
+// Preconditions - Conditions outside the recipe a service waits for before
+// it is spawned, e.g. a device node that udev creates
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a `tcp` precondition waits for each connection attempt
+pub const TCP_PRECONDITION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An entry of `wait_for:`, a single `kind: value` pair such as
+/// `device: /dev/ttyUSB0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// A file or directory exists
+    File(PathBuf),
+    /// A character or block device node exists
+    Device(PathBuf),
+    /// A TCP endpoint (`host:port`) accepts connections
+    Tcp(String),
+    /// An environment variable is set and not empty
+    Env(String),
+}
+
+impl fmt::Display for Precondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Precondition::File(path) => write!(f, "file {}", path.display()),
+            Precondition::Device(path) => write!(f, "device {}", path.display()),
+            Precondition::Tcp(address) => write!(f, "tcp {}", address),
+            Precondition::Env(name) => write!(f, "env {}", name),
+        }
+    }
+}
+
+impl Serialize for Precondition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Precondition::File(path) => map.serialize_entry("file", path)?,
+            Precondition::Device(path) => map.serialize_entry("device", path)?,
+            Precondition::Tcp(address) => map.serialize_entry("tcp", address)?,
+            Precondition::Env(name) => map.serialize_entry("env", name)?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Precondition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entry = BTreeMap::<String, String>::deserialize(deserializer)?;
+        let mut pairs = entry.into_iter();
+        let (Some((kind, value)), None) = (pairs.next(), pairs.next()) else {
+            return Err(serde::de::Error::custom(
+                "expected a single condition such as `device: /dev/ttyUSB0`",
+            ));
+        };
+        match kind.as_str() {
+            "file" => Ok(Precondition::File(value.into())),
+            "device" => Ok(Precondition::Device(value.into())),
+            "tcp" => Ok(Precondition::Tcp(value)),
+            "env" => Ok(Precondition::Env(value)),
+            other => Err(serde::de::Error::custom(format!(
+                "unknown condition '{}', expected file, device, tcp or env",
+                other
+            ))),
+        }
+    }
+}
+
+impl Precondition {
+    /// Problem with the entry itself, one no wait can fix
+    pub fn check(&self) -> Option<String> {
+        match self {
+            Precondition::File(path) | Precondition::Device(path)
+                if path.as_os_str().is_empty() =>
+            {
+                Some(format!("{} has an empty path", self))
+            }
+            Precondition::Tcp(address) => match address.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => None,
+                _ => Some(format!(
+                    "tcp '{}' is not a host:port address, e.g. 192.168.1.201:2368",
+                    address
+                )),
+            },
+            Precondition::Env(name) if name.is_empty() || name.contains('=') => {
+                Some(format!("env '{}' is not a variable name", name))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the condition holds now. `env` is the service's environment;
+    /// variables missing there are looked up in the daemon's. A `tcp`
+    /// condition blocks for up to `TCP_PRECONDITION_TIMEOUT` per address.
+    pub fn is_met(&self, env: &HashMap<String, String>) -> bool {
+        match self {
+            Precondition::File(path) => path.exists(),
+            Precondition::Device(path) => is_device(path),
+            Precondition::Tcp(address) => address
+                .to_socket_addrs()
+                .map(|mut addrs| {
+                    addrs.any(|addr| {
+                        TcpStream::connect_timeout(&addr, TCP_PRECONDITION_TIMEOUT).is_ok()
+                    })
+                })
+                .unwrap_or(false),
+            Precondition::Env(name) => env
+                .get(name)
+                .cloned()
+                .or_else(|| std::env::var(name).ok())
+                .is_some_and(|value| !value.is_empty()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_device(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|meta| {
+        let file_type = meta.file_type();
+        file_type.is_char_device() || file_type.is_block_device()
+    })
+}
+
+#[cfg(not(unix))]
+fn is_device(path: &std::path::Path) -> bool {
+    path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_preconditions() {
+        let yaml = r#"
+- device: /dev/ttyUSB0
+- file: /var/run/calibrated
+- tcp: 192.168.1.201:2368
+- env: ROS_DOMAIN_ID
+"#;
+        let conditions: Vec<Precondition> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            conditions,
+            vec![
+                Precondition::Device("/dev/ttyUSB0".into()),
+                Precondition::File("/var/run/calibrated".into()),
+                Precondition::Tcp("192.168.1.201:2368".to_string()),
+                Precondition::Env("ROS_DOMAIN_ID".to_string()),
+            ]
+        );
+        assert!(conditions.iter().all(|c| c.check().is_none()));
+
+        assert!(Precondition::Tcp("lidar.local".to_string())
+            .check()
+            .is_some());
+        assert!(Precondition::Env(String::new()).check().is_some());
+        assert!(serde_yaml::from_str::<Precondition>("socket: /tmp/x").is_err());
+        assert!(serde_yaml::from_str::<Precondition>("{file: /a, env: B}").is_err());
+
+        let yaml = serde_yaml::to_string(&conditions[0]).unwrap();
+        assert_eq!(yaml.trim(), "device: /dev/ttyUSB0");
+    }
+
+    #[test]
+    fn test_preconditions_met() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("ready");
+        assert!(!Precondition::File(file.clone()).is_met(&HashMap::new()));
+        std::fs::write(&file, "").unwrap();
+        assert!(Precondition::File(file.clone()).is_met(&HashMap::new()));
+        // A regular file is no device node
+        assert!(!Precondition::Device(file).is_met(&HashMap::new()));
+        #[cfg(unix)]
+        assert!(Precondition::Device("/dev/null".into()).is_met(&HashMap::new()));
+
+        let env = HashMap::from([("KRILL_TEST_READY".to_string(), "1".to_string())]);
+        assert!(Precondition::Env("KRILL_TEST_READY".to_string()).is_met(&env));
+        assert!(!Precondition::Env("KRILL_TEST_UNSET_VARIABLE".to_string()).is_met(&env));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(Precondition::Tcp(address.clone()).is_met(&env));
+        drop(listener);
+        assert!(!Precondition::Tcp(address).is_met(&env));
+    }
+}
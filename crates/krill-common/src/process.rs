@@ -1,7 +1,9 @@
-use crate::execute::ExecuteConfig;
+use crate::execute::{ContainerEngine, CustomExecute, ExecuteConfig, PortMapping, VolumeMount};
+use crate::executor;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command as StdCommand;
+use std::time::Duration;
 use thiserror::Error;
 
 #[cfg(unix)]
@@ -24,19 +26,22 @@ pub enum ProcessError {
 
 /// Find the full path to an executable using which/where
 pub fn find_executable(program: &str) -> Result<String, ProcessError> {
-    // If it's already an absolute path or contains a slash, use it directly
-    if program.starts_with('/') || program.contains('/') {
+    // If it's already an absolute path or contains a separator, use it directly
+    if program.starts_with('/') || program.contains('/') || program.contains('\\') {
         return Ok(program.to_string());
     }
 
-    // Try to find it using 'which' command
-    let output = StdCommand::new("which")
+    // Try to find it using 'which' ('where' on Windows)
+    let finder = if cfg!(windows) { "where" } else { "which" };
+    let output = StdCommand::new(finder)
         .arg(program)
         .output()
-        .map_err(|e| ProcessError::CommandNotFound(format!("Failed to run 'which': {}", e)))?;
+        .map_err(|e| ProcessError::CommandNotFound(format!("Failed to run '{}': {}", finder, e)))?;
 
     if output.status.success() {
-        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        // 'where' lists every match, one per line
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let path = stdout.lines().next().unwrap_or("").trim().to_string();
         if !path.is_empty() {
             return Ok(path);
         }
@@ -123,7 +128,7 @@ pub fn build_command(
 
         ExecuteConfig::Shell { command, .. } => {
             // Use shell to execute the command
-            Ok(vec!["sh".to_string(), "-c".to_string(), command.clone()])
+            Ok(shell_command(command))
         }
 
         ExecuteConfig::Docker {
@@ -132,59 +137,283 @@ pub fn build_command(
             ports,
             privileged,
             network,
+        } => Ok(container_run_command(
+            ContainerEngine::Docker,
+            ContainerRun {
+                image,
+                volumes,
+                ports,
+                privileged: *privileged,
+                network: network.as_deref(),
+                userns: None,
+            },
+            env_vars,
+        )),
+
+        ExecuteConfig::Podman {
+            image,
+            volumes,
+            ports,
+            privileged,
+            network,
+            userns,
+        } => Ok(container_run_command(
+            ContainerEngine::Podman,
+            ContainerRun {
+                image,
+                volumes,
+                ports,
+                privileged: *privileged,
+                network: network.as_deref(),
+                userns: userns.as_deref(),
+            },
+            env_vars,
+        )),
+
+        ExecuteConfig::Compose {
+            file,
+            services,
+            exit_code_from,
+            engine,
+            ..
         } => {
-            let mut cmd = vec!["docker".to_string(), "run".to_string()];
-
-            // Add volume mounts
-            for volume in volumes {
-                let mount_spec = if volume.read_only() {
-                    format!(
-                        "{}:{}:ro",
-                        volume.host().display(),
-                        volume.container().display()
-                    )
-                } else {
-                    format!(
-                        "{}:{}",
-                        volume.host().display(),
-                        volume.container().display()
-                    )
-                };
-                cmd.push("-v".to_string());
-                cmd.push(mount_spec);
+            let mut cmd = compose_command(*engine, file);
+            cmd.push("up".to_string());
+            // Stop the whole project as soon as one container exits, so krill sees it
+            cmd.push("--abort-on-container-exit".to_string());
+            if let Some(service) = exit_code_from {
+                cmd.push("--exit-code-from".to_string());
+                cmd.push(service.clone());
             }
+            cmd.extend(services.iter().cloned());
+            Ok(cmd)
+        }
 
-            // Add port mappings
-            for port in ports {
-                cmd.push("-p".to_string());
-                cmd.push(format!(
-                    "{}:{}/{}",
-                    port.host, port.container, port.protocol
-                ));
-            }
+        ExecuteConfig::Custom(custom) => {
+            registered_executor(custom)?.build_command(&custom.options, env_vars)
+        }
+    }
+}
 
-            // Add privileged flag
-            if *privileged {
-                cmd.push("--privileged".to_string());
-            }
+fn registered_executor(
+    custom: &CustomExecute,
+) -> Result<std::sync::Arc<dyn executor::Executor>, ProcessError> {
+    executor::executor(&custom.kind).ok_or_else(|| {
+        ProcessError::BuildFailed(format!("No executor registered for type '{}'", custom.kind))
+    })
+}
 
-            // Add network mode
-            if let Some(net) = network {
-                cmd.push("--network".to_string());
-                cmd.push(net.clone());
-            }
+/// Options of a `docker run` or `podman run`
+struct ContainerRun<'a> {
+    image: &'a str,
+    volumes: &'a [VolumeMount],
+    ports: &'a [PortMapping],
+    privileged: bool,
+    network: Option<&'a str>,
+    userns: Option<&'a str>,
+}
 
-            // Add environment variables
-            for (key, value) in env_vars {
-                cmd.push("-e".to_string());
-                cmd.push(format!("{}={}", key, value));
-            }
+fn container_run_command(
+    engine: ContainerEngine,
+    run: ContainerRun,
+    env_vars: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut cmd = vec![engine.program().to_string(), "run".to_string()];
+
+    // Add volume mounts
+    for volume in run.volumes {
+        let mount_spec = if volume.read_only() {
+            format!(
+                "{}:{}:ro",
+                volume.host().display(),
+                volume.container().display()
+            )
+        } else {
+            format!(
+                "{}:{}",
+                volume.host().display(),
+                volume.container().display()
+            )
+        };
+        cmd.push("-v".to_string());
+        cmd.push(mount_spec);
+    }
 
-            // Add image name
-            cmd.push(image.clone());
+    // Add port mappings
+    for port in run.ports {
+        cmd.push("-p".to_string());
+        cmd.push(format!(
+            "{}:{}/{}",
+            port.host, port.container, port.protocol
+        ));
+    }
 
-            Ok(cmd)
+    // Add privileged flag
+    if run.privileged {
+        cmd.push("--privileged".to_string());
+    }
+
+    // Add network mode
+    if let Some(net) = run.network {
+        cmd.push("--network".to_string());
+        cmd.push(net.to_string());
+    }
+
+    // Add user namespace mode
+    if let Some(userns) = run.userns {
+        cmd.push("--userns".to_string());
+        cmd.push(userns.to_string());
+    }
+
+    // Add environment variables by name: the engine takes their values from
+    // its own environment, so none (secrets included) show up in its arguments
+    for key in env_vars.keys() {
+        cmd.push("-e".to_string());
+        cmd.push(key.clone());
+    }
+
+    // Add image name
+    cmd.push(run.image.to_string());
+
+    cmd
+}
+
+/// `<engine> compose -f <file>`
+fn compose_command(engine: ContainerEngine, file: &Path) -> Vec<String> {
+    vec![
+        engine.program().to_string(),
+        "compose".to_string(),
+        "-f".to_string(),
+        file.display().to_string(),
+    ]
+}
+
+/// Container name of a container service, or project name of a compose
+/// service; deterministic so it can be stopped, removed, and inspected by name
+pub fn container_name(workspace_name: &str, service_name: &str) -> String {
+    format!("krill-{}-{}", workspace_name, service_name)
+}
+
+/// `build_command` for a container service, with the container (or compose
+/// project) named `container_name`
+pub fn build_container_command(
+    config: &ExecuteConfig,
+    env_vars: &HashMap<String, String>,
+    container_name: &str,
+) -> Result<Vec<String>, ProcessError> {
+    let name_option = match config {
+        ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. } => "--name",
+        ExecuteConfig::Compose { .. } => "-p",
+        _ => {
+            return Err(ProcessError::BuildFailed(
+                "Not a container execute config".to_string(),
+            ))
         }
+    };
+
+    let mut cmd = build_command(config, env_vars)?;
+    // Right after `docker run` or `docker compose`
+    cmd.splice(2..2, [name_option.to_string(), container_name.to_string()]);
+    Ok(cmd)
+}
+
+impl ContainerEngine {
+    /// `docker stop`, letting the container shut down for `timeout` before the engine kills it
+    pub fn stop_command(self, container_name: &str, timeout: Duration) -> Vec<String> {
+        vec![
+            self.program().to_string(),
+            "stop".to_string(),
+            "-t".to_string(),
+            timeout.as_secs().to_string(),
+            container_name.to_string(),
+        ]
+    }
+
+    /// `docker rm -f`, removing the container whether or not it still runs
+    pub fn remove_command(self, container_name: &str) -> Vec<String> {
+        vec![
+            self.program().to_string(),
+            "rm".to_string(),
+            "-f".to_string(),
+            container_name.to_string(),
+        ]
+    }
+
+    /// `docker inspect` printing the container's health status, or nothing if
+    /// the image defines no HEALTHCHECK
+    pub fn health_command(self, container_name: &str) -> Vec<String> {
+        vec![
+            self.program().to_string(),
+            "inspect".to_string(),
+            "--format".to_string(),
+            "{{if .State.Health}}{{.State.Health.Status}}{{end}}".to_string(),
+            container_name.to_string(),
+        ]
+    }
+}
+
+/// `docker compose down` of a compose service's project, giving its
+/// containers `timeout` to stop and removing them; `None` for other services
+pub fn compose_down_command(
+    config: &ExecuteConfig,
+    project: &str,
+    timeout: Duration,
+) -> Option<Vec<String>> {
+    let ExecuteConfig::Compose { file, engine, .. } = config else {
+        return None;
+    };
+    let mut cmd = compose_command(*engine, file);
+    cmd.extend([
+        "-p".to_string(),
+        project.to_string(),
+        "down".to_string(),
+        "-t".to_string(),
+        timeout.as_secs().to_string(),
+    ]);
+    Some(cmd)
+}
+
+/// `docker pause` (or `unpause` to resume) of a container service, or
+/// `docker compose pause` of a compose service's project; `None` for other
+/// services
+pub fn container_pause_command(
+    config: &ExecuteConfig,
+    container: &str,
+    pause: bool,
+) -> Option<Vec<String>> {
+    let verb = if pause { "pause" } else { "unpause" };
+    if let ExecuteConfig::Compose { file, engine, .. } = config {
+        let mut cmd = compose_command(*engine, file);
+        cmd.extend(["-p".to_string(), container.to_string(), verb.to_string()]);
+        return Some(cmd);
+    }
+    let engine = config.container_engine()?;
+    Some(vec![
+        engine.program().to_string(),
+        verb.to_string(),
+        container.to_string(),
+    ])
+}
+
+/// Why a container service exited with `code`. The engine CLI passes the
+/// container's exit code through, but reserves 125-127 for its own failures.
+pub fn container_exit_reason(code: i32) -> String {
+    match code {
+        125 => "Container engine failed to run the container (exit code 125)".to_string(),
+        126 => "Container command cannot be invoked (exit code 126)".to_string(),
+        127 => "Container command not found (exit code 127)".to_string(),
+        137 => "Container was killed (exit code 137, SIGKILL or out of memory)".to_string(),
+        code => format!("Container exited with code {}", code),
+    }
+}
+
+/// Interpret `ContainerEngine::health_command` output: `Some(true)` for healthy,
+/// `Some(false)` for unhealthy, `None` while starting or without a HEALTHCHECK
+pub fn parse_container_health(output: &str) -> Option<bool> {
+    match output.trim() {
+        "healthy" => Some(true),
+        "unhealthy" => Some(false),
+        _ => None,
     }
 }
 
@@ -194,7 +423,11 @@ pub fn get_working_dir(config: &ExecuteConfig) -> Option<PathBuf> {
         ExecuteConfig::Pixi { working_dir, .. } => working_dir.clone(),
         ExecuteConfig::Ros2 { working_dir, .. } => working_dir.clone(),
         ExecuteConfig::Shell { working_dir, .. } => working_dir.clone(),
-        ExecuteConfig::Docker { .. } => None, // Docker handles working dir internally
+        ExecuteConfig::Compose { working_dir, .. } => working_dir.clone(),
+        // Containers handle the working dir internally
+        ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. } => None,
+        ExecuteConfig::Custom(custom) => executor::executor(&custom.kind)
+            .and_then(|executor| executor.working_dir(&custom.options)),
     }
 }
 
@@ -222,23 +455,40 @@ pub fn get_stop_command(config: &ExecuteConfig) -> Option<Vec<String>> {
             ..
         } => {
             // Assume stop task is a simple command
-            Some(vec!["sh".to_string(), "-c".to_string(), task.clone()])
+            Some(shell_command(task))
         }
 
         ExecuteConfig::Shell {
             stop_command: Some(cmd),
             ..
-        } => Some(vec!["sh".to_string(), "-c".to_string(), cmd.clone()]),
+        } => Some(shell_command(cmd)),
+
+        ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. } => {
+            // Containers are stopped by name, see `ContainerEngine::stop_command`
+            None
+        }
 
-        ExecuteConfig::Docker { .. } => {
-            // Docker stop is handled by container name/ID
+        ExecuteConfig::Compose { .. } => {
+            // Compose projects are stopped by name, see `compose_down_command`
             None
         }
 
+        ExecuteConfig::Custom(custom) => executor::executor(&custom.kind)
+            .and_then(|executor| executor.stop_command(&custom.options)),
+
         _ => None,
     }
 }
 
+/// Wrap a command line in the platform shell (`sh -c`, or `cmd /C` on Windows)
+pub fn shell_command(command: &str) -> Vec<String> {
+    if cfg!(windows) {
+        vec!["cmd".to_string(), "/C".to_string(), command.to_string()]
+    } else {
+        vec!["sh".to_string(), "-c".to_string(), command.to_string()]
+    }
+}
+
 /// Set up a new process group for the given process ID
 #[cfg(unix)]
 pub fn setup_process_group(pid: u32) -> Result<(), ProcessError> {
@@ -298,6 +548,96 @@ pub fn get_process_group(_pid: u32) -> Result<u32, ProcessError> {
     ))
 }
 
+/// Windows Job Object grouping a service's process tree, the counterpart of a
+/// Unix process group. Processes in the job are killed when it is dropped.
+#[cfg(windows)]
+pub struct JobObject {
+    handle: windows_sys::Win32::Foundation::HANDLE,
+}
+
+// Job handles may be used from any thread
+#[cfg(windows)]
+unsafe impl Send for JobObject {}
+#[cfg(windows)]
+unsafe impl Sync for JobObject {}
+
+#[cfg(windows)]
+impl JobObject {
+    pub fn new() -> Result<Self, ProcessError> {
+        use windows_sys::Win32::System::JobObjects::{
+            CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        };
+
+        let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if handle.is_null() {
+            return Err(last_os_error("Failed to create job object"));
+        }
+        let job = Self { handle };
+
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let ok = unsafe {
+            SetInformationJobObject(
+                job.handle,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const std::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if ok == 0 {
+            return Err(last_os_error("Failed to configure job object"));
+        }
+
+        Ok(job)
+    }
+
+    /// Add a process (and its future children) to the job
+    pub fn assign(&self, pid: u32) -> Result<(), ProcessError> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::JobObjects::AssignProcessToJobObject;
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+        };
+
+        let process = unsafe { OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid) };
+        if process.is_null() {
+            return Err(last_os_error("Failed to open process"));
+        }
+
+        let ok = unsafe { AssignProcessToJobObject(self.handle, process) };
+        let result = if ok == 0 {
+            Err(last_os_error("Failed to assign process to job object"))
+        } else {
+            Ok(())
+        };
+        unsafe { CloseHandle(process) };
+        result
+    }
+
+    /// Terminate every process in the job
+    pub fn terminate(&self, exit_code: u32) -> Result<(), ProcessError> {
+        use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+
+        if unsafe { TerminateJobObject(self.handle, exit_code) } == 0 {
+            return Err(last_os_error("Failed to terminate job object"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(self.handle) };
+    }
+}
+
+#[cfg(windows)]
+fn last_os_error(context: &str) -> ProcessError {
+    ProcessError::PgidError(format!("{}: {}", context, std::io::Error::last_os_error()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -395,6 +735,172 @@ mod tests {
         assert!(cmd.contains(&"-p".to_string()));
     }
 
+    #[test]
+    fn test_build_docker_command_passes_env_by_name() {
+        let config = ExecuteConfig::Docker {
+            image: "ros:humble".to_string(),
+            volumes: vec![],
+            ports: vec![],
+            privileged: false,
+            network: None,
+        };
+
+        let mut env = HashMap::new();
+        env.insert("ROS_DOMAIN_ID".to_string(), "42".to_string());
+
+        // Values reach the engine through its environment, not its arguments
+        let cmd = build_command(&config, &env).unwrap();
+        assert!(cmd.windows(2).any(|pair| pair == ["-e", "ROS_DOMAIN_ID"]));
+        assert!(!cmd.iter().any(|arg| arg.contains("42")));
+    }
+
+    #[test]
+    fn test_build_container_command_names_container() {
+        let config = ExecuteConfig::Docker {
+            image: "nginx:latest".to_string(),
+            volumes: vec![],
+            ports: vec![],
+            privileged: false,
+            network: None,
+        };
+
+        let name = container_name("robot", "web");
+        let cmd = build_container_command(&config, &HashMap::new(), &name).unwrap();
+        assert_eq!(
+            cmd,
+            vec!["docker", "run", "--name", "krill-robot-web", "nginx:latest"]
+        );
+
+        let shell = ExecuteConfig::Shell {
+            command: "echo hi".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        assert!(build_container_command(&shell, &HashMap::new(), &name).is_err());
+
+        assert_eq!(
+            container_pause_command(&config, &name, true).unwrap(),
+            vec!["docker", "pause", "krill-robot-web"]
+        );
+        assert_eq!(
+            container_pause_command(&config, &name, false).unwrap(),
+            vec!["docker", "unpause", "krill-robot-web"]
+        );
+        assert_eq!(container_pause_command(&shell, &name, true), None);
+    }
+
+    #[test]
+    fn test_docker_lifecycle_commands() {
+        assert_eq!(
+            ContainerEngine::Docker.stop_command("krill-robot-web", Duration::from_secs(10)),
+            vec!["docker", "stop", "-t", "10", "krill-robot-web"]
+        );
+        assert_eq!(
+            ContainerEngine::Docker.remove_command("krill-robot-web"),
+            vec!["docker", "rm", "-f", "krill-robot-web"]
+        );
+        assert_eq!(parse_container_health("healthy\n"), Some(true));
+        assert_eq!(parse_container_health("unhealthy"), Some(false));
+        assert_eq!(parse_container_health("starting"), None);
+        assert_eq!(parse_container_health(""), None);
+    }
+
+    #[test]
+    fn test_build_podman_command() {
+        let config = ExecuteConfig::Podman {
+            image: "ros:humble".to_string(),
+            volumes: vec![],
+            ports: vec![],
+            privileged: false,
+            network: Some("host".to_string()),
+            userns: Some("keep-id".to_string()),
+        };
+
+        let cmd = build_container_command(&config, &HashMap::new(), "krill-robot-nav").unwrap();
+        assert_eq!(
+            cmd,
+            vec![
+                "podman",
+                "run",
+                "--name",
+                "krill-robot-nav",
+                "--network",
+                "host",
+                "--userns",
+                "keep-id",
+                "ros:humble"
+            ]
+        );
+        assert_eq!(
+            ContainerEngine::Podman.remove_command("krill-robot-nav"),
+            vec!["podman", "rm", "-f", "krill-robot-nav"]
+        );
+    }
+
+    #[test]
+    fn test_compose_commands() {
+        let config = ExecuteConfig::Compose {
+            file: PathBuf::from("/robot/compose.yaml"),
+            services: vec!["nav".to_string(), "map".to_string()],
+            exit_code_from: Some("nav".to_string()),
+            engine: ContainerEngine::Podman,
+            working_dir: None,
+        };
+
+        let cmd = build_container_command(&config, &HashMap::new(), "krill-robot-stack").unwrap();
+        assert_eq!(
+            cmd,
+            vec![
+                "podman",
+                "compose",
+                "-p",
+                "krill-robot-stack",
+                "-f",
+                "/robot/compose.yaml",
+                "up",
+                "--abort-on-container-exit",
+                "--exit-code-from",
+                "nav",
+                "nav",
+                "map"
+            ]
+        );
+        assert_eq!(
+            compose_down_command(&config, "krill-robot-stack", Duration::from_secs(10)).unwrap(),
+            vec![
+                "podman",
+                "compose",
+                "-f",
+                "/robot/compose.yaml",
+                "-p",
+                "krill-robot-stack",
+                "down",
+                "-t",
+                "10"
+            ]
+        );
+        assert_eq!(
+            container_pause_command(&config, "krill-robot-stack", true).unwrap(),
+            vec![
+                "podman",
+                "compose",
+                "-f",
+                "/robot/compose.yaml",
+                "-p",
+                "krill-robot-stack",
+                "pause"
+            ]
+        );
+        assert_eq!(get_stop_command(&config), None);
+    }
+
+    #[test]
+    fn test_container_exit_reason() {
+        assert_eq!(container_exit_reason(3), "Container exited with code 3");
+        assert!(container_exit_reason(125).contains("engine failed"));
+        assert!(container_exit_reason(137).contains("out of memory"));
+    }
+
     #[test]
     fn test_get_stop_command() {
         let config = ExecuteConfig::Shell {
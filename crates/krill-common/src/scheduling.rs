@@ -0,0 +1,118 @@
+// CPU Scheduling - Core affinity and realtime scheduling policy of a service
+
+use serde::{Deserialize, Serialize};
+
+/// Highest CPU index `cpu_affinity` may name (the size of a Linux `cpu_set_t`)
+pub const MAX_CPU_INDEX: usize = 1023;
+
+/// Linux scheduling policy of a service's processes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedulerPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`)
+    #[default]
+    Other,
+    /// Time-sharing for CPU-bound batch work (`SCHED_BATCH`)
+    Batch,
+    /// Only runs when nothing else wants the CPU (`SCHED_IDLE`)
+    Idle,
+    /// Realtime first-in first-out (`SCHED_FIFO`)
+    Fifo,
+    /// Realtime round-robin (`SCHED_RR`)
+    Rr,
+}
+
+impl SchedulerPolicy {
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, SchedulerPolicy::Fifo | SchedulerPolicy::Rr)
+    }
+}
+
+/// `scheduler:` of a service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulerConfig {
+    pub policy: SchedulerPolicy,
+    /// Realtime priority from 1 (lowest) to 99; 0 for the other policies
+    #[serde(default)]
+    pub priority: u8,
+}
+
+impl SchedulerConfig {
+    /// Check the priority fits the policy: 1 to 99 for `fifo` and `rr`, 0
+    /// for the others
+    pub fn check(&self) -> Option<String> {
+        if self.policy.is_realtime() && !(1..=99).contains(&self.priority) {
+            return Some(format!(
+                "realtime priority must be between 1 and 99, got {}",
+                self.priority
+            ));
+        }
+        if !self.policy.is_realtime() && self.priority != 0 {
+            return Some(format!(
+                "priority is only used by the fifo and rr policies, got {}",
+                self.priority
+            ));
+        }
+        None
+    }
+}
+
+/// Reject an empty `cpu_affinity` list and CPUs past `MAX_CPU_INDEX`
+pub fn check_cpu_affinity(cpus: &[usize]) -> Option<String> {
+    if cpus.is_empty() {
+        return Some("cpu_affinity must list at least one CPU".to_string());
+    }
+    if let Some(cpu) = cpus.iter().find(|cpu| **cpu > MAX_CPU_INDEX) {
+        return Some(format!("CPU {} is out of range (0-{})", cpu, MAX_CPU_INDEX));
+    }
+    None
+}
+
+/// `docker run` option pinning a container to `cpus`
+pub fn cpu_affinity_docker_args(cpus: &[usize]) -> Vec<String> {
+    let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+    vec!["--cpuset-cpus".to_string(), cpus.join(",")]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_scheduler() {
+        let scheduler: SchedulerConfig =
+            serde_yaml::from_str("policy: fifo\npriority: 50\n").unwrap();
+        assert_eq!(
+            scheduler,
+            SchedulerConfig {
+                policy: SchedulerPolicy::Fifo,
+                priority: 50,
+            }
+        );
+        assert_eq!(scheduler.check(), None);
+
+        let scheduler: SchedulerConfig = serde_yaml::from_str("policy: batch\n").unwrap();
+        assert_eq!(scheduler.check(), None);
+
+        assert!(serde_yaml::from_str::<SchedulerConfig>("policy: deadline\n").is_err());
+        assert!(serde_yaml::from_str::<SchedulerConfig>("priority: 10\n").is_err());
+    }
+
+    #[test]
+    fn test_check_scheduling() {
+        let check = |policy, priority| SchedulerConfig { policy, priority }.check();
+        assert!(check(SchedulerPolicy::Rr, 0).is_some());
+        assert!(check(SchedulerPolicy::Fifo, 100).is_some());
+        assert!(check(SchedulerPolicy::Other, 5).is_some());
+        assert!(check(SchedulerPolicy::Rr, 99).is_none());
+
+        assert_eq!(check_cpu_affinity(&[2, 3]), None);
+        assert!(check_cpu_affinity(&[]).is_some());
+        assert!(check_cpu_affinity(&[1, 4096]).is_some());
+        assert_eq!(
+            cpu_affinity_docker_args(&[2, 3]),
+            vec!["--cpuset-cpus", "2,3"]
+        );
+    }
+}
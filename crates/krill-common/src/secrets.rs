@@ -0,0 +1,170 @@
+// Secrets - Values of a service's `secrets:`, read at spawn time instead of
+// being written into the recipe
+
+use crate::shell_command;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Where the value of a secret comes from, a single `kind: value` pair such
+/// as `file: /run/secrets/token` or `command: pass show telemetry`. Trailing
+/// newlines are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// Contents of the file
+    File(PathBuf),
+    /// Standard output of the shell command
+    Command(String),
+}
+
+impl Serialize for SecretSource {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            SecretSource::File(path) => map.serialize_entry("file", path)?,
+            SecretSource::Command(command) => map.serialize_entry("command", command)?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entry = BTreeMap::<String, String>::deserialize(deserializer)?;
+        let mut pairs = entry.into_iter();
+        let (Some((kind, value)), None) = (pairs.next(), pairs.next()) else {
+            return Err(serde::de::Error::custom(
+                "expected a single source such as `file: /run/secrets/token`",
+            ));
+        };
+        match kind.as_str() {
+            "file" => Ok(SecretSource::File(value.into())),
+            "command" => Ok(SecretSource::Command(value)),
+            other => Err(serde::de::Error::custom(format!(
+                "unknown secret source '{}', expected file or command",
+                other
+            ))),
+        }
+    }
+}
+
+/// A secret could not be read. Never carries the value, only where it was
+/// looked for.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("secret '{name}': failed to read {path}: {reason}")]
+    File {
+        name: String,
+        path: PathBuf,
+        reason: String,
+    },
+
+    #[error("secret '{name}': command failed: {reason}")]
+    Command { name: String, reason: String },
+}
+
+impl SecretSource {
+    /// Read the value of the secret `name`
+    pub fn read(&self, name: &str) -> Result<String, SecretError> {
+        let value = match self {
+            SecretSource::File(path) => {
+                std::fs::read_to_string(path).map_err(|e| SecretError::File {
+                    name: name.to_string(),
+                    path: path.clone(),
+                    reason: e.to_string(),
+                })?
+            }
+            SecretSource::Command(command) => {
+                let argv = shell_command(command);
+                let output = std::process::Command::new(&argv[0])
+                    .args(&argv[1..])
+                    .stdin(std::process::Stdio::null())
+                    .output()
+                    .map_err(|e| SecretError::Command {
+                        name: name.to_string(),
+                        reason: e.to_string(),
+                    })?;
+                return Self::command_value(name, output);
+            }
+        };
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+
+    /// Value of the secret `name` from the output of its `command`, for
+    /// callers that run the command themselves
+    pub fn command_value(name: &str, output: std::process::Output) -> Result<String, SecretError> {
+        let failed = |reason: String| SecretError::Command {
+            name: name.to_string(),
+            reason,
+        };
+        // Stderr may echo the value, so only the exit status is reported
+        if !output.status.success() {
+            return Err(failed(output.status.to_string()));
+        }
+        let value = String::from_utf8(output.stdout)
+            .map_err(|_| failed("output is not valid UTF-8".to_string()))?;
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// Read every secret of a service
+pub fn read_secrets(
+    secrets: &BTreeMap<String, SecretSource>,
+) -> Result<HashMap<String, String>, SecretError> {
+    secrets
+        .iter()
+        .map(|(name, source)| Ok((name.clone(), source.read(name)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_secrets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let token = dir.path().join("token");
+        std::fs::write(&token, "s3cr3t\n").unwrap();
+
+        let mut secrets = BTreeMap::from([
+            ("TOKEN".to_string(), SecretSource::File(token)),
+            (
+                "PASSWORD".to_string(),
+                SecretSource::Command("echo hunter2".to_string()),
+            ),
+        ]);
+        let values = read_secrets(&secrets).unwrap();
+        assert_eq!(values["TOKEN"], "s3cr3t");
+        assert_eq!(values["PASSWORD"], "hunter2");
+
+        secrets.insert(
+            "BROKEN".to_string(),
+            SecretSource::Command("echo leaked >&2; exit 3".to_string()),
+        );
+        let err = read_secrets(&secrets).unwrap_err().to_string();
+        assert!(
+            err.starts_with("secret 'BROKEN': command failed"),
+            "{}",
+            err
+        );
+        assert!(!err.contains("leaked"));
+    }
+
+    #[test]
+    fn test_secret_source_yaml() {
+        let source: SecretSource = serde_yaml::from_str("command: pass show uplink").unwrap();
+        assert_eq!(
+            source,
+            SecretSource::Command("pass show uplink".to_string())
+        );
+        assert_eq!(
+            serde_yaml::to_string(&source).unwrap(),
+            "command: pass show uplink\n"
+        );
+        assert!(serde_yaml::from_str::<SecretSource>("env: TOKEN").is_err());
+        assert!(serde_yaml::from_str::<SecretSource>("{file: /a, command: b}").is_err());
+    }
+}
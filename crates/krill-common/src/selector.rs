@@ -0,0 +1,165 @@
+// Label Selectors - Pick services by their `labels`
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// One condition of a [`LabelSelector`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelRequirement {
+    /// `key=value` (or `key==value`)
+    Equals(String, String),
+    /// `key!=value`; also holds when the label is not set
+    NotEquals(String, String),
+    /// `key`
+    Exists(String),
+    /// `!key`
+    NotExists(String),
+}
+
+impl LabelRequirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            LabelRequirement::Equals(key, value) => labels.get(key) == Some(value),
+            LabelRequirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            LabelRequirement::Exists(key) => labels.contains_key(key),
+            LabelRequirement::NotExists(key) => !labels.contains_key(key),
+        }
+    }
+}
+
+impl fmt::Display for LabelRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelRequirement::Equals(key, value) => write!(f, "{}={}", key, value),
+            LabelRequirement::NotEquals(key, value) => write!(f, "{}!={}", key, value),
+            LabelRequirement::Exists(key) => write!(f, "{}", key),
+            LabelRequirement::NotExists(key) => write!(f, "!{}", key),
+        }
+    }
+}
+
+/// Comma-separated conditions on a service's labels that must all hold,
+/// e.g. `tier=perception,robot!=r2,gpu,!sim`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelSelector {
+    requirements: Vec<LabelRequirement>,
+}
+
+impl LabelSelector {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.matches(labels))
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut requirements = Vec::new();
+        for part in text.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("empty condition in selector '{}'", text));
+            }
+            let requirement = if let Some(key) = part.strip_prefix('!') {
+                LabelRequirement::NotExists(key.trim().to_string())
+            } else if let Some((key, value)) = part.split_once("!=") {
+                LabelRequirement::NotEquals(key.trim().to_string(), value.trim().to_string())
+            } else if let Some((key, value)) =
+                part.split_once("==").or_else(|| part.split_once('='))
+            {
+                LabelRequirement::Equals(key.trim().to_string(), value.trim().to_string())
+            } else {
+                LabelRequirement::Exists(part.to_string())
+            };
+
+            let (key, value) = match &requirement {
+                LabelRequirement::Equals(key, value) | LabelRequirement::NotEquals(key, value) => {
+                    (key, value.as_str())
+                }
+                LabelRequirement::Exists(key) | LabelRequirement::NotExists(key) => (key, ""),
+            };
+            if let Some(reason) = check_label(key, value) {
+                return Err(format!("{} in selector '{}'", reason, text));
+            }
+            requirements.push(requirement);
+        }
+        Ok(Self { requirements })
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, requirement) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", requirement)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the syntax of a label: keys are non-empty, and keys and values
+/// consist of letters, digits, `-`, `_`, `.` and `/`
+pub fn check_label(key: &str, value: &str) -> Option<String> {
+    let valid = |text: &str| {
+        text.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+    };
+    if key.is_empty() {
+        Some("empty label key".to_string())
+    } else if !valid(key) {
+        Some(format!("invalid label key '{}'", key))
+    } else if !valid(value) {
+        Some(format!("invalid value '{}' of label '{}'", value, key))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_selector_matches_labels() {
+        let camera = labels(&[("tier", "perception"), ("robot", "r1"), ("gpu", "")]);
+        let planner = labels(&[("tier", "planning"), ("robot", "r2")]);
+
+        let selector: LabelSelector = "tier=perception".parse().unwrap();
+        assert!(selector.matches(&camera));
+        assert!(!selector.matches(&planner));
+
+        let selector: LabelSelector = "robot != r2, gpu".parse().unwrap();
+        assert!(selector.matches(&camera));
+        assert!(!selector.matches(&planner));
+
+        let selector: LabelSelector = "tier==planning,!gpu".parse().unwrap();
+        assert!(!selector.matches(&camera));
+        assert!(selector.matches(&planner));
+        assert_eq!(selector.to_string(), "tier=planning,!gpu");
+
+        // A missing label is not equal to any value
+        let selector: LabelSelector = "site!=lab".parse().unwrap();
+        assert!(selector.matches(&camera));
+    }
+
+    #[test]
+    fn test_invalid_selectors() {
+        assert!("".parse::<LabelSelector>().is_err());
+        assert!("tier=perception,".parse::<LabelSelector>().is_err());
+        assert!("=perception".parse::<LabelSelector>().is_err());
+        assert!("tier=a b".parse::<LabelSelector>().is_err());
+        assert!("!".parse::<LabelSelector>().is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// Service Templates - `templates:` blocks instantiated by services with parameters
+
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+
+/// Expand the services of a recipe that name a `template:`. The template's
+/// fields are copied into the service with `${name}` replaced by the
+/// service's `params`, the built-in `service` (its name), or a workspace
+/// `env` value, in that order; `$${` stays a literal `${`. Fields the service
+/// sets itself are taken as written and override the template's, merging
+/// nested mappings. The
+/// `templates:` section is removed.
+pub fn expand_templates(doc: &mut Value) -> Result<(), String> {
+    let Some(root) = doc.as_mapping_mut() else {
+        return Ok(());
+    };
+    let templates = match root.remove("templates") {
+        None | Some(Value::Null) => Mapping::new(),
+        Some(Value::Mapping(templates)) => templates,
+        Some(_) => return Err("templates must be a mapping of template names".to_string()),
+    };
+    let env: HashMap<String, String> = root
+        .get("env")
+        .and_then(Value::as_mapping)
+        .map(|env| {
+            env.iter()
+                .filter_map(|(key, value)| Some((key.as_str()?.to_string(), scalar(value)?)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let Some(services) = root.get_mut("services").and_then(Value::as_mapping_mut) else {
+        return Ok(());
+    };
+    for (name, service) in services.iter_mut() {
+        let Some(fields) = service.as_mapping_mut() else {
+            continue;
+        };
+        let Some(template_name) = fields.remove("template") else {
+            continue;
+        };
+        let name = name.as_str().unwrap_or_default();
+        let template_name = template_name
+            .as_str()
+            .ok_or_else(|| format!("Service '{}': template must be a name", name))?;
+        let template = templates.get(template_name).ok_or_else(|| {
+            format!(
+                "Service '{}' uses unknown template '{}'",
+                name, template_name
+            )
+        })?;
+
+        let mut params = HashMap::from([("service".to_string(), name.to_string())]);
+        match fields.remove("params") {
+            None | Some(Value::Null) => {}
+            Some(Value::Mapping(values)) => {
+                for (key, value) in values {
+                    let (Some(key), Some(value)) = (key.as_str(), scalar(&value)) else {
+                        return Err(format!(
+                            "Service '{}': params must map names to strings or numbers",
+                            name
+                        ));
+                    };
+                    params.insert(key.to_string(), value);
+                }
+            }
+            Some(_) => return Err(format!("Service '{}': params must be a mapping", name)),
+        }
+
+        let mut used = Vec::new();
+        let mut expanded = template.clone();
+        substitute(&mut expanded, &mut |var| {
+            used.push(var.to_string());
+            params
+                .get(var)
+                .or_else(|| env.get(var))
+                .cloned()
+                .ok_or_else(|| {
+                    format!(
+                        "Service '{}': template '{}' uses undefined parameter '{}'",
+                        name, template_name, var
+                    )
+                })
+        })?;
+        if let Some(unused) = params
+            .keys()
+            .filter(|key| *key != "service" && !used.contains(key))
+            .min()
+        {
+            return Err(format!(
+                "Service '{}': parameter '{}' is not used by template '{}'",
+                name, unused, template_name
+            ));
+        }
+
+        merge(&mut expanded, Value::Mapping(std::mem::take(fields)));
+        *service = expanded;
+    }
+    Ok(())
+}
+
+/// Replace `${name}` in every string of `value`
+fn substitute(
+    value: &mut Value,
+    lookup: &mut impl FnMut(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    match value {
+        Value::String(text) => *text = substitute_str(text, lookup)?,
+        Value::Sequence(items) => {
+            for item in items {
+                substitute(item, lookup)?;
+            }
+        }
+        Value::Mapping(fields) => {
+            for (_, field) in fields.iter_mut() {
+                substitute(field, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace `${name}` in `text`, keeping `$${` as a literal `${`
+pub(crate) fn substitute_str(
+    text: &str,
+    lookup: &mut impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(escaped) = after.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(open) = after.strip_prefix("${") {
+            let end = open
+                .find('}')
+                .ok_or_else(|| format!("Unclosed '${{' in '{}'", text))?;
+            result.push_str(&lookup(open[..end].trim())?);
+            rest = &open[end + 1..];
+        } else {
+            result.push('$');
+            rest = &after[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Overlay `overrides` onto `base`, merging mappings key by key
+pub(crate) fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(yaml: &str) -> Result<Value, String> {
+        let mut doc: Value = serde_yaml::from_str(yaml).unwrap();
+        expand_templates(&mut doc).map(|()| doc)
+    }
+
+    #[test]
+    fn test_services_instantiate_templates() {
+        let doc = expand(
+            r#"
+env:
+  ROS_DOMAIN_ID: "7"
+templates:
+  camera:
+    execute:
+      type: shell
+      command: camera_driver --device ${device} --ns ${ns} --domain ${ROS_DOMAIN_ID}
+      stop_command: echo $${HOME}
+      working_dir: /opt/${service}
+    critical: true
+services:
+  cam-front:
+    template: camera
+    params:
+      device: /dev/video0
+      ns: front
+  cam-rear:
+    template: camera
+    params:
+      device: /dev/video1
+      ns: rear
+    critical: false
+    execute:
+      working_dir: /opt/rear
+"#,
+        )
+        .unwrap();
+
+        assert!(doc.get("templates").is_none());
+        let front = &doc["services"]["cam-front"];
+        assert_eq!(
+            front["execute"]["command"].as_str(),
+            Some("camera_driver --device /dev/video0 --ns front --domain 7")
+        );
+        assert_eq!(
+            front["execute"]["working_dir"].as_str(),
+            Some("/opt/cam-front")
+        );
+        assert_eq!(front["critical"].as_bool(), Some(true));
+        assert!(front.get("params").is_none());
+
+        let rear = &doc["services"]["cam-rear"];
+        assert_eq!(rear["critical"].as_bool(), Some(false));
+        assert_eq!(rear["execute"]["type"].as_str(), Some("shell"));
+        assert_eq!(rear["execute"]["working_dir"].as_str(), Some("/opt/rear"));
+        assert_eq!(
+            rear["execute"]["stop_command"].as_str(),
+            Some("echo ${HOME}")
+        );
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let template = "templates:\n  cam:\n    execute:\n      type: shell\n      command: driver ${device}\n";
+
+        let err = expand(&format!(
+            "{}services:\n  a:\n    template: nope\n",
+            template
+        ));
+        assert_eq!(err.unwrap_err(), "Service 'a' uses unknown template 'nope'");
+
+        let err = expand(&format!("{}services:\n  a:\n    template: cam\n", template));
+        assert_eq!(
+            err.unwrap_err(),
+            "Service 'a': template 'cam' uses undefined parameter 'device'"
+        );
+
+        let err = expand(&format!(
+            "{}services:\n  a:\n    template: cam\n    params:\n      device: x\n      devcie: y\n",
+            template
+        ));
+        assert_eq!(
+            err.unwrap_err(),
+            "Service 'a': parameter 'devcie' is not used by template 'cam'"
+        );
+    }
+}
@@ -12,7 +12,9 @@ use krill_common::dag::DependencyGraph;
 use krill_common::dependency::{Dependency, DependencyCondition};
 use krill_common::execute::ExecuteConfig;
 use krill_common::health::HealthChecker;
-use krill_common::ipc::{ClientMessage, CommandAction, LogLevel, ServerMessage, ServiceStatus};
+use krill_common::ipc::{
+    ClientMessage, CommandAction, LogLevel, LogStream, ServerMessage, ServiceStatus,
+};
 use krill_common::policy::{PolicyConfig, RestartPolicy};
 use krill_common::process::{
     build_command, generate_process_name, get_stop_command, get_working_dir,
@@ -660,6 +662,9 @@ mod health {
         let checker = HealthChecker::Heartbeat {
             last_seen: Some(old_time),
             timeout: Duration::from_secs(5),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
         };
         assert!(
             checker.is_timed_out(),
@@ -672,6 +677,9 @@ mod health {
         let checker = HealthChecker::Heartbeat {
             last_seen: None,
             timeout: Duration::from_secs(5),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
         };
         assert!(
             !checker.is_timed_out(),
@@ -692,6 +700,7 @@ mod health {
             port: 3000,
             path: "/health".into(),
             expected_status: 200,
+            headers: HashMap::new(),
         };
         let result = http.record_heartbeat();
         assert!(result.is_err());
@@ -709,6 +718,9 @@ mod health {
         let mut checker = HealthChecker::Heartbeat {
             last_seen: None,
             timeout: Duration::from_secs(30),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
         };
 
         // First heartbeat
@@ -741,6 +753,7 @@ mod ipc {
         let msg = ClientMessage::Subscribe {
             events: true,
             logs: Some("lidar".into()),
+            raw_logs: false,
         };
         let json = serde_json::to_string(&msg).unwrap();
         let back: ClientMessage = serde_json::from_str(&json).unwrap();
@@ -752,12 +765,35 @@ mod ipc {
         let msg = ClientMessage::Subscribe {
             events: false,
             logs: None,
+            raw_logs: false,
         };
         let json = serde_json::to_string(&msg).unwrap();
         let back: ClientMessage = serde_json::from_str(&json).unwrap();
         assert_eq!(msg, back);
     }
 
+    #[test]
+    fn subscribe_message_raw_logs_defaults_to_throttled() {
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"type":"subscribe","events":true,"logs":null}"#).unwrap();
+        assert!(matches!(
+            msg,
+            ClientMessage::Subscribe {
+                raw_logs: false,
+                ..
+            }
+        ));
+
+        let msg = ClientMessage::Subscribe {
+            events: false,
+            logs: Some("dds".into()),
+            raw_logs: true,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""raw_logs":true"#));
+        assert_eq!(serde_json::from_str::<ClientMessage>(&json).unwrap(), msg);
+    }
+
     #[test]
     fn get_snapshot_message_roundtrip() {
         let msg = ClientMessage::GetSnapshot;
@@ -771,6 +807,7 @@ mod ipc {
     fn get_logs_message_with_service() {
         let msg = ClientMessage::GetLogs {
             service: Some("navigator".into()),
+            level: Some(LogLevel::Warn),
         };
         let json = serde_json::to_string(&msg).unwrap();
         let back: ClientMessage = serde_json::from_str(&json).unwrap();
@@ -779,10 +816,39 @@ mod ipc {
 
     #[test]
     fn get_logs_message_all_services() {
-        let msg = ClientMessage::GetLogs { service: None };
+        let msg = ClientMessage::GetLogs {
+            service: None,
+            level: None,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"get_logs","service":null}"#);
+        let back: ClientMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, back);
+    }
+
+    #[test]
+    fn export_logs_messages() {
+        let msg = ClientMessage::ExportLogs { session: None };
         let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"export_logs"}"#);
         let back: ClientMessage = serde_json::from_str(&json).unwrap();
         assert_eq!(msg, back);
+
+        let msg = ServerMessage::LogBundleChunk {
+            data: "H4sIAAAAAAAA".into(),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"log_bundle_chunk","data":"H4sIAAAAAAAA"}"#);
+        let back: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, back);
+
+        let msg = ServerMessage::LogsExported {
+            session: "session-20261016-081500".into(),
+            size: 48213,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        let back: ServerMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(msg, back);
     }
 
     #[test]
@@ -801,12 +867,29 @@ mod ipc {
         let msg = ServerMessage::LogLine {
             service: "lidar".into(),
             line: "sensor initialized".into(),
+            level: Some(LogLevel::Info),
+            timestamp: Some("2025-01-01T12:00:00.250Z".parse().unwrap()),
+            stream: Some(LogStream::Stderr),
         };
         let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""stream":"stderr""#));
         let back: ServerMessage = serde_json::from_str(&json).unwrap();
         assert_eq!(msg, back);
     }
 
+    #[test]
+    fn log_line_without_timestamp_and_stream() {
+        // As sent by daemons predating them
+        let json = r#"{"type":"log_line","service":"lidar","line":"up"}"#;
+        let ServerMessage::LogLine {
+            timestamp, stream, ..
+        } = serde_json::from_str(json).unwrap()
+        else {
+            panic!("expected a log line");
+        };
+        assert_eq!((timestamp, stream), (None, None));
+    }
+
     #[test]
     fn log_history_server_message() {
         let msg = ServerMessage::LogHistory {
@@ -856,6 +939,7 @@ mod ipc {
             CommandAction::Restart,
             CommandAction::Kill,
             CommandAction::StopDaemon,
+            CommandAction::Reload,
         ];
 
         for action in variants {
@@ -969,6 +1053,7 @@ restart: always
             max_restarts: 10,
             restart_delay: Duration::from_secs(30),
             stop_timeout: Duration::from_secs(60),
+            ..Default::default()
         };
 
         let yaml = serde_yaml::to_string(&policy).unwrap();
@@ -0,0 +1,410 @@
+// Access Policy - Socket permissions and peer-credential checks of IPC commands
+
+use krill_common::{
+    AccessConfig, ClientIdentity, CommandAction, ConfigError, KrillError, Principal,
+    DEFAULT_SOCKET_MODE,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Commands restricted whatever `restricted_commands` says: an upgrade
+/// starts a program as the daemon's user, and the log filter can silence
+/// or flood the daemon's own log
+const ALWAYS_RESTRICTED: [CommandAction; 2] =
+    [CommandAction::UpgradeDaemon, CommandAction::SetLogFilter];
+
+/// Why `access:` cannot be turned into a policy
+#[derive(Debug, Error)]
+pub enum AccessError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("Invalid access settings: unknown user '{0}'")]
+    UnknownUser(String),
+
+    #[error("Invalid access settings: unknown group '{0}'")]
+    UnknownGroup(String),
+
+    #[error("Invalid access settings: user and group names are not supported here: '{0}'")]
+    NamesUnsupported(String),
+}
+
+/// `access:` of a recipe with user and group names resolved to ids
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    socket_mode: u32,
+    socket_gid: Option<u32>,
+    restricted: Vec<CommandAction>,
+    users: HashSet<u32>,
+    groups: HashSet<u32>,
+    upgrade_binaries: Vec<PathBuf>,
+}
+
+impl Default for AccessPolicy {
+    /// Owner-only socket; every client that can connect may send anything
+    fn default() -> Self {
+        Self {
+            socket_mode: DEFAULT_SOCKET_MODE,
+            socket_gid: None,
+            restricted: Vec::new(),
+            users: HashSet::new(),
+            groups: HashSet::new(),
+            upgrade_binaries: Vec::new(),
+        }
+    }
+}
+
+impl AccessPolicy {
+    pub fn from_config(config: &AccessConfig) -> Result<Self, AccessError> {
+        Ok(Self {
+            socket_mode: config.socket_mode_bits()?,
+            socket_gid: config
+                .socket_group
+                .as_ref()
+                .map(resolve_group)
+                .transpose()?,
+            restricted: config.restricted_commands.clone(),
+            users: config
+                .allow_users
+                .iter()
+                .map(resolve_user)
+                .collect::<Result<_, _>>()?,
+            groups: config
+                .allow_groups
+                .iter()
+                .map(resolve_group)
+                .collect::<Result<_, _>>()?,
+            upgrade_binaries: config.upgrade_binaries.clone(),
+        })
+    }
+
+    /// Permission bits to give the Unix socket
+    pub fn socket_mode(&self) -> u32 {
+        self.socket_mode
+    }
+
+    /// Group to hand the Unix socket to
+    pub fn socket_gid(&self) -> Option<u32> {
+        self.socket_gid
+    }
+
+    /// Whether `client` may send `action`. Restricted commands need a local
+    /// client whose peer credentials name root, the daemon's own user, an
+    /// allowed user, or a member of an allowed group.
+    pub async fn authorize(
+        &self,
+        action: CommandAction,
+        client: &ClientIdentity,
+    ) -> Result<(), KrillError> {
+        if !self.restricted.contains(&action) && !ALWAYS_RESTRICTED.contains(&action) {
+            return Ok(());
+        }
+        let Some(uid) = client.uid else {
+            return Err(KrillError::PermissionDenied(format!(
+                "{:?} is restricted to local clients with known credentials",
+                action
+            )));
+        };
+        if self.is_allowed(uid, client.gid).await {
+            return Ok(());
+        }
+        Err(KrillError::PermissionDenied(format!(
+            "uid {} may not send {:?}",
+            uid, action
+        )))
+    }
+
+    /// Whether `client` may connect to an abstract socket. Such a socket
+    /// has no permission bits, so its peers are held to what `socket_mode`
+    /// grants on a file: the daemon's user, root and the allowed principals.
+    pub async fn admit_peer(&self, client: &ClientIdentity) -> Result<(), KrillError> {
+        match client.uid {
+            Some(uid) if self.is_allowed(uid, client.gid).await => Ok(()),
+            Some(uid) => Err(KrillError::PermissionDenied(format!(
+                "uid {} may not connect to the daemon",
+                uid
+            ))),
+            None => Err(KrillError::PermissionDenied(
+                "clients without known credentials may not connect to the daemon".to_string(),
+            )),
+        }
+    }
+
+    /// Root, the daemon's own user, an allowed user, or a member of an
+    /// allowed group
+    async fn is_allowed(&self, uid: u32, gid: Option<u32>) -> bool {
+        if uid == 0
+            || uid == daemon_uid()
+            || self.users.contains(&uid)
+            || gid.is_some_and(|gid| self.groups.contains(&gid))
+        {
+            return true;
+        }
+        if self.groups.is_empty() {
+            return false;
+        }
+        // The user and group databases may be served over the network (NSS
+        // with LDAP or sssd), so they are read off the runtime
+        let groups = tokio::task::spawn_blocking(move || user_groups(uid))
+            .await
+            .unwrap_or_default();
+        groups.iter().any(|gid| self.groups.contains(gid))
+    }
+
+    /// Whether an upgrade may start `binary`: the daemon's own executable
+    /// (the default) or one listed in `upgrade_binaries`, compared after
+    /// resolving symlinks
+    pub fn check_upgrade(&self, binary: Option<&Path>) -> Result<(), KrillError> {
+        let Some(binary) = binary else {
+            return Ok(());
+        };
+        if !binary.is_absolute() {
+            return Err(KrillError::InvalidRequest(format!(
+                "the new daemon binary must be an absolute path, got {:?}",
+                binary
+            )));
+        }
+        let resolved = std::fs::canonicalize(binary).map_err(|e| {
+            KrillError::InvalidRequest(format!("the new daemon binary {:?}: {}", binary, e))
+        })?;
+        let allowed = crate::handoff::own_executable()
+            .into_iter()
+            .chain(self.upgrade_binaries.iter().cloned())
+            .filter_map(|allowed| std::fs::canonicalize(allowed).ok())
+            .any(|allowed| allowed == resolved);
+        if allowed {
+            Ok(())
+        } else {
+            Err(KrillError::PermissionDenied(format!(
+                "{:?} is neither the daemon's executable nor listed in access.upgrade_binaries",
+                binary
+            )))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn daemon_uid() -> u32 {
+    nix::unistd::geteuid().as_raw()
+}
+
+#[cfg(not(unix))]
+fn daemon_uid() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn resolve_user(user: &Principal) -> Result<u32, AccessError> {
+    match user {
+        Principal::Id(uid) => Ok(*uid),
+        Principal::Name(name) => nix::unistd::User::from_name(name)
+            .ok()
+            .flatten()
+            .map(|user| user.uid.as_raw())
+            .ok_or_else(|| AccessError::UnknownUser(name.clone())),
+    }
+}
+
+#[cfg(unix)]
+fn resolve_group(group: &Principal) -> Result<u32, AccessError> {
+    match group {
+        Principal::Id(gid) => Ok(*gid),
+        Principal::Name(name) => nix::unistd::Group::from_name(name)
+            .ok()
+            .flatten()
+            .map(|group| group.gid.as_raw())
+            .ok_or_else(|| AccessError::UnknownGroup(name.clone())),
+    }
+}
+
+#[cfg(not(unix))]
+fn resolve_user(user: &Principal) -> Result<u32, AccessError> {
+    match user {
+        Principal::Id(uid) => Ok(*uid),
+        Principal::Name(name) => Err(AccessError::NamesUnsupported(name.clone())),
+    }
+}
+
+#[cfg(not(unix))]
+fn resolve_group(group: &Principal) -> Result<u32, AccessError> {
+    match group {
+        Principal::Id(gid) => Ok(*gid),
+        Principal::Name(name) => Err(AccessError::NamesUnsupported(name.clone())),
+    }
+}
+
+/// Supplementary groups of a user, looked up in the group database
+#[cfg(target_os = "linux")]
+fn user_groups(uid: u32) -> Vec<u32> {
+    use nix::unistd::{getgrouplist, Uid, User};
+    use std::ffi::CString;
+
+    let Some(user) = User::from_uid(Uid::from_raw(uid)).ok().flatten() else {
+        return Vec::new();
+    };
+    let Ok(name) = CString::new(user.name) else {
+        return Vec::new();
+    };
+    getgrouplist(&name, user.gid)
+        .map(|groups| groups.into_iter().map(|gid| gid.as_raw()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn user_groups(_uid: u32) -> Vec<u32> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restricted_commands_need_allowed_peer() {
+        let policy = AccessPolicy::from_config(&AccessConfig {
+            allow_users: vec![Principal::Id(4001)],
+            allow_groups: vec![Principal::Id(5001)],
+            ..Default::default()
+        })
+        .unwrap();
+        let client = |uid: Option<u32>, gid: Option<u32>| ClientIdentity {
+            uid,
+            gid,
+            ..Default::default()
+        };
+
+        // Status-changing commands outside the restricted list stay open
+        assert!(policy
+            .authorize(CommandAction::Restart, &client(Some(4999), None))
+            .await
+            .is_ok());
+
+        assert!(policy
+            .authorize(CommandAction::EmergencyStop, &client(Some(4001), None))
+            .await
+            .is_ok());
+        assert!(policy
+            .authorize(CommandAction::StopDaemon, &client(Some(4999), Some(5001)))
+            .await
+            .is_ok());
+        assert!(policy
+            .authorize(CommandAction::StopDaemon, &client(Some(0), None))
+            .await
+            .is_ok());
+        assert!(policy
+            .authorize(CommandAction::StopDaemon, &client(Some(daemon_uid()), None))
+            .await
+            .is_ok());
+
+        let err = policy
+            .authorize(CommandAction::StopDaemon, &client(Some(4999), Some(4999)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), krill_common::ERROR_PERMISSION_DENIED);
+        // TCP clients carry no credentials
+        assert!(policy
+            .authorize(CommandAction::EmergencyStop, &client(None, None))
+            .await
+            .is_err());
+
+        // Without a policy, or with a list leaving them out, nothing is
+        // restricted but upgrades and log filter changes
+        assert!(AccessPolicy::default()
+            .authorize(CommandAction::StopDaemon, &client(None, None))
+            .await
+            .is_ok());
+        let open = AccessPolicy::from_config(&AccessConfig {
+            restricted_commands: Vec::new(),
+            ..Default::default()
+        })
+        .unwrap();
+        let listed = AccessPolicy::from_config(&AccessConfig {
+            restricted_commands: vec![CommandAction::StopDaemon],
+            ..Default::default()
+        })
+        .unwrap();
+        for policy in [&open, &listed, &AccessPolicy::default()] {
+            for action in [CommandAction::UpgradeDaemon, CommandAction::SetLogFilter] {
+                assert!(policy
+                    .authorize(action, &client(Some(4999), None))
+                    .await
+                    .is_err());
+                assert!(policy.authorize(action, &client(None, None)).await.is_err());
+                assert!(policy
+                    .authorize(action, &client(Some(daemon_uid()), None))
+                    .await
+                    .is_ok());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_abstract_socket_peers_need_allowed_credentials() {
+        let policy = AccessPolicy::from_config(&AccessConfig {
+            allow_users: vec![Principal::Id(4001)],
+            allow_groups: vec![Principal::Id(5001)],
+            ..Default::default()
+        })
+        .unwrap();
+        let client = |uid: Option<u32>, gid: Option<u32>| ClientIdentity {
+            uid,
+            gid,
+            ..Default::default()
+        };
+
+        assert!(policy
+            .admit_peer(&client(Some(daemon_uid()), None))
+            .await
+            .is_ok());
+        assert!(policy.admit_peer(&client(Some(0), None)).await.is_ok());
+        assert!(policy.admit_peer(&client(Some(4001), None)).await.is_ok());
+        assert!(policy
+            .admit_peer(&client(Some(4999), Some(5001)))
+            .await
+            .is_ok());
+        assert!(policy
+            .admit_peer(&client(Some(4999), Some(4999)))
+            .await
+            .is_err());
+        assert!(policy.admit_peer(&client(None, None)).await.is_err());
+    }
+
+    #[test]
+    fn test_upgrade_binary_must_be_allowed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let listed = dir.path().join("krill-daemon-next");
+        let other = dir.path().join("other");
+        std::fs::write(&listed, "").unwrap();
+        std::fs::write(&other, "").unwrap();
+        let link = dir.path().join("krill-daemon-link");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&listed, &link).unwrap();
+        #[cfg(not(unix))]
+        std::fs::write(&link, "").unwrap();
+
+        let policy = AccessPolicy::from_config(&AccessConfig {
+            upgrade_binaries: vec![listed.clone()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // The daemon's own executable is always allowed, and the default
+        assert!(policy.check_upgrade(None).is_ok());
+        let own = crate::handoff::own_executable().unwrap();
+        assert!(policy.check_upgrade(Some(&own)).is_ok());
+        assert!(policy.check_upgrade(Some(&listed)).is_ok());
+        #[cfg(unix)]
+        assert!(policy.check_upgrade(Some(&link)).is_ok());
+
+        let err = policy.check_upgrade(Some(&other)).unwrap_err();
+        assert_eq!(err.code(), krill_common::ERROR_PERMISSION_DENIED);
+        let err = policy
+            .check_upgrade(Some(Path::new("krill-daemon-next")))
+            .unwrap_err();
+        assert_eq!(err.code(), krill_common::ERROR_INVALID_REQUEST);
+        assert!(policy
+            .check_upgrade(Some(&dir.path().join("missing")))
+            .is_err());
+    }
+}
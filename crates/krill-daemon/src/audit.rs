@@ -0,0 +1,187 @@
+// Audit Log - Control commands received from clients, with who sent them
+
+use chrono::{Duration as ChronoDuration, Utc};
+use krill_common::{AuditOutcome, AuditRecord, ClientIdentity, CommandAction, KrillError};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Maximum audit records kept in memory
+pub const MAX_RECORDS: usize = 10_000;
+
+/// Ring buffer of recent control commands, optionally appended to a JSONL file
+pub struct AuditLog {
+    records: Mutex<VecDeque<AuditRecord>>,
+    capacity: usize,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl AuditLog {
+    /// Audit log that keeps records in memory only
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::new()),
+            capacity,
+            file: None,
+        }
+    }
+
+    /// Audit log that also appends every record to `path` as a JSON line
+    pub fn with_file(capacity: usize, path: &Path) -> std::io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path)?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+            ..Self::new(capacity)
+        })
+    }
+
+    /// Path of the audit file inside a log session directory
+    pub fn session_path(session_dir: &Path) -> PathBuf {
+        session_dir.join("audit.jsonl")
+    }
+
+    /// Record a command from `client`, timestamped now; `result` is whether
+    /// the daemon accepted it. Returns once the record is synced to the
+    /// audit file, which happens off the runtime.
+    pub async fn record(
+        &self,
+        action: CommandAction,
+        target: Option<&str>,
+        client: &ClientIdentity,
+        result: Result<(), &KrillError>,
+    ) {
+        let outcome = match result {
+            Ok(()) => AuditOutcome::Accepted,
+            Err(e) => AuditOutcome::Rejected {
+                code: e.code(),
+                message: e.to_string(),
+            },
+        };
+        self.record_outcome(action, target, client, outcome).await;
+    }
+
+    /// Record a command from `client` with any outcome, such as a command
+    /// scheduled for later
+    pub async fn record_outcome(
+        &self,
+        action: CommandAction,
+        target: Option<&str>,
+        client: &ClientIdentity,
+        outcome: AuditOutcome,
+    ) {
+        info!(
+            "Audit: {:?} for {:?} from {} ({:?})",
+            action, target, client, outcome
+        );
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            target: target.map(str::to_string),
+            client: client.clone(),
+            outcome,
+        };
+
+        if let Some(ref file) = self.file {
+            if let Ok(json) = serde_json::to_string(&record) {
+                let file = Arc::clone(file);
+                let written = tokio::task::spawn_blocking(move || {
+                    let mut file = file.lock().unwrap();
+                    writeln!(file, "{}", json).and_then(|()| file.sync_data())
+                })
+                .await;
+                match written {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("Failed to write audit log: {}", e),
+                    Err(e) => warn!("Failed to write audit log: {}", e),
+                }
+            }
+        }
+
+        let mut records = self.records.lock().unwrap();
+        records.push_back(record);
+        while records.len() > self.capacity {
+            records.pop_front();
+        }
+    }
+
+    /// Records of the last `since` (all if `None`), optionally for one
+    /// target, oldest first
+    pub fn query(&self, since: Option<Duration>, target: Option<&str>) -> Vec<AuditRecord> {
+        let cutoff = since
+            .and_then(|since| ChronoDuration::from_std(since).ok())
+            .and_then(|since| Utc::now().checked_sub_signed(since));
+
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| cutoff.is_none_or(|cutoff| record.timestamp >= cutoff))
+            .filter(|record| target.is_none_or(|target| record.target.as_deref() == Some(target)))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_are_appended_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = AuditLog::session_path(dir.path());
+        let audit = AuditLog::with_file(2, &path).unwrap();
+        let client = ClientIdentity {
+            uid: Some(1000),
+            pid: Some(77),
+            ..Default::default()
+        };
+
+        audit
+            .record(CommandAction::Stop, Some("lidar"), &client, Ok(()))
+            .await;
+        audit
+            .record(
+                CommandAction::Restart,
+                Some("motor"),
+                &client,
+                Err(&KrillError::ServiceNotFound("motor".to_string())),
+            )
+            .await;
+        audit
+            .record(CommandAction::EmergencyStop, None, &client, Ok(()))
+            .await;
+
+        // The file keeps every record; memory only the newest
+        let content = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<AuditRecord> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].client.pid, Some(77));
+        assert_eq!(
+            records[1].outcome,
+            AuditOutcome::Rejected {
+                code: krill_common::ERROR_NOT_FOUND,
+                message: "Service 'motor' not found".to_string(),
+            }
+        );
+
+        assert_eq!(audit.query(None, None).len(), 2);
+        let motor = audit.query(None, Some("motor"));
+        assert_eq!(motor.len(), 1);
+        assert_eq!(motor[0].action, CommandAction::Restart);
+    }
+}
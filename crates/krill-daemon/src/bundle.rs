@@ -0,0 +1,390 @@
+// Log Bundles - Archives of a log session for bug reports
+
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use krill_common::{KrillConfig, KrillError};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Copy of the recipe the session ran with, inside its session directory
+pub const CONFIG_FILE: &str = "config.yaml";
+
+/// Prefix of the copies of the files the recipe includes, numbered in merge
+/// order, e.g. `include-1-common.yaml`
+pub const INCLUDE_PREFIX: &str = "include-";
+
+/// Daemon metadata of the session, inside its session directory
+pub const METADATA_FILE: &str = "daemon.json";
+
+/// Snapshot of the services, added to bundles of the running session
+pub const STATUS_FILE: &str = "status.json";
+
+/// Prefix of the session directories under the log directory
+const SESSION_PREFIX: &str = "session-";
+
+/// What the recipe copies show instead of an inline secret
+pub const REDACTED: &str = "<redacted>";
+
+/// Parts of environment variable names that mark their values as secrets,
+/// e.g. `UPLINK_TOKEN` or `DB_PASSWORD`
+const SECRET_NAME_PARTS: &[&str] = &[
+    "SECRET",
+    "TOKEN",
+    "PASSWORD",
+    "PASSWD",
+    "KEY",
+    "CREDENTIAL",
+    "AUTH",
+];
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("No log sessions in {0:?}")]
+    NoSessions(PathBuf),
+
+    #[error("Unknown log session '{0}'")]
+    UnknownSession(String),
+}
+
+impl From<BundleError> for KrillError {
+    fn from(err: BundleError) -> Self {
+        match err {
+            BundleError::NoSessions(_) | BundleError::UnknownSession(_) => {
+                KrillError::InvalidRequest(err.to_string())
+            }
+            other => KrillError::Internal(other.to_string()),
+        }
+    }
+}
+
+/// Who recorded a session: written to `daemon.json` when the daemon starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub workspace: String,
+    pub version: String,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub config_path: PathBuf,
+    pub os: String,
+    pub arch: String,
+}
+
+impl SessionMetadata {
+    /// Metadata of this daemon process, running the recipe at `config_path`
+    pub fn current(workspace: &str, config_path: &Path) -> Self {
+        Self {
+            workspace: workspace.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            started_at: Utc::now(),
+            config_path: config_path
+                .canonicalize()
+                .unwrap_or_else(|_| config_path.to_path_buf()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+}
+
+/// Record the daemon metadata and a copy of the recipe in a session
+/// directory, so the session can be bundled after the daemon is gone
+pub fn record_session(session_dir: &Path, metadata: &SessionMetadata) -> Result<(), BundleError> {
+    fs::write(
+        session_dir.join(METADATA_FILE),
+        serde_json::to_vec_pretty(metadata)?,
+    )?;
+    record_config(session_dir, &metadata.config_path)
+}
+
+/// Replace the recipe copy of a session, and those of the files it
+/// includes, e.g. after a reload. Inline secrets are redacted from the
+/// copies.
+pub fn record_config(session_dir: &Path, config_path: &Path) -> Result<(), BundleError> {
+    let content = fs::read_to_string(config_path)?;
+    fs::write(session_dir.join(CONFIG_FILE), redact(&content)?)?;
+
+    for entry in fs::read_dir(session_dir)?.filter_map(|entry| entry.ok()) {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(INCLUDE_PREFIX)
+        {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    if let Ok(config) = KrillConfig::parse(&content, config_path.parent()) {
+        for (index, file) in config.included.iter().enumerate() {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let copy = format!("{}{}-{}", INCLUDE_PREFIX, index + 1, name);
+            fs::write(session_dir.join(copy), redact(&fs::read_to_string(file)?)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// The recipe (or included file) `content` with the values of its `env`
+/// variables whose names look secret replaced by [`REDACTED`]. The copy
+/// is re-serialized, so it loses the comments and layout of the original.
+fn redact(content: &str) -> Result<String, BundleError> {
+    let mut recipe: serde_yaml::Value = serde_yaml::from_str(content)?;
+    redact_value(&mut recipe);
+    Ok(serde_yaml::to_string(&recipe)?)
+}
+
+fn redact_value(value: &mut serde_yaml::Value) {
+    let Some(mapping) = value.as_mapping_mut() else {
+        return;
+    };
+    for (key, value) in mapping.iter_mut() {
+        if let (Some("env"), Some(env)) = (key.as_str(), value.as_mapping_mut()) {
+            for (name, value) in env.iter_mut() {
+                if name.as_str().is_some_and(is_secret_name) {
+                    *value = REDACTED.into();
+                }
+            }
+        } else {
+            redact_value(value);
+        }
+    }
+}
+
+/// Whether an environment variable named `name` likely holds a secret
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// Session directory named `session` (with or without its `session-`
+/// prefix) under `log_dir`, or the newest one
+pub fn find_session(log_dir: &Path, session: Option<&str>) -> Result<PathBuf, BundleError> {
+    match session {
+        Some(name) => {
+            let name = if name.starts_with(SESSION_PREFIX) {
+                name.to_string()
+            } else {
+                format!("{}{}", SESSION_PREFIX, name)
+            };
+            // Only plain names, so a session never resolves outside `log_dir`
+            let dir = log_dir.join(&name);
+            if Path::new(&name).components().count() != 1 || !dir.is_dir() {
+                return Err(BundleError::UnknownSession(name));
+            }
+            Ok(dir)
+        }
+        None => {
+            // Session names sort by their timestamp
+            let newest = fs::read_dir(log_dir)
+                .map_err(|_| BundleError::NoSessions(log_dir.to_path_buf()))?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| name.starts_with(SESSION_PREFIX))
+                .max();
+            newest
+                .map(|name| log_dir.join(name))
+                .ok_or_else(|| BundleError::NoSessions(log_dir.to_path_buf()))
+        }
+    }
+}
+
+/// Write a gzipped tarball of `session_dir` to `out`, with `extra` files
+/// added next to the session's own. Entries are under a directory named
+/// after the session. Returns `out`, flushed.
+pub fn export_session<W: Write>(
+    session_dir: &Path,
+    out: W,
+    extra: &[(&str, Vec<u8>)],
+) -> Result<W, BundleError> {
+    let root = session_dir
+        .file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("session"));
+
+    let encoder = GzEncoder::new(out, Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    append_dir(&mut archive, session_dir, &root)?;
+
+    for (name, contents) in extra {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp().max(0) as u64);
+        archive.append_data(&mut header, root.join(name), contents.as_slice())?;
+    }
+
+    let mut out = archive.into_inner()?.finish()?;
+    out.flush()?;
+    Ok(out)
+}
+
+/// Add the files below `dir` as `name/...`. Logs of a running session grow
+/// while they are archived, so each file is cut at its size when opened.
+fn append_dir<W: Write>(
+    archive: &mut tar::Builder<W>,
+    dir: &Path,
+    name: &Path,
+) -> Result<(), BundleError> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.filter_map(|entry| entry.ok()).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let entry_name = name.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            append_dir(archive, &path, &entry_name)?;
+        } else if file_type.is_file() {
+            let file = File::open(&path)?;
+            let metadata = file.metadata()?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            archive.append_data(&mut header, &entry_name, file.take(metadata.len()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    fn entries(bundle: &Path) -> Vec<String> {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(bundle).unwrap()));
+        let mut names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_export_session() {
+        let log_dir = TempDir::new().unwrap();
+        let config = log_dir.path().join("krill.yaml");
+        fs::write(&config, "include: [common.yaml]\nname: robot\n").unwrap();
+        fs::write(
+            log_dir.path().join("common.yaml"),
+            "version: \"1\"\nservices: {}\n",
+        )
+        .unwrap();
+
+        let old = log_dir.path().join("session-20260101-080000");
+        let new = log_dir.path().join("session-20260102-080000");
+        for dir in [&old, &new] {
+            fs::create_dir_all(dir.join("mapping")).unwrap();
+            fs::write(dir.join("lidar.log"), "[2026-01-02 08:00:00.000] up\n").unwrap();
+        }
+        fs::write(new.join("mapping").join("slam.log"), "").unwrap();
+        record_session(&new, &SessionMetadata::current("robot", &config)).unwrap();
+
+        assert_eq!(find_session(log_dir.path(), None).unwrap(), new);
+        assert_eq!(
+            find_session(log_dir.path(), Some("20260101-080000")).unwrap(),
+            old
+        );
+        assert!(matches!(
+            find_session(log_dir.path(), Some("../session-20260101-080000")),
+            Err(BundleError::UnknownSession(_))
+        ));
+
+        let bundle = log_dir.path().join("session-20260102-080000.tar.gz");
+        let out = File::create(&bundle).unwrap();
+        export_session(&new, out, &[(STATUS_FILE, b"{}".to_vec())]).unwrap();
+
+        let names = entries(&bundle);
+        for name in [
+            "config.yaml",
+            "daemon.json",
+            "include-1-common.yaml",
+            "lidar.log",
+            "mapping/slam.log",
+            "status.json",
+        ] {
+            let path = format!("session-20260102-080000/{}", name);
+            assert!(names.contains(&path), "{} missing from {:?}", path, names);
+        }
+    }
+
+    #[test]
+    fn test_recipe_copies_redact_inline_secrets() {
+        let dir = TempDir::new().unwrap();
+        let config = dir.path().join("krill.yaml");
+        fs::write(
+            &config,
+            r#"
+version: "1"
+name: robot
+include: [common.yaml]
+env:
+  ROBOT: krill01
+  MAPS_API_KEY: abc123
+services:
+  uplink:
+    execute:
+      type: pixi
+      task: uplink
+    env:
+      UPLINK_TOKEN: hunter2
+      UPLINK_HOST: relay.local
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("common.yaml"),
+            "env:\n  db_password: s3cret\n",
+        )
+        .unwrap();
+
+        let session = dir.path().join("session-20260102-080000");
+        fs::create_dir(&session).unwrap();
+        record_config(&session, &config).unwrap();
+
+        let copy = fs::read_to_string(session.join(CONFIG_FILE)).unwrap();
+        assert!(
+            !copy.contains("abc123") && !copy.contains("hunter2"),
+            "{}",
+            copy
+        );
+        assert!(
+            copy.contains("krill01") && copy.contains("relay.local"),
+            "{}",
+            copy
+        );
+        let copy: serde_yaml::Value = serde_yaml::from_str(&copy).unwrap();
+        assert_eq!(copy["env"]["MAPS_API_KEY"], REDACTED);
+        assert_eq!(copy["services"]["uplink"]["env"]["UPLINK_TOKEN"], REDACTED);
+
+        let include = fs::read_to_string(session.join("include-1-common.yaml")).unwrap();
+        assert_eq!(include, "env:\n  db_password: <redacted>\n");
+    }
+
+    #[test]
+    fn test_find_session_without_sessions() {
+        let log_dir = TempDir::new().unwrap();
+        assert!(matches!(
+            find_session(log_dir.path(), None),
+            Err(BundleError::NoSessions(_))
+        ));
+        assert!(matches!(
+            find_session(&log_dir.path().join("missing"), None),
+            Err(BundleError::NoSessions(_))
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+// Daemon Log - The daemon's own tracing output, on stdout and in krill.log
+
+use chrono::{SecondsFormat, Utc};
+use krill_common::{DaemonLogConfig, DaemonLogFormat};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+#[derive(Debug, Error)]
+pub enum DaemonLogError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid log filter '{filter}': {reason}")]
+    InvalidFilter { filter: String, reason: String },
+
+    #[error("Failed to apply log filter: {0}")]
+    Reload(#[from] reload::Error),
+
+    #[error("Failed to install the daemon log: {0}")]
+    Init(#[from] tracing::subscriber::SetGlobalDefaultError),
+}
+
+/// Where the daemon's messages go besides `krill.log`
+#[derive(Debug, Clone, Default)]
+pub struct DaemonLogOptions {
+    /// Also print them, colored when stdout is a terminal
+    pub stdout: bool,
+    /// Filter replacing the configured one and `RUST_LOG`, e.g. from `--verbose`
+    pub filter: Option<String>,
+}
+
+/// Changes the daemon's log filter while it runs, for `set_log_filter`
+pub struct LogFilterHandle {
+    handle: reload::Handle<EnvFilter, Registry>,
+    filter: Mutex<String>,
+}
+
+impl LogFilterHandle {
+    pub fn new(handle: reload::Handle<EnvFilter, Registry>, filter: impl Into<String>) -> Self {
+        Self {
+            handle,
+            filter: Mutex::new(filter.into()),
+        }
+    }
+
+    /// The filter in effect, e.g. `info,krill_daemon::health=debug`
+    pub fn filter(&self) -> String {
+        self.filter.lock().unwrap().clone()
+    }
+
+    /// Replace the filter; an invalid one leaves the current filter in place
+    pub fn set(&self, filter: &str) -> Result<(), DaemonLogError> {
+        let env_filter = parse_filter(filter)?;
+        self.handle.reload(env_filter)?;
+        *self.filter.lock().unwrap() = filter.to_string();
+        Ok(())
+    }
+}
+
+fn parse_filter(filter: &str) -> Result<EnvFilter, DaemonLogError> {
+    EnvFilter::try_new(filter).map_err(|e| DaemonLogError::InvalidFilter {
+        filter: filter.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// What the output layers of the daemon's subscriber sit on
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Where messages go besides stdout: stderr until `krill.log` is open
+type OutputLayer = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// The daemon's tracing subscriber, installed by [`init`] before the recipe
+/// is read, so loading it is logged as well
+pub struct DaemonLog {
+    filter: LogFilterHandle,
+    output: reload::Handle<Option<OutputLayer>, Filtered>,
+    /// The filter came from the options or `RUST_LOG`, not the recipe
+    filter_given: bool,
+}
+
+impl DaemonLog {
+    /// Write to `krill.log` in `session_dir` in the configured format and
+    /// switch to the configured level and module overrides, unless a filter
+    /// was given to [`init`]
+    pub fn open(
+        self,
+        config: &DaemonLogConfig,
+        session_dir: &Path,
+    ) -> Result<LogFilterHandle, DaemonLogError> {
+        if !self.filter_given {
+            self.filter.set(&config.filter())?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(session_dir.join("krill.log"))?;
+        let file_layer = match config.format {
+            DaemonLogFormat::Compact => tracing_subscriber::fmt::layer()
+                .compact()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .boxed(),
+            DaemonLogFormat::Json => tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .with_writer(Mutex::new(file))
+                .boxed(),
+        };
+        self.output.reload(Some(file_layer))?;
+
+        Ok(self.filter)
+    }
+}
+
+/// Install the daemon's tracing subscriber, printing to stdout if asked for
+/// and otherwise to stderr until [`DaemonLog::open`] adds `krill.log`. The
+/// filter is the one given in `options`, else `RUST_LOG`, else the default
+/// level until the recipe's applies.
+pub fn init(options: DaemonLogOptions) -> Result<DaemonLog, DaemonLogError> {
+    let given = options
+        .filter
+        .or_else(|| std::env::var(EnvFilter::DEFAULT_ENV).ok())
+        .filter(|filter| !filter.is_empty());
+    let filter_given = given.is_some();
+    let filter = given.unwrap_or_else(|| DaemonLogConfig::default().filter());
+    let (filter_layer, handle) = reload::Layer::new(parse_filter(&filter)?);
+
+    let stderr_layer = (!options.stdout).then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_ansi(false)
+            .with_writer(std::io::stderr)
+            .boxed()
+    });
+    let (output_layer, output) = reload::Layer::new(stderr_layer);
+    let stdout_layer = options.stdout.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_ansi(std::io::stdout().is_terminal())
+    });
+
+    let subscriber = tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(output_layer)
+        .with(stdout_layer);
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    Ok(DaemonLog {
+        filter: LogFilterHandle::new(handle, filter),
+        output,
+        filter_given,
+    })
+}
+
+/// One JSON object per line: `timestamp`, `level`, `target`, the enclosing
+/// `spans` if any, and the event's fields including `message`
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert(
+            "level".to_string(),
+            metadata.level().as_str().to_ascii_lowercase().into(),
+        );
+        line.insert("target".to_string(), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope.from_root().map(|span| span.name().into()).collect();
+            line.insert("spans".to_string(), spans.into());
+        }
+
+        event.record(&mut JsonFields(&mut line));
+
+        let json = serde_json::to_string(&line).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", json)
+    }
+}
+
+/// Collects an event's fields into the JSON line
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonFields<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(JsonFormat)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("reload");
+            let _entered = span.enter();
+            tracing::warn!(service = "lidar", attempt = 3, "Restarting {}", "lidar");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert_eq!(line["level"], "warn");
+        assert_eq!(line["message"], "Restarting lidar");
+        assert_eq!(line["service"], "lidar");
+        assert_eq!(line["attempt"], 3);
+        assert_eq!(line["spans"], serde_json::json!(["reload"]));
+        assert!(line["target"].as_str().unwrap().starts_with("krill_daemon"));
+    }
+
+    #[test]
+    fn test_filter_handle_rejects_invalid_filter() {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let _subscriber = tracing_subscriber::registry().with(layer);
+        let handle = LogFilterHandle::new(handle, "info");
+
+        handle.set("info,krill_daemon::health=debug").unwrap();
+        assert_eq!(handle.filter(), "info,krill_daemon::health=debug");
+
+        assert!(matches!(
+            handle.set("krill_daemon=loud"),
+            Err(DaemonLogError::InvalidFilter { .. })
+        ));
+        assert_eq!(handle.filter(), "info,krill_daemon::health=debug");
+    }
+}
@@ -0,0 +1,203 @@
+// Daemonize - Detaching the daemon from the terminal that started it, and the
+// PID file keeping a second daemon of the same workspace from starting
+
+use crate::handoff::HANDOFF_TIMEOUT;
+use krill_common::{check_workspace_name, ConfigError};
+use nix::fcntl::{Flock, FlockArg};
+use nix::unistd::{fork, setsid, ForkResult};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::warn;
+
+/// How often a daemon taking over checks whether the PID file was released
+const RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Leave the session of the terminal that started the daemon: fork, start a
+/// new session, and fork again so that the daemon can never reacquire a
+/// controlling terminal. The calling process exits in the first fork.
+///
+/// Must run before the process starts any threads, i.e. before the tokio
+/// runtime is built. The working directory is kept, as recipes resolve
+/// relative paths against it.
+pub fn detach() -> io::Result<()> {
+    // SAFETY: the process is still single-threaded, and each parent exits
+    // without running atexit handlers or flushing buffers its child shares
+    if let ForkResult::Parent { .. } = unsafe { fork() }? {
+        unsafe { nix::libc::_exit(0) }
+    }
+    setsid()?;
+    // The session leader exits too, so the daemon is not one
+    if let ForkResult::Parent { .. } = unsafe { fork() }? {
+        unsafe { nix::libc::_exit(0) }
+    }
+    Ok(())
+}
+
+/// Read stdin from `/dev/null` and append stdout and stderr, panics
+/// included, to `log`
+pub fn redirect_output(log: &Path) -> io::Result<()> {
+    let null = File::open("/dev/null")?;
+    let log = OpenOptions::new().create(true).append(true).open(log)?;
+    nix::unistd::dup2_stdin(&null)?;
+    nix::unistd::dup2_stdout(&log)?;
+    nix::unistd::dup2_stderr(&log)?;
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum PidFileError {
+    #[error("a daemon of this workspace is already running (pid {pid})")]
+    Running { pid: u32 },
+
+    #[error("a daemon of this workspace is already running")]
+    Locked,
+
+    #[error("PID file {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+}
+
+/// `~/.krill/run/<workspace>.pid`, locked with `flock` for as long as the
+/// daemon holds it. The lock, not the file, tells whether a daemon runs: the
+/// file is left behind on exit and taken over by the next daemon.
+pub struct PidFile {
+    path: PathBuf,
+    lock: Flock<File>,
+}
+
+impl PidFile {
+    /// PID file of a workspace, `~/.krill/run/<workspace>.pid`. Fails for
+    /// a name that could point elsewhere, e.g. `../robot`.
+    pub fn default_path(workspace: &str) -> Result<PathBuf, ConfigError> {
+        check_workspace_name(workspace)?;
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        Ok(home
+            .join(".krill")
+            .join("run")
+            .join(format!("{}.pid", workspace)))
+    }
+
+    /// Lock the PID file and write this process's PID to it, failing if
+    /// another daemon holds it
+    pub fn acquire(path: &Path) -> Result<Self, PidFileError> {
+        let io_error = |source| PidFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        // Not truncated before the lock is held, so a running daemon's PID stays readable
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io_error)?;
+
+        let lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((_, nix::errno::Errno::EWOULDBLOCK)) => {
+                return Err(match Self::read_pid(path) {
+                    Some(pid) => PidFileError::Running { pid },
+                    None => PidFileError::Locked,
+                })
+            }
+            Err((_, errno)) => return Err(io_error(errno.into())),
+        };
+        let mut pid_file = Self {
+            path: path.to_path_buf(),
+            lock,
+        };
+        pid_file.write_pid().map_err(io_error)?;
+        Ok(pid_file)
+    }
+
+    /// Like [`acquire`](Self::acquire), but wait for the daemon holding the
+    /// PID file to exit, as the old daemon of a handoff does
+    pub async fn acquire_released(path: &Path) -> Result<Self, PidFileError> {
+        loop {
+            match Self::acquire(path) {
+                Err(PidFileError::Running { .. } | PidFileError::Locked) => {
+                    tokio::time::sleep(RELEASE_POLL_INTERVAL).await
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Take over the PID file of the daemon this one replaces in a handoff,
+    /// once it released it on exit. `None` if it does not within the handoff
+    /// timeout; the daemon then runs without one.
+    pub async fn take_over(path: &Path) -> Option<Self> {
+        match tokio::time::timeout(HANDOFF_TIMEOUT, Self::acquire_released(path)).await {
+            Ok(Ok(pid_file)) => Some(pid_file),
+            Ok(Err(e)) => {
+                warn!("Failed to take over the PID file: {}", e);
+                None
+            }
+            Err(_) => {
+                warn!("The previous daemon did not release the PID file");
+                None
+            }
+        }
+    }
+
+    /// Record the PID of this process, e.g. once [`detach`] forked it off
+    /// the process that took the lock
+    pub fn write_pid(&mut self) -> io::Result<()> {
+        self.lock.set_len(0)?;
+        self.lock.rewind()?;
+        writeln!(self.lock, "{}", std::process::id())
+    }
+
+    /// PID recorded in the file, whether or not that daemon still runs
+    pub fn read_pid(path: &Path) -> Option<u32> {
+        fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_lock() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("run").join("robot.pid");
+
+        let pid_file = PidFile::acquire(&path).unwrap();
+        assert_eq!(PidFile::read_pid(&path), Some(std::process::id()));
+
+        // A lock is held per open file, so a second one fails in-process too
+        match PidFile::acquire(&path) {
+            Err(PidFileError::Running { pid }) => assert_eq!(pid, std::process::id()),
+            other => panic!("expected Running, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(PidFile::read_pid(&path), Some(std::process::id()));
+
+        // The file outlives the daemon; only the lock is released
+        drop(pid_file);
+        assert!(path.exists());
+        std::fs::write(&path, "4242\n").unwrap();
+        let pid_file = PidFile::acquire(&path).unwrap();
+        assert_eq!(pid_file.path(), path);
+        assert_eq!(PidFile::read_pid(&path), Some(std::process::id()));
+    }
+
+    #[test]
+    fn test_default_path_rejects_paths() {
+        let path = PidFile::default_path("robot").unwrap();
+        assert!(path.ends_with(".krill/run/robot.pid"));
+        for name in ["../robot", "robot/x", ""] {
+            assert!(PidFile::default_path(name).is_err(), "{:?}", name);
+        }
+    }
+}
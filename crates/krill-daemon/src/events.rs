@@ -0,0 +1,86 @@
+// Event Journal - History of service status changes
+
+use chrono::{Duration as ChronoDuration, Utc};
+use krill_common::{EventRecord, ServiceStatus};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// Maximum events kept in memory
+pub const MAX_EVENTS: usize = 10_000;
+
+/// Ring buffer of recent events, optionally mirrored to a JSONL file
+pub struct EventJournal {
+    events: Mutex<VecDeque<EventRecord>>,
+    capacity: usize,
+    file: Option<Mutex<File>>,
+}
+
+impl EventJournal {
+    /// Journal that keeps events in memory only
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            capacity,
+            file: None,
+        }
+    }
+
+    /// Journal that also appends every event to `path` as a JSON line
+    pub fn with_file(capacity: usize, path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+            ..Self::new(capacity)
+        })
+    }
+
+    /// Path of the journal file inside a log session directory
+    pub fn session_path(session_dir: &Path) -> PathBuf {
+        session_dir.join("events.jsonl")
+    }
+
+    /// Record a status change, timestamped now
+    pub fn record(&self, service: &str, status: ServiceStatus) {
+        let event = EventRecord {
+            timestamp: Utc::now(),
+            service: service.to_string(),
+            status,
+        };
+
+        if let Some(ref file) = self.file {
+            if let Ok(json) = serde_json::to_string(&event) {
+                let mut file = file.lock().unwrap();
+                if let Err(e) = writeln!(file, "{}", json) {
+                    warn!("Failed to write event journal: {}", e);
+                }
+            }
+        }
+
+        let mut events = self.events.lock().unwrap();
+        events.push_back(event);
+        while events.len() > self.capacity {
+            events.pop_front();
+        }
+    }
+
+    /// Events of the last `since` (all if `None`), optionally for one service, oldest first
+    pub fn query(&self, since: Option<Duration>, service: Option<&str>) -> Vec<EventRecord> {
+        let cutoff = since
+            .and_then(|since| ChronoDuration::from_std(since).ok())
+            .and_then(|since| Utc::now().checked_sub_signed(since));
+
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| cutoff.is_none_or(|cutoff| event.timestamp >= cutoff))
+            .filter(|event| service.is_none_or(|service| event.service == service))
+            .cloned()
+            .collect()
+    }
+}
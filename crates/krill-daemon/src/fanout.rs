@@ -0,0 +1,400 @@
+// Fan-out - Per-client queues for the messages the daemon pushes to
+// clients, and what happens to clients that read slower than they arrive
+
+use krill_common::ServerMessage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// Log lines a client may have queued before the oldest are dropped
+pub const LOG_QUEUE_LEN: usize = 1000;
+
+/// Messages that must be delivered (status changes, snapshot deltas) a
+/// client may have queued before it is disconnected as too slow
+pub const MAX_LAG: usize = 1000;
+
+/// Delivery to one client since it connected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientLag {
+    /// Who the client is
+    pub client: String,
+    /// Messages queued and not yet written to the client
+    pub queued: usize,
+    /// Most messages that were queued at once
+    pub max_queued: usize,
+    /// Log lines dropped, and metrics replaced by newer ones, because the
+    /// client fell behind
+    pub dropped: u64,
+}
+
+/// A queued message and its place in the order the client gets them
+type Queued = (u64, ServerMessage);
+
+struct QueueState {
+    /// Messages that must be delivered
+    messages: VecDeque<Queued>,
+    /// Log lines, kept apart so the oldest is dropped without a search
+    logs: VecDeque<Queued>,
+    /// The latest resource usage
+    metrics: Option<Queued>,
+    /// Place of the next message queued
+    next: u64,
+    /// Whether the client asked for `snapshot_delta` messages
+    snapshots: bool,
+    /// Whether the client asked for service output without throttling
+    raw_logs: bool,
+    /// The client fell too far behind and is disconnected
+    lagged: bool,
+    lag: ClientLag,
+}
+
+impl QueueState {
+    fn queued(&self) -> usize {
+        self.messages.len() + self.logs.len() + usize::from(self.metrics.is_some())
+    }
+
+    /// The message queued first, from whichever lane holds it
+    fn pop(&mut self) -> Option<ServerMessage> {
+        let place = |queued: Option<&Queued>| queued.map_or(u64::MAX, |(place, _)| *place);
+        let message = place(self.messages.front());
+        let log = place(self.logs.front());
+        let metrics = place(self.metrics.as_ref());
+
+        let (_, popped) = if message < log && message < metrics {
+            self.messages.pop_front()?
+        } else if log < metrics {
+            self.logs.pop_front()?
+        } else {
+            self.metrics.take()?
+        };
+        self.lag.queued = self.queued();
+        Some(popped)
+    }
+}
+
+struct ClientQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+impl ClientQueue {
+    /// Queue `message`, unless it is a log line for clients that take
+    /// their output raw (`Some(true)`) or throttled (`Some(false)`) and this
+    /// one does not; `true` if the client now lags too far behind
+    fn push(
+        &self,
+        message: &ServerMessage,
+        raw_logs: Option<bool>,
+        log_queue_len: usize,
+        max_lag: usize,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.lagged || raw_logs.is_some_and(|raw| raw != state.raw_logs) {
+            return false;
+        }
+
+        let queued = (state.next, message.clone());
+        state.next += 1;
+        match message {
+            ServerMessage::SnapshotDelta { .. } if !state.snapshots => return false,
+            // Log lines are lossy: the oldest goes to make room
+            ServerMessage::LogLine { .. } => {
+                if state.logs.len() >= log_queue_len {
+                    state.logs.pop_front();
+                    state.lag.dropped += 1;
+                }
+                state.logs.push_back(queued);
+            }
+            // Only the latest resource usage matters
+            ServerMessage::ServiceMetrics { .. } => {
+                if state.metrics.replace(queued).is_some() {
+                    state.lag.dropped += 1;
+                }
+            }
+            // Everything else is delivered, or the client goes
+            _ => {
+                if state.messages.len() >= max_lag {
+                    state.lagged = true;
+                    state.messages.clear();
+                    state.logs.clear();
+                    state.metrics = None;
+                    state.lag.queued = 0;
+                    drop(state);
+                    self.notify.notify_one();
+                    return true;
+                }
+                state.messages.push_back(queued);
+            }
+        }
+
+        state.lag.queued = state.queued();
+        state.lag.max_queued = state.lag.max_queued.max(state.lag.queued);
+        drop(state);
+        self.notify.notify_one();
+        false
+    }
+}
+
+/// Delivers each message pushed to the daemon's clients through a queue of
+/// its own per client, so one stalled client holds up no other. A client
+/// that falls behind loses its oldest log lines first; one that falls
+/// [`MAX_LAG`] messages behind on anything else is disconnected rather
+/// than silently missing status changes.
+pub struct Fanout {
+    log_queue_len: usize,
+    max_lag: usize,
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, Arc<ClientQueue>>>,
+    disconnected: AtomicU64,
+}
+
+impl Default for Fanout {
+    fn default() -> Self {
+        Self::new(LOG_QUEUE_LEN, MAX_LAG)
+    }
+}
+
+impl Fanout {
+    pub fn new(log_queue_len: usize, max_lag: usize) -> Self {
+        Self {
+            log_queue_len: log_queue_len.max(1),
+            max_lag: max_lag.max(1),
+            next_id: AtomicU64::new(0),
+            clients: Mutex::new(HashMap::new()),
+            disconnected: AtomicU64::new(0),
+        }
+    }
+
+    /// Start queueing messages for a new client
+    pub fn subscribe(self: &Arc<Self>, client: String) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let queue = Arc::new(ClientQueue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                logs: VecDeque::new(),
+                metrics: None,
+                next: 0,
+                snapshots: false,
+                raw_logs: false,
+                lagged: false,
+                lag: ClientLag {
+                    client,
+                    ..Default::default()
+                },
+            }),
+            notify: Notify::new(),
+        });
+        self.clients.lock().unwrap().insert(id, Arc::clone(&queue));
+        Subscription {
+            id,
+            queue,
+            fanout: Arc::downgrade(self),
+        }
+    }
+
+    /// Queue `message` for every client
+    pub fn send(&self, message: ServerMessage) {
+        self.send_to(message, None);
+    }
+
+    /// Queue the log line `message` for the clients that asked for raw
+    /// output (`raw`), or for the others, which get throttled output
+    pub fn send_log(&self, message: ServerMessage, raw: bool) {
+        self.send_to(message, Some(raw));
+    }
+
+    fn send_to(&self, message: ServerMessage, raw_logs: Option<bool>) {
+        let clients: Vec<Arc<ClientQueue>> =
+            self.clients.lock().unwrap().values().cloned().collect();
+        for queue in clients {
+            if queue.push(&message, raw_logs, self.log_queue_len, self.max_lag) {
+                self.disconnected.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Disconnecting client {} that fell {} messages behind",
+                    queue.state.lock().unwrap().lag.client,
+                    self.max_lag
+                );
+            }
+        }
+    }
+
+    /// Delivery to each connected client, by connection number
+    pub fn lag(&self) -> HashMap<u64, ClientLag> {
+        self.clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, queue)| (*id, queue.state.lock().unwrap().lag.clone()))
+            .collect()
+    }
+
+    /// Clients disconnected for falling too far behind since the daemon started
+    pub fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+/// One client's queue, removed from the fan-out when dropped
+pub struct Subscription {
+    id: u64,
+    queue: Arc<ClientQueue>,
+    fanout: Weak<Fanout>,
+}
+
+impl Subscription {
+    /// Next queued message, or `None` once the client is disconnected for
+    /// falling too far behind
+    pub async fn recv(&self) -> Option<ServerMessage> {
+        loop {
+            {
+                let mut state = self.queue.state.lock().unwrap();
+                if state.lagged {
+                    return None;
+                }
+                if let Some(message) = state.pop() {
+                    return Some(message);
+                }
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Whether to queue `snapshot_delta` messages for the client
+    pub fn set_snapshots(&self, snapshots: bool) {
+        self.queue.state.lock().unwrap().snapshots = snapshots;
+    }
+
+    /// Whether to queue every line of service output for the client, or
+    /// the throttled output
+    pub fn set_raw_logs(&self, raw_logs: bool) {
+        self.queue.state.lock().unwrap().raw_logs = raw_logs;
+    }
+
+    /// Name the client by, e.g. once it introduced itself
+    pub fn set_client(&self, client: String) {
+        self.queue.state.lock().unwrap().lag.client = client;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(fanout) = self.fanout.upgrade() {
+            fanout.clients.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use krill_common::{LogStream, ServiceStatus};
+    use std::time::Duration;
+
+    fn log(line: &str) -> ServerMessage {
+        ServerMessage::LogLine {
+            service: "lidar".to_string(),
+            line: line.to_string(),
+            level: None,
+            timestamp: None,
+            stream: Some(LogStream::Stdout),
+        }
+    }
+
+    fn status(status: ServiceStatus) -> ServerMessage {
+        ServerMessage::StatusUpdate {
+            service: "lidar".to_string(),
+            status,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_drops_oldest_logs_but_keeps_events() {
+        let fanout = Arc::new(Fanout::new(2, 10));
+        let slow = fanout.subscribe("slow".to_string());
+
+        fanout.send(log("1"));
+        fanout.send(status(ServiceStatus::Running));
+        fanout.send(log("2"));
+        fanout.send(log("3"));
+        fanout.send(ServerMessage::ServiceMetrics {
+            services: HashMap::new(),
+        });
+        fanout.send(ServerMessage::ServiceMetrics {
+            services: HashMap::new(),
+        });
+
+        let lag = &fanout.lag()[&0];
+        assert_eq!(lag.client, "slow");
+        assert_eq!(lag.queued, 4);
+        assert_eq!(lag.dropped, 2);
+
+        assert_eq!(slow.recv().await, Some(status(ServiceStatus::Running)));
+        assert_eq!(slow.recv().await, Some(log("2")));
+        assert_eq!(slow.recv().await, Some(log("3")));
+        assert!(matches!(
+            slow.recv().await,
+            Some(ServerMessage::ServiceMetrics { .. })
+        ));
+        assert_eq!(fanout.lag()[&0].queued, 0);
+
+        // Snapshot deltas only reach clients that asked for them
+        let delta = ServerMessage::SnapshotDelta {
+            seq: 1,
+            changed: HashMap::new(),
+            removed: Vec::new(),
+        };
+        fanout.send(delta.clone());
+        slow.set_snapshots(true);
+        fanout.send(delta.clone());
+        assert_eq!(slow.recv().await, Some(delta));
+
+        drop(slow);
+        assert!(fanout.lag().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_client_lagging_on_events_is_disconnected() {
+        let fanout = Arc::new(Fanout::new(10, 3));
+        let slow = fanout.subscribe("slow".to_string());
+        let fast = fanout.subscribe("fast".to_string());
+
+        for _ in 0..3 {
+            fanout.send(status(ServiceStatus::Running));
+            assert_eq!(fast.recv().await, Some(status(ServiceStatus::Running)));
+        }
+        assert_eq!(fanout.disconnected(), 0);
+        fanout.send(status(ServiceStatus::Healthy));
+        assert_eq!(fanout.disconnected(), 1);
+
+        assert_eq!(slow.recv().await, None);
+        assert_eq!(fast.recv().await, Some(status(ServiceStatus::Healthy)));
+
+        // A waiting client wakes up to new messages
+        let waiter = tokio::spawn(async move { fast.recv().await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        fanout.send(log("late"));
+        assert_eq!(waiter.await.unwrap(), Some(log("late")));
+    }
+
+    #[tokio::test]
+    async fn test_log_lines_reach_raw_or_throttled_clients() {
+        let fanout = Arc::new(Fanout::new(10, 10));
+        let tui = fanout.subscribe("tui".to_string());
+        let raw = fanout.subscribe("raw".to_string());
+        raw.set_raw_logs(true);
+
+        fanout.send_log(log("spam"), true);
+        fanout.send_log(log("[krill] 9 identical lines suppressed"), false);
+        fanout.send(log("everyone"));
+
+        assert_eq!(
+            tui.recv().await,
+            Some(log("[krill] 9 identical lines suppressed"))
+        );
+        assert_eq!(tui.recv().await, Some(log("everyone")));
+        assert_eq!(raw.recv().await, Some(log("spam")));
+        assert_eq!(raw.recv().await, Some(log("everyone")));
+    }
+}
@@ -0,0 +1,413 @@
+// HTTP Gateway - JSON over HTTP for remote fleet tooling, behind a bearer token
+
+use crate::audit::AuditLog;
+use crate::ipc_server::CommandRequest;
+use crate::logging::LogStore;
+use crate::watchdog::Liveness;
+use krill_common::ipc::ServiceSnapshot;
+use krill_common::{ClientIdentity, CommandAction, KrillError};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration};
+use tracing::{debug, info, warn};
+
+/// Log lines returned when a request does not ask for a specific amount
+const DEFAULT_LOG_LINES: usize = 100;
+/// Upper bound for `?lines=` on the logs endpoint
+const MAX_LOG_LINES: usize = 1000;
+/// Largest request head (request line plus headers) the gateway accepts
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+/// Time a client gets to send its request
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum GatewayError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to read gateway token file {0}: {1}")]
+    TokenRead(PathBuf, String),
+
+    #[error("Gateway token file {0} is empty")]
+    EmptyToken(PathBuf),
+}
+
+/// Read the bearer token from a file, ignoring surrounding whitespace
+pub fn load_token(path: &Path) -> Result<String, GatewayError> {
+    let token = std::fs::read_to_string(path)
+        .map_err(|e| GatewayError::TokenRead(path.to_path_buf(), e.to_string()))?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(GatewayError::EmptyToken(path.to_path_buf()));
+    }
+    Ok(token.to_string())
+}
+
+/// Serves `GET /v1/status`, `POST /v1/services/{name}/restart`,
+/// `GET /v1/services/{name}/logs?lines=N` and `GET /v1/healthz`
+pub struct HttpGateway {
+    token: String,
+    command_tx: mpsc::UnboundedSender<CommandRequest>,
+    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    log_store: Option<Arc<LogStore>>,
+    liveness: Option<Arc<Liveness>>,
+    audit_log: Option<Arc<AuditLog>>,
+}
+
+impl HttpGateway {
+    pub fn new(
+        token: String,
+        command_tx: mpsc::UnboundedSender<CommandRequest>,
+        snapshot_req_tx: mpsc::UnboundedSender<
+            mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>,
+        >,
+        log_store: Option<Arc<LogStore>>,
+    ) -> Self {
+        Self {
+            token,
+            command_tx,
+            snapshot_req_tx,
+            log_store,
+            liveness: None,
+            audit_log: None,
+        }
+    }
+
+    /// Serve `GET /v1/healthz` from the daemon's self-heartbeat
+    pub fn set_liveness(&mut self, liveness: Arc<Liveness>) {
+        self.liveness = Some(liveness);
+    }
+
+    /// Record restart requests, and who sent them, in the given audit log
+    pub fn set_audit_log(&mut self, audit_log: Arc<AuditLog>) {
+        self.audit_log = Some(audit_log);
+    }
+
+    /// Bind `addr` and serve requests until the task is dropped
+    pub async fn start(self: Arc<Self>, addr: SocketAddr) -> Result<(), GatewayError> {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve(listener).await
+    }
+
+    /// Serve requests on an already bound listener
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), GatewayError> {
+        info!("HTTP gateway listening on {}", listener.local_addr()?);
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let gateway = Arc::clone(&self);
+            tokio::spawn(async move {
+                let client = ClientIdentity {
+                    address: Some(peer.to_string()),
+                    client: Some("http-gateway".to_string()),
+                    ..Default::default()
+                };
+                if let Err(e) = gateway.handle_connection(stream, &client).await {
+                    debug!("HTTP gateway connection from {} ended: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection<S>(&self, stream: S, client: &ClientIdentity) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+
+        let response = match time::timeout(REQUEST_TIMEOUT, read_request(&mut reader)).await {
+            Ok(Ok(Some(request))) => self.respond(&request, client).await,
+            Ok(Ok(None)) => Response::error(400, "Malformed request"),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => Response::error(408, "Request timed out"),
+        };
+
+        writer.write_all(&response.to_bytes()).await?;
+        writer.shutdown().await
+    }
+
+    async fn respond(&self, request: &Request, client: &ClientIdentity) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        if !self.authorized(request) {
+            warn!(
+                "Rejected unauthorized HTTP gateway request to {}",
+                request.path
+            );
+            if let ("POST", ["v1", "services", service, "restart"]) =
+                (request.method.as_str(), segments.as_slice())
+            {
+                let denied = KrillError::PermissionDenied("invalid bearer token".to_string());
+                self.audit(service, client, Err(&denied)).await;
+            }
+            return Response::error(401, "Missing or invalid bearer token");
+        }
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["v1", "status"]) => {
+                let services = self.snapshot().await;
+                Response::json(200, json!({ "services": services }))
+            }
+            ("POST", ["v1", "services", service, "restart"]) => {
+                let force = request.query_param("force") == Some("true");
+                let result = self.restart(service, force).await;
+                self.audit(service, client, result.as_ref().map(|_| ()))
+                    .await;
+                match result {
+                    Ok(()) => {
+                        Response::json(202, json!({ "service": service, "action": "restart" }))
+                    }
+                    Err(e) => e.into(),
+                }
+            }
+            ("GET", ["v1", "services", service, "logs"]) => {
+                if !self.snapshot().await.contains_key(*service) {
+                    return KrillError::ServiceNotFound(service.to_string()).into();
+                }
+                let limit = match request.query_param("lines") {
+                    Some(value) => match value.parse::<usize>() {
+                        Ok(n) => n.min(MAX_LOG_LINES),
+                        Err(_) => return Response::error(400, "'lines' must be a number"),
+                    },
+                    None => DEFAULT_LOG_LINES,
+                };
+                let lines = match self.log_store {
+                    Some(ref log_store) => log_store.get_logs(Some(service), limit).await,
+                    None => Vec::new(),
+                };
+                Response::json(200, json!({ "service": service, "lines": lines }))
+            }
+            ("GET", ["v1", "healthz"]) => match self.liveness {
+                Some(ref liveness) => {
+                    let status = if liveness.is_alive() { 200 } else { 503 };
+                    Response::json(status, liveness.report())
+                }
+                None => Response::error(404, "Not found"),
+            },
+            (_, ["v1", "status"])
+            | (_, ["v1", "healthz"])
+            | (_, ["v1", "services", _, "restart"])
+            | (_, ["v1", "services", _, "logs"]) => Response::error(405, "Method not allowed"),
+            _ => Response::error(404, "Not found"),
+        }
+    }
+
+    /// Send the restart of `service`, unless it is unknown, or protected
+    /// and not forced
+    async fn restart(&self, service: &str, force: bool) -> Result<(), KrillError> {
+        let Some(snapshot) = self.snapshot().await.remove(service) else {
+            return Err(KrillError::ServiceNotFound(service.to_string()));
+        };
+        if snapshot.protected && !force {
+            return Err(KrillError::Protected(service.to_string()));
+        }
+        self.command_tx
+            .send((CommandAction::Restart, Some(service.to_string())))
+            .map_err(|_| KrillError::ShuttingDown)
+    }
+
+    /// Record a restart request of `client` in the audit log
+    async fn audit(&self, service: &str, client: &ClientIdentity, result: Result<(), &KrillError>) {
+        if let Some(ref audit_log) = self.audit_log {
+            audit_log
+                .record(CommandAction::Restart, Some(service), client, result)
+                .await;
+        }
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), self.token.as_bytes()))
+    }
+
+    async fn snapshot(&self) -> HashMap<String, ServiceSnapshot> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        if self.snapshot_req_tx.send(tx).is_err() {
+            return HashMap::new();
+        }
+        rx.recv().await.unwrap_or_default()
+    }
+}
+
+/// Parsed request head; bodies are not used by any endpoint and are ignored
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    query: String,
+    headers: HashMap<String, String>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Read the request line and headers; `None` if they are malformed or too large
+pub(crate) async fn read_request<R>(reader: &mut BufReader<R>) -> std::io::Result<Option<Request>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = Vec::new();
+    let mut limited = reader.take(MAX_HEAD_BYTES as u64);
+    loop {
+        let read = limited.read_until(b'\n', &mut head).await?;
+        if read == 0 || head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+    }
+
+    let Ok(head) = String::from_utf8(head) else {
+        return Ok(None);
+    };
+    let mut lines = head.lines();
+
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Ok(None);
+    };
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+    }))
+}
+
+struct Response {
+    status: u16,
+    body: serde_json::Value,
+}
+
+impl From<KrillError> for Response {
+    fn from(err: KrillError) -> Self {
+        // Error codes follow HTTP status codes
+        Self::error(err.code() as u16, &err.to_string())
+    }
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self { status, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body.to_string();
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason_phrase(self.status),
+            body.len()
+        );
+        if self.status == 401 {
+            head.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(body.as_bytes());
+        bytes
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        428 => "Precondition Required",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Compare tokens without leaking the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(raw: &str) -> Option<Request> {
+        let mut reader = BufReader::new(raw.as_bytes());
+        read_request(&mut reader).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let request = parse(
+            "GET /v1/services/lidar/logs?lines=20 HTTP/1.1\r\nHost: robot\r\nAuthorization: Bearer abc\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/v1/services/lidar/logs");
+        assert_eq!(request.query_param("lines"), Some("20"));
+        assert_eq!(request.header("authorization"), Some("Bearer abc"));
+
+        assert!(parse("garbage\r\n\r\n").await.is_none());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[test]
+    fn test_load_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+
+        std::fs::write(&path, "s3cret\n").unwrap();
+        assert_eq!(load_token(&path).unwrap(), "s3cret");
+
+        std::fs::write(&path, "  \n").unwrap();
+        assert!(matches!(
+            load_token(&path),
+            Err(GatewayError::EmptyToken(_))
+        ));
+    }
+}
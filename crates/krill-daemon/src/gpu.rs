@@ -0,0 +1,235 @@
+// GPU - Detecting NVIDIA GPUs and probing their health with nvidia-smi
+
+use crate::reaper;
+use std::fmt;
+use std::process::Stdio;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+const NVIDIA_SMI: &str = "nvidia-smi";
+
+/// How long a query may take before `nvidia-smi` is killed; a wedged
+/// driver can hang it indefinitely
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clock event reasons that mean a GPU runs slower than it should, by their
+/// bit in `clocks_throttle_reasons.active`. Idle, application clock settings
+/// and the software power cap are normal operation.
+const THROTTLE_REASONS: [(u64, &str); 4] = [
+    (0x08, "hardware slowdown"),
+    (0x20, "thermal slowdown"),
+    (0x40, "hardware thermal slowdown"),
+    (0x80, "power brake slowdown"),
+];
+
+/// Why `nvidia-smi` could not tell about the GPUs
+#[derive(Debug, Error)]
+pub enum GpuError {
+    #[error("nvidia-smi could not be run: {0}")]
+    NotRun(#[from] std::io::Error),
+
+    #[error("nvidia-smi failed: {0}")]
+    Failed(String),
+
+    #[error("nvidia-smi did not answer within {0:?}")]
+    TimedOut(Duration),
+
+    #[error("nvidia-smi lists no GPUs")]
+    NoGpus,
+}
+
+/// A GPU `nvidia-smi` lists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gpu {
+    pub index: u32,
+    pub name: String,
+    pub memory_mb: u64,
+}
+
+impl fmt::Display for Gpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPU {}: {} ({} MiB)",
+            self.index, self.name, self.memory_mb
+        )
+    }
+}
+
+/// What `nvidia-smi` reports about a GPU's health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuHealth {
+    pub index: u32,
+    /// Active clock event reasons, a bitmask
+    pub throttle_reasons: u64,
+    /// Uncorrected ECC errors since the driver loaded; `None` without ECC
+    pub ecc_errors: Option<u64>,
+}
+
+impl GpuHealth {
+    /// What is wrong with the GPU, e.g. `GPU 0 is throttling (thermal slowdown)`
+    pub fn problem(&self) -> Option<String> {
+        let throttling: Vec<&str> = THROTTLE_REASONS
+            .iter()
+            .filter(|(bit, _)| self.throttle_reasons & bit != 0)
+            .map(|(_, reason)| *reason)
+            .collect();
+        let mut problems = Vec::new();
+        if !throttling.is_empty() {
+            problems.push(format!("is throttling ({})", throttling.join(", ")));
+        }
+        if let Some(errors) = self.ecc_errors.filter(|&errors| errors > 0) {
+            problems.push(format!("reports {} uncorrected ECC errors", errors));
+        }
+        (!problems.is_empty()).then(|| format!("GPU {} {}", self.index, problems.join(" and ")))
+    }
+}
+
+/// The GPUs of this machine, or why none could be found
+pub async fn detect() -> Result<Vec<Gpu>, GpuError> {
+    let gpus = parse_gpus(&query("index,name,memory.total").await?);
+    if gpus.is_empty() {
+        return Err(GpuError::NoGpus);
+    }
+    Ok(gpus)
+}
+
+/// Log the GPUs found at startup, warning when `services` need one but
+/// there is none, as they will not be started
+pub async fn log_detected(services: Vec<String>) {
+    match detect().await {
+        Ok(gpus) => {
+            for gpu in gpus {
+                info!("Detected {}", gpu);
+            }
+        }
+        Err(e) if !services.is_empty() => warn!(
+            "No GPU detected ({}); services requiring one will not start: {}",
+            e,
+            services.join(", ")
+        ),
+        Err(e) => info!("No GPU detected: {}", e),
+    }
+}
+
+/// Query the health of every GPU
+pub async fn probe() -> Result<Vec<GpuHealth>, GpuError> {
+    Ok(parse_health(
+        &query("index,clocks_throttle_reasons.active,ecc.errors.uncorrected.volatile.total")
+            .await?,
+    ))
+}
+
+/// Problems of all GPUs, or `None` if they are all fine
+pub fn problems(health: &[GpuHealth]) -> Option<String> {
+    let problems: Vec<String> = health.iter().filter_map(GpuHealth::problem).collect();
+    (!problems.is_empty()).then(|| problems.join("; "))
+}
+
+/// Standard output of a successful `nvidia-smi` query of `fields`. It
+/// reports driver failures on stdout, so that is the error when stderr is
+/// empty.
+async fn query(fields: &str) -> Result<String, GpuError> {
+    let mut command = tokio::process::Command::new(NVIDIA_SMI);
+    command
+        .arg(format!("--query-gpu={}", fields))
+        .arg("--format=csv,noheader,nounits")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(QUERY_TIMEOUT, reaper::output(&mut command))
+        .await
+        .map_err(|_| GpuError::TimedOut(QUERY_TIMEOUT))??;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if output.status.success() {
+        return Ok(stdout);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = [stderr.trim(), stdout.trim()]
+        .into_iter()
+        .find(|message| !message.is_empty())
+        .unwrap_or("no output");
+    Err(GpuError::Failed(message.to_string()))
+}
+
+/// Parse `index, name, memory.total` lines; malformed lines are skipped
+pub fn parse_gpus(output: &str) -> Vec<Gpu> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [index, name, memory] = fields[..] else {
+                return None;
+            };
+            Some(Gpu {
+                index: index.parse().ok()?,
+                name: name.to_string(),
+                memory_mb: memory.parse().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Parse `index, clocks_throttle_reasons.active, ecc.errors.uncorrected.volatile.total`
+/// lines. Fields a GPU does not support read `[N/A]` or `[Not Supported]`.
+pub fn parse_health(output: &str) -> Vec<GpuHealth> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [index, reasons, ecc] = fields[..] else {
+                return None;
+            };
+            let reasons = reasons.trim_start_matches("0x");
+            Some(GpuHealth {
+                index: index.parse().ok()?,
+                throttle_reasons: u64::from_str_radix(reasons, 16).unwrap_or(0),
+                ecc_errors: ecc.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gpus() {
+        let output = "0, NVIDIA RTX A2000 12GB, 12282\n1, Tesla T4, 15360\nNo devices were found\n";
+        let gpus = parse_gpus(output);
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(
+            gpus[0].to_string(),
+            "GPU 0: NVIDIA RTX A2000 12GB (12282 MiB)"
+        );
+        assert_eq!(gpus[1].index, 1);
+    }
+
+    #[test]
+    fn test_gpu_health_problems() {
+        let output =
+            "0, 0x0000000000000004, 0\n1, 0x0000000000000060, [N/A]\n2, 0x0000000000000001, 3\n";
+        let health = parse_health(output);
+        assert_eq!(health.len(), 3);
+
+        // Power-capped at full load is fine
+        assert_eq!(health[0].problem(), None);
+        assert_eq!(health[1].ecc_errors, None);
+        assert_eq!(
+            health[1].problem().unwrap(),
+            "GPU 1 is throttling (thermal slowdown, hardware thermal slowdown)"
+        );
+        assert_eq!(
+            health[2].problem().unwrap(),
+            "GPU 2 reports 3 uncorrected ECC errors"
+        );
+        assert_eq!(
+            problems(&health).unwrap(),
+            "GPU 1 is throttling (thermal slowdown, hardware thermal slowdown); GPU 2 reports 3 uncorrected ECC errors"
+        );
+        assert_eq!(problems(&health[..1]), None);
+    }
+}
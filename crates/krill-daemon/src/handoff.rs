@@ -0,0 +1,418 @@
+// Handoff - Passing the running services to an upgraded daemon without restarting them
+
+use crate::ipc_server::CommandRequest;
+use crate::reaper;
+use crate::state::ServiceRecord;
+use crate::workspaces::Workspaces;
+use krill_common::{CommandAction, KrillError, ServiceStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// Time the new daemon gets to connect and confirm the process table, and
+/// the old one to exit afterwards
+pub const HANDOFF_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Option naming the handoff socket a new daemon takes over from
+pub const HANDOFF_ARG: &str = "--handoff";
+
+/// Line the new daemon sends once it holds the process table
+const RECEIVED: &str = "received";
+
+/// A service as handed over to the next daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandedService {
+    pub status: ServiceStatus,
+    pub restart_count: u32,
+    /// Restarts since the workspace's daemon started, kept across upgrades
+    #[serde(default)]
+    pub total_restarts: u32,
+    /// Process of a running service
+    #[serde(default)]
+    pub process: Option<ServiceRecord>,
+    /// Read ends of the service's output pipes, left open across exec for
+    /// the next daemon
+    #[serde(default)]
+    pub stdout_fd: Option<i32>,
+    #[serde(default)]
+    pub stderr_fd: Option<i32>,
+}
+
+#[cfg(unix)]
+impl HandedService {
+    /// Take ownership of the output pipes inherited from the previous daemon
+    pub fn take_output(&mut self) -> (Option<std::os::fd::OwnedFd>, Option<std::os::fd::OwnedFd>) {
+        use std::os::fd::FromRawFd;
+
+        let own = |fd: Option<i32>| {
+            // SAFETY: the previous daemon left the fd open for this process,
+            // which does not use it otherwise; F_GETFD checks it is open
+            fd.filter(|&fd| unsafe { nix::libc::fcntl(fd, nix::libc::F_GETFD) } != -1)
+                .map(|fd| unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) })
+        };
+        (own(self.stdout_fd.take()), own(self.stderr_fd.take()))
+    }
+}
+
+/// Connection to the new daemon, held by the old one until it exits
+#[cfg(unix)]
+pub type HandoffStream = tokio::net::UnixStream;
+#[cfg(not(unix))]
+pub type HandoffStream = std::convert::Infallible;
+
+/// Services of the old daemon, sent as one JSON line over the handoff socket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessTable {
+    pub daemon_pid: u32,
+    pub services: BTreeMap<String, HandedService>,
+}
+
+/// Handoff socket of the daemon listening on `socket`
+pub fn handoff_path(socket: &Path) -> PathBuf {
+    let mut path = socket.as_os_str().to_owned();
+    path.push(".handoff");
+    PathBuf::from(path)
+}
+
+/// Hand the services over to `binary` (by default this daemon's executable)
+/// while watching `commands` for an emergency stop, which calls the upgrade
+/// off. The IPC server refuses other commands until the upgrade is over.
+/// Returns the handoff connection if the new daemon took over.
+pub async fn upgrade(
+    workspaces: &Workspaces,
+    socket: &Path,
+    binary: Option<PathBuf>,
+    commands: &mut mpsc::UnboundedReceiver<CommandRequest>,
+) -> Option<HandoffStream> {
+    let handoff = hand_over(workspaces, socket, binary);
+    tokio::pin!(handoff);
+    let result = loop {
+        tokio::select! {
+            result = &mut handoff => break result,
+            command = commands.recv() => match command {
+                Some((CommandAction::EmergencyStop, _)) => {
+                    warn!("Emergency stop during the upgrade; keeping this daemon");
+                    // Dropping the handoff kills the new daemon
+                    workspaces.emergency_stop().await;
+                    return None;
+                }
+                Some((action, _)) => warn!("Ignoring {:?} sent during the upgrade", action),
+                None => break (&mut handoff).await,
+            },
+        }
+    };
+    match result {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            error!("Upgrade failed, keeping this daemon: {}", e);
+            None
+        }
+    }
+}
+
+/// The new daemon while it takes over, killed if the handoff does not
+/// complete, e.g. because it timed out or was called off
+#[cfg(unix)]
+struct Successor(Option<tokio::process::Child>);
+
+#[cfg(unix)]
+impl Drop for Successor {
+    fn drop(&mut self) {
+        if let Some(child) = self.0.as_mut() {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+/// Handoff socket, removed again once the handoff is over
+#[cfg(unix)]
+struct HandoffListener {
+    path: PathBuf,
+    listener: tokio::net::UnixListener,
+}
+
+#[cfg(unix)]
+impl HandoffListener {
+    /// The handoff socket of an abstract socket is abstract too
+    fn bind(path: PathBuf) -> Result<Self, KrillError> {
+        let is_file = krill_common::abstract_socket_name(&path).is_none();
+        if is_file {
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = krill_common::bind_unix_socket(&path)
+            .and_then(tokio::net::UnixListener::from_std)
+            .map_err(|e| {
+                KrillError::Internal(format!("Failed to bind handoff socket {:?}: {}", path, e))
+            })?;
+        if is_file {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+        }
+        Ok(Self { path, listener })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for HandoffListener {
+    fn drop(&mut self) {
+        if krill_common::abstract_socket_name(&self.path).is_none() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Start `binary` (by default this daemon's executable) with this daemon's
+/// arguments and hand it the process table. Returns the handoff connection,
+/// which must stay open until this daemon exits: its closing tells the new
+/// daemon that the socket and ports are free.
+#[cfg(unix)]
+pub async fn hand_over(
+    workspaces: &Workspaces,
+    socket: &Path,
+    binary: Option<PathBuf>,
+) -> Result<HandoffStream, KrillError> {
+    let handoff = HandoffListener::bind(handoff_path(socket))?;
+    let table = ProcessTable {
+        daemon_pid: std::process::id(),
+        services: workspaces.process_table().await,
+    };
+    let mut successor = Successor(Some(spawn_successor(binary, &handoff.path, &table)?));
+    let child = successor.0.as_mut().expect("successor was just spawned");
+    let stream = tokio::time::timeout(
+        HANDOFF_TIMEOUT,
+        send_table(&handoff.listener, child, &table),
+    )
+    .await
+    .unwrap_or_else(|_| Err(KrillError::Timeout("the new daemon".to_string())))?;
+
+    // The new daemon runs on after this one exits
+    successor.0 = None;
+    Ok(stream)
+}
+
+#[cfg(not(unix))]
+pub async fn hand_over(
+    _workspaces: &Workspaces,
+    _socket: &Path,
+    _binary: Option<PathBuf>,
+) -> Result<HandoffStream, KrillError> {
+    Err(KrillError::Unsupported("Daemon upgrade".to_string()))
+}
+
+/// Spawn the next daemon in its own session, with the output pipes of the
+/// services inherited
+#[cfg(unix)]
+fn spawn_successor(
+    binary: Option<PathBuf>,
+    handoff: &Path,
+    table: &ProcessTable,
+) -> Result<tokio::process::Child, KrillError> {
+    use std::process::Stdio;
+
+    let binary = match binary {
+        Some(binary) => binary,
+        None => own_executable()?,
+    };
+
+    // This daemon's own startup pipe is gone, and a handoff it took over from is done
+    let mut args = Vec::new();
+    let mut original = std::env::args_os().skip(1);
+    while let Some(arg) = original.next() {
+        if arg == "--startup-pipe-fd" || arg == HANDOFF_ARG {
+            original.next();
+            continue;
+        }
+        if arg.to_str().is_some_and(|arg| {
+            arg.starts_with("--startup-pipe-fd=") || arg.starts_with(&format!("{}=", HANDOFF_ARG))
+        }) {
+            continue;
+        }
+        args.push(arg);
+    }
+    args.push(HANDOFF_ARG.into());
+    args.push(handoff.as_os_str().to_owned());
+
+    let fds: Vec<i32> = table
+        .services
+        .values()
+        .flat_map(|service| [service.stdout_fd, service.stderr_fd])
+        .flatten()
+        .collect();
+
+    let mut command = tokio::process::Command::new(&binary);
+    command
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: only async-signal-safe system calls run between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            nix::unistd::setsid()?;
+            for &fd in &fds {
+                if nix::libc::fcntl(fd, nix::libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    info!("Starting new daemon {:?} to take over", binary);
+    reaper::spawn(&mut command).map_err(|e| KrillError::SpawnFailed(format!("{:?}: {}", binary, e)))
+}
+
+/// Path of the running executable. After a package upgrade replaced the
+/// file, Linux reports it as deleted; the replacement is what to start.
+pub fn own_executable() -> Result<PathBuf, KrillError> {
+    let exe = std::env::current_exe()
+        .map_err(|e| KrillError::Internal(format!("Failed to locate own executable: {}", e)))?;
+    Ok(
+        match exe.to_str().and_then(|exe| exe.strip_suffix(" (deleted)")) {
+            Some(replaced) => PathBuf::from(replaced),
+            None => exe,
+        },
+    )
+}
+
+/// Send the table to the new daemon once it connects and wait for its confirmation
+#[cfg(unix)]
+async fn send_table(
+    listener: &tokio::net::UnixListener,
+    child: &mut tokio::process::Child,
+    table: &ProcessTable,
+) -> Result<tokio::net::UnixStream, KrillError> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let io_error = |e: io::Error| KrillError::Internal(format!("Handoff failed: {}", e));
+    // The table carries the services' output pipes: only the new daemon
+    // gets it, however many other local processes connect
+    let successor = child.id().map(|pid| pid as i32);
+    let stream = loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted.map_err(io_error)?.0,
+            status = child.wait() => {
+                return Err(KrillError::SpawnFailed(format!(
+                    "the new daemon exited before taking over ({}); see its krill.log",
+                    status.map_or_else(|e| e.to_string(), |status| status.to_string())
+                )));
+            }
+        };
+        let peer = stream.peer_cred().ok().and_then(|cred| cred.pid());
+        if peer.is_some() && peer == successor {
+            break stream;
+        }
+        warn!(
+            "Refusing handoff connection from process {:?}, not the new daemon",
+            peer
+        );
+    };
+
+    let mut stream = BufReader::new(stream);
+    let json = serde_json::to_string(table)
+        .map_err(|e| KrillError::Internal(format!("Failed to serialize process table: {}", e)))?;
+    stream
+        .get_mut()
+        .write_all(format!("{}\n", json).as_bytes())
+        .await
+        .map_err(io_error)?;
+
+    let mut line = String::new();
+    stream.read_line(&mut line).await.map_err(io_error)?;
+    if line.trim() != RECEIVED {
+        return Err(KrillError::Internal(
+            "the new daemon did not confirm the process table".to_string(),
+        ));
+    }
+    Ok(stream.into_inner())
+}
+
+/// Take the process table from the daemon being replaced, then wait for it
+/// to exit so that its socket and ports are free
+#[cfg(unix)]
+pub async fn receive(handoff: &Path) -> io::Result<ProcessTable> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let timed_out = |_| io::Error::new(io::ErrorKind::TimedOut, "previous daemon did not respond");
+    let stream = tokio::net::UnixStream::from_std(krill_common::connect_unix_socket(handoff)?)?;
+    let mut stream = BufReader::new(stream);
+
+    let mut line = String::new();
+    tokio::time::timeout(HANDOFF_TIMEOUT, stream.read_line(&mut line))
+        .await
+        .map_err(timed_out)??;
+    let table: ProcessTable =
+        serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    stream
+        .get_mut()
+        .write_all(format!("{}\n", RECEIVED).as_bytes())
+        .await?;
+
+    // The old daemon holds the connection open until it exits
+    let mut rest = Vec::new();
+    tokio::time::timeout(HANDOFF_TIMEOUT, stream.read_to_end(&mut rest))
+        .await
+        .map_err(timed_out)??;
+    Ok(table)
+}
+
+#[cfg(not(unix))]
+pub async fn receive(_handoff: &Path) -> io::Result<ProcessTable> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "daemon upgrades are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_table_round_trip() {
+        let table = ProcessTable {
+            daemon_pid: 4242,
+            services: BTreeMap::from([
+                (
+                    "lidar".to_string(),
+                    HandedService {
+                        status: ServiceStatus::Healthy,
+                        restart_count: 2,
+                        total_restarts: 7,
+                        process: Some(ServiceRecord {
+                            pid: 100,
+                            pgid: Some(100),
+                            start_ticks: Some(12345),
+                            started_at: chrono::Utc::now(),
+                        }),
+                        stdout_fd: Some(17),
+                        stderr_fd: Some(18),
+                    },
+                ),
+                (
+                    "planner".to_string(),
+                    HandedService {
+                        status: ServiceStatus::Stopped,
+                        restart_count: 0,
+                        total_restarts: 0,
+                        process: None,
+                        stdout_fd: None,
+                        stderr_fd: None,
+                    },
+                ),
+            ]),
+        };
+        let json = serde_json::to_string(&table).unwrap();
+        assert!(!json.contains('\n'));
+        assert_eq!(serde_json::from_str::<ProcessTable>(&json).unwrap(), table);
+
+        assert_eq!(
+            handoff_path(Path::new("/run/user/1000/krill-robot.sock")),
+            PathBuf::from("/run/user/1000/krill-robot.sock.handoff")
+        );
+    }
+}
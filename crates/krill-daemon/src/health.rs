@@ -0,0 +1,592 @@
+// Health Monitor - Executes configured health checks for running services
+
+use crate::orchestrator::ServiceEvent;
+use crate::reaper;
+use crate::runner::{ServiceRunner, ServiceState};
+use crate::watchdog;
+use krill_common::{
+    lifecycle_get_command, parse_container_health, parse_lifecycle_state, HealthChecker,
+    HealthError, LifecycleState,
+};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::time::{self, Duration};
+use tracing::{debug, warn};
+
+/// Interval between consecutive health checks of a service
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Timeout for HTTP checks, which have no configurable timeout
+const HTTP_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Timeout for reading a container's health with `docker inspect`
+const CONTAINER_INSPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct HealthMonitor {
+    runners: Arc<RwLock<HashMap<String, Arc<Mutex<ServiceRunner>>>>>,
+    event_tx: mpsc::UnboundedSender<ServiceEvent>,
+}
+
+impl HealthMonitor {
+    pub fn new(
+        runners: Arc<RwLock<HashMap<String, Arc<Mutex<ServiceRunner>>>>>,
+        event_tx: mpsc::UnboundedSender<ServiceEvent>,
+    ) -> Self {
+        Self { runners, event_tx }
+    }
+
+    /// Spawn tasks that periodically check a service while its current process is alive
+    pub fn start_service_monitoring(self: &Arc<Self>, service_name: &str) {
+        self.start_readiness_probe(service_name);
+        self.start_container_health_monitoring(service_name);
+
+        let monitor = Arc::clone(self);
+        let service_name = service_name.to_string();
+
+        tokio::spawn(async move {
+            let runner = match monitor.runners.read().await.get(&service_name) {
+                Some(r) => Arc::clone(r),
+                None => return,
+            };
+
+            let pid = {
+                let runner_guard = runner.lock().await;
+                if runner_guard.health_checker().is_none() {
+                    return;
+                }
+                runner_guard.pid()
+            };
+
+            let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                match next_round(&runner, pid).await {
+                    Round::Check => {}
+                    Round::Skip => continue,
+                    Round::Stop => break,
+                }
+
+                monitor.trigger_health_check(&service_name).await;
+            }
+
+            debug!("Health monitoring for '{}' stopped", service_name);
+        });
+    }
+
+    /// Spawn a task that runs the readiness check until it passes once
+    fn start_readiness_probe(self: &Arc<Self>, service_name: &str) {
+        let monitor = Arc::clone(self);
+        let service_name = service_name.to_string();
+
+        tokio::spawn(async move {
+            let runner = match monitor.runners.read().await.get(&service_name) {
+                Some(r) => Arc::clone(r),
+                None => return,
+            };
+
+            let (pid, checker) = {
+                let runner_guard = runner.lock().await;
+                match runner_guard.readiness_checker() {
+                    Some(checker) => (runner_guard.pid(), checker.clone()),
+                    None => return,
+                }
+            };
+
+            let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                match next_round(&runner, pid).await {
+                    Round::Check => {}
+                    Round::Skip => continue,
+                    Round::Stop => break,
+                }
+
+                match run_check(&checker).await {
+                    Ok(()) => {
+                        let mut runner_guard = runner.lock().await;
+                        if runner_guard.pid() == pid {
+                            runner_guard.mark_ready();
+                        }
+                        break;
+                    }
+                    Err(e) => debug!("Readiness check for '{}' failed: {}", service_name, e),
+                }
+            }
+        });
+    }
+
+    /// Spawn a task that mirrors the HEALTHCHECK status of a container into the
+    /// service state; only for docker and podman services without a `health_check`
+    fn start_container_health_monitoring(self: &Arc<Self>, service_name: &str) {
+        let monitor = Arc::clone(self);
+        let service_name = service_name.to_string();
+
+        tokio::spawn(async move {
+            let runner = match monitor.runners.read().await.get(&service_name) {
+                Some(r) => Arc::clone(r),
+                None => return,
+            };
+
+            let (pid, health_command) = {
+                let runner_guard = runner.lock().await;
+                if runner_guard.health_checker().is_some() {
+                    return;
+                }
+                match runner_guard.container_name() {
+                    Some(container) if runner_guard.is_single_container() => (
+                        runner_guard.pid(),
+                        runner_guard.container_engine().health_command(&container),
+                    ),
+                    _ => return,
+                }
+            };
+
+            let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                match next_round(&runner, pid).await {
+                    Round::Check => {}
+                    Round::Skip => continue,
+                    Round::Stop => break,
+                }
+
+                // Images without a HEALTHCHECK never give a verdict
+                let Some(healthy) = container_health(&health_command).await else {
+                    continue;
+                };
+
+                // Gone, or paused while the engine was asked
+                let mut runner_guard = runner.lock().await;
+                if runner_guard.pid() != pid {
+                    break;
+                }
+                if !is_alive(&runner_guard.state()) {
+                    continue;
+                }
+                monitor.record_health(&service_name, &mut runner_guard, healthy);
+            }
+
+            debug!("Container health monitoring for '{}' stopped", service_name);
+        });
+    }
+
+    /// Run the configured health check once and update the service state.
+    /// Returns `None` if the service has no health check or no verdict yet.
+    pub async fn trigger_health_check(&self, service_name: &str) -> Option<bool> {
+        let runner = Arc::clone(self.runners.read().await.get(service_name)?);
+
+        // Release the runner lock while the check runs
+        let checker = runner.lock().await.health_checker()?.clone();
+
+        let (healthy, failure) = match &checker {
+            HealthChecker::Lifecycle { node, timeout } => {
+                return self
+                    .check_lifecycle(service_name, &runner, node, *timeout)
+                    .await;
+            }
+            HealthChecker::Heartbeat { last_seen, .. } => {
+                if last_seen.is_none() {
+                    return None;
+                }
+                // Heartbeat misses are explained by the service's own metadata
+                (!checker.is_timed_out(), None)
+            }
+            _ => match run_check(&checker).await {
+                Ok(()) => (true, None),
+                Err(e) => {
+                    debug!("Health check for '{}' failed: {}", service_name, e);
+                    (false, Some(e.to_string()))
+                }
+            },
+        };
+
+        let mut runner_guard = runner.lock().await;
+        if !is_alive(&runner_guard.state()) {
+            return None;
+        }
+
+        runner_guard.set_health_failure(failure);
+        self.record_health(service_name, &mut runner_guard, healthy);
+
+        Some(healthy)
+    }
+
+    /// Query the state of a service's lifecycle node and apply it. A node
+    /// that cannot be queried counts as unhealthy; a finalized node will not
+    /// come back, which the orchestrator acts on by stopping its service.
+    async fn check_lifecycle(
+        &self,
+        service_name: &str,
+        runner: &Mutex<ServiceRunner>,
+        node: &str,
+        timeout: Duration,
+    ) -> Option<bool> {
+        let state = lifecycle_state(node, timeout).await;
+
+        let mut runner_guard = runner.lock().await;
+        if !is_alive(&runner_guard.state()) {
+            return None;
+        }
+
+        let previous = runner_guard.get_status();
+        match state {
+            Some(state) => runner_guard.update_lifecycle(state),
+            None => runner_guard.update_health(false),
+        }
+        let status = runner_guard.get_status();
+        if status != previous {
+            let _ = self.event_tx.send((service_name.to_string(), status));
+        }
+
+        Some(state == Some(LifecycleState::Active))
+    }
+
+    /// Apply a health verdict, emitting an event if the status changes
+    fn record_health(&self, service_name: &str, runner: &mut ServiceRunner, healthy: bool) {
+        let previous = runner.get_status();
+        runner.update_health(healthy);
+        let status = runner.get_status();
+        if status != previous {
+            let _ = self.event_tx.send((service_name.to_string(), status));
+        }
+    }
+}
+
+/// What a monitoring task does on its next tick
+enum Round {
+    Check,
+    Skip,
+    Stop,
+}
+
+/// Check a service on this tick unless it is paused, and stop once the
+/// process the task was started for (`pid`) is gone
+async fn next_round(runner: &Mutex<ServiceRunner>, pid: Option<u32>) -> Round {
+    let runner = runner.lock().await;
+    if runner.pid() != pid {
+        return Round::Stop;
+    }
+    match runner.state() {
+        // Not checked while frozen, and picked up again once resumed
+        ServiceState::Paused => Round::Skip,
+        state if !is_alive(&state) => Round::Stop,
+        _ => Round::Check,
+    }
+}
+
+fn is_alive(state: &ServiceState) -> bool {
+    matches!(
+        state,
+        ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+    )
+}
+
+/// Execute a health check once
+pub async fn run_check(checker: &HealthChecker) -> Result<(), HealthError> {
+    match checker {
+        HealthChecker::Heartbeat { .. } => {
+            if checker.is_timed_out() {
+                Err(HealthError::Timeout)
+            } else {
+                Ok(())
+            }
+        }
+        HealthChecker::Tcp { port, timeout } => check_tcp(*port, *timeout).await,
+        HealthChecker::Http {
+            port,
+            path,
+            expected_status,
+            headers,
+        } => check_http(*port, path, *expected_status, headers, HTTP_CHECK_TIMEOUT).await,
+        HealthChecker::Script { command, timeout } => check_script(command, *timeout).await,
+        HealthChecker::Lifecycle { node, timeout } => match lifecycle_state(node, *timeout).await {
+            Some(LifecycleState::Active) => Ok(()),
+            Some(state) => Err(HealthError::CheckFailed(format!(
+                "Lifecycle node '{}' is {:?}",
+                node, state
+            ))),
+            None => Err(HealthError::CheckFailed(format!(
+                "Lifecycle node '{}' did not report a state",
+                node
+            ))),
+        },
+    }
+}
+
+async fn check_tcp(port: u16, timeout: Duration) -> Result<(), HealthError> {
+    match time::timeout(timeout, TcpStream::connect(("127.0.0.1", port))).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(HealthError::CheckFailed(format!(
+            "TCP connect to port {} failed: {}",
+            port, e
+        ))),
+        Err(_) => Err(HealthError::Timeout),
+    }
+}
+
+/// Send `GET http://localhost:port/path` and compare the response status
+pub async fn check_http(
+    port: u16,
+    path: &str,
+    expected_status: u16,
+    headers: &HashMap<String, String>,
+    timeout: Duration,
+) -> Result<(), HealthError> {
+    let request = build_http_request(port, path, headers);
+
+    let response = time::timeout(timeout, async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        stream.write_all(request.as_bytes()).await?;
+
+        // Only the status line is needed
+        let mut buf = vec![0u8; 512];
+        let mut len = 0;
+        while len < buf.len() {
+            let n = stream.read(&mut buf[len..]).await?;
+            if n == 0 || buf[..len + n].contains(&b'\n') {
+                len += n;
+                break;
+            }
+            len += n;
+        }
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&buf[..len]).into_owned())
+    })
+    .await
+    .map_err(|_| HealthError::Timeout)?
+    .map_err(|e| {
+        HealthError::CheckFailed(format!("HTTP request to port {} failed: {}", port, e))
+    })?;
+
+    let status = parse_status_line(&response).ok_or_else(|| {
+        HealthError::CheckFailed(format!("Invalid HTTP response from port {}", port))
+    })?;
+
+    if status == expected_status {
+        Ok(())
+    } else {
+        Err(HealthError::CheckFailed(format!(
+            "HTTP status {} (expected {})",
+            status, expected_status
+        )))
+    }
+}
+
+fn build_http_request(port: u16, path: &str, headers: &HashMap<String, String>) -> String {
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost:{}\r\nConnection: close\r\n",
+        path, port
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request
+}
+
+/// Extract the status code from an HTTP status line, e.g. `HTTP/1.1 200 OK`
+fn parse_status_line(response: &str) -> Option<u16> {
+    let line = response.lines().next()?;
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// Health of a container per its HEALTHCHECK, read with `cmd` (see
+/// `ContainerEngine::health_command`), or `None` without a verdict
+async fn container_health(cmd: &[String]) -> Option<bool> {
+    let container = cmd.last().map(String::as_str).unwrap_or_default();
+    let output = time::timeout(
+        CONTAINER_INSPECT_TIMEOUT,
+        reaper::output(
+            Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true),
+        ),
+    )
+    .await;
+
+    match output {
+        Ok(Ok(output)) if output.status.success() => {
+            parse_container_health(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            debug!("Failed to inspect container '{}': {}", container, e);
+            None
+        }
+        Err(_) => {
+            warn!("Inspecting container '{}' timed out", container);
+            None
+        }
+    }
+}
+
+/// State of a ROS2 lifecycle node, or `None` if it cannot be queried
+async fn lifecycle_state(node: &str, timeout: Duration) -> Option<LifecycleState> {
+    let cmd = lifecycle_get_command(node);
+    let output = time::timeout(
+        timeout,
+        reaper::output(
+            Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true),
+        ),
+    )
+    .await;
+
+    match output {
+        Ok(Ok(output)) if output.status.success() => {
+            parse_lifecycle_state(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            debug!("Failed to query lifecycle node '{}': {}", node, e);
+            None
+        }
+        Err(_) => {
+            warn!("Querying lifecycle node '{}' timed out", node);
+            None
+        }
+    }
+}
+
+async fn check_script(command: &str, timeout: Duration) -> Result<(), HealthError> {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    // Its own process group, so a timeout kills whatever the script started too
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = reaper::spawn(watchdog::hide_notify_socket(&mut cmd))
+        .map_err(|e| HealthError::CheckFailed(format!("Failed to run '{}': {}", command, e)))?;
+
+    match time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(HealthError::CheckFailed(format!(
+            "'{}' exited with {}",
+            command, status
+        ))),
+        Ok(Err(e)) => Err(HealthError::CheckFailed(e.to_string())),
+        Err(_) => {
+            warn!("Health check script '{}' timed out, killing it", command);
+            #[cfg(unix)]
+            if let Some(pid) = child.id() {
+                let _ = krill_common::kill_process_group(pid, nix::sys::signal::Signal::SIGKILL);
+            }
+            let _ = child.kill().await;
+            Err(HealthError::CommandTimeout {
+                command: command.to_string(),
+                timeout,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn serve_once(response: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(parse_status_line("HTTP/1.1 200 OK\r\n"), Some(200));
+        assert_eq!(
+            parse_status_line("HTTP/1.0 503 Service Unavailable"),
+            Some(503)
+        );
+        assert_eq!(parse_status_line("garbage"), None);
+        assert_eq!(parse_status_line(""), None);
+    }
+
+    #[test]
+    fn test_build_http_request_includes_headers() {
+        let mut headers = HashMap::new();
+        headers.insert("X-Token".to_string(), "abc".to_string());
+
+        let request = build_http_request(8080, "healthz", &headers);
+        assert!(request.starts_with("GET /healthz HTTP/1.1\r\n"));
+        assert!(request.contains("Host: localhost:8080\r\n"));
+        assert!(request.contains("X-Token: abc\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_http_check_expected_status() {
+        let port = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await;
+        let result = check_http(port, "/health", 200, &HashMap::new(), HTTP_CHECK_TIMEOUT).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_http_check_unexpected_status() {
+        let port = serve_once("HTTP/1.1 503 Service Unavailable\r\n\r\n").await;
+        let result = check_http(port, "/health", 200, &HashMap::new(), HTTP_CHECK_TIMEOUT).await;
+        assert!(matches!(result, Err(HealthError::CheckFailed(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_script_check_timeout_kills_its_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("child.pid");
+        let command = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+
+        let result = check_script(&command, Duration::from_millis(300)).await;
+        assert!(matches!(result, Err(HealthError::CommandTimeout { .. })));
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        // Killed, at most left as a zombie until it is reaped
+        time::sleep(Duration::from_millis(100)).await;
+        let stat =
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    }
+
+    #[tokio::test]
+    async fn test_script_check_exit_status() {
+        assert!(check_script("true", Duration::from_secs(5)).await.is_ok());
+        assert!(matches!(
+            check_script("exit 3", Duration::from_secs(5)).await,
+            Err(HealthError::CheckFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+// Heartbeat Limiter - Per-service token buckets protecting the heartbeat path
+
+use crate::ipc_server::HeartbeatMessage;
+use krill_common::ServiceStatus;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Heartbeats per second a service may sustain
+pub const HEARTBEAT_RATE: f64 = 10.0;
+
+/// Heartbeats a service may send at once before it is limited
+pub const HEARTBEAT_BURST: f64 = 20.0;
+
+/// How often coalesced heartbeats are retried
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Heartbeats seen for one service since the daemon started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeartbeatCounters {
+    pub received: u64,
+    pub dropped: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// Status and metadata of the last forwarded heartbeat
+    last: Option<(ServiceStatus, HashMap<String, String>)>,
+    /// Newest limited heartbeat that changes what was last forwarded
+    pending: Option<HeartbeatMessage>,
+    counters: HeartbeatCounters,
+}
+
+impl Bucket {
+    fn new(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            refilled: now,
+            last: None,
+            pending: None,
+            counters: HeartbeatCounters::default(),
+        }
+    }
+
+    /// Refill for the time passed and take a token if one is available
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn forward(&mut self, message: HeartbeatMessage) -> HeartbeatMessage {
+        self.pending = None;
+        self.last = Some((message.1.clone(), message.2.clone()));
+        message
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    /// Services heartbeats are accepted for, so clients inventing names
+    /// grow neither the table nor the exported metrics
+    services: HashSet<String>,
+    buckets: HashMap<String, Bucket>,
+}
+
+/// Rate limits heartbeats per service before they reach the orchestrator.
+/// Heartbeats over the limit that repeat the last forwarded one are dropped;
+/// one that changes the status or metadata is held back and forwarded once
+/// the service has a token again, replaced by any newer one meanwhile.
+/// Heartbeats for services not set with `set_services` are dropped.
+pub struct HeartbeatLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for HeartbeatLimiter {
+    fn default() -> Self {
+        Self::new(HEARTBEAT_RATE, HEARTBEAT_BURST)
+    }
+}
+
+impl HeartbeatLimiter {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1.0),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Accept heartbeats for `services` only, forgetting the buckets and
+    /// counters of services no longer among them
+    pub fn set_services<'a>(&self, services: impl IntoIterator<Item = &'a String>) {
+        let mut state = self.buckets.lock().unwrap();
+        state.services = services.into_iter().cloned().collect();
+        let Buckets { services, buckets } = &mut *state;
+        buckets.retain(|service, _| services.contains(service));
+    }
+
+    /// The heartbeat to forward now, if `message` may pass
+    pub fn admit(&self, message: HeartbeatMessage, now: Instant) -> Option<HeartbeatMessage> {
+        let mut state = self.buckets.lock().unwrap();
+        if !state.services.contains(&message.0) {
+            return None;
+        }
+        let bucket = state
+            .buckets
+            .entry(message.0.clone())
+            .or_insert_with(|| Bucket::new(self.burst, now));
+        bucket.counters.received += 1;
+
+        if bucket.take(self.rate, self.burst, now) {
+            return Some(bucket.forward(message));
+        }
+
+        let repeats_last = bucket
+            .last
+            .as_ref()
+            .is_some_and(|(status, metadata)| *status == message.1 && *metadata == message.2);
+        // A change is held back instead of dropped, superseding any older
+        // held-back heartbeat; a repeat makes a held-back one stale
+        let superseded = if repeats_last {
+            bucket.counters.dropped += 1;
+            bucket.pending.take()
+        } else {
+            bucket.pending.replace(message)
+        };
+        if superseded.is_some() {
+            bucket.counters.dropped += 1;
+        }
+        None
+    }
+
+    /// Held-back heartbeats of services that have a token again
+    pub fn due(&self, now: Instant) -> Vec<HeartbeatMessage> {
+        let mut state = self.buckets.lock().unwrap();
+        state
+            .buckets
+            .values_mut()
+            .filter(|bucket| bucket.pending.is_some())
+            .filter_map(|bucket| {
+                if bucket.take(self.rate, self.burst, now) {
+                    let message = bucket.pending.take()?;
+                    Some(bucket.forward(message))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Counters of every service that sent a heartbeat
+    pub fn counters(&self) -> HashMap<String, HeartbeatCounters> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .buckets
+            .iter()
+            .map(|(service, bucket)| (service.clone(), bucket.counters))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(status: ServiceStatus) -> HeartbeatMessage {
+        ("lidar".to_string(), status, HashMap::new())
+    }
+
+    fn limiter(burst: f64) -> HeartbeatLimiter {
+        let limiter = HeartbeatLimiter::new(10.0, burst);
+        limiter.set_services(&["lidar".to_string()]);
+        limiter
+    }
+
+    #[test]
+    fn test_excess_heartbeats_are_dropped() {
+        let limiter = limiter(3.0);
+        let now = Instant::now();
+
+        let forwarded = (0..10)
+            .filter(|_| {
+                limiter
+                    .admit(heartbeat(ServiceStatus::Healthy), now)
+                    .is_some()
+            })
+            .count();
+        assert_eq!(forwarded, 3);
+        assert!(limiter.due(now).is_empty());
+        assert_eq!(
+            limiter.counters()["lidar"],
+            HeartbeatCounters {
+                received: 10,
+                dropped: 7
+            }
+        );
+
+        // One token back after 100ms at 10/s
+        let later = now + Duration::from_millis(100);
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Healthy), later)
+            .is_some());
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Healthy), later)
+            .is_none());
+    }
+
+    #[test]
+    fn test_limited_status_change_is_coalesced() {
+        let limiter = limiter(1.0);
+        let now = Instant::now();
+
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Healthy), now)
+            .is_some());
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Degraded), now)
+            .is_none());
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Running), now)
+            .is_none());
+        assert!(limiter.due(now).is_empty());
+
+        let due = limiter.due(now + Duration::from_millis(100));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].1, ServiceStatus::Running);
+        assert!(limiter.due(now + Duration::from_secs(1)).is_empty());
+        // The replaced degraded heartbeat is the only one lost
+        assert_eq!(limiter.counters()["lidar"].dropped, 1);
+    }
+
+    #[test]
+    fn test_heartbeats_for_unknown_services_are_dropped() {
+        let limiter = limiter(3.0);
+        let now = Instant::now();
+
+        let mut unknown = heartbeat(ServiceStatus::Healthy);
+        unknown.0 = "made-up".to_string();
+        assert!(limiter.admit(unknown, now).is_none());
+        assert!(!limiter.counters().contains_key("made-up"));
+
+        // A service removed from the recipe loses its counters
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Healthy), now)
+            .is_some());
+        limiter.set_services(&[]);
+        assert!(limiter.counters().is_empty());
+        assert!(limiter
+            .admit(heartbeat(ServiceStatus::Healthy), now)
+            .is_none());
+    }
+}
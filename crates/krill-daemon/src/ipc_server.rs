@@ -1,15 +1,36 @@
-// IPC Server - Unix socket server for client communication
+// IPC Server - Unix socket (and optional TCP) server for client communication
 
+use crate::access::AccessPolicy;
+use crate::audit::AuditLog;
+use crate::bundle;
+use crate::daemon_log::LogFilterHandle;
+use crate::events::EventJournal;
+use crate::fanout::{Fanout, Subscription};
+use crate::heartbeat_limit::{HeartbeatLimiter, FLUSH_INTERVAL};
+use crate::log_throttle::{LogThrottle, MARKER_INTERVAL};
 use crate::logging::LogStore;
+use crate::orchestrator::ReloadSummary;
+use crate::scheduler::Scheduler;
+use crate::storm::StormTripped;
+use base64::Engine;
 use krill_common::ipc::ServiceSnapshot;
-use krill_common::{ClientMessage, CommandAction, ServerMessage, ServiceStatus};
+use krill_common::{
+    AuditOutcome, ClientIdentity, ClientMessage, ClientRequest, CommandAction, KrillError,
+    LabelSelector, MaintenanceAction, MaintenanceEvent, ProcessOutputLine, ProtocolVersion,
+    ServerMessage, ServerReply, ServiceMetrics, ServiceShutdown, ServiceStatus,
+};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Error)]
@@ -25,19 +46,102 @@ pub enum IpcError {
 
     #[error("Invalid message: {0}")]
     InvalidMessage(String),
+
+    #[error("Incompatible protocol version {client} (server speaks {server})")]
+    IncompatibleProtocol {
+        client: ProtocolVersion,
+        server: ProtocolVersion,
+    },
 }
 
 pub type CommandRequest = (CommandAction, Option<String>);
 pub type HeartbeatMessage = (String, ServiceStatus, HashMap<String, String>); // (service_name, status, metadata)
+/// Channel asking the orchestrator for a snapshot of all services
+pub type SnapshotRequestTx =
+    mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>;
+
+/// Request to reload the recipe from disk, answered on `reply`
+pub struct ReloadRequest {
+    /// Only plan the reload, changing nothing
+    pub dry_run: bool,
+    pub reply: mpsc::UnboundedSender<Result<ReloadSummary, KrillError>>,
+}
+
+/// Transport carrying the line-delimited JSON protocol (Unix socket, named pipe, or TCP)
+pub trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> IpcStream for T {}
+
+/// How long a client waits for the daemon to apply a config reload
+const RELOAD_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+
+/// How often changes are published to `subscribe_snapshots` clients
+pub const SNAPSHOT_DELTA_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the daemon waits for its last messages to go out as it exits
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a write to a client may block before the client is
+/// disconnected; its socket buffer is full, so it stopped reading
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Snapshot last published to `subscribe_snapshots` clients
+#[derive(Default)]
+struct PublishedSnapshot {
+    seq: u64,
+    services: HashMap<String, ServiceSnapshot>,
+    /// The last storm of each workspace, told again to clients subscribing
+    /// while its restarts are still paused
+    storms: HashMap<String, StormTripped>,
+}
+
+impl PublishedSnapshot {
+    /// `system_degraded` of the workspaces whose restarts are paused
+    fn degraded(&self) -> Vec<ServerMessage> {
+        let mut storms: Vec<&StormTripped> = self
+            .storms
+            .values()
+            .filter(|storm| {
+                self.services.values().any(|snapshot| {
+                    snapshot.restarts_paused && snapshot.namespace == storm.workspace
+                })
+            })
+            .collect();
+        storms.sort_by(|a, b| a.workspace.cmp(&b.workspace));
+        storms.into_iter().cloned().map(system_degraded).collect()
+    }
+}
+
+fn system_degraded(tripped: StormTripped) -> ServerMessage {
+    ServerMessage::SystemDegraded {
+        workspace: tripped.workspace,
+        failures: tripped.failures,
+        window: tripped.window,
+    }
+}
 
 pub struct IpcServer {
     socket_path: PathBuf,
-    event_broadcast: broadcast::Sender<ServerMessage>,
+    tcp_addr: Option<SocketAddr>,
+    fanout: Arc<Fanout>,
     command_tx: mpsc::UnboundedSender<CommandRequest>,
-    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    snapshot_req_tx: SnapshotRequestTx,
     heartbeat_tx: Option<mpsc::UnboundedSender<HeartbeatMessage>>,
+    heartbeat_limiter: Arc<HeartbeatLimiter>,
+    log_throttle: Arc<LogThrottle>,
+    reload_tx: Option<mpsc::UnboundedSender<ReloadRequest>>,
     log_store: Option<Arc<LogStore>>,
+    event_journal: Option<Arc<EventJournal>>,
+    audit_log: Option<Arc<AuditLog>>,
+    log_filter: Option<Arc<LogFilterHandle>>,
+    scheduler: Option<Arc<Scheduler>>,
+    access: Arc<AccessPolicy>,
+    published: Arc<Mutex<PublishedSnapshot>>,
     shutdown: Arc<Mutex<bool>>,
+    /// The socket file belongs to the daemon that took over
+    socket_released: AtomicBool,
+    /// An upgrade was sent to the command loop and has not failed yet
+    upgrading: Arc<AtomicBool>,
 }
 
 impl IpcServer {
@@ -71,59 +175,189 @@ impl IpcServer {
         heartbeat_tx: Option<mpsc::UnboundedSender<HeartbeatMessage>>,
         log_store: Option<Arc<LogStore>>,
     ) -> Result<Self, IpcError> {
-        // Remove existing socket if it exists
-        if socket_path.exists() {
+        // Remove existing socket if it exists; an abstract socket has no file
+        if krill_common::abstract_socket_name(&socket_path).is_none() && socket_path.exists() {
             std::fs::remove_file(&socket_path)?;
         }
 
-        let (event_broadcast, _) = broadcast::channel(100);
-
         Ok(Self {
             socket_path,
-            event_broadcast,
+            tcp_addr: None,
+            fanout: Arc::default(),
             command_tx,
             snapshot_req_tx,
             heartbeat_tx,
+            heartbeat_limiter: Arc::default(),
+            log_throttle: Arc::default(),
+            reload_tx: None,
             log_store,
+            event_journal: None,
+            audit_log: None,
+            log_filter: None,
+            scheduler: None,
+            access: Arc::default(),
+            published: Arc::default(),
             shutdown: Arc::new(Mutex::new(false)),
+            socket_released: AtomicBool::new(false),
+            upgrading: Arc::default(),
         })
     }
 
+    /// Enable `reload_config` requests, forwarded to the given channel
+    pub fn set_reload_tx(&mut self, reload_tx: mpsc::UnboundedSender<ReloadRequest>) {
+        self.reload_tx = Some(reload_tx);
+    }
+
+    /// Serve `get_events` requests from the given journal
+    pub fn set_event_journal(&mut self, journal: Arc<EventJournal>) {
+        self.event_journal = Some(journal);
+    }
+
+    /// Record control commands in the given audit log and serve
+    /// `get_audit_log` requests from it
+    pub fn set_audit_log(&mut self, audit_log: Arc<AuditLog>) {
+        self.audit_log = Some(audit_log);
+    }
+
+    /// Serve `set_log_filter` requests by changing the filter behind `handle`
+    pub fn set_log_filter(&mut self, handle: Arc<LogFilterHandle>) {
+        self.log_filter = Some(handle);
+    }
+
+    /// Serve `schedule_command` requests by planning them in `scheduler`
+    pub fn set_scheduler(&mut self, scheduler: Arc<Scheduler>) {
+        self.scheduler = Some(scheduler);
+    }
+
+    /// Set the socket's permissions and restrict commands to allowed peers
+    pub fn set_access_policy(&mut self, access: AccessPolicy) {
+        self.access = Arc::new(access);
+    }
+
+    /// Limiter applied to incoming heartbeats, whose counters feed the
+    /// Prometheus exporter
+    pub fn heartbeat_limiter(&self) -> Arc<HeartbeatLimiter> {
+        Arc::clone(&self.heartbeat_limiter)
+    }
+
+    /// Throttle applied to the service output streamed to clients, whose
+    /// settings follow the recipe's `log_throttle`
+    pub fn log_throttle(&self) -> Arc<LogThrottle> {
+        Arc::clone(&self.log_throttle)
+    }
+
+    /// Per-client queues of the messages pushed to clients, with their lag
+    pub fn fanout(&self) -> Arc<Fanout> {
+        Arc::clone(&self.fanout)
+    }
+
+    /// Additionally accept clients over TCP on the given address
+    pub fn set_tcp_listen(&mut self, addr: SocketAddr) {
+        self.tcp_addr = Some(addr);
+    }
+
     pub async fn start(&self) -> Result<(), IpcError> {
         info!("Starting IPC server on {:?}", self.socket_path);
 
-        let listener = UnixListener::bind(&self.socket_path)?;
+        let mut listener = LocalListener::bind(&self.socket_path)?;
 
-        // Set permissions to 0600 (owner read/write only)
+        // Set permissions (owner read/write only unless configured). An
+        // abstract socket has none, so its peers are checked as they connect
+        let is_abstract = krill_common::abstract_socket_name(&self.socket_path).is_some();
         #[cfg(unix)]
-        {
+        if is_abstract {
+            if self.access.socket_mode() != krill_common::DEFAULT_SOCKET_MODE
+                || self.access.socket_gid().is_some()
+            {
+                warn!(
+                    "socket_mode and socket_group do not apply to abstract socket {:?}; \
+                     only the daemon's user, root, allow_users and allow_groups may connect",
+                    self.socket_path
+                );
+            }
+        } else {
             use std::os::unix::fs::PermissionsExt;
             let metadata = std::fs::metadata(&self.socket_path)?;
             let mut permissions = metadata.permissions();
-            permissions.set_mode(0o600);
+            permissions.set_mode(self.access.socket_mode());
             std::fs::set_permissions(&self.socket_path, permissions)?;
+
+            if let Some(gid) = self.access.socket_gid() {
+                std::os::unix::fs::chown(&self.socket_path, None, Some(gid))?;
+            }
         }
 
-        info!("IPC server listening on {:?}", self.socket_path);
+        info!("IPC server listening on {}", listener.address());
+
+        let tcp_listener = match self.tcp_addr {
+            Some(addr) => {
+                let tcp_listener = TcpListener::bind(addr).await?;
+                if !addr.ip().is_loopback() {
+                    warn!(
+                        "IPC server accepting unauthenticated TCP clients on non-loopback address {}",
+                        addr
+                    );
+                }
+                info!(
+                    "IPC server listening on tcp://{}",
+                    tcp_listener.local_addr()?
+                );
+                Some(tcp_listener)
+            }
+            None => None,
+        };
+
+        let flusher = self.heartbeat_tx.clone().map(|heartbeat_tx| {
+            tokio::spawn(flush_heartbeats(
+                Arc::downgrade(&self.heartbeat_limiter),
+                heartbeat_tx,
+            ))
+        });
+
+        let marker_flusher = tokio::spawn(flush_log_markers(
+            Arc::downgrade(&self.log_throttle),
+            Arc::downgrade(&self.fanout),
+        ));
 
         loop {
             if *self.shutdown.lock().await {
                 break;
             }
 
-            match listener.accept().await {
-                Ok((stream, _addr)) => {
-                    debug!("New client connected");
-                    let (handler, writer) = ClientHandler::new(
-                        stream,
-                        self.event_broadcast.clone(),
-                        self.command_tx.clone(),
-                        self.snapshot_req_tx.clone(),
-                        self.heartbeat_tx.clone(),
-                        self.log_store.clone(),
-                    );
+            // Whether the client must be admitted by its credentials, as
+            // an abstract socket has no permission bits to keep others out
+            let accepted: std::io::Result<(Box<dyn IpcStream>, ClientIdentity, bool)> = tokio::select! {
+                result = listener.accept() => {
+                    result.map(|(stream, identity)| (stream, identity, is_abstract))
+                }
+                result = accept_tcp(tcp_listener.as_ref()) => {
+                    result.map(|(stream, addr)| {
+                        let identity = ClientIdentity {
+                            address: Some(addr.to_string()),
+                            ..Default::default()
+                        };
+                        (Box::new(stream) as Box<dyn IpcStream>, identity, false)
+                    })
+                }
+            };
 
+            match accepted {
+                Ok((stream, identity, admit)) => {
+                    debug!("New client connected ({})", identity);
+                    let (handler, writer) = ClientHandler::new(stream, identity, self);
+
+                    // Admitted in the client's task, so a slow group lookup
+                    // does not hold up accepting others
                     tokio::spawn(async move {
+                        if admit {
+                            if let Err(e) = handler.access.admit_peer(&handler.identity).await {
+                                error!(
+                                    "Failed to accept connection: refused client ({}): {}",
+                                    handler.identity, e
+                                );
+                                return;
+                            }
+                        }
                         if let Err(e) = handler.handle(writer).await {
                             error!("Client handler error: {}", e);
                         }
@@ -135,6 +369,10 @@ impl IpcServer {
             }
         }
 
+        if let Some(flusher) = flusher {
+            flusher.abort();
+        }
+        marker_flusher.abort();
         info!("IPC server stopped");
         Ok(())
     }
@@ -143,56 +381,335 @@ impl IpcServer {
         *self.shutdown.lock().await = true;
 
         // Remove socket file
-        if self.socket_path.exists() {
+        if krill_common::abstract_socket_name(&self.socket_path).is_none()
+            && self.socket_path.exists()
+            && !self.socket_released.load(Ordering::SeqCst)
+        {
             if let Err(e) = std::fs::remove_file(&self.socket_path) {
                 error!("Failed to remove socket file: {}", e);
             }
         }
     }
 
+    /// Leave the socket file to the daemon taking over after an upgrade
+    /// instead of removing it on shutdown
+    pub fn release_socket(&self) {
+        self.socket_released.store(true, Ordering::SeqCst);
+    }
+
+    /// Accept commands again after an upgrade did not go through
+    pub fn upgrade_aborted(&self) {
+        self.upgrading.store(false, Ordering::SeqCst);
+    }
+
     /// Broadcast an event to all connected clients
     pub fn broadcast_event(&self, service: String, status: ServiceStatus) {
         let message = ServerMessage::StatusUpdate { service, status };
-        let _ = self.event_broadcast.send(message);
+        self.fanout.send(message);
+    }
+
+    /// Broadcast a scheduled or due maintenance action to clients
+    pub fn broadcast_maintenance(
+        &self,
+        service: String,
+        event: MaintenanceEvent,
+        maintenance: MaintenanceAction,
+    ) {
+        let message = ServerMessage::Maintenance {
+            service,
+            event,
+            maintenance,
+        };
+        self.fanout.send(message);
+    }
+
+    /// Tell clients that a restart storm paused the automatic restarts of `workspace`
+    pub async fn broadcast_system_degraded(&self, tripped: StormTripped) {
+        let mut published = self.published.lock().await;
+        published
+            .storms
+            .insert(tripped.workspace.clone(), tripped.clone());
+        self.fanout.send(system_degraded(tripped));
+    }
+
+    /// Tell clients how the services were stopped, as the daemon exits
+    pub fn broadcast_shutdown_report(&self, services: Vec<ServiceShutdown>) {
+        let message = ServerMessage::ShutdownReport { services };
+        self.fanout.send(message);
+    }
+
+    /// Wait until every client's queued messages were handed to its
+    /// connection, so the last ones go out before the daemon exits; a
+    /// client that does not read them is given up on after [`FLUSH_TIMEOUT`]
+    pub async fn flush(&self) {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        while self.fanout.lag().values().any(|lag| lag.queued > 0) && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Broadcast per-service resource usage to clients
+    pub fn broadcast_metrics(&self, services: HashMap<String, ServiceMetrics>) {
+        let message = ServerMessage::ServiceMetrics { services };
+        self.fanout.send(message);
+    }
+
+    /// Send what changed since the last published snapshot to
+    /// `subscribe_snapshots` clients. Uptimes and ages are published in
+    /// whole seconds, so a running service does not change on every call.
+    pub async fn publish_snapshot(&self, mut services: HashMap<String, ServiceSnapshot>) {
+        for snapshot in services.values_mut() {
+            snapshot.uptime = snapshot.uptime.map(|d| Duration::from_secs(d.as_secs()));
+            snapshot.heartbeat_age = snapshot
+                .heartbeat_age
+                .map(|d| Duration::from_secs(d.as_secs()));
+            snapshot.state_age = snapshot.state_age.map(|d| Duration::from_secs(d.as_secs()));
+        }
+
+        self.heartbeat_limiter.set_services(services.keys());
+
+        // Held while broadcasting, so a client subscribing meanwhile gets a
+        // snapshot consistent with the deltas that follow it
+        let mut published = self.published.lock().await;
+        let (changed, removed) = krill_common::diff_snapshots(&published.services, &services);
+        if changed.is_empty() && removed.is_empty() {
+            return;
+        }
+        published.seq += 1;
+        published.services = services;
+        let message = ServerMessage::SnapshotDelta {
+            seq: published.seq,
+            changed,
+            removed,
+        };
+        self.fanout.send(message);
     }
 
-    /// Broadcast a log message to clients
-    pub fn broadcast_log(&self, service: String, line: String) {
-        let message = ServerMessage::LogLine { service, line };
-        let _ = self.event_broadcast.send(message);
+    /// Broadcast a log message to clients, throttled for those that did
+    /// not ask for raw output
+    pub fn broadcast_log(&self, output: ProcessOutputLine) {
+        let admitted = self.log_throttle.admit(&output, Instant::now());
+        let message = log_message(output);
+        if admitted.is_unchanged() {
+            self.fanout.send(message);
+            return;
+        }
+
+        for marker in admitted.markers {
+            self.fanout.send_log(log_message(marker), false);
+        }
+        if admitted.forward {
+            self.fanout.send_log(message.clone(), false);
+        }
+        self.fanout.send_log(message, true);
+    }
+}
+
+/// Listener for local clients: a Unix socket, or a named pipe on Windows
+#[cfg(unix)]
+struct LocalListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl LocalListener {
+    fn bind(socket_path: &std::path::Path) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: UnixListener::from_std(krill_common::bind_unix_socket(socket_path)?)?,
+            path: socket_path.to_path_buf(),
+        })
+    }
+
+    fn address(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn accept(&mut self) -> std::io::Result<(Box<dyn IpcStream>, ClientIdentity)> {
+        let (stream, _addr) = self.listener.accept().await?;
+        let identity = match stream.peer_cred() {
+            Ok(cred) => ClientIdentity {
+                uid: Some(cred.uid()),
+                gid: Some(cred.gid()),
+                pid: cred.pid(),
+                ..Default::default()
+            },
+            Err(e) => {
+                warn!("Failed to read peer credentials of client: {}", e);
+                ClientIdentity::default()
+            }
+        };
+        Ok((Box::new(stream), identity))
+    }
+}
+
+#[cfg(windows)]
+struct LocalListener {
+    name: String,
+    /// Pipe instance waiting for the next client
+    next: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+#[cfg(windows)]
+impl LocalListener {
+    fn bind(socket_path: &std::path::Path) -> std::io::Result<Self> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let name = krill_common::pipe_name(socket_path);
+        let next = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&name)?;
+        Ok(Self { name, next })
+    }
+
+    fn address(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn accept(&mut self) -> std::io::Result<(Box<dyn IpcStream>, ClientIdentity)> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        self.next.connect().await?;
+
+        // Each client gets its own instance; create the next one before handing this out
+        let next = ServerOptions::new().create(&self.name)?;
+        let connected = std::mem::replace(&mut self.next, next);
+        Ok((Box::new(connected), ClientIdentity::default()))
+    }
+}
+
+/// Forward heartbeats the limiter held back once their service may send again
+async fn flush_heartbeats(
+    limiter: Weak<HeartbeatLimiter>,
+    heartbeat_tx: mpsc::UnboundedSender<HeartbeatMessage>,
+) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(limiter) = limiter.upgrade() else {
+            return;
+        };
+        for message in limiter.due(Instant::now()) {
+            if heartbeat_tx.send(message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Tell throttled clients about services whose suppressed lines have
+/// gone unreported for a while
+async fn flush_log_markers(throttle: Weak<LogThrottle>, fanout: Weak<Fanout>) {
+    let mut interval = tokio::time::interval(MARKER_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (Some(throttle), Some(fanout)) = (throttle.upgrade(), fanout.upgrade()) else {
+            return;
+        };
+        for marker in throttle.due(Instant::now()) {
+            fanout.send_log(log_message(marker), false);
+        }
+    }
+}
+
+fn log_message(output: ProcessOutputLine) -> ServerMessage {
+    ServerMessage::LogLine {
+        service: output.service,
+        line: output.line,
+        level: output.level,
+        timestamp: Some(output.timestamp),
+        stream: output.stream,
+    }
+}
+
+/// Write one message as a line of JSON. The write fails if the client
+/// does not take it within [`WRITE_TIMEOUT`], as it stopped reading.
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &impl serde::Serialize,
+) -> std::io::Result<()> {
+    let json = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    tokio::time::timeout(
+        WRITE_TIMEOUT,
+        writer.write_all(format!("{}\n", json).as_bytes()),
+    )
+    .await
+    .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "client stopped reading"))?
+}
+
+/// Accept the next TCP client, or wait forever when TCP is disabled
+async fn accept_tcp(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+
+    let (stream, addr) = listener.accept().await?;
+    debug!("TCP client connected from {}", addr);
+    stream.set_nodelay(true)?;
+    Ok((stream, addr))
+}
+
+/// Sends the replies to one client request, tagged with its `request_id`
+struct Replies<'a> {
+    tx: &'a mpsc::UnboundedSender<ServerReply>,
+    request_id: Option<String>,
+}
+
+impl Replies<'_> {
+    #[allow(clippy::result_large_err)]
+    fn send(&self, message: ServerMessage) -> Result<(), mpsc::error::SendError<ServerReply>> {
+        self.tx
+            .send(ServerReply::new(self.request_id.clone(), message))
     }
 }
 
 struct ClientHandler {
-    event_rx: broadcast::Receiver<ServerMessage>,
+    /// Queue of the events, logs and deltas pushed to the client
+    subscription: Arc<Subscription>,
     command_tx: mpsc::UnboundedSender<CommandRequest>,
-    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    snapshot_req_tx: SnapshotRequestTx,
     heartbeat_tx: Option<mpsc::UnboundedSender<HeartbeatMessage>>,
+    heartbeat_limiter: Arc<HeartbeatLimiter>,
+    reload_tx: Option<mpsc::UnboundedSender<ReloadRequest>>,
     log_store: Option<Arc<LogStore>>,
-    reader: BufReader<tokio::io::ReadHalf<UnixStream>>,
+    event_journal: Option<Arc<EventJournal>>,
+    audit_log: Option<Arc<AuditLog>>,
+    log_filter: Option<Arc<LogFilterHandle>>,
+    scheduler: Option<Arc<Scheduler>>,
+    access: Arc<AccessPolicy>,
+    /// Who is on the other end, recorded with the commands it sends
+    identity: ClientIdentity,
+    published: Arc<Mutex<PublishedSnapshot>>,
+    upgrading: Arc<AtomicBool>,
+    reader: BufReader<tokio::io::ReadHalf<Box<dyn IpcStream>>>,
 }
 
 impl ClientHandler {
     fn new(
-        stream: UnixStream,
-        event_broadcast: broadcast::Sender<ServerMessage>,
-        command_tx: mpsc::UnboundedSender<CommandRequest>,
-        snapshot_req_tx: mpsc::UnboundedSender<
-            mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>,
-        >,
-        heartbeat_tx: Option<mpsc::UnboundedSender<HeartbeatMessage>>,
-        log_store: Option<Arc<LogStore>>,
-    ) -> (Self, tokio::io::WriteHalf<UnixStream>) {
-        let event_rx = event_broadcast.subscribe();
+        stream: Box<dyn IpcStream>,
+        identity: ClientIdentity,
+        server: &IpcServer,
+    ) -> (Self, tokio::io::WriteHalf<Box<dyn IpcStream>>) {
+        let subscription = Arc::new(server.fanout.subscribe(identity.to_string()));
         let (reader, writer) = tokio::io::split(stream);
         let reader = BufReader::new(reader);
 
         let handler = Self {
-            event_rx,
-            command_tx,
-            snapshot_req_tx,
-            heartbeat_tx,
-            log_store,
+            subscription,
+            command_tx: server.command_tx.clone(),
+            snapshot_req_tx: server.snapshot_req_tx.clone(),
+            heartbeat_tx: server.heartbeat_tx.clone(),
+            heartbeat_limiter: Arc::clone(&server.heartbeat_limiter),
+            reload_tx: server.reload_tx.clone(),
+            log_store: server.log_store.clone(),
+            event_journal: server.event_journal.clone(),
+            audit_log: server.audit_log.clone(),
+            log_filter: server.log_filter.clone(),
+            scheduler: server.scheduler.clone(),
+            access: Arc::clone(&server.access),
+            identity,
+            published: Arc::clone(&server.published),
+            upgrading: Arc::clone(&server.upgrading),
             reader,
         };
 
@@ -201,47 +718,56 @@ impl ClientHandler {
 
     async fn handle(
         mut self,
-        mut writer: tokio::io::WriteHalf<UnixStream>,
+        mut writer: tokio::io::WriteHalf<Box<dyn IpcStream>>,
     ) -> Result<(), IpcError> {
         let mut line_buffer = String::new();
 
         // Create response channel for sending messages back to client
-        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<ServerMessage>();
+        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<ServerReply>();
 
         // Spawn task to forward events and responses to this client
-        let mut event_rx = self.event_rx.resubscribe();
+        let subscription = Arc::clone(&self.subscription);
         let (close_tx, mut close_rx) = mpsc::channel::<()>(1);
+        // Dropped when the writer gives up, which ends the connection
+        let (writer_tx, mut writer_rx) = oneshot::channel::<()>();
 
         tokio::spawn(async move {
+            let _writer_tx = writer_tx;
             loop {
                 tokio::select! {
-                    event = event_rx.recv() => {
-                        match event {
-                            Ok(message) => {
-                                if let Ok(json) = serde_json::to_string(&message) {
-                                    let line = format!("{}\n", json);
-                                    if writer.write_all(line.as_bytes()).await.is_err() {
-                                        break;
-                                    }
-                                }
-                            }
-                            Err(broadcast::error::RecvError::Lagged(_)) => {
-                                warn!("Client lagging behind on events");
+                    // Responses first: a subscription's snapshot goes out
+                    // before the deltas published after it
+                    biased;
+
+                    response = response_rx.recv() => {
+                        if let Some(message) = response {
+                            if write_message(&mut writer, &message).await.is_err() {
+                                break;
                             }
-                            Err(_) => break,
                         }
                     }
-                    response = response_rx.recv() => {
-                        if let Some(message) = response {
-                            if let Ok(json) = serde_json::to_string(&message) {
-                                let line = format!("{}\n", json);
-                                if writer.write_all(line.as_bytes()).await.is_err() {
+                    event = subscription.recv() => {
+                        match event {
+                            Some(message) => {
+                                if write_message(&mut writer, &message).await.is_err() {
                                     break;
                                 }
                             }
+                            None => {
+                                let _ = write_message(&mut writer, &ServerMessage::from(KrillError::SlowClient))
+                                    .await;
+                                let _ = writer.shutdown().await;
+                                break;
+                            }
                         }
                     }
                     _ = close_rx.recv() => {
+                        // Flush pending responses, e.g. a handshake rejection
+                        while let Ok(message) = response_rx.try_recv() {
+                            if write_message(&mut writer, &message).await.is_err() {
+                                break;
+                            }
+                        }
                         break;
                     }
                 }
@@ -252,7 +778,14 @@ impl ClientHandler {
         loop {
             line_buffer.clear();
 
-            match self.reader.read_line(&mut line_buffer).await {
+            let read = tokio::select! {
+                read = self.reader.read_line(&mut line_buffer) => read,
+                _ = &mut writer_rx => {
+                    debug!("Closing connection of client that stopped reading");
+                    break;
+                }
+            };
+            match read {
                 Ok(0) => {
                     debug!("Client disconnected");
                     break;
@@ -263,16 +796,28 @@ impl ClientHandler {
                         continue;
                     }
 
-                    match serde_json::from_str::<ClientMessage>(trimmed) {
-                        Ok(message) => {
-                            if let Err(e) = self.handle_message(message, &response_tx).await {
-                                error!("Error handling message: {}", e);
+                    match serde_json::from_str::<ClientRequest>(trimmed) {
+                        Ok(request) => {
+                            let replies = Replies {
+                                tx: &response_tx,
+                                request_id: request.request_id,
+                            };
+                            match self.handle_message(request.message, &replies).await {
+                                Ok(()) => {}
+                                Err(e @ IpcError::IncompatibleProtocol { .. }) => {
+                                    warn!("Closing connection: {}", e);
+                                    break;
+                                }
+                                Err(e) => error!("Error handling message: {}", e),
                             }
                         }
                         Err(e) => {
                             error!("Failed to parse client message: {}", e);
-                            // Error responses would need a separate writer channel
-                            // For now, just log the error
+                            let _ = response_tx.send(ServerReply::new(
+                                None,
+                                KrillError::InvalidRequest(format!("Unparsable message: {}", e))
+                                    .into(),
+                            ));
                         }
                     }
                 }
@@ -290,9 +835,39 @@ impl ClientHandler {
     async fn handle_message(
         &mut self,
         message: ClientMessage,
-        response_tx: &mpsc::UnboundedSender<ServerMessage>,
+        response_tx: &Replies<'_>,
     ) -> Result<(), IpcError> {
         match message {
+            ClientMessage::Hello {
+                protocol_version,
+                client,
+            } => {
+                let server = ProtocolVersion::CURRENT;
+                let Some(negotiated) = server.negotiate(&protocol_version) else {
+                    let _ = response_tx.send(
+                        KrillError::IncompatibleProtocol {
+                            client: protocol_version,
+                            server,
+                        }
+                        .into(),
+                    );
+                    return Err(IpcError::IncompatibleProtocol {
+                        client: protocol_version,
+                        server,
+                    });
+                };
+
+                debug!(
+                    "Client {:?} connected with protocol {} (negotiated {})",
+                    client, protocol_version, negotiated
+                );
+                self.identity.client = client;
+                self.subscription.set_client(self.identity.to_string());
+                let _ = response_tx.send(ServerMessage::Welcome {
+                    protocol_version: negotiated,
+                });
+            }
+
             ClientMessage::Heartbeat {
                 service,
                 status,
@@ -300,57 +875,241 @@ impl ClientHandler {
             } => {
                 debug!("Received heartbeat from service '{}'", service);
                 if let Some(ref tx) = self.heartbeat_tx {
-                    let _ = tx.send((service, status, metadata));
+                    match self
+                        .heartbeat_limiter
+                        .admit((service, status, metadata), Instant::now())
+                    {
+                        Some(message) => {
+                            let _ = tx.send(message);
+                        }
+                        None => debug!("Heartbeat rate limited or not for a configured service"),
+                    }
                 }
             }
 
-            ClientMessage::Command { action, target } => {
+            ClientMessage::Command {
+                action,
+                target,
+                force,
+            } => {
                 debug!("Received command: {:?} for {:?}", action, target);
-                self.command_tx
-                    .send((action, target))
-                    .map_err(|_| IpcError::InvalidMessage("Failed to send command".to_string()))?;
+                let mut result = self.access.authorize(action, &self.identity).await;
+                if result.is_ok() && action == CommandAction::UpgradeDaemon {
+                    result = self
+                        .access
+                        .check_upgrade(target.as_deref().map(std::path::Path::new));
+                }
+                if result.is_ok() {
+                    result =
+                        check_command(&self.snapshot_req_tx, action, &[target.as_deref()], force)
+                            .await;
+                }
+                if result.is_ok() {
+                    result = self.check_upgrading(action);
+                }
+                if result.is_ok() && self.command_tx.send((action, target.clone())).is_err() {
+                    result = Err(KrillError::ShuttingDown);
+                }
+                self.audit(action, target.as_deref(), result.as_ref().map(|_| ()))
+                    .await;
 
                 // Send acknowledgment
-                let ack = ServerMessage::Ack { request_id: None };
-                let _ = response_tx.send(ack);
+                let response = match result {
+                    Ok(()) => ServerMessage::Ack { request_id: None },
+                    Err(e) => e.into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::BatchCommand {
+                action,
+                targets,
+                force,
+            } => {
+                debug!("Received batch command: {:?} for {:?}", action, targets);
+                if targets.is_empty() {
+                    let _ = response_tx.send(
+                        KrillError::InvalidRequest("Batch command has no targets".to_string())
+                            .into(),
+                    );
+                    return Ok(());
+                }
+                if action == CommandAction::UpgradeDaemon {
+                    let _ = response_tx.send(
+                        KrillError::InvalidRequest("An upgrade cannot be batched".to_string())
+                            .into(),
+                    );
+                    return Ok(());
+                }
+                // The batch is applied as a whole or not at all
+                let mut result = self.access.authorize(action, &self.identity).await;
+                if result.is_ok() {
+                    let checked: Vec<Option<&str>> =
+                        targets.iter().map(|target| Some(target.as_str())).collect();
+                    result = check_command(&self.snapshot_req_tx, action, &checked, force).await;
+                }
+                if result.is_ok() {
+                    result = self.check_upgrading(action);
+                }
+                if result.is_ok() {
+                    for target in &targets {
+                        if self
+                            .command_tx
+                            .send((action, Some(target.clone())))
+                            .is_err()
+                        {
+                            result = Err(KrillError::ShuttingDown);
+                            break;
+                        }
+                    }
+                }
+                for target in &targets {
+                    self.audit(action, Some(target), result.as_ref().map(|_| ()))
+                        .await;
+                }
+
+                let response = match result {
+                    Ok(()) => ServerMessage::Ack { request_id: None },
+                    Err(e) => e.into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::ScheduleCommand {
+                action,
+                targets,
+                at,
+                force,
+            } => {
+                debug!("Received command: {:?} for {:?} at {}", action, targets, at);
+                let Some(ref scheduler) = self.scheduler else {
+                    let _ = response_tx
+                        .send(KrillError::Unsupported("Scheduled commands".to_string()).into());
+                    return Ok(());
+                };
+                let invalid = if targets.is_empty() {
+                    Some("Scheduled command has no targets".to_string())
+                } else if !matches!(
+                    action,
+                    CommandAction::Start | CommandAction::Stop | CommandAction::Restart
+                ) {
+                    Some(format!("{:?} cannot be scheduled", action))
+                } else if at <= chrono::Utc::now() {
+                    Some(format!("{} is in the past", at))
+                } else {
+                    None
+                };
+                if let Some(reason) = invalid {
+                    let _ = response_tx.send(KrillError::InvalidRequest(reason).into());
+                    return Ok(());
+                }
+
+                // Checked now, so a mistyped target fails right away
+                let mut result = self.access.authorize(action, &self.identity).await;
+                if result.is_ok() {
+                    let checked: Vec<Option<&str>> =
+                        targets.iter().map(|target| Some(target.as_str())).collect();
+                    result = check_command(&self.snapshot_req_tx, action, &checked, force).await;
+                }
+                if result.is_ok() {
+                    result = self.check_upgrading(action);
+                }
+                if result.is_ok() {
+                    for target in &targets {
+                        scheduler.schedule(target, action, at, self.identity.clone());
+                    }
+                }
+                // Recorded again by the scheduler once applied
+                for target in &targets {
+                    match result {
+                        Ok(()) => {
+                            self.audit_outcome(action, Some(target), AuditOutcome::Scheduled { at })
+                                .await
+                        }
+                        Err(ref e) => self.audit(action, Some(target), Err(e)).await,
+                    }
+                }
+
+                let response = match result {
+                    Ok(()) => ServerMessage::Ack { request_id: None },
+                    Err(e) => e.into(),
+                };
+                let _ = response_tx.send(response);
             }
 
-            ClientMessage::Subscribe { events, logs } => {
-                debug!("Client subscribed - events: {}, logs: {:?}", events, logs);
+            ClientMessage::Subscribe {
+                events,
+                logs,
+                raw_logs,
+            } => {
+                debug!(
+                    "Client subscribed - events: {}, logs: {:?}, raw logs: {}",
+                    events, logs, raw_logs
+                );
                 // Subscription is handled automatically via broadcast channel
+                self.subscription.set_raw_logs(raw_logs);
+                // A storm from before the client connected is still in effect
+                if events {
+                    for message in self.published.lock().await.degraded() {
+                        let _ = response_tx.send(message);
+                    }
+                }
             }
 
             ClientMessage::GetSnapshot => {
                 debug!("Client requested snapshot");
 
-                // Create a channel to receive the snapshot
-                let (snapshot_tx, mut snapshot_rx) = mpsc::unbounded_channel();
-
-                // Send request to orchestrator
-                if self.snapshot_req_tx.send(snapshot_tx).is_err() {
-                    error!("Failed to request snapshot from orchestrator");
-                    return Ok(());
+                if let Some(services) = request_snapshot(&self.snapshot_req_tx).await {
+                    let response = ServerMessage::Snapshot {
+                        services,
+                        seq: None,
+                    };
+                    let _ = response_tx.send(response);
                 }
+            }
 
-                // Wait for response (with timeout)
-                tokio::select! {
-                    snapshot = snapshot_rx.recv() => {
-                        if let Some(services) = snapshot {
-                            let response = ServerMessage::Snapshot { services };
-                            let _ = response_tx.send(response);
-                        }
+            ClientMessage::ListServices { selector } => {
+                debug!("Client listed services matching {:?}", selector);
+
+                let selector = match selector.as_deref().map(str::parse::<LabelSelector>) {
+                    Some(Err(e)) => {
+                        let _ = response_tx.send(KrillError::InvalidRequest(e).into());
+                        return Ok(());
                     }
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
-                        error!("Timeout waiting for snapshot");
+                    Some(Ok(selector)) => Some(selector),
+                    None => None,
+                };
+                if let Some(mut services) = request_snapshot(&self.snapshot_req_tx).await {
+                    if let Some(selector) = selector {
+                        services.retain(|_, snapshot| selector.matches(&snapshot.labels));
                     }
+                    let response = ServerMessage::Snapshot {
+                        services,
+                        seq: None,
+                    };
+                    let _ = response_tx.send(response);
                 }
             }
 
-            ClientMessage::GetLogs { service } => {
-                debug!("Client requested logs for: {:?}", service);
+            ClientMessage::SubscribeSnapshots => {
+                debug!("Client subscribed to snapshot deltas");
+                self.subscription.set_snapshots(true);
+
+                let published = self.published.lock().await;
+                let response = ServerMessage::Snapshot {
+                    services: published.services.clone(),
+                    seq: Some(published.seq),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::GetLogs { service, level } => {
+                debug!("Client requested logs for: {:?} ({:?})", service, level);
 
                 let lines = if let Some(ref log_store) = self.log_store {
-                    log_store.get_logs(service.as_deref(), 1000).await
+                    log_store
+                        .get_logs_at_level(service.as_deref(), 1000, level)
+                        .await
                 } else {
                     vec!["Log store not available.".to_string()]
                 };
@@ -358,16 +1117,509 @@ impl ClientHandler {
                 let response = ServerMessage::LogHistory { service, lines };
                 let _ = response_tx.send(response);
             }
+
+            ClientMessage::GetLogsPage {
+                service,
+                before,
+                lines,
+                level,
+            } => {
+                debug!(
+                    "Client requested {} log lines of '{}' before {:?}",
+                    lines, service, before
+                );
+
+                let response = match self.log_store {
+                    Some(ref log_store) => {
+                        let page = log_store.get_log_page(&service, before, lines, level).await;
+                        ServerMessage::LogPage {
+                            service,
+                            lines: page.lines,
+                            cursor: page.cursor,
+                        }
+                    }
+                    None => KrillError::Unsupported("Log retrieval".to_string()).into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::GetEvents {
+                since_secs,
+                service,
+            } => {
+                debug!(
+                    "Client requested events for {:?} since {:?}s",
+                    service, since_secs
+                );
+
+                let response = match self.event_journal {
+                    Some(ref journal) => ServerMessage::EventHistory {
+                        events: journal
+                            .query(since_secs.map(Duration::from_secs), service.as_deref()),
+                    },
+                    None => KrillError::Unsupported("Event history".to_string()).into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::GetAuditLog {
+                since_secs,
+                service,
+            } => {
+                debug!(
+                    "Client requested audit log for {:?} since {:?}s",
+                    service, since_secs
+                );
+
+                let response = match self.audit_log {
+                    Some(ref audit_log) => ServerMessage::AuditLog {
+                        records: audit_log
+                            .query(since_secs.map(Duration::from_secs), service.as_deref()),
+                    },
+                    None => KrillError::Unsupported("Audit log".to_string()).into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::ReloadConfig { dry_run } => {
+                debug!("Client requested config reload (dry run: {})", dry_run);
+
+                let Some(ref reload_tx) = self.reload_tx else {
+                    let _ = response_tx
+                        .send(KrillError::Unsupported("Config reload".to_string()).into());
+                    return Ok(());
+                };
+
+                let (result_tx, mut result_rx) = mpsc::unbounded_channel();
+                let request = ReloadRequest {
+                    dry_run,
+                    reply: result_tx,
+                };
+                if reload_tx.send(request).is_err() {
+                    error!("Failed to request config reload");
+                    return Ok(());
+                }
+
+                let response = match tokio::time::timeout(RELOAD_TIMEOUT, result_rx.recv()).await {
+                    Ok(Some(Ok(summary))) => ServerMessage::ConfigReloaded {
+                        added: summary.added,
+                        removed: summary.removed,
+                        restarted: summary.restarted,
+                        updated: summary.updated,
+                        changes: summary.changes,
+                        dry_run,
+                    },
+                    Ok(Some(Err(e))) => e.into(),
+                    Ok(None) | Err(_) => KrillError::Timeout("config reload".to_string()).into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::ExportLogs { session } => {
+                debug!("Client requested a log bundle of session {:?}", session);
+
+                let response = match self.log_store {
+                    Some(ref log_store) => export_logs(
+                        log_store,
+                        &self.snapshot_req_tx,
+                        session.as_deref(),
+                        response_tx,
+                    )
+                    .await
+                    .unwrap_or_else(ServerMessage::from),
+                    None => KrillError::Unsupported("Log export".to_string()).into(),
+                };
+                let _ = response_tx.send(response);
+            }
+
+            ClientMessage::GetStats { service } => {
+                debug!("Client requested stats for {:?}", service);
+
+                if let Some(services) = request_snapshot(&self.snapshot_req_tx).await {
+                    let response = match service {
+                        Some(name) => match services.get(&name) {
+                            Some(snapshot) => ServerMessage::Stats {
+                                services: HashMap::from([(name, snapshot.availability.clone())]),
+                            },
+                            None => KrillError::ServiceNotFound(name).into(),
+                        },
+                        None => ServerMessage::Stats {
+                            services: services
+                                .into_iter()
+                                .map(|(name, snapshot)| (name, snapshot.availability))
+                                .collect(),
+                        },
+                    };
+                    let _ = response_tx.send(response);
+                }
+            }
+
+            ClientMessage::SetLogFilter { filter } => {
+                debug!("Client requested log filter {:?}", filter);
+                let action = CommandAction::SetLogFilter;
+
+                let result = self
+                    .access
+                    .authorize(action, &self.identity)
+                    .await
+                    .and_then(|()| match self.log_filter {
+                        Some(ref log_filter) => log_filter
+                            .set(&filter)
+                            .map_err(|e| KrillError::InvalidRequest(e.to_string())),
+                        None => Err(KrillError::Unsupported(
+                            "Changing the log filter".to_string(),
+                        )),
+                    });
+                if result.is_ok() {
+                    info!("Daemon log filter set to {}", filter);
+                }
+                self.audit(action, Some(&filter), result.as_ref().map(|_| ()))
+                    .await;
+
+                let response = match result {
+                    Ok(()) => ServerMessage::Ack { request_id: None },
+                    Err(e) => e.into(),
+                };
+                let _ = response_tx.send(response);
+            }
         }
 
         Ok(())
     }
+
+    /// Refuse commands while the daemon is being upgraded, as the new
+    /// daemon would not apply them; an emergency stop calls the upgrade off.
+    /// An accepted upgrade starts the upgrade.
+    fn check_upgrading(&self, action: CommandAction) -> Result<(), KrillError> {
+        match action {
+            CommandAction::EmergencyStop => Ok(()),
+            CommandAction::UpgradeDaemon => self
+                .upgrading
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .map(|_| ())
+                .map_err(|_| KrillError::Upgrading),
+            _ if self.upgrading.load(Ordering::SeqCst) => Err(KrillError::Upgrading),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a control command of this client in the audit log
+    async fn audit(
+        &mut self,
+        action: CommandAction,
+        target: Option<&str>,
+        result: Result<(), &KrillError>,
+    ) {
+        if let Some(ref audit_log) = self.audit_log {
+            audit_log
+                .record(action, target, &self.identity, result)
+                .await;
+        }
+    }
+
+    async fn audit_outcome(
+        &mut self,
+        action: CommandAction,
+        target: Option<&str>,
+        outcome: AuditOutcome,
+    ) {
+        if let Some(ref audit_log) = self.audit_log {
+            audit_log
+                .record_outcome(action, target, &self.identity, outcome)
+                .await;
+        }
+    }
+}
+
+/// Bundle a log session and stream it to the client as `LogBundleChunk`s;
+/// the running session also gets a snapshot of the services
+async fn export_logs(
+    log_store: &LogStore,
+    snapshot_req_tx: &SnapshotRequestTx,
+    session: Option<&str>,
+    replies: &Replies<'_>,
+) -> Result<ServerMessage, KrillError> {
+    let current = log_store.session_dir().to_path_buf();
+    let session_dir = match (session, current.parent()) {
+        (Some(name), Some(log_dir)) => bundle::find_session(log_dir, Some(name))?,
+        _ => current.clone(),
+    };
+
+    let mut extra = Vec::new();
+    if session_dir == current {
+        if let Some(services) = request_snapshot(snapshot_req_tx).await {
+            let status = serde_json::to_vec_pretty(&services)
+                .map_err(|e| KrillError::Internal(e.to_string()))?;
+            extra.push((bundle::STATUS_FILE, status));
+        }
+    }
+
+    let session = session_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    info!("Exporting log session {:?}", session_dir);
+    let out = BundleChunks {
+        tx: replies.tx.clone(),
+        request_id: replies.request_id.clone(),
+        buffer: Vec::with_capacity(BUNDLE_CHUNK_SIZE),
+        size: 0,
+    };
+    let out =
+        tokio::task::spawn_blocking(move || bundle::export_session(&session_dir, out, &extra))
+            .await
+            .map_err(|e| KrillError::Internal(e.to_string()))??;
+
+    Ok(ServerMessage::LogsExported {
+        session,
+        size: out.size,
+    })
+}
+
+/// Bytes of a log bundle per `LogBundleChunk`, before encoding
+const BUNDLE_CHUNK_SIZE: usize = 48 * 1024;
+
+/// Sends a log bundle to the client as it is written, in `LogBundleChunk`s
+/// of [`BUNDLE_CHUNK_SIZE`] bytes
+struct BundleChunks {
+    tx: mpsc::UnboundedSender<ServerReply>,
+    request_id: Option<String>,
+    buffer: Vec<u8>,
+    /// Bytes sent so far
+    size: u64,
+}
+
+impl BundleChunks {
+    fn send_buffer(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let data = base64::engine::general_purpose::STANDARD.encode(&self.buffer);
+        let message = ServerMessage::LogBundleChunk { data };
+        self.tx
+            .send(ServerReply::new(self.request_id.clone(), message))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        self.size += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl std::io::Write for BundleChunks {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(BUNDLE_CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BUNDLE_CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+/// Current snapshot from the orchestrator, or `None` if it does not
+/// answer within a second
+async fn request_snapshot(
+    snapshot_req_tx: &SnapshotRequestTx,
+) -> Option<HashMap<String, ServiceSnapshot>> {
+    let (snapshot_tx, mut snapshot_rx) = mpsc::unbounded_channel();
+    if snapshot_req_tx.send(snapshot_tx).is_err() {
+        error!("Failed to request snapshot from orchestrator");
+        return None;
+    }
+
+    match tokio::time::timeout(Duration::from_secs(1), snapshot_rx.recv()).await {
+        Ok(services) => services,
+        Err(_) => {
+            error!("Timeout waiting for snapshot");
+            None
+        }
+    }
+}
+
+/// Reject a command that cannot apply to one of its targets, so the client
+/// gets an error instead of an ack. All targets are checked against the
+/// same snapshot, taken once.
+async fn check_command(
+    snapshot_req_tx: &SnapshotRequestTx,
+    action: CommandAction,
+    targets: &[Option<&str>],
+    force: bool,
+) -> Result<(), KrillError> {
+    let services = if needs_snapshot(action) {
+        request_snapshot(snapshot_req_tx).await
+    } else {
+        None
+    };
+    targets
+        .iter()
+        .try_for_each(|target| check_target(services.as_ref(), action, *target, force))
+}
+
+/// Whether checking `action` looks at the services at all
+fn needs_snapshot(action: CommandAction) -> bool {
+    !matches!(
+        action,
+        CommandAction::StopDaemon
+            | CommandAction::EmergencyStop
+            | CommandAction::ResumeRestarts
+            | CommandAction::UpgradeDaemon
+            | CommandAction::SetLogFilter
+            | CommandAction::StartGroup
+    )
+}
+
+/// Reject a command that cannot apply to `target`. Stopping, restarting,
+/// killing or pausing a protected service needs `force`, as does stopping
+/// a group with a protected member. Without a snapshot those commands are
+/// refused, since protection cannot be checked; the others are let through
+/// and the orchestrator reports failures in its log.
+fn check_target(
+    services: Option<&HashMap<String, ServiceSnapshot>>,
+    action: CommandAction,
+    target: Option<&str>,
+    force: bool,
+) -> Result<(), KrillError> {
+    match action {
+        CommandAction::StopDaemon
+        | CommandAction::EmergencyStop
+        | CommandAction::ResumeRestarts
+        | CommandAction::UpgradeDaemon => return Ok(()),
+        CommandAction::SetLogFilter => {
+            return Err(KrillError::InvalidRequest(
+                "The log filter is changed with set_log_filter".to_string(),
+            ))
+        }
+        CommandAction::StartGroup | CommandAction::StopGroup if target.is_none() => {
+            return Err(KrillError::InvalidRequest(format!(
+                "{:?} requires a target group",
+                action
+            )));
+        }
+        CommandAction::StartGroup => return Ok(()),
+        _ => {}
+    }
+
+    let Some(target) = target else {
+        return Err(KrillError::InvalidRequest(format!(
+            "{:?} requires a target service",
+            action
+        )));
+    };
+    let guards_protection = matches!(
+        action,
+        CommandAction::Stop
+            | CommandAction::Restart
+            | CommandAction::Kill
+            | CommandAction::Pause
+            | CommandAction::StopGroup
+    ) && !force;
+    let Some(services) = services else {
+        if guards_protection {
+            return Err(KrillError::Timeout(
+                "the service states to check protection".to_string(),
+            ));
+        }
+        return Ok(());
+    };
+    if action == CommandAction::StopGroup {
+        // Members are checked here; an unknown group is reported by the
+        // orchestrator
+        let mut protected: Vec<&String> = services
+            .iter()
+            .filter(|(_, snapshot)| {
+                snapshot.protected && snapshot.config.groups.iter().any(|group| group == target)
+            })
+            .map(|(name, _)| name)
+            .collect();
+        protected.sort();
+        return match protected.first() {
+            Some(name) if guards_protection => Err(KrillError::Protected(name.to_string())),
+            _ => Ok(()),
+        };
+    }
+    let service = target;
+    let Some(snapshot) = services.get(service) else {
+        return Err(KrillError::ServiceNotFound(service.to_string()));
+    };
+    match action {
+        CommandAction::Start if snapshot.safety_stopped => {
+            Err(KrillError::SafetyStopped(service.to_string()))
+        }
+        CommandAction::Stop
+        | CommandAction::Restart
+        | CommandAction::Kill
+        | CommandAction::Pause
+            if snapshot.protected && guards_protection =>
+        {
+            Err(KrillError::Protected(service.to_string()))
+        }
+        CommandAction::Reload
+            if !matches!(
+                snapshot.status,
+                ServiceStatus::Running | ServiceStatus::Healthy | ServiceStatus::Degraded
+            ) =>
+        {
+            Err(KrillError::InvalidState {
+                service: service.to_string(),
+                reason: "is not running".to_string(),
+            })
+        }
+        CommandAction::Pause
+            if !matches!(
+                snapshot.status,
+                ServiceStatus::Running | ServiceStatus::Healthy | ServiceStatus::Degraded
+            ) =>
+        {
+            Err(KrillError::InvalidState {
+                service: service.to_string(),
+                reason: "is not running".to_string(),
+            })
+        }
+        CommandAction::Resume if snapshot.status != ServiceStatus::Paused => {
+            Err(KrillError::InvalidState {
+                service: service.to_string(),
+                reason: "is not paused".to_string(),
+            })
+        }
+        CommandAction::ClearSafetyStop if !snapshot.safety_stopped => {
+            Err(KrillError::InvalidState {
+                service: service.to_string(),
+                reason: "is not held by an emergency stop".to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
 }
 
 impl Drop for IpcServer {
     fn drop(&mut self) {
-        if self.socket_path.exists() {
+        if krill_common::abstract_socket_name(&self.socket_path).is_none()
+            && self.socket_path.exists()
+            && !self.socket_released.load(Ordering::SeqCst)
+        {
             let _ = std::fs::remove_file(&self.socket_path);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_to_client_that_stopped_reading_times_out() {
+        let (mut writer, _client) = tokio::io::duplex(64);
+        let message = ServerMessage::from(KrillError::SlowClient);
+
+        // The client reads nothing, so the buffer fills up
+        let mut result = Ok(());
+        while result.is_ok() {
+            result = write_message(&mut writer, &message).await;
+        }
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -1,20 +1,56 @@
 // Krill Daemon - Process orchestrator for robotics systems
 
+pub mod access;
+pub mod audit;
+pub mod bundle;
+pub mod daemon_log;
+#[cfg(unix)]
+pub mod daemonize;
+pub mod events;
+pub mod fanout;
+pub mod gateway;
+pub mod gpu;
+pub mod handoff;
+pub mod health;
+pub mod heartbeat_limit;
 pub mod ipc_server;
+pub mod limits;
+pub mod log_throttle;
 pub mod logging;
+pub mod metrics;
 pub mod orchestrator;
+pub mod prometheus;
+pub mod reaper;
 pub mod runner;
+pub mod scheduler;
+pub mod state;
+pub mod storm;
+pub mod watch;
+pub mod watchdog;
+pub mod workspaces;
 
 use krill_common::{Deserialize, Serialize};
 use std::fmt;
 use std::path::PathBuf;
 // use thiserror::Error;
 
+pub use access::{AccessError, AccessPolicy};
+pub use audit::AuditLog;
+pub use daemon_log::LogFilterHandle;
+pub use events::EventJournal;
+pub use gateway::HttpGateway;
+pub use health::HealthMonitor;
 pub use ipc_server::IpcServer;
 #[allow(deprecated)]
-pub use logging::LogStore;
-pub use orchestrator::Orchestrator;
+pub use logging::{LogPage, LogStore};
+pub use metrics::MetricsCollector;
+pub use orchestrator::{Orchestrator, OrchestratorError};
+pub use prometheus::PrometheusExporter;
 pub use runner::ServiceRunner;
+pub use scheduler::Scheduler;
+pub use state::StateFile;
+pub use watchdog::Liveness;
+pub use workspaces::Workspaces;
 
 #[derive(Serialize, Deserialize)]
 pub enum StartupMessage {
@@ -60,4 +96,6 @@ pub enum ErrorCategory {
     LogStore,
     Orchestrator,
     IpcServer,
+    Gateway,
+    Metrics,
 }
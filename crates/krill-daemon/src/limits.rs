@@ -0,0 +1,420 @@
+// Resource Limits - cgroup v2 placement of services, with a setrlimit/nice fallback,
+// and their CPU affinity and scheduling policy
+
+use krill_common::{format_memory_size, ResourceLimits, ServiceMetrics};
+#[cfg(target_os = "linux")]
+use krill_common::{SchedulerConfig, SchedulerPolicy};
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+/// Mount point of the cgroup v2 hierarchy
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Scheduling period for `cpu.max`, in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// A cgroup v2 holding the processes of one service
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+    /// `nr_throttled` of `cpu.stat` at the last check
+    throttled: u64,
+    /// `max` plus `oom_kill` of `memory.events` at the last check
+    memory_events: u64,
+}
+
+impl Cgroup {
+    /// Create (or reuse) the cgroup of a service below the daemon's cgroup.
+    /// `None` if cgroup v2 is unavailable or not delegated to the daemon.
+    pub fn for_service(service: &str, limits: &ResourceLimits) -> Option<Self> {
+        let parent = delegated_parent()?;
+        match Self::create(parent, &format!("krill-{}", service), limits) {
+            Ok(cgroup) => Some(cgroup),
+            Err(e) => {
+                warn!("Failed to create cgroup for '{}': {}", service, e);
+                None
+            }
+        }
+    }
+
+    /// Create the cgroup `name` below `parent` and write its limits
+    pub fn create(parent: &Path, name: &str, limits: &ResourceLimits) -> io::Result<Self> {
+        let path = parent.join(name);
+        fs::create_dir_all(&path)?;
+
+        if let Some(cpu) = limits.cpu {
+            let quota = ((cpu * CPU_PERIOD_US as f64) as u64).max(1000);
+            fs::write(path.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD_US))?;
+        }
+        if let Some(memory) = limits.memory {
+            fs::write(path.join("memory.max"), memory.to_string())?;
+        }
+
+        let mut cgroup = Self {
+            path,
+            throttled: 0,
+            memory_events: 0,
+        };
+        // Only count events from here on
+        cgroup.check();
+        Ok(cgroup)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `cgroup.procs` of the cgroup, for [`join_cgroup`] in a forked child
+    #[cfg(unix)]
+    pub fn procs_path(&self) -> io::Result<CString> {
+        use std::os::unix::ffi::OsStrExt;
+        CString::new(self.path.join("cgroup.procs").as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Limit hits since the last check: CPU throttling, or memory reaching
+    /// `memory.max` (including OOM kills)
+    pub fn check(&mut self) -> Option<String> {
+        let throttled = read_counter(&self.path.join("cpu.stat"), &["nr_throttled"]);
+        let memory_events = read_counter(&self.path.join("memory.events"), &["max", "oom_kill"]);
+
+        let violation = if memory_events > self.memory_events {
+            Some("memory reached its limit".to_string())
+        } else if throttled > self.throttled {
+            Some("CPU throttled at its limit".to_string())
+        } else {
+            None
+        };
+
+        self.throttled = throttled;
+        self.memory_events = memory_events;
+        violation
+    }
+
+    /// Remove the cgroup once its processes are gone
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            debug!("Failed to remove cgroup {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Sum of the named `key value` lines of a cgroup stat file
+fn read_counter(path: &Path, keys: &[&str]) -> u64 {
+    let Ok(content) = fs::read_to_string(path) else {
+        return 0;
+    };
+    content
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(key, _)| keys.contains(key))
+        .filter_map(|(_, value)| value.trim().parse::<u64>().ok())
+        .sum()
+}
+
+/// Usage above the limits in a metrics sample, for limits that are not
+/// enforced by a cgroup
+pub fn violation(limits: &ResourceLimits, metrics: &ServiceMetrics) -> Option<String> {
+    if let Some(memory) = limits.memory {
+        if metrics.memory_bytes > memory {
+            return Some(format!(
+                "memory {} exceeds its limit of {}",
+                format_memory_size(metrics.memory_bytes),
+                format_memory_size(memory)
+            ));
+        }
+    }
+    if let Some(cpu) = limits.cpu {
+        if f64::from(metrics.cpu_percent) > cpu * 100.0 {
+            return Some(format!(
+                "CPU {:.0}% exceeds its limit of {:.0}%",
+                metrics.cpu_percent,
+                cpu * 100.0
+            ));
+        }
+    }
+    None
+}
+
+/// Cgroup below which service cgroups are created, with the `cpu` and
+/// `memory` controllers enabled for its children. cgroup v2 only allows
+/// that for a cgroup without processes of its own, so the daemon first
+/// moves itself into a `krill-daemon` leaf of its cgroup.
+fn delegated_parent() -> Option<&'static PathBuf> {
+    static PARENT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PARENT
+        .get_or_init(|| {
+            let root = Path::new(CGROUP_ROOT);
+            if !root.join("cgroup.controllers").exists() {
+                info!("cgroup v2 is not available, limiting services with rlimits");
+                return None;
+            }
+
+            let own = fs::read_to_string("/proc/self/cgroup").ok()?;
+            let own = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+            let parent = root.join(own.trim().trim_start_matches('/'));
+
+            match enable_controllers(&parent) {
+                Ok(()) => Some(parent),
+                Err(e) => {
+                    info!(
+                        "Cannot create cgroups below {:?} ({}), limiting services with rlimits",
+                        parent, e
+                    );
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+fn enable_controllers(parent: &Path) -> io::Result<()> {
+    let control = parent.join("cgroup.subtree_control");
+    if fs::write(&control, "+cpu +memory").is_ok() {
+        return Ok(());
+    }
+
+    let leaf = parent.join("krill-daemon");
+    fs::create_dir_all(&leaf)?;
+    fs::write(leaf.join("cgroup.procs"), std::process::id().to_string())?;
+    fs::write(&control, "+cpu +memory")
+}
+
+/// Apply the limits a cgroup does not enforce to the calling process: its
+/// nice value, and for `memory_rlimit` without a cgroup an address space
+/// limit.
+/// Runs in the forked child before exec, so it only makes raw system calls.
+#[cfg(unix)]
+pub fn apply_to_current_process(nice: Option<i32>, memory: Option<u64>) -> io::Result<()> {
+    use nix::libc;
+    use nix::sys::resource::{setrlimit, Resource};
+
+    if let Some(nice) = nice {
+        // SAFETY: setpriority has no memory safety requirements
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    if let Some(memory) = memory {
+        setrlimit(Resource::RLIMIT_AS, memory, memory).map_err(io::Error::from)?;
+    }
+    Ok(())
+}
+
+/// Move the calling process into the cgroup whose `cgroup.procs` is `procs`.
+/// Runs in the forked child before exec, so the service is in its cgroup
+/// before it can spawn anything; it only makes raw system calls.
+#[cfg(unix)]
+pub fn join_cgroup(procs: &CStr) -> io::Result<()> {
+    use nix::libc;
+
+    // SAFETY: `procs` is a valid C string, and `fd` is only used while open
+    unsafe {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // "0" stands for the writing process
+        let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+        let result = if written == 1 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        libc::close(fd);
+        result
+    }
+}
+
+/// CPU affinity and scheduling policy of a service, prepared before fork so
+/// the child only has to make the system calls
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+pub struct Scheduling {
+    cpus: Option<nix::libc::cpu_set_t>,
+    /// `SCHED_*` policy and its priority
+    policy: Option<(nix::libc::c_int, nix::libc::c_int)>,
+}
+
+#[cfg(target_os = "linux")]
+impl Scheduling {
+    pub fn new(cpu_affinity: Option<&[usize]>, scheduler: Option<SchedulerConfig>) -> Self {
+        use nix::libc;
+
+        let cpus = cpu_affinity.map(|cpus| {
+            // SAFETY: cpu_set_t is a plain bit array, valid when zeroed, and
+            // the config only allows CPU indices that fit in it
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for &cpu in cpus {
+                    libc::CPU_SET(cpu, &mut set);
+                }
+                set
+            }
+        });
+        let policy = scheduler.map(|scheduler| {
+            let policy = match scheduler.policy {
+                SchedulerPolicy::Other => libc::SCHED_OTHER,
+                SchedulerPolicy::Batch => libc::SCHED_BATCH,
+                SchedulerPolicy::Idle => libc::SCHED_IDLE,
+                SchedulerPolicy::Fifo => libc::SCHED_FIFO,
+                SchedulerPolicy::Rr => libc::SCHED_RR,
+            };
+            (policy, scheduler.priority.into())
+        });
+        Self { cpus, policy }
+    }
+
+    /// Apply to the calling process; runs in the forked child before exec.
+    /// Realtime policies need CAP_SYS_NICE or a sufficient RLIMIT_RTPRIO.
+    pub fn apply(&self) -> io::Result<()> {
+        use nix::libc;
+
+        if let Some(cpus) = &self.cpus {
+            // SAFETY: `cpus` is a valid cpu_set_t of the given size
+            let result =
+                unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), cpus) };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some((policy, priority)) = self.policy {
+            let param = libc::sched_param {
+                sched_priority: priority,
+            };
+            // SAFETY: `param` is a valid sched_param
+            if unsafe { libc::sched_setscheduler(0, policy, &param) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_scheduling_applies_before_exec() {
+        use std::os::unix::process::CommandExt;
+
+        let scheduling = Scheduling::new(
+            Some(&[0]),
+            Some(SchedulerConfig {
+                policy: SchedulerPolicy::Batch,
+                priority: 0,
+            }),
+        );
+        let mut command = std::process::Command::new("grep");
+        command.args(["Cpus_allowed_list", "/proc/self/status"]);
+        // SAFETY: only system calls run between fork and exec
+        unsafe {
+            command.pre_exec(move || scheduling.apply());
+        }
+        let output = command.output().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "Cpus_allowed_list:\t0"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_join_cgroup_before_exec() {
+        use std::os::unix::process::CommandExt;
+
+        let dir = TempDir::new().unwrap();
+        let cgroup = Cgroup::create(dir.path(), "krill-lidar", &Default::default()).unwrap();
+        fs::write(cgroup.path().join("cgroup.procs"), "").unwrap();
+
+        let procs = cgroup.procs_path().unwrap();
+        let mut command = std::process::Command::new("true");
+        // SAFETY: only system calls run between fork and exec
+        unsafe {
+            command.pre_exec(move || join_cgroup(&procs));
+        }
+        assert!(command.status().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(cgroup.path().join("cgroup.procs")).unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_create_writes_limits() {
+        let dir = TempDir::new().unwrap();
+        let limits = ResourceLimits {
+            cpu: Some(1.5),
+            memory: Some(512 << 20),
+            ..Default::default()
+        };
+
+        let cgroup = Cgroup::create(dir.path(), "krill-lidar", &limits).unwrap();
+        assert_eq!(cgroup.path(), dir.path().join("krill-lidar"));
+        assert_eq!(
+            fs::read_to_string(cgroup.path().join("cpu.max")).unwrap(),
+            "150000 100000"
+        );
+        assert_eq!(
+            fs::read_to_string(cgroup.path().join("memory.max")).unwrap(),
+            "536870912"
+        );
+    }
+
+    #[test]
+    fn test_check_reports_new_limit_hits() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("krill-lidar");
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("cpu.stat"), "usage_usec 100\nnr_throttled 3\n").unwrap();
+
+        let mut cgroup = Cgroup::create(dir.path(), "krill-lidar", &Default::default()).unwrap();
+        assert_eq!(cgroup.check(), None);
+
+        fs::write(path.join("cpu.stat"), "usage_usec 200\nnr_throttled 5\n").unwrap();
+        assert_eq!(
+            cgroup.check().as_deref(),
+            Some("CPU throttled at its limit")
+        );
+        assert_eq!(cgroup.check(), None);
+
+        fs::write(
+            path.join("memory.events"),
+            "low 0\nhigh 0\nmax 1\noom 0\noom_kill 1\n",
+        )
+        .unwrap();
+        assert_eq!(cgroup.check().as_deref(), Some("memory reached its limit"));
+    }
+
+    #[test]
+    fn test_violation_from_metrics() {
+        let limits = ResourceLimits {
+            cpu: Some(0.5),
+            memory: Some(100 << 20),
+            ..Default::default()
+        };
+        let metrics = |cpu_percent, memory_bytes| ServiceMetrics {
+            cpu_percent,
+            memory_bytes,
+            threads: 1,
+        };
+
+        assert_eq!(violation(&limits, &metrics(40.0, 50 << 20)), None);
+        assert_eq!(
+            violation(&limits, &metrics(80.0, 50 << 20)).as_deref(),
+            Some("CPU 80% exceeds its limit of 50%")
+        );
+        assert_eq!(
+            violation(&limits, &metrics(10.0, 200 << 20)).as_deref(),
+            Some("memory 200.0 MiB exceeds its limit of 100.0 MiB")
+        );
+    }
+}
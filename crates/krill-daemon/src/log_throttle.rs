@@ -0,0 +1,312 @@
+// Log Throttle - Per-service rate limiting and deduplication of the output streamed to clients
+
+use krill_common::{LogLevel, LogThrottleConfig, ProcessOutputLine};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often a service that keeps flooding is reported with a marker
+pub const MARKER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What clients taking throttled output receive for a line
+#[derive(Debug, Default, PartialEq)]
+pub struct Admitted {
+    /// Markers for lines of the service suppressed before this one
+    pub markers: Vec<ProcessOutputLine>,
+    /// Whether the line itself is forwarded
+    pub forward: bool,
+}
+
+impl Admitted {
+    /// The line goes out unchanged, with no markers
+    pub fn is_unchanged(&self) -> bool {
+        self.forward && self.markers.is_empty()
+    }
+}
+
+struct ServiceLogs {
+    tokens: f64,
+    refilled: Instant,
+    /// Last line the service printed, and how often it came in a row
+    last_line: Option<String>,
+    repeats: u64,
+    /// Repeats of `last_line` suppressed since the last marker
+    suppressed_repeats: u64,
+    /// Lines over the rate limit suppressed since the last marker
+    suppressed_lines: u64,
+    /// When the first line suppressed since the last marker came in
+    suppressed_since: Option<Instant>,
+}
+
+impl ServiceLogs {
+    fn new(burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            refilled: now,
+            last_line: None,
+            repeats: 0,
+            suppressed_repeats: 0,
+            suppressed_lines: 0,
+            suppressed_since: None,
+        }
+    }
+
+    /// Refill for the time passed and take a token if one is available
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn suppress(&mut self, now: Instant) {
+        self.suppressed_since.get_or_insert(now);
+    }
+
+    /// Marker for the repeats suppressed since the last one, if any
+    fn repeats_marker(&mut self, service: &str) -> Option<ProcessOutputLine> {
+        let count = std::mem::take(&mut self.suppressed_repeats);
+        (count > 0).then(|| {
+            marker(
+                service,
+                format!("{} identical {} suppressed", count, lines(count)),
+            )
+        })
+    }
+
+    /// Marker for the lines over the rate limit since the last one, if any
+    fn rate_marker(&mut self, service: &str, rate: u32) -> Option<ProcessOutputLine> {
+        let count = std::mem::take(&mut self.suppressed_lines);
+        (count > 0).then(|| {
+            marker(
+                service,
+                format!(
+                    "{} {} suppressed (over {} lines/s)",
+                    count,
+                    lines(count),
+                    rate
+                ),
+            )
+        })
+    }
+}
+
+/// Limits the output of each service streamed to clients, so a service
+/// flooding stdout does not drown the TUI. A line repeated more than
+/// `max_repeats` times in a row is withheld, as is output beyond
+/// `lines_per_sec` once the `burst` is spent; clients are then sent a
+/// marker saying how many lines were suppressed, when the run ends or
+/// every [`MARKER_INTERVAL`]. Clients that ask for raw output, the log
+/// files and the log history get every line.
+pub struct LogThrottle {
+    config: Mutex<LogThrottleConfig>,
+    services: Mutex<HashMap<String, ServiceLogs>>,
+}
+
+impl Default for LogThrottle {
+    fn default() -> Self {
+        Self::new(LogThrottleConfig::default())
+    }
+}
+
+impl LogThrottle {
+    pub fn new(config: LogThrottleConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply new settings, e.g. from a reloaded recipe, starting afresh
+    pub fn set_config(&self, config: LogThrottleConfig) {
+        *self.config.lock().unwrap() = config;
+        self.services.lock().unwrap().clear();
+    }
+
+    /// What throttled clients receive for `output`
+    pub fn admit(&self, output: &ProcessOutputLine, now: Instant) -> Admitted {
+        let config = self.config.lock().unwrap().clone();
+        if !config.enabled {
+            return Admitted {
+                markers: Vec::new(),
+                forward: true,
+            };
+        }
+        let (rate, burst) = (config.lines_per_sec as f64, config.burst.max(1) as f64);
+
+        let mut services = self.services.lock().unwrap();
+        let logs = services
+            .entry(output.service.clone())
+            .or_insert_with(|| ServiceLogs::new(burst, now));
+        let mut admitted = Admitted::default();
+
+        // Repeats cost no tokens, so a repeating service is not rate limited
+        if logs.last_line.as_deref() == Some(output.line.as_str()) {
+            logs.repeats += 1;
+            if logs.repeats > config.max_repeats as u64 {
+                logs.suppressed_repeats += 1;
+                logs.suppress(now);
+                return admitted;
+            }
+        } else {
+            admitted
+                .markers
+                .extend(logs.repeats_marker(&output.service));
+            logs.last_line = Some(output.line.clone());
+            logs.repeats = 1;
+        }
+
+        if !logs.take(rate, burst, now) {
+            logs.suppressed_lines += 1;
+            logs.suppress(now);
+            return admitted;
+        }
+        admitted
+            .markers
+            .extend(logs.rate_marker(&output.service, config.lines_per_sec));
+        if logs.suppressed_repeats == 0 && logs.suppressed_lines == 0 {
+            logs.suppressed_since = None;
+        }
+        admitted.forward = true;
+        admitted
+    }
+
+    /// Markers for services that had lines suppressed for at least
+    /// [`MARKER_INTERVAL`] without one, e.g. because they keep flooding or
+    /// went quiet during a run of repeats
+    pub fn due(&self, now: Instant) -> Vec<ProcessOutputLine> {
+        let rate = self.config.lock().unwrap().lines_per_sec;
+        let mut services = self.services.lock().unwrap();
+        let mut markers = Vec::new();
+        for (service, logs) in services.iter_mut() {
+            let due = logs
+                .suppressed_since
+                .is_some_and(|since| now.saturating_duration_since(since) >= MARKER_INTERVAL);
+            if due {
+                markers.extend(logs.repeats_marker(service));
+                markers.extend(logs.rate_marker(service, rate));
+                logs.suppressed_since = None;
+            }
+        }
+        markers
+    }
+}
+
+/// A line the daemon adds to a service's output about suppressed lines
+fn marker(service: &str, message: String) -> ProcessOutputLine {
+    let mut line = ProcessOutputLine::new(service, format!("[krill] {}", message));
+    line.level = Some(LogLevel::Warn);
+    line
+}
+
+fn lines(count: u64) -> &'static str {
+    if count == 1 {
+        "line"
+    } else {
+        "lines"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(lines_per_sec: u32, burst: u32, max_repeats: u32) -> LogThrottle {
+        LogThrottle::new(LogThrottleConfig {
+            enabled: true,
+            lines_per_sec,
+            burst,
+            max_repeats,
+        })
+    }
+
+    fn output(line: &str) -> ProcessOutputLine {
+        ProcessOutputLine::new("dds", line)
+    }
+
+    fn marker_lines(admitted: &Admitted) -> Vec<&str> {
+        admitted.markers.iter().map(|m| m.line.as_str()).collect()
+    }
+
+    #[test]
+    fn test_repeated_lines_are_collapsed() {
+        let throttle = throttle(1000, 1000, 2);
+        let now = Instant::now();
+
+        assert!(throttle
+            .admit(&output("participant found"), now)
+            .is_unchanged());
+        assert!(throttle
+            .admit(&output("participant found"), now)
+            .is_unchanged());
+        for _ in 0..5 {
+            assert_eq!(
+                throttle.admit(&output("participant found"), now),
+                Admitted::default()
+            );
+        }
+
+        let admitted = throttle.admit(&output("discovery done"), now);
+        assert!(admitted.forward);
+        assert_eq!(
+            marker_lines(&admitted),
+            ["[krill] 5 identical lines suppressed"]
+        );
+        assert_eq!(admitted.markers[0].level, Some(LogLevel::Warn));
+        assert_eq!(admitted.markers[0].stream, None);
+    }
+
+    #[test]
+    fn test_flood_is_rate_limited() {
+        let throttle = throttle(10, 3, 100);
+        let start = Instant::now();
+
+        let forwarded = (0..10)
+            .filter(|i| throttle.admit(&output(&i.to_string()), start).forward)
+            .count();
+        assert_eq!(forwarded, 3);
+
+        // Tokens come back with time, and the first line to pass says
+        // how many did not
+        let later = start + Duration::from_millis(200);
+        let admitted = throttle.admit(&output("back"), later);
+        assert!(admitted.forward);
+        assert_eq!(
+            marker_lines(&admitted),
+            ["[krill] 7 lines suppressed (over 10 lines/s)"]
+        );
+    }
+
+    #[test]
+    fn test_due_reports_ongoing_suppression() {
+        let throttle = throttle(1000, 1000, 1);
+        let start = Instant::now();
+        throttle.admit(&output("spam"), start);
+        throttle.admit(&output("spam"), start);
+
+        assert!(throttle.due(start).is_empty());
+        let markers = throttle.due(start + MARKER_INTERVAL);
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].service, "dds");
+        assert_eq!(markers[0].line, "[krill] 1 identical line suppressed");
+        assert!(throttle.due(start + MARKER_INTERVAL * 2).is_empty());
+    }
+
+    #[test]
+    fn test_disabled_throttle_forwards_everything() {
+        let throttle = LogThrottle::default();
+        throttle.set_config(LogThrottleConfig {
+            enabled: false,
+            ..LogThrottleConfig::default()
+        });
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(throttle.admit(&output("same"), now).is_unchanged());
+        }
+    }
+}
@@ -3,6 +3,9 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use dirs::home_dir;
+use krill_common::{
+    parse_log_level, KrillConfig, LogFormat, LogRotationConfig, LogStream, ProcessOutputLine,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::fs::{self, File, OpenOptions};
@@ -13,8 +16,11 @@ use thiserror::Error;
 use tokio::sync::RwLock;
 use tracing::info;
 
-/// Maximum log lines to keep in memory per service
-const MAX_LOG_LINES: usize = 5000;
+/// Most lines returned in one page of [`LogStore::get_log_page`]
+pub const MAX_LOG_PAGE_LINES: usize = 5000;
+
+/// Bytes accounted per buffered line on top of its text
+const LOG_ENTRY_OVERHEAD: usize = std::mem::size_of::<LogEntry>();
 
 /// Expand ~ to home directory in paths
 fn expand_tilde(path: &Path) -> Result<PathBuf> {
@@ -33,6 +39,18 @@ fn expand_tilde(path: &Path) -> Result<PathBuf> {
     Ok(path.to_path_buf())
 }
 
+/// Directory holding the log sessions: `log_dir` of the recipe with ~
+/// expanded, `~/.krill/logs` if not specified
+pub fn log_base_dir(log_dir: Option<&Path>) -> PathBuf {
+    match log_dir {
+        Some(dir) => expand_tilde(dir).unwrap(),
+        None => {
+            let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+            home.join(".krill").join("logs")
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum LogError {
     #[error("IO error: {0}")]
@@ -59,26 +77,211 @@ pub enum LogLevel {
     Error,
 }
 
+impl From<krill_common::LogLevel> for LogLevel {
+    fn from(level: krill_common::LogLevel) -> Self {
+        match level {
+            krill_common::LogLevel::Trace | krill_common::LogLevel::Debug => LogLevel::Debug,
+            krill_common::LogLevel::Info => LogLevel::Info,
+            krill_common::LogLevel::Warn => LogLevel::Warn,
+            krill_common::LogLevel::Error => LogLevel::Error,
+        }
+    }
+}
+
+/// A line of a service log file in the `jsonl` format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonLogLine {
+    pub ts: DateTime<Utc>,
+    pub stream: Option<LogStream>,
+    pub service: String,
+    pub line: String,
+    pub level: Option<krill_common::LogLevel>,
+}
+
+impl From<&ProcessOutputLine> for JsonLogLine {
+    fn from(output: &ProcessOutputLine) -> Self {
+        Self {
+            ts: output.timestamp,
+            stream: output.stream,
+            service: output.service.clone(),
+            line: output.line.clone(),
+            level: output.level,
+        }
+    }
+}
+
+/// A buffered line of service output
+struct LogEntry {
+    line: String,
+    level: Option<krill_common::LogLevel>,
+}
+
+impl LogEntry {
+    fn size(&self) -> usize {
+        self.line.len() + LOG_ENTRY_OVERHEAD
+    }
+}
+
+/// How much output the in-memory buffers keep
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferLimits {
+    /// Lines per service without an override
+    pub lines: usize,
+    /// Lines per service, for services that set their own
+    pub service_lines: HashMap<String, usize>,
+    /// Bytes across all buffers
+    pub max_bytes: usize,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        Self {
+            lines: 5000,
+            service_lines: HashMap::new(),
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl BufferLimits {
+    pub fn from_config(config: &KrillConfig) -> Self {
+        Self {
+            lines: config.log_buffer_lines,
+            service_lines: config
+                .services
+                .iter()
+                .filter_map(|(name, service)| Some((name.clone(), service.log_buffer_lines?)))
+                .collect(),
+            max_bytes: usize::try_from(config.log_buffer_max_mb.saturating_mul(1024 * 1024))
+                .unwrap_or(usize::MAX),
+        }
+    }
+
+    fn lines(&self, service: &str) -> usize {
+        self.service_lines
+            .get(service)
+            .copied()
+            .unwrap_or(self.lines)
+    }
+}
+
+/// Buffered output of one service
+#[derive(Default)]
+struct ServiceBuffer {
+    entries: VecDeque<LogEntry>,
+    bytes: usize,
+    /// Lines dropped from the front; older output is only on disk
+    dropped: u64,
+}
+
+impl ServiceBuffer {
+    fn pop_front(&mut self) -> Option<usize> {
+        let entry = self.entries.pop_front()?;
+        self.dropped += 1;
+        self.bytes -= entry.size();
+        Some(entry.size())
+    }
+}
+
+/// In-memory buffers of all services, with their memory accounted
+#[derive(Default)]
+struct Buffers {
+    services: HashMap<String, ServiceBuffer>,
+    bytes: usize,
+    limits: BufferLimits,
+}
+
+impl Buffers {
+    fn push(&mut self, service: &str, entry: LogEntry) {
+        let max_lines = self.limits.lines(service);
+        let buffer = self.services.entry(service.to_string()).or_default();
+
+        self.bytes += entry.size();
+        buffer.bytes += entry.size();
+        buffer.entries.push_back(entry);
+        while buffer.entries.len() > max_lines {
+            self.bytes -= buffer.pop_front().unwrap_or(0);
+        }
+
+        self.enforce_memory_cap();
+    }
+
+    /// Drop the oldest lines of the largest buffers until all of them fit
+    fn enforce_memory_cap(&mut self) {
+        while self.bytes > self.limits.max_bytes {
+            let Some(largest) = self.services.values_mut().max_by_key(|buffer| buffer.bytes) else {
+                break;
+            };
+            match largest.pop_front() {
+                Some(size) => self.bytes -= size,
+                None => break,
+            }
+        }
+    }
+
+    fn set_limits(&mut self, limits: BufferLimits) {
+        self.limits = limits;
+        for (service, buffer) in self.services.iter_mut() {
+            let max_lines = self.limits.lines(service);
+            while buffer.entries.len() > max_lines {
+                self.bytes -= buffer.pop_front().unwrap_or(0);
+            }
+        }
+        self.enforce_memory_cap();
+    }
+}
+
+/// Whether a line at `level` passes a `min_level` filter; lines without a
+/// recognizable level only pass when there is no filter
+fn passes(
+    level: Option<krill_common::LogLevel>,
+    min_level: Option<krill_common::LogLevel>,
+) -> bool {
+    min_level.is_none_or(|min| level.is_some_and(|level| level >= min))
+}
+
+/// A page of a service's output, see [`LogStore::get_log_page`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogPage {
+    /// Lines oldest first
+    pub lines: Vec<String>,
+    /// Position of the oldest line returned, to pass as `before` for the
+    /// preceding page; `None` once nothing older is left
+    pub cursor: Option<u64>,
+}
+
 /// Thread-safe log storage with file persistence
 pub struct LogStore {
     /// In-memory log buffer per service
-    logs: RwLock<HashMap<String, VecDeque<String>>>,
+    logs: RwLock<Buffers>,
     /// Session directory for log files
     session_dir: PathBuf,
     /// Timeline file handle
     timeline_file: RwLock<File>,
+    /// Size-based rotation settings for per-service log files
+    rotation: LogRotationConfig,
+    /// Format of the per-service log files
+    format: LogFormat,
 }
 
 impl LogStore {
     pub fn new(base_dir: Option<PathBuf>) -> Result<Arc<Self>, LogError> {
-        // Default to ~/.krill/logs if not specified
-        let base_dir = base_dir.unwrap_or_else(|| {
-            let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-            home.join(".krill").join("logs")
-        });
+        Self::with_rotation(base_dir, LogRotationConfig::default())
+    }
+
+    pub fn with_rotation(
+        base_dir: Option<PathBuf>,
+        rotation: LogRotationConfig,
+    ) -> Result<Arc<Self>, LogError> {
+        Self::with_format(base_dir, rotation, LogFormat::default())
+    }
 
-        // Expand ~ in the path if present
-        let base_dir = expand_tilde(&base_dir).unwrap();
+    pub fn with_format(
+        base_dir: Option<PathBuf>,
+        rotation: LogRotationConfig,
+        format: LogFormat,
+    ) -> Result<Arc<Self>, LogError> {
+        let base_dir = log_base_dir(base_dir.as_deref());
 
         // Create session directory with timestamp
         let timestamp = Utc::now().format("%Y%m%d-%H%M%S");
@@ -99,43 +302,72 @@ impl LogStore {
         File::create(daemon_log_path)?;
 
         Ok(Arc::new(Self {
-            logs: RwLock::new(HashMap::new()),
+            logs: RwLock::new(Buffers::default()),
             session_dir,
             timeline_file: RwLock::new(timeline_file),
+            rotation,
+            format,
         }))
     }
 
-    /// Add a log line for a service
+    /// Add a log line for a service, classifying its level
     pub async fn add_log(&self, service: &str, line: String) {
+        self.add_output(&ProcessOutputLine::new(service, line))
+            .await;
+    }
+
+    /// Add a captured line of service output
+    pub async fn add_output(&self, output: &ProcessOutputLine) {
+        let service = output.service.as_str();
+        let line = &output.line;
+
         // Add to in-memory buffer
-        {
-            let mut logs = self.logs.write().await;
-            let service_logs = logs
-                .entry(service.to_string())
-                .or_insert_with(VecDeque::new);
-            service_logs.push_back(line.clone());
-
-            // Trim if too many lines
-            while service_logs.len() > MAX_LOG_LINES {
-                service_logs.pop_front();
+        self.logs.write().await.push(
+            service,
+            LogEntry {
+                line: line.clone(),
+                level: output.level,
+            },
+        );
+
+        // Write to file, rotating once it exceeds the size limit
+        let log_path = self.service_log_path(service);
+        if service.contains('/') {
+            // Services of hosted workspaces log into a directory per workspace
+            if let Some(dir) = log_path.parent() {
+                let _ = fs::create_dir_all(dir);
             }
         }
-
-        // Write to file
-        let log_path = self.session_dir.join(format!("{}.log", service));
         if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&log_path) {
-            let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f");
-            let _ = writeln!(file, "[{}] {}", timestamp, line);
+            match self.format {
+                LogFormat::Text => {
+                    let timestamp = output.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+                    let _ = writeln!(file, "[{}] {}", timestamp, line);
+                }
+                LogFormat::Jsonl => {
+                    if let Ok(json) = serde_json::to_string(&JsonLogLine::from(output)) {
+                        let _ = writeln!(file, "{}", json);
+                    }
+                }
+            }
+
+            let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+            drop(file);
+            if size > self.rotation.max_size_bytes() {
+                if let Err(e) = rotate_log_file(&log_path, self.rotation.max_files) {
+                    tracing::warn!("Failed to rotate log file {:?}: {}", log_path, e);
+                }
+            }
         }
 
         // Write to timeline
         {
             let mut timeline = self.timeline_file.write().await;
             let event = TimelineEvent {
-                timestamp: Utc::now(),
+                timestamp: output.timestamp,
                 service: service.to_string(),
-                level: LogLevel::Info,
-                message: line,
+                level: output.level.map(LogLevel::from).unwrap_or(LogLevel::Info),
+                message: line.clone(),
             };
             if let Ok(json) = serde_json::to_string(&event) {
                 let _ = writeln!(*timeline, "{}", json);
@@ -144,21 +376,72 @@ impl LogStore {
         }
     }
 
-    /// Get log history for a service (or all services if None)
+    /// Change how much output the in-memory buffers keep, trimming them
+    /// right away if they hold more
+    pub async fn set_buffer_limits(&self, limits: BufferLimits) {
+        self.logs.write().await.set_limits(limits);
+    }
+
+    /// Bytes held by the in-memory buffers
+    pub async fn buffered_bytes(&self) -> usize {
+        self.logs.read().await.bytes
+    }
+
+    /// Get log history for a service (or all services if None).
+    /// Falls back to the log files on disk when the in-memory buffer has
+    /// been trimmed and cannot satisfy `limit`.
     pub async fn get_logs(&self, service: Option<&str>, limit: usize) -> Vec<String> {
+        self.get_logs_at_level(service, limit, None).await
+    }
+
+    /// Like [`get_logs`](Self::get_logs), but only the last `limit` lines
+    /// classified at `min_level` or above
+    pub async fn get_logs_at_level(
+        &self,
+        service: Option<&str>,
+        limit: usize,
+        min_level: Option<krill_common::LogLevel>,
+    ) -> Vec<String> {
         let logs = self.logs.read().await;
 
         match service {
-            Some(svc) => logs
-                .get(svc)
-                .map(|v| v.iter().rev().take(limit).rev().cloned().collect())
-                .unwrap_or_default(),
+            Some(svc) => {
+                let buffer = logs.services.get(svc);
+                let buffered: Vec<&str> = buffer
+                    .map(|buffer| {
+                        buffer
+                            .entries
+                            .iter()
+                            .filter(|entry| passes(entry.level, min_level))
+                            .map(|entry| entry.line.as_str())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let trimmed = buffer.is_some_and(|buffer| buffer.dropped > 0);
+                if trimmed && limit > buffered.len() {
+                    let from_disk: Vec<String> = self
+                        .read_service_log_files(svc)
+                        .into_iter()
+                        .filter(|line| passes(parse_log_level(line), min_level))
+                        .collect();
+                    if from_disk.len() > buffered.len() {
+                        let skip = from_disk.len().saturating_sub(limit);
+                        return from_disk.into_iter().skip(skip).collect();
+                    }
+                }
+
+                let skip = buffered.len().saturating_sub(limit);
+                buffered.into_iter().skip(skip).map(String::from).collect()
+            }
             None => {
                 // Return interleaved logs from all services (simplified: just concatenate)
                 let mut all_logs: Vec<String> = Vec::new();
-                for (svc, svc_logs) in logs.iter() {
-                    for line in svc_logs.iter() {
-                        all_logs.push(format!("[{}] {}", svc, line));
+                for (svc, buffer) in logs.services.iter() {
+                    for entry in buffer.entries.iter() {
+                        if passes(entry.level, min_level) {
+                            all_logs.push(format!("[{}] {}", svc, entry.line));
+                        }
                     }
                 }
                 // Take last N lines
@@ -168,11 +451,96 @@ impl LogStore {
         }
     }
 
+    /// Up to `limit` lines of a service's output (at `min_level` or above)
+    /// older than the cursor `before`, or the newest lines without one.
+    /// Cursors count the service's lines since the daemon started, so they
+    /// stay valid while new output arrives; lines no longer in memory are
+    /// read back from the log files.
+    pub async fn get_log_page(
+        &self,
+        service: &str,
+        before: Option<u64>,
+        limit: usize,
+        min_level: Option<krill_common::LogLevel>,
+    ) -> LogPage {
+        let limit = limit.min(MAX_LOG_PAGE_LINES);
+        let logs = self.logs.read().await;
+        let Some(buffer) = logs.services.get(service) else {
+            return LogPage::default();
+        };
+
+        let total = buffer.dropped + buffer.entries.len() as u64;
+        let mut position = before.unwrap_or(total).min(total);
+        let mut lines = Vec::new();
+
+        while position > buffer.dropped && lines.len() < limit {
+            position -= 1;
+            let entry = &buffer.entries[(position - buffer.dropped) as usize];
+            if passes(entry.level, min_level) {
+                lines.push(entry.line.clone());
+            }
+        }
+
+        let mut oldest = 0;
+        if lines.len() < limit && position > 0 {
+            // The log files end with the buffered lines
+            let from_disk = self.read_service_log_files(service);
+            oldest = total.saturating_sub(from_disk.len() as u64);
+            while position > oldest && lines.len() < limit {
+                position -= 1;
+                let line = &from_disk[(position - oldest) as usize];
+                if passes(parse_log_level(line), min_level) {
+                    lines.push(line.clone());
+                }
+            }
+        }
+
+        lines.reverse();
+        LogPage {
+            lines,
+            cursor: (position > oldest).then_some(position),
+        }
+    }
+
     /// Get session directory path
     pub fn session_dir(&self) -> &Path {
         &self.session_dir
     }
 
+    /// Get the path of a service's current log file
+    pub fn service_log_path(&self, service: &str) -> PathBuf {
+        let extension = match self.format {
+            LogFormat::Text => "log",
+            LogFormat::Jsonl => "jsonl",
+        };
+        self.session_dir.join(format!("{}.{}", service, extension))
+    }
+
+    /// Read all persisted lines for a service, oldest first, without timestamps
+    fn read_service_log_files(&self, service: &str) -> Vec<String> {
+        let current = self.service_log_path(service);
+        let mut lines = Vec::new();
+
+        let mut read = |path: &Path| {
+            if let Ok(content) = fs::read_to_string(path) {
+                lines.extend(content.lines().map(|line| {
+                    match self.format {
+                        LogFormat::Text => strip_timestamp(line),
+                        LogFormat::Jsonl => serde_json::from_str::<JsonLogLine>(line)
+                            .map(|entry| entry.line)
+                            .unwrap_or_else(|_| line.to_string()),
+                    }
+                }));
+            }
+        };
+        for index in (1..=self.rotation.max_files).rev() {
+            read(&rotated_path(&current, index));
+        }
+        read(&current);
+
+        lines
+    }
+
     /// Log a daemon event
     pub async fn log_daemon(&self, level: LogLevel, message: &str) {
         let daemon_log_path = self.session_dir.join("krill.log");
@@ -203,6 +571,43 @@ impl LogStore {
     }
 }
 
+/// Path of the `index`-th rotated file, e.g. `lidar.log.1`
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Shift `file.log` -> `file.log.1` -> ... and drop files beyond `max_files`
+fn rotate_log_file(path: &Path, max_files: u32) -> std::io::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+
+    let oldest = rotated_path(path, max_files);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..max_files).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+/// Strip the `[timestamp] ` prefix written by `add_log`
+fn strip_timestamp(line: &str) -> String {
+    match line
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+    {
+        Some((_, message)) => message.to_string(),
+        None => line.to_string(),
+    }
+}
+
 // Keep the old LogManager for backward compatibility but mark as deprecated
 // #[deprecated(note = "Use LogStore instead")]
 // pub struct LogManager {
@@ -371,4 +776,206 @@ mod tests {
         assert!(log_store.session_dir().join("timeline.jsonl").exists());
         assert!(log_store.session_dir().join("krill.log").exists());
     }
+
+    #[tokio::test]
+    async fn test_service_log_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let rotation = LogRotationConfig {
+            max_size_mb: 0,
+            max_files: 2,
+        };
+        let log_store =
+            LogStore::with_rotation(Some(temp_dir.path().to_path_buf()), rotation).unwrap();
+
+        for i in 0..4 {
+            log_store.add_log("svc", format!("line-{}", i)).await;
+        }
+
+        let current = log_store.service_log_path("svc");
+        assert!(rotated_path(&current, 1).exists());
+        assert!(rotated_path(&current, 2).exists());
+        assert!(!rotated_path(&current, 3).exists());
+
+        let newest = fs::read_to_string(rotated_path(&current, 1)).unwrap();
+        assert!(newest.contains("line-3"));
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_service_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_store = LogStore::with_format(
+            Some(temp_dir.path().to_path_buf()),
+            LogRotationConfig::default(),
+            LogFormat::Jsonl,
+        )
+        .unwrap();
+
+        let mut output = ProcessOutputLine::new("lidar", "ERROR: no packets [eth0]");
+        output.stream = Some(LogStream::Stderr);
+        log_store.add_output(&output).await;
+        log_store.add_log("lidar", "up".to_string()).await;
+
+        let path = log_store.service_log_path("lidar");
+        assert_eq!(path.file_name().unwrap(), "lidar.jsonl");
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["service"], "lidar");
+        assert_eq!(lines[0]["stream"], "stderr");
+        assert_eq!(lines[0]["level"], "error");
+        assert_eq!(lines[0]["line"], "ERROR: no packets [eth0]");
+        assert!(lines[0]["ts"].as_str().unwrap().ends_with('Z'));
+        assert!(lines[1]["stream"].is_null());
+
+        // Reading back from disk yields the lines themselves
+        assert_eq!(
+            log_store.read_service_log_files("lidar"),
+            ["ERROR: no packets [eth0]", "up"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_logs_filters_by_level() {
+        use krill_common::LogLevel as Level;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_store = LogStore::new(Some(temp_dir.path().to_path_buf())).unwrap();
+
+        log_store.add_log("svc", "plain output".to_string()).await;
+        log_store.add_log("svc", "DEBUG tick".to_string()).await;
+        log_store
+            .add_log("svc", "[WARN] [1.0] [svc]: hot".to_string())
+            .await;
+        log_store.add_log("svc", "ERROR: failed".to_string()).await;
+
+        assert_eq!(log_store.get_logs(Some("svc"), 10).await.len(), 4);
+        assert_eq!(
+            log_store
+                .get_logs_at_level(Some("svc"), 10, Some(Level::Warn))
+                .await,
+            vec!["[WARN] [1.0] [svc]: hot", "ERROR: failed"]
+        );
+        assert_eq!(
+            log_store
+                .get_logs_at_level(Some("svc"), 1, Some(Level::Debug))
+                .await,
+            vec!["ERROR: failed"]
+        );
+        assert_eq!(
+            log_store
+                .get_logs_at_level(None, 10, Some(Level::Error))
+                .await,
+            vec!["[svc] ERROR: failed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_pages_walk_back_through_memory_and_disk() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_store = LogStore::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        log_store
+            .set_buffer_limits(BufferLimits {
+                lines: 3,
+                ..Default::default()
+            })
+            .await;
+
+        for i in 0..7 {
+            log_store.add_log("svc", format!("line-{}", i)).await;
+        }
+
+        let page = log_store.get_log_page("svc", None, 2, None).await;
+        assert_eq!(page.lines, vec!["line-5", "line-6"]);
+        assert_eq!(page.cursor, Some(5));
+
+        // New output does not shift the cursor
+        log_store.add_log("svc", "line-7".to_string()).await;
+
+        // Crosses from the buffer into the log file
+        let page = log_store.get_log_page("svc", page.cursor, 3, None).await;
+        assert_eq!(page.lines, vec!["line-2", "line-3", "line-4"]);
+        assert_eq!(page.cursor, Some(2));
+
+        let page = log_store.get_log_page("svc", page.cursor, 10, None).await;
+        assert_eq!(page.lines, vec!["line-0", "line-1"]);
+        assert_eq!(page.cursor, None);
+
+        let page = log_store.get_log_page("missing", None, 10, None).await;
+        assert_eq!(page, LogPage::default());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_lines_per_service() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_store = LogStore::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        log_store
+            .set_buffer_limits(BufferLimits {
+                lines: 2,
+                service_lines: HashMap::from([("chatty".to_string(), 4)]),
+                ..Default::default()
+            })
+            .await;
+
+        for i in 0..6 {
+            log_store.add_log("quiet", format!("quiet-{}", i)).await;
+            log_store.add_log("chatty", format!("chatty-{}", i)).await;
+        }
+
+        let logs = log_store.logs.read().await;
+        assert_eq!(logs.services["quiet"].entries.len(), 2);
+        assert_eq!(logs.services["chatty"].entries.len(), 4);
+        drop(logs);
+
+        // Trimmed lines are still served from the log file
+        assert_eq!(
+            log_store.get_logs(Some("quiet"), 3).await,
+            vec!["quiet-3", "quiet-4", "quiet-5"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_cap_trims_largest_buffer() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_store = LogStore::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        let line = "x".repeat(100);
+        let entry_size = line.len() + LOG_ENTRY_OVERHEAD;
+        log_store
+            .set_buffer_limits(BufferLimits {
+                max_bytes: 10 * entry_size,
+                ..Default::default()
+            })
+            .await;
+
+        log_store.add_log("quiet", line.clone()).await;
+        for _ in 0..20 {
+            log_store.add_log("chatty", line.clone()).await;
+        }
+
+        assert_eq!(log_store.buffered_bytes().await, 10 * entry_size);
+        let logs = log_store.logs.read().await;
+        assert_eq!(logs.services["quiet"].entries.len(), 1);
+        assert_eq!(logs.services["chatty"].entries.len(), 9);
+        drop(logs);
+
+        // Lowering the cap trims existing buffers right away
+        log_store
+            .set_buffer_limits(BufferLimits {
+                max_bytes: 2 * entry_size,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(log_store.buffered_bytes().await, 2 * entry_size);
+    }
+
+    #[test]
+    fn test_strip_timestamp() {
+        assert_eq!(
+            strip_timestamp("[2025-01-01 00:00:00.000] hello [world]"),
+            "hello [world]"
+        );
+        assert_eq!(strip_timestamp("no prefix"), "no prefix");
+    }
 }
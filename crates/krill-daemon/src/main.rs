@@ -2,15 +2,34 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use krill_common::KrillConfig;
-use krill_daemon::{IpcServer, LogStore, Orchestrator};
+use krill_common::{KrillConfig, KrillError, SOCKET_ENV};
+use krill_daemon::audit::{self, AuditLog};
+use krill_daemon::bundle::{self, SessionMetadata};
+use krill_daemon::daemon_log::{self, DaemonLog, DaemonLogOptions};
+#[cfg(unix)]
+use krill_daemon::daemonize::{self, PidFile};
+use krill_daemon::events::{self, EventJournal};
+use krill_daemon::gateway::{self, HttpGateway};
+use krill_daemon::handoff;
+use krill_daemon::ipc_server::{ReloadRequest, SNAPSHOT_DELTA_INTERVAL};
+use krill_daemon::logging::BufferLimits;
+use krill_daemon::metrics::METRICS_INTERVAL;
+use krill_daemon::orchestrator;
+use krill_daemon::reaper;
+use krill_daemon::scheduler;
+use krill_daemon::watch;
+use krill_daemon::watchdog::{self, SystemdNotifier};
+use krill_daemon::{
+    AccessPolicy, IpcServer, Liveness, LogStore, Orchestrator, PrometheusExporter, Scheduler,
+    StateFile, Workspaces,
+};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::mpsc;
 use tracing::{error, info, warn};
-use tracing_subscriber::{fmt, EnvFilter};
 
 #[derive(Parser, Debug)]
 #[command(name = "krill-daemon")]
@@ -24,43 +43,180 @@ struct Args {
     #[arg(long, value_name = "DIR")]
     log_dir: Option<PathBuf>,
 
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    socket: PathBuf,
+    /// IPC socket path, or @name for an abstract socket (defaults to the
+    /// recipe's `socket`, else one named after the workspace)
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Also accept IPC clients over TCP (e.g. 127.0.0.1:7777)
+    #[arg(long, value_name = "ADDR")]
+    listen: Option<SocketAddr>,
+
+    /// Only start this service group (and its dependencies)
+    #[arg(long, value_name = "GROUP")]
+    group: Option<String>,
+
+    /// Serve the HTTP/JSON gateway on this address (e.g. 0.0.0.0:8080)
+    #[arg(long, value_name = "ADDR", requires = "http_token_file")]
+    http: Option<SocketAddr>,
+
+    /// File holding the bearer token required by the HTTP gateway
+    #[arg(long, value_name = "FILE")]
+    http_token_file: Option<PathBuf>,
+
+    /// Also host the workspace of this recipe; its services are addressed as
+    /// `workspace/service` (repeatable)
+    #[arg(long = "workspace", value_name = "FILE")]
+    workspaces: Vec<PathBuf>,
+
+    /// Reload the configuration whenever its file, an included file or a
+    /// service's env file or file secret changes
+    #[arg(long)]
+    watch: bool,
+
+    /// Detach from the terminal into a session of its own, with output in
+    /// the session's krill.log and a locked PID file (the default stays in
+    /// the foreground, as systemd expects)
+    #[arg(long)]
+    daemonize: bool,
+
+    /// Take over the services of the daemon handing them over on this socket
+    /// (started by `upgrade_daemon`)
+    #[arg(long, hide = true, value_name = "SOCKET")]
+    handoff: Option<PathBuf>,
 
     /// Verbose logging
     #[arg(short, long)]
     verbose: bool,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Initialize tracing
-    let filter = if args.verbose {
-        EnvFilter::new("debug")
+    // Tracing comes first, so loading the recipe is logged; krill.log and
+    // the recipe's filter follow once the session directory is known
+    let daemon_log = daemon_log::init(DaemonLogOptions {
+        stdout: !args.daemonize,
+        filter: args.verbose.then(|| "debug".to_string()),
+    })
+    .context("Failed to initialize tracing")?;
+
+    // Load configuration
+    let config = KrillConfig::from_file(&args.config).context("Failed to load configuration")?;
+
+    // Forking is only safe before the runtime starts its threads. A daemon
+    // taking over in a handoff already runs in a session of its own.
+    let pid_file = if args.daemonize && args.handoff.is_none() {
+        Some(daemonize(&config)?)
     } else {
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+        None
     };
 
-    fmt().with_env_filter(filter).with_target(false).init();
+    // Services and the SDK clients they embed find the daemon through
+    // KRILL_SOCKET, set while the process is still single-threaded
+    let socket = match args.socket.clone() {
+        Some(socket) => socket,
+        None => config.socket_path()?,
+    };
+    std::env::set_var(SOCKET_ENV, &socket);
 
-    info!("Starting krill-daemon");
+    let notifier = SystemdNotifier::from_env();
 
-    // Load configuration
-    info!("Loading configuration from {:?}", args.config);
-    let config = KrillConfig::from_file(&args.config).context("Failed to load configuration")?;
+    tokio::runtime::Runtime::new()
+        .context("Failed to start the async runtime")?
+        .block_on(run(args, socket, config, pid_file, notifier, daemon_log))
+}
 
-    info!("Loaded workspace: {}", config.name);
-    info!("Services: {}", config.services.len());
+/// Lock the workspace's PID file, so a second daemon fails while the
+/// terminal can still show why, then detach from that terminal
+#[cfg(unix)]
+fn daemonize(config: &KrillConfig) -> Result<PidFile> {
+    let mut pid_file = PidFile::acquire(&PidFile::default_path(&config.name)?)?;
+    daemonize::detach().context("Failed to detach from the terminal")?;
+    pid_file
+        .write_pid()
+        .context("Failed to record the daemon's PID")?;
+    Ok(pid_file)
+}
+
+#[cfg(not(unix))]
+fn daemonize(_config: &KrillConfig) -> Result<PidFile> {
+    anyhow::bail!("--daemonize is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+type PidFile = std::convert::Infallible;
+
+async fn run(
+    args: Args,
+    socket: PathBuf,
+    config: KrillConfig,
+    pid_file: Option<PidFile>,
+    notifier: SystemdNotifier,
+    daemon_log: DaemonLog,
+) -> Result<()> {
+    if let Some(ref group) = args.group {
+        if !config.groups.contains_key(group) {
+            anyhow::bail!("Unknown service group '{}'", group);
+        }
+    }
 
     // Initialize logging system
     let log_dir = args.log_dir.or(config.log_dir.clone());
-    let log_store = LogStore::new(log_dir).context("Failed to initialize log manager")?;
+    let log_store = LogStore::with_format(log_dir, config.log_rotation.clone(), config.log_format)
+        .context("Failed to initialize log manager")?;
+
+    // Trace to the session's krill.log as well
+    let log_filter = daemon_log
+        .open(&config.daemon_log, log_store.session_dir())
+        .context("Failed to initialize tracing")?;
+
+    // Panics and anything else printed land in krill.log, not a closed terminal
+    #[cfg(unix)]
+    if args.daemonize {
+        daemonize::redirect_output(&log_store.session_dir().join("krill.log"))
+            .context("Failed to redirect output to krill.log")?;
+    }
 
+    info!("Starting krill-daemon");
+    info!("Loaded configuration from {:?}", args.config);
+    info!("Loaded workspace: {}", config.name);
+    info!("Services: {}", config.services.len());
     info!("Logs directory: {:?}", log_store.session_dir());
 
+    // Keep the recipe and who ran it with the logs, for `krill logs --export`
+    let metadata = SessionMetadata::current(&config.name, &args.config);
+    if let Err(e) = bundle::record_session(log_store.session_dir(), &metadata) {
+        warn!("Failed to record session metadata: {}", e);
+    }
+    log_store
+        .set_buffer_limits(BufferLimits::from_config(&config))
+        .await;
+
+    // Record status changes for `krill events`
+    let journal_path = EventJournal::session_path(log_store.session_dir());
+    let event_journal = Arc::new(
+        EventJournal::with_file(events::MAX_EVENTS, &journal_path).unwrap_or_else(|e| {
+            warn!("Failed to open event journal {:?}: {}", journal_path, e);
+            EventJournal::new(events::MAX_EVENTS)
+        }),
+    );
+
+    // Record control commands and who sent them for `krill audit`
+    let audit_path = AuditLog::session_path(log_store.session_dir());
+    let audit_log = Arc::new(
+        AuditLog::with_file(audit::MAX_RECORDS, &audit_path).unwrap_or_else(|e| {
+            warn!("Failed to open audit log {:?}: {}", audit_path, e);
+            AuditLog::new(audit::MAX_RECORDS)
+        }),
+    );
+
+    // Processes orphaned inside a service are reparented to the daemon,
+    // which ends them along with the service and reaps those it does not track
+    if reaper::enable_subreaper() {
+        tokio::spawn(reaper::reap_orphans());
+    }
+
     // Create event channel
     let (event_tx, mut event_rx) = mpsc::unbounded_channel();
 
@@ -75,22 +231,151 @@ async fn main() -> Result<()> {
     // Create heartbeat channel
     let (heartbeat_tx, mut heartbeat_rx) = mpsc::unbounded_channel();
 
+    // Create config reload request channel
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel::<ReloadRequest>();
+
+    let prometheus = config.prometheus.clone();
+    let log_throttle = config.log_throttle.clone();
+    let access = AccessPolicy::from_config(&config.access)?;
+    let workspace_name = config.name.clone();
+    #[cfg(unix)]
+    let pid_path = PidFile::default_path(&config.name)?;
+
+    // Processes left behind by a crashed daemon of this workspace
+    let state_file = StateFile::new(StateFile::default_path(&config.name));
+    let previous_state = state_file.load().unwrap_or_else(|e| {
+        warn!(
+            "Ignoring unreadable state file {:?}: {}",
+            state_file.path(),
+            e
+        );
+        None
+    });
+
+    // Fail now rather than let services crash-loop on ports held elsewhere;
+    // handed over services keep theirs
+    if args.handoff.is_none() {
+        if let Some((service, conflict)) = orchestrator::startup_port_conflict(
+            &config,
+            args.group.as_deref(),
+            previous_state.as_ref(),
+        ) {
+            anyhow::bail!("Service '{}' cannot start: required {}", service, conflict);
+        }
+    }
+
+    // Maintenance actions: the recipes' `schedule:` and one-shot requests
+    let (maintenance_tx, mut maintenance_rx) = mpsc::unbounded_channel();
+    let scheduler = Arc::new(Scheduler::new(maintenance_tx));
+    scheduler.set_recipe(None, &config);
+
+    // Restart storms pausing the automatic restarts of a workspace
+    let (storm_tx, mut storm_rx) = mpsc::unbounded_channel();
+
     // Create orchestrator
-    let orchestrator = Arc::new(
-        Orchestrator::new(config, event_tx.clone()).context("Failed to create orchestrator")?,
-    );
+    let mut orchestrator =
+        Orchestrator::new(config, event_tx.clone()).context("Failed to create orchestrator")?;
+    orchestrator.set_state_file(state_file);
+    orchestrator.set_storm_tx(storm_tx.clone());
+    let orchestrator = Arc::new(orchestrator);
+
+    // Host the additional workspaces next to this one
+    let mut workspaces = Workspaces::new(workspace_name, Arc::clone(&orchestrator));
+    workspaces.set_scheduler(Arc::clone(&scheduler));
+    workspaces.set_storm_tx(storm_tx);
+    for path in &args.workspaces {
+        info!("Loading hosted workspace from {:?}", path);
+        let config = KrillConfig::from_file(path)
+            .with_context(|| format!("Failed to load workspace {:?}", path))?;
+        workspaces
+            .add(config, event_tx.clone(), None)
+            .with_context(|| format!("Failed to host workspace {:?}", path))?;
+    }
+    let workspaces = Arc::new(workspaces);
+
+    // Services handed over by the daemon this one replaces; it exits once
+    // they are, freeing the socket and ports bound below
+    let handoff = match args.handoff {
+        Some(ref path) => Some(
+            handoff::receive(path)
+                .await
+                .context("Failed to take over from the previous daemon")?,
+        ),
+        None => None,
+    };
+
+    // The previous daemon released the PID file as it exited; held until this one does
+    #[cfg(unix)]
+    let _pid_file = match pid_file {
+        None if args.daemonize => PidFile::take_over(&pid_path).await,
+        pid_file => pid_file,
+    };
+    #[cfg(not(unix))]
+    let _pid_file = pid_file;
+
+    let gateway_command_tx = command_tx.clone();
+    let gateway_snapshot_req_tx = snapshot_req_tx.clone();
+    let prometheus_snapshot_req_tx = snapshot_req_tx.clone();
+    let watchdog_snapshot_req_tx = snapshot_req_tx.clone();
+
+    // Self-heartbeat for the systemd watchdog and the /healthz endpoints
+    let liveness = Arc::new(Liveness::new(watchdog::heartbeat_interval(&notifier)));
 
     // Create IPC server with heartbeat channel
-    let ipc_server = Arc::new(
-        IpcServer::with_heartbeat_tx(
-            args.socket.clone(),
-            command_tx,
-            snapshot_req_tx,
-            Some(heartbeat_tx),
-            Some(log_store),
-        )
-        .context("Failed to create IPC server")?,
-    );
+    let mut ipc_server = IpcServer::with_heartbeat_tx(
+        socket.clone(),
+        command_tx,
+        snapshot_req_tx,
+        Some(heartbeat_tx),
+        Some(Arc::clone(&log_store)),
+    )
+    .context("Failed to create IPC server")?;
+    ipc_server.set_reload_tx(reload_tx.clone());
+    ipc_server.set_event_journal(Arc::clone(&event_journal));
+    ipc_server.set_audit_log(Arc::clone(&audit_log));
+    ipc_server.set_log_filter(Arc::new(log_filter));
+    ipc_server.set_access_policy(access);
+    ipc_server.set_scheduler(Arc::clone(&scheduler));
+    ipc_server.log_throttle().set_config(log_throttle);
+    if let Some(addr) = args.listen {
+        ipc_server.set_tcp_listen(addr);
+    }
+    let ipc_server = Arc::new(ipc_server);
+
+    // Spawn HTTP gateway task
+    let gateway_handle = match (args.http, args.http_token_file.as_deref()) {
+        (Some(addr), Some(token_file)) => {
+            let token = gateway::load_token(token_file)?;
+            let mut gateway = HttpGateway::new(
+                token,
+                gateway_command_tx,
+                gateway_snapshot_req_tx,
+                Some(Arc::clone(&log_store)),
+            );
+            gateway.set_liveness(Arc::clone(&liveness));
+            gateway.set_audit_log(Arc::clone(&audit_log));
+            let gateway = Arc::new(gateway);
+            Some(tokio::spawn(async move {
+                if let Err(e) = gateway.start(addr).await {
+                    error!("HTTP gateway error: {}", e);
+                }
+            }))
+        }
+        _ => None,
+    };
+
+    // Spawn Prometheus exporter task
+    let prometheus_handle = prometheus.enabled.then(|| {
+        let mut exporter = PrometheusExporter::new(prometheus_snapshot_req_tx);
+        exporter.set_heartbeat_limiter(ipc_server.heartbeat_limiter());
+        exporter.set_fanout(ipc_server.fanout());
+        exporter.set_liveness(Arc::clone(&liveness));
+        tokio::spawn(async move {
+            if let Err(e) = exporter.start(prometheus.listen).await {
+                error!("Prometheus exporter error: {}", e);
+            }
+        })
+    });
 
     // Spawn IPC server task
     let ipc_server_clone = Arc::clone(&ipc_server);
@@ -102,15 +387,77 @@ async fn main() -> Result<()> {
 
     // Spawn event forwarding task
     let ipc_server_clone = Arc::clone(&ipc_server);
+    let orchestrator_clone = Arc::clone(&orchestrator);
     let event_handle = tokio::spawn(async move {
         while let Some((service, status)) = event_rx.recv().await {
             info!("Event: {} -> {:?}", service, status);
+            event_journal.record(&service, status.clone());
             ipc_server_clone.broadcast_event(service, status);
+            orchestrator_clone.state_changed();
         }
     });
 
-    // Spawn command handling task
+    // Spawn maintenance forwarding task - tells clients about scheduled actions
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let maintenance_handle = tokio::spawn(async move {
+        while let Some((service, event, maintenance)) = maintenance_rx.recv().await {
+            info!(
+                "Maintenance: {:?} of {} at {} {:?}",
+                maintenance.action, service, maintenance.at, event
+            );
+            ipc_server_clone.broadcast_maintenance(service, event, maintenance);
+        }
+    });
+
+    // Spawn restart storm forwarding task - tells clients the system is degraded
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let storm_handle = tokio::spawn(async move {
+        while let Some(tripped) = storm_rx.recv().await {
+            ipc_server_clone.broadcast_system_degraded(tripped).await;
+        }
+    });
+
+    // Spawn scheduler task - applies maintenance actions as they come due
+    let scheduler_handle = tokio::spawn(scheduler::run(
+        Arc::clone(&scheduler),
+        Arc::clone(&workspaces),
+        Arc::clone(&audit_log),
+    ));
+
+    // Spawn state persistence task - records service processes for crash recovery
     let orchestrator_clone = Arc::clone(&orchestrator);
+    let state_handle = tokio::spawn(async move {
+        orchestrator_clone.persist_state_loop().await;
+    });
+
+    // Spawn metrics sampling task - broadcasts per-service resource usage
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let metrics_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(METRICS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let metrics = workspaces_clone.sample_metrics().await;
+            ipc_server_clone.broadcast_metrics(metrics);
+        }
+    });
+
+    // Spawn snapshot publishing task - sends snapshot deltas to subscribed clients
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let delta_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SNAPSHOT_DELTA_INTERVAL);
+        loop {
+            interval.tick().await;
+            let snapshot = workspaces_clone.get_snapshot().await;
+            ipc_server_clone.publish_snapshot(snapshot).await;
+        }
+    });
+
+    // Spawn command handling task
+    let workspaces_clone = Arc::clone(&workspaces);
+    let ipc_server_clone = Arc::clone(&ipc_server);
+    let socket_clone = socket.clone();
     let command_handle = tokio::spawn(async move {
         while let Some((action, target)) = command_rx.recv().await {
             info!("Command: {:?} for {:?}", action, target);
@@ -119,53 +466,57 @@ async fn main() -> Result<()> {
             match action {
                 CommandAction::StopDaemon => {
                     info!("Received stop daemon command");
-                    if let Err(e) = orchestrator_clone.shutdown().await {
-                        error!("Shutdown error: {}", e);
-                    }
+                    let report = workspaces_clone.shutdown().await;
+                    ipc_server_clone.broadcast_shutdown_report(report);
+                    ipc_server_clone.flush().await;
                     break;
                 }
-                CommandAction::Stop => {
-                    if let Some(service) = target {
-                        if let Err(e) = orchestrator_clone.stop_service(&service).await {
-                            error!("Failed to stop service '{}': {}", service, e);
-                        }
-                    } else {
-                        warn!("Stop command requires a target service");
-                    }
-                }
-                CommandAction::Restart => {
-                    if let Some(service) = target {
-                        if let Err(e) = orchestrator_clone.restart_service(&service).await {
-                            error!("Failed to restart service '{}': {}", service, e);
-                        }
-                    } else {
-                        warn!("Restart command requires a target service");
+                CommandAction::UpgradeDaemon => {
+                    info!("Received upgrade daemon command");
+                    let binary = target.map(PathBuf::from);
+                    let handed_over =
+                        handoff::upgrade(&workspaces_clone, &socket_clone, binary, &mut command_rx)
+                            .await;
+                    if handed_over.is_some() {
+                        return handed_over;
                     }
+                    ipc_server_clone.upgrade_aborted();
                 }
-                CommandAction::Start => {
-                    warn!("Start command not implemented - services start automatically");
+                CommandAction::EmergencyStop => {
+                    warn!("Received emergency stop command");
+                    workspaces_clone.emergency_stop().await;
                 }
-                CommandAction::Kill => {
-                    warn!("Kill command not implemented - use Stop instead");
+                _ => {
+                    if let Err(e) = workspaces_clone.execute(action, target).await {
+                        error!("Failed to apply {:?}: {}", action, e);
+                    }
                 }
             }
         }
+        None
     });
 
     // Spawn snapshot request handling task
-    let orchestrator_clone = Arc::clone(&orchestrator);
+    let workspaces_clone = Arc::clone(&workspaces);
     tokio::spawn(async move {
         while let Some(response_tx) = snapshot_req_rx.recv().await {
-            let snapshot = orchestrator_clone.get_snapshot().await;
+            let snapshot = workspaces_clone.get_snapshot().await;
             let _ = response_tx.send(snapshot);
         }
     });
 
+    // Spawn self-heartbeat task
+    let watchdog_handle = tokio::spawn(watchdog::run(
+        liveness,
+        watchdog_snapshot_req_tx,
+        notifier.clone(),
+    ));
+
     // Spawn heartbeat handling task
-    let orchestrator_clone = Arc::clone(&orchestrator);
+    let workspaces_clone = Arc::clone(&workspaces);
     tokio::spawn(async move {
         while let Some((service, status, metadata)) = heartbeat_rx.recv().await {
-            if let Err(e) = orchestrator_clone
+            if let Err(e) = workspaces_clone
                 .process_heartbeat(&service, status, metadata)
                 .await
             {
@@ -174,17 +525,85 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Start all services
-    info!("Starting all services...");
-    if let Err(e) = orchestrator.start_all().await {
-        error!("Failed to start services: {}", e);
-        return Err(e.into());
+    // Spawn config reload handling task
+    let orchestrator_clone = Arc::clone(&orchestrator);
+    let log_store_clone = Arc::clone(&log_store);
+    let log_throttle = ipc_server.log_throttle();
+    let config_path = args.config.clone();
+    tokio::spawn(async move {
+        while let Some(request) = reload_rx.recv().await {
+            let result = match KrillConfig::from_file(&config_path) {
+                Ok(config) if request.dry_run => orchestrator_clone
+                    .plan_reload(&config)
+                    .await
+                    .map_err(KrillError::from),
+                Ok(config) => {
+                    info!("Reloading configuration from {:?}", config_path);
+                    let limits = BufferLimits::from_config(&config);
+                    let result = orchestrator_clone
+                        .reload_config(config.clone())
+                        .await
+                        .map_err(KrillError::from);
+                    if result.is_ok() {
+                        scheduler.set_recipe(None, &config);
+                        log_store_clone.set_buffer_limits(limits).await;
+                        log_throttle.set_config(config.log_throttle.clone());
+                        let session_dir = log_store_clone.session_dir();
+                        if let Err(e) = bundle::record_config(session_dir, &config_path) {
+                            warn!("Failed to record reloaded config: {}", e);
+                        }
+                    }
+                    result
+                }
+                Err(e) => Err(KrillError::from(e)),
+            };
+            if let Err(ref e) = result {
+                error!("Config reload failed: {}", e);
+            }
+            let _ = request.reply.send(result);
+        }
+    });
+
+    // Reload on edits of the config file
+    if args.watch {
+        tokio::spawn(watch::watch_config(args.config.clone(), reload_tx));
+    }
+
+    if let Some(handoff) = handoff {
+        // The services keep running across the upgrade; nothing is started
+        info!("Taking over the services of daemon {}", handoff.daemon_pid);
+        workspaces.take_over(handoff.services).await;
+    } else {
+        // Take over services that survived a crash of the previous daemon
+        if let Some(ref previous) = previous_state {
+            orchestrator.recover(previous).await;
+        }
+
+        // Start all services, or only the requested group
+        if let Some(ref group) = args.group {
+            info!("Starting service group '{}'...", group);
+            if let Err(e) = orchestrator.start_group(group).await {
+                error!("Failed to start group '{}': {}", group, e);
+                return Err(e.into());
+            }
+        } else {
+            info!("Starting all services...");
+            if let Err(e) = orchestrator.start_all().await {
+                error!("Failed to start services: {}", e);
+                return Err(e.into());
+            }
+        }
+
+        workspaces.start_hosted().await;
     }
 
     info!("All services started successfully");
     info!("Daemon running. Press Ctrl+C to stop.");
+    notifier.ready();
 
-    // Wait for shutdown signal
+    // Wait for shutdown signal; after an upgrade, the connection to the new
+    // daemon, held until this one exits
+    let mut handed_over = None;
     tokio::select! {
         result = signal::ctrl_c() => {
             match result {
@@ -192,7 +611,8 @@ async fn main() -> Result<()> {
                 Err(e) => error!("Failed to listen for Ctrl+C: {}", e),
             }
         }
-        _ = command_handle => {
+        result = command_handle => {
+            handed_over = result.ok().flatten();
             info!("Command handler stopped, initiating shutdown");
         }
         _ = ipc_handle => {
@@ -201,17 +621,35 @@ async fn main() -> Result<()> {
     }
 
     // Shutdown
-    info!("Shutting down daemon...");
+    notifier.notify("STOPPING=1");
+    if handed_over.is_some() {
+        info!("Services handed over to the new daemon; exiting without stopping them");
+        ipc_server.release_socket();
+    } else {
+        info!("Shutting down daemon...");
 
-    if let Err(e) = orchestrator.shutdown().await {
-        error!("Error during shutdown: {}", e);
+        workspaces.shutdown().await;
     }
 
     ipc_server.shutdown().await;
 
     // Cancel event forwarding task
     event_handle.abort();
+    state_handle.abort();
+    metrics_handle.abort();
+    delta_handle.abort();
+    maintenance_handle.abort();
+    storm_handle.abort();
+    scheduler_handle.abort();
+    watchdog_handle.abort();
+    if let Some(handle) = gateway_handle {
+        handle.abort();
+    }
+    if let Some(handle) = prometheus_handle {
+        handle.abort();
+    }
 
     info!("Daemon stopped");
+    drop(handed_over);
     Ok(())
 }
@@ -0,0 +1,275 @@
+// Metrics Collector - Samples per-service CPU, memory and thread usage from /proc
+
+use krill_common::ServiceMetrics;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Interval between metrics samples broadcast to clients
+pub const METRICS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Fields of `/proc/<pid>/stat` needed for metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProcStat {
+    pgrp: u32,
+    cpu_ticks: u64,
+    threads: u32,
+    rss_pages: u64,
+}
+
+/// Usage summed over all processes of a process group
+#[derive(Debug, Clone, Copy, Default)]
+struct GroupUsage {
+    cpu_ticks: u64,
+    threads: u32,
+    rss_pages: u64,
+}
+
+pub struct MetricsCollector {
+    proc_root: PathBuf,
+    ticks_per_second: f64,
+    page_size: u64,
+    state: Mutex<CollectorState>,
+}
+
+#[derive(Default)]
+struct CollectorState {
+    /// CPU ticks per process group at the previous sample
+    previous: HashMap<u32, (u64, Instant)>,
+    /// Most recent metrics per service
+    latest: HashMap<String, ServiceMetrics>,
+}
+
+impl MetricsCollector {
+    pub fn new() -> Self {
+        Self::with_proc_root(PathBuf::from("/proc"))
+    }
+
+    pub fn with_proc_root(proc_root: PathBuf) -> Self {
+        // Without procfs (e.g. on Windows) samples are simply empty
+        #[cfg(unix)]
+        let (ticks_per_second, page_size) = {
+            use nix::unistd::{sysconf, SysconfVar};
+
+            let ticks = sysconf(SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as f64;
+            let page_size = sysconf(SysconfVar::PAGE_SIZE)
+                .ok()
+                .flatten()
+                .unwrap_or(4096) as u64;
+            (ticks, page_size)
+        };
+        #[cfg(not(unix))]
+        let (ticks_per_second, page_size) = (100.0, 4096);
+
+        Self {
+            proc_root,
+            ticks_per_second,
+            page_size,
+            state: Mutex::new(CollectorState::default()),
+        }
+    }
+
+    /// Sample usage for each service, given the process group it runs in.
+    /// CPU usage is derived from the previous sample, so the first one reports 0%.
+    pub fn sample(&self, groups: &HashMap<String, u32>) -> HashMap<String, ServiceMetrics> {
+        let usage = self.read_group_usage();
+        let now = Instant::now();
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut previous = HashMap::new();
+        let mut metrics = HashMap::new();
+
+        for (service, pgid) in groups {
+            let Some(group) = usage.get(pgid) else {
+                continue;
+            };
+
+            let cpu_percent = match state.previous.get(pgid) {
+                Some(&(ticks, at)) => {
+                    let elapsed = now.duration_since(at).as_secs_f64();
+                    let delta = group.cpu_ticks.saturating_sub(ticks) as f64;
+                    if elapsed > 0.0 {
+                        (delta / self.ticks_per_second / elapsed * 100.0) as f32
+                    } else {
+                        0.0
+                    }
+                }
+                None => 0.0,
+            };
+            previous.insert(*pgid, (group.cpu_ticks, now));
+
+            metrics.insert(
+                service.clone(),
+                ServiceMetrics {
+                    cpu_percent,
+                    memory_bytes: group.rss_pages * self.page_size,
+                    threads: group.threads,
+                },
+            );
+        }
+
+        state.previous = previous;
+        state.latest = metrics.clone();
+        metrics
+    }
+
+    /// Metrics from the most recent sample
+    pub fn latest(&self, service: &str) -> Option<ServiceMetrics> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.latest.get(service).copied()
+    }
+
+    fn read_group_usage(&self) -> HashMap<u32, GroupUsage> {
+        let mut usage: HashMap<u32, GroupUsage> = HashMap::new();
+
+        let Ok(entries) = fs::read_dir(&self.proc_root) else {
+            return usage;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if !name.to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+                continue;
+            }
+
+            // Processes may exit between listing and reading
+            let Some(stat) = fs::read_to_string(entry.path().join("stat"))
+                .ok()
+                .and_then(|content| parse_stat(&content))
+            else {
+                continue;
+            };
+
+            let group = usage.entry(stat.pgrp).or_default();
+            group.cpu_ticks += stat.cpu_ticks;
+            group.threads += stat.threads;
+            group.rss_pages += stat.rss_pages;
+        }
+
+        usage
+    }
+}
+
+impl Default for MetricsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse `/proc/<pid>/stat`. The command name may contain spaces and
+/// parentheses, so fields are counted from the last `)`.
+fn parse_stat(content: &str) -> Option<ProcStat> {
+    let rest = &content[content.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+
+    // fields[0] is field 3 (state) in proc(5) numbering
+    let field = |n: usize| fields.get(n - 3).and_then(|f| f.parse::<u64>().ok());
+
+    Some(ProcStat {
+        pgrp: field(5)? as u32,
+        cpu_ticks: field(14)? + field(15)?,
+        threads: field(20)? as u32,
+        rss_pages: field(24)?,
+    })
+}
+
+/// Start time of a live process in clock ticks since boot (field 22 of
+/// `/proc/<pid>/stat`). Together with the PID it identifies the process
+/// across PID reuse. Zombies and missing processes yield `None`.
+pub(crate) fn process_start_ticks(proc_root: &Path, pid: u32) -> Option<u64> {
+    let content = fs::read_to_string(proc_root.join(pid.to_string()).join("stat")).ok()?;
+    let fields: Vec<&str> = content[content.rfind(')')? + 1..]
+        .split_whitespace()
+        .collect();
+
+    if matches!(fields.first(), Some(&"Z") | Some(&"X")) {
+        return None;
+    }
+    fields.get(22 - 3)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn stat_line(pid: u32, comm: &str, pgrp: u32, utime: u64, threads: u32, rss: u64) -> String {
+        format!(
+            "{pid} ({comm}) S 1 {pgrp} {pgrp} 0 -1 4194560 100 0 0 0 {utime} 10 0 0 20 0 {threads} 0 1000 123456 {rss} 18446744073709551615\n"
+        )
+    }
+
+    fn write_proc(root: &TempDir, pid: u32, content: &str) {
+        let dir = root.path().join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("stat"), content).unwrap();
+    }
+
+    #[test]
+    fn test_parse_stat_with_spaces_in_name() {
+        let stat = parse_stat(&stat_line(42, "my (weird) proc", 40, 90, 3, 250)).unwrap();
+        assert_eq!(
+            stat,
+            ProcStat {
+                pgrp: 40,
+                cpu_ticks: 100,
+                threads: 3,
+                rss_pages: 250,
+            }
+        );
+        assert!(parse_stat("garbage").is_none());
+    }
+
+    #[test]
+    fn test_process_start_ticks_skips_zombies() {
+        let root = TempDir::new().unwrap();
+        write_proc(&root, 42, &stat_line(42, "a) b", 42, 0, 1, 10));
+        assert_eq!(process_start_ticks(root.path(), 42), Some(1000));
+
+        write_proc(
+            &root,
+            42,
+            &stat_line(42, "a) b", 42, 0, 1, 10).replace(") S ", ") Z "),
+        );
+        assert_eq!(process_start_ticks(root.path(), 42), None);
+        assert_eq!(process_start_ticks(root.path(), 43), None);
+    }
+
+    #[test]
+    fn test_sample_sums_process_group() {
+        let root = TempDir::new().unwrap();
+        write_proc(&root, 100, &stat_line(100, "sh", 100, 0, 1, 10));
+        write_proc(&root, 101, &stat_line(101, "python", 100, 0, 4, 30));
+        write_proc(&root, 200, &stat_line(200, "other", 200, 0, 2, 5));
+
+        let collector = MetricsCollector::with_proc_root(root.path().to_path_buf());
+        let groups = HashMap::from([("svc".to_string(), 100), ("gone".to_string(), 999)]);
+
+        let metrics = collector.sample(&groups);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics["svc"].threads, 5);
+        assert_eq!(metrics["svc"].memory_bytes, 40 * collector.page_size);
+        assert_eq!(metrics["svc"].cpu_percent, 0.0);
+        assert_eq!(collector.latest("svc"), Some(metrics["svc"]));
+        assert_eq!(collector.latest("gone"), None);
+    }
+
+    #[test]
+    fn test_sample_reports_cpu_from_tick_delta() {
+        let root = TempDir::new().unwrap();
+        write_proc(&root, 100, &stat_line(100, "busy", 100, 0, 1, 10));
+
+        let collector = MetricsCollector::with_proc_root(root.path().to_path_buf());
+        let groups = HashMap::from([("svc".to_string(), 100)]);
+        collector.sample(&groups);
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let ticks = collector.ticks_per_second as u64;
+        write_proc(&root, 100, &stat_line(100, "busy", 100, ticks, 1, 10));
+
+        let metrics = collector.sample(&groups);
+        assert!(metrics["svc"].cpu_percent > 0.0);
+    }
+}
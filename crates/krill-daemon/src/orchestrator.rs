@@ -1,14 +1,27 @@
 // Daemon Orchestrator - Coordinates all services using DAG
 
-use crate::runner::{RunnerError, ServiceRunner, ServiceState};
+use crate::gpu;
+use crate::handoff::HandedService;
+use crate::health::HealthMonitor;
+use crate::metrics::MetricsCollector;
+use crate::reaper;
+use crate::runner::{
+    self, InputHashes, PreparedHook, RunnerError, ServiceRunner, ServiceState, StopOutcome,
+};
+use crate::state::{self, DaemonState, StateFile};
+use crate::storm::{RestartStorm, StormTripped};
+use crate::watchdog;
+use crate::workspaces::qualified_name;
 use krill_common::{
-    DagError, Dependency, DependencyCondition, DependencyGraph, KrillConfig, ServiceStatus,
+    shell_command, DagError, DependencyCondition, DependencyGraph, EmergencyAction, Hook,
+    KrillConfig, KrillError, LifecycleState, LogStream, PortConflict, Precondition,
+    ProcessOutputLine, ServiceShutdown, ServiceStatus, DEFAULT_EMERGENCY_COMMAND_TIMEOUT,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::sync::{mpsc, Mutex, MutexGuard, Notify, OwnedSemaphorePermit, RwLock, Semaphore};
 use tokio::time::{self, Duration};
 use tracing::{debug, error, info, warn};
 
@@ -20,25 +33,161 @@ pub enum OrchestratorError {
     #[error("Service '{0}' not found")]
     ServiceNotFound(String),
 
+    #[error("Group '{0}' not found")]
+    GroupNotFound(String),
+
     #[error("Runner error: {0}")]
     RunnerError(#[from] RunnerError),
 
     #[error("Shutdown in progress")]
     ShuttingDown,
+
+    #[error("Service '{service}' cannot be started from state {state:?}")]
+    InvalidState {
+        service: String,
+        state: ServiceState,
+    },
+
+    #[error("Service '{0}' is held by an emergency stop; clear it first")]
+    SafetyStopped(String),
+
+    #[error("Service '{0}' is not held by an emergency stop")]
+    NotSafetyStopped(String),
+
+    #[error("Service '{0}' is not paused")]
+    NotPaused(String),
+
+    #[error("Dependency '{dependency}' of '{service}' is not {condition:?}")]
+    DependencyNotReady {
+        service: String,
+        dependency: String,
+        condition: DependencyCondition,
+    },
+
+    #[error("Dependency '{dependency}' of '{service}' was not {condition:?} within {timeout:?}")]
+    DependencyTimedOut {
+        service: String,
+        dependency: String,
+        condition: DependencyCondition,
+        timeout: Duration,
+    },
+
+    #[error("Service '{service}' waits for {condition}")]
+    PreconditionNotMet { service: String, condition: String },
+
+    #[error("Service '{service}' waited for {condition} longer than {timeout:?}")]
+    PreconditionTimedOut {
+        service: String,
+        condition: String,
+        timeout: Duration,
+    },
+
+    #[error("Workspace '{0}' is already hosted by this daemon")]
+    WorkspaceExists(String),
+}
+
+impl From<OrchestratorError> for KrillError {
+    fn from(err: OrchestratorError) -> Self {
+        match err {
+            OrchestratorError::DagError(e) => KrillError::InvalidConfig(e.to_string()),
+            OrchestratorError::ServiceNotFound(service) => KrillError::ServiceNotFound(service),
+            OrchestratorError::GroupNotFound(group) => KrillError::GroupNotFound(group),
+            OrchestratorError::RunnerError(e) => e.into(),
+            OrchestratorError::ShuttingDown => KrillError::ShuttingDown,
+            OrchestratorError::InvalidState { service, state } => KrillError::InvalidState {
+                service,
+                reason: format!("cannot be started from state {:?}", state),
+            },
+            OrchestratorError::SafetyStopped(service) => KrillError::SafetyStopped(service),
+            OrchestratorError::NotSafetyStopped(service) => KrillError::InvalidState {
+                service,
+                reason: "is not held by an emergency stop".to_string(),
+            },
+            OrchestratorError::NotPaused(service) => KrillError::InvalidState {
+                service,
+                reason: "is not paused".to_string(),
+            },
+            OrchestratorError::DependencyNotReady {
+                service,
+                dependency,
+                condition,
+            } => KrillError::InvalidState {
+                service,
+                reason: format!("waits for '{}' to be {:?}", dependency, condition),
+            },
+            OrchestratorError::DependencyTimedOut {
+                dependency,
+                condition,
+                timeout,
+                ..
+            } => KrillError::Timeout(format!(
+                "'{}' to be {:?} ({:?})",
+                dependency, condition, timeout
+            )),
+            OrchestratorError::PreconditionNotMet { service, condition } => {
+                KrillError::InvalidState {
+                    service,
+                    reason: format!("waits for {}", condition),
+                }
+            }
+            OrchestratorError::PreconditionTimedOut {
+                condition, timeout, ..
+            } => KrillError::Timeout(format!("{} ({:?})", condition, timeout)),
+            OrchestratorError::WorkspaceExists(workspace) => KrillError::InvalidConfig(format!(
+                "workspace '{}' is already hosted by this daemon",
+                workspace
+            )),
+        }
+    }
 }
 
 pub type ServiceEvent = (String, ServiceStatus);
-pub type LogLine = (String, String); // (service_name, line)
+
+/// Services affected by a configuration reload
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReloadSummary {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Running (or starting) services whose spawn settings changed
+    pub restarted: Vec<String>,
+    /// Services whose other settings changed, applied without a restart,
+    /// and services not running whose spawn settings changed
+    pub updated: Vec<String>,
+    /// Changed settings of the restarted and updated services
+    pub changes: BTreeMap<String, Vec<String>>,
+}
 
 pub struct Orchestrator {
-    config: Arc<KrillConfig>,
-    dag: Arc<DependencyGraph>,
+    config: Arc<RwLock<KrillConfig>>,
+    dag: Arc<RwLock<DependencyGraph>>,
     runners: Arc<RwLock<HashMap<String, Arc<Mutex<ServiceRunner>>>>>,
     event_tx: mpsc::UnboundedSender<ServiceEvent>,
-    log_tx: Option<mpsc::UnboundedSender<LogLine>>,
+    log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+    health_monitor: Arc<HealthMonitor>,
+    metrics: Arc<MetricsCollector>,
     shutdown: Arc<Mutex<bool>>,
+    /// Services latched by an emergency stop; they stay down until cleared
+    safety_stopped: Arc<RwLock<HashSet<String>>>,
+    /// Where the processes of running services are recorded for crash recovery
+    state_file: Option<Arc<StateFile>>,
+    state_changed: Arc<Notify>,
+    /// Hosted next to another workspace; its services are known to SDK
+    /// clients as `workspace/service`
+    hosted: bool,
+    /// Launch slots when `max_concurrent_starts` is set
+    start_slots: Arc<RwLock<Option<Arc<Semaphore>>>>,
+    /// Failures counted against `restart_storm`
+    restart_storm: Arc<Mutex<RestartStorm>>,
+    /// Where a tripped `restart_storm` is reported
+    storm_tx: Option<mpsc::UnboundedSender<StormTripped>>,
 }
 
+/// How long a started service without `start_timeout_sec` holds its launch slot
+const START_SLOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often unmet `wait_for` conditions are checked again
+const PRECONDITION_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
 impl Orchestrator {
     pub fn new(
         config: KrillConfig,
@@ -50,44 +199,259 @@ impl Orchestrator {
     pub fn with_log_tx(
         config: KrillConfig,
         event_tx: mpsc::UnboundedSender<ServiceEvent>,
-        log_tx: Option<mpsc::UnboundedSender<LogLine>>,
+        log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+    ) -> Result<Self, OrchestratorError> {
+        Self::build(config, event_tx, log_tx, false)
+    }
+
+    /// Orchestrator of a workspace hosted next to the daemon's own one
+    pub fn hosted(
+        config: KrillConfig,
+        event_tx: mpsc::UnboundedSender<ServiceEvent>,
+        log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+    ) -> Result<Self, OrchestratorError> {
+        Self::build(config, event_tx, log_tx, true)
+    }
+
+    fn build(
+        config: KrillConfig,
+        event_tx: mpsc::UnboundedSender<ServiceEvent>,
+        log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+        hosted: bool,
     ) -> Result<Self, OrchestratorError> {
-        // Build dependency graph
-        let deps_map: HashMap<String, Vec<Dependency>> = config
+        let dag = config.dependency_graph()?;
+
+        let gpu_services: Vec<String> = config
             .services
             .iter()
-            .map(|(name, svc)| (name.clone(), svc.dependencies.clone()))
+            .filter(|(_, service)| service.gpu)
+            .map(|(name, _)| name.clone())
             .collect();
-
-        let dag = DependencyGraph::new(&deps_map)?;
+        if !gpu_services.is_empty() {
+            tokio::spawn(gpu::log_detected(gpu_services));
+        }
 
         // Create runners for all services
         let mut runners = HashMap::new();
-        for (name, svc_config) in &config.services {
-            let runner = ServiceRunner::new(
+        for name in config.services.keys() {
+            runners.insert(
                 name.clone(),
-                config.name.clone(),
-                svc_config.clone(),
-                config.env.clone(),
+                Arc::new(Mutex::new(new_runner(&config, name, hosted, &log_tx))),
             );
-            runners.insert(name.clone(), Arc::new(Mutex::new(runner)));
         }
 
+        let runners = Arc::new(RwLock::new(runners));
+        let start_slots = Arc::new(RwLock::new(start_slots(&config)));
+        let health_monitor = Arc::new(HealthMonitor::new(Arc::clone(&runners), event_tx.clone()));
+
         Ok(Self {
-            config: Arc::new(config),
-            dag: Arc::new(dag),
-            runners: Arc::new(RwLock::new(runners)),
+            config: Arc::new(RwLock::new(config)),
+            dag: Arc::new(RwLock::new(dag)),
+            runners,
             event_tx,
             log_tx,
+            health_monitor,
+            metrics: Arc::new(MetricsCollector::new()),
             shutdown: Arc::new(Mutex::new(false)),
+            safety_stopped: Arc::new(RwLock::new(HashSet::new())),
+            state_file: None,
+            state_changed: Arc::new(Notify::new()),
+            hosted,
+            start_slots,
+            restart_storm: Arc::default(),
+            storm_tx: None,
         })
     }
 
+    /// Record the processes of running services in `state_file`
+    pub fn set_state_file(&mut self, state_file: StateFile) {
+        self.state_file = Some(Arc::new(state_file));
+    }
+
+    /// Report to `storm_tx` when `restart_storm` pauses automatic restarts
+    pub fn set_storm_tx(&mut self, storm_tx: mpsc::UnboundedSender<StormTripped>) {
+        self.storm_tx = Some(storm_tx);
+    }
+
+    /// Adopt the processes a crashed daemon left running for services that
+    /// are still configured, and reap those of services that are not.
+    /// Returns the adopted services.
+    pub async fn recover(&self, previous: &DaemonState) -> Vec<String> {
+        if previous.daemon_alive() {
+            warn!(
+                "Daemon {} of session {} is still running; not recovering its services",
+                previous.daemon_pid, previous.session_id
+            );
+            return Vec::new();
+        }
+
+        let mut adopted = Vec::new();
+        for (name, record) in previous.live_services() {
+            let runner = self.runners.read().await.get(name).cloned();
+            let Some(runner) = runner else {
+                #[cfg(unix)]
+                state::reap(name, record, state::REAP_TIMEOUT).await;
+                continue;
+            };
+
+            let mut runner_guard = runner.lock().await;
+            runner_guard.adopt(record);
+            let _ = self
+                .event_tx
+                .send((name.to_string(), runner_guard.get_status()));
+            drop(runner_guard);
+
+            self.start_monitoring_task(name);
+            self.health_monitor.start_service_monitoring(name);
+            adopted.push(name.to_string());
+        }
+
+        if !adopted.is_empty() {
+            info!(
+                "Adopted {} service(s) from session {}; their output is not captured until restarted",
+                adopted.len(),
+                previous.session_id
+            );
+        }
+        adopted
+    }
+
+    /// Every service with its status and process, for handing over to an
+    /// upgraded daemon
+    pub async fn process_table(&self) -> BTreeMap<String, HandedService> {
+        let mut services = BTreeMap::new();
+        for (name, runner) in self.runners.read().await.iter() {
+            let runner_guard = runner.lock().await;
+            #[cfg(unix)]
+            let (stdout_fd, stderr_fd) = {
+                use std::os::fd::AsRawFd;
+                let (stdout, stderr) = runner_guard.output_fds();
+                (
+                    stdout.map(|fd| fd.as_raw_fd()),
+                    stderr.map(|fd| fd.as_raw_fd()),
+                )
+            };
+            #[cfg(not(unix))]
+            let (stdout_fd, stderr_fd) = (None, None);
+            services.insert(
+                name.clone(),
+                HandedService {
+                    status: runner_guard.get_status(),
+                    restart_count: runner_guard.restart_count(),
+                    total_restarts: runner_guard.total_restarts(),
+                    process: runner_guard.record(),
+                    stdout_fd,
+                    stderr_fd,
+                },
+            );
+        }
+        services
+    }
+
+    /// Take over the services handed over by the daemon this one replaces:
+    /// adopt their processes and keep capturing their output, without
+    /// starting or stopping anything. Returns the adopted services.
+    pub async fn take_over(&self, services: BTreeMap<String, HandedService>) -> Vec<String> {
+        let mut adopted = Vec::new();
+        for (name, mut service) in services {
+            #[cfg(unix)]
+            let (stdout_fd, stderr_fd) = service.take_output();
+            let runner = self.runners.read().await.get(&name).cloned();
+            let Some(runner) = runner else {
+                // Removed from the recipe since the old daemon loaded it
+                #[cfg(unix)]
+                if let Some(ref record) = service.process {
+                    state::reap(&name, record, state::REAP_TIMEOUT).await;
+                }
+                continue;
+            };
+
+            let mut runner_guard = runner.lock().await;
+            runner_guard.take_over(&service);
+            let _ = self
+                .event_tx
+                .send((name.clone(), runner_guard.get_status()));
+            if runner_guard.pid().is_none() {
+                continue;
+            }
+
+            // The runner keeps the pipes for the next upgrade, the readers a copy
+            #[cfg(unix)]
+            {
+                for (fd, is_stderr) in [(&stdout_fd, false), (&stderr_fd, true)] {
+                    let reader = fd
+                        .as_ref()
+                        .and_then(|fd| fd.try_clone().ok())
+                        .and_then(|fd| tokio::net::unix::pipe::Receiver::from_owned_fd(fd).ok());
+                    if let Some(reader) = reader {
+                        self.spawn_output_reader(name.clone(), reader, is_stderr);
+                    }
+                }
+                runner_guard.set_output_fds(stdout_fd, stderr_fd);
+            }
+            drop(runner_guard);
+
+            self.start_monitoring_task(&name);
+            self.health_monitor.start_service_monitoring(&name);
+            adopted.push(name);
+        }
+
+        // Services the old daemon did not know are left stopped
+        for runner in self.runners.read().await.values() {
+            runner.lock().await.mark_stopped();
+        }
+        adopted
+    }
+
+    /// Signal that service processes may have changed and the state file
+    /// needs to be rewritten
+    pub fn state_changed(&self) {
+        self.state_changed.notify_one();
+    }
+
+    /// Keep the state file up to date until shutdown; run as a task
+    pub async fn persist_state_loop(&self) {
+        loop {
+            self.state_changed.notified().await;
+            if !self.persist_state().await {
+                break;
+            }
+        }
+    }
+
+    /// Write the processes of running services to the state file. Returns
+    /// false once shutdown has begun.
+    pub async fn persist_state(&self) -> bool {
+        let Some(ref state_file) = self.state_file else {
+            return true;
+        };
+
+        let mut services = BTreeMap::new();
+        for (name, runner) in self.runners.read().await.iter() {
+            if let Some(record) = runner.lock().await.record() {
+                services.insert(name.clone(), record);
+            }
+        }
+
+        // Shutdown removes the file; hold its lock so a late write cannot recreate it
+        let shutdown = self.shutdown.lock().await;
+        if *shutdown {
+            return false;
+        }
+        if let Err(e) = state_file.save(services) {
+            warn!("Failed to save state to {:?}: {}", state_file.path(), e);
+        }
+        true
+    }
+
     /// Start all services in DAG order
     pub async fn start_all(&self) -> Result<(), OrchestratorError> {
         info!("Starting all services in DAG order");
 
-        let startup_order = self.dag.startup_order()?;
+        let (startup_order, standby) = {
+            let dag = self.dag.read().await;
+            (dag.startup_order()?, dag.standby_services())
+        };
 
         // Start all services concurrently - dependencies are handled by start_when_ready
         let mut handles = vec![];
@@ -96,6 +460,16 @@ impl Orchestrator {
             let self_clone = self.clone_for_task();
             let service_name = service_name.clone();
 
+            // Fallbacks may wait forever for a failure, so startup does not wait for them
+            if standby.contains(&service_name) {
+                tokio::spawn(async move {
+                    if let Err(e) = self_clone.start_when_ready(&service_name).await {
+                        debug!("Fallback service '{}' not started: {}", service_name, e);
+                    }
+                });
+                continue;
+            }
+
             let handle =
                 tokio::spawn(async move { self_clone.start_when_ready(&service_name).await });
 
@@ -121,18 +495,109 @@ impl Orchestrator {
         Ok(())
     }
 
+    /// Start the services of a group and their dependencies in DAG order.
+    /// Services outside the group that were never started are marked stopped.
+    pub async fn start_group(&self, group: &str) -> Result<(), OrchestratorError> {
+        let selected = self
+            .config
+            .read()
+            .await
+            .group_with_dependencies(group)
+            .ok_or_else(|| OrchestratorError::GroupNotFound(group.to_string()))?;
+
+        info!("Starting group '{}' ({} services)", group, selected.len());
+
+        let startup_order = self.dag.read().await.startup_order()?;
+        let runners = self.runners.read().await;
+
+        for service_name in startup_order {
+            let Some(runner) = runners.get(&service_name) else {
+                continue;
+            };
+            let mut runner_guard = runner.lock().await;
+
+            if !selected.contains(&service_name) {
+                if runner_guard.state() == ServiceState::Pending {
+                    runner_guard.mark_stopped();
+                    let _ = self
+                        .event_tx
+                        .send((service_name.clone(), runner_guard.get_status()));
+                }
+                continue;
+            }
+
+            if !matches!(
+                runner_guard.state(),
+                ServiceState::Pending
+                    | ServiceState::Stopped
+                    | ServiceState::Failed
+                    | ServiceState::Blocked
+            ) {
+                continue;
+            }
+            runner_guard.reset_restart_count();
+            drop(runner_guard);
+
+            let self_clone = self.clone_for_task();
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.start_when_ready(&service_name).await {
+                    error!("Failed to start service '{}': {}", service_name, e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Stop the services of a group in reverse dependency order
+    pub async fn stop_group(&self, group: &str) -> Result<(), OrchestratorError> {
+        let members: HashSet<String> = self
+            .config
+            .read()
+            .await
+            .groups
+            .get(group)
+            .ok_or_else(|| OrchestratorError::GroupNotFound(group.to_string()))?
+            .iter()
+            .cloned()
+            .collect();
+
+        info!("Stopping group '{}'", group);
+
+        let shutdown_order = self.dag.read().await.shutdown_order()?;
+        for service_name in shutdown_order {
+            if !members.contains(&service_name) {
+                continue;
+            }
+
+            let state = match self.runners.read().await.get(&service_name) {
+                Some(runner) => runner.lock().await.state(),
+                None => continue,
+            };
+            if !matches!(state, ServiceState::Pending | ServiceState::Stopped) {
+                self.stop_service(&service_name).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Start a service when its dependencies are ready
     async fn start_when_ready(&self, service_name: &str) -> Result<(), OrchestratorError> {
         debug!("Waiting for dependencies of '{}'", service_name);
 
-        let service_config = self
+        let (dependencies, dependency_timeout) = self
             .config
+            .read()
+            .await
             .services
             .get(service_name)
+            .map(|svc| (svc.dependencies.clone(), svc.dependency_timeout))
             .ok_or_else(|| OrchestratorError::ServiceNotFound(service_name.to_string()))?;
+        let deadline = dependency_timeout.map(|timeout| time::Instant::now() + timeout);
 
         // Wait for each dependency to meet its condition
-        for dep in &service_config.dependencies {
+        for dep in &dependencies {
             let dep_service = dep.service_name();
             let condition = dep.condition();
 
@@ -140,25 +605,18 @@ impl Orchestrator {
                 if *self.shutdown.lock().await {
                     return Err(OrchestratorError::ShuttingDown);
                 }
+                self.ensure_not_safety_stopped(service_name).await?;
 
-                let dep_state = {
+                let met = {
                     let runners = self.runners.read().await;
                     let runner = runners.get(dep_service).ok_or_else(|| {
                         OrchestratorError::ServiceNotFound(dep_service.to_string())
                     })?;
-                    let state = runner.lock().await.state();
-                    state
-                };
-
-                let condition_met = match condition {
-                    DependencyCondition::Started => matches!(
-                        dep_state,
-                        ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
-                    ),
-                    DependencyCondition::Healthy => matches!(dep_state, ServiceState::Healthy),
+                    let runner_guard = runner.lock().await;
+                    condition_met(condition, &runner_guard)
                 };
 
-                if condition_met {
+                if met {
                     debug!(
                         "Dependency '{}' of '{}' satisfied ({:?})",
                         dep_service, service_name, condition
@@ -166,26 +624,102 @@ impl Orchestrator {
                     break;
                 }
 
+                if let (Some(deadline), Some(timeout)) = (deadline, dependency_timeout) {
+                    if time::Instant::now() >= deadline {
+                        let error = OrchestratorError::DependencyTimedOut {
+                            service: service_name.to_string(),
+                            dependency: dep_service.to_string(),
+                            condition,
+                            timeout,
+                        };
+                        self.block_service(service_name, error.to_string()).await;
+                        return Err(error);
+                    }
+                }
+
                 // Wait a bit before checking again
                 time::sleep(Duration::from_millis(100)).await;
             }
         }
 
+        // Wait for conditions outside the recipe, e.g. a device node
+        self.wait_for_preconditions(service_name).await?;
+
+        // Give hardware behind the dependencies time to settle
+        let start_delay = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .and_then(|svc| svc.start_delay);
+        if let Some(delay) = start_delay {
+            info!(
+                "Dependencies of '{}' satisfied, starting in {:?}",
+                service_name, delay
+            );
+            time::sleep(delay).await;
+            if *self.shutdown.lock().await {
+                return Err(OrchestratorError::ShuttingDown);
+            }
+        }
+
+        // Wait for a launch slot; the semaphore is fair, so services launch
+        // in the order their dependencies were satisfied
+        let slots = self.start_slots.read().await.clone();
+        let permit = match slots {
+            Some(slots) => {
+                debug!("Waiting for a launch slot for '{}'", service_name);
+                let permit = slots
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| OrchestratorError::ShuttingDown)?;
+                if *self.shutdown.lock().await {
+                    return Err(OrchestratorError::ShuttingDown);
+                }
+                Some(permit)
+            }
+            None => None,
+        };
+
         // All dependencies satisfied, start the service
+        self.ensure_not_safety_stopped(service_name).await?;
         info!("Starting service '{}'", service_name);
+        let refused = self.start_refusal(service_name).await;
 
-        let runners = self.runners.read().await;
-        let runner = runners
+        let runner = self
+            .runners
+            .read()
+            .await
             .get(service_name)
+            .cloned()
             .ok_or_else(|| OrchestratorError::ServiceNotFound(service_name.to_string()))?;
 
-        let mut runner_guard = runner.lock().await;
-        if let Err(e) = runner_guard.start().await {
-            error!("Failed to start service '{}': {}", service_name, e);
-            runner_guard.mark_failed(Some(e.to_string()));
-            let status = runner_guard.get_status();
-            let _ = self.event_tx.send((service_name.to_string(), status));
-            return Err(e.into());
+        let runner_guard = runner.lock().await;
+
+        // Already running, e.g. adopted from a previous daemon
+        if matches!(
+            runner_guard.state(),
+            ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+        ) {
+            debug!("Service '{}' is already running", service_name);
+            return Ok(());
+        }
+
+        let (mut runner_guard, started) = match refused {
+            Some(e) => (runner_guard, Err(e)),
+            None => start_runner(service_name, &runner, runner_guard).await,
+        };
+        match started {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                error!("Failed to start service '{}': {}", service_name, e);
+                runner_guard.mark_failed(Some(e.to_string()));
+                let status = runner_guard.get_status();
+                let _ = self.event_tx.send((service_name.to_string(), status));
+                return Err(e.into());
+            }
         }
 
         // Take stdout/stderr handles and spawn output capture tasks
@@ -200,21 +734,168 @@ impl Orchestrator {
         let status = runner_guard.get_status();
         let _ = self.event_tx.send((service_name.to_string(), status));
 
-        // Start monitoring task
+        // Start monitoring tasks
         drop(runner_guard);
-        drop(runners);
+        let started = runner;
         self.start_monitoring_task(service_name);
+        self.health_monitor.start_service_monitoring(service_name);
+
+        if let Some(permit) = permit {
+            tokio::spawn(hold_start_slot(started, permit));
+        }
 
         Ok(())
     }
 
+    /// Wait until the `wait_for` conditions of a service hold, checking them
+    /// again every `PRECONDITION_RETRY_INTERVAL`. Past its
+    /// `wait_for_timeout` the service is blocked.
+    async fn wait_for_preconditions(&self, service_name: &str) -> Result<(), OrchestratorError> {
+        let timeout = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .and_then(|svc| svc.wait_for_timeout);
+        let deadline = timeout.map(|timeout| time::Instant::now() + timeout);
+
+        let mut waiting_for = None;
+        loop {
+            if *self.shutdown.lock().await {
+                return Err(OrchestratorError::ShuttingDown);
+            }
+            self.ensure_not_safety_stopped(service_name).await?;
+
+            let Some(condition) = self.unmet_precondition(service_name).await else {
+                if waiting_for.is_some() {
+                    info!("Conditions of '{}' hold", service_name);
+                }
+                return Ok(());
+            };
+            if waiting_for.as_ref() != Some(&condition) {
+                info!("Service '{}' waits for {}", service_name, condition);
+                waiting_for = Some(condition.clone());
+            }
+
+            if let (Some(deadline), Some(timeout)) = (deadline, timeout) {
+                if time::Instant::now() >= deadline {
+                    let error = OrchestratorError::PreconditionTimedOut {
+                        service: service_name.to_string(),
+                        condition: condition.to_string(),
+                        timeout,
+                    };
+                    self.block_service(service_name, error.to_string()).await;
+                    return Err(error);
+                }
+            }
+
+            time::sleep(PRECONDITION_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// The first `wait_for` condition of a service that does not hold now
+    async fn unmet_precondition(&self, service_name: &str) -> Option<Precondition> {
+        let conditions = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .map(|svc| svc.wait_for.clone())
+            .unwrap_or_default();
+        if conditions.is_empty() {
+            return None;
+        }
+
+        let runner = self.runners.read().await.get(service_name).cloned();
+        let env = match runner {
+            Some(runner) => runner.lock().await.environment().unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        // File lookups and TCP connects block
+        tokio::task::spawn_blocking(move || {
+            conditions
+                .into_iter()
+                .find(|condition| !condition.is_met(&env))
+        })
+        .await
+        .unwrap_or(None)
+    }
+
+    /// A port of the service's `requires_ports` that another process holds,
+    /// with that process. Walking /proc for it takes a while, so this runs
+    /// before any runner is locked.
+    async fn required_port_conflict(&self, service_name: &str) -> Option<PortConflict> {
+        let ports = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .map(|svc| svc.requires_ports.clone())
+            .unwrap_or_default();
+        if ports.is_empty() {
+            return None;
+        }
+        tokio::task::spawn_blocking(move || krill_common::find_port_conflict(&ports))
+            .await
+            .unwrap_or(None)
+    }
+
+    /// Why a service that requires a GPU cannot start, if none is detected.
+    /// Asking the driver may take a while, so this runs before any runner is
+    /// locked.
+    async fn missing_gpu(&self, service_name: &str) -> Option<RunnerError> {
+        let gpu = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .is_some_and(|svc| svc.gpu);
+        if !gpu {
+            return None;
+        }
+        runner::check_gpu_available().await.err()
+    }
+
+    /// Why a service cannot start right now: a missing GPU or a required
+    /// port held by another process
+    async fn start_refusal(&self, service_name: &str) -> Option<RunnerError> {
+        if let Some(e) = self.missing_gpu(service_name).await {
+            return Some(e);
+        }
+        self.required_port_conflict(service_name)
+            .await
+            .map(RunnerError::PortInUse)
+    }
+
+    /// Mark a service that gave up waiting for its dependencies or its
+    /// `wait_for` conditions as blocked
+    async fn block_service(&self, service_name: &str, reason: String) {
+        let runners = self.runners.read().await;
+        let Some(runner) = runners.get(service_name) else {
+            return;
+        };
+        let mut runner_guard = runner.lock().await;
+        runner_guard.mark_blocked(reason);
+        let _ = self
+            .event_tx
+            .send((service_name.to_string(), runner_guard.get_status()));
+    }
+
     /// Spawn a task to read output from a process stream
     fn spawn_output_reader<R>(&self, service_name: String, reader: R, is_stderr: bool)
     where
         R: tokio::io::AsyncRead + Unpin + Send + 'static,
     {
         let log_tx = self.log_tx.clone();
-        let stream_type = if is_stderr { "stderr" } else { "stdout" };
+        let stream = if is_stderr {
+            LogStream::Stderr
+        } else {
+            LogStream::Stdout
+        };
 
         tokio::spawn(async move {
             let mut lines = BufReader::new(reader).lines();
@@ -228,25 +909,39 @@ impl Orchestrator {
 
                 // Send to log channel if available
                 if let Some(ref tx) = log_tx {
-                    let _ = tx.send((service_name.clone(), line));
+                    let _ = tx.send(
+                        ProcessOutputLine::new(service_name.clone(), line).with_stream(stream),
+                    );
                 }
             }
-            debug!("[{}] {} stream closed", service_name, stream_type);
+            debug!("[{}] {:?} stream closed", service_name, stream);
         });
     }
 
-    /// Start a monitoring task for a service
+    /// Start the monitoring and startup timeout tasks for a service
     fn start_monitoring_task(&self, service_name: &str) {
-        let service_name = service_name.to_string();
+        let name = service_name.to_string();
+        let self_clone = self.clone_for_task();
+
+        tokio::spawn(async move {
+            self_clone.monitor_service(&name).await;
+        });
+
+        let name = service_name.to_string();
         let self_clone = self.clone_for_task();
 
         tokio::spawn(async move {
-            self_clone.monitor_service(&service_name).await;
+            self_clone.watch_startup(&name).await;
         });
     }
 
     /// Monitor a service and handle failures/restarts
     async fn monitor_service(&self, service_name: &str) {
+        let monitored = match self.runners.read().await.get(service_name) {
+            Some(r) => Arc::clone(r),
+            None => return,
+        };
+        let pid = monitored.lock().await.pid();
         let mut interval = time::interval(Duration::from_secs(1));
 
         loop {
@@ -256,14 +951,40 @@ impl Orchestrator {
                 break;
             }
 
+            // Walking /proc takes a while, so it runs with the runner unlocked
+            let tracking = {
+                let runner_guard = monitored.lock().await;
+                if runner_guard.pid() != pid {
+                    break;
+                }
+                runner_guard.descendant_tracking()
+            };
+            let descendants = tracking.scan();
+
             let runners = self.runners.read().await;
             let runner = match runners.get(service_name) {
-                Some(r) => r,
-                None => break,
+                // Stop if the service was removed or replaced by a config reload
+                Some(r) if Arc::ptr_eq(r, &monitored) => r,
+                _ => break,
             };
 
             let mut runner_guard = runner.lock().await;
 
+            // A stop or a new start hands monitoring over to a fresh task
+            if runner_guard.pid() != pid {
+                break;
+            }
+
+            runner_guard.track_descendants(descendants);
+
+            // A finalized lifecycle node will not come back
+            if runner_guard.lifecycle() == Some(LifecycleState::Finalized) {
+                drop(runner_guard);
+                drop(runners);
+                self.stop_finalized(service_name, &monitored, pid).await;
+                break;
+            }
+
             // Check if process is still running
             if !runner_guard.is_running() {
                 let exit_code = runner_guard.get_exit_code();
@@ -277,80 +998,315 @@ impl Orchestrator {
                     continue;
                 }
 
+                // Whatever the process spawned goes down with it, once the
+                // locks are released
+                let leftovers = runner_guard.leftovers(reaper::GRACE_PERIOD);
+
+                // The hook may take up to its timeout, so it runs once the
+                // locks are released
+                let post_stop = match runner_guard.prepare_hook(Hook::PostStop).await {
+                    Ok(post_stop) => post_stop,
+                    Err(e) => {
+                        warn!("Service '{}': {}", service_name, e);
+                        None
+                    }
+                };
+
+                if runner_guard.config.oneshot && exit_code == Some(0) {
+                    runner_guard.mark_completed();
+                    let status = runner_guard.get_status();
+                    let _ = self.event_tx.send((service_name.to_string(), status));
+                    drop(runner_guard);
+                    drop(runners);
+                    leftovers.terminate().await;
+                    run_post_stop(service_name, post_stop).await;
+                    break;
+                }
+
                 warn!(
                     "Service '{}' process exited with code: {:?}",
                     service_name, exit_code
                 );
 
+                runner_guard.settle_restart_count();
                 let should_restart = runner_guard.should_restart(exit_code);
+                if should_restart {
+                    runner_guard.schedule_restart();
+                }
 
                 let error_msg = match exit_code {
+                    // The engine CLI exits with the container's code
+                    Some(code) if runner_guard.container_name().is_some() => {
+                        krill_common::container_exit_reason(code)
+                    }
                     Some(code) => format!("Process exited with code {}", code),
                     None => "Process terminated unexpectedly".to_string(),
                 };
                 runner_guard.mark_failed(Some(error_msg));
                 let status = runner_guard.get_status();
                 let _ = self.event_tx.send((service_name.to_string(), status));
+                self.record_failure().await;
+
+                // Back off based on consecutive failures so crash loops slow down
+                let restart_delay = runner_guard
+                    .config
+                    .policy
+                    .restart_delay_for(runner_guard.restart_count());
+                drop(runner_guard);
+                drop(runners);
+
+                leftovers.terminate().await;
+                run_post_stop(service_name, post_stop).await;
+                self.recover_failed_service(service_name, should_restart.then_some(restart_delay))
+                    .await;
+                break;
+            }
+        }
+    }
 
-                if should_restart {
-                    info!("Restarting service '{}'", service_name);
+    /// Stop a service whose lifecycle node was finalized, as the health
+    /// monitor reported, unless its process `pid` was replaced meanwhile
+    async fn stop_finalized(
+        &self,
+        service_name: &str,
+        runner: &Mutex<ServiceRunner>,
+        pid: Option<u32>,
+    ) {
+        let runner_guard = runner.lock().await;
+        if runner_guard.pid() != pid {
+            return;
+        }
+        info!(
+            "Lifecycle node of '{}' was finalized, stopping the service",
+            service_name
+        );
+        let _ = self
+            .event_tx
+            .send((service_name.to_string(), ServiceStatus::Stopping));
+        let (runner_guard, stopped) = stop_runner(runner, runner_guard).await;
+        if let Err(e) = stopped {
+            warn!("Error stopping '{}': {}", service_name, e);
+        }
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((service_name.to_string(), status));
+    }
 
-                    // Wait for restart delay
-                    let restart_delay = runner_guard.config.policy.restart_delay;
-                    drop(runner_guard);
-                    drop(runners);
+    /// Fail a service that does not finish starting within its
+    /// `start_timeout_sec`; time it spends paused does not count
+    async fn watch_startup(&self, service_name: &str) {
+        let watched = match self.runners.read().await.get(service_name) {
+            Some(r) => Arc::clone(r),
+            None => return,
+        };
+        let (pid, timeout) = {
+            let runner_guard = watched.lock().await;
+            (runner_guard.pid(), runner_guard.start_timeout())
+        };
+        let Some(timeout) = timeout else {
+            return;
+        };
+        let started = time::Instant::now();
+        let mut deadline = started + timeout;
+
+        let runner_guard = loop {
+            time::sleep_until(deadline).await;
 
-                    time::sleep(restart_delay).await;
+            if *self.shutdown.lock().await {
+                return;
+            }
 
-                    // Try to restart
-                    if let Err(e) = self.start_when_ready(service_name).await {
-                        error!("Failed to restart service '{}': {}", service_name, e);
-                    }
-                } else {
-                    info!("Service '{}' will not be restarted", service_name);
-
-                    // Check if this is a critical service
-                    let is_critical = self
-                        .config
-                        .services
-                        .get(service_name)
-                        .map(|s| s.critical)
-                        .unwrap_or(false);
-
-                    if is_critical {
-                        error!(
-                            "Critical service '{}' failed, initiating emergency stop",
-                            service_name
-                        );
-                        drop(runner_guard);
-                        drop(runners);
-                        self.emergency_stop().await;
-                    } else {
-                        // Cascade failure to dependents
-                        drop(runner_guard);
-                        drop(runners);
-                        self.cascade_failure(service_name).await;
-                    }
+            let runner_guard = watched.lock().await;
+
+            // Stopped, restarted, or replaced in the meantime
+            if runner_guard.pid() != pid
+                || !matches!(
+                    runner_guard.state(),
+                    ServiceState::Starting
+                        | ServiceState::Running
+                        | ServiceState::Healthy
+                        | ServiceState::Degraded
+                        | ServiceState::Paused
+                )
+                || runner_guard.startup_complete()
+            {
+                return;
+            }
 
-                    break;
-                }
+            deadline = started + timeout + runner_guard.time_paused();
+            if deadline <= time::Instant::now() {
+                break runner_guard;
             }
-        }
-    }
+        };
+
+        warn!(
+            "Service '{}' did not become ready within {:?}",
+            service_name, timeout
+        );
+
+        let (mut runner_guard, stopped) = stop_runner(&watched, runner_guard).await;
+        if let Err(e) = stopped {
+            warn!("Error stopping timed out service '{}': {}", service_name, e);
+        }
+
+        let should_restart = runner_guard.should_restart(None);
+        if should_restart {
+            runner_guard.schedule_restart();
+        }
+        runner_guard.mark_failed(Some(format!(
+            "Did not become ready within {}s",
+            timeout.as_secs()
+        )));
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((service_name.to_string(), status));
+        self.record_failure().await;
+
+        let restart_delay = runner_guard
+            .config
+            .policy
+            .restart_delay_for(runner_guard.restart_count());
+        drop(runner_guard);
+
+        self.recover_failed_service(service_name, should_restart.then_some(restart_delay))
+            .await;
+    }
+
+    /// Restart a failed service after `restart_delay`, or if it is not to be
+    /// restarted, escalate to an emergency stop (critical services) or stop
+    /// its dependents
+    async fn recover_failed_service(&self, service_name: &str, restart_delay: Option<Duration>) {
+        if let Some(restart_delay) = restart_delay {
+            if self.hold_restart(service_name).await {
+                return;
+            }
+            info!(
+                "Restarting service '{}' in {:?}",
+                service_name, restart_delay
+            );
+            time::sleep(restart_delay).await;
+
+            // The breaker may have tripped during the delay
+            if self.hold_restart(service_name).await {
+                return;
+            }
+            if let Err(e) = self.start_when_ready(service_name).await {
+                error!("Failed to restart service '{}': {}", service_name, e);
+            }
+            return;
+        }
+
+        info!("Service '{}' will not be restarted", service_name);
+
+        // Check if this is a critical service
+        let is_critical = self
+            .config
+            .read()
+            .await
+            .services
+            .get(service_name)
+            .map(|s| s.critical)
+            .unwrap_or(false);
+
+        if is_critical {
+            error!(
+                "Critical service '{}' failed, initiating emergency stop",
+                service_name
+            );
+            self.emergency_stop().await;
+        } else {
+            // Cascade failure to dependents
+            self.cascade_failure(service_name).await;
+        }
+    }
+
+    /// Count a service failure against `restart_storm`, pausing automatic
+    /// restarts once the failures within its window exceed `max_failures`
+    async fn record_failure(&self) {
+        let (workspace, storm_config) = {
+            let config = self.config.read().await;
+            (config.name.clone(), config.restart_storm.clone())
+        };
+        let Some(storm_config) = storm_config else {
+            return;
+        };
+        let Some(failures) = self
+            .restart_storm
+            .lock()
+            .await
+            .record_failure(&storm_config, std::time::Instant::now())
+        else {
+            return;
+        };
+
+        error!(
+            "{} service failures within {:?}: automatic restarts paused until 'krill resume'",
+            failures, storm_config.window
+        );
+        if let Some(ref storm_tx) = self.storm_tx {
+            let _ = storm_tx.send(StormTripped {
+                workspace,
+                failures,
+                window: storm_config.window,
+            });
+        }
+    }
+
+    /// Whether the restart of `service_name` is held back by a tripped
+    /// `restart_storm`, remembering it for [`resume_restarts`](Self::resume_restarts)
+    async fn hold_restart(&self, service_name: &str) -> bool {
+        let mut storm = self.restart_storm.lock().await;
+        if !storm.is_tripped() {
+            return false;
+        }
+        warn!(
+            "Not restarting service '{}': automatic restarts are paused",
+            service_name
+        );
+        storm.hold(service_name);
+        true
+    }
+
+    /// Whether a restart storm paused automatic restarts
+    pub async fn restarts_paused(&self) -> bool {
+        self.restart_storm.lock().await.is_tripped()
+    }
+
+    /// Resume automatic restarts after a restart storm, restarting the
+    /// services that are still failed and whose restart was held back
+    pub async fn resume_restarts(&self) {
+        let held = self.restart_storm.lock().await.resume();
+        info!("Automatic restarts resumed");
+
+        for name in held {
+            let runner = self.runners.read().await.get(&name).cloned();
+            let Some(runner) = runner else {
+                continue;
+            };
+            if runner.lock().await.state() != ServiceState::Failed {
+                continue;
+            }
+            let self_clone = self.clone_for_task();
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.start_when_ready(&name).await {
+                    error!("Failed to restart service '{}': {}", name, e);
+                }
+            });
+        }
+    }
 
     /// Handle cascading failure
     async fn cascade_failure(&self, failed_service: &str) {
         info!("Cascading failure from '{}'", failed_service);
 
-        let dependents = self.dag.cascade_failure(failed_service);
+        let dependents = self.dag.read().await.cascade_failure(failed_service);
 
         for dependent in dependents {
             info!("Stopping dependent service '{}'", dependent);
 
-            let runners = self.runners.read().await;
-            if let Some(runner) = runners.get(&dependent) {
-                let mut runner_guard = runner.lock().await;
-                if let Err(e) = runner_guard.stop().await {
+            let runner = self.runners.read().await.get(&dependent).cloned();
+            if let Some(runner) = runner {
+                let runner_guard = runner.lock().await;
+                let (runner_guard, stopped) = stop_runner(&runner, runner_guard).await;
+                if let Err(e) = stopped {
                     error!("Failed to stop dependent '{}': {}", dependent, e);
                 }
 
@@ -360,54 +1316,136 @@ impl Orchestrator {
         }
     }
 
-    /// Emergency stop all services
-    async fn emergency_stop(&self) {
-        error!("EMERGENCY STOP - Stopping all services immediately");
+    /// Emergency stop: run the workspace's `emergency_stop_cmd` and
+    /// `on_emergency` actions in order.
+    /// Stopping services latches every service, which then refuses to start
+    /// again until its safety stop is cleared.
+    pub async fn emergency_stop(&self) {
+        error!("EMERGENCY STOP - Running emergency actions");
+
+        let (actions, env) = {
+            let config = self.config.read().await;
+            (config.emergency_actions(), config.env.clone())
+        };
+
+        for action in &actions {
+            match action {
+                EmergencyAction::StopServices => self.stop_all_latched().await,
+                EmergencyAction::Command { command, timeout } => {
+                    info!("Running emergency command '{}'", command);
+                    run_emergency_command(&shell_command(command), &env, *timeout).await;
+                }
+                EmergencyAction::Reboot | EmergencyAction::Poweroff => {
+                    if let Some(argv) = action.host_command() {
+                        warn!("Emergency action: {}", argv.join(" "));
+                        run_emergency_command(&argv, &env, DEFAULT_EMERGENCY_COMMAND_TIMEOUT).await;
+                    }
+                }
+            }
+        }
+    }
 
-        *self.shutdown.lock().await = true;
+    /// Stop all services immediately and latch them
+    async fn stop_all_latched(&self) {
+        error!("EMERGENCY STOP - Stopping all services immediately");
 
         let runners = self.runners.read().await;
+        self.safety_stopped
+            .write()
+            .await
+            .extend(runners.keys().cloned());
+
         for (name, runner) in runners.iter() {
             let mut runner_guard = runner.lock().await;
             info!("Emergency stopping service '{}'", name);
-            if let Err(e) = runner_guard.stop().await {
+            if let Err(e) = runner_guard.stop_without_hooks().await {
                 error!("Error during emergency stop of '{}': {}", name, e);
             }
+
+            let status = runner_guard.get_status();
+            let _ = self.event_tx.send((name.clone(), status));
         }
     }
 
-    /// Graceful shutdown in reverse DAG order
-    pub async fn shutdown(&self) -> Result<(), OrchestratorError> {
-        info!("Starting graceful shutdown");
+    /// Release the emergency stop latch of a service so it can be started again
+    pub async fn clear_safety_stop(&self, name: &str) -> Result<(), OrchestratorError> {
+        if !self.runners.read().await.contains_key(name) {
+            return Err(OrchestratorError::ServiceNotFound(name.to_string()));
+        }
 
-        *self.shutdown.lock().await = true;
+        if !self.safety_stopped.write().await.remove(name) {
+            return Err(OrchestratorError::NotSafetyStopped(name.to_string()));
+        }
 
-        let shutdown_order = self.dag.shutdown_order()?;
+        info!("Cleared safety stop of service '{}'", name);
+        Ok(())
+    }
 
-        // Stop services sequentially in reverse dependency order
-        for service_name in shutdown_order {
-            let runners = self.runners.read().await;
-            if let Some(runner) = runners.get(&service_name) {
-                let runner = runner.clone();
-                let event_tx = self.event_tx.clone();
-                let name = service_name.clone();
+    async fn ensure_not_safety_stopped(&self, name: &str) -> Result<(), OrchestratorError> {
+        if self.safety_stopped.read().await.contains(name) {
+            return Err(OrchestratorError::SafetyStopped(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Graceful shutdown in reverse DAG order, reporting how each running
+    /// service was stopped
+    pub async fn shutdown(&self) -> Result<Vec<ServiceShutdown>, OrchestratorError> {
+        info!("Starting graceful shutdown");
+
+        *self.shutdown.lock().await = true;
 
-                drop(runners); // Release read lock before spawning
+        let levels = self.dag.read().await.shutdown_levels()?;
+        let mut report = Vec::new();
+
+        // Stop dependents before their dependencies, a level at a time. The
+        // services of a level are stopped in parallel, each within its own
+        // stop_timeout / stop_sequence.
+        for level in levels {
+            let runners: Vec<(String, Arc<Mutex<ServiceRunner>>)> = {
+                let runners = self.runners.read().await;
+                level
+                    .into_iter()
+                    .filter_map(|name| Some((name.clone(), runners.get(&name)?.clone())))
+                    .collect()
+            };
 
-                let mut runner_guard = runner.lock().await;
+            let stops = runners.into_iter().map(|(name, runner)| async move {
+                let runner_guard = runner.lock().await;
                 info!("Stopping service '{}'", name);
 
-                if let Err(e) = runner_guard.stop().await {
+                let started = time::Instant::now();
+                let (runner_guard, result) = stop_runner(&runner, runner_guard).await;
+                if let Err(ref e) = result {
                     error!("Failed to stop '{}': {}", name, e);
                 }
+                let stopped = shutdown_entry(&name, runner_guard.last_stop(), result, started);
 
                 let status = runner_guard.get_status();
-                let _ = event_tx.send((name, status));
+                let _ = self.event_tx.send((name, status));
+                stopped
+            });
+            let mut stopped: Vec<ServiceShutdown> = futures::future::join_all(stops)
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+            stopped.sort_by_key(|service| service.duration);
+            report.extend(stopped);
+        }
+
+        // Processes the services left behind go down before the daemon
+        reaper::settle().await;
+
+        if let Some(ref state_file) = self.state_file {
+            let _shutdown = self.shutdown.lock().await;
+            if let Err(e) = state_file.remove() {
+                warn!("Failed to remove state file {:?}: {}", state_file.path(), e);
             }
         }
 
         info!("Graceful shutdown complete");
-        Ok(())
+        Ok(report)
     }
 
     /// Process heartbeat from a service
@@ -415,7 +1453,7 @@ impl Orchestrator {
         &self,
         service_name: &str,
         status: ServiceStatus,
-        _metadata: HashMap<String, String>,
+        metadata: HashMap<String, String>,
     ) -> Result<(), OrchestratorError> {
         let runners = self.runners.read().await;
         let runner = runners
@@ -425,6 +1463,8 @@ impl Orchestrator {
         drop(runners);
 
         let mut runner_guard = runner.lock().await;
+        runner_guard.record_heartbeat();
+        runner_guard.set_heartbeat_metadata(metadata);
 
         // Update the service health based on the heartbeat status
         // Healthy and Running statuses indicate the service is responsive
@@ -440,42 +1480,41 @@ impl Orchestrator {
         Ok(())
     }
 
+    /// Run a service's health check immediately
+    pub async fn trigger_health_check(&self, service_name: &str) -> Option<bool> {
+        self.health_monitor.trigger_health_check(service_name).await
+    }
+
     /// Get status of all services
     pub async fn get_snapshot(&self) -> HashMap<String, krill_common::ServiceSnapshot> {
         let mut snapshot = HashMap::new();
+        let restarts_paused = self.restarts_paused().await;
+        let config = self.config.read().await;
         let runners = self.runners.read().await;
+        let safety_stopped = self.safety_stopped.read().await;
 
         for (name, runner) in runners.iter() {
             let runner_guard = runner.lock().await;
-            let service_config = self.config.services.get(name);
+            let service_config = config.services.get(name);
 
             // Calculate uptime if service has started
             let uptime = runner_guard.uptime();
 
-            // Get dependencies
-            let dependencies = service_config
-                .map(|cfg| {
-                    cfg.dependencies
-                        .iter()
-                        .map(|d| d.service_name().to_string())
-                        .collect()
-                })
+            // Dependencies, executor, restart policy and flags from the recipe
+            let mut summary = service_config
+                .map(krill_common::ServiceConfigSummary::from)
+                .unwrap_or_default();
+            summary.groups = config
+                .groups
+                .iter()
+                .filter(|(_, members)| members.contains(name))
+                .map(|(group, _)| group.clone())
+                .collect();
+            summary.groups.sort();
+            let protected = service_config.map(|cfg| cfg.protect).unwrap_or(false);
+            let labels = service_config
+                .map(|cfg| cfg.labels.clone())
                 .unwrap_or_default();
-
-            // Get GPU usage
-            let uses_gpu = service_config.map(|cfg| cfg.gpu).unwrap_or(false);
-
-            // Get critical flag
-            let critical = service_config.map(|cfg| cfg.critical).unwrap_or(false);
-
-            // Get restart policy
-            let restart_policy = service_config
-                .map(|cfg| format!("{:?}", cfg.policy.restart))
-                .unwrap_or_else(|| "Unknown".to_string());
-
-            let max_restarts = service_config
-                .map(|cfg| cfg.policy.max_restarts)
-                .unwrap_or(0);
 
             snapshot.insert(
                 name.clone(),
@@ -485,14 +1524,32 @@ impl Orchestrator {
                     uid: runner_guard.uid().to_string(),
                     uptime,
                     restart_count: runner_guard.restart_count(),
-                    last_error: runner_guard.last_error().map(String::from),
+                    // A limit violation, flapping or failing health check explains a
+                    // degraded status best
+                    last_error: runner_guard
+                        .limit_violation()
+                        .or(runner_guard.gpu_problem())
+                        .or(runner_guard.flapping())
+                        .or(runner_guard.health_failure())
+                        .or(runner_guard.last_error())
+                        .map(String::from),
                     namespace: runner_guard.namespace().to_string(),
-                    executor_type: runner_guard.executor_type().to_string(),
-                    dependencies,
-                    uses_gpu,
-                    critical,
-                    restart_policy,
-                    max_restarts,
+                    legacy_config: (&summary).into(),
+                    config: summary,
+                    protected,
+                    labels,
+                    safety_stopped: safety_stopped.contains(name),
+                    restarts_paused,
+                    metrics: self.metrics.latest(name),
+                    heartbeat_metadata: runner_guard.heartbeat_metadata().clone(),
+                    heartbeat_age: runner_guard.heartbeat_age(),
+                    missed_heartbeats: runner_guard.missed_heartbeats(),
+                    total_restarts: runner_guard.total_restarts(),
+                    last_exit_code: runner_guard.last_exit_code(),
+                    state_history: runner_guard.state_history(),
+                    state_age: Some(runner_guard.state_age()),
+                    availability: runner_guard.availability(),
+                    maintenance: Vec::new(),
                 },
             );
         }
@@ -500,6 +1557,199 @@ impl Orchestrator {
         snapshot
     }
 
+    /// Sample resource usage of every running service
+    pub async fn sample_metrics(&self) -> HashMap<String, krill_common::ServiceMetrics> {
+        let mut groups = HashMap::new();
+        for (name, runner) in self.runners.read().await.iter() {
+            let runner_guard = runner.lock().await;
+            if let Some(pgid) = runner_guard.pgid().or(runner_guard.pid()) {
+                groups.insert(name.clone(), pgid);
+            }
+        }
+
+        let collector = Arc::clone(&self.metrics);
+        let metrics = tokio::task::spawn_blocking(move || collector.sample(&groups))
+            .await
+            .unwrap_or_default();
+
+        self.check_limits(&metrics).await;
+        self.check_gpu_health().await;
+        metrics
+    }
+
+    /// Degrade running `gpu_health` services while a GPU throttles or reports
+    /// ECC errors (or cannot be probed), and restore them once it recovers
+    async fn check_gpu_health(&self) {
+        let watched: HashSet<String> = self
+            .config
+            .read()
+            .await
+            .services
+            .iter()
+            .filter(|(_, service)| service.gpu_health)
+            .map(|(name, _)| name.clone())
+            .collect();
+        if watched.is_empty() {
+            return;
+        }
+
+        let mut probed = Vec::new();
+        for (name, runner) in self.runners.read().await.iter() {
+            let runner_guard = runner.lock().await;
+            if watched.contains(name)
+                && matches!(
+                    runner_guard.state(),
+                    ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+                )
+            {
+                probed.push((name.clone(), Arc::clone(runner)));
+            }
+        }
+        if probed.is_empty() {
+            return;
+        }
+
+        let problem = match gpu::probe().await {
+            Ok(health) => gpu::problems(&health),
+            Err(e) => Some(format!("GPU probe failed: {}", e)),
+        };
+        for (name, runner) in probed {
+            let mut runner_guard = runner.lock().await;
+            if runner_guard.check_gpu(problem.as_deref()) {
+                let _ = self.event_tx.send((name, runner_guard.get_status()));
+            }
+        }
+    }
+
+    /// Degrade running services that exceed their `limits`, and restore
+    /// those back within them
+    async fn check_limits(&self, metrics: &HashMap<String, krill_common::ServiceMetrics>) {
+        for (name, runner) in self.runners.read().await.iter() {
+            let mut runner_guard = runner.lock().await;
+            if !matches!(
+                runner_guard.state(),
+                ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+            ) {
+                continue;
+            }
+            if runner_guard.check_limits(metrics.get(name)) {
+                let _ = self
+                    .event_tx
+                    .send((name.clone(), runner_guard.get_status()));
+            }
+        }
+    }
+
+    /// Start a stopped or failed service whose dependencies are already satisfied
+    pub async fn start_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        if *self.shutdown.lock().await {
+            return Err(OrchestratorError::ShuttingDown);
+        }
+        self.ensure_not_safety_stopped(name).await?;
+
+        let dependencies = self
+            .config
+            .read()
+            .await
+            .services
+            .get(name)
+            .map(|svc| svc.dependencies.clone())
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?;
+
+        let runners = self.runners.read().await;
+        let runner = runners
+            .get(name)
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?
+            .clone();
+
+        // Unlike startup, a manual start does not wait for dependencies
+        for dep in &dependencies {
+            let met = match runners.get(dep.service_name()) {
+                Some(r) => condition_met(dep.condition(), &*r.lock().await),
+                None => {
+                    return Err(OrchestratorError::ServiceNotFound(
+                        dep.service_name().to_string(),
+                    ))
+                }
+            };
+            if !met {
+                return Err(OrchestratorError::DependencyNotReady {
+                    service: name.to_string(),
+                    dependency: dep.service_name().to_string(),
+                    condition: dep.condition(),
+                });
+            }
+        }
+        drop(runners);
+
+        if let Some(condition) = self.unmet_precondition(name).await {
+            return Err(OrchestratorError::PreconditionNotMet {
+                service: name.to_string(),
+                condition: condition.to_string(),
+            });
+        }
+
+        let refused = self.start_refusal(name).await;
+
+        let mut runner_guard = runner.lock().await;
+        let state = runner_guard.state();
+        if !matches!(
+            state,
+            ServiceState::Stopped
+                | ServiceState::Failed
+                | ServiceState::Completed
+                | ServiceState::Blocked
+        ) {
+            return Err(OrchestratorError::InvalidState {
+                service: name.to_string(),
+                state,
+            });
+        }
+
+        info!("Starting service '{}'", name);
+        let _ = self
+            .event_tx
+            .send((name.to_string(), krill_common::ServiceStatus::Starting));
+
+        // An operator start gets a fresh restart budget
+        runner_guard.reset_restart_count();
+
+        let (mut runner_guard, started) = match refused {
+            Some(e) => (runner_guard, Err(e)),
+            None => start_runner(name, &runner, runner_guard).await,
+        };
+        match started {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                error!("Failed to start service '{}': {}", name, e);
+                runner_guard.mark_failed(Some(e.to_string()));
+                let status = runner_guard.get_status();
+                let _ = self.event_tx.send((name.to_string(), status));
+                return Err(e.into());
+            }
+        }
+
+        // Take stdout/stderr handles and spawn output capture tasks
+        if let Some(stdout) = runner_guard.take_stdout() {
+            self.spawn_output_reader(name.to_string(), stdout, false);
+        }
+        if let Some(stderr) = runner_guard.take_stderr() {
+            self.spawn_output_reader(name.to_string(), stderr, true);
+        }
+
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((name.to_string(), status));
+
+        drop(runner_guard);
+        self.start_monitoring_task(name);
+        self.health_monitor.start_service_monitoring(name);
+
+        info!("Service '{}' started", name);
+
+        Ok(())
+    }
+
     /// Stop a specific service
     pub async fn stop_service(&self, name: &str) -> Result<(), OrchestratorError> {
         let runners = self.runners.read().await;
@@ -509,7 +1759,7 @@ impl Orchestrator {
             .clone();
         drop(runners);
 
-        let mut runner_guard = runner.lock().await;
+        let runner_guard = runner.lock().await;
         info!("Stopping service '{}'", name);
 
         // Send "stopping" status
@@ -517,7 +1767,8 @@ impl Orchestrator {
             .event_tx
             .send((name.to_string(), krill_common::ServiceStatus::Stopping));
 
-        runner_guard.stop().await?;
+        let (runner_guard, stopped) = stop_runner(&runner, runner_guard).await;
+        stopped?;
 
         let status = runner_guard.get_status();
         let _ = self.event_tx.send((name.to_string(), status));
@@ -527,8 +1778,94 @@ impl Orchestrator {
         Ok(())
     }
 
+    /// Ask a running service to reload its configuration with its
+    /// `reload_signal`, without restarting it
+    pub async fn reload_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        let runners = self.runners.read().await;
+        let runner = runners
+            .get(name)
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?
+            .clone();
+        drop(runners);
+
+        let runner_guard = runner.lock().await;
+        runner_guard.reload()?;
+        Ok(())
+    }
+
+    /// Kill a specific service with SIGKILL, without a graceful stop
+    pub async fn kill_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        let runners = self.runners.read().await;
+        let runner = runners
+            .get(name)
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?
+            .clone();
+        drop(runners);
+
+        let mut runner_guard = runner.lock().await;
+        runner_guard.kill().await?;
+
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((name.to_string(), status));
+
+        info!("Service '{}' killed", name);
+        Ok(())
+    }
+
+    /// Freeze a running service with SIGSTOP until it is resumed; its
+    /// health is not checked meanwhile
+    pub async fn pause_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        let runners = self.runners.read().await;
+        let runner = runners
+            .get(name)
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?
+            .clone();
+        drop(runners);
+
+        let mut runner_guard = runner.lock().await;
+        runner_guard.pause().await?;
+
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((name.to_string(), status));
+        Ok(())
+    }
+
+    /// Continue a paused service with SIGCONT
+    pub async fn resume_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        let runners = self.runners.read().await;
+        let runner = runners
+            .get(name)
+            .ok_or_else(|| OrchestratorError::ServiceNotFound(name.to_string()))?
+            .clone();
+        drop(runners);
+
+        let mut runner_guard = runner.lock().await;
+        match runner_guard.resume().await {
+            Err(RunnerError::NotPaused) => {
+                return Err(OrchestratorError::NotPaused(name.to_string()))
+            }
+            result => result?,
+        }
+
+        let status = runner_guard.get_status();
+        let _ = self.event_tx.send((name.to_string(), status));
+        Ok(())
+    }
+
     /// Restart a specific service
     pub async fn restart_service(&self, name: &str) -> Result<(), OrchestratorError> {
+        self.ensure_not_safety_stopped(name).await?;
+        // Checked before stopping, so the service is not left down
+        if let Some(condition) = self.unmet_precondition(name).await {
+            return Err(OrchestratorError::PreconditionNotMet {
+                service: name.to_string(),
+                condition: condition.to_string(),
+            });
+        }
+        if let Some(e) = self.missing_gpu(name).await {
+            return Err(e.into());
+        }
+
         let runners = self.runners.read().await;
         let runner = runners
             .get(name)
@@ -536,7 +1873,7 @@ impl Orchestrator {
             .clone();
         drop(runners);
 
-        let mut runner_guard = runner.lock().await;
+        let runner_guard = runner.lock().await;
         info!("Restarting service '{}'", name);
 
         // Send "restarting" status (we use Stopping as intermediate state)
@@ -545,7 +1882,8 @@ impl Orchestrator {
             .send((name.to_string(), krill_common::ServiceStatus::Stopping));
 
         // Stop first
-        if let Err(e) = runner_guard.stop().await {
+        let (mut runner_guard, stopped) = stop_runner(&runner, runner_guard).await;
+        if let Err(e) = stopped {
             warn!("Error stopping service '{}' during restart: {}", name, e);
         }
 
@@ -566,7 +1904,18 @@ impl Orchestrator {
         runner_guard.increment_restart_count();
 
         // Start again
-        runner_guard.start().await?;
+        let (mut runner_guard, started) = start_runner(name, &runner, runner_guard).await;
+        match started {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => {
+                error!("Failed to restart service '{}': {}", name, e);
+                runner_guard.mark_failed(Some(e.to_string()));
+                let status = runner_guard.get_status();
+                let _ = self.event_tx.send((name.to_string(), status));
+                return Err(e.into());
+            }
+        }
 
         // Take stdout/stderr handles and spawn output capture tasks
         if let Some(stdout) = runner_guard.take_stdout() {
@@ -579,15 +1928,177 @@ impl Orchestrator {
         let status = runner_guard.get_status();
         let _ = self.event_tx.send((name.to_string(), status));
 
-        // Start monitoring task for the restarted service
+        // Start monitoring tasks for the restarted service
         drop(runner_guard);
         self.start_monitoring_task(name);
+        self.health_monitor.start_service_monitoring(name);
 
         info!("Service '{}' restarted successfully", name);
 
         Ok(())
     }
 
+    /// What `reload_config` would do with `new_config`, without doing it
+    pub async fn plan_reload(
+        &self,
+        new_config: &KrillConfig,
+    ) -> Result<ReloadSummary, OrchestratorError> {
+        new_config.dependency_graph()?;
+
+        // Env files and file secrets can change on disk with the recipe
+        // unchanged; compare them with what each run started with
+        let mut inputs = HashMap::new();
+        // Only these are restarted; the others, e.g. services stopped with
+        // `krill stop` or failed ones, take their new settings on their next
+        // start
+        let mut active = HashSet::new();
+        for (name, runner) in self.runners.read().await.iter() {
+            let runner_guard = runner.lock().await;
+            if let Some(hashes) = runner_guard.inputs() {
+                inputs.insert(name.clone(), hashes);
+            }
+            if matches!(
+                runner_guard.state(),
+                ServiceState::Pending
+                    | ServiceState::Starting
+                    | ServiceState::Running
+                    | ServiceState::Healthy
+                    | ServiceState::Degraded
+                    | ServiceState::Paused
+            ) {
+                active.insert(name.clone());
+            }
+        }
+
+        let old_config = self.config.read().await;
+        let mut summary = ReloadSummary::default();
+        for (name, old) in &old_config.services {
+            let Some(new) = new_config.services.get(name) else {
+                summary.removed.push(name.clone());
+                continue;
+            };
+            let mut changes = old.changes(&old_config.env, new, &new_config.env);
+            if let Some(started) = inputs.get(name) {
+                for setting in started.changed(&InputHashes::of(new)) {
+                    if !changes.0.contains(&setting) {
+                        changes.0.push(setting);
+                    }
+                }
+                changes.0.sort();
+            }
+            if changes.is_empty() {
+                continue;
+            }
+            if changes.needs_restart() && active.contains(name) {
+                summary.restarted.push(name.clone());
+            } else {
+                summary.updated.push(name.clone());
+            }
+            summary.changes.insert(name.clone(), changes.0);
+        }
+        summary.added = new_config
+            .services
+            .keys()
+            .filter(|name| !old_config.services.contains_key(*name))
+            .cloned()
+            .collect();
+
+        summary.added.sort();
+        summary.removed.sort();
+        summary.restarted.sort();
+        summary.updated.sort();
+        Ok(summary)
+    }
+
+    /// Apply a new configuration: start added services, stop removed ones,
+    /// restart services whose spawn settings changed and hand the other
+    /// changes to the running services
+    pub async fn reload_config(
+        &self,
+        new_config: KrillConfig,
+    ) -> Result<ReloadSummary, OrchestratorError> {
+        if *self.shutdown.lock().await {
+            return Err(OrchestratorError::ShuttingDown);
+        }
+
+        info!("Reloading configuration");
+
+        // Validate the new graph before touching any running service
+        let new_dag = new_config.dependency_graph()?;
+        let summary = self.plan_reload(&new_config).await?;
+        let mut to_start = Vec::new();
+
+        // Stop removed and changed services in reverse dependency order
+        let shutdown_order = self.dag.read().await.shutdown_order()?;
+        for name in shutdown_order {
+            if summary.removed.contains(&name) || summary.restarted.contains(&name) {
+                if let Err(e) = self.stop_service(&name).await {
+                    warn!("Failed to stop '{}' during reload: {}", name, e);
+                }
+            }
+        }
+
+        {
+            let mut runners = self.runners.write().await;
+            for name in &summary.removed {
+                info!("Removing service '{}'", name);
+                runners.remove(name);
+            }
+
+            for name in summary.added.iter().chain(summary.restarted.iter()) {
+                runners.insert(
+                    name.clone(),
+                    Arc::new(Mutex::new(new_runner(
+                        &new_config,
+                        name,
+                        self.hosted,
+                        &self.log_tx,
+                    ))),
+                );
+                to_start.push(name.clone());
+            }
+
+            // The other services take their new settings now; a health check
+            // added to a service that had none is monitored from its next start
+            for (name, runner) in runners.iter() {
+                if to_start.contains(name) {
+                    continue;
+                }
+                if let Some(svc_config) = new_config.services.get(name) {
+                    runner
+                        .lock()
+                        .await
+                        .update_config(svc_config.clone(), new_config.env.clone());
+                }
+            }
+        }
+
+        if new_config.max_concurrent_starts != self.config.read().await.max_concurrent_starts {
+            *self.start_slots.write().await = start_slots(&new_config);
+        }
+        *self.dag.write().await = new_dag;
+        *self.config.write().await = new_config;
+
+        for name in to_start {
+            let self_clone = self.clone_for_task();
+            tokio::spawn(async move {
+                if let Err(e) = self_clone.start_when_ready(&name).await {
+                    error!("Failed to start service '{}' after reload: {}", name, e);
+                }
+            });
+        }
+
+        info!(
+            "Configuration reloaded: {} added, {} removed, {} restarted, {} updated",
+            summary.added.len(),
+            summary.removed.len(),
+            summary.restarted.len(),
+            summary.updated.len()
+        );
+
+        Ok(summary)
+    }
+
     fn clone_for_task(&self) -> Self {
         Self {
             config: Arc::clone(&self.config),
@@ -595,7 +2106,309 @@ impl Orchestrator {
             runners: Arc::clone(&self.runners),
             event_tx: self.event_tx.clone(),
             log_tx: self.log_tx.clone(),
+            health_monitor: Arc::clone(&self.health_monitor),
+            metrics: Arc::clone(&self.metrics),
             shutdown: Arc::clone(&self.shutdown),
+            safety_stopped: Arc::clone(&self.safety_stopped),
+            state_file: self.state_file.clone(),
+            state_changed: Arc::clone(&self.state_changed),
+            hosted: self.hosted,
+            start_slots: Arc::clone(&self.start_slots),
+            restart_storm: Arc::clone(&self.restart_storm),
+            storm_tx: self.storm_tx.clone(),
+        }
+    }
+}
+
+/// The first service, in name order, that the daemon is about to start (all
+/// of them, or those of `group`) while another process holds one of its
+/// `requires_ports`. Services still running under a previous daemon hold
+/// their own ports and are skipped.
+pub fn startup_port_conflict(
+    config: &KrillConfig,
+    group: Option<&str>,
+    previous: Option<&DaemonState>,
+) -> Option<(String, PortConflict)> {
+    let selected = group.and_then(|group| config.group_with_dependencies(group));
+    let adopted: HashSet<&str> = previous
+        .map(|previous| {
+            previous
+                .live_services()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut names: Vec<&String> = config
+        .services
+        .iter()
+        .filter(|(_, service)| !service.requires_ports.is_empty())
+        .map(|(name, _)| name)
+        .filter(|name| {
+            selected
+                .as_ref()
+                .is_none_or(|selected| selected.contains(*name))
+        })
+        .filter(|name| !adopted.contains(name.as_str()))
+        .collect();
+    names.sort();
+
+    names.into_iter().find_map(|name| {
+        krill_common::find_port_conflict(&config.services[name].requires_ports)
+            .map(|conflict| (name.clone(), conflict))
+    })
+}
+
+/// Launch slots for `max_concurrent_starts`, if it is set
+fn start_slots(config: &KrillConfig) -> Option<Arc<Semaphore>> {
+    config
+        .max_concurrent_starts
+        .map(|limit| Arc::new(Semaphore::new(limit)))
+}
+
+/// Hold a launch slot until the service finished starting, stopped, or ran
+/// out of its start timeout
+async fn hold_start_slot(runner: Arc<Mutex<ServiceRunner>>, _permit: OwnedSemaphorePermit) {
+    let (pid, timeout) = {
+        let runner_guard = runner.lock().await;
+        (runner_guard.pid(), runner_guard.start_timeout())
+    };
+    let deadline = time::Instant::now() + timeout.unwrap_or(START_SLOT_TIMEOUT);
+
+    while time::Instant::now() < deadline {
+        {
+            let runner_guard = runner.lock().await;
+            if runner_guard.pid() != pid
+                || runner_guard.startup_complete()
+                || !matches!(
+                    runner_guard.state(),
+                    ServiceState::Starting
+                        | ServiceState::Running
+                        | ServiceState::Healthy
+                        | ServiceState::Degraded
+                )
+            {
+                return;
+            }
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// The shutdown report entry of a service whose stop began at `started`,
+/// logged for the daemon's log; `None` if it was not running
+fn shutdown_entry(
+    name: &str,
+    outcome: Option<&StopOutcome>,
+    result: Result<(), RunnerError>,
+    started: time::Instant,
+) -> Option<ServiceShutdown> {
+    let mut errors = outcome
+        .map(|outcome| outcome.errors.clone())
+        .unwrap_or_default();
+    if let Err(e) = result {
+        errors.push(e.to_string());
+    }
+    if outcome.is_none() && errors.is_empty() {
+        return None;
+    }
+
+    let entry = ServiceShutdown {
+        service: name.to_string(),
+        method: outcome.map(|outcome| outcome.method),
+        duration: started.elapsed(),
+        exit_code: outcome.and_then(|outcome| outcome.exit_code),
+        signal: outcome.and_then(|outcome| outcome.signal),
+        errors,
+    };
+    match entry.method {
+        Some(method) => info!(
+            "Service '{}' stopped by {} after {:?}",
+            name, method, entry.duration
+        ),
+        None => warn!(
+            "Service '{}' could not be stopped after {:?}",
+            name, entry.duration
+        ),
+    }
+    Some(entry)
+}
+
+/// Run the `post_stop` hook of a service whose process exited
+async fn run_post_stop(service_name: &str, post_stop: Option<PreparedHook>) {
+    let Some(post_stop) = post_stop else {
+        return;
+    };
+    if let Err(e) = run_hook_unlocked(post_stop).await {
+        warn!("Service '{}': {}", service_name, e);
+    }
+}
+
+/// Run a hook prepared under the runner's lock in a task of its own, so no
+/// lock is held while it runs
+async fn run_hook_unlocked(prepared: PreparedHook) -> Result<(), RunnerError> {
+    let hook = prepared.hook();
+    tokio::spawn(prepared.run()).await.unwrap_or_else(|e| {
+        Err(RunnerError::HookFailed {
+            hook,
+            reason: e.to_string(),
+        })
+    })
+}
+
+/// Start the service `runner` runs, given its lock. Its hooks may take up to
+/// their timeout, so `pre_start` runs with the runner unlocked and
+/// `post_start` in the background; the guard returned holds the lock again.
+/// `Ok(false)` if the service was stopped while `pre_start` ran.
+async fn start_runner<'a>(
+    service_name: &str,
+    runner: &'a Mutex<ServiceRunner>,
+    mut runner_guard: MutexGuard<'a, ServiceRunner>,
+) -> (MutexGuard<'a, ServiceRunner>, Result<bool, RunnerError>) {
+    match runner_guard.begin_start().await {
+        Ok(true) => {}
+        Ok(false) => return (runner_guard, Ok(true)),
+        Err(e) => return (runner_guard, Err(e)),
+    }
+    match runner_guard.prepare_hook(Hook::PreStart).await {
+        Ok(Some(pre_start)) => {
+            drop(runner_guard);
+            let result = run_hook_unlocked(pre_start).await;
+            runner_guard = runner.lock().await;
+            if let Err(e) = result {
+                return (runner_guard, Err(e));
+            }
         }
+        Ok(None) => {}
+        Err(e) => return (runner_guard, Err(e)),
+    }
+
+    let launched = runner_guard.launch().await;
+    if let Ok(true) = launched {
+        match runner_guard.prepare_hook(Hook::PostStart).await {
+            Ok(Some(post_start)) => {
+                let service_name = service_name.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) = post_start.run().await {
+                        warn!("Service '{}': {}", service_name, e);
+                    }
+                });
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Service '{}': {}", service_name, e),
+        }
+    }
+    (runner_guard, launched)
+}
+
+/// Stop the service `runner` runs gracefully, given its lock. Its
+/// `pre_stop` and `post_stop` hooks may take up to their timeout, so they
+/// run with the runner unlocked; the guard returned holds the lock again.
+async fn stop_runner<'a>(
+    runner: &'a Mutex<ServiceRunner>,
+    mut runner_guard: MutexGuard<'a, ServiceRunner>,
+) -> (MutexGuard<'a, ServiceRunner>, Result<(), RunnerError>) {
+    let Some((pre_stop, post_stop)) = runner_guard.begin_stop().await else {
+        return (runner_guard, Ok(()));
+    };
+    if let Some(pre_stop) = pre_stop {
+        drop(runner_guard);
+        let hook_result = run_hook_unlocked(pre_stop).await;
+        runner_guard = runner.lock().await;
+        if let Err(e) = hook_result {
+            runner_guard.record_stop_error(e);
+        }
+    }
+    let result = runner_guard.stop_without_hooks().await;
+    if let Some(post_stop) = post_stop {
+        drop(runner_guard);
+        let hook_result = run_hook_unlocked(post_stop).await;
+        runner_guard = runner.lock().await;
+        if let Err(e) = hook_result {
+            runner_guard.record_stop_error(e);
+        }
+    }
+    runner_guard.finish_stop();
+    (runner_guard, result)
+}
+
+/// Whether the dependency run by `runner` satisfies `condition`
+fn condition_met(condition: DependencyCondition, runner: &ServiceRunner) -> bool {
+    let state = runner.state();
+    let started = matches!(
+        state,
+        ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+    );
+    match condition {
+        // A oneshot that already finished has started, too
+        DependencyCondition::Started => started || matches!(state, ServiceState::Completed),
+        // A readiness check, when configured, decides instead of liveness
+        DependencyCondition::Healthy => match runner.readiness() {
+            Some(ready) => started && ready,
+            None => matches!(state, ServiceState::Healthy),
+        },
+        DependencyCondition::Completed => matches!(state, ServiceState::Completed),
+        // Not while it waits to be restarted
+        DependencyCondition::Failed => runner.gave_up(),
+    }
+}
+
+/// Run an emergency action's command line, killing it after `timeout`.
+/// Failures are logged so the remaining actions still run.
+async fn run_emergency_command(argv: &[String], env: &HashMap<String, String>, timeout: Duration) {
+    let Some((program, args)) = argv.split_first() else {
+        return;
+    };
+
+    let child = reaper::spawn(watchdog::hide_notify_socket(
+        tokio::process::Command::new(program)
+            .args(args)
+            .envs(env)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true),
+    ));
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to run emergency action '{}': {}", argv.join(" "), e);
+            return;
+        }
+    };
+
+    match time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => {}
+        Ok(Ok(status)) => error!(
+            "Emergency action '{}' exited with {}",
+            argv.join(" "),
+            status
+        ),
+        Ok(Err(e)) => error!("Emergency action '{}' failed: {}", argv.join(" "), e),
+        Err(_) => error!(
+            "Emergency action '{}' timed out after {:?}",
+            argv.join(" "),
+            timeout
+        ),
+    }
+}
+
+fn new_runner(
+    config: &KrillConfig,
+    service_name: &str,
+    hosted: bool,
+    log_tx: &Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+) -> ServiceRunner {
+    let mut runner = ServiceRunner::new(
+        service_name.to_string(),
+        config.name.clone(),
+        config.services[service_name].clone(),
+        config.env.clone(),
+    );
+    if hosted {
+        runner.set_client_name(qualified_name(&config.name, service_name));
+    }
+    if let Some(log_tx) = log_tx {
+        runner.set_log_tx(log_tx.clone());
     }
+    runner
 }
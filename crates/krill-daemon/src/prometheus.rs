@@ -0,0 +1,475 @@
+// Prometheus Exporter - Service state and resource usage in the text exposition format
+
+use crate::fanout::{ClientLag, Fanout};
+use crate::gateway::{read_request, REQUEST_TIMEOUT};
+use crate::heartbeat_limit::{HeartbeatCounters, HeartbeatLimiter};
+use crate::watchdog::Liveness;
+use krill_common::ipc::ServiceSnapshot;
+use krill_common::ServiceStatus;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::{debug, info};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+const STATES: [(ServiceStatus, &str); 10] = [
+    (ServiceStatus::Starting, "starting"),
+    (ServiceStatus::Running, "running"),
+    (ServiceStatus::Healthy, "healthy"),
+    (ServiceStatus::Degraded, "degraded"),
+    (ServiceStatus::Stopping, "stopping"),
+    (ServiceStatus::Stopped, "stopped"),
+    (ServiceStatus::Failed, "failed"),
+    (ServiceStatus::Completed, "completed"),
+    (ServiceStatus::Blocked, "blocked"),
+    (ServiceStatus::Paused, "paused"),
+];
+
+/// Serves `GET /metrics` without authentication, like any Prometheus target,
+/// and the daemon's liveness on `GET /healthz`
+pub struct PrometheusExporter {
+    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    heartbeat_limiter: Option<Arc<HeartbeatLimiter>>,
+    fanout: Option<Arc<Fanout>>,
+    liveness: Option<Arc<Liveness>>,
+}
+
+impl PrometheusExporter {
+    pub fn new(
+        snapshot_req_tx: mpsc::UnboundedSender<
+            mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>,
+        >,
+    ) -> Self {
+        Self {
+            snapshot_req_tx,
+            heartbeat_limiter: None,
+            fanout: None,
+            liveness: None,
+        }
+    }
+
+    /// Also export the heartbeat counters of the given limiter
+    pub fn set_heartbeat_limiter(&mut self, limiter: Arc<HeartbeatLimiter>) {
+        self.heartbeat_limiter = Some(limiter);
+    }
+
+    /// Also export how far behind each IPC client is
+    pub fn set_fanout(&mut self, fanout: Arc<Fanout>) {
+        self.fanout = Some(fanout);
+    }
+
+    /// Serve `GET /healthz` from the daemon's self-heartbeat
+    pub fn set_liveness(&mut self, liveness: Arc<Liveness>) {
+        self.liveness = Some(liveness);
+    }
+
+    /// Bind `addr` and serve scrapes until the task is dropped
+    pub async fn start(self, addr: SocketAddr) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve(listener).await
+    }
+
+    /// Serve scrapes on an already bound listener
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        info!(
+            "Prometheus metrics on http://{}/metrics",
+            listener.local_addr()?
+        );
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let snapshot_req_tx = self.snapshot_req_tx.clone();
+            let exports = Exports {
+                heartbeat_limiter: self.heartbeat_limiter.clone(),
+                fanout: self.fanout.clone(),
+                liveness: self.liveness.clone(),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, snapshot_req_tx, exports).await {
+                    debug!("Metrics connection from {} ended: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// What a scrape reports besides the services
+struct Exports {
+    heartbeat_limiter: Option<Arc<HeartbeatLimiter>>,
+    fanout: Option<Arc<Fanout>>,
+    liveness: Option<Arc<Liveness>>,
+}
+
+async fn handle_connection<S>(
+    stream: S,
+    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    exports: Exports,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let mut content_type = CONTENT_TYPE;
+    let (status, body) = match time::timeout(REQUEST_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(Some(request))) if request.path == "/metrics" => {
+            if request.method == "GET" {
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let services = match snapshot_req_tx.send(tx) {
+                    Ok(()) => rx.recv().await.unwrap_or_default(),
+                    Err(_) => HashMap::new(),
+                };
+                let mut body = render(&services);
+                if let Some(limiter) = exports.heartbeat_limiter {
+                    body.push_str(&render_heartbeats(&limiter.counters()));
+                }
+                if let Some(fanout) = exports.fanout {
+                    body.push_str(&render_clients(&fanout.lag(), fanout.disconnected()));
+                }
+                ("200 OK", body)
+            } else {
+                ("405 Method Not Allowed", "Method not allowed\n".to_string())
+            }
+        }
+        Ok(Ok(Some(request))) if request.path == "/healthz" && exports.liveness.is_some() => {
+            if request.method == "GET" {
+                let liveness = exports.liveness.unwrap();
+                content_type = "application/json";
+                let status = if liveness.is_alive() {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                (status, format!("{}\n", liveness.report()))
+            } else {
+                ("405 Method Not Allowed", "Method not allowed\n".to_string())
+            }
+        }
+        Ok(Ok(Some(_))) => ("404 Not Found", "Not found\n".to_string()),
+        Ok(Ok(None)) => ("400 Bad Request", "Malformed request\n".to_string()),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => ("408 Request Timeout", "Request timed out\n".to_string()),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Render service snapshots in the Prometheus text exposition format
+pub fn render(services: &HashMap<String, ServiceSnapshot>) -> String {
+    let mut names: Vec<&String> = services.keys().collect();
+    names.sort_unstable();
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: Vec<String>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for sample in samples {
+            let _ = writeln!(out, "{}", sample);
+        }
+    };
+
+    let labels = |name: &str, snapshot: &ServiceSnapshot| {
+        format!(
+            "workspace=\"{}\",service=\"{}\"",
+            escape(&snapshot.namespace),
+            escape(name)
+        )
+    };
+    let samples = |metric: &str, value: &dyn Fn(&ServiceSnapshot) -> Option<f64>| {
+        names
+            .iter()
+            .filter_map(|name| {
+                let snapshot = &services[*name];
+                value(snapshot).map(|v| format!("{}{{{}}} {}", metric, labels(name, snapshot), v))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    family(
+        "krill_service_state",
+        "gauge",
+        "Current service state (1 for the active state)",
+        names
+            .iter()
+            .flat_map(|name| {
+                let snapshot = &services[*name];
+                STATES.iter().map(move |(status, state)| {
+                    format!(
+                        "krill_service_state{{{},state=\"{}\"}} {}",
+                        labels(name, snapshot),
+                        state,
+                        u8::from(snapshot.status == *status)
+                    )
+                })
+            })
+            .collect(),
+    );
+    family(
+        "krill_service_restarts_total",
+        "counter",
+        "Restarts since the daemon started, kept across upgrades",
+        samples("krill_service_restarts_total", &|s| {
+            Some(f64::from(s.total_restarts))
+        }),
+    );
+    family(
+        "krill_service_uptime_seconds",
+        "gauge",
+        "Time since the service's process started",
+        samples("krill_service_uptime_seconds", &|s| {
+            s.uptime.map(|d| d.as_secs_f64())
+        }),
+    );
+    family(
+        "krill_service_heartbeat_age_seconds",
+        "gauge",
+        "Time since the service's last heartbeat",
+        samples("krill_service_heartbeat_age_seconds", &|s| {
+            s.heartbeat_age.map(|d| d.as_secs_f64())
+        }),
+    );
+    family(
+        "krill_service_cpu_percent",
+        "gauge",
+        "CPU usage of the service's process group in percent of one core",
+        samples("krill_service_cpu_percent", &|s| {
+            s.metrics.map(|m| f64::from(m.cpu_percent))
+        }),
+    );
+    family(
+        "krill_service_memory_bytes",
+        "gauge",
+        "Resident memory of the service's process group",
+        samples("krill_service_memory_bytes", &|s| {
+            s.metrics.map(|m| m.memory_bytes as f64)
+        }),
+    );
+    family(
+        "krill_service_threads",
+        "gauge",
+        "Threads in the service's process group",
+        samples("krill_service_threads", &|s| {
+            s.metrics.map(|m| f64::from(m.threads))
+        }),
+    );
+
+    out
+}
+
+/// Render heartbeat limiter counters in the Prometheus text exposition format
+pub fn render_heartbeats(counters: &HashMap<String, HeartbeatCounters>) -> String {
+    let mut names: Vec<&String> = counters.keys().collect();
+    names.sort_unstable();
+
+    let mut out = String::new();
+    let mut family = |name: &str, help: &str, value: fn(&HeartbeatCounters) -> u64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for service in &names {
+            let value = value(&counters[*service]);
+            let _ = writeln!(out, "{}{{service=\"{}\"}} {}", name, escape(service), value);
+        }
+    };
+
+    family(
+        "krill_heartbeats_received_total",
+        "Heartbeats received for the service",
+        |c| c.received,
+    );
+    family(
+        "krill_heartbeats_dropped_total",
+        "Heartbeats discarded by the per-service rate limit",
+        |c| c.dropped,
+    );
+
+    out
+}
+
+/// Render the lag of IPC clients in the Prometheus text exposition format
+pub fn render_clients(clients: &HashMap<u64, ClientLag>, disconnected: u64) -> String {
+    let mut ids: Vec<&u64> = clients.keys().collect();
+    ids.sort_unstable();
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, value: fn(&ClientLag) -> u64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for id in &ids {
+            let lag = &clients[*id];
+            let _ = writeln!(
+                out,
+                "{}{{connection=\"{}\",client=\"{}\"}} {}",
+                name,
+                id,
+                escape(&lag.client),
+                value(lag)
+            );
+        }
+    };
+
+    family(
+        "krill_ipc_client_queued_messages",
+        "gauge",
+        "Messages queued for the IPC client and not yet written",
+        |lag| lag.queued as u64,
+    );
+    family(
+        "krill_ipc_client_max_queued_messages",
+        "gauge",
+        "Most messages queued for the IPC client at once",
+        |lag| lag.max_queued as u64,
+    );
+    family(
+        "krill_ipc_client_dropped_total",
+        "counter",
+        "Log lines and metrics updates the IPC client missed by falling behind",
+        |lag| lag.dropped,
+    );
+
+    let name = "krill_ipc_slow_clients_disconnected_total";
+    let _ = writeln!(
+        out,
+        "# HELP {} IPC clients disconnected for falling too far behind",
+        name
+    );
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, disconnected);
+
+    out
+}
+
+/// Escape a label value per the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use krill_common::{RestartPolicy, ServiceConfigSummary, ServiceMetrics};
+    use std::time::Duration;
+
+    fn snapshot(status: ServiceStatus) -> ServiceSnapshot {
+        ServiceSnapshot {
+            status,
+            pid: Some(42),
+            uid: "abc1234".to_string(),
+            uptime: Some(Duration::from_secs(90)),
+            restart_count: 0,
+            last_error: None,
+            namespace: "robot".to_string(),
+            config: ServiceConfigSummary {
+                executor: "shell".to_string(),
+                restart_policy: RestartPolicy::Always,
+                ..Default::default()
+            },
+            protected: false,
+            legacy_config: Default::default(),
+            labels: Default::default(),
+            safety_stopped: false,
+            restarts_paused: false,
+            metrics: Some(ServiceMetrics {
+                cpu_percent: 12.5,
+                memory_bytes: 1024,
+                threads: 3,
+            }),
+            heartbeat_metadata: HashMap::new(),
+            heartbeat_age: None,
+            missed_heartbeats: 0,
+            total_restarts: 5,
+            last_exit_code: None,
+            state_history: vec![],
+            state_age: None,
+            availability: Default::default(),
+            maintenance: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let services = HashMap::from([("lidar".to_string(), snapshot(ServiceStatus::Healthy))]);
+        let text = render(&services);
+
+        assert!(text.contains("# TYPE krill_service_restarts_total counter"));
+        assert!(text.contains(
+            r#"krill_service_state{workspace="robot",service="lidar",state="healthy"} 1"#
+        ));
+        assert!(text.contains(
+            r#"krill_service_state{workspace="robot",service="lidar",state="failed"} 0"#
+        ));
+        // The counter never goes down, although the restart count that the
+        // restart limit uses settled back to zero
+        assert!(
+            text.contains(r#"krill_service_restarts_total{workspace="robot",service="lidar"} 5"#)
+        );
+        assert!(
+            text.contains(r#"krill_service_uptime_seconds{workspace="robot",service="lidar"} 90"#)
+        );
+        assert!(
+            text.contains(r#"krill_service_cpu_percent{workspace="robot",service="lidar"} 12.5"#)
+        );
+        assert!(!text.contains("krill_service_heartbeat_age_seconds{"));
+    }
+
+    #[test]
+    fn test_render_heartbeats() {
+        let counters = HashMap::from([(
+            "lidar".to_string(),
+            HeartbeatCounters {
+                received: 40,
+                dropped: 15,
+            },
+        )]);
+        let text = render_heartbeats(&counters);
+
+        assert!(text.contains("# TYPE krill_heartbeats_dropped_total counter"));
+        assert!(text.contains(r#"krill_heartbeats_received_total{service="lidar"} 40"#));
+        assert!(text.contains(r#"krill_heartbeats_dropped_total{service="lidar"} 15"#));
+    }
+
+    #[test]
+    fn test_render_clients() {
+        let clients = HashMap::from([(
+            3,
+            ClientLag {
+                client: "pid=42 client=tui".to_string(),
+                queued: 5,
+                max_queued: 80,
+                dropped: 12,
+            },
+        )]);
+        let text = render_clients(&clients, 1);
+
+        assert!(text.contains("# TYPE krill_ipc_client_dropped_total counter"));
+        assert!(text.contains(
+            r#"krill_ipc_client_queued_messages{connection="3",client="pid=42 client=tui"} 5"#
+        ));
+        assert!(text.contains(
+            r#"krill_ipc_client_dropped_total{connection="3",client="pid=42 client=tui"} 12"#
+        ));
+        assert!(text.contains("krill_ipc_slow_clients_disconnected_total 1"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -0,0 +1,550 @@
+// Reaper - Find and end the processes a service leaves behind when they
+// escape its process group (double forks, setsid)
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Where processes are looked up
+pub const PROC_ROOT: &str = "/proc";
+
+/// Environment variable holding a unique ID of the service's current run.
+/// Everything a service spawns inherits it, so its leftovers are found
+/// after they leave the process group and their parent exits.
+pub const MARKER_VAR: &str = "KRILL_SERVICE_UID";
+
+/// Time leftovers get to exit after SIGTERM before they are killed
+pub const GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Time a killed leftover gets to turn into a zombie before it is reaped
+const KILL_WAIT: Duration = Duration::from_secs(1);
+
+/// Time between two looks at the daemon's zombie children. An orphan is
+/// reaped once it was a zombie in both, which leaves a synchronous
+/// `wait` on a child spawned through `std::process` the time to run.
+const ORPHAN_WAIT: Duration = Duration::from_secs(1);
+
+/// Children the daemon spawned through [`spawn`], whose exit status
+/// belongs to the code waiting for them
+static OWN_CHILDREN: Mutex<Vec<Descendant>> = Mutex::new(Vec::new());
+
+/// Terminations of leftovers still under way, see [`end`]
+static TERMINATIONS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// A process spawned under a service, other than its main process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descendant {
+    pub pid: u32,
+    /// Start time in clock ticks since boot, which tells a reused PID apart
+    pub start_ticks: u64,
+}
+
+/// Make the daemon the subreaper of its services, so processes orphaned
+/// inside a service are reparented to the daemon rather than to init and
+/// can still be traced to the service. Linux only.
+pub fn enable_subreaper() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        match nix::sys::prctl::set_child_subreaper(true) {
+            Ok(()) => {
+                debug!("Daemon is the subreaper of its services");
+                true
+            }
+            Err(e) => {
+                warn!("Failed to become a child subreaper: {}", e);
+                false
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    false
+}
+
+/// Spawn `command` as a child the orphan reaper leaves to its caller.
+/// Every process the daemon waits for through tokio is spawned here.
+pub fn spawn(command: &mut tokio::process::Command) -> std::io::Result<tokio::process::Child> {
+    // Held until the child is registered, so a reaper pass cannot take it
+    // for an orphan in between
+    let mut own = OWN_CHILDREN.lock().unwrap_or_else(PoisonError::into_inner);
+    let child = command.spawn()?;
+    if let Some(entry) = child
+        .id()
+        .and_then(|pid| read_stat(Path::new(PROC_ROOT), pid))
+    {
+        own.push(Descendant {
+            pid: entry.pid,
+            start_ticks: entry.start_ticks,
+        });
+    }
+    Ok(child)
+}
+
+/// Run `command` through [`spawn`] and collect its output; unlike
+/// `Command::output` the caller sets up stdout and stderr
+pub async fn output(
+    command: &mut tokio::process::Command,
+) -> std::io::Result<std::process::Output> {
+    spawn(command)?.wait_with_output().await
+}
+
+/// Run `command` through [`spawn`] and wait for it to exit
+pub async fn status(
+    command: &mut tokio::process::Command,
+) -> std::io::Result<std::process::ExitStatus> {
+    spawn(command)?.wait().await
+}
+
+/// Reap the orphans the daemon adopted as subreaper whenever a child
+/// exits, so those no service tracks do not linger as zombies. Children
+/// the daemon spawned itself are left to the code waiting for them.
+#[cfg(target_os = "linux")]
+pub async fn reap_orphans() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigchld = match signal(SignalKind::child()) {
+        Ok(sigchld) => sigchld,
+        Err(e) => {
+            warn!("Failed to watch for exited children: {}", e);
+            return;
+        }
+    };
+
+    let mut seen = Vec::new();
+    loop {
+        if seen.is_empty() {
+            if sigchld.recv().await.is_none() {
+                return;
+            }
+        } else {
+            tokio::time::sleep(ORPHAN_WAIT).await;
+        }
+        let previous = std::mem::take(&mut seen);
+        seen = tokio::task::spawn_blocking(move || reap_orphan_zombies(&previous))
+            .await
+            .unwrap_or_default();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn reap_orphans() {}
+
+/// Reap the zombie children of the daemon that it did not spawn itself
+/// and that were zombies already in the `previous` pass. Returns the
+/// zombies seen for the first time, for the next pass.
+#[cfg(target_os = "linux")]
+fn reap_orphan_zombies(previous: &[Descendant]) -> Vec<Descendant> {
+    use nix::sys::wait::{waitpid, WaitPidFlag};
+    use nix::unistd::Pid;
+
+    let mut own = OWN_CHILDREN.lock().unwrap_or_else(PoisonError::into_inner);
+    let children: Vec<ProcEntry> = scan(Path::new(PROC_ROOT))
+        .into_iter()
+        .filter(|entry| entry.ppid == std::process::id())
+        .collect();
+    let (reap, seen) = orphan_zombies(&children, &mut own, previous);
+    for process in reap {
+        debug!("Reaping orphaned PID {}", process.pid);
+        let _ = waitpid(
+            Pid::from_raw(process.pid as i32),
+            Some(WaitPidFlag::WNOHANG),
+        );
+    }
+    seen
+}
+
+/// Split the zombies among `children` the daemon did not spawn (`own`)
+/// into those to reap, which were zombies in the `previous` pass too, and
+/// those seen for the first time. Processes gone from `children` are
+/// dropped from `own`.
+fn orphan_zombies(
+    children: &[ProcEntry],
+    own: &mut Vec<Descendant>,
+    previous: &[Descendant],
+) -> (Vec<Descendant>, Vec<Descendant>) {
+    let matches = |entry: &ProcEntry, process: &Descendant| {
+        entry.pid == process.pid && entry.start_ticks == process.start_ticks
+    };
+    own.retain(|process| children.iter().any(|entry| matches(entry, process)));
+
+    children
+        .iter()
+        .filter(|entry| entry.zombie && !own.iter().any(|process| matches(entry, process)))
+        .map(|entry| Descendant {
+            pid: entry.pid,
+            start_ticks: entry.start_ticks,
+        })
+        .partition(|zombie| previous.contains(zombie))
+}
+
+/// The fields of `/proc/<pid>/stat` the reaper needs
+#[derive(Debug, Clone, Copy)]
+struct ProcEntry {
+    pid: u32,
+    ppid: u32,
+    pgrp: u32,
+    start_ticks: u64,
+    zombie: bool,
+}
+
+fn read_stat(proc_root: &Path, pid: u32) -> Option<ProcEntry> {
+    let content = fs::read_to_string(proc_root.join(pid.to_string()).join("stat")).ok()?;
+    let fields: Vec<&str> = content[content.rfind(')')? + 1..]
+        .split_whitespace()
+        .collect();
+
+    Some(ProcEntry {
+        pid,
+        ppid: fields.get(1)?.parse().ok()?,
+        pgrp: fields.get(2)?.parse().ok()?,
+        start_ticks: fields.get(22 - 3)?.parse().ok()?,
+        zombie: fields.first() == Some(&"Z"),
+    })
+}
+
+fn scan(proc_root: &Path) -> Vec<ProcEntry> {
+    let Ok(entries) = fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter_map(|pid| read_stat(proc_root, pid))
+        .collect()
+}
+
+/// Whether the process's environment carries the marker of runner `uid`
+fn has_marker(proc_root: &Path, pid: u32, uid: &str) -> bool {
+    let Ok(environ) = fs::read(proc_root.join(pid.to_string()).join("environ")) else {
+        return false;
+    };
+    let marker = format!("{}={}", MARKER_VAR, uid);
+    environ
+        .split(|byte| *byte == 0)
+        .any(|var| var == marker.as_bytes())
+}
+
+/// Processes spawned under the service whose main process is `root` and
+/// whose runner is `uid`, other than `root` itself: children of `root`,
+/// processes seen before (`known`) and, with `search_environ`, every
+/// process carrying the runner's marker, along with their own children.
+/// Without `search_environ` only orphans the daemon adopted as subreaper
+/// are checked for the marker, which keeps a periodic scan cheap.
+pub fn descendants(
+    proc_root: &Path,
+    root: Option<u32>,
+    uid: &str,
+    known: &[Descendant],
+    search_environ: bool,
+) -> Vec<Descendant> {
+    let daemon = std::process::id();
+    let entries = scan(proc_root);
+
+    let mut found = HashSet::new();
+    for entry in &entries {
+        if Some(entry.pid) == root || entry.pid == daemon {
+            continue;
+        }
+        let adopted = entry.ppid == daemon;
+        let claimed = known
+            .iter()
+            .any(|k| k.pid == entry.pid && k.start_ticks == entry.start_ticks)
+            // An adopted orphan still in the service's group, even a zombie
+            || (adopted && root.is_some_and(|root| entry.pgrp == root))
+            || ((search_environ || adopted) && has_marker(proc_root, entry.pid, uid));
+        if claimed {
+            found.insert(entry.pid);
+        }
+    }
+
+    let mut parents: Vec<u32> = root.into_iter().chain(found.iter().copied()).collect();
+    while let Some(parent) = parents.pop() {
+        for entry in entries.iter().filter(|e| e.ppid == parent) {
+            if entry.pid != daemon && Some(entry.pid) != root && found.insert(entry.pid) {
+                parents.push(entry.pid);
+            }
+        }
+    }
+
+    entries
+        .iter()
+        .filter(|entry| found.contains(&entry.pid))
+        .map(|entry| Descendant {
+            pid: entry.pid,
+            start_ticks: entry.start_ticks,
+        })
+        .collect()
+}
+
+/// What the periodic scan for a service's descendants needs, taken from
+/// its runner so `/proc` is walked without holding it
+#[derive(Debug, Clone)]
+pub struct Tracking {
+    /// The service's main process
+    pub root: Option<u32>,
+    /// Marker of the service's current run
+    pub marker: String,
+    pub known: Vec<Descendant>,
+}
+
+impl Tracking {
+    /// The processes running under the service now, see [`descendants`]
+    pub fn scan(&self) -> Vec<Descendant> {
+        descendants(
+            Path::new(PROC_ROOT),
+            self.root,
+            &self.marker,
+            &self.known,
+            false,
+        )
+    }
+}
+
+/// The processes a service's exited main process may have left behind
+#[derive(Debug, Clone)]
+pub struct Leftovers {
+    pub service: String,
+    /// The main process, whose PID may already be reused, so it is no root
+    pub main_pid: Option<u32>,
+    /// Marker of the run that left them
+    pub marker: String,
+    pub known: Vec<Descendant>,
+    /// Time they get to exit after SIGTERM, see [`terminate`]
+    pub grace: Duration,
+}
+
+impl Leftovers {
+    /// Find the leftovers and end them
+    pub async fn terminate(self) {
+        let proc_root = Path::new(PROC_ROOT);
+        let mut leftovers = descendants(proc_root, None, &self.marker, &self.known, true);
+        leftovers.retain(|process| Some(process.pid) != self.main_pid);
+        terminate(proc_root, &self.service, &leftovers, self.grace).await;
+    }
+}
+
+/// End `leftovers` in the background, so their runner is not held through
+/// the grace period. A new run of the service carries another marker and
+/// is never taken for them.
+pub fn terminate_later(leftovers: Leftovers) {
+    let mut terminations = TERMINATIONS.lock().unwrap_or_else(PoisonError::into_inner);
+    terminations.retain(|termination| !termination.is_finished());
+    terminations.push(tokio::spawn(leftovers.terminate()));
+}
+
+/// Wait until the leftovers handed to [`terminate_later`] are ended
+pub async fn settle() {
+    loop {
+        let terminations =
+            std::mem::take(&mut *TERMINATIONS.lock().unwrap_or_else(PoisonError::into_inner));
+        if terminations.is_empty() {
+            return;
+        }
+        futures::future::join_all(terminations).await;
+    }
+}
+
+/// Whether the process still runs and is the one that was found
+fn is_alive(proc_root: &Path, process: &Descendant) -> bool {
+    read_stat(proc_root, process.pid)
+        .is_some_and(|entry| !entry.zombie && entry.start_ticks == process.start_ticks)
+}
+
+/// End the leftovers of `service`: SIGTERM, then SIGKILL for those still
+/// running after `grace` (right away for a zero `grace`). Those that were
+/// children of the daemon are reaped.
+#[cfg(unix)]
+pub async fn terminate(proc_root: &Path, service: &str, leftovers: &[Descendant], grace: Duration) {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let running = || {
+        leftovers
+            .iter()
+            .filter(|process| is_alive(proc_root, process))
+            .collect::<Vec<_>>()
+    };
+    let signal = |signal: Signal| {
+        for process in running() {
+            debug!("Sending {} to leftover PID {}", signal, process.pid);
+            let _ = kill(Pid::from_raw(process.pid as i32), signal);
+        }
+    };
+    let wait = |timeout: Duration| async move {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while !running().is_empty() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+
+    let count = running().len();
+    if count > 0 {
+        info!(
+            "Terminating {} process(es) left behind by service '{}'",
+            count, service
+        );
+        if !grace.is_zero() {
+            signal(Signal::SIGTERM);
+            wait(grace).await;
+        }
+        if !running().is_empty() {
+            if !grace.is_zero() {
+                warn!(
+                    "Processes left behind by service '{}' ignored SIGTERM, killing them",
+                    service
+                );
+            }
+            signal(Signal::SIGKILL);
+            wait(KILL_WAIT).await;
+        }
+    }
+    reap(proc_root, leftovers);
+}
+
+#[cfg(not(unix))]
+pub async fn terminate(
+    _proc_root: &Path,
+    _service: &str,
+    _leftovers: &[Descendant],
+    _grace: Duration,
+) {
+}
+
+/// Collect the exit status of the processes that died as children of the
+/// daemon, so they do not linger as zombies. Only the given processes are
+/// waited for, never a process the daemon spawned itself.
+pub fn reap(proc_root: &Path, processes: &[Descendant]) {
+    #[cfg(unix)]
+    {
+        use nix::sys::wait::{waitpid, WaitPidFlag};
+        use nix::unistd::Pid;
+
+        let daemon = std::process::id();
+        for process in processes {
+            let zombie_child = read_stat(proc_root, process.pid).is_some_and(|entry| {
+                entry.zombie && entry.ppid == daemon && entry.start_ticks == process.start_ticks
+            });
+            if zombie_child {
+                debug!("Reaping leftover PID {}", process.pid);
+                let _ = waitpid(
+                    Pid::from_raw(process.pid as i32),
+                    Some(WaitPidFlag::WNOHANG),
+                );
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (proc_root, processes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_proc(root: &TempDir, pid: u32, ppid: u32, pgrp: u32, ticks: u64, env: &[&str]) {
+        let dir = root.path().join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("stat"),
+            format!(
+                "{pid} (proc) S {ppid} {pgrp} {pgrp} 0 -1 4194560 100 0 0 0 0 10 0 0 20 0 1 0 {ticks} 123456 10 18446744073709551615\n"
+            ),
+        )
+        .unwrap();
+        let mut environ = env.join("\0").into_bytes();
+        environ.push(0);
+        fs::write(dir.join("environ"), environ).unwrap();
+    }
+
+    fn pids(found: &[Descendant]) -> Vec<u32> {
+        let mut pids: Vec<u32> = found.iter().map(|d| d.pid).collect();
+        pids.sort();
+        pids
+    }
+
+    #[test]
+    fn test_descendants_follow_tree_and_marker() {
+        let root = TempDir::new().unwrap();
+        let marker = "KRILL_SERVICE_UID=abc1234";
+        // Service 100 with a child, a grandchild and a double-forked
+        // process that moved to its own session under init
+        write_proc(&root, 100, 1, 100, 500, &[marker]);
+        write_proc(&root, 101, 100, 100, 510, &[marker]);
+        write_proc(&root, 102, 101, 100, 520, &[]);
+        write_proc(&root, 200, 1, 200, 530, &["PATH=/bin", marker]);
+        write_proc(&root, 201, 200, 200, 540, &[]);
+        // Another runner's service and an unrelated process
+        write_proc(&root, 300, 1, 300, 550, &["KRILL_SERVICE_UID=other"]);
+        write_proc(&root, 400, 1, 400, 560, &[]);
+
+        let tree = descendants(root.path(), Some(100), "abc1234", &[], false);
+        assert_eq!(pids(&tree), vec![101, 102]);
+
+        let all = descendants(root.path(), Some(100), "abc1234", &[], true);
+        assert_eq!(pids(&all), vec![101, 102, 200, 201]);
+
+        // Once the service is gone, known processes are still found
+        fs::remove_dir_all(root.path().join("100")).unwrap();
+        let known = descendants(root.path(), None, "none", &tree, false);
+        assert_eq!(pids(&known), vec![101, 102]);
+    }
+
+    #[test]
+    fn test_orphan_zombies_exclude_own_children() {
+        let child = |pid: u32, start_ticks: u64, zombie: bool| ProcEntry {
+            pid,
+            ppid: std::process::id(),
+            pgrp: pid,
+            start_ticks,
+            zombie,
+        };
+        let orphan = Descendant {
+            pid: 20,
+            start_ticks: 200,
+        };
+        let own_zombie = Descendant {
+            pid: 10,
+            start_ticks: 100,
+        };
+        // A child the daemon spawned that exited and was reaped since
+        let gone = Descendant {
+            pid: 30,
+            start_ticks: 300,
+        };
+        let children = [
+            child(10, 100, true),
+            child(20, 200, true),
+            child(40, 400, false),
+        ];
+        let mut own = vec![own_zombie, gone];
+
+        // An orphan is reaped once it was a zombie in two passes
+        let (reap, seen) = orphan_zombies(&children, &mut own, &[]);
+        assert!(reap.is_empty());
+        assert_eq!(seen, vec![orphan]);
+        assert_eq!(own, vec![own_zombie]);
+
+        let (reap, seen) = orphan_zombies(&children, &mut own, &seen);
+        assert_eq!(reap, vec![orphan]);
+        assert!(seen.is_empty());
+    }
+
+    #[test]
+    fn test_reused_pid_is_not_known() {
+        let root = TempDir::new().unwrap();
+        write_proc(&root, 101, 1, 101, 900, &[]);
+
+        let known = [Descendant {
+            pid: 101,
+            start_ticks: 510,
+        }];
+        assert!(descendants(root.path(), None, "abc1234", &known, true).is_empty());
+        assert!(!is_alive(root.path(), &known[0]));
+    }
+}
@@ -1,24 +1,126 @@
 // Service Runner - Manages individual service lifecycle
 
+use crate::handoff::HandedService;
+use crate::limits::{self, Cgroup};
+use crate::reaper::{self, Descendant};
+use crate::state::{self, ServiceRecord};
+use crate::watchdog;
 use krill_common::{
-    build_command, generate_process_name, get_stop_command, get_working_dir, HealthChecker,
-    ServiceConfig, ServiceStatus,
+    build_command, build_container_command, compose_down_command, container_name,
+    container_pause_command, generate_process_name, get_stop_command, get_working_dir,
+    shell_command, AvailabilityStats, ContainerEngine, ExecuteConfig, HealthChecker,
+    HealthDebounce, Hook, KrillError, LifecycleState, LogStream, PortConflict, ProcessOutputLine,
+    SecretError, SecretSource, ServiceConfig, ServiceHooks, ServiceMetrics, ServiceStatus,
+    StateTransition, StopAction, StopMethod,
 };
+#[cfg(unix)]
 use nix::sys::signal::{self, Signal};
+#[cfg(unix)]
 use nix::unistd::Pid;
-use std::collections::HashMap;
-use std::process::Stdio;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::process::{Child, ChildStderr, ChildStdout, Command};
+use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// Extra time given to `docker stop` on top of the container's stop timeout
+const CONTAINER_STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// Timeout for `docker rm`
+const CONTAINER_REMOVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Status changes kept per service for its snapshot's `state_history`
+pub const STATE_HISTORY_LEN: usize = 20;
+
+/// Outcome of waiting for a process with a timeout
+type WaitResult = Result<std::io::Result<Option<ExitStatus>>, tokio::time::error::Elapsed>;
+
+/// Signal sent by a signal step of a stop sequence
+#[cfg(unix)]
+fn stop_signal(action: StopAction) -> Signal {
+    match action {
+        StopAction::Sigint => Signal::SIGINT,
+        StopAction::Sighup => Signal::SIGHUP,
+        StopAction::Sigterm | StopAction::StopCommand => Signal::SIGTERM,
+    }
+}
+
+/// Count `elapsed` in `status` as uptime or downtime, if it is either
+fn add_availability(stats: &mut AvailabilityStats, status: &ServiceStatus, elapsed: Duration) {
+    match status {
+        ServiceStatus::Running | ServiceStatus::Healthy | ServiceStatus::Degraded => {
+            stats.uptime += elapsed
+        }
+        ServiceStatus::Starting | ServiceStatus::Failed | ServiceStatus::Blocked => {
+            stats.downtime += elapsed
+        }
+        ServiceStatus::Stopping
+        | ServiceStatus::Stopped
+        | ServiceStatus::Completed
+        | ServiceStatus::Paused => {}
+    }
+}
+
+/// Content of the files a service reads its environment from, hashed, so a
+/// reload can tell an `env_file` or a file secret changed on disk although
+/// the recipe did not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputHashes {
+    env_file: u64,
+    secrets: u64,
+}
+
+impl InputHashes {
+    /// Hash the files `config` names as they are now; a missing file hashes
+    /// differently from an empty one
+    pub fn of(config: &ServiceConfig) -> Self {
+        let hash = |files: &mut dyn Iterator<Item = &PathBuf>| {
+            let mut hasher = DefaultHasher::new();
+            for file in files {
+                std::fs::read(file).ok().hash(&mut hasher);
+            }
+            hasher.finish()
+        };
+        Self {
+            env_file: hash(&mut config.env_file.iter()),
+            secrets: hash(
+                &mut config.secrets.values().filter_map(|source| match source {
+                    SecretSource::File(path) => Some(path),
+                    SecretSource::Command(_) => None,
+                }),
+            ),
+        }
+    }
+
+    /// Settings whose files differ between `self` and `now`
+    pub fn changed(&self, now: &InputHashes) -> Vec<String> {
+        let mut changed = Vec::new();
+        if self.env_file != now.env_file {
+            changed.push("env_file".to_string());
+        }
+        if self.secrets != now.secrets {
+            changed.push("secrets".to_string());
+        }
+        changed
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum RunnerError {
     #[error("Failed to spawn process: {0}")]
     SpawnFailed(String),
 
+    #[error("Permission denied spawning process: {0}")]
+    PermissionDenied(String),
+
     #[error("Process not running")]
     ProcessNotRunning,
 
@@ -33,6 +135,50 @@ pub enum RunnerError {
 
     #[error("Restart limit exceeded")]
     RestartLimitExceeded,
+
+    #[error("{hook} hook failed: {reason}")]
+    HookFailed { hook: Hook, reason: String },
+
+    #[error("Signals are not supported on this platform")]
+    SignalsUnsupported,
+
+    #[error("Required {0}")]
+    PortInUse(PortConflict),
+
+    #[error("Service is not paused")]
+    NotPaused,
+
+    #[error("Failed to {action} service: {reason}")]
+    PauseFailed {
+        action: &'static str,
+        reason: String,
+    },
+}
+
+impl From<RunnerError> for KrillError {
+    fn from(err: RunnerError) -> Self {
+        match err {
+            RunnerError::SpawnFailed(reason) => KrillError::SpawnFailed(reason),
+            RunnerError::PermissionDenied(reason) => KrillError::PermissionDenied(reason),
+            RunnerError::SignalsUnsupported => KrillError::Unsupported("Signals".to_string()),
+            RunnerError::PortInUse(conflict) => {
+                KrillError::SpawnFailed(format!("required {}", conflict))
+            }
+            RunnerError::GpuNotAvailable(reason) => KrillError::SpawnFailed(reason),
+            other => KrillError::Internal(other.to_string()),
+        }
+    }
+}
+
+/// How the last stop ended the service's process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopOutcome {
+    pub method: StopMethod,
+    pub exit_code: Option<i32>,
+    /// Signal that ended the process, on Unix
+    pub signal: Option<i32>,
+    /// Failed hooks and stop commands along the way
+    pub errors: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -45,6 +191,75 @@ pub enum ServiceState {
     Stopping,
     Stopped,
     Failed,
+    /// A oneshot service exited successfully
+    Completed,
+    /// Gave up waiting for its dependencies
+    Blocked,
+    /// Frozen by a pause command; health checks are suspended
+    Paused,
+}
+
+/// A service's hook command with everything it needs to run, so that it
+/// can run after the runner's lock is released
+pub struct PreparedHook {
+    hook: Hook,
+    service_name: String,
+    argv: Vec<String>,
+    env: HashMap<String, String>,
+    work_dir: Option<PathBuf>,
+    timeout: Duration,
+    log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+}
+
+impl PreparedHook {
+    pub fn hook(&self) -> Hook {
+        self.hook
+    }
+
+    /// Run the hook, logging its output as the service's
+    pub async fn run(self) -> Result<(), RunnerError> {
+        let hook = self.hook;
+        let failed = |reason: String| RunnerError::HookFailed { hook, reason };
+
+        info!("Running {} hook of '{}'", hook, self.service_name);
+        let mut cmd = Command::new(&self.argv[0]);
+        cmd.args(&self.argv[1..])
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(ref work_dir) = self.work_dir {
+            cmd.current_dir(work_dir);
+        }
+
+        let child = reaper::spawn(watchdog::hide_notify_socket(&mut cmd))
+            .map_err(|e| failed(e.to_string()))?;
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| failed(format!("timed out after {:?}", self.timeout)))?
+            .map_err(|e| failed(e.to_string()))?;
+
+        for (bytes, stream) in [
+            (&output.stdout, LogStream::Stdout),
+            (&output.stderr, LogStream::Stderr),
+        ] {
+            for line in String::from_utf8_lossy(bytes).lines() {
+                debug!("[{}] {}: {}", self.service_name, hook, line);
+                if let Some(ref tx) = self.log_tx {
+                    let line = format!("[{}] {}", hook, line);
+                    let _ = tx.send(
+                        ProcessOutputLine::new(self.service_name.clone(), line).with_stream(stream),
+                    );
+                }
+            }
+        }
+
+        if !output.status.success() {
+            return Err(failed(format!("exited with {}", output.status)));
+        }
+        Ok(())
+    }
 }
 
 pub struct ServiceRunner {
@@ -55,14 +270,90 @@ pub struct ServiceRunner {
     process: Option<Child>,
     pid: Option<u32>,
     pgid: Option<u32>,
+    /// Start time of the process in clock ticks since boot, where procfs is available
+    start_ticks: Option<u64>,
+    /// The process was left behind by a previous daemon and is not our child
+    adopted: bool,
     uid: String,
+    /// Value of the reaper's marker variable in the current run, which
+    /// tells its processes from those a previous run left behind
+    marker: String,
     restart_count: u32,
+    /// Restarts since the daemon started (or the one it took over from),
+    /// never reset
+    total_restarts: u32,
+    /// Whether a process was started before, so the next start is a restart
+    started: bool,
+    /// Exit code of the last process that exited with one
+    last_exit_code: Option<i32>,
+    /// How the last `stop` ended the process, if it ended one
+    last_stop: Option<StopOutcome>,
+    /// Errors of the stop in progress, for its outcome
+    stop_errors: Vec<String>,
+    /// Most recent status changes, oldest first
+    history: VecDeque<StateTransition>,
+    /// When the service entered its current status
+    status_since: Instant,
+    /// Uptime, downtime and failures of the statuses left so far
+    availability: AvailabilityStats,
+    /// A restart after the last failure is pending
+    restart_scheduled: bool,
+    /// State to return to when the paused service is resumed
+    paused_from: Option<ServiceState>,
+    /// Time spent paused since the last start, until the current pause
+    paused_for: Duration,
     start_time: Option<Instant>,
     last_healthy_time: Option<Instant>,
     last_error: Option<String>,
-    #[allow(dead_code)] // Reserved for future health check implementation
     health_checker: Option<HealthChecker>,
+    /// Whether the readiness probe has passed since the last start
+    ready: bool,
+    /// Last reported state of the node, for lifecycle health checks
+    lifecycle: Option<LifecycleState>,
+    /// cgroup enforcing the service's `limits`, where cgroup v2 is delegated
+    cgroup: Option<Cgroup>,
+    /// Processes seen under the service, which outlive it if they escape
+    /// its process group
+    descendants: Vec<Descendant>,
+    /// Why the service currently exceeds its `limits`
+    limit_violation: Option<String>,
+    /// Limit samples in a row that disagree with `limit_violation`
+    limit_streak: u32,
+    /// What the GPU probe found wrong, for `gpu_health` services
+    gpu_problem: Option<String>,
+    /// Why the last health check failed, cleared when one passes
+    health_failure: Option<String>,
+    /// Health verdicts in a row that disagree with the current health
+    health_streak: u32,
+    /// When the health changed between healthy and degraded, within the
+    /// flapping window
+    health_changes: VecDeque<Instant>,
+    /// Why the service is held degraded for changing its health too often
+    flapping: Option<String>,
+    /// Metadata of the last heartbeat since the last start
+    heartbeat_metadata: HashMap<String, String>,
+    /// When the last heartbeat since the last start arrived
+    last_heartbeat: Option<Instant>,
     env_vars: HashMap<String, String>,
+    /// Values of the `secrets` read for the current run, which its hooks
+    /// reuse
+    secrets: Option<HashMap<String, String>>,
+    /// Files the current run read its environment from, as they were then
+    inputs: Option<InputHashes>,
+    /// Name the service's SDK client reports in heartbeats, when it differs
+    /// from the service name
+    client_name: Option<String>,
+    /// Where the output of hooks goes, next to the service's own output
+    log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+    /// Copies of the read ends of the output pipes, for handing the process
+    /// over to an upgraded daemon
+    #[cfg(unix)]
+    stdout_fd: Option<OwnedFd>,
+    #[cfg(unix)]
+    stderr_fd: Option<OwnedFd>,
+    /// Job object holding the process tree (Windows stand-in for the process group)
+    #[cfg(windows)]
+    job: Option<krill_common::JobObject>,
 }
 
 impl ServiceRunner {
@@ -85,13 +376,50 @@ impl ServiceRunner {
             process: None,
             pid: None,
             pgid: None,
+            start_ticks: None,
+            adopted: false,
             uid,
+            marker: Uuid::new_v4().simple().to_string(),
             restart_count: 0,
+            total_restarts: 0,
+            started: false,
+            last_exit_code: None,
+            last_stop: None,
+            stop_errors: Vec::new(),
+            history: VecDeque::new(),
+            status_since: Instant::now(),
+            availability: AvailabilityStats::default(),
+            restart_scheduled: false,
+            paused_from: None,
+            paused_for: Duration::ZERO,
             start_time: None,
             last_healthy_time: None,
             last_error: None,
             health_checker,
+            ready: false,
+            lifecycle: None,
+            cgroup: None,
+            descendants: Vec::new(),
+            limit_violation: None,
+            limit_streak: 0,
+            gpu_problem: None,
+            health_failure: None,
+            health_streak: 0,
+            health_changes: VecDeque::new(),
+            flapping: None,
+            heartbeat_metadata: HashMap::new(),
+            last_heartbeat: None,
             env_vars,
+            secrets: None,
+            inputs: None,
+            client_name: None,
+            log_tx: None,
+            #[cfg(unix)]
+            stdout_fd: None,
+            #[cfg(unix)]
+            stderr_fd: None,
+            #[cfg(windows)]
+            job: None,
         }
     }
 
@@ -99,41 +427,240 @@ impl ServiceRunner {
         self.state.clone()
     }
 
+    fn set_state(&mut self, state: ServiceState) {
+        self.state = state;
+        self.record_status();
+    }
+
+    /// Add the current status to the history if it changed
+    fn record_status(&mut self) {
+        let status = self.get_status();
+        if self.history.back().map(|t| &t.status) == Some(&status) {
+            return;
+        }
+        if let Some(previous) = self.history.back() {
+            let elapsed = self.status_since.elapsed();
+            add_availability(&mut self.availability, &previous.status, elapsed);
+        }
+        if status == ServiceStatus::Failed {
+            self.availability.failures += 1;
+        }
+        if self.history.len() == STATE_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(StateTransition {
+            timestamp: chrono::Utc::now(),
+            status,
+        });
+        self.status_since = Instant::now();
+    }
+
+    /// Most recent status changes, oldest first
+    pub fn state_history(&self) -> Vec<StateTransition> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// Time since the service entered its current status
+    pub fn state_age(&self) -> Duration {
+        self.status_since.elapsed()
+    }
+
+    /// Uptime, downtime and failures so far, including the current status
+    pub fn availability(&self) -> AvailabilityStats {
+        let mut availability = self.availability.clone();
+        if let Some(current) = self.history.back() {
+            add_availability(&mut availability, &current.status, self.state_age());
+        }
+        if availability.failures > 0 {
+            availability.mtbf = Some(availability.uptime / availability.failures);
+        }
+        availability
+    }
+
     pub fn pid(&self) -> Option<u32> {
         self.pid
     }
 
+    pub fn pgid(&self) -> Option<u32> {
+        self.pgid
+    }
+
+    pub fn start_ticks(&self) -> Option<u64> {
+        self.start_ticks
+    }
+
+    /// Whether the process was adopted from a previous daemon
+    pub fn is_adopted(&self) -> bool {
+        self.adopted
+    }
+
+    /// Take over a process a previous daemon left running. Its output is not
+    /// captured; its exit is detected by polling.
+    pub fn adopt(&mut self, record: &ServiceRecord) {
+        info!(
+            "Adopting running process {} of service '{}'",
+            record.pid, self.service_name
+        );
+        let uptime = (chrono::Utc::now() - record.started_at)
+            .to_std()
+            .unwrap_or_default();
+
+        self.pid = Some(record.pid);
+        self.pgid = record.pgid;
+        self.start_ticks = record.start_ticks;
+        self.adopted = true;
+        self.started = true;
+        self.set_state(ServiceState::Running);
+        self.start_time = Instant::now().checked_sub(uptime).or(Some(Instant::now()));
+        self.last_error = None;
+    }
+
+    /// Take over a service handed over by the daemon this one replaces
+    pub fn take_over(&mut self, service: &HandedService) {
+        self.restart_count = service.restart_count;
+        self.total_restarts = service.total_restarts;
+        self.started = true;
+        match service.process {
+            Some(ref record) => {
+                self.adopt(record);
+                self.set_state(match service.status {
+                    ServiceStatus::Healthy => ServiceState::Healthy,
+                    ServiceStatus::Degraded => ServiceState::Degraded,
+                    _ => ServiceState::Running,
+                });
+            }
+            None => {
+                self.set_state(match service.status {
+                    ServiceStatus::Failed => ServiceState::Failed,
+                    ServiceStatus::Completed => ServiceState::Completed,
+                    _ => ServiceState::Stopped,
+                });
+            }
+        }
+    }
+
+    /// Process of the running service, as recorded in the state file and
+    /// handed over on upgrades
+    pub fn record(&self) -> Option<ServiceRecord> {
+        let pid = self.pid?;
+        let uptime = self
+            .uptime()
+            .and_then(|uptime| chrono::Duration::from_std(uptime).ok())
+            .unwrap_or_else(chrono::Duration::zero);
+        Some(ServiceRecord {
+            pid,
+            pgid: self.pgid,
+            start_ticks: self.start_ticks,
+            started_at: chrono::Utc::now() - uptime,
+        })
+    }
+
+    /// Read ends of the output pipes of the running process
+    #[cfg(unix)]
+    pub fn output_fds(&self) -> (Option<&OwnedFd>, Option<&OwnedFd>) {
+        (self.stdout_fd.as_ref(), self.stderr_fd.as_ref())
+    }
+
+    /// Keep the output pipes of a process taken over from another daemon
+    #[cfg(unix)]
+    pub fn set_output_fds(&mut self, stdout_fd: Option<OwnedFd>, stderr_fd: Option<OwnedFd>) {
+        self.stdout_fd = stdout_fd;
+        self.stderr_fd = stderr_fd;
+    }
+
     pub fn restart_count(&self) -> u32 {
         self.restart_count
     }
 
+    /// Restarts since the daemon started, including those counted by the
+    /// daemon it took over from; unlike `restart_count` never reset
+    pub fn total_restarts(&self) -> u32 {
+        self.total_restarts
+    }
+
+    /// Exit code of the last process that exited with one
+    pub fn last_exit_code(&self) -> Option<i32> {
+        self.last_exit_code
+    }
+
+    /// How the last `stop` ended the process; `None` if it found none running
+    pub fn last_stop(&self) -> Option<&StopOutcome> {
+        self.last_stop.as_ref()
+    }
+
     pub fn increment_restart_count(&mut self) {
         self.restart_count += 1;
     }
 
-    /// Start the service
-    pub async fn start(&mut self) -> Result<(), RunnerError> {
-        if self.state != ServiceState::Pending
-            && self.state != ServiceState::Stopped
-            && self.state != ServiceState::Failed
+    pub fn reset_restart_count(&mut self) {
+        self.restart_count = 0;
+    }
+
+    /// Reset the restart count if the current run stayed up for the
+    /// policy's `stable_after`, so the failure ending it is backed off as
+    /// a first one. Called when the process exits.
+    pub fn settle_restart_count(&mut self) {
+        if self
+            .uptime()
+            .is_some_and(|uptime| uptime >= self.config.policy.stable_after)
         {
+            self.restart_count = 0;
+        }
+    }
+
+    /// Record that the failed service is going to be restarted
+    pub fn schedule_restart(&mut self) {
+        self.restart_scheduled = true;
+    }
+
+    /// Failed with no restart pending, which is when `failed` dependencies
+    /// of its fallbacks are met
+    pub fn gave_up(&self) -> bool {
+        self.state == ServiceState::Failed && !self.restart_scheduled
+    }
+
+    /// Start the service, running its `pre_start` and `post_start` hooks
+    pub async fn start(&mut self) -> Result<(), RunnerError> {
+        if self.config.gpu {
+            check_gpu_available().await?;
+        }
+        if !self.begin_start().await? {
+            return Ok(());
+        }
+        self.run_hook(Hook::PreStart).await?;
+        if !self.launch().await? {
+            return Ok(());
+        }
+        if let Err(e) = self.run_hook(Hook::PostStart).await {
+            warn!("Service '{}': {}", self.service_name, e);
+        }
+        Ok(())
+    }
+
+    /// Begin a start, up to its `pre_start` hook: check the service can
+    /// start, clear what its last run left behind and read its secrets.
+    /// `false` if it is not stopped, so there is nothing to start.
+    pub async fn begin_start(&mut self) -> Result<bool, RunnerError> {
+        if !matches!(
+            self.state,
+            ServiceState::Pending
+                | ServiceState::Stopped
+                | ServiceState::Failed
+                | ServiceState::Blocked
+        ) {
             warn!(
                 "Service '{}' already in state {:?}, skipping start",
                 self.service_name, self.state
             );
-            return Ok(());
+            return Ok(false);
         }
+        self.restart_scheduled = false;
 
-        // Check GPU if required
-        if self.config.gpu {
-            let gpu_req = krill_common::GpuRequirement {
-                required: true,
-                min_memory_gb: None,
-                compute_capability: None,
-            };
-            krill_common::validate_gpu_available(&gpu_req).map_err(|e| {
-                RunnerError::GpuNotAvailable(format!("Service requires GPU: {}", e))
-            })?;
+        // A port held by another process would only make the service
+        // crash-loop. Its owner is left to the orchestrator, which looks it
+        // up without the runner locked.
+        if let Some(port) = krill_common::find_busy_port(&self.config.requires_ports) {
+            return Err(RunnerError::PortInUse(PortConflict { port, owner: None }));
         }
 
         // Check restart limit
@@ -144,16 +671,69 @@ impl ServiceRunner {
                 "Service '{}' exceeded max restarts ({})",
                 self.service_name, self.config.policy.max_restarts
             );
-            self.state = ServiceState::Failed;
+            self.set_state(ServiceState::Failed);
             return Err(RunnerError::RestartLimitExceeded);
         }
 
         info!("Starting service '{}'", self.service_name);
-        self.state = ServiceState::Starting;
+        self.set_state(ServiceState::Starting);
+        self.ready = false;
+        self.lifecycle = None;
+        self.limit_violation = None;
+        self.limit_streak = 0;
+        self.gpu_problem = None;
+        self.health_failure = None;
+        self.health_streak = 0;
+        self.health_changes.clear();
+        self.flapping = None;
+        self.paused_for = Duration::ZERO;
+        self.marker = Uuid::new_v4().simple().to_string();
+        self.descendants.clear();
+        self.heartbeat_metadata.clear();
+        self.last_heartbeat = None;
+        if let Some(checker) = self.health_checker.as_mut() {
+            checker.reset_heartbeat();
+        }
+
+        self.secrets = None;
+        self.inputs = Some(InputHashes::of(&self.config));
+        self.environment()?;
+        self.secrets = Some(self.read_secrets().await?);
+        Ok(true)
+    }
+
+    /// Spawn the process of a start [`begin_start`](Self::begin_start)
+    /// began, once its `pre_start` hook ran. `false` if the service was
+    /// stopped in between.
+    pub async fn launch(&mut self) -> Result<bool, RunnerError> {
+        if self.state != ServiceState::Starting {
+            info!(
+                "Service '{}' was stopped before it started",
+                self.service_name
+            );
+            return Ok(false);
+        }
+
+        let mut env_vars = self.environment()?;
+        env_vars.extend(self.secrets.clone().unwrap_or_default());
 
         // Build command
-        let cmd_parts = build_command(&self.config.execute, &self.env_vars)
-            .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?;
+        let container = self.container_name();
+        let mut cmd_parts = match container {
+            Some(ref name) => build_container_command(&self.config.execute, &env_vars, name),
+            None => build_command(&self.config.execute, &env_vars),
+        }
+        .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?;
+
+        // The engine enforces the limits of a container itself, right after `docker run`;
+        // compose services set them in the compose file
+        let single_container = self.is_single_container();
+        if let (true, Some(limits)) = (single_container, &self.config.limits) {
+            cmd_parts.splice(2..2, limits.docker_args());
+        }
+        if let (true, Some(cpus)) = (single_container, &self.config.cpu_affinity) {
+            cmd_parts.splice(2..2, krill_common::cpu_affinity_docker_args(cpus));
+        }
 
         if cmd_parts.is_empty() {
             return Err(RunnerError::SpawnFailed("Empty command".to_string()));
@@ -166,6 +746,11 @@ impl ServiceRunner {
         let program_path = krill_common::find_executable(program)
             .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?;
 
+        // A container left behind by a crash or an exited run would block the name
+        if let Some(ref name) = container {
+            remove_container(&self.container_remove_command(name), name).await;
+        }
+
         // Set up process name
         let process_name = generate_process_name(&self.service_name, None)
             .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?;
@@ -174,8 +759,12 @@ impl ServiceRunner {
         let mut command = Command::new(&program_path);
         command
             .args(args)
-            .env("KRILL_SERVICE_NAME", &self.service_name)
+            .env(
+                "KRILL_SERVICE_NAME",
+                self.client_name.as_ref().unwrap_or(&self.service_name),
+            )
             .env("KRILL_PROCESS_NAME", &process_name)
+            .env(reaper::MARKER_VAR, &self.marker)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
@@ -186,14 +775,80 @@ impl ServiceRunner {
         }
 
         // Add environment variables
-        for (key, value) in &self.env_vars {
+        for (key, value) in &env_vars {
             command.env(key, value);
         }
 
+        // Resource limits: a cgroup where available, else an address space
+        // rlimit if the service asks for one
+        if let (None, Some(limits)) = (&container, self.config.limits) {
+            if self.cgroup.is_none() && (limits.cpu.is_some() || limits.memory.is_some()) {
+                self.cgroup = Cgroup::for_service(&self.service_name, &limits);
+            }
+
+            #[cfg(unix)]
+            {
+                if let Some(ref cgroup) = self.cgroup {
+                    let procs = cgroup.procs_path().map_err(|e| {
+                        RunnerError::SpawnFailed(format!("Invalid cgroup path: {}", e))
+                    })?;
+                    // SAFETY: only async-signal-safe system calls run between fork and exec
+                    unsafe {
+                        command.pre_exec(move || limits::join_cgroup(&procs));
+                    }
+                }
+
+                let memory = limits
+                    .memory
+                    .filter(|_| self.cgroup.is_none() && limits.memory_rlimit);
+                if limits.nice.is_some() || memory.is_some() {
+                    // SAFETY: only async-signal-safe system calls run between fork and exec
+                    unsafe {
+                        command.pre_exec(move || {
+                            limits::apply_to_current_process(limits.nice, memory)
+                        });
+                    }
+                }
+            }
+        }
+
+        // CPU affinity and scheduling policy, inherited by everything the service spawns
+        if container.is_none()
+            && (self.config.cpu_affinity.is_some() || self.config.scheduler.is_some())
+        {
+            #[cfg(target_os = "linux")]
+            {
+                let scheduling = limits::Scheduling::new(
+                    self.config.cpu_affinity.as_deref(),
+                    self.config.scheduler,
+                );
+                // SAFETY: only system calls run between fork and exec
+                unsafe {
+                    command.pre_exec(move || scheduling.apply());
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            warn!(
+                "Service '{}': cpu_affinity and scheduler are only supported on Linux, ignoring them",
+                self.service_name
+            );
+        }
+
+        // Make the process a group leader before it execs, so signals reach
+        // everything it spawns
+        #[cfg(unix)]
+        command.process_group(0);
+
         // Spawn process
-        let child = command
-            .spawn()
-            .map_err(|e| RunnerError::SpawnFailed(format!("Failed to spawn: {}", e)))?;
+        let child =
+            reaper::spawn(watchdog::hide_notify_socket(&mut command)).map_err(|e| {
+                match e.kind() {
+                    std::io::ErrorKind::PermissionDenied => {
+                        RunnerError::PermissionDenied(e.to_string())
+                    }
+                    _ => RunnerError::SpawnFailed(format!("Failed to spawn: {}", e)),
+                }
+            })?;
 
         let pid = child
             .id()
@@ -201,26 +856,41 @@ impl ServiceRunner {
 
         debug!("Spawned service '{}' with PID {}", self.service_name, pid);
 
-        // Set up process group for isolation
         #[cfg(unix)]
         {
-            if let Err(e) = krill_common::setup_process_group(pid) {
-                warn!(
-                    "Failed to set process group for '{}': {}",
+            self.pgid = Some(pid);
+            debug!("Service '{}' process group: {}", self.service_name, pid);
+        }
+
+        // Put the process tree in a job object so it can be terminated as a whole
+        #[cfg(windows)]
+        {
+            match krill_common::JobObject::new().and_then(|job| job.assign(pid).map(|_| job)) {
+                Ok(job) => self.job = Some(job),
+                Err(e) => warn!(
+                    "Failed to create job object for '{}': {}",
                     self.service_name, e
-                );
-            } else {
-                // Get the PGID
-                if let Ok(pgid) = krill_common::get_process_group(pid) {
-                    self.pgid = Some(pgid);
-                    debug!("Service '{}' process group: {}", self.service_name, pgid);
-                }
+                ),
             }
         }
 
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsFd;
+            let copy = |fd: std::os::fd::BorrowedFd<'_>| fd.try_clone_to_owned().ok();
+            self.stdout_fd = child.stdout.as_ref().and_then(|out| copy(out.as_fd()));
+            self.stderr_fd = child.stderr.as_ref().and_then(|err| copy(err.as_fd()));
+        }
+
+        self.start_ticks = state::start_ticks(pid);
         self.process = Some(child);
         self.pid = Some(pid);
-        self.state = ServiceState::Running;
+        self.adopted = false;
+        if self.started {
+            self.total_restarts += 1;
+        }
+        self.started = true;
+        self.set_state(ServiceState::Running);
         self.start_time = Some(Instant::now());
         self.last_error = None;
 
@@ -229,56 +899,447 @@ impl ServiceRunner {
             self.service_name, pid
         );
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Environment of the service's processes, secrets aside: its env file,
+    /// overridden by the workspace `env`, overridden by its own `env`
+    pub fn environment(&self) -> Result<HashMap<String, String>, RunnerError> {
+        self.config
+            .environment(&self.env_vars)
+            .map_err(|e| RunnerError::SpawnFailed(e.to_string()))
+    }
+
+    /// Hashes of the env file and file secrets the last start read, if the
+    /// runner started the service
+    pub fn inputs(&self) -> Option<InputHashes> {
+        self.inputs
+    }
+
+    /// Values of the service's `secrets`. A `command` gets as long as a
+    /// hook to print its value, since the runner stays locked meanwhile.
+    async fn read_secrets(&self) -> Result<HashMap<String, String>, RunnerError> {
+        let timeout = self
+            .config
+            .hooks
+            .as_ref()
+            .map(|hooks| hooks.timeout)
+            .unwrap_or_else(|| ServiceHooks::default().timeout);
+        let mut values = HashMap::new();
+        for (name, source) in &self.config.secrets {
+            let value = match source {
+                SecretSource::File(_) => {
+                    let (name, source) = (name.clone(), source.clone());
+                    tokio::task::spawn_blocking(move || source.read(&name))
+                        .await
+                        .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?
+                }
+                SecretSource::Command(command) => {
+                    let argv = shell_command(command);
+                    let mut cmd = Command::new(&argv[0]);
+                    cmd.args(&argv[1..])
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::null())
+                        .kill_on_drop(true);
+                    watchdog::hide_notify_socket(&mut cmd);
+                    let failed = |reason: String| SecretError::Command {
+                        name: name.clone(),
+                        reason,
+                    };
+                    match tokio::time::timeout(timeout, reaper::output(&mut cmd)).await {
+                        Ok(Ok(output)) => SecretSource::command_value(name, output),
+                        Ok(Err(e)) => Err(failed(e.to_string())),
+                        Err(_) => Err(failed(format!("timed out after {:?}", timeout))),
+                    }
+                }
+            }
+            .map_err(|e| RunnerError::SpawnFailed(e.to_string()))?;
+            values.insert(name.clone(), value);
+        }
+        Ok(values)
+    }
+
+    /// Run the service's `hook` command, if it has one, with the service's
+    /// environment and working directory. Its output is logged as the
+    /// service's, prefixed with the hook.
+    pub async fn run_hook(&self, hook: Hook) -> Result<(), RunnerError> {
+        match self.prepare_hook(hook).await? {
+            Some(prepared) => prepared.run().await,
+            None => Ok(()),
+        }
+    }
+
+    /// The service's `hook` command, if it has one, ready to run once the
+    /// runner's lock is released
+    pub async fn prepare_hook(&self, hook: Hook) -> Result<Option<PreparedHook>, RunnerError> {
+        let Some(hooks) = &self.config.hooks else {
+            return Ok(None);
+        };
+        let Some(command) = hooks.command(hook) else {
+            return Ok(None);
+        };
+
+        let mut env = self.environment()?;
+        // Hooks of a run see the secrets its process was started with; a
+        // service adopted from another daemon has them read now
+        match &self.secrets {
+            Some(secrets) => env.extend(secrets.clone()),
+            None => env.extend(self.read_secrets().await?),
+        }
+        env.insert(
+            "KRILL_SERVICE_NAME".to_string(),
+            self.client_name
+                .clone()
+                .unwrap_or_else(|| self.service_name.clone()),
+        );
+        env.insert("KRILL_HOOK".to_string(), hook.key().to_string());
+        Ok(Some(PreparedHook {
+            hook,
+            service_name: self.service_name.clone(),
+            argv: shell_command(command),
+            env,
+            work_dir: get_working_dir(&self.config.execute),
+            timeout: hooks.timeout,
+            log_tx: self.log_tx.clone(),
+        }))
     }
 
-    /// Stop the service gracefully
+    /// Stop the service gracefully, running its `pre_stop` and `post_stop` hooks
     pub async fn stop(&mut self) -> Result<(), RunnerError> {
-        if self.state == ServiceState::Stopped || self.state == ServiceState::Pending {
+        let Some((pre_stop, post_stop)) = self.begin_stop().await else {
+            return Ok(());
+        };
+        if let Some(pre_stop) = pre_stop {
+            if let Err(e) = pre_stop.run().await {
+                self.record_stop_error(e);
+            }
+        }
+        let result = self.stop_without_hooks().await;
+        if let Some(post_stop) = post_stop {
+            if let Err(e) = post_stop.run().await {
+                self.record_stop_error(e);
+            }
+        }
+        self.finish_stop();
+        result
+    }
+
+    /// Begin a graceful stop: its `pre_stop` and `post_stop` hooks, ready
+    /// to run around [`stop_without_hooks`](Self::stop_without_hooks) with
+    /// the runner unlocked. `None` if the service is not running.
+    pub async fn begin_stop(&mut self) -> Option<(Option<PreparedHook>, Option<PreparedHook>)> {
+        self.last_stop = None;
+        if matches!(
+            self.state,
+            ServiceState::Stopped | ServiceState::Pending | ServiceState::Completed
+        ) || self.abandon_launch()
+        {
+            return None;
+        }
+
+        self.stop_errors.clear();
+        let pre_stop = self.prepare_stop_hook(Hook::PreStop).await;
+        let post_stop = self.prepare_stop_hook(Hook::PostStop).await;
+        Some((pre_stop, post_stop))
+    }
+
+    async fn prepare_stop_hook(&mut self, hook: Hook) -> Option<PreparedHook> {
+        match self.prepare_hook(hook).await {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                self.record_stop_error(e);
+                None
+            }
+        }
+    }
+
+    /// Note a stop hook that failed in the outcome of the stop
+    pub fn record_stop_error(&mut self, error: RunnerError) {
+        warn!("Service '{}': {}", self.service_name, error);
+        self.stop_errors.push(error.to_string());
+    }
+
+    /// End a graceful stop, once its `post_stop` hook ran
+    pub fn finish_stop(&mut self) {
+        let errors = std::mem::take(&mut self.stop_errors);
+        if let Some(outcome) = self.last_stop.as_mut() {
+            outcome.errors = errors;
+        }
+    }
+
+    /// Drop a start whose `pre_start` hook is still running, as its process
+    /// was not spawned yet; `true` if there was one
+    fn abandon_launch(&mut self) -> bool {
+        if self.state != ServiceState::Starting || self.process.is_some() || self.pid.is_some() {
+            return false;
+        }
+        info!("Cancelling the start of service '{}'", self.service_name);
+        self.cleanup();
+        true
+    }
+
+    /// Stop the service gracefully without running its hooks, so an
+    /// emergency stop is not held up by them
+    pub async fn stop_without_hooks(&mut self) -> Result<(), RunnerError> {
+        if matches!(
+            self.state,
+            ServiceState::Stopped | ServiceState::Pending | ServiceState::Completed
+        ) || self.abandon_launch()
+        {
             return Ok(());
         }
 
+        // A frozen process would not act on the stop signal
+        if self.state == ServiceState::Paused {
+            if let Err(e) = self.freeze(false).await {
+                warn!("Service '{}': {}", self.service_name, e);
+            }
+        }
+
         info!("Stopping service '{}'", self.service_name);
-        self.state = ServiceState::Stopping;
+        self.set_state(ServiceState::Stopping);
+
+        // Signalling the docker CLI would leave the container running
+        if let Some(container) = self.container_name() {
+            let result = self.stop_container(&container).await;
+            remove_container(&self.container_remove_command(&container), &container).await;
+            return result;
+        }
 
-        // Try graceful stop command first
-        if let Some(stop_cmd) = get_stop_command(&self.config.execute) {
-            debug!("Executing stop command for '{}'", self.service_name);
-            if let Ok(mut cmd) = Command::new(&stop_cmd[0]).args(&stop_cmd[1..]).spawn() {
-                let _ = tokio::time::timeout(Duration::from_secs(5), cmd.wait()).await;
+        let stop_cmd = get_stop_command(&self.config.execute);
+        for step in self.config.policy.stop_steps(stop_cmd.is_some()) {
+            match step.action {
+                StopAction::StopCommand => {
+                    let Some(ref stop_cmd) = stop_cmd else {
+                        continue;
+                    };
+                    debug!("Executing stop command for '{}'", self.service_name);
+                    if let Err(e) = reaper::spawn(watchdog::hide_notify_socket(
+                        Command::new(&stop_cmd[0]).args(&stop_cmd[1..]),
+                    )) {
+                        warn!(
+                            "Failed to run stop command for '{}': {}",
+                            self.service_name, e
+                        );
+                        self.stop_errors
+                            .push(format!("Failed to run stop command: {}", e));
+                        continue;
+                    }
+                }
+                #[cfg(unix)]
+                action => self.send_signal(stop_signal(action)),
+                // Windows has no signals; only a stop command can ask for a graceful exit
+                #[cfg(windows)]
+                action => {
+                    debug!(
+                        "Skipping {:?} for '{}' on Windows",
+                        action, self.service_name
+                    );
+                    continue;
+                }
+            }
+
+            match self.wait_for_exit(step.timeout).await? {
+                Ok(Ok(status)) => {
+                    self.log_stopped(status, step.action.into());
+                    self.end_descendants(reaper::GRACE_PERIOD);
+                    self.cleanup();
+                    return Ok(());
+                }
+                Ok(Err(e)) => {
+                    error!("Error waiting for service '{}': {}", self.service_name, e);
+                    return self.force_kill().await;
+                }
+                Err(_) => debug!(
+                    "Service '{}' still running after {:?} step",
+                    self.service_name, step.action
+                ),
             }
         }
 
-        // Send SIGTERM to process group
+        warn!(
+            "Service '{}' did not stop within timeout, sending SIGKILL",
+            self.service_name
+        );
+        self.force_kill().await
+    }
+
+    /// Kill the service's process group with SIGKILL right away, skipping its
+    /// stop command and grace periods (a container is removed)
+    pub async fn kill(&mut self) -> Result<(), RunnerError> {
+        if matches!(
+            self.state,
+            ServiceState::Stopped | ServiceState::Pending | ServiceState::Completed
+        ) || self.abandon_launch()
+        {
+            return Ok(());
+        }
+
+        warn!("Killing service '{}'", self.service_name);
+        self.set_state(ServiceState::Stopping);
+        self.force_kill().await
+    }
+
+    /// Send the configured `reload_signal` so the service reloads its
+    /// configuration in place; `docker run` and `podman run` pass it on to
+    /// the container. Only the main process gets it unless `reload_group`
+    /// is set, as helpers in the group may treat it as a request to exit.
+    pub fn reload(&self) -> Result<(), RunnerError> {
+        if !matches!(
+            self.state,
+            ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+        ) {
+            return Err(RunnerError::ProcessNotRunning);
+        }
+
+        #[cfg(unix)]
+        {
+            use krill_common::ReloadSignal;
+
+            let signal = match self.config.policy.reload_signal {
+                ReloadSignal::Sighup => Signal::SIGHUP,
+                ReloadSignal::Sigusr1 => Signal::SIGUSR1,
+                ReloadSignal::Sigusr2 => Signal::SIGUSR2,
+            };
+            info!("Reloading service '{}' with {}", self.service_name, signal);
+            if self.config.policy.reload_group {
+                self.send_signal(signal);
+            } else if let Some(pid) = self.pid {
+                debug!("Sending {} to PID {}", signal, pid);
+                let _ = signal::kill(Pid::from_raw(pid as i32), signal);
+            }
+            Ok(())
+        }
+        #[cfg(windows)]
+        Err(RunnerError::SignalsUnsupported)
+    }
+
+    /// Freeze the service's process group with SIGSTOP, keeping its state;
+    /// a container is paused by its engine instead
+    pub async fn pause(&mut self) -> Result<(), RunnerError> {
+        if !matches!(
+            self.state,
+            ServiceState::Running | ServiceState::Healthy | ServiceState::Degraded
+        ) {
+            return Err(RunnerError::ProcessNotRunning);
+        }
+
+        self.freeze(true).await?;
+        info!("Service '{}' paused", self.service_name);
+        self.paused_from = Some(self.state.clone());
+        self.set_state(ServiceState::Paused);
+        Ok(())
+    }
+
+    /// Continue a paused service with SIGCONT, back in the state it was
+    /// paused in
+    pub async fn resume(&mut self) -> Result<(), RunnerError> {
+        if self.state != ServiceState::Paused {
+            return Err(RunnerError::NotPaused);
+        }
+
+        self.freeze(false).await?;
+        info!("Service '{}' resumed", self.service_name);
+
+        // Time spent paused does not count as missed heartbeats
+        if let Some(HealthChecker::Heartbeat {
+            last_seen: Some(_), ..
+        }) = self.health_checker
+        {
+            self.record_heartbeat();
+        }
+        self.health_streak = 0;
+        self.paused_for += self.status_since.elapsed();
+        let state = self.paused_from.take().unwrap_or(ServiceState::Running);
+        self.set_state(state);
+        Ok(())
+    }
+
+    /// Stop (`freeze`) or continue the service's processes
+    async fn freeze(&self, freeze: bool) -> Result<(), RunnerError> {
+        let action = if freeze { "pause" } else { "resume" };
+        if let Some(container) = self.container_name() {
+            let Some(cmd) = container_pause_command(&self.config.execute, &container, freeze)
+            else {
+                return Ok(());
+            };
+            let failed = |reason: String| RunnerError::PauseFailed { action, reason };
+            let output = reaper::output(
+                Command::new(&cmd[0])
+                    .args(&cmd[1..])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped()),
+            )
+            .await
+            .map_err(|e| failed(format!("'{}': {}", cmd.join(" "), e)))?;
+            if !output.status.success() {
+                return Err(failed(
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ));
+            }
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        {
+            self.send_signal(if freeze {
+                Signal::SIGSTOP
+            } else {
+                Signal::SIGCONT
+            });
+            Ok(())
+        }
+        #[cfg(windows)]
+        Err(RunnerError::SignalsUnsupported)
+    }
+
+    /// Signal the service's process group, or its PID if it has none
+    #[cfg(unix)]
+    fn send_signal(&self, signal: Signal) {
         if let Some(pgid) = self.pgid {
-            debug!("Sending SIGTERM to process group {}", pgid);
-            let _ = krill_common::kill_process_group(pgid, Signal::SIGTERM);
+            debug!("Sending {} to process group {}", signal, pgid);
+            let _ = krill_common::kill_process_group(pgid, signal);
         } else if let Some(pid) = self.pid {
-            debug!("Sending SIGTERM to PID {}", pid);
-            let _ = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+            debug!("Sending {} to PID {}", signal, pid);
+            let _ = signal::kill(Pid::from_raw(pid as i32), signal);
         }
+    }
 
-        // Wait for process to exit
+    /// Stop a container service's container (or compose project) and wait
+    /// for its `docker run` (or `docker compose up`) to exit
+    async fn stop_container(&mut self, container: &str) -> Result<(), RunnerError> {
         let timeout = self.config.policy.stop_timeout;
-        let wait_result = if let Some(ref mut process) = self.process {
-            tokio::time::timeout(timeout, process.wait()).await
-        } else {
-            return Err(RunnerError::ProcessNotRunning);
-        };
+        let cmd = compose_down_command(&self.config.execute, container, timeout)
+            .unwrap_or_else(|| self.container_engine().stop_command(container, timeout));
+        debug!("Stopping container '{}'", container);
+
+        match reaper::status(
+            Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null()),
+        )
+        .await
+        {
+            Ok(status) if !status.success() => {
+                warn!("'{}' exited with {}", cmd.join(" "), status)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to run '{}': {}", cmd.join(" "), e),
+        }
 
-        match wait_result {
+        match self.wait_for_exit(timeout + CONTAINER_STOP_GRACE).await? {
             Ok(Ok(status)) => {
-                info!("Service '{}' stopped: {:?}", self.service_name, status);
+                self.log_stopped(status, StopMethod::ContainerStop);
+                self.end_descendants(reaper::GRACE_PERIOD);
                 self.cleanup();
                 Ok(())
             }
-            Ok(Err(e)) => {
-                error!("Error waiting for service '{}': {}", self.service_name, e);
-                self.force_kill().await
-            }
-            Err(_) => {
+            _ => {
                 warn!(
-                    "Service '{}' did not stop within timeout, sending SIGKILL",
+                    "Container of '{}' did not stop within timeout, killing it",
                     self.service_name
                 );
                 self.force_kill().await
@@ -286,37 +1347,148 @@ impl ServiceRunner {
         }
     }
 
-    /// Force kill the service with SIGKILL
+    /// Force kill the service with SIGKILL (or by terminating its job object on Windows)
     async fn force_kill(&mut self) -> Result<(), RunnerError> {
-        if let Some(pgid) = self.pgid {
-            debug!("Sending SIGKILL to process group {}", pgid);
-            let _ = krill_common::kill_process_group(pgid, Signal::SIGKILL);
-        } else if let Some(pid) = self.pid {
-            debug!("Sending SIGKILL to PID {}", pid);
-            let _ = signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        if let Some(container) = self.container_name() {
+            remove_container(&self.container_remove_command(&container), &container).await;
         }
 
-        // Force wait
-        if let Some(ref mut process) = self.process {
-            let _ = tokio::time::timeout(Duration::from_secs(5), process.wait()).await;
+        #[cfg(unix)]
+        self.send_signal(Signal::SIGKILL);
+
+        #[cfg(windows)]
+        {
+            if let Some(ref job) = self.job {
+                debug!("Terminating job object of '{}'", self.service_name);
+                let _ = job.terminate(1);
+            } else if let Some(ref mut process) = self.process {
+                let _ = process.start_kill();
+            }
         }
 
+        // Force wait
+        let status = match self.wait_for_exit(Duration::from_secs(5)).await {
+            Ok(Ok(Ok(status))) => status,
+            _ => None,
+        };
+        self.log_stopped(status, StopMethod::Sigkill);
+
+        self.end_descendants(Duration::ZERO);
         self.cleanup();
         Ok(())
     }
 
+    /// Wait up to `timeout` for the process to exit. An adopted process is
+    /// not our child, so its exit is polled and its status is unknown.
+    async fn wait_for_exit(&mut self, timeout: Duration) -> Result<WaitResult, RunnerError> {
+        if let Some(ref mut process) = self.process {
+            return Ok(
+                tokio::time::timeout(timeout, async { process.wait().await.map(Some) }).await,
+            );
+        }
+
+        match self.pid {
+            Some(pid) if self.adopted => {
+                let start_ticks = self.start_ticks;
+                Ok(tokio::time::timeout(timeout, async move {
+                    while state::process_alive(pid, start_ticks) {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    Ok(None)
+                })
+                .await)
+            }
+            _ => Err(RunnerError::ProcessNotRunning),
+        }
+    }
+
+    fn log_stopped(&mut self, status: Option<ExitStatus>, method: StopMethod) {
+        let exit_code = status.and_then(|status| status.code());
+        if let Some(code) = exit_code {
+            self.last_exit_code = Some(code);
+        }
+        #[cfg(unix)]
+        let signal = {
+            use std::os::unix::process::ExitStatusExt;
+            status.and_then(|status| status.signal())
+        };
+        #[cfg(windows)]
+        let signal = None;
+        self.last_stop = Some(StopOutcome {
+            method,
+            exit_code,
+            signal,
+            errors: Vec::new(),
+        });
+        match status {
+            Some(status) => info!("Service '{}' stopped: {:?}", self.service_name, status),
+            None => info!("Service '{}' stopped", self.service_name),
+        }
+    }
+
+    /// What a scan for the processes running under the service needs; the
+    /// scan runs with the runner unlocked and its result goes to
+    /// [`Self::track_descendants`]
+    pub fn descendant_tracking(&self) -> reaper::Tracking {
+        reaper::Tracking {
+            root: self.pid,
+            marker: self.marker.clone(),
+            known: self.descendants.clone(),
+        }
+    }
+
+    /// Remember the processes running under the service, so they are still
+    /// found once they leave its process tree
+    pub fn track_descendants(&mut self, descendants: Vec<Descendant>) {
+        self.descendants = descendants;
+    }
+
+    /// The processes the service's exited main process may have left
+    /// behind, to be ended with SIGTERM, then SIGKILL after `grace`
+    pub fn leftovers(&mut self, grace: Duration) -> reaper::Leftovers {
+        reaper::Leftovers {
+            service: self.service_name.clone(),
+            main_pid: self.pid,
+            marker: self.marker.clone(),
+            known: std::mem::take(&mut self.descendants),
+            grace,
+        }
+    }
+
+    /// End the processes left behind by the exited main process in the
+    /// background, see [`reaper::terminate_later`]
+    fn end_descendants(&mut self, grace: Duration) {
+        reaper::terminate_later(self.leftovers(grace));
+    }
+
     fn cleanup(&mut self) {
-        self.state = ServiceState::Stopped;
+        if let Some(cgroup) = self.cgroup.take() {
+            cgroup.remove();
+        }
+        self.set_state(ServiceState::Stopped);
         self.process = None;
         self.pid = None;
         self.pgid = None;
+        self.start_ticks = None;
+        self.adopted = false;
         self.start_time = None;
+        #[cfg(unix)]
+        {
+            self.stdout_fd = None;
+            self.stderr_fd = None;
+        }
+        #[cfg(windows)]
+        {
+            self.job = None;
+        }
     }
 
     /// Check if process is still running
     pub fn is_running(&mut self) -> bool {
         if let Some(ref mut process) = self.process {
             process.try_wait().ok().flatten().is_none()
+        } else if let (Some(pid), true) = (self.pid, self.adopted) {
+            state::process_alive(pid, self.start_ticks)
         } else {
             false
         }
@@ -326,53 +1498,334 @@ impl ServiceRunner {
     pub fn get_exit_code(&mut self) -> Option<i32> {
         if let Some(ref mut process) = self.process {
             if let Ok(Some(status)) = process.try_wait() {
+                if let Some(code) = status.code() {
+                    self.last_exit_code = Some(code);
+                }
                 return status.code();
             }
         }
         None
     }
 
-    /// Update health status
+    /// Apply a health verdict. Per the service's `health_debounce`, the
+    /// health only changes once that many verdicts in a row call for it,
+    /// and changing too often within the flapping window keeps the service
+    /// degraded until it settles.
     pub fn update_health(&mut self, is_healthy: bool) {
+        let debounce = self.config.health_debounce.unwrap_or_default();
+        let changes = matches!(
+            (&self.state, is_healthy),
+            (ServiceState::Running, true)
+                | (ServiceState::Healthy, false)
+                | (ServiceState::Degraded, true)
+        );
+        if !changes {
+            self.health_streak = 0;
+            self.update_flapping(&debounce);
+            return;
+        }
+        self.health_streak += 1;
+        if self.health_streak < debounce.checks {
+            return;
+        }
+        self.health_streak = 0;
+
         match (self.state.clone(), is_healthy) {
             (ServiceState::Running, true) => {
-                self.state = ServiceState::Healthy;
+                self.set_state(ServiceState::Healthy);
                 self.last_healthy_time = Some(Instant::now());
-
-                // Reset restart count after being healthy for 60 seconds
-                if let Some(last_healthy) = self.last_healthy_time {
-                    if last_healthy.elapsed() > Duration::from_secs(60) {
-                        self.restart_count = 0;
-                    }
-                }
             }
             (ServiceState::Healthy, false) => {
-                self.state = ServiceState::Degraded;
+                self.health_changes.push_back(Instant::now());
+                self.update_flapping(&debounce);
+                self.set_state(ServiceState::Degraded);
                 warn!("Service '{}' degraded", self.service_name);
             }
             (ServiceState::Degraded, true) => {
-                self.state = ServiceState::Healthy;
+                self.health_changes.push_back(Instant::now());
+                self.update_flapping(&debounce);
+                self.set_state(ServiceState::Healthy);
                 info!("Service '{}' recovered", self.service_name);
             }
             _ => {}
         }
     }
 
+    /// Mark the service flapping while its health changed at least
+    /// `flapping_threshold` times within the flapping window
+    fn update_flapping(&mut self, debounce: &HealthDebounce) {
+        let Some(threshold) = debounce.flapping_threshold else {
+            return;
+        };
+        while self
+            .health_changes
+            .front()
+            .is_some_and(|changed| changed.elapsed() > debounce.flapping_window)
+        {
+            self.health_changes.pop_front();
+        }
+
+        let changes = self.health_changes.len();
+        match (&self.flapping, changes >= threshold as usize) {
+            (None, true) => {
+                let reason = format!(
+                    "flapping: health changed {} times within {}s",
+                    changes,
+                    debounce.flapping_window.as_secs()
+                );
+                warn!("Service '{}' is {}", self.service_name, reason);
+                self.flapping = Some(reason);
+            }
+            (Some(_), false) => {
+                info!("Service '{}' stopped flapping", self.service_name);
+                self.flapping = None;
+            }
+            _ => return,
+        }
+        self.record_status();
+    }
+
+    /// Why the service is held degraded for changing its health too often
+    pub fn flapping(&self) -> Option<&str> {
+        self.flapping.as_deref()
+    }
+
+    /// Last reported state of the service's lifecycle node
+    pub fn lifecycle(&self) -> Option<LifecycleState> {
+        self.lifecycle
+    }
+
+    /// Apply the lifecycle state of the service's node: an active node is
+    /// healthy, and leaving the active state degrades the service. A node
+    /// that has not been activated yet keeps the service starting.
+    pub fn update_lifecycle(&mut self, state: LifecycleState) {
+        if self.lifecycle != Some(state) {
+            debug!("Lifecycle node of '{}' is {:?}", self.service_name, state);
+        }
+        self.lifecycle = Some(state);
+        self.update_health(state == LifecycleState::Active);
+        self.record_status();
+    }
+
+    /// Compare the service's usage against its `limits`, degrading it while
+    /// it exceeds them. Like health checks, a sample only changes that once
+    /// `health_debounce.checks` samples in a row agree with it. Returns
+    /// whether its status changed.
+    pub fn check_limits(&mut self, metrics: Option<&ServiceMetrics>) -> bool {
+        let Some(limits) = self.config.limits else {
+            return false;
+        };
+        let previous = self.get_status();
+
+        let violation = self
+            .cgroup
+            .as_mut()
+            .and_then(Cgroup::check)
+            .or_else(|| metrics.and_then(|metrics| limits::violation(&limits, metrics)));
+
+        if violation.is_some() == self.limit_violation.is_some() {
+            self.limit_streak = 0;
+            if violation.is_some() {
+                self.limit_violation = violation;
+            }
+            return false;
+        }
+        self.limit_streak += 1;
+        let checks = self.config.health_debounce.unwrap_or_default().checks;
+        if self.limit_streak < checks {
+            return false;
+        }
+        self.limit_streak = 0;
+
+        match (&self.limit_violation, &violation) {
+            (None, Some(reason)) => {
+                warn!(
+                    "Service '{}' exceeds its limits: {}",
+                    self.service_name, reason
+                )
+            }
+            (Some(_), None) => info!("Service '{}' is back within its limits", self.service_name),
+            _ => {}
+        }
+        self.limit_violation = violation;
+        self.record_status();
+
+        self.get_status() != previous
+    }
+
+    /// Why the service currently exceeds its `limits`
+    pub fn limit_violation(&self) -> Option<&str> {
+        self.limit_violation.as_deref()
+    }
+
+    /// Apply what the GPU probe found wrong, degrading a `gpu_health`
+    /// service while there is a problem. Returns whether its status changed.
+    pub fn check_gpu(&mut self, problem: Option<&str>) -> bool {
+        if !self.config.gpu_health {
+            return false;
+        }
+        let previous = self.get_status();
+
+        match (&self.gpu_problem, problem) {
+            (None, Some(problem)) => {
+                warn!("Service '{}' degraded: {}", self.service_name, problem)
+            }
+            (Some(_), None) => info!("GPU of service '{}' recovered", self.service_name),
+            _ => {}
+        }
+        self.gpu_problem = problem.map(String::from);
+        self.record_status();
+
+        self.get_status() != previous
+    }
+
+    /// What the GPU probe found wrong, for `gpu_health` services
+    pub fn gpu_problem(&self) -> Option<&str> {
+        self.gpu_problem.as_deref()
+    }
+
+    pub fn set_health_failure(&mut self, reason: Option<String>) {
+        self.health_failure = reason;
+    }
+
+    /// Why the service's last health check failed
+    pub fn health_failure(&self) -> Option<&str> {
+        self.health_failure.as_deref()
+    }
+
+    /// Mark a service that was never started as stopped
+    pub fn mark_stopped(&mut self) {
+        if self.state == ServiceState::Pending {
+            self.set_state(ServiceState::Stopped);
+        }
+    }
+
     /// Mark service as failed
     pub fn mark_failed(&mut self, error: Option<String>) {
         error!(
             "Service '{}' marked as failed: {:?}",
             self.service_name, error
         );
-        self.state = ServiceState::Failed;
+        self.set_state(ServiceState::Failed);
         self.last_error = error;
         self.restart_count += 1;
     }
 
+    /// Mark a service that was not started because a dependency did not
+    /// become ready in time
+    pub fn mark_blocked(&mut self, reason: String) {
+        warn!("Service '{}' blocked: {}", self.service_name, reason);
+        self.set_state(ServiceState::Blocked);
+        self.last_error = Some(reason);
+    }
+
+    /// Mark a oneshot service whose process exited with code 0 as completed
+    pub fn mark_completed(&mut self) {
+        info!("Service '{}' completed", self.service_name);
+        self.cleanup();
+        self.set_state(ServiceState::Completed);
+        self.last_error = None;
+    }
+
     pub fn last_error(&self) -> Option<&str> {
         self.last_error.as_deref()
     }
 
+    /// Replace the service configuration; takes effect on the next start
+    pub fn update_config(&mut self, config: ServiceConfig, env_vars: HashMap<String, String>) {
+        self.health_checker = config.health_check.clone();
+        if !config.gpu_health {
+            self.gpu_problem = None;
+        }
+        self.config = config;
+        self.env_vars = env_vars;
+    }
+
+    pub fn health_checker(&self) -> Option<&HealthChecker> {
+        self.health_checker.as_ref()
+    }
+
+    pub fn readiness_checker(&self) -> Option<&HealthChecker> {
+        self.config.readiness.as_ref()
+    }
+
+    /// Readiness of the current process, or `None` if no readiness check is configured
+    pub fn readiness(&self) -> Option<bool> {
+        self.config.readiness.as_ref().map(|_| self.ready)
+    }
+
+    pub fn mark_ready(&mut self) {
+        if !self.ready {
+            info!("Service '{}' is ready", self.service_name);
+        }
+        self.ready = true;
+    }
+
+    /// Time a started service gets to become ready, if limited
+    pub fn start_timeout(&self) -> Option<Duration> {
+        self.config.start_timeout_sec.map(Duration::from_secs)
+    }
+
+    /// Whether the current process finished starting: its readiness probe
+    /// passed, or without one its health check passed, or without either it runs
+    /// Time spent paused since the last start, including the current pause
+    pub fn time_paused(&self) -> Duration {
+        match self.state {
+            ServiceState::Paused => self.paused_for + self.status_since.elapsed(),
+            _ => self.paused_for,
+        }
+    }
+
+    pub fn startup_complete(&self) -> bool {
+        if self.config.readiness.is_some() {
+            return self.ready;
+        }
+        match self.state {
+            ServiceState::Healthy | ServiceState::Degraded => true,
+            ServiceState::Running => self.health_checker.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Record a heartbeat, feeding the health check if it is heartbeat based
+    pub fn record_heartbeat(&mut self) {
+        self.last_heartbeat = Some(Instant::now());
+        if let Some(checker) = self.health_checker.as_mut() {
+            let _ = checker.record_heartbeat();
+        }
+    }
+
+    /// Report heartbeats of the service's SDK client under `name`
+    pub fn set_client_name(&mut self, name: String) {
+        self.client_name = Some(name);
+    }
+
+    /// Send the output of hooks to `log_tx`
+    pub fn set_log_tx(&mut self, log_tx: mpsc::UnboundedSender<ProcessOutputLine>) {
+        self.log_tx = Some(log_tx);
+    }
+
+    pub fn set_heartbeat_metadata(&mut self, metadata: HashMap<String, String>) {
+        self.heartbeat_metadata = metadata;
+    }
+
+    pub fn heartbeat_metadata(&self) -> &HashMap<String, String> {
+        &self.heartbeat_metadata
+    }
+
+    /// Time since the last heartbeat, if one arrived since the last start
+    pub fn heartbeat_age(&self) -> Option<Duration> {
+        self.last_heartbeat.map(|at| at.elapsed())
+    }
+
+    /// Heartbeats missed in a row by the current process
+    pub fn missed_heartbeats(&self) -> u32 {
+        self.health_checker
+            .as_ref()
+            .map(HealthChecker::missed_beats)
+            .unwrap_or(0)
+    }
+
     /// Check if service should be restarted
     pub fn should_restart(&self, exit_code: Option<i32>) -> bool {
         use krill_common::policy::RestartPolicy;
@@ -401,12 +1854,30 @@ impl ServiceRunner {
         match self.state {
             ServiceState::Pending => ServiceStatus::Starting,
             ServiceState::Starting => ServiceStatus::Starting,
+            // The lifecycle node is still being configured or activated
+            ServiceState::Running
+                if self
+                    .lifecycle
+                    .is_some_and(|state| state.status() == ServiceStatus::Starting) =>
+            {
+                ServiceStatus::Starting
+            }
+            ServiceState::Running | ServiceState::Healthy
+                if self.limit_violation.is_some()
+                    || self.gpu_problem.is_some()
+                    || self.flapping.is_some() =>
+            {
+                ServiceStatus::Degraded
+            }
             ServiceState::Running => ServiceStatus::Running,
             ServiceState::Healthy => ServiceStatus::Healthy,
             ServiceState::Degraded => ServiceStatus::Degraded,
             ServiceState::Stopping => ServiceStatus::Stopping,
             ServiceState::Stopped => ServiceStatus::Stopped,
             ServiceState::Failed => ServiceStatus::Failed,
+            ServiceState::Completed => ServiceStatus::Completed,
+            ServiceState::Blocked => ServiceStatus::Blocked,
+            ServiceState::Paused => ServiceStatus::Paused,
         }
     }
 
@@ -414,6 +1885,33 @@ impl ServiceRunner {
         &self.workspace_name
     }
 
+    /// Name of the service's container (or compose project), for container services
+    pub fn container_name(&self) -> Option<String> {
+        self.config
+            .execute
+            .container_engine()
+            .map(|_| container_name(&self.workspace_name, &self.service_name))
+    }
+
+    /// Engine running the service's containers; Docker for other services
+    pub fn container_engine(&self) -> ContainerEngine {
+        self.config.execute.container_engine().unwrap_or_default()
+    }
+
+    /// Whether the service is a single container, whose health the engine tracks
+    pub fn is_single_container(&self) -> bool {
+        matches!(
+            self.config.execute,
+            ExecuteConfig::Docker { .. } | ExecuteConfig::Podman { .. }
+        )
+    }
+
+    /// Command removing the service's container, or taking its compose project down at once
+    fn container_remove_command(&self, container: &str) -> Vec<String> {
+        compose_down_command(&self.config.execute, container, Duration::ZERO)
+            .unwrap_or_else(|| self.container_engine().remove_command(container))
+    }
+
     pub fn executor_type(&self) -> &str {
         self.config.execute.executor_type()
     }
@@ -437,3 +1935,37 @@ impl ServiceRunner {
         self.process.as_mut().and_then(|p| p.stderr.take())
     }
 }
+
+/// Refuse to start a service that requires a GPU when none is detected, as
+/// it would only crash-loop. Asking the driver may take a while, so the
+/// orchestrator checks before it locks the runner.
+pub async fn check_gpu_available() -> Result<(), RunnerError> {
+    #[cfg(target_os = "linux")]
+    crate::gpu::detect().await.map_err(|e| {
+        RunnerError::GpuNotAvailable(format!(
+            "the service requires a GPU, but none was detected ({})",
+            e
+        ))
+    })?;
+    Ok(())
+}
+
+/// Force-remove a container with `cmd`; a missing container is not an error
+async fn remove_container(cmd: &[String], container: &str) {
+    let result = tokio::time::timeout(
+        CONTAINER_REMOVE_TIMEOUT,
+        reaper::status(
+            Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null()),
+        ),
+    )
+    .await;
+
+    match result {
+        Ok(Ok(_)) => debug!("Removed container '{}'", container),
+        Ok(Err(e)) => warn!("Failed to run '{}': {}", cmd.join(" "), e),
+        Err(_) => warn!("'{}' timed out", cmd.join(" ")),
+    }
+}
@@ -0,0 +1,412 @@
+// Scheduler - Maintenance actions applied to services at set times: the
+// `schedule:` cron expressions of the recipes, and one-shot requests such
+// as `krill restart lidar --at 02:00`
+
+use crate::audit::AuditLog;
+use crate::workspaces::{qualified_name, Workspaces, WORKSPACE_SEPARATOR};
+use chrono::{DateTime, Local, Utc};
+use krill_common::{
+    ClientIdentity, CommandAction, CronSchedule, KrillConfig, MaintenanceAction, MaintenanceEvent,
+    ServiceStatus,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// How often due actions are looked for
+pub const TICK: Duration = Duration::from_secs(1);
+
+/// A maintenance action of a service, and what happened to it
+pub type MaintenanceNotice = (String, MaintenanceEvent, MaintenanceAction);
+
+/// A maintenance action come due for a service, with the client that
+/// scheduled it (`None` for the recipe's schedules)
+pub type DueAction = (String, MaintenanceAction, Option<ClientIdentity>);
+
+struct Planned {
+    /// Service as clients address it, `workspace/service` when hosted
+    service: String,
+    maintenance: MaintenanceAction,
+    /// Schedule the action is planned again on once applied
+    cron: Option<CronSchedule>,
+    /// Client that asked for a one-shot action
+    client: Option<ClientIdentity>,
+}
+
+/// Upcoming maintenance actions of the services of every workspace. Cron
+/// schedules run in the daemon's local time; each announces its next run
+/// as a `scheduled` notice, and each action that comes due as `due`.
+pub struct Scheduler {
+    planned: Mutex<Vec<Planned>>,
+    notice_tx: mpsc::UnboundedSender<MaintenanceNotice>,
+}
+
+impl Scheduler {
+    pub fn new(notice_tx: mpsc::UnboundedSender<MaintenanceNotice>) -> Self {
+        Self {
+            planned: Mutex::new(Vec::new()),
+            notice_tx,
+        }
+    }
+
+    /// Plan the `schedule:` actions of the services of `config`, the
+    /// daemon's own workspace for `None`, replacing those planned for the
+    /// workspace before. Unchanged schedules keep their next run; one-shot
+    /// actions of services no longer in the recipe are dropped.
+    pub fn set_recipe(&self, workspace: Option<&str>, config: &KrillConfig) {
+        let name = |service: &str| match workspace {
+            Some(workspace) => qualified_name(workspace, service),
+            None => service.to_string(),
+        };
+        let in_workspace = |service: &str| match service.split_once(WORKSPACE_SEPARATOR) {
+            Some((owner, _)) => Some(owner) == workspace,
+            None => workspace.is_none(),
+        };
+        let wanted: Vec<(String, CommandAction, &CronSchedule)> = config
+            .services
+            .iter()
+            .flat_map(|(service, cfg)| {
+                cfg.schedule
+                    .actions()
+                    .into_iter()
+                    .map(move |(action, cron)| (name(service), action, cron))
+            })
+            .collect();
+
+        let mut planned = self.planned.lock().unwrap();
+        planned.retain(|entry| {
+            if !in_workspace(&entry.service) {
+                return true;
+            }
+            match entry.cron {
+                Some(ref cron) => wanted.iter().any(|(service, action, wanted)| {
+                    *service == entry.service
+                        && *action == entry.maintenance.action
+                        && *wanted == cron
+                }),
+                None => config.services.keys().any(|s| name(s) == entry.service),
+            }
+        });
+
+        let now = Local::now();
+        for (service, action, cron) in wanted {
+            let exists = planned.iter().any(|entry| {
+                entry.service == service
+                    && entry.maintenance.action == action
+                    && entry.cron.as_ref() == Some(cron)
+            });
+            if exists {
+                continue;
+            }
+            let Some(at) = cron.next_after(&now) else {
+                warn!(
+                    "Schedule '{}' of service '{}' never runs; ignoring it",
+                    cron, service
+                );
+                continue;
+            };
+            let maintenance = MaintenanceAction {
+                action,
+                at: at.with_timezone(&Utc),
+                cron: Some(cron.to_string()),
+            };
+            self.notify(&service, MaintenanceEvent::Scheduled, &maintenance);
+            planned.push(Planned {
+                service,
+                maintenance,
+                cron: Some(cron.clone()),
+                client: None,
+            });
+        }
+    }
+
+    /// Apply `action` to `service` once, at `at`, on behalf of `client`
+    pub fn schedule(
+        &self,
+        service: &str,
+        action: CommandAction,
+        at: DateTime<Utc>,
+        client: ClientIdentity,
+    ) {
+        let maintenance = MaintenanceAction {
+            action,
+            at,
+            cron: None,
+        };
+        self.notify(service, MaintenanceEvent::Scheduled, &maintenance);
+        self.planned.lock().unwrap().push(Planned {
+            service: service.to_string(),
+            maintenance,
+            cron: None,
+            client: Some(client),
+        });
+    }
+
+    /// Upcoming actions of `service`, soonest first
+    pub fn upcoming(&self, service: &str) -> Vec<MaintenanceAction> {
+        let mut upcoming: Vec<MaintenanceAction> = self
+            .planned
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.service == service)
+            .map(|entry| entry.maintenance.clone())
+            .collect();
+        upcoming.sort_by_key(|maintenance| maintenance.at);
+        upcoming
+    }
+
+    /// Take the actions due by `now`, soonest first. Those on a cron
+    /// schedule are planned again for their next run.
+    pub fn take_due(&self, now: DateTime<Utc>) -> Vec<DueAction> {
+        let mut planned = self.planned.lock().unwrap();
+        let mut due = Vec::new();
+        let mut index = 0;
+        while index < planned.len() {
+            if planned[index].maintenance.at > now {
+                index += 1;
+                continue;
+            }
+
+            let entry = &mut planned[index];
+            let maintenance = entry.maintenance.clone();
+            self.notify(&entry.service, MaintenanceEvent::Due, &maintenance);
+            due.push((entry.service.clone(), maintenance, entry.client.clone()));
+
+            let next = entry
+                .cron
+                .as_ref()
+                .and_then(|cron| cron.next_after(&now.with_timezone(&Local)));
+            match next {
+                Some(at) => {
+                    entry.maintenance.at = at.with_timezone(&Utc);
+                    self.notify(
+                        &entry.service,
+                        MaintenanceEvent::Scheduled,
+                        &entry.maintenance,
+                    );
+                    index += 1;
+                }
+                None => {
+                    planned.remove(index);
+                }
+            }
+        }
+        due.sort_by_key(|(_, maintenance, _)| maintenance.at);
+        due
+    }
+
+    fn notify(&self, service: &str, event: MaintenanceEvent, maintenance: &MaintenanceAction) {
+        let _ = self
+            .notice_tx
+            .send((service.to_string(), event, maintenance.clone()));
+    }
+}
+
+/// Apply the actions of `scheduler` as they come due. Scheduled restarts
+/// leave services that were stopped or completed alone, so a nightly
+/// restart does not start a service stopped on purpose. Actions clients
+/// scheduled are audited as they are applied.
+pub async fn run(scheduler: Arc<Scheduler>, workspaces: Arc<Workspaces>, audit_log: Arc<AuditLog>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let due = scheduler.take_due(Utc::now());
+        if due.is_empty() {
+            continue;
+        }
+
+        let snapshot = workspaces.get_snapshot().await;
+        for (service, maintenance, client) in due {
+            let idle = snapshot.get(&service).is_some_and(|snapshot| {
+                matches!(
+                    snapshot.status,
+                    ServiceStatus::Stopped | ServiceStatus::Completed
+                )
+            });
+            if maintenance.cron.is_some() && maintenance.action == CommandAction::Restart && idle {
+                info!(
+                    "Skipping scheduled restart of '{}', which is not running",
+                    service
+                );
+                continue;
+            }
+
+            info!(
+                "Applying scheduled {:?} of '{}'",
+                maintenance.action, service
+            );
+            let result = workspaces
+                .execute(maintenance.action, Some(service.clone()))
+                .await;
+            if let Err(ref e) = result {
+                error!(
+                    "Scheduled {:?} of '{}' failed: {}",
+                    maintenance.action, service, e
+                );
+            }
+            if let Some(client) = client {
+                audit_log
+                    .record(
+                        maintenance.action,
+                        Some(&service),
+                        &client,
+                        result.as_ref().map(|_| ()),
+                    )
+                    .await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> KrillConfig {
+        KrillConfig::parse(yaml, None).unwrap()
+    }
+
+    const RECIPE: &str = r#"
+version: "1"
+name: robot
+services:
+  lidar:
+    execute:
+      type: shell
+      command: sleep 60
+    schedule:
+      restart: "*/5 * * * *"
+  planner:
+    execute:
+      type: shell
+      command: sleep 60
+"#;
+
+    #[test]
+    fn test_cron_actions_are_planned_again_once_due() {
+        let (notice_tx, mut notice_rx) = mpsc::unbounded_channel();
+        let scheduler = Scheduler::new(notice_tx);
+        scheduler.set_recipe(None, &config(RECIPE));
+        scheduler.set_recipe(Some("arm"), &config(RECIPE));
+
+        let upcoming = scheduler.upcoming("lidar");
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].action, CommandAction::Restart);
+        assert_eq!(upcoming[0].cron.as_deref(), Some("*/5 * * * *"));
+        assert_eq!(scheduler.upcoming("arm/lidar").len(), 1);
+        assert!(scheduler.upcoming("planner").is_empty());
+
+        let (service, event, _) = notice_rx.try_recv().unwrap();
+        assert_eq!(
+            (service.as_str(), event),
+            ("lidar", MaintenanceEvent::Scheduled)
+        );
+
+        // Reloading an unchanged recipe keeps the planned run
+        scheduler.set_recipe(None, &config(RECIPE));
+        assert_eq!(scheduler.upcoming("lidar"), upcoming);
+
+        let first = upcoming[0].at;
+        let due = scheduler.take_due(first);
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].0, "lidar");
+        let next = scheduler.upcoming("lidar")[0].at;
+        assert_eq!(next - first, chrono::Duration::minutes(5));
+
+        // Dropping the schedule from the recipe drops its runs
+        let unscheduled = RECIPE.replace("    schedule:\n      restart: \"*/5 * * * *\"\n", "");
+        scheduler.set_recipe(None, &config(&unscheduled));
+        assert!(scheduler.upcoming("lidar").is_empty());
+        assert_eq!(scheduler.upcoming("arm/lidar").len(), 1);
+    }
+
+    #[test]
+    fn test_one_shot_actions_run_once() {
+        let (notice_tx, _notice_rx) = mpsc::unbounded_channel();
+        let scheduler = Scheduler::new(notice_tx);
+        let at = Utc::now() + chrono::Duration::minutes(30);
+        scheduler.schedule(
+            "planner",
+            CommandAction::Restart,
+            at,
+            ClientIdentity::default(),
+        );
+        scheduler.schedule(
+            "planner",
+            CommandAction::Stop,
+            at - chrono::Duration::minutes(10),
+            ClientIdentity::default(),
+        );
+
+        let upcoming = scheduler.upcoming("planner");
+        assert_eq!(upcoming[0].action, CommandAction::Stop);
+        assert_eq!(upcoming[1].action, CommandAction::Restart);
+
+        assert!(scheduler.take_due(Utc::now()).is_empty());
+        assert_eq!(scheduler.take_due(at).len(), 2);
+        assert!(scheduler.upcoming("planner").is_empty());
+
+        // One-shot actions go with their service
+        scheduler.schedule(
+            "gone",
+            CommandAction::Restart,
+            at,
+            ClientIdentity::default(),
+        );
+        scheduler.set_recipe(None, &config(RECIPE));
+        assert!(scheduler.upcoming("gone").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_applied_one_shot_actions_are_audited() {
+        use crate::orchestrator::Orchestrator;
+        use krill_common::{AuditOutcome, ERROR_NOT_FOUND};
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Arc::new(Orchestrator::new(config(RECIPE), event_tx).unwrap());
+        let workspaces = Arc::new(Workspaces::new("robot", orchestrator));
+        let audit_log = Arc::new(AuditLog::new(10));
+        let (notice_tx, _notice_rx) = mpsc::unbounded_channel();
+        let scheduler = Arc::new(Scheduler::new(notice_tx));
+        scheduler.set_recipe(None, &config(RECIPE));
+
+        let client = ClientIdentity {
+            client: Some("krill-cli".to_string()),
+            ..Default::default()
+        };
+        scheduler.schedule("planner", CommandAction::Stop, Utc::now(), client.clone());
+        scheduler.schedule("radar", CommandAction::Stop, Utc::now(), client.clone());
+        let task = tokio::spawn(run(
+            Arc::clone(&scheduler),
+            workspaces,
+            Arc::clone(&audit_log),
+        ));
+
+        let records = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let records = audit_log.query(None, None);
+                if records.len() == 2 {
+                    return records;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        task.abort();
+
+        assert_eq!(records[0].target.as_deref(), Some("planner"));
+        assert_eq!(records[0].outcome, AuditOutcome::Accepted);
+        assert_eq!(records[0].client, client);
+        assert_eq!(records[1].target.as_deref(), Some("radar"));
+        assert!(matches!(
+            records[1].outcome,
+            AuditOutcome::Rejected {
+                code: ERROR_NOT_FOUND,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,299 @@
+// Daemon State - Service processes persisted across daemon restarts
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Time a reaped orphan gets to exit after SIGTERM before it is killed
+pub const REAP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Process of a running service, as recorded in the state file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceRecord {
+    pub pid: u32,
+    pub pgid: Option<u32>,
+    /// Start time of the process in clock ticks since boot, where procfs is available
+    #[serde(default)]
+    pub start_ticks: Option<u64>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl ServiceRecord {
+    /// Whether the recorded process still runs (and is not a new process
+    /// that reused its PID)
+    pub fn is_alive(&self) -> bool {
+        process_alive(self.pid, self.start_ticks)
+    }
+}
+
+/// Contents of the state file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonState {
+    pub session_id: String,
+    pub daemon_pid: u32,
+    /// Kernel boot ID; processes of a previous boot are all gone
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceRecord>,
+}
+
+impl DaemonState {
+    /// Whether the state was written during the current boot
+    pub fn same_boot(&self) -> bool {
+        match (&self.boot_id, current_boot_id()) {
+            (Some(recorded), Some(current)) => *recorded == current,
+            _ => true,
+        }
+    }
+
+    /// Whether the daemon that wrote the state is still running
+    pub fn daemon_alive(&self) -> bool {
+        self.same_boot()
+            && self.daemon_pid != std::process::id()
+            && process_alive(self.daemon_pid, None)
+    }
+
+    /// Recorded services whose process still runs
+    pub fn live_services(&self) -> Vec<(&str, &ServiceRecord)> {
+        if !self.same_boot() {
+            return Vec::new();
+        }
+        self.services
+            .iter()
+            .filter(|(_, record)| record.is_alive())
+            .map(|(name, record)| (name.as_str(), record))
+            .collect()
+    }
+}
+
+/// PID and PGID per service
+type Processes = BTreeMap<String, (u32, Option<u32>)>;
+
+/// JSON file recording the processes of the running services, so that a
+/// daemon restarted after a crash can find the processes it left behind
+pub struct StateFile {
+    path: PathBuf,
+    session_id: String,
+    boot_id: Option<String>,
+    /// PID and PGID per service at the last save, to skip unchanged writes
+    saved: Mutex<Option<Processes>>,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            session_id: Uuid::new_v4().to_string(),
+            boot_id: current_boot_id(),
+            saved: Mutex::new(None),
+        }
+    }
+
+    /// State file of a workspace, `~/.krill/state/<workspace>.json`
+    pub fn default_path(workspace: &str) -> PathBuf {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        home.join(".krill")
+            .join("state")
+            .join(format!("{}.json", workspace))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Read the state left by a previous daemon, if any
+    pub fn load(&self) -> io::Result<Option<DaemonState>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Record the processes of the running services. The file is replaced
+    /// atomically and only rewritten when a PID or PGID changed.
+    pub fn save(&self, services: BTreeMap<String, ServiceRecord>) -> io::Result<()> {
+        let processes: Processes = services
+            .iter()
+            .map(|(name, record)| (name.clone(), (record.pid, record.pgid)))
+            .collect();
+
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        if saved.as_ref() == Some(&processes) {
+            return Ok(());
+        }
+
+        let state = DaemonState {
+            session_id: self.session_id.clone(),
+            daemon_pid: std::process::id(),
+            boot_id: self.boot_id.clone(),
+            services,
+        };
+        let json = serde_json::to_string_pretty(&state)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)?;
+
+        debug!("Saved daemon state to {:?}", self.path);
+        *saved = Some(processes);
+        Ok(())
+    }
+
+    /// Delete the state file after a clean shutdown
+    pub fn remove(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether `pid` runs. With `start_ticks` the process must also have been
+/// started at that time, which rules out a reused PID.
+pub fn process_alive(pid: u32, start_ticks: Option<u64>) -> bool {
+    #[cfg(unix)]
+    {
+        use nix::errno::Errno;
+        use nix::sys::signal::kill;
+        use nix::unistd::Pid;
+
+        match start_ticks {
+            Some(ticks) => self::start_ticks(pid) == Some(ticks),
+            None => !matches!(kill(Pid::from_raw(pid as i32), None), Err(Errno::ESRCH)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (pid, start_ticks);
+        false
+    }
+}
+
+/// Start time of a running process in clock ticks since boot, where procfs
+/// is available
+pub fn start_ticks(pid: u32) -> Option<u64> {
+    crate::metrics::process_start_ticks(Path::new("/proc"), pid)
+}
+
+/// Terminate an orphaned process and its group: SIGTERM, then SIGKILL if it
+/// is still running after `timeout`
+#[cfg(unix)]
+pub async fn reap(name: &str, record: &ServiceRecord, timeout: std::time::Duration) {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let signal = |signal: Signal| match record.pgid {
+        Some(pgid) => {
+            let _ = krill_common::kill_process_group(pgid, signal);
+        }
+        None => {
+            let _ = kill(Pid::from_raw(record.pid as i32), signal);
+        }
+    };
+
+    info!(
+        "Reaping orphaned process {} of '{}' from a previous daemon",
+        record.pid, name
+    );
+    signal(Signal::SIGTERM);
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    while record.is_alive() {
+        if tokio::time::Instant::now() >= deadline {
+            warn!(
+                "Orphaned process {} of '{}' ignored SIGTERM, killing it",
+                record.pid, name
+            );
+            signal(Signal::SIGKILL);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+}
+
+fn current_boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(pid: u32, start_ticks: Option<u64>) -> ServiceRecord {
+        ServiceRecord {
+            pid,
+            pgid: Some(pid),
+            start_ticks,
+            started_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let file = StateFile::new(dir.path().join("state").join("ws.json"));
+        assert_eq!(file.load().unwrap(), None);
+
+        let services = BTreeMap::from([("svc".to_string(), record(1234, Some(99)))]);
+        file.save(services.clone()).unwrap();
+
+        let state = file.load().unwrap().unwrap();
+        assert_eq!(state.session_id, file.session_id());
+        assert_eq!(state.daemon_pid, std::process::id());
+        assert_eq!(state.services, services);
+
+        file.remove().unwrap();
+        assert_eq!(file.load().unwrap(), None);
+        file.remove().unwrap();
+    }
+
+    #[test]
+    fn test_save_skips_unchanged_processes() {
+        let dir = TempDir::new().unwrap();
+        let file = StateFile::new(dir.path().join("ws.json"));
+        file.save(BTreeMap::from([("svc".to_string(), record(1, None))]))
+            .unwrap();
+
+        fs::remove_file(file.path()).unwrap();
+        file.save(BTreeMap::from([("svc".to_string(), record(1, None))]))
+            .unwrap();
+        assert!(!file.path().exists());
+
+        file.save(BTreeMap::new()).unwrap();
+        assert!(file.path().exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reused_pid_is_not_alive() {
+        let pid = std::process::id();
+        let ticks = start_ticks(pid).unwrap();
+
+        assert!(record(pid, Some(ticks)).is_alive());
+        assert!(!record(pid, Some(ticks + 1)).is_alive());
+        assert!(record(pid, None).is_alive());
+    }
+}
@@ -0,0 +1,112 @@
+// Restart Storm - Circuit breaker pausing automatic restarts after too many
+// failures across a workspace
+
+use krill_common::RestartStormConfig;
+use std::collections::{BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The breaker of a workspace tripped; sent to clients as `system_degraded`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StormTripped {
+    pub workspace: String,
+    pub failures: u32,
+    pub window: Duration,
+}
+
+/// Recent service failures of a workspace, and whether they tripped the
+/// breaker. Once tripped it stays so until [`resume`](Self::resume).
+#[derive(Debug, Default)]
+pub struct RestartStorm {
+    failures: VecDeque<Instant>,
+    tripped: bool,
+    /// Services whose restart was held back while tripped
+    held: BTreeSet<String>,
+}
+
+impl RestartStorm {
+    /// Count a failure at `now`. Returns the failures within the window
+    /// when they just tripped the breaker.
+    pub fn record_failure(&mut self, config: &RestartStormConfig, now: Instant) -> Option<u32> {
+        self.failures.push_back(now);
+        while self
+            .failures
+            .front()
+            .is_some_and(|failure| now.saturating_duration_since(*failure) > config.window)
+        {
+            self.failures.pop_front();
+        }
+
+        let failures = u32::try_from(self.failures.len()).unwrap_or(u32::MAX);
+        if self.tripped || failures <= config.max_failures {
+            return None;
+        }
+        self.tripped = true;
+        Some(failures)
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// Hold back the restart of `service` until the breaker is reset
+    pub fn hold(&mut self, service: &str) {
+        self.held.insert(service.to_string());
+    }
+
+    /// Reset the breaker, returning the services whose restart it held back
+    pub fn resume(&mut self) -> Vec<String> {
+        self.tripped = false;
+        self.failures.clear();
+        std::mem::take(&mut self.held).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trips_on_failures_within_window() {
+        let config = RestartStormConfig {
+            max_failures: 3,
+            window: Duration::from_secs(10),
+        };
+        let mut storm = RestartStorm::default();
+        let start = Instant::now();
+
+        // Failures spread wider than the window never trip it
+        for i in 0..6 {
+            let at = start + Duration::from_secs(6 * i);
+            assert_eq!(storm.record_failure(&config, at), None);
+        }
+        assert!(!storm.is_tripped());
+
+        let at = start + Duration::from_secs(100);
+        for i in 0..3 {
+            assert_eq!(
+                storm.record_failure(&config, at + Duration::from_secs(i)),
+                None
+            );
+        }
+        assert_eq!(
+            storm.record_failure(&config, at + Duration::from_secs(3)),
+            Some(4)
+        );
+        assert!(storm.is_tripped());
+        // Reported once, not on every further failure
+        assert_eq!(
+            storm.record_failure(&config, at + Duration::from_secs(4)),
+            None
+        );
+
+        storm.hold("lidar");
+        storm.hold("camera");
+        storm.hold("lidar");
+        assert_eq!(storm.resume(), ["camera", "lidar"]);
+        assert!(!storm.is_tripped());
+        assert_eq!(
+            storm.record_failure(&config, at + Duration::from_secs(5)),
+            None
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// Config Watch - Hot reload of the recipe whenever its file, a file it
+// includes or a file its services read their environment from changes
+
+use crate::ipc_server::ReloadRequest;
+use krill_common::{KrillConfig, SecretSource};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Time the files must stay unchanged before they are reloaded, so an
+/// editor writing one in several steps triggers a single reload
+pub const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Follows a config file and the other files it depends on through file
+/// system notifications, and reports when they settled on content that was
+/// not seen before
+pub struct ConfigWatcher {
+    path: PathBuf,
+    /// Files besides the recipe: its includes, env files and file secrets
+    files: Vec<PathBuf>,
+    debounce: Duration,
+    /// Hash of the content last reported (or present at startup)
+    seen: Option<u64>,
+    watcher: RecommendedWatcher,
+    /// Directories watched for the files; editors often save by renaming a
+    /// new file over the old one, which a watch on the file itself misses
+    dirs: HashSet<PathBuf>,
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>, debounce: Duration) -> notify::Result<Self> {
+        let path = path.into();
+        let path = std::path::absolute(&path).unwrap_or(path);
+        let (event_tx, events) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = event_tx.send(event);
+        })?;
+        let mut watcher = Self {
+            path,
+            files: Vec::new(),
+            debounce,
+            seen: None,
+            watcher,
+            dirs: HashSet::new(),
+            events,
+        };
+        watcher.set_files(Vec::new());
+        Ok(watcher)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Also watch `files`, as of the recipe's last load
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        self.files = files
+            .into_iter()
+            .map(|file| std::path::absolute(&file).unwrap_or(file))
+            .collect();
+
+        let dirs: HashSet<PathBuf> = self
+            .watched()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+        for dir in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        self.dirs.retain(|dir| dirs.contains(dir));
+        for dir in dirs {
+            if self.dirs.contains(&dir) {
+                continue;
+            }
+            match self.watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.dirs.insert(dir);
+                }
+                Err(e) => warn!("Cannot watch {:?} for changes: {}", dir, e),
+            }
+        }
+        self.seen = self.content_hash();
+    }
+
+    /// Wait until the files settle on content not seen before; `false` once
+    /// the notifications stopped. A missing recipe (e.g. mid-save) is not a
+    /// change.
+    pub async fn changed(&mut self) -> bool {
+        loop {
+            loop {
+                match self.events.recv().await {
+                    Some(event) if self.concerns(&event) => break,
+                    Some(_) => {}
+                    None => return false,
+                }
+            }
+
+            // Every further change pushes the deadline back
+            let mut deadline = Instant::now() + self.debounce;
+            loop {
+                match time::timeout_at(deadline, self.events.recv()).await {
+                    Ok(Some(event)) if self.concerns(&event) => {
+                        deadline = Instant::now() + self.debounce;
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => return false,
+                    Err(_) => break,
+                }
+            }
+
+            let hash = self.content_hash();
+            if hash.is_some() && hash != self.seen {
+                self.seen = hash;
+                return true;
+            }
+        }
+    }
+
+    fn watched(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.path).chain(&self.files)
+    }
+
+    /// Whether `event` may have changed a watched file. Reading a file is no
+    /// change; a failed notification might hide one.
+    fn concerns(&self, event: &notify::Result<Event>) -> bool {
+        match event {
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_))
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| self.watched().any(|file| file == path))
+            }
+            Err(e) => {
+                warn!("Watching {:?}: {}", self.path, e);
+                true
+            }
+        }
+    }
+
+    /// Hash of the recipe and the other files, `None` if the recipe cannot
+    /// be read. The other files may be missing; that is content too.
+    fn content_hash(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        std::fs::read(&self.path).ok()?.hash(&mut hasher);
+        for file in &self.files {
+            std::fs::read(file).ok().hash(&mut hasher);
+        }
+        Some(hasher.finish())
+    }
+}
+
+/// Files besides the recipe whose changes change the configuration: the
+/// files it includes and the env files and file secrets of its services
+fn watched_files(config: &KrillConfig) -> Vec<PathBuf> {
+    let mut files = config.included.clone();
+    for service in config.services.values() {
+        files.extend(service.env_file.iter().cloned());
+        files.extend(service.secrets.values().filter_map(|source| match source {
+            SecretSource::File(path) => Some(path.clone()),
+            SecretSource::Command(_) => None,
+        }));
+    }
+    files
+}
+
+/// Watch the config file and request a reload whenever it changes. Edits
+/// that do not load or validate are reported and leave the running
+/// configuration untouched.
+pub async fn watch_config(path: PathBuf, reload_tx: mpsc::UnboundedSender<ReloadRequest>) {
+    let mut watcher = match ConfigWatcher::new(path, WATCH_DEBOUNCE) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Cannot watch the config file for changes: {}", e);
+            return;
+        }
+    };
+    info!("Watching {:?} for changes", watcher.path());
+    if let Ok(config) = KrillConfig::from_file(&watcher.path().to_path_buf()) {
+        watcher.set_files(watched_files(&config));
+    }
+
+    while watcher.changed().await {
+        match KrillConfig::from_file(&watcher.path().to_path_buf()) {
+            Ok(config) => watcher.set_files(watched_files(&config)),
+            Err(e) => {
+                warn!(
+                    "Not reloading {:?}, keeping the running configuration: {}",
+                    watcher.path(),
+                    e
+                );
+                continue;
+            }
+        }
+
+        info!("{:?} changed, reloading", watcher.path());
+        let (result_tx, mut result_rx) = mpsc::unbounded_channel();
+        let request = ReloadRequest {
+            dry_run: false,
+            reply: result_tx,
+        };
+        if reload_tx.send(request).is_err() {
+            return;
+        }
+        match result_rx.recv().await {
+            Some(Ok(summary)) => info!(
+                "Reloaded configuration: {} added, {} removed, {} restarted, {} updated",
+                summary.added.len(),
+                summary.removed.len(),
+                summary.restarted.len(),
+                summary.updated.len()
+            ),
+            Some(Err(e)) => error!("Watched reload failed: {}", e),
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    /// Whether `watcher` reports a change within a generous time
+    async fn reports(watcher: &mut ConfigWatcher) -> bool {
+        time::timeout(Duration::from_secs(5), watcher.changed())
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Whether `watcher` stays quiet for a while
+    async fn stays_quiet(watcher: &mut ConfigWatcher) -> bool {
+        time::timeout(Duration::from_secs(1), watcher.changed())
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn test_change_is_reported_once_settled() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::write(&path, "name: a\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(&path, DEBOUNCE).unwrap();
+        std::fs::write(&path, "name: b\n").unwrap();
+        std::fs::write(&path, "name: c\n").unwrap();
+        assert!(reports(&mut watcher).await);
+        assert!(stays_quiet(&mut watcher).await);
+
+        // Removed mid-save, then written back unchanged
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "name: c\n").unwrap();
+        assert!(stays_quiet(&mut watcher).await);
+
+        // Saved by renaming a new file over it
+        let saved = dir.path().join(".krill.yaml.swp");
+        std::fs::write(&saved, "name: d\n").unwrap();
+        std::fs::rename(&saved, &path).unwrap();
+        assert!(reports(&mut watcher).await);
+    }
+
+    #[tokio::test]
+    async fn test_included_and_env_files_are_watched() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("krill.yaml");
+        std::fs::create_dir(dir.path().join("robots")).unwrap();
+        let common = dir.path().join("common.yaml");
+        let env_file = dir.path().join("robots/krill01.env");
+        std::fs::write(
+            &path,
+            r#"
+version: "1"
+name: test
+include: [common.yaml]
+services:
+  nav:
+    execute:
+      type: pixi
+      task: nav
+    env_file: robots/krill01.env
+"#,
+        )
+        .unwrap();
+        std::fs::write(&common, "env:\n  ROBOT: krill01\n").unwrap();
+        std::fs::write(&env_file, "MAP=lab\n").unwrap();
+
+        let config = KrillConfig::from_file(&path).unwrap();
+        assert_eq!(watched_files(&config), [common.clone(), env_file.clone()]);
+
+        let mut watcher = ConfigWatcher::new(&path, DEBOUNCE).unwrap();
+        watcher.set_files(watched_files(&config));
+
+        std::fs::write(&common, "env:\n  ROBOT: krill02\n").unwrap();
+        assert!(reports(&mut watcher).await);
+
+        std::fs::write(&env_file, "MAP=warehouse\n").unwrap();
+        assert!(reports(&mut watcher).await);
+
+        // Unrelated files in the same directory are no change
+        std::fs::write(dir.path().join("notes.txt"), "hello\n").unwrap();
+        assert!(stays_quiet(&mut watcher).await);
+    }
+}
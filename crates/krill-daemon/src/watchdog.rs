@@ -0,0 +1,277 @@
+// Watchdog - The daemon's own liveness, for systemd and HTTP probes
+
+use krill_common::ipc::ServiceSnapshot;
+use serde_json::json;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+/// How often the daemon checks itself when systemd asks for nothing shorter
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// Missed self-heartbeats after which the daemon reports itself not alive
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// When the daemon last proved its main loop answers
+pub struct Liveness {
+    started: Instant,
+    interval: Duration,
+    last_beat: Mutex<Option<Instant>>,
+}
+
+impl Liveness {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            interval,
+            last_beat: Mutex::new(None),
+        }
+    }
+
+    /// Interval between self-heartbeats
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn beat(&self) {
+        *self.last_beat.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Time since the last self-heartbeat, or since start before the first
+    pub fn age(&self) -> Duration {
+        self.last_beat
+            .lock()
+            .unwrap()
+            .unwrap_or(self.started)
+            .elapsed()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.age() <= self.interval * MISSED_HEARTBEATS
+    }
+
+    /// JSON body of the liveness endpoints
+    pub fn report(&self) -> serde_json::Value {
+        json!({
+            "alive": self.is_alive(),
+            "pid": std::process::id(),
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "last_heartbeat_ms": self.age().as_millis() as u64,
+        })
+    }
+}
+
+/// Variables systemd sets for the daemon to talk to it
+const NOTIFY_VARS: [&str; 3] = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// Connection to systemd when run by a `Type=notify` unit
+#[derive(Debug, Clone, Default)]
+pub struct SystemdNotifier {
+    socket: Option<OsString>,
+    watchdog: Option<Duration>,
+}
+
+impl SystemdNotifier {
+    /// Read `NOTIFY_SOCKET` and `WATCHDOG_USEC`. They stay in the daemon's
+    /// environment, so a new daemon started by an upgrade still reaches
+    /// systemd; [`hide_notify_socket`] keeps them from the services.
+    pub fn from_env() -> Self {
+        let socket = std::env::var_os("NOTIFY_SOCKET");
+        let watchdog_pid = std::env::var("WATCHDOG_PID").ok();
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0)
+            .filter(|_| {
+                watchdog_pid.is_none_or(|pid| pid.parse::<u32>().ok() == Some(std::process::id()))
+            })
+            .map(Duration::from_micros);
+        Self { socket, watchdog }
+    }
+
+    /// Half the watchdog timeout systemd set (`WatchdogSec=`), if any
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// Tell systemd the services are started. The IPC socket is up well
+    /// before, but units ordered after krill may expect the services, so
+    /// readiness waits for them. MAINPID lets a unit running `krill up`
+    /// (with `NotifyAccess=all`) follow the daemon it forks.
+    pub fn ready(&self) -> bool {
+        self.notify(&format!("READY=1\nMAINPID={}", std::process::id()))
+    }
+
+    /// Send a state such as `READY=1`; `false` when not run by systemd
+    pub fn notify(&self, state: &str) -> bool {
+        let Some(ref socket) = self.socket else {
+            return false;
+        };
+        match send_notify(socket, state) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to notify systemd of {}: {}", state, e);
+                false
+            }
+        }
+    }
+}
+
+/// Keep a process the daemon spawns from inheriting the variables systemd
+/// set for the daemon, so it cannot notify systemd in the daemon's name
+pub fn hide_notify_socket(command: &mut tokio::process::Command) -> &mut tokio::process::Command {
+    for var in NOTIFY_VARS {
+        command.env_remove(var);
+    }
+    command
+}
+
+#[cfg(unix)]
+fn send_notify(path: &OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in the abstract namespace
+    if let Some(name) = path.as_encoded_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(std::io::ErrorKind::Unsupported.into());
+        }
+    }
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notify(_path: &OsStr, _state: &str) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Interval of the self-heartbeat: [`HEARTBEAT_INTERVAL`], or shorter if
+/// the systemd watchdog needs it
+pub fn heartbeat_interval(notifier: &SystemdNotifier) -> Duration {
+    notifier
+        .watchdog_interval()
+        .map_or(HEARTBEAT_INTERVAL, |interval| {
+            interval.min(HEARTBEAT_INTERVAL)
+        })
+}
+
+/// Check every interval that the daemon still answers snapshot requests,
+/// which go through the same tasks and locks as every command. Each answer
+/// is a self-heartbeat and, under a systemd watchdog, a `WATCHDOG=1` ping; a
+/// hung daemon stops pinging and systemd restarts it.
+pub async fn run(
+    liveness: Arc<Liveness>,
+    snapshot_req_tx: mpsc::UnboundedSender<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>,
+    notifier: SystemdNotifier,
+) {
+    let systemd_watchdog = notifier.watchdog_interval().is_some();
+    if systemd_watchdog {
+        info!(
+            "Pinging the systemd watchdog every {:?}",
+            liveness.interval()
+        );
+    }
+    let mut interval = time::interval(liveness.interval());
+    loop {
+        interval.tick().await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        if snapshot_req_tx.send(tx).is_err() {
+            debug!("Snapshot handler is gone; stopping self-heartbeats");
+            return;
+        }
+        match time::timeout(liveness.interval(), rx.recv()).await {
+            Ok(Some(_)) => {
+                liveness.beat();
+                if systemd_watchdog {
+                    notifier.notify("WATCHDOG=1");
+                }
+            }
+            _ => warn!(
+                "Daemon did not answer its self-heartbeat within {:?}",
+                liveness.interval()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_heartbeat_needs_snapshot_answers() {
+        let liveness = Arc::new(Liveness::new(Duration::from_millis(25)));
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let task = tokio::spawn(run(
+            Arc::clone(&liveness),
+            snapshot_req_tx,
+            SystemdNotifier::default(),
+        ));
+
+        // Requests go unanswered: no heartbeat, and stale after 3 intervals
+        let pending = snapshot_req_rx.recv().await.unwrap();
+        time::sleep(Duration::from_millis(120)).await;
+        assert!(!liveness.is_alive());
+        assert_eq!(liveness.report()["alive"], false);
+
+        drop(pending);
+        let responder = tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ = response_tx.send(HashMap::new());
+            }
+        });
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(liveness.is_alive());
+
+        task.abort();
+        responder.abort();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_notify_sends_datagram() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+
+        assert!(!SystemdNotifier::default().notify("READY=1"));
+        let notifier = SystemdNotifier {
+            socket: Some(path.into_os_string()),
+            watchdog: Some(Duration::from_secs(10)),
+        };
+        assert!(notifier.notify("READY=1"));
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(5)));
+
+        let mut buf = [0u8; 64];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_spawned_processes_do_not_inherit_notify_socket() {
+        let mut command = tokio::process::Command::new("sh");
+        command
+            .args(["-c", "echo ${NOTIFY_SOCKET:-unset}"])
+            .env("NOTIFY_SOCKET", "/run/systemd/notify");
+        let output = hide_notify_socket(&mut command).output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "unset");
+    }
+}
@@ -0,0 +1,481 @@
+// Workspaces - Several workspaces hosted by one daemon, addressed as `workspace/service`
+
+use crate::handoff::HandedService;
+use crate::orchestrator::{Orchestrator, OrchestratorError, ServiceEvent};
+use crate::scheduler::Scheduler;
+use crate::state::{DaemonState, StateFile};
+use crate::storm::StormTripped;
+use krill_common::{
+    CommandAction, KrillConfig, KrillError, ProcessOutputLine, ServiceMetrics, ServiceShutdown,
+    ServiceSnapshot, ServiceStatus,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Separates a hosted workspace from its services and groups in names
+pub const WORKSPACE_SEPARATOR: char = '/';
+
+/// Name under which `service` of a hosted `workspace` is known to clients
+pub fn qualified_name(workspace: &str, service: &str) -> String {
+    format!("{}{}{}", workspace, WORKSPACE_SEPARATOR, service)
+}
+
+struct Hosted {
+    orchestrator: Arc<Orchestrator>,
+    /// Processes left behind by a crashed daemon, taken over by `start_hosted`
+    previous_state: Mutex<Option<DaemonState>>,
+}
+
+/// The daemon's own workspace plus the workspaces it hosts. Services and
+/// groups of the own workspace keep their plain names; those of a hosted
+/// workspace are addressed as `workspace/name`. Every workspace keeps its
+/// own DAG, state file and log directory.
+pub struct Workspaces {
+    primary: Arc<Orchestrator>,
+    hosted: BTreeMap<String, Hosted>,
+    primary_name: String,
+    tasks: Vec<JoinHandle<()>>,
+    /// Maintenance actions of the services, listed in their snapshots
+    scheduler: Option<Arc<Scheduler>>,
+    /// Where the workspaces added from now on report a restart storm
+    storm_tx: Option<mpsc::UnboundedSender<StormTripped>>,
+}
+
+impl Workspaces {
+    pub fn new(primary_name: impl Into<String>, primary: Arc<Orchestrator>) -> Self {
+        Self {
+            primary,
+            hosted: BTreeMap::new(),
+            primary_name: primary_name.into(),
+            tasks: Vec::new(),
+            scheduler: None,
+            storm_tx: None,
+        }
+    }
+
+    /// List the upcoming maintenance actions of `scheduler` in snapshots,
+    /// and plan the `schedule:` actions of the workspaces added from now on
+    pub fn set_scheduler(&mut self, scheduler: Arc<Scheduler>) {
+        self.scheduler = Some(scheduler);
+    }
+
+    /// Report to `storm_tx` when `restart_storm` pauses the automatic
+    /// restarts of a workspace added from now on
+    pub fn set_storm_tx(&mut self, storm_tx: mpsc::UnboundedSender<StormTripped>) {
+        self.storm_tx = Some(storm_tx);
+    }
+
+    /// Host the workspace of `config`. Its status events and output lines
+    /// are forwarded to `event_tx` and `log_tx` under qualified names.
+    pub fn add(
+        &mut self,
+        config: KrillConfig,
+        event_tx: mpsc::UnboundedSender<ServiceEvent>,
+        log_tx: Option<mpsc::UnboundedSender<ProcessOutputLine>>,
+    ) -> Result<(), OrchestratorError> {
+        let name = config.name.clone();
+        if name == self.primary_name || self.hosted.contains_key(&name) {
+            return Err(OrchestratorError::WorkspaceExists(name));
+        }
+
+        let (hosted_event_tx, mut hosted_event_rx) = mpsc::unbounded_channel::<ServiceEvent>();
+        let hosted_log_tx = log_tx.as_ref().map(|log_tx| {
+            let (hosted_log_tx, mut hosted_log_rx) = mpsc::unbounded_channel::<ProcessOutputLine>();
+            let log_tx = log_tx.clone();
+            let workspace = name.clone();
+            self.tasks.push(tokio::spawn(async move {
+                while let Some(mut output) = hosted_log_rx.recv().await {
+                    output.service = qualified_name(&workspace, &output.service);
+                    let _ = log_tx.send(output);
+                }
+            }));
+            hosted_log_tx
+        });
+
+        let state_file = StateFile::new(StateFile::default_path(&name));
+        let previous_state = state_file.load().unwrap_or_else(|e| {
+            warn!(
+                "Ignoring unreadable state file {:?}: {}",
+                state_file.path(),
+                e
+            );
+            None
+        });
+
+        if let Some(ref scheduler) = self.scheduler {
+            scheduler.set_recipe(Some(&name), &config);
+        }
+
+        let mut orchestrator = Orchestrator::hosted(config, hosted_event_tx, hosted_log_tx)?;
+        orchestrator.set_state_file(state_file);
+        if let Some(ref storm_tx) = self.storm_tx {
+            orchestrator.set_storm_tx(storm_tx.clone());
+        }
+        let orchestrator = Arc::new(orchestrator);
+
+        let orchestrator_clone = Arc::clone(&orchestrator);
+        let workspace = name.clone();
+        self.tasks.push(tokio::spawn(async move {
+            while let Some((service, status)) = hosted_event_rx.recv().await {
+                orchestrator_clone.state_changed();
+                let _ = event_tx.send((qualified_name(&workspace, &service), status));
+            }
+        }));
+
+        let orchestrator_clone = Arc::clone(&orchestrator);
+        self.tasks.push(tokio::spawn(async move {
+            orchestrator_clone.persist_state_loop().await;
+        }));
+
+        info!("Hosting workspace '{}'", name);
+        self.hosted.insert(
+            name,
+            Hosted {
+                orchestrator,
+                previous_state: Mutex::new(previous_state),
+            },
+        );
+        Ok(())
+    }
+
+    /// Recover and start the services of every hosted workspace. A workspace
+    /// that fails to start is logged and left to its own.
+    pub async fn start_hosted(&self) {
+        for (name, hosted) in &self.hosted {
+            if let Some(previous) = hosted.previous_state.lock().await.take() {
+                hosted.orchestrator.recover(&previous).await;
+            }
+            info!("Starting services of workspace '{}'...", name);
+            if let Err(e) = hosted.orchestrator.start_all().await {
+                error!("Failed to start workspace '{}': {}", name, e);
+            }
+        }
+    }
+
+    /// The orchestrator owning `name` and the name within its workspace
+    fn resolve<'a>(&self, name: &'a str) -> Result<(&Arc<Orchestrator>, &'a str), KrillError> {
+        match name.split_once(WORKSPACE_SEPARATOR) {
+            None => Ok((&self.primary, name)),
+            Some((workspace, local)) => self
+                .hosted
+                .get(workspace)
+                .map(|hosted| (&hosted.orchestrator, local))
+                .ok_or_else(|| KrillError::ServiceNotFound(name.to_string())),
+        }
+    }
+
+    /// Apply a client command. `StopDaemon` is left to the caller.
+    pub async fn execute(
+        &self,
+        action: CommandAction,
+        target: Option<String>,
+    ) -> Result<(), KrillError> {
+        if action == CommandAction::EmergencyStop {
+            self.emergency_stop().await;
+            return Ok(());
+        }
+        if action == CommandAction::ResumeRestarts {
+            return self.resume_restarts(target.as_deref()).await;
+        }
+        let target = target.ok_or_else(|| {
+            KrillError::InvalidRequest(format!("{:?} command requires a target", action))
+        })?;
+        let (orchestrator, name) = self.resolve(&target).map_err(|e| match action {
+            CommandAction::StartGroup | CommandAction::StopGroup => {
+                KrillError::GroupNotFound(target.clone())
+            }
+            _ => e,
+        })?;
+
+        let result = match action {
+            CommandAction::Start => orchestrator.start_service(name).await,
+            CommandAction::Stop => orchestrator.stop_service(name).await,
+            CommandAction::Restart => orchestrator.restart_service(name).await,
+            CommandAction::Kill => orchestrator.kill_service(name).await,
+            CommandAction::Reload => orchestrator.reload_service(name).await,
+            CommandAction::StartGroup => orchestrator.start_group(name).await,
+            CommandAction::StopGroup => orchestrator.stop_group(name).await,
+            CommandAction::ClearSafetyStop => orchestrator.clear_safety_stop(name).await,
+            CommandAction::Pause => orchestrator.pause_service(name).await,
+            CommandAction::Resume => orchestrator.resume_service(name).await,
+            CommandAction::EmergencyStop
+            | CommandAction::ResumeRestarts
+            | CommandAction::StopDaemon
+            | CommandAction::UpgradeDaemon
+            | CommandAction::SetLogFilter => {
+                return Err(KrillError::InvalidRequest(format!(
+                    "{:?} is not a service command",
+                    action
+                )))
+            }
+        };
+        result.map_err(|e| qualify_error(KrillError::from(e), &target, name))
+    }
+
+    /// Emergency stop every workspace
+    pub async fn emergency_stop(&self) {
+        self.primary.emergency_stop().await;
+        for hosted in self.hosted.values() {
+            hosted.orchestrator.emergency_stop().await;
+        }
+    }
+
+    /// Resume the automatic restarts of `workspace`, or of every workspace
+    pub async fn resume_restarts(&self, workspace: Option<&str>) -> Result<(), KrillError> {
+        match workspace {
+            None => {
+                self.primary.resume_restarts().await;
+                for hosted in self.hosted.values() {
+                    hosted.orchestrator.resume_restarts().await;
+                }
+            }
+            Some(name) if name == self.primary_name => self.primary.resume_restarts().await,
+            Some(name) => match self.hosted.get(name) {
+                Some(hosted) => hosted.orchestrator.resume_restarts().await,
+                None => {
+                    return Err(KrillError::InvalidRequest(format!(
+                        "Unknown workspace '{}'",
+                        name
+                    )))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub async fn process_heartbeat(
+        &self,
+        service: &str,
+        status: ServiceStatus,
+        metadata: HashMap<String, String>,
+    ) -> Result<(), KrillError> {
+        let (orchestrator, name) = self.resolve(service)?;
+        orchestrator
+            .process_heartbeat(name, status, metadata)
+            .await
+            .map_err(|e| qualify_error(KrillError::from(e), service, name))
+    }
+
+    /// Snapshot of every workspace, with hosted services under qualified names
+    pub async fn get_snapshot(&self) -> HashMap<String, ServiceSnapshot> {
+        let mut snapshot = self.primary.get_snapshot().await;
+        for (workspace, hosted) in &self.hosted {
+            for (name, mut service) in hosted.orchestrator.get_snapshot().await {
+                qualify_snapshot(workspace, &mut service);
+                snapshot.insert(qualified_name(workspace, &name), service);
+            }
+        }
+        if let Some(ref scheduler) = self.scheduler {
+            for (name, service) in snapshot.iter_mut() {
+                service.maintenance = scheduler.upcoming(name);
+            }
+        }
+        snapshot
+    }
+
+    /// Resource usage of every workspace, with hosted services under qualified names
+    pub async fn sample_metrics(&self) -> HashMap<String, ServiceMetrics> {
+        let mut metrics = self.primary.sample_metrics().await;
+        for (workspace, hosted) in &self.hosted {
+            for (name, sample) in hosted.orchestrator.sample_metrics().await {
+                metrics.insert(qualified_name(workspace, &name), sample);
+            }
+        }
+        metrics
+    }
+
+    /// Every service of every workspace, with hosted services under qualified
+    /// names, for handing over to an upgraded daemon
+    pub async fn process_table(&self) -> BTreeMap<String, HandedService> {
+        let mut table = self.primary.process_table().await;
+        for (workspace, hosted) in &self.hosted {
+            for (name, service) in hosted.orchestrator.process_table().await {
+                table.insert(qualified_name(workspace, &name), service);
+            }
+        }
+        table
+    }
+
+    /// Take over the services handed over by the daemon this one replaces,
+    /// in place of recovering and starting them
+    pub async fn take_over(&self, table: BTreeMap<String, HandedService>) {
+        let mut primary = BTreeMap::new();
+        let mut hosted: BTreeMap<&str, BTreeMap<String, HandedService>> = BTreeMap::new();
+        for (name, service) in table {
+            match name.split_once(WORKSPACE_SEPARATOR) {
+                None => {
+                    primary.insert(name, service);
+                }
+                Some((workspace, local)) => match self.hosted.get_key_value(workspace) {
+                    Some((workspace, _)) => {
+                        hosted
+                            .entry(workspace.as_str())
+                            .or_default()
+                            .insert(local.to_string(), service);
+                    }
+                    None => warn!(
+                        "Workspace of '{}' is no longer hosted; leaving its process alone",
+                        name
+                    ),
+                },
+            }
+        }
+
+        let adopted = self.primary.take_over(primary).await;
+        info!("Took over {} running service(s)", adopted.len());
+        for (name, hosted_workspace) in &self.hosted {
+            let services = hosted.remove(name.as_str()).unwrap_or_default();
+            let adopted = hosted_workspace.orchestrator.take_over(services).await;
+            info!(
+                "Took over {} running service(s) of workspace '{}'",
+                adopted.len(),
+                name
+            );
+        }
+    }
+
+    /// Gracefully stop the hosted workspaces, then the daemon's own one,
+    /// reporting how each running service was stopped. A workspace that
+    /// fails to shut down is logged; the others are still reported.
+    pub async fn shutdown(&self) -> Vec<ServiceShutdown> {
+        let mut report = Vec::new();
+        for (name, hosted) in &self.hosted {
+            match hosted.orchestrator.shutdown().await {
+                Ok(stopped) => report.extend(stopped.into_iter().map(|mut service| {
+                    service.service = qualified_name(name, &service.service);
+                    service
+                })),
+                Err(e) => error!("Error during shutdown of workspace '{}': {}", name, e),
+            }
+        }
+        match self.primary.shutdown().await {
+            Ok(stopped) => report.extend(stopped),
+            Err(e) => error!("Error during shutdown: {}", e),
+        }
+        for task in &self.tasks {
+            task.abort();
+        }
+        report
+    }
+}
+
+/// Refer to dependencies of a hosted service by their qualified names
+fn qualify_snapshot(workspace: &str, service: &mut ServiceSnapshot) {
+    for dependency in service.config.dependencies.iter_mut() {
+        dependency.service = qualified_name(workspace, &dependency.service);
+    }
+    for group in service.config.groups.iter_mut() {
+        *group = qualified_name(workspace, group);
+    }
+    service.legacy_config = (&service.config).into();
+}
+
+/// Name the service or group of an error raised inside a hosted workspace
+/// the way the client addressed it
+fn qualify_error(err: KrillError, qualified: &str, local: &str) -> KrillError {
+    if qualified == local {
+        return err;
+    }
+    let qualify = |name: String| {
+        if name == local {
+            qualified.to_string()
+        } else {
+            name
+        }
+    };
+    match err {
+        KrillError::ServiceNotFound(name) => KrillError::ServiceNotFound(qualify(name)),
+        KrillError::GroupNotFound(name) => KrillError::GroupNotFound(qualify(name)),
+        KrillError::SafetyStopped(name) => KrillError::SafetyStopped(qualify(name)),
+        KrillError::InvalidState { service, reason } => KrillError::InvalidState {
+            service: qualify(service),
+            reason,
+        },
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> KrillConfig {
+        KrillConfig::parse(yaml, None).unwrap()
+    }
+
+    const SERVICES: &str = r#"
+services:
+  driver:
+    execute:
+      type: shell
+      command: sleep 60
+  planner:
+    execute:
+      type: shell
+      command: sleep 60
+    dependencies:
+      - driver
+"#;
+
+    #[tokio::test]
+    async fn test_hosted_services_are_qualified() {
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let primary = config(&format!("version: \"1\"\nname: base\n{}", SERVICES));
+        let primary = Arc::new(Orchestrator::new(primary, event_tx.clone()).unwrap());
+        let mut workspaces = Workspaces::new("base", primary);
+        workspaces
+            .add(
+                config(&format!("version: \"1\"\nname: arm\n{}", SERVICES)),
+                event_tx.clone(),
+                None,
+            )
+            .unwrap();
+
+        let err = workspaces
+            .add(
+                config(&format!("version: \"1\"\nname: base\n{}", SERVICES)),
+                event_tx,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(err, OrchestratorError::WorkspaceExists(name) if name == "base"));
+
+        let snapshot = workspaces.get_snapshot().await;
+        let mut names: Vec<&str> = snapshot.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["arm/driver", "arm/planner", "driver", "planner"]);
+        assert_eq!(
+            snapshot["arm/planner"]
+                .config
+                .dependency_names()
+                .collect::<Vec<_>>(),
+            ["arm/driver"]
+        );
+        assert_eq!(
+            snapshot["planner"]
+                .config
+                .dependency_names()
+                .collect::<Vec<_>>(),
+            ["driver"]
+        );
+
+        let err = workspaces
+            .execute(CommandAction::Stop, Some("arm/radar".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err, KrillError::ServiceNotFound("arm/radar".to_string()));
+        let err = workspaces
+            .execute(CommandAction::Stop, Some("leg/driver".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err, KrillError::ServiceNotFound("leg/driver".to_string()));
+        let err = workspaces
+            .execute(CommandAction::StartGroup, Some("leg/sensors".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err, KrillError::GroupNotFound("leg/sensors".to_string()));
+    }
+}
@@ -4,10 +4,11 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use krill_common::{
-    ExecuteConfig, KrillConfig, PolicyConfig, RestartPolicy, ServiceConfig, ServiceStatus,
+    ContainerEngine, EmergencyAction, ExecuteConfig, HealthDebounce, KrillConfig, PolicyConfig,
+    RestartPolicy, ServiceConfig, ServiceStatus,
 };
 use krill_daemon::runner::ServiceState;
-use krill_daemon::{LogStore, Orchestrator, ServiceRunner};
+use krill_daemon::{LogStore, Orchestrator, OrchestratorError, ServiceRunner};
 use tempfile::TempDir;
 
 // ---------------------------------------------------------------------------
@@ -25,12 +26,34 @@ fn make_service_config(policy: RestartPolicy, max_restarts: u32) -> ServiceConfi
         dependencies: vec![],
         critical: false,
         gpu: false,
+        gpu_health: false,
+        labels: Default::default(),
+        oneshot: false,
         health_check: None,
+        readiness: None,
+        health_debounce: None,
+        env_file: None,
+        env: HashMap::new(),
+        secrets: Default::default(),
+        start_timeout_sec: None,
+        start_delay: None,
+        dependency_timeout: None,
+        wait_for: Vec::new(),
+        wait_for_timeout: None,
+        requires_ports: Vec::new(),
+        protect: false,
+        limits: None,
+        cpu_affinity: None,
+        scheduler: None,
+        hooks: None,
+        schedule: Default::default(),
+        log_buffer_lines: None,
         policy: PolicyConfig {
             restart: policy,
             max_restarts,
             restart_delay: Duration::from_secs(1),
             stop_timeout: Duration::from_secs(5),
+            ..Default::default()
         },
     }
 }
@@ -59,8 +82,23 @@ fn make_single_service_krill_config() -> KrillConfig {
         version: "1".to_string(),
         name: "test-workspace".to_string(),
         log_dir: None,
+        log_rotation: Default::default(),
+        log_format: Default::default(),
+        log_buffer_lines: 5000,
+        log_buffer_max_mb: 64,
         env: HashMap::new(),
+        groups: HashMap::new(),
+        prometheus: Default::default(),
+        access: Default::default(),
+        emergency_stop_cmd: None,
+        on_emergency: vec![EmergencyAction::StopServices],
+        restart_storm: None,
+        max_concurrent_starts: None,
+        daemon_log: Default::default(),
+        log_throttle: Default::default(),
         services,
+        socket: None,
+        included: Vec::new(),
     }
 }
 
@@ -176,6 +214,51 @@ mod log_store_tests {
     }
 }
 
+// ===========================================================================
+// EventJournal tests
+// ===========================================================================
+
+mod event_journal_tests {
+    use super::*;
+    use krill_daemon::EventJournal;
+
+    #[test]
+    fn test_query_filters_by_service_and_trims_to_capacity() {
+        let journal = EventJournal::new(3);
+        journal.record("lidar", ServiceStatus::Starting);
+        journal.record("camera", ServiceStatus::Starting);
+        journal.record("lidar", ServiceStatus::Running);
+        journal.record("lidar", ServiceStatus::Failed);
+
+        let all = journal.query(None, None);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].service, "camera");
+
+        let lidar: Vec<ServiceStatus> = journal
+            .query(Some(Duration::from_secs(60)), Some("lidar"))
+            .into_iter()
+            .map(|event| event.status)
+            .collect();
+        assert_eq!(lidar, vec![ServiceStatus::Running, ServiceStatus::Failed]);
+    }
+
+    #[test]
+    fn test_journal_file_holds_one_event_per_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = EventJournal::session_path(temp_dir.path());
+        let journal = EventJournal::with_file(10, &path).unwrap();
+        journal.record("lidar", ServiceStatus::Running);
+        journal.record("lidar", ServiceStatus::Stopped);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<krill_common::EventRecord> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events, journal.query(None, None));
+    }
+}
+
 // ===========================================================================
 // ServiceRunner tests
 // ===========================================================================
@@ -189,6 +272,24 @@ mod service_runner_tests {
         assert_eq!(runner.state(), ServiceState::Pending);
     }
 
+    #[test]
+    fn test_take_over_keeps_restart_counts() {
+        use krill_daemon::handoff::HandedService;
+
+        let mut runner = make_runner("test-svc", make_default_service_config());
+        runner.take_over(&HandedService {
+            status: ServiceStatus::Failed,
+            restart_count: 2,
+            total_restarts: 7,
+            process: None,
+            stdout_fd: None,
+            stderr_fd: None,
+        });
+        assert_eq!(runner.state(), ServiceState::Failed);
+        assert_eq!(runner.restart_count(), 2);
+        assert_eq!(runner.total_restarts(), 7);
+    }
+
     #[test]
     fn test_update_health_running_to_healthy() {
         let config = make_service_config(RestartPolicy::OnFailure, 3);
@@ -233,12 +334,34 @@ mod service_runner_tests {
             dependencies: vec![],
             critical: false,
             gpu: false,
+            gpu_health: false,
+            labels: Default::default(),
+            oneshot: false,
             health_check: None,
+            readiness: None,
+            health_debounce: None,
+            env_file: None,
+            env: HashMap::new(),
+            secrets: Default::default(),
+            start_timeout_sec: None,
+            start_delay: None,
+            dependency_timeout: None,
+            wait_for: Vec::new(),
+            wait_for_timeout: None,
+            requires_ports: Vec::new(),
+            protect: false,
+            limits: None,
+            cpu_affinity: None,
+            scheduler: None,
+            hooks: None,
+            schedule: Default::default(),
+            log_buffer_lines: None,
             policy: PolicyConfig {
                 restart: RestartPolicy::OnFailure,
                 max_restarts: 3,
                 restart_delay: Duration::from_secs(1),
                 stop_timeout: Duration::from_secs(2),
+                ..Default::default()
             },
         };
 
@@ -266,6 +389,111 @@ mod service_runner_tests {
         assert_eq!(runner.state(), ServiceState::Stopped);
     }
 
+    #[tokio::test]
+    async fn test_health_debounce_and_flapping() {
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.health_debounce = Some(HealthDebounce {
+            checks: 2,
+            flapping_threshold: Some(3),
+            flapping_window: Duration::from_secs(60),
+        });
+        let mut runner = make_runner("flappy", config);
+        runner.start().await.unwrap();
+
+        // A single verdict does not change the health
+        runner.update_health(true);
+        assert_eq!(runner.state(), ServiceState::Running);
+        runner.update_health(true);
+        assert_eq!(runner.state(), ServiceState::Healthy);
+
+        // An interrupted streak starts over
+        runner.update_health(false);
+        runner.update_health(true);
+        runner.update_health(false);
+        assert_eq!(runner.get_status(), ServiceStatus::Healthy);
+        runner.update_health(false);
+        assert_eq!(runner.get_status(), ServiceStatus::Degraded);
+
+        runner.update_health(true);
+        runner.update_health(true);
+        assert_eq!(runner.get_status(), ServiceStatus::Healthy);
+        assert_eq!(runner.flapping(), None);
+
+        // The third change within the window marks it flapping, which keeps
+        // it degraded while healthy
+        runner.update_health(false);
+        runner.update_health(false);
+        runner.update_health(true);
+        runner.update_health(true);
+        assert_eq!(runner.state(), ServiceState::Healthy);
+        assert_eq!(runner.get_status(), ServiceStatus::Degraded);
+        assert!(runner.flapping().unwrap().starts_with("flapping"));
+
+        // A restart starts with a clean record
+        runner.stop().await.unwrap();
+        runner.start().await.unwrap();
+        assert_eq!(runner.flapping(), None);
+        runner.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_history_exit_code_and_total_restarts() {
+        let mut config = make_service_config(RestartPolicy::OnFailure, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "exit 3".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        let mut runner = make_runner("svc", config);
+        assert!(runner.state_history().is_empty());
+
+        runner.start().await.unwrap();
+        let mut exit_code = None;
+        for _ in 0..100 {
+            exit_code = runner.get_exit_code();
+            if exit_code.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(exit_code, Some(3));
+        assert_eq!(runner.last_exit_code(), Some(3));
+        runner.mark_failed(None);
+        assert_eq!(runner.total_restarts(), 0);
+
+        // The policy counter resets, the total does not
+        runner.start().await.unwrap();
+        runner.reset_restart_count();
+        assert_eq!(runner.total_restarts(), 1);
+
+        let statuses: Vec<ServiceStatus> = runner
+            .state_history()
+            .into_iter()
+            .map(|transition| transition.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ServiceStatus::Starting,
+                ServiceStatus::Running,
+                ServiceStatus::Failed,
+                ServiceStatus::Starting,
+                ServiceStatus::Running,
+            ]
+        );
+        assert!(runner.state_age() < Duration::from_secs(5));
+
+        let availability = runner.availability();
+        assert_eq!(availability.failures, 1);
+        assert_eq!(availability.mtbf, Some(availability.uptime));
+        assert!(availability.uptime + availability.downtime < Duration::from_secs(5));
+    }
+
     #[test]
     fn test_should_restart_never_returns_false() {
         let config = make_service_config(RestartPolicy::Never, 0);
@@ -365,12 +593,34 @@ mod service_runner_tests {
             dependencies: vec![],
             critical: false,
             gpu: false,
+            gpu_health: false,
+            labels: Default::default(),
+            oneshot: false,
             health_check: None,
+            readiness: None,
+            health_debounce: None,
+            env_file: None,
+            env: HashMap::new(),
+            secrets: Default::default(),
+            start_timeout_sec: None,
+            start_delay: None,
+            dependency_timeout: None,
+            wait_for: Vec::new(),
+            wait_for_timeout: None,
+            requires_ports: Vec::new(),
+            protect: false,
+            limits: None,
+            cpu_affinity: None,
+            scheduler: None,
+            hooks: None,
+            schedule: Default::default(),
+            log_buffer_lines: None,
             policy: PolicyConfig {
                 restart: RestartPolicy::Never,
                 max_restarts: 0,
                 restart_delay: Duration::from_secs(1),
                 stop_timeout: Duration::from_secs(2),
+                ..Default::default()
             },
         };
         let mut runner = make_runner("svc", config);
@@ -382,6 +632,530 @@ mod service_runner_tests {
         assert_eq!(runner.get_status(), ServiceStatus::Stopped);
     }
 
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_limits_apply_nice_value() {
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.limits = Some(krill_common::ResourceLimits {
+            nice: Some(10),
+            ..Default::default()
+        });
+        let mut runner = make_runner("svc", config);
+
+        runner.start().await.unwrap();
+        let pid = runner.pid().unwrap();
+
+        // Field 19 of /proc/<pid>/stat, counted after the `(comm)` field
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+        let fields: Vec<&str> = stat
+            .rsplit_once(')')
+            .unwrap()
+            .1
+            .split_whitespace()
+            .collect();
+        assert_eq!(fields[16], "10");
+
+        // Without usage above the limits the service stays running
+        assert!(!runner.check_limits(None));
+        assert_eq!(runner.get_status(), ServiceStatus::Running);
+
+        runner.stop().await.unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_limit_violations_are_debounced() {
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.limits = Some(krill_common::ResourceLimits {
+            memory: Some(100 << 20),
+            ..Default::default()
+        });
+        config.health_debounce = Some(HealthDebounce {
+            checks: 2,
+            ..Default::default()
+        });
+        let mut runner = make_runner("svc", config);
+        runner.start().await.unwrap();
+
+        // Without `memory_rlimit` the address space is left alone
+        let limits =
+            std::fs::read_to_string(format!("/proc/{}/limits", runner.pid().unwrap())).unwrap();
+        let address_space = limits
+            .lines()
+            .find(|line| line.starts_with("Max address space"))
+            .unwrap();
+        assert!(address_space.contains("unlimited"), "{}", address_space);
+
+        let sample = |memory_bytes| krill_common::ServiceMetrics {
+            cpu_percent: 1.0,
+            memory_bytes,
+            threads: 1,
+        };
+        assert!(!runner.check_limits(Some(&sample(200 << 20))));
+        assert_eq!(runner.get_status(), ServiceStatus::Running);
+        assert!(runner.check_limits(Some(&sample(200 << 20))));
+        assert_eq!(runner.get_status(), ServiceStatus::Degraded);
+
+        // A single sample back within the limits does not restore it
+        assert!(!runner.check_limits(Some(&sample(50 << 20))));
+        assert!(!runner.check_limits(Some(&sample(200 << 20))));
+        assert!(!runner.check_limits(Some(&sample(50 << 20))));
+        assert_eq!(runner.get_status(), ServiceStatus::Degraded);
+        assert!(runner.check_limits(Some(&sample(50 << 20))));
+        assert_eq!(runner.get_status(), ServiceStatus::Running);
+
+        runner.stop().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_gpu_problem_degrades_gpu_health_services() {
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        let mut runner = make_runner("detector", config.clone());
+        runner.start().await.unwrap();
+
+        // Only services asking for the probe are affected by it
+        assert!(!runner.check_gpu(Some("GPU 0 is throttling (thermal slowdown)")));
+        assert_eq!(runner.get_status(), ServiceStatus::Running);
+
+        config.gpu_health = true;
+        runner.update_config(config, HashMap::new());
+        assert!(runner.check_gpu(Some("GPU 0 is throttling (thermal slowdown)")));
+        assert_eq!(runner.get_status(), ServiceStatus::Degraded);
+        assert_eq!(
+            runner.gpu_problem(),
+            Some("GPU 0 is throttling (thermal slowdown)")
+        );
+
+        assert!(runner.check_gpu(None));
+        assert_eq!(runner.get_status(), ServiceStatus::Running);
+        runner.stop().await.unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_gpu_service_refused_without_gpu() {
+        // Machines with an NVIDIA GPU start the service
+        if krill_daemon::gpu::detect().await.is_ok() {
+            return;
+        }
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.gpu = true;
+        let mut runner = make_runner("detector", config);
+
+        let err = runner.start().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the service requires a GPU, but none was detected"),
+            "unexpected error: {}",
+            err
+        );
+        assert_eq!(runner.pid(), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_service_leads_the_group_of_its_children() {
+        let dir = TempDir::new().unwrap();
+        let pid_file = dir.path().join("child.pid");
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: format!("sleep 60 & echo $! > {}; wait", pid_file.display()),
+            stop_command: None,
+            working_dir: None,
+        };
+        let mut runner = make_runner("svc", config);
+        runner.start().await.unwrap();
+        let pid = runner.pid().unwrap();
+        assert_eq!(krill_common::get_process_group(pid).unwrap(), pid);
+
+        let mut child = None;
+        for _ in 0..50 {
+            child = std::fs::read_to_string(&pid_file)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok());
+            if child.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let child = child.expect("service did not record its child");
+        assert_eq!(krill_common::get_process_group(child).unwrap(), pid);
+
+        runner.stop().await.unwrap();
+        let gone = |pid: u32| krill_common::get_process_group(pid).is_err();
+        for _ in 0..50 {
+            if gone(child) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(gone(child), "stopping the service left its child running");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stop_ends_processes_that_left_the_group() {
+        use krill_daemon::{reaper, state};
+
+        let dir = TempDir::new().unwrap();
+        let pid_file = dir.path().join("orphan.pid");
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: format!(
+                "setsid sleep 60 & echo $! > {}; exec sleep 60",
+                pid_file.display()
+            ),
+            stop_command: None,
+            working_dir: None,
+        };
+        let mut runner = make_runner("svc", config);
+        runner.start().await.unwrap();
+
+        let mut orphan = None;
+        for _ in 0..50 {
+            orphan = std::fs::read_to_string(&pid_file)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok());
+            if orphan.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let orphan = orphan.expect("service did not record its orphan");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let ticks = state::start_ticks(orphan);
+        assert!(ticks.is_some());
+
+        // The orphan has its own session, out of reach of the group signal.
+        // It is ended in the background, without holding the runner.
+        runner.stop().await.unwrap();
+        assert_eq!(runner.get_status(), ServiceStatus::Stopped);
+        reaper::settle().await;
+        assert!(!state::process_alive(orphan, ticks));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_sequence_runs_configured_signal() {
+        use krill_common::{StopAction, StopStep};
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "exec sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.policy.stop_sequence = vec![StopStep {
+            action: StopAction::Sigint,
+            timeout: Duration::from_secs(5),
+        }];
+        let mut runner = make_runner("svc", config);
+
+        runner.start().await.unwrap();
+        let started = std::time::Instant::now();
+        runner.stop().await.unwrap();
+
+        // SIGINT ends `sleep` well before the step times out and SIGKILL follows
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(runner.get_status(), ServiceStatus::Stopped);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_run_around_start_and_stop() {
+        use krill_common::{LogStream, ServiceHooks};
+
+        let dir = TempDir::new().unwrap();
+        let trace = dir.path().join("hooks.log");
+        let hook = |name: &str| Some(format!("echo {} >> {}", name, trace.display()));
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some(format!(
+                "echo resetting usb && echo pre_start >> {}",
+                trace.display()
+            )),
+            post_start: hook("post_start"),
+            pre_stop: hook("pre_stop"),
+            post_stop: hook("post_stop"),
+            ..Default::default()
+        });
+        let mut runner = make_runner("lidar", config);
+        let (log_tx, mut log_rx) = tokio::sync::mpsc::unbounded_channel();
+        runner.set_log_tx(log_tx);
+
+        runner.start().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&trace).unwrap(),
+            "pre_start\npost_start\n"
+        );
+        runner.stop().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&trace).unwrap(),
+            "pre_start\npost_start\npre_stop\npost_stop\n"
+        );
+
+        let output = log_rx.try_recv().unwrap();
+        assert_eq!(output.service, "lidar");
+        assert_eq!(output.line, "[pre_start] resetting usb");
+        assert_eq!(output.stream, Some(LogStream::Stdout));
+    }
+
+    #[tokio::test]
+    async fn test_failing_pre_start_hook_fails_start() {
+        use krill_common::ServiceHooks;
+        use krill_daemon::runner::RunnerError;
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some("exit 3".to_string()),
+            ..Default::default()
+        });
+        let mut runner = make_runner("lidar", config.clone());
+        assert!(matches!(
+            runner.start().await,
+            Err(RunnerError::HookFailed { .. })
+        ));
+        assert!(runner.pid().is_none());
+
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some("sleep 5".to_string()),
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        });
+        let mut runner = make_runner("lidar", config.clone());
+        let started = std::time::Instant::now();
+        assert!(runner.start().await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // A restart whose hook fails leaves the service failed, and says so
+        let dir = TempDir::new().unwrap();
+        let broken = dir.path().join("broken");
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some(format!("test ! -e {}", broken.display())),
+            ..Default::default()
+        });
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("lidar".to_string(), config)]);
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        std::fs::write(&broken, "").unwrap();
+        assert!(orchestrator.restart_service("lidar").await.is_err());
+        let snapshot = &orchestrator.get_snapshot().await["lidar"];
+        assert_eq!(snapshot.status, ServiceStatus::Failed);
+        assert!(snapshot
+            .last_error
+            .as_deref()
+            .is_some_and(|error| error.contains("pre_start")));
+
+        let mut last = None;
+        while let Ok((_, status)) = event_rx.try_recv() {
+            last = Some(status);
+        }
+        assert_eq!(last, Some(ServiceStatus::Failed));
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_required_port_in_use_fails_start() {
+        use krill_common::{PortProtocol, PortSpec};
+        use krill_daemon::runner::RunnerError;
+
+        let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = PortSpec {
+            port: listener.local_addr().unwrap().port(),
+            protocol: PortProtocol::Tcp,
+        };
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.requires_ports = vec![port];
+        let mut runner = make_runner("web", config);
+        match runner.start().await {
+            // The orchestrator looks the owner up, without the runner locked
+            Err(RunnerError::PortInUse(conflict)) => {
+                assert_eq!(conflict.port, port);
+                assert!(conflict.owner.is_none());
+            }
+            other => panic!("expected a port conflict, got {:?}", other),
+        }
+        assert!(runner.pid().is_none());
+
+        drop(listener);
+        runner.start().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_env_file_merged_under_explicit_env() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = TempDir::new().unwrap();
+        let env_file = dir.path().join("robot.env");
+        std::fs::write(&env_file, "ROBOT_NAME=krill01\nROS_DOMAIN_ID=7\n").unwrap();
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "printenv ROBOT_NAME ROS_DOMAIN_ID".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.env_file = Some(env_file);
+
+        let mut env = HashMap::new();
+        env.insert("ROS_DOMAIN_ID".to_string(), "42".to_string());
+        let mut runner =
+            ServiceRunner::new("svc".to_string(), "test-workspace".to_string(), config, env);
+
+        runner.start().await.unwrap();
+        let mut output = String::new();
+        runner
+            .take_stdout()
+            .unwrap()
+            .read_to_string(&mut output)
+            .await
+            .unwrap();
+        assert_eq!(output, "krill01\n42\n");
+
+        // A missing env file fails the start instead of running without it
+        runner.mark_failed(None);
+        runner.config.env_file = Some(dir.path().join("missing.env"));
+        assert!(runner.start().await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_service_env_and_secrets_layered_over_workspace_env() {
+        use krill_common::SecretSource;
+        use tokio::io::AsyncReadExt;
+
+        let dir = TempDir::new().unwrap();
+        let token = dir.path().join("token");
+        std::fs::write(&token, "s3cr3t\n").unwrap();
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "printenv ROS_DOMAIN_ID LOG UPLINK_TOKEN UPLINK_USER".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.env = HashMap::from([
+            ("LOG".to_string(), "debug".to_string()),
+            ("UPLINK_USER".to_string(), "plain".to_string()),
+        ]);
+        config.secrets = [
+            ("UPLINK_TOKEN".to_string(), SecretSource::File(token)),
+            (
+                "UPLINK_USER".to_string(),
+                SecretSource::Command("echo robot".to_string()),
+            ),
+        ]
+        .into();
+
+        let env = HashMap::from([
+            ("ROS_DOMAIN_ID".to_string(), "42".to_string()),
+            ("LOG".to_string(), "info".to_string()),
+        ]);
+        let mut runner =
+            ServiceRunner::new("svc".to_string(), "test-workspace".to_string(), config, env);
+
+        // Secrets stay out of the environment reported for the service
+        let environment = runner.environment().unwrap();
+        assert_eq!(environment["LOG"], "debug");
+        assert!(!environment.contains_key("UPLINK_TOKEN"));
+
+        runner.start().await.unwrap();
+        let mut output = String::new();
+        runner
+            .take_stdout()
+            .unwrap()
+            .read_to_string(&mut output)
+            .await
+            .unwrap();
+        assert_eq!(output, "42\ndebug\ns3cr3t\nrobot\n");
+
+        // An unreadable secret fails the start without revealing anything
+        runner.mark_failed(None);
+        runner.config.secrets.insert(
+            "MISSING".to_string(),
+            SecretSource::File(dir.path().join("missing")),
+        );
+        let err = runner.start().await.unwrap_err().to_string();
+        assert!(err.contains("secret 'MISSING'"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_secrets_read_once_per_start_and_bounded() {
+        use krill_common::{SecretSource, ServiceHooks};
+
+        let dir = TempDir::new().unwrap();
+        let calls = dir.path().join("calls");
+        let seen = dir.path().join("seen");
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.secrets = [(
+            "STAMP".to_string(),
+            SecretSource::Command(format!("echo x >> {0} && wc -l < {0}", calls.display())),
+        )]
+        .into();
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some(format!("printenv STAMP >> {}", seen.display())),
+            pre_stop: Some(format!("printenv STAMP >> {}", seen.display())),
+            timeout: Duration::from_millis(500),
+            ..Default::default()
+        });
+        let mut runner = make_runner("svc", config);
+
+        // The hooks of a run reuse the value its process was started with
+        runner.start().await.unwrap();
+        runner.stop().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&calls).unwrap().lines().count(), 1);
+        assert_eq!(std::fs::read_to_string(&seen).unwrap(), "1\n1\n");
+
+        // A secret command gets as long as a hook
+        runner.config.secrets.insert(
+            "SLOW".to_string(),
+            SecretSource::Command("sleep 30".to_string()),
+        );
+        let started = std::time::Instant::now();
+        let err = runner.start().await.unwrap_err().to_string();
+        assert!(err.contains("secret 'SLOW'"), "{}", err);
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
     #[test]
     fn test_executor_type_returns_correct_string() {
         // Shell executor
@@ -400,13 +1174,71 @@ mod service_runner_tests {
             dependencies: vec![],
             critical: false,
             gpu: false,
+            gpu_health: false,
+            labels: Default::default(),
+            oneshot: false,
             health_check: None,
+            readiness: None,
+            health_debounce: None,
+            env_file: None,
+            env: HashMap::new(),
+            secrets: Default::default(),
+            start_timeout_sec: None,
+            start_delay: None,
+            dependency_timeout: None,
+            wait_for: Vec::new(),
+            wait_for_timeout: None,
+            requires_ports: Vec::new(),
+            protect: false,
+            limits: None,
+            cpu_affinity: None,
+            scheduler: None,
+            hooks: None,
+            schedule: Default::default(),
+            log_buffer_lines: None,
             policy: PolicyConfig::default(),
         };
         let runner = make_runner("pixi-svc", pixi_config);
         assert_eq!(runner.executor_type(), "pixi");
     }
 
+    #[test]
+    fn test_container_name_only_for_container_services() {
+        let runner = make_runner("svc", make_default_service_config());
+        assert_eq!(runner.container_name(), None);
+
+        let mut docker_config = make_default_service_config();
+        docker_config.execute = ExecuteConfig::Docker {
+            image: "nginx:latest".to_string(),
+            volumes: vec![],
+            ports: vec![],
+            privileged: false,
+            network: None,
+        };
+        let runner = make_runner("web", docker_config);
+        assert_eq!(
+            runner.container_name().as_deref(),
+            Some("krill-test-workspace-web")
+        );
+        assert!(runner.is_single_container());
+
+        let mut compose_config = make_default_service_config();
+        compose_config.execute = ExecuteConfig::Compose {
+            file: "compose.yaml".into(),
+            services: vec![],
+            exit_code_from: None,
+            engine: ContainerEngine::Podman,
+            working_dir: None,
+        };
+        let runner = make_runner("stack", compose_config);
+        assert_eq!(
+            runner.container_name().as_deref(),
+            Some("krill-test-workspace-stack")
+        );
+        assert_eq!(runner.container_engine(), ContainerEngine::Podman);
+        assert!(!runner.is_single_container());
+    }
+
     #[test]
     fn test_namespace_returns_workspace_name() {
         let config = make_default_service_config();
@@ -432,11 +1264,30 @@ mod service_runner_tests {
         runner.increment_restart_count();
         assert_eq!(runner.restart_count(), 2);
     }
-}
-
-// ===========================================================================
-// Orchestrator tests
-// ===========================================================================
+
+    #[tokio::test]
+    async fn test_restart_count_settles_after_stable_run() {
+        let mut config = make_service_config(RestartPolicy::Always, 0);
+        config.policy.stable_after = Duration::from_millis(200);
+        let mut runner = make_runner("svc", config);
+        runner.increment_restart_count();
+        runner.increment_restart_count();
+
+        // A run that ends early keeps counting failures
+        runner.start().await.unwrap();
+        runner.settle_restart_count();
+        assert_eq!(runner.restart_count(), 2);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        runner.settle_restart_count();
+        assert_eq!(runner.restart_count(), 0);
+        runner.stop().await.unwrap();
+    }
+}
+
+// ===========================================================================
+// Orchestrator tests
+// ===========================================================================
 
 mod orchestrator_tests {
     use super::*;
@@ -473,8 +1324,23 @@ mod orchestrator_tests {
             version: "1".to_string(),
             name: "circular-workspace".to_string(),
             log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
             env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
             services,
+            socket: None,
+            included: Vec::new(),
         };
 
         let (event_tx, _event_rx) = mpsc::unbounded_channel();
@@ -506,8 +1372,23 @@ mod orchestrator_tests {
             version: "1".to_string(),
             name: "snap-workspace".to_string(),
             log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
             env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
             services,
+            socket: None,
+            included: Vec::new(),
         };
 
         let (event_tx, _event_rx) = mpsc::unbounded_channel();
@@ -522,4 +1403,3469 @@ mod orchestrator_tests {
         assert_eq!(snapshot["svc-a"].status, ServiceStatus::Starting);
         assert_eq!(snapshot["svc-b"].status, ServiceStatus::Starting);
     }
+
+    #[tokio::test]
+    async fn test_snapshot_config_summary() {
+        use krill_common::{DependencyCondition, DependencySummary};
+
+        let mut services = HashMap::new();
+        services.insert("lidar".to_string(), make_default_service_config());
+        let mut fusion = make_service_config(RestartPolicy::Always, 3);
+        fusion.dependencies = vec![Dependency::WithCondition {
+            service: "lidar".to_string(),
+            condition: DependencyCondition::Healthy,
+        }];
+        fusion.critical = true;
+        fusion.gpu = true;
+        services.insert("fusion".to_string(), fusion);
+
+        let config = KrillConfig {
+            services,
+            ..make_single_service_krill_config()
+        };
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+
+        let snapshot = orchestrator.get_snapshot().await;
+        let summary = &snapshot["fusion"].config;
+        assert_eq!(summary.executor, "shell");
+        assert_eq!(
+            summary.dependencies,
+            vec![DependencySummary {
+                service: "lidar".to_string(),
+                condition: DependencyCondition::Healthy,
+            }]
+        );
+        assert_eq!(summary.restart_policy, RestartPolicy::Always);
+        assert_eq!(summary.max_restarts, 3);
+        assert!(summary.critical);
+        assert!(summary.uses_gpu);
+
+        let summary = &snapshot["lidar"].config;
+        assert!(summary.dependencies.is_empty());
+        assert!(!summary.critical);
+        assert!(!summary.uses_gpu);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_metadata_in_snapshot() {
+        let config = make_single_service_krill_config();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+
+        let metadata = HashMap::from([("reason".to_string(), "lidar dropout".to_string())]);
+        orchestrator
+            .process_heartbeat("svc-a", ServiceStatus::Degraded, metadata.clone())
+            .await
+            .unwrap();
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["svc-a"].heartbeat_metadata, metadata);
+    }
+
+    #[tokio::test]
+    async fn test_trigger_health_check_without_checker_returns_none() {
+        let config = make_single_service_krill_config();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+
+        assert_eq!(orchestrator.trigger_health_check("svc-a").await, None);
+        assert_eq!(orchestrator.trigger_health_check("missing").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_diffs_services() {
+        let mut services = HashMap::new();
+        services.insert("svc-a".to_string(), make_default_service_config());
+        services.insert("svc-b".to_string(), make_default_service_config());
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "reload-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config.clone(), event_tx).unwrap();
+
+        // svc-a gets a new command, svc-b is dropped and svc-c is new
+        let mut new_config = config;
+        new_config.services.remove("svc-b");
+        new_config.services.get_mut("svc-a").unwrap().execute = ExecuteConfig::Shell {
+            command: "echo changed".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        new_config
+            .services
+            .insert("svc-c".to_string(), make_default_service_config());
+
+        let summary = orchestrator.reload_config(new_config).await.unwrap();
+        assert_eq!(summary.added, vec!["svc-c".to_string()]);
+        assert_eq!(summary.removed, vec!["svc-b".to_string()]);
+        assert_eq!(summary.restarted, vec!["svc-a".to_string()]);
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot.len(), 2);
+        assert!(snapshot.contains_key("svc-a"));
+        assert!(snapshot.contains_key("svc-c"));
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reload_restarts_only_services_with_changed_spawn_settings() {
+        let sleeper = || {
+            let mut service = make_default_service_config();
+            service.execute = ExecuteConfig::Shell {
+                command: "sleep 30".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            service
+        };
+        let mut config = make_single_service_krill_config();
+        config
+            .env
+            .insert("ROS_DOMAIN_ID".to_string(), "42".to_string());
+        config.services.insert("svc-a".to_string(), sleeper());
+        let mut svc_b = sleeper();
+        svc_b
+            .env
+            .insert("ROS_DOMAIN_ID".to_string(), "42".to_string());
+        config.services.insert("svc-b".to_string(), svc_b);
+        config.services.insert("svc-c".to_string(), sleeper());
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config.clone(), event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        let pids = |snapshot: &HashMap<String, krill_common::ServiceSnapshot>| {
+            ["svc-a", "svc-b", "svc-c"].map(|name| snapshot[name].pid)
+        };
+        let before = pids(&orchestrator.get_snapshot().await);
+        assert!(before.iter().all(Option::is_some));
+
+        // svc-a's inherited env changes, svc-b (which pins the variable) only
+        // gains a label, and svc-c starts pinning the changed variable itself
+        let mut new_config = config;
+        new_config
+            .env
+            .insert("ROS_DOMAIN_ID".to_string(), "7".to_string());
+        let svc_b = new_config.services.get_mut("svc-b").unwrap();
+        svc_b
+            .labels
+            .insert("tier".to_string(), "perception".to_string());
+        svc_b.policy.max_restarts = 9;
+        new_config
+            .services
+            .get_mut("svc-c")
+            .unwrap()
+            .env
+            .insert("ROS_DOMAIN_ID".to_string(), "42".to_string());
+
+        // A dry run reports the plan and changes nothing
+        let plan = orchestrator.plan_reload(&new_config).await.unwrap();
+        assert_eq!(plan.restarted, ["svc-a"]);
+        assert_eq!(plan.updated, ["svc-b"]);
+        assert_eq!(plan.changes["svc-a"], ["env"]);
+        assert_eq!(plan.changes["svc-b"], ["labels", "policy"]);
+        assert!(!plan.changes.contains_key("svc-c"));
+        assert!(plan.added.is_empty() && plan.removed.is_empty());
+        assert_eq!(pids(&orchestrator.get_snapshot().await), before);
+
+        let summary = orchestrator.reload_config(new_config).await.unwrap();
+        assert_eq!(summary, plan);
+
+        let mut after = pids(&orchestrator.get_snapshot().await);
+        for _ in 0..50 {
+            if after[0].is_some() && after[0] != before[0] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            after = pids(&orchestrator.get_snapshot().await);
+        }
+        assert_ne!(after[0], before[0], "svc-a was not restarted");
+        assert_eq!(after[1..], before[1..]);
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["svc-b"].labels["tier"], "perception");
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reload_restarts_services_whose_env_files_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join("nav.env");
+        let token = dir.path().join("token");
+        std::fs::write(&env_file, "MAP=lab\n").unwrap();
+        std::fs::write(&token, "s3cret\n").unwrap();
+
+        let sleeper = || {
+            let mut service = make_default_service_config();
+            service.execute = ExecuteConfig::Shell {
+                command: "sleep 30".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            service
+        };
+        let mut config = make_single_service_krill_config();
+        let mut nav = sleeper();
+        nav.env_file = Some(env_file.clone());
+        config.services.insert("nav".to_string(), nav);
+        let mut uplink = sleeper();
+        uplink.secrets.insert(
+            "TOKEN".to_string(),
+            krill_common::SecretSource::File(token.clone()),
+        );
+        config.services.insert("uplink".to_string(), uplink);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config.clone(), event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        // Same recipe, same files: nothing to do
+        let plan = orchestrator.plan_reload(&config).await.unwrap();
+        assert!(plan.restarted.is_empty() && plan.updated.is_empty());
+
+        std::fs::write(&env_file, "MAP=warehouse\n").unwrap();
+        std::fs::write(&token, "n3w\n").unwrap();
+        let plan = orchestrator.plan_reload(&config).await.unwrap();
+        assert_eq!(plan.restarted, ["nav", "uplink"]);
+        assert_eq!(plan.changes["nav"], ["env_file"]);
+        assert_eq!(plan.changes["uplink"], ["secrets"]);
+
+        // A stopped service is not started by a reload
+        orchestrator.stop_service("uplink").await.unwrap();
+        let summary = orchestrator.reload_config(config).await.unwrap();
+        assert_eq!(summary.restarted, ["nav"]);
+        assert_eq!(summary.updated, ["uplink"]);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["uplink"].status, ServiceStatus::Stopped);
+        assert_ne!(snapshot["nav"].status, ServiceStatus::Stopped);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_with_cycle_keeps_old_services() {
+        let config = make_single_service_krill_config();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config.clone(), event_tx).unwrap();
+
+        let mut new_config = config;
+        let mut config_b = make_default_service_config();
+        config_b.dependencies = vec![Dependency::Simple("svc-a".to_string())];
+        new_config.services.insert("svc-b".to_string(), config_b);
+        new_config.services.get_mut("svc-a").unwrap().dependencies =
+            vec![Dependency::Simple("svc-b".to_string())];
+
+        assert!(orchestrator.reload_config(new_config).await.is_err());
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert!(snapshot.contains_key("svc-a"));
+    }
+
+    #[tokio::test]
+    async fn test_service_failed_when_not_ready_within_start_timeout() {
+        // Heartbeats never arrive, so the service never becomes healthy
+        let mut service = make_service_config(RestartPolicy::Never, 0);
+        service.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        service.health_check = Some(krill_common::HealthChecker::Heartbeat {
+            last_seen: None,
+            timeout: Duration::from_secs(60),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
+        });
+        service.start_timeout_sec = Some(1);
+
+        let mut config = make_single_service_krill_config();
+        config.services.insert("svc-a".to_string(), service);
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        let failed = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some((service, status)) = event_rx.recv().await {
+                if service == "svc-a" && status == ServiceStatus::Failed {
+                    return true;
+                }
+            }
+            false
+        })
+        .await
+        .unwrap_or(false);
+        assert!(failed, "svc-a should fail after its start timeout");
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Failed);
+        assert!(snapshot["svc-a"].pid.is_none());
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_time_paused_does_not_count_against_start_timeout() {
+        let mut service = make_service_config(RestartPolicy::Never, 0);
+        service.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        service.health_check = Some(krill_common::HealthChecker::Heartbeat {
+            last_seen: None,
+            timeout: Duration::from_secs(60),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
+        });
+        service.start_timeout_sec = Some(1);
+
+        let mut config = make_single_service_krill_config();
+        config.services.insert("svc-a".to_string(), service);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        orchestrator.pause_service("svc-a").await.unwrap();
+
+        // Paused past its start timeout, it is not failed
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Paused);
+
+        // Resumed, it still has to finish starting in time
+        orchestrator.resume_service("svc-a").await.unwrap();
+        let mut status = ServiceStatus::Running;
+        for _ in 0..50 {
+            status = orchestrator.get_snapshot().await["svc-a"].status.clone();
+            if status == ServiceStatus::Failed {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(status, ServiceStatus::Failed);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_required_port_in_use_names_its_owner() {
+        use krill_common::{PortProtocol, PortSpec};
+
+        let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let mut config = make_single_service_krill_config();
+        config.services.get_mut("svc-a").unwrap().requires_ports = vec![PortSpec {
+            port: listener.local_addr().unwrap().port(),
+            protocol: PortProtocol::Tcp,
+        }];
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        let _ = orchestrator.start_all().await;
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..50 {
+            if snapshot["svc-a"].status == ServiceStatus::Failed {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Failed);
+        let error = snapshot["svc-a"].last_error.clone().unwrap();
+        assert!(error.contains("already in use"), "{}", error);
+        #[cfg(target_os = "linux")]
+        assert!(
+            error.contains(&format!("by PID {}", std::process::id())),
+            "{}",
+            error
+        );
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_gpu_service_fails_without_gpu() {
+        // Machines with an NVIDIA GPU start the service
+        if krill_daemon::gpu::detect().await.is_ok() {
+            return;
+        }
+        let mut config = make_single_service_krill_config();
+        config.services.get_mut("svc-a").unwrap().gpu = true;
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        let _ = orchestrator.start_all().await;
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..50 {
+            if snapshot["svc-a"].status == ServiceStatus::Failed {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Failed);
+        let error = snapshot["svc-a"].last_error.clone().unwrap();
+        assert!(error.contains("requires a GPU"), "{}", error);
+        assert_eq!(snapshot["svc-a"].pid, None);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_service_rejects_pending_and_unknown_services() {
+        let config = make_single_service_krill_config();
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+
+        assert!(matches!(
+            orchestrator.start_service("svc-a").await,
+            Err(OrchestratorError::InvalidState {
+                state: ServiceState::Pending,
+                ..
+            })
+        ));
+        assert!(matches!(
+            orchestrator.start_service("missing").await,
+            Err(OrchestratorError::ServiceNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_start_service_after_stop() {
+        let mut services = HashMap::new();
+        let mut config_a = make_default_service_config();
+        config_a.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        services.insert("svc-a".to_string(), config_a);
+
+        let mut config_b = make_default_service_config();
+        config_b.dependencies = vec![Dependency::Simple("svc-a".to_string())];
+        services.insert("svc-b".to_string(), config_b);
+
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "start-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        orchestrator.stop_service("svc-a").await.unwrap();
+        orchestrator.stop_service("svc-b").await.unwrap();
+
+        // svc-b cannot start while its dependency is stopped
+        assert!(matches!(
+            orchestrator.start_service("svc-b").await,
+            Err(OrchestratorError::DependencyNotReady { .. })
+        ));
+
+        while event_rx.try_recv().is_ok() {}
+        orchestrator.start_service("svc-a").await.unwrap();
+
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            ("svc-a".to_string(), ServiceStatus::Starting)
+        );
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            ("svc-a".to_string(), ServiceStatus::Running)
+        );
+        assert!(matches!(
+            orchestrator.start_service("svc-a").await,
+            Err(OrchestratorError::InvalidState { .. })
+        ));
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_emergency_stop_latches_until_cleared() {
+        let mut services = HashMap::new();
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        services.insert("svc-a".to_string(), config);
+
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "estop-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        while event_rx.try_recv().is_ok() {}
+
+        orchestrator.emergency_stop().await;
+        assert_eq!(
+            event_rx.try_recv().unwrap(),
+            ("svc-a".to_string(), ServiceStatus::Stopped)
+        );
+        assert!(orchestrator.get_snapshot().await["svc-a"].safety_stopped);
+
+        assert!(matches!(
+            orchestrator.start_service("svc-a").await,
+            Err(OrchestratorError::SafetyStopped(_))
+        ));
+        assert!(matches!(
+            orchestrator.restart_service("svc-a").await,
+            Err(OrchestratorError::SafetyStopped(_))
+        ));
+
+        orchestrator.clear_safety_stop("svc-a").await.unwrap();
+        assert!(matches!(
+            orchestrator.clear_safety_stop("svc-a").await,
+            Err(OrchestratorError::NotSafetyStopped(_))
+        ));
+        assert!(matches!(
+            orchestrator.clear_safety_stop("missing").await,
+            Err(OrchestratorError::ServiceNotFound(_))
+        ));
+
+        orchestrator.start_service("svc-a").await.unwrap();
+        assert!(!orchestrator.get_snapshot().await["svc-a"].safety_stopped);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_emergency_actions_run_in_order() {
+        let dir = TempDir::new().unwrap();
+        let log = dir.path().join("estop.log");
+
+        let mut services = HashMap::new();
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        services.insert("svc-a".to_string(), config);
+
+        let hook = |step: &str| EmergencyAction::Command {
+            command: format!("echo {} >> {}", step, log.display()),
+            timeout: Duration::from_secs(5),
+        };
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "estop-hooks".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![
+                hook("brakes"),
+                EmergencyAction::Command {
+                    command: "false".to_string(),
+                    timeout: Duration::from_secs(5),
+                },
+                EmergencyAction::StopServices,
+                hook("notified"),
+            ],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        orchestrator.emergency_stop().await;
+
+        // A failing action does not keep the later ones from running
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "brakes\nnotified\n");
+        let snapshot = orchestrator.get_snapshot().await;
+        assert!(snapshot["svc-a"].safety_stopped);
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Stopped);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_dependents_first_in_parallel() {
+        let service = |command: &str, dependencies: &[&str]| {
+            let mut config = make_default_service_config();
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config.dependencies = dependencies
+                .iter()
+                .map(|dep| Dependency::Simple(dep.to_string()))
+                .collect();
+            config
+        };
+        // Takes a second to exit on SIGTERM
+        let slow_stop = "trap 'sleep 1; exit 0' TERM; while true; do sleep 0.1; done";
+
+        let mut services = HashMap::new();
+        services.insert("driver".to_string(), service("sleep 30", &[]));
+        services.insert("camera".to_string(), service(slow_stop, &["driver"]));
+        services.insert("lidar".to_string(), service(slow_stop, &["driver"]));
+
+        let mut config = make_single_service_krill_config();
+        config.services = services;
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        while event_rx.try_recv().is_ok() {}
+
+        let started = std::time::Instant::now();
+        orchestrator.shutdown().await.unwrap();
+        let elapsed = started.elapsed();
+
+        let mut stopped = Vec::new();
+        while let Ok((name, status)) = event_rx.try_recv() {
+            if status == ServiceStatus::Stopped {
+                stopped.push(name);
+            }
+        }
+        assert_eq!(stopped.len(), 3);
+        assert_eq!(stopped[2], "driver");
+
+        // camera and lidar stop side by side, not one after the other
+        assert!(elapsed < Duration::from_millis(1800), "took {:?}", elapsed);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_restart_hooks_hold_no_locks() {
+        use krill_common::ServiceHooks;
+
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 60".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.hooks = Some(ServiceHooks {
+            pre_start: Some("sleep 1".to_string()),
+            pre_stop: Some("sleep 1".to_string()),
+            ..Default::default()
+        });
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("lidar".to_string(), config)]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        // Snapshots keep coming while the hooks run
+        let (restarted, slowest) = tokio::join!(orchestrator.restart_service("lidar"), async {
+            let mut slowest = Duration::ZERO;
+            for _ in 0..20 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let started = std::time::Instant::now();
+                orchestrator.get_snapshot().await;
+                slowest = slowest.max(started.elapsed());
+            }
+            slowest
+        });
+        restarted.unwrap();
+        assert!(slowest < Duration::from_millis(500), "took {:?}", slowest);
+        assert_eq!(
+            orchestrator.get_snapshot().await["lidar"].status,
+            ServiceStatus::Running
+        );
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_post_stop_hook_of_exited_service_holds_no_locks() {
+        use krill_common::ServiceHooks;
+
+        let dir = TempDir::new().unwrap();
+        let trace = dir.path().join("post_stop.log");
+        let mut config = make_service_config(RestartPolicy::Never, 0);
+        config.execute = ExecuteConfig::Shell {
+            command: "sleep 0.2; exit 1".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.hooks = Some(ServiceHooks {
+            post_stop: Some(format!("sleep 3; echo done >> {}", trace.display())),
+            ..Default::default()
+        });
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("lidar".to_string(), config)]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        // Snapshots keep coming while the hook runs
+        let failed = tokio::time::timeout(Duration::from_millis(2500), async {
+            loop {
+                if orchestrator.get_snapshot().await["lidar"].status == ServiceStatus::Failed {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        assert!(failed.is_ok(), "snapshot stalled behind the post_stop hook");
+        assert!(!trace.exists());
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(std::fs::read_to_string(&trace).unwrap(), "done\n");
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_reports_how_each_service_stopped() {
+        use krill_common::{ServiceHooks, StopMethod};
+
+        let service = |command: &str| {
+            let mut config = make_default_service_config();
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config.policy.stop_timeout = Duration::from_millis(500);
+            config
+        };
+        let mut graceful = service("trap 'exit 3' TERM; while true; do sleep 0.1; done");
+        graceful.hooks = Some(ServiceHooks {
+            post_stop: Some("exit 1".to_string()),
+            ..Default::default()
+        });
+
+        let mut config = make_single_service_krill_config();
+        config.services = HashMap::from([
+            ("graceful".to_string(), graceful),
+            (
+                "stubborn".to_string(),
+                service("trap '' TERM; while true; do sleep 0.1; done"),
+            ),
+            ("idle".to_string(), service("sleep 30")),
+        ]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        orchestrator.stop_service("idle").await.unwrap();
+
+        let report = orchestrator.shutdown().await.unwrap();
+        // Stopped services are left out, the slowest of a level comes last
+        let names: Vec<&str> = report.iter().map(|s| s.service.as_str()).collect();
+        assert_eq!(names, ["graceful", "stubborn"]);
+
+        assert_eq!(report[0].method, Some(StopMethod::Sigterm));
+        assert_eq!(report[0].exit_code, Some(3));
+        assert_eq!(report[0].errors.len(), 1);
+        assert!(report[0].errors[0].contains("post_stop"));
+
+        assert_eq!(report[1].method, Some(StopMethod::Sigkill));
+        assert_eq!(report[1].exit_code, None);
+        assert_eq!(report[1].signal, Some(9));
+        assert!(report[1].duration >= Duration::from_millis(500));
+        assert!(report[1].errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_kill_skips_stop_command_and_grace_period() {
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("stop-command-ran");
+
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: "trap '' TERM; while true; do sleep 0.1; done".to_string(),
+            stop_command: Some(format!("touch {}", marker.display())),
+            working_dir: None,
+        };
+        config.policy.stop_timeout = Duration::from_secs(30);
+
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("stubborn".to_string(), config)]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        let started = std::time::Instant::now();
+        orchestrator.kill_service("stubborn").await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            orchestrator.get_snapshot().await["stubborn"].status,
+            ServiceStatus::Stopped
+        );
+        assert!(!marker.exists());
+
+        assert!(matches!(
+            orchestrator.kill_service("missing").await,
+            Err(OrchestratorError::ServiceNotFound(_))
+        ));
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pause_freezes_service_and_suspends_health_checks() {
+        let dir = TempDir::new().unwrap();
+        let ticks = dir.path().join("ticks");
+
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: format!(
+                "while true; do echo x >> {}; sleep 0.1; done",
+                ticks.display()
+            ),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.health_check = Some(krill_common::HealthChecker::Heartbeat {
+            last_seen: None,
+            timeout: Duration::from_millis(300),
+            misses_allowed: 0,
+            grace_period_on_start: None,
+            started: None,
+        });
+
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("mapping".to_string(), config)]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        // Give the shell time to write its first ticks
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        orchestrator
+            .process_heartbeat("mapping", ServiceStatus::Healthy, HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            orchestrator.get_snapshot().await["mapping"].status,
+            ServiceStatus::Healthy
+        );
+
+        assert!(matches!(
+            orchestrator.resume_service("mapping").await,
+            Err(OrchestratorError::NotPaused(_))
+        ));
+        orchestrator.pause_service("mapping").await.unwrap();
+        assert_eq!(
+            orchestrator.get_snapshot().await["mapping"].status,
+            ServiceStatus::Paused
+        );
+
+        // Frozen: no output, and no heartbeat verdict past a health check interval
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let frozen = std::fs::read_to_string(&ticks).unwrap().len();
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(std::fs::read_to_string(&ticks).unwrap().len(), frozen);
+        assert_eq!(
+            orchestrator.get_snapshot().await["mapping"].status,
+            ServiceStatus::Paused
+        );
+        assert_eq!(orchestrator.trigger_health_check("mapping").await, None);
+
+        // Back in the state it was paused in, without the pause counting as missed beats
+        orchestrator.resume_service("mapping").await.unwrap();
+        assert_eq!(
+            orchestrator.get_snapshot().await["mapping"].status,
+            ServiceStatus::Healthy
+        );
+        assert_eq!(
+            orchestrator.trigger_health_check("mapping").await,
+            Some(true)
+        );
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(std::fs::read_to_string(&ticks).unwrap().len() > frozen);
+
+        // A paused service still stops
+        orchestrator.pause_service("mapping").await.unwrap();
+        orchestrator.stop_service("mapping").await.unwrap();
+        assert_eq!(
+            orchestrator.get_snapshot().await["mapping"].status,
+            ServiceStatus::Stopped
+        );
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reload_sends_reload_signal() {
+        use krill_common::ReloadSignal;
+
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("reloaded");
+        let helper = dir.path().join("helper");
+
+        let mut config = make_default_service_config();
+        config.execute = ExecuteConfig::Shell {
+            command: format!(
+                "sleep 30 & echo $! > {}; trap 'touch {}' USR1; while true; do sleep 0.1; done",
+                helper.display(),
+                marker.display()
+            ),
+            stop_command: None,
+            working_dir: None,
+        };
+        config.policy.reload_signal = ReloadSignal::Sigusr1;
+
+        let mut krill_config = make_single_service_krill_config();
+        krill_config.services = HashMap::from([("proxy".to_string(), config)]);
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(krill_config, event_tx).unwrap();
+        orchestrator.start_all().await.unwrap();
+        // Give the shell time to install its trap
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let pid = orchestrator.get_snapshot().await["proxy"].pid;
+
+        orchestrator.reload_service("proxy").await.unwrap();
+        for _ in 0..50 {
+            if marker.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(marker.exists());
+
+        // Only the main process was signalled, not the rest of its group;
+        // a killed helper would linger as a zombie of the shell
+        let helper = std::fs::read_to_string(&helper).unwrap();
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", helper.trim())).unwrap();
+        let state = stat.rsplit_once(')').unwrap().1.split_whitespace().next();
+        assert_eq!(state, Some("S"));
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["proxy"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["proxy"].pid, pid);
+
+        orchestrator.stop_service("proxy").await.unwrap();
+        assert!(matches!(
+            orchestrator.reload_service("proxy").await,
+            Err(OrchestratorError::RunnerError(_))
+        ));
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_and_stop_group() {
+        let sleeper = || {
+            let mut config = make_default_service_config();
+            config.execute = ExecuteConfig::Shell {
+                command: "sleep 30".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        services.insert("driver".to_string(), sleeper());
+        let mut detector = sleeper();
+        detector.dependencies = vec![Dependency::Simple("driver".to_string())];
+        services.insert("detector".to_string(), detector);
+        services.insert("sim".to_string(), sleeper());
+
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "group-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::from([("perception".to_string(), vec!["detector".to_string()])]),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(config, event_tx).unwrap();
+
+        assert!(matches!(
+            orchestrator.start_group("missing").await,
+            Err(OrchestratorError::GroupNotFound(_))
+        ));
+
+        orchestrator.start_group("perception").await.unwrap();
+
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..50 {
+            if snapshot["detector"].status == ServiceStatus::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["detector"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["sim"].status, ServiceStatus::Stopped);
+
+        // Only group members are stopped, not their dependencies
+        orchestrator.stop_group("perception").await.unwrap();
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["detector"].status, ServiceStatus::Stopped);
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_healthy_dependency_waits_for_readiness() {
+        use krill_common::{DependencyCondition, HealthChecker};
+
+        let temp_dir = TempDir::new().unwrap();
+        let ready_file = temp_dir.path().join("map-loaded");
+
+        let sleeper = || {
+            let mut config = make_default_service_config();
+            config.execute = ExecuteConfig::Shell {
+                command: "sleep 30".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        let mut map_server = sleeper();
+        map_server.readiness = Some(HealthChecker::Script {
+            command: format!("test -f {}", ready_file.display()),
+            timeout: Duration::from_secs(1),
+        });
+        services.insert("map_server".to_string(), map_server);
+        let mut planner = sleeper();
+        planner.dependencies = vec![Dependency::WithCondition {
+            service: "map_server".to_string(),
+            condition: DependencyCondition::Healthy,
+        }];
+        services.insert("planner".to_string(), planner);
+
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "readiness-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        // start_all returns once every service has started, so run it in the background
+        let starter = std::sync::Arc::clone(&orchestrator);
+        tokio::spawn(async move { starter.start_all().await });
+
+        // Running without a liveness check, but not ready yet
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["map_server"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["planner"].status, ServiceStatus::Starting);
+
+        std::fs::write(&ready_file, "").unwrap();
+
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..100 {
+            if snapshot["planner"].status == ServiceStatus::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["planner"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_completed_dependency_waits_for_oneshot_exit() {
+        use krill_common::DependencyCondition;
+
+        let shell = |command: &str| {
+            let mut config = make_service_config(RestartPolicy::Never, 0);
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        let mut calibrate = shell("sleep 1");
+        calibrate.oneshot = true;
+        services.insert("calibrate".to_string(), calibrate);
+        let mut driver = shell("sleep 30");
+        driver.dependencies = vec![Dependency::WithCondition {
+            service: "calibrate".to_string(),
+            condition: DependencyCondition::Completed,
+        }];
+        services.insert("driver".to_string(), driver);
+
+        let config = KrillConfig {
+            version: "1".to_string(),
+            name: "oneshot-workspace".to_string(),
+            log_dir: None,
+            log_rotation: Default::default(),
+            log_format: Default::default(),
+            log_buffer_lines: 5000,
+            log_buffer_max_mb: 64,
+            env: HashMap::new(),
+            groups: HashMap::new(),
+            prometheus: Default::default(),
+            access: Default::default(),
+            emergency_stop_cmd: None,
+            on_emergency: vec![EmergencyAction::StopServices],
+            restart_storm: None,
+            max_concurrent_starts: None,
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            socket: None,
+            included: Vec::new(),
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        let starter = std::sync::Arc::clone(&orchestrator);
+        tokio::spawn(async move { starter.start_all().await });
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["calibrate"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Starting);
+
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..100 {
+            if snapshot["driver"].status == ServiceStatus::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["calibrate"].status, ServiceStatus::Completed);
+        assert_eq!(snapshot["calibrate"].restart_count, 0);
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_dependency_waits_for_restarts_to_give_up() {
+        use krill_common::DependencyCondition;
+
+        let shell = |command: &str, policy: RestartPolicy| {
+            let mut config = make_service_config(policy, 1);
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        let mut primary = shell("false", RestartPolicy::OnFailure);
+        primary.policy.restart_delay = Duration::from_secs(2);
+        services.insert("gps-rtk".to_string(), primary);
+        let mut fallback = shell("sleep 30", RestartPolicy::Never);
+        fallback.dependencies = vec![Dependency::WithCondition {
+            service: "gps-rtk".to_string(),
+            condition: DependencyCondition::Failed,
+        }];
+        services.insert("gps-basic".to_string(), fallback);
+
+        let config = KrillConfig {
+            services,
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        // Startup does not wait for the fallback
+        tokio::time::timeout(Duration::from_secs(5), orchestrator.start_all())
+            .await
+            .expect("start_all waited for the fallback")
+            .unwrap();
+
+        // Not while the primary waits for its restart
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..20 {
+            if snapshot["gps-rtk"].status == ServiceStatus::Failed {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["gps-rtk"].status, ServiceStatus::Failed);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_ne!(snapshot["gps-basic"].status, ServiceStatus::Running);
+
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..100 {
+            if snapshot["gps-basic"].status == ServiceStatus::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["gps-rtk"].status, ServiceStatus::Failed);
+        assert_eq!(snapshot["gps-rtk"].restart_count, 2);
+        assert_eq!(snapshot["gps-basic"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restart_storm_pauses_restarts_until_resumed() {
+        use krill_common::RestartStormConfig;
+        use krill_daemon::storm::StormTripped;
+
+        let mut services = HashMap::new();
+        for name in ["lidar", "camera"] {
+            let mut config = make_service_config(RestartPolicy::OnFailure, 10);
+            config.execute = ExecuteConfig::Shell {
+                command: "false".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            services.insert(name.to_string(), config);
+        }
+        let config = KrillConfig {
+            services,
+            restart_storm: Some(RestartStormConfig {
+                max_failures: 1,
+                window: Duration::from_secs(60),
+            }),
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let (storm_tx, mut storm_rx) = mpsc::unbounded_channel();
+        let mut orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.set_storm_tx(storm_tx);
+        let orchestrator = std::sync::Arc::new(orchestrator);
+        let _ = orchestrator.start_all().await;
+
+        let tripped = tokio::time::timeout(Duration::from_secs(5), storm_rx.recv())
+            .await
+            .expect("restart storm not reported")
+            .unwrap();
+        assert_eq!(
+            tripped,
+            StormTripped {
+                workspace: "test-workspace".to_string(),
+                failures: 2,
+                window: Duration::from_secs(60),
+            }
+        );
+        assert!(orchestrator.restarts_paused().await);
+
+        // Well past the restart delay, neither service came back
+        tokio::time::pause();
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        tokio::time::resume();
+        let snapshot = orchestrator.get_snapshot().await;
+        for name in ["lidar", "camera"] {
+            assert_eq!(snapshot[name].status, ServiceStatus::Failed);
+            assert_eq!(snapshot[name].restart_count, 1);
+            assert!(snapshot[name].restarts_paused);
+        }
+
+        // Resuming restarts them, and their failures trip the breaker again
+        orchestrator.resume_restarts().await;
+        assert!(!orchestrator.restarts_paused().await);
+        tokio::time::timeout(Duration::from_secs(5), storm_rx.recv())
+            .await
+            .expect("restart storm not reported again")
+            .unwrap();
+        let snapshot = orchestrator.get_snapshot().await;
+        for name in ["lidar", "camera"] {
+            assert_eq!(snapshot[name].restart_count, 2);
+        }
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_delay_waits_after_dependencies() {
+        let shell = |command: &str| {
+            let mut config = make_service_config(RestartPolicy::Never, 0);
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        services.insert("power".to_string(), shell("sleep 30"));
+        let mut driver = shell("sleep 30");
+        driver.dependencies = vec![Dependency::Simple("power".to_string())];
+        driver.start_delay = Some(Duration::from_millis(1500));
+        services.insert("driver".to_string(), driver);
+
+        let config = KrillConfig {
+            services,
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        let starter = std::sync::Arc::clone(&orchestrator);
+        tokio::spawn(async move { starter.start_all().await });
+
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["power"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Starting);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["driver"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dependency_timeout_blocks_service() {
+        use krill_common::DependencyCondition;
+
+        let shell = |command: &str| {
+            let mut config = make_service_config(RestartPolicy::Never, 0);
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        services.insert("lidar".to_string(), shell("false"));
+        let mut slam = shell("sleep 30");
+        slam.dependencies = vec![Dependency::WithCondition {
+            service: "lidar".to_string(),
+            condition: DependencyCondition::Healthy,
+        }];
+        slam.dependency_timeout = Some(Duration::from_millis(500));
+        services.insert("slam".to_string(), slam);
+
+        let config = KrillConfig {
+            services,
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        tokio::time::timeout(Duration::from_secs(5), orchestrator.start_all())
+            .await
+            .expect("start_all kept waiting for the dependency")
+            .unwrap();
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["slam"].status, ServiceStatus::Blocked);
+        let error = snapshot["slam"].last_error.as_deref().unwrap();
+        assert!(error.contains("'lidar'"), "unexpected error: {}", error);
+
+        let mut blocked_event = false;
+        while let Ok((service, status)) = event_rx.try_recv() {
+            if service == "slam" && status == ServiceStatus::Blocked {
+                blocked_event = true;
+            }
+        }
+        assert!(blocked_event, "no event for the blocked service");
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_holds_spawn_until_conditions_hold() {
+        use krill_common::Precondition;
+
+        let temp_dir = TempDir::new().unwrap();
+        let device_ready = temp_dir.path().join("ttyUSB0.ready");
+
+        let shell = |command: &str| {
+            let mut config = make_service_config(RestartPolicy::Never, 0);
+            config.execute = ExecuteConfig::Shell {
+                command: command.to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config
+        };
+
+        let mut services = HashMap::new();
+        let mut lidar = shell("sleep 30");
+        lidar.wait_for = vec![Precondition::File(device_ready.clone())];
+        services.insert("lidar".to_string(), lidar);
+        let mut camera = shell("sleep 30");
+        camera.wait_for = vec![Precondition::Device(temp_dir.path().join("video0"))];
+        camera.wait_for_timeout = Some(Duration::from_millis(500));
+        services.insert("camera".to_string(), camera);
+
+        let config = KrillConfig {
+            services,
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        let starting = {
+            let orchestrator = std::sync::Arc::clone(&orchestrator);
+            tokio::spawn(async move { orchestrator.start_all().await })
+        };
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["lidar"].status, ServiceStatus::Starting);
+        assert_eq!(snapshot["lidar"].pid, None);
+
+        std::fs::write(&device_ready, "").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), starting)
+            .await
+            .expect("start_all kept waiting for the conditions")
+            .unwrap()
+            .unwrap();
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["lidar"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["camera"].status, ServiceStatus::Blocked);
+        let error = snapshot["camera"].last_error.as_deref().unwrap();
+        assert!(error.contains("video0"), "unexpected error: {}", error);
+
+        // A manual start does not wait
+        let result = orchestrator.start_service("camera").await;
+        assert!(
+            matches!(result, Err(OrchestratorError::PreconditionNotMet { .. })),
+            "unexpected result: {:?}",
+            result
+        );
+
+        orchestrator.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_starts_waits_for_readiness() {
+        use krill_common::HealthChecker;
+
+        let temp_dir = TempDir::new().unwrap();
+        let ready_file = temp_dir.path().join("calibrated");
+
+        let mut services = HashMap::new();
+        for name in ["camera-left", "camera-right"] {
+            let mut config = make_service_config(RestartPolicy::Never, 0);
+            config.execute = ExecuteConfig::Shell {
+                command: "sleep 30".to_string(),
+                stop_command: None,
+                working_dir: None,
+            };
+            config.readiness = Some(HealthChecker::Script {
+                command: format!("test -f {}", ready_file.display()),
+                timeout: Duration::from_secs(1),
+            });
+            services.insert(name.to_string(), config);
+        }
+
+        let config = KrillConfig {
+            max_concurrent_starts: Some(1),
+            daemon_log: Default::default(),
+            log_throttle: Default::default(),
+            services,
+            ..make_single_service_krill_config()
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = std::sync::Arc::new(Orchestrator::new(config, event_tx).unwrap());
+
+        let starter = std::sync::Arc::clone(&orchestrator);
+        tokio::spawn(async move { starter.start_all().await });
+
+        // The first camera holds the only slot until it is ready
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        let snapshot = orchestrator.get_snapshot().await;
+        let mut statuses: Vec<_> = ["camera-left", "camera-right"]
+            .iter()
+            .map(|name| snapshot[*name].status.clone())
+            .collect();
+        statuses.sort_by_key(|status| format!("{:?}", status));
+        assert_eq!(
+            statuses,
+            vec![ServiceStatus::Running, ServiceStatus::Starting]
+        );
+
+        std::fs::write(&ready_file, "").unwrap();
+
+        let mut snapshot = orchestrator.get_snapshot().await;
+        for _ in 0..100 {
+            if snapshot
+                .values()
+                .all(|svc| svc.status == ServiceStatus::Running)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            snapshot = orchestrator.get_snapshot().await;
+        }
+        assert_eq!(snapshot["camera-left"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["camera-right"].status, ServiceStatus::Running);
+
+        orchestrator.shutdown().await.unwrap();
+    }
+}
+
+// ===========================================================================
+// IpcServer tests
+// ===========================================================================
+
+mod ipc_server_tests {
+    use super::*;
+    use krill_common::{ClientMessage, ServerMessage, ServiceConfigSummary, ServiceSnapshot};
+    use krill_daemon::IpcServer;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_tcp_client_receives_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+
+        // Reserve a free port for the TCP listener
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ = response_tx.send(HashMap::new());
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let stream = stream.expect("TCP listener did not come up");
+        let (reader, mut writer) = tokio::io::split(stream);
+
+        let request = serde_json::to_string(&ClientMessage::GetSnapshot).unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        tokio::time::timeout(
+            Duration::from_secs(2),
+            BufReader::new(reader).read_line(&mut line),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let response: ServerMessage = serde_json::from_str(line.trim()).unwrap();
+        assert!(matches!(response, ServerMessage::Snapshot { .. }));
+
+        server.shutdown().await;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_abstract_socket_client_receives_snapshot() {
+        let socket_path = std::path::PathBuf::from(format!("@krill-test-{}", std::process::id()));
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let server =
+            Arc::new(IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap());
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ = response_tx.send(HashMap::new());
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = krill_common::connect_unix_socket(&socket_path) {
+                stream = Some(tokio::net::UnixStream::from_std(s).unwrap());
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let stream = stream.expect("abstract socket did not come up");
+        let (reader, mut writer) = tokio::io::split(stream);
+
+        let request = serde_json::to_string(&ClientMessage::GetSnapshot).unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        tokio::time::timeout(
+            Duration::from_secs(2),
+            BufReader::new(reader).read_line(&mut line),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let response: ServerMessage = serde_json::from_str(line.trim()).unwrap();
+        assert!(matches!(response, ServerMessage::Snapshot { .. }));
+
+        // No socket file was created, so none is left to clean up
+        assert!(!socket_path.exists());
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_client_falling_behind_on_events_is_disconnected() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let stream = stream.expect("TCP listener did not come up");
+        for _ in 0..50 {
+            if !server.fanout().lag().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(server.fanout().lag().len(), 1);
+
+        // Status changes pile up faster than the client is served
+        for _ in 0..=krill_daemon::fanout::MAX_LAG {
+            server.broadcast_event("lidar".to_string(), ServiceStatus::Running);
+        }
+        assert_eq!(server.fanout().disconnected(), 1);
+
+        // The client is told why before the connection closes
+        let mut reader = BufReader::new(stream);
+        let mut last = String::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            if read == 0 {
+                break;
+            }
+            last = line.clone();
+        }
+        let response: ServerMessage = serde_json::from_str(last.trim()).unwrap();
+        assert!(matches!(
+            response,
+            ServerMessage::Error {
+                code: Some(krill_common::ERROR_SLOW_CLIENT),
+                ..
+            }
+        ));
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_client_receives_snapshot_deltas() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        let service = |status: ServiceStatus| ServiceSnapshot {
+            status,
+            pid: Some(42),
+            uid: "abc1234".to_string(),
+            uptime: Some(Duration::from_millis(1500)),
+            restart_count: 0,
+            last_error: None,
+            namespace: "test".to_string(),
+            config: ServiceConfigSummary {
+                executor: "shell".to_string(),
+                restart_policy: RestartPolicy::Never,
+                ..Default::default()
+            },
+            protected: false,
+            legacy_config: Default::default(),
+            labels: Default::default(),
+            safety_stopped: false,
+            restarts_paused: false,
+            metrics: None,
+            heartbeat_metadata: HashMap::new(),
+            heartbeat_age: None,
+            missed_heartbeats: 0,
+            total_restarts: 0,
+            last_exit_code: None,
+            state_history: vec![],
+            state_age: None,
+            availability: Default::default(),
+            maintenance: Vec::new(),
+        };
+        server
+            .publish_snapshot(HashMap::from([(
+                "svc-a".to_string(),
+                service(ServiceStatus::Running),
+            )]))
+            .await;
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+        let request = serde_json::to_string(&ClientMessage::SubscribeSnapshots).unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(line.trim()).unwrap() {
+            ServerMessage::Snapshot { services, seq } => {
+                assert_eq!(seq, Some(1));
+                // Published uptimes are whole seconds
+                assert_eq!(services["svc-a"].uptime, Some(Duration::from_secs(1)));
+            }
+            other => panic!("Expected snapshot, got: {:?}", other),
+        }
+
+        // Publishing an unchanged snapshot sends nothing; a change only its fields
+        let mut running = service(ServiceStatus::Running);
+        running.uptime = Some(Duration::from_millis(1900));
+        server
+            .publish_snapshot(HashMap::from([("svc-a".to_string(), running)]))
+            .await;
+        server
+            .publish_snapshot(HashMap::from([(
+                "svc-a".to_string(),
+                service(ServiceStatus::Healthy),
+            )]))
+            .await;
+
+        line.clear();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(line.trim()).unwrap() {
+            ServerMessage::SnapshotDelta {
+                seq,
+                changed,
+                removed,
+            } => {
+                assert_eq!(seq, 2);
+                assert!(removed.is_empty());
+                let fields: Vec<_> = changed["svc-a"].keys().collect();
+                assert_eq!(fields, vec!["status"]);
+            }
+            other => panic!("Expected snapshot delta, got: {:?}", other),
+        }
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_is_told_of_paused_restarts() {
+        use krill_daemon::storm::StormTripped;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        // The storm trips before any client connects
+        let paused = ServiceSnapshot {
+            status: ServiceStatus::Failed,
+            restarts_paused: true,
+            ..running_snapshot()
+        };
+        server
+            .publish_snapshot(HashMap::from([("svc-a".to_string(), paused)]))
+            .await;
+        server
+            .broadcast_system_degraded(StormTripped {
+                workspace: "test".to_string(),
+                failures: 3,
+                window: Duration::from_secs(60),
+            })
+            .await;
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+        let request = serde_json::to_string(&ClientMessage::Subscribe {
+            events: true,
+            logs: None,
+            raw_logs: false,
+        })
+        .unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(line.trim()).unwrap() {
+            ServerMessage::SystemDegraded {
+                workspace,
+                failures,
+                window,
+            } => {
+                assert_eq!(workspace, "test");
+                assert_eq!(failures, 3);
+                assert_eq!(window, Duration::from_secs(60));
+            }
+            other => panic!("Expected system_degraded, got: {:?}", other),
+        }
+
+        server.shutdown().await;
+    }
+
+    /// Snapshot of a running shell service
+    pub(super) fn running_snapshot() -> ServiceSnapshot {
+        ServiceSnapshot {
+            status: ServiceStatus::Running,
+            pid: Some(42),
+            uid: "abc1234".to_string(),
+            uptime: Some(Duration::from_secs(1)),
+            restart_count: 0,
+            last_error: None,
+            namespace: "test".to_string(),
+            config: ServiceConfigSummary {
+                executor: "shell".to_string(),
+                restart_policy: RestartPolicy::Never,
+                ..Default::default()
+            },
+            protected: false,
+            legacy_config: Default::default(),
+            labels: Default::default(),
+            safety_stopped: false,
+            restarts_paused: false,
+            metrics: None,
+            heartbeat_metadata: HashMap::new(),
+            heartbeat_age: None,
+            missed_heartbeats: 0,
+            total_restarts: 0,
+            last_exit_code: None,
+            state_history: vec![],
+            state_age: None,
+            availability: Default::default(),
+            maintenance: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_command_queues_every_target() {
+        use krill_common::{CommandAction, ERROR_NOT_FOUND};
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        let snapshots = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let snapshots_clone = Arc::clone(&snapshots);
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                snapshots_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let _ = response_tx.send(HashMap::from([
+                    ("camera".to_string(), running_snapshot()),
+                    ("lidar".to_string(), running_snapshot()),
+                ]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        // An unknown target rejects the whole batch
+        let request = serde_json::to_string(&ClientMessage::BatchCommand {
+            action: CommandAction::Restart,
+            targets: vec!["camera".to_string(), "radar".to_string()],
+            force: false,
+        })
+        .unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(line.trim()).unwrap() {
+            ServerMessage::Error { message, code } => {
+                assert_eq!(code, Some(ERROR_NOT_FOUND));
+                assert_eq!(message, "Service 'radar' not found");
+            }
+            other => panic!("Expected error, got: {:?}", other),
+        }
+
+        let request = serde_json::to_string(&ClientMessage::BatchCommand {
+            action: CommandAction::Restart,
+            targets: vec!["camera".to_string(), "lidar".to_string()],
+            force: false,
+        })
+        .unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        let response: ServerMessage = serde_json::from_str(line.trim()).unwrap();
+        assert!(matches!(response, ServerMessage::Ack { .. }));
+
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::Restart, Some("camera".to_string())))
+        );
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::Restart, Some("lidar".to_string())))
+        );
+        // Each batch is checked against one snapshot, whatever its size
+        assert_eq!(snapshots.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_schedule_command_plans_action() {
+        use krill_common::{AuditOutcome, CommandAction, MaintenanceEvent, ERROR_INVALID_REQUEST};
+        use krill_daemon::{AuditLog, Scheduler};
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let (notice_tx, mut notice_rx) = mpsc::unbounded_channel();
+        let scheduler = Arc::new(Scheduler::new(notice_tx));
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        server.set_scheduler(Arc::clone(&scheduler));
+        let audit_log = Arc::new(AuditLog::new(10));
+        server.set_audit_log(Arc::clone(&audit_log));
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ =
+                    response_tx.send(HashMap::from([("lidar".to_string(), running_snapshot())]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        let past = chrono::Utc::now() - chrono::Duration::minutes(1);
+        let at = chrono::Utc::now() + chrono::Duration::hours(1);
+        for (when, accepted) in [(past, false), (at, true)] {
+            let request = serde_json::to_string(&ClientMessage::ScheduleCommand {
+                action: CommandAction::Restart,
+                targets: vec!["lidar".to_string()],
+                at: when,
+                force: false,
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str(line.trim()).unwrap() {
+                ServerMessage::Ack { .. } if accepted => {}
+                ServerMessage::Error { code, .. } if !accepted => {
+                    assert_eq!(code, Some(ERROR_INVALID_REQUEST));
+                }
+                other => panic!("Unexpected response: {:?}", other),
+            }
+        }
+
+        // Planned for later, not applied now
+        let upcoming = scheduler.upcoming("lidar");
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(upcoming[0].action, CommandAction::Restart);
+        assert_eq!(upcoming[0].at, at);
+        let (service, event, _) = notice_rx.recv().await.unwrap();
+        assert_eq!(
+            (service.as_str(), event),
+            ("lidar", MaintenanceEvent::Scheduled)
+        );
+        assert!(command_rx.try_recv().is_err());
+
+        // Audited as scheduled; the scheduler audits it again once applied
+        let records = audit_log.query(None, Some("lidar"));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].action, CommandAction::Restart);
+        assert_eq!(records[0].outcome, AuditOutcome::Scheduled { at });
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_protected_service_requires_force() {
+        use krill_common::{CommandAction, ERROR_CONFIRMATION_REQUIRED};
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let mut planner = running_snapshot();
+                planner.protected = true;
+                planner.config.groups = vec!["planning".to_string()];
+                let _ = response_tx.send(HashMap::from([("planner".to_string(), planner)]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        let commands = [
+            (CommandAction::Stop, "planner"),
+            (CommandAction::Pause, "planner"),
+            (CommandAction::StopGroup, "planning"),
+        ];
+        for (action, target) in commands {
+            for force in [false, true] {
+                let request = serde_json::to_string(&ClientMessage::Command {
+                    action,
+                    target: Some(target.to_string()),
+                    force,
+                })
+                .unwrap();
+                writer
+                    .write_all(format!("{}\n", request).as_bytes())
+                    .await
+                    .unwrap();
+                let mut line = String::new();
+                tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                match (force, serde_json::from_str(line.trim()).unwrap()) {
+                    (false, ServerMessage::Error { code, .. }) => {
+                        assert_eq!(code, Some(ERROR_CONFIRMATION_REQUIRED), "{:?}", action);
+                        assert!(command_rx.try_recv().is_err());
+                    }
+                    (true, ServerMessage::Ack { .. }) => {}
+                    (_, other) => panic!(
+                        "Unexpected response to {:?} with force={}: {:?}",
+                        action, force, other
+                    ),
+                }
+            }
+
+            assert_eq!(
+                command_rx.recv().await,
+                Some((action, Some(target.to_string())))
+            );
+        }
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_protection_check_fails_closed_without_snapshot() {
+        use krill_common::{CommandAction, ERROR_TIMEOUT};
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        // Snapshot requests are never answered
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        for (action, refused) in [(CommandAction::Stop, true), (CommandAction::Start, false)] {
+            let request = serde_json::to_string(&ClientMessage::Command {
+                action,
+                target: Some("planner".to_string()),
+                force: false,
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(3), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str(line.trim()).unwrap() {
+                ServerMessage::Error { code, .. } if refused => {
+                    assert_eq!(code, Some(ERROR_TIMEOUT));
+                    assert!(command_rx.try_recv().is_err());
+                }
+                ServerMessage::Ack { .. } if !refused => {}
+                other => panic!("Unexpected response to {:?}: {:?}", action, other),
+            }
+        }
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_services_by_label_selector() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path, command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let services = [("camera", "perception"), ("lidar", "perception")]
+                    .into_iter()
+                    .chain([("planner", "planning")])
+                    .map(|(name, tier)| {
+                        let mut snapshot = running_snapshot();
+                        snapshot.labels.insert("tier".to_string(), tier.to_string());
+                        if name == "lidar" {
+                            snapshot
+                                .labels
+                                .insert("robot".to_string(), "r2".to_string());
+                        }
+                        (name.to_string(), snapshot)
+                    })
+                    .collect();
+                let _ = response_tx.send(services);
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        for (selector, expected) in [
+            (Some("tier=perception"), Ok(vec!["camera", "lidar"])),
+            (Some("tier=perception,robot!=r2"), Ok(vec!["camera"])),
+            (None, Ok(vec!["camera", "lidar", "planner"])),
+            (Some("tier=a b"), Err(())),
+        ] {
+            let request = serde_json::to_string(&ClientMessage::ListServices {
+                selector: selector.map(String::from),
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            match (expected, serde_json::from_str(line.trim()).unwrap()) {
+                (Ok(expected), ServerMessage::Snapshot { services, .. }) => {
+                    let mut names: Vec<_> = services.keys().map(String::as_str).collect();
+                    names.sort();
+                    assert_eq!(names, expected, "selector {:?}", selector);
+                }
+                (Err(()), ServerMessage::Error { .. }) => {}
+                (_, other) => panic!("Unexpected response to {:?}: {:?}", selector, other),
+            }
+        }
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hello_handshake_negotiates_or_rejects() {
+        use krill_common::{ProtocolVersion, ERROR_INCOMPATIBLE_PROTOCOL};
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let server =
+            Arc::new(IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap());
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        async fn hello(
+            socket_path: &std::path::Path,
+            version: ProtocolVersion,
+        ) -> Vec<ServerMessage> {
+            let mut stream = None;
+            for _ in 0..50 {
+                if let Ok(s) = UnixStream::connect(socket_path).await {
+                    stream = Some(s);
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            let (reader, mut writer) = tokio::io::split(stream.expect("socket did not come up"));
+
+            let request = serde_json::to_string(&ClientMessage::Hello {
+                protocol_version: version,
+                client: Some("test".to_string()),
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+
+            // Read until the server closes the connection or stays quiet
+            let mut reader = BufReader::new(reader);
+            let mut messages = Vec::new();
+            let mut line = String::new();
+            while let Ok(Ok(n)) =
+                tokio::time::timeout(Duration::from_millis(500), reader.read_line(&mut line)).await
+            {
+                if n == 0 {
+                    break;
+                }
+                messages.push(serde_json::from_str(line.trim()).unwrap());
+                line.clear();
+            }
+            messages
+        }
+
+        let newer_minor = ProtocolVersion {
+            major: ProtocolVersion::CURRENT.major,
+            minor: ProtocolVersion::CURRENT.minor + 1,
+        };
+        let messages = hello(&socket_path, newer_minor).await;
+        assert_eq!(
+            messages,
+            vec![ServerMessage::Welcome {
+                protocol_version: ProtocolVersion::CURRENT
+            }]
+        );
+
+        let next_major = ProtocolVersion {
+            major: ProtocolVersion::CURRENT.major + 1,
+            minor: 0,
+        };
+        let messages = hello(&socket_path, next_major).await;
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0],
+            ServerMessage::Error {
+                code: Some(ERROR_INCOMPATIBLE_PROTOCOL),
+                ..
+            }
+        ));
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_replies_carry_request_id() {
+        use krill_common::{ClientRequest, CommandAction, ServerReply};
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let server =
+            Arc::new(IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap());
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let services = HashMap::from([("lidar".to_string(), running_snapshot())]);
+                let _ = response_tx.send(services);
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = UnixStream::connect(&socket_path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("socket did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        for (request_id, message) in [
+            (Some("1"), ClientMessage::GetSnapshot),
+            (
+                Some("2"),
+                ClientMessage::Command {
+                    action: CommandAction::Start,
+                    target: Some("lidar".to_string()),
+                    force: false,
+                },
+            ),
+            (
+                Some("3"),
+                ClientMessage::Command {
+                    action: CommandAction::Start,
+                    target: Some("missing".to_string()),
+                    force: false,
+                },
+            ),
+            (None, ClientMessage::GetSnapshot),
+        ] {
+            let request = serde_json::to_string(&ClientRequest {
+                request_id: request_id.map(String::from),
+                message,
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        let mut replies = Vec::new();
+        for _ in 0..4 {
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            replies.push(serde_json::from_str::<ServerReply>(line.trim()).unwrap());
+        }
+
+        let ids: Vec<_> = replies.iter().map(|r| r.request_id.as_deref()).collect();
+        assert_eq!(ids, [Some("1"), Some("2"), Some("3"), None]);
+        assert!(matches!(replies[0].message, ServerMessage::Snapshot { .. }));
+        assert!(matches!(replies[1].message, ServerMessage::Ack { .. }));
+        assert!(matches!(replies[2].message, ServerMessage::Error { .. }));
+        assert!(matches!(replies[3].message, ServerMessage::Snapshot { .. }));
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_access_policy_sets_socket_mode_and_restricts_tcp_clients() {
+        use krill_common::{AccessConfig, CommandAction, ERROR_PERMISSION_DENIED};
+        use krill_daemon::AccessPolicy;
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let mut server = IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap();
+        server.set_tcp_listen(addr);
+        server.set_access_policy(
+            AccessPolicy::from_config(&AccessConfig {
+                socket_mode: "0660".to_string(),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("TCP listener did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        let mode = std::fs::metadata(&socket_path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // TCP clients have no peer credentials, so restricted commands are refused
+        let request = serde_json::to_string(&ClientMessage::Command {
+            action: CommandAction::EmergencyStop,
+            target: None,
+            force: false,
+        })
+        .unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            serde_json::from_str(line.trim()).unwrap(),
+            ServerMessage::Error {
+                code: Some(ERROR_PERMISSION_DENIED),
+                ..
+            }
+        ));
+        assert!(command_rx.try_recv().is_err());
+
+        // So is changing the daemon's log filter
+        let request = serde_json::to_string(&ClientMessage::SetLogFilter {
+            filter: "trace".to_string(),
+        })
+        .unwrap();
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        line.clear();
+        tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            serde_json::from_str(line.trim()).unwrap(),
+            ServerMessage::Error {
+                code: Some(ERROR_PERMISSION_DENIED),
+                ..
+            }
+        ));
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_upgrade_is_checked_and_holds_other_commands() {
+        use krill_common::{
+            AccessConfig, CommandAction, ERROR_INVALID_REQUEST, ERROR_PERMISSION_DENIED,
+            ERROR_SHUTTING_DOWN,
+        };
+        use krill_daemon::{AccessPolicy, Scheduler};
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap();
+        // Even a recipe that restricts nothing keeps upgrades restricted
+        server.set_access_policy(
+            AccessPolicy::from_config(&AccessConfig {
+                restricted_commands: Vec::new(),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let (notice_tx, _notice_rx) = mpsc::unbounded_channel();
+        let scheduler = Arc::new(Scheduler::new(notice_tx));
+        server.set_scheduler(Arc::clone(&scheduler));
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ =
+                    response_tx.send(HashMap::from([("lidar".to_string(), running_snapshot())]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = UnixStream::connect(&socket_path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut stream = BufReader::new(stream.expect("socket did not come up"));
+        async fn command(
+            stream: &mut BufReader<UnixStream>,
+            action: CommandAction,
+            target: Option<&str>,
+        ) -> Option<i32> {
+            let request = serde_json::to_string(&ClientMessage::Command {
+                action,
+                target: target.map(String::from),
+                force: false,
+            })
+            .unwrap();
+            stream
+                .get_mut()
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(2), stream.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str(line.trim()).unwrap() {
+                ServerMessage::Ack { .. } => None,
+                ServerMessage::Error { code, .. } => code,
+                other => panic!("unexpected reply {:?}", other),
+            }
+        }
+
+        // Only the daemon's own executable or a listed one may be started
+        assert_eq!(
+            command(&mut stream, CommandAction::UpgradeDaemon, Some("/bin/sh")).await,
+            Some(ERROR_PERMISSION_DENIED)
+        );
+        assert_eq!(
+            command(
+                &mut stream,
+                CommandAction::UpgradeDaemon,
+                Some("bin/krill-daemon")
+            )
+            .await,
+            Some(ERROR_INVALID_REQUEST)
+        );
+        assert!(command_rx.try_recv().is_err());
+
+        assert_eq!(
+            command(&mut stream, CommandAction::UpgradeDaemon, None).await,
+            None
+        );
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::UpgradeDaemon, None))
+        );
+
+        // Commands the new daemon would not apply are refused meanwhile,
+        // while an emergency stop goes through
+        assert_eq!(
+            command(&mut stream, CommandAction::Restart, Some("lidar")).await,
+            Some(ERROR_SHUTTING_DOWN)
+        );
+        assert_eq!(
+            command(&mut stream, CommandAction::UpgradeDaemon, None).await,
+            Some(ERROR_SHUTTING_DOWN)
+        );
+        let request = serde_json::to_string(&ClientMessage::ScheduleCommand {
+            action: CommandAction::Restart,
+            targets: vec!["lidar".to_string()],
+            at: chrono::Utc::now() + chrono::Duration::hours(1),
+            force: false,
+        })
+        .unwrap();
+        stream
+            .get_mut()
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(2), stream.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(line.trim()).unwrap() {
+            ServerMessage::Error { code, .. } => assert_eq!(code, Some(ERROR_SHUTTING_DOWN)),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(scheduler.upcoming("lidar").is_empty());
+        assert_eq!(
+            command(&mut stream, CommandAction::EmergencyStop, None).await,
+            None
+        );
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::EmergencyStop, None))
+        );
+
+        server.upgrade_aborted();
+        assert_eq!(
+            command(&mut stream, CommandAction::Restart, Some("lidar")).await,
+            None
+        );
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::Restart, Some("lidar".to_string())))
+        );
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_export_logs_bundles_running_session() {
+        use base64::engine::general_purpose::STANDARD;
+        use base64::Engine;
+        use flate2::read::GzDecoder;
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+        let log_store = LogStore::new(Some(temp_dir.path().join("logs"))).unwrap();
+        log_store.add_log("lidar", "spinning up".to_string()).await;
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let server = Arc::new(
+            IpcServer::with_heartbeat_tx(
+                socket_path.clone(),
+                command_tx,
+                snapshot_req_tx,
+                None,
+                Some(Arc::clone(&log_store)),
+            )
+            .unwrap(),
+        );
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ =
+                    response_tx.send(HashMap::from([("lidar".to_string(), running_snapshot())]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = UnixStream::connect(&socket_path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("socket did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        for session in [None, Some("19700101-000000")] {
+            let request = serde_json::to_string(&ClientMessage::ExportLogs {
+                session: session.map(String::from),
+            })
+            .unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        // The archive comes in chunks, then the reply to the first request
+        let mut bundle = Vec::new();
+        let mut responses = Vec::new();
+        while responses.len() < 2 {
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str::<ServerMessage>(line.trim()).unwrap() {
+                ServerMessage::LogBundleChunk { data } => {
+                    assert!(responses.is_empty(), "chunk after the reply");
+                    bundle.extend(STANDARD.decode(data).unwrap());
+                }
+                response => responses.push(response),
+            }
+        }
+
+        let ServerMessage::LogsExported { ref session, size } = responses[0] else {
+            panic!("unexpected response {:?}", responses[0]);
+        };
+        assert_eq!(size, bundle.len() as u64);
+        let mut archive = tar::Archive::new(GzDecoder::new(bundle.as_slice()));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect();
+        for name in ["lidar.log", "timeline.jsonl", "status.json"] {
+            assert!(
+                names.contains(&format!("{}/{}", session, name)),
+                "{:?}",
+                names
+            );
+        }
+
+        assert!(matches!(
+            responses[1],
+            ServerMessage::Error { ref message, .. } if message.contains("session-19700101-000000")
+        ));
+
+        // Nothing is left behind on the daemon's side
+        let logs: Vec<_> = std::fs::read_dir(temp_dir.path().join("logs"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(logs.len(), 1, "{:?}", logs);
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_commands_are_audited_with_peer_credentials() {
+        use krill_common::{AuditOutcome, CommandAction, ProtocolVersion};
+        use krill_daemon::AuditLog;
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let mut server = IpcServer::new(socket_path.clone(), command_tx, snapshot_req_tx).unwrap();
+        let audit_path = AuditLog::session_path(temp_dir.path());
+        server.set_audit_log(Arc::new(AuditLog::with_file(100, &audit_path).unwrap()));
+        let server = Arc::new(server);
+
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ =
+                    response_tx.send(HashMap::from([("motor".to_string(), running_snapshot())]));
+            }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = UnixStream::connect(&socket_path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (reader, mut writer) = tokio::io::split(stream.expect("socket did not come up"));
+        let mut reader = BufReader::new(reader);
+
+        let requests = [
+            ClientMessage::Hello {
+                protocol_version: ProtocolVersion::CURRENT,
+                client: Some("krill-cli".to_string()),
+            },
+            ClientMessage::Command {
+                action: CommandAction::Restart,
+                target: Some("motor".to_string()),
+                force: false,
+            },
+            ClientMessage::Command {
+                action: CommandAction::Stop,
+                target: Some("gripper".to_string()),
+                force: false,
+            },
+            ClientMessage::GetAuditLog {
+                since_secs: None,
+                service: None,
+            },
+        ];
+        for request in &requests {
+            let request = serde_json::to_string(request).unwrap();
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        let records = loop {
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(2), reader.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            if let ServerMessage::AuditLog { records } = serde_json::from_str(line.trim()).unwrap()
+            {
+                break records;
+            }
+        };
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].action, CommandAction::Restart);
+        assert_eq!(records[0].target.as_deref(), Some("motor"));
+        assert_eq!(records[0].outcome, AuditOutcome::Accepted);
+        // The socket was created by this process, so it carries our uid
+        let uid = std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(&socket_path).unwrap());
+        assert_eq!(records[0].client.uid, Some(uid));
+        assert_eq!(records[0].client.pid, Some(std::process::id() as i32));
+        assert_eq!(records[0].client.client.as_deref(), Some("krill-cli"));
+        assert!(matches!(
+            records[1].outcome,
+            AuditOutcome::Rejected {
+                code: krill_common::ERROR_NOT_FOUND,
+                ..
+            }
+        ));
+
+        // Both commands are on disk as well
+        let content = std::fs::read_to_string(&audit_path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+
+        server.shutdown().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_heartbeat_flood_is_rate_limited() {
+        use krill_daemon::heartbeat_limit::HEARTBEAT_BURST;
+        use tokio::net::UnixStream;
+
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("krill.sock");
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) = mpsc::unbounded_channel();
+        let (heartbeat_tx, mut heartbeat_rx) = mpsc::unbounded_channel();
+        let server = Arc::new(
+            IpcServer::with_heartbeat_tx(
+                socket_path.clone(),
+                command_tx,
+                snapshot_req_tx,
+                Some(heartbeat_tx),
+                None,
+            )
+            .unwrap(),
+        );
+        // Heartbeats are only taken for the services the daemon published
+        server
+            .publish_snapshot(HashMap::from([("lidar".to_string(), running_snapshot())]))
+            .await;
+        let server_clone = Arc::clone(&server);
+        tokio::spawn(async move { server_clone.start().await });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = UnixStream::connect(&socket_path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut stream = stream.expect("socket did not come up");
+
+        // A flood of identical heartbeats ending in a status change, after
+        // one for a service that does not exist
+        let mut flood = serde_json::to_string(&ClientMessage::Heartbeat {
+            service: "made-up".to_string(),
+            status: ServiceStatus::Healthy,
+            metadata: HashMap::new(),
+        })
+        .unwrap();
+        flood.push('\n');
+        for i in 0..=200 {
+            let status = if i == 200 {
+                ServiceStatus::Degraded
+            } else {
+                ServiceStatus::Healthy
+            };
+            let message = ClientMessage::Heartbeat {
+                service: "lidar".to_string(),
+                status,
+                metadata: HashMap::new(),
+            };
+            flood.push_str(&serde_json::to_string(&message).unwrap());
+            flood.push('\n');
+        }
+        stream.write_all(flood.as_bytes()).await.unwrap();
+
+        let mut forwarded = Vec::new();
+        while forwarded.last() != Some(&ServiceStatus::Degraded) {
+            let (_, status, _) = tokio::time::timeout(Duration::from_secs(2), heartbeat_rx.recv())
+                .await
+                .expect("status change was not forwarded")
+                .unwrap();
+            forwarded.push(status);
+        }
+
+        // Only the burst (plus what refilled while reading) got through,
+        // and the status change arrived despite the limit
+        assert!(
+            forwarded.len() <= HEARTBEAT_BURST as usize + 5,
+            "{:?}",
+            forwarded
+        );
+        let counters = server.heartbeat_limiter().counters();
+        assert!(!counters.contains_key("made-up"));
+        let counters = counters["lidar"];
+        assert_eq!(counters.received, 201);
+        assert_eq!(counters.dropped, 201 - forwarded.len() as u64);
+
+        server.shutdown().await;
+    }
+}
+
+// ===========================================================================
+// HttpGateway tests
+// ===========================================================================
+
+mod http_gateway_tests {
+    use super::*;
+    use krill_common::ServiceSnapshot;
+    use krill_daemon::HttpGateway;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    async fn request(addr: std::net::SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(2), stream.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_gateway_requires_token_and_serves_status() {
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let _ = response_tx.send(HashMap::new());
+            }
+        });
+
+        let gateway = Arc::new(HttpGateway::new(
+            "s3cret".to_string(),
+            command_tx,
+            snapshot_req_tx,
+            None,
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(gateway.serve(listener));
+
+        let response = request(addr, "GET /v1/status HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+
+        let response = request(
+            addr,
+            "GET /v1/status HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"services":{}}"#));
+
+        // Unknown services are rejected before any command is sent
+        let response = request(
+            addr,
+            "POST /v1/services/missing/restart HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gateway_restarts_are_audited() {
+        use krill_common::{AuditOutcome, CommandAction};
+        use krill_daemon::AuditLog;
+
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, mut snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        tokio::spawn(async move {
+            while let Some(response_tx) = snapshot_req_rx.recv().await {
+                let services = HashMap::from([(
+                    "svc-a".to_string(),
+                    super::ipc_server_tests::running_snapshot(),
+                )]);
+                let _ = response_tx.send(services);
+            }
+        });
+
+        let audit_log = Arc::new(AuditLog::new(10));
+        let mut gateway = HttpGateway::new("s3cret".to_string(), command_tx, snapshot_req_tx, None);
+        gateway.set_audit_log(Arc::clone(&audit_log));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(gateway).serve(listener));
+
+        let response = request(addr, "POST /v1/services/svc-a/restart HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+        let response = request(
+            addr,
+            "POST /v1/services/svc-a/restart HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 202"));
+        assert_eq!(
+            command_rx.recv().await,
+            Some((CommandAction::Restart, Some("svc-a".to_string())))
+        );
+
+        let records = audit_log.query(None, Some("svc-a"));
+        assert_eq!(records.len(), 2);
+        assert!(matches!(
+            records[0].outcome,
+            AuditOutcome::Rejected {
+                code: krill_common::ERROR_PERMISSION_DENIED,
+                ..
+            }
+        ));
+        assert_eq!(records[1].outcome, AuditOutcome::Accepted);
+        assert_eq!(records[1].action, CommandAction::Restart);
+        assert_eq!(records[1].client.client.as_deref(), Some("http-gateway"));
+        assert!(records[1].client.address.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_gateway_reports_liveness() {
+        use krill_daemon::Liveness;
+
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let (snapshot_req_tx, _snapshot_req_rx) =
+            mpsc::unbounded_channel::<mpsc::UnboundedSender<HashMap<String, ServiceSnapshot>>>();
+        let liveness = Arc::new(Liveness::new(Duration::from_millis(20)));
+
+        let mut gateway = HttpGateway::new("s3cret".to_string(), command_tx, snapshot_req_tx, None);
+        gateway.set_liveness(Arc::clone(&liveness));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(gateway).serve(listener));
+
+        let raw = "GET /v1/healthz HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n";
+        liveness.beat();
+        let response = request(addr, raw).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""alive":true"#));
+
+        // Three intervals without a self-heartbeat
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = request(addr, raw).await;
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.contains(r#""alive":false"#));
+    }
+}
+
+// ===========================================================================
+// Crash recovery tests
+// ===========================================================================
+
+#[cfg(target_os = "linux")]
+mod crash_recovery_tests {
+    use super::*;
+    use krill_daemon::state::{self, DaemonState, ServiceRecord};
+    use krill_daemon::StateFile;
+    use std::collections::BTreeMap;
+    use std::os::unix::process::CommandExt;
+    use std::thread::JoinHandle;
+    use tokio::sync::mpsc;
+
+    /// Spawn `sleep` in its own process group, the way a previous daemon left
+    /// it. A thread reaps it on exit, as init would for a real orphan.
+    fn spawn_orphan() -> (ServiceRecord, JoinHandle<std::process::ExitStatus>) {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let record = ServiceRecord {
+            pid,
+            pgid: Some(pid),
+            start_ticks: state::start_ticks(pid),
+            started_at: chrono::Utc::now() - chrono::Duration::seconds(60),
+        };
+        (record, std::thread::spawn(move || child.wait().unwrap()))
+    }
+
+    /// State of a previous daemon that has exited
+    fn previous_state(services: BTreeMap<String, ServiceRecord>) -> DaemonState {
+        let mut daemon = std::process::Command::new("true").spawn().unwrap();
+        daemon.wait().unwrap();
+        DaemonState {
+            session_id: "previous".to_string(),
+            daemon_pid: daemon.id(),
+            boot_id: None,
+            services,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_adopts_live_service_process() {
+        let (record, orphan) = spawn_orphan();
+        let previous = previous_state(BTreeMap::from([("svc-a".to_string(), record.clone())]));
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(make_single_service_krill_config(), event_tx).unwrap();
+
+        assert_eq!(orchestrator.recover(&previous).await, vec!["svc-a"]);
+
+        let snapshot = orchestrator.get_snapshot().await;
+        assert_eq!(snapshot["svc-a"].status, ServiceStatus::Running);
+        assert_eq!(snapshot["svc-a"].pid, Some(record.pid));
+        assert!(snapshot["svc-a"].uptime.unwrap() >= Duration::from_secs(59));
+
+        // Starting all services leaves the adopted one alone
+        orchestrator.start_all().await.unwrap();
+        assert_eq!(
+            orchestrator.get_snapshot().await["svc-a"].pid,
+            Some(record.pid)
+        );
+
+        orchestrator.stop_service("svc-a").await.unwrap();
+        assert!(!orphan.join().unwrap().success());
+        assert_eq!(
+            orchestrator.get_snapshot().await["svc-a"].status,
+            ServiceStatus::Stopped
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_reaps_orphan_of_removed_service() {
+        let (record, orphan) = spawn_orphan();
+        let previous = previous_state(BTreeMap::from([("removed".to_string(), record)]));
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let orchestrator = Orchestrator::new(make_single_service_krill_config(), event_tx).unwrap();
+
+        assert!(orchestrator.recover(&previous).await.is_empty());
+        assert!(!orphan.join().unwrap().success());
+        assert_eq!(
+            orchestrator.get_snapshot().await["svc-a"].status,
+            ServiceStatus::Starting
+        );
+    }
+
+    #[tokio::test]
+    async fn test_state_file_tracks_running_services() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+
+        let mut config = make_single_service_krill_config();
+        let service = config.services.get_mut("svc-a").unwrap();
+        service.execute = ExecuteConfig::Shell {
+            command: "sleep 30".to_string(),
+            stop_command: None,
+            working_dir: None,
+        };
+
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let mut orchestrator = Orchestrator::new(config, event_tx).unwrap();
+        orchestrator.set_state_file(StateFile::new(path.clone()));
+
+        orchestrator.start_all().await.unwrap();
+        assert!(orchestrator.persist_state().await);
+
+        let state = StateFile::new(path.clone()).load().unwrap().unwrap();
+        let record = &state.services["svc-a"];
+        assert_eq!(
+            Some(record.pid),
+            orchestrator.get_snapshot().await["svc-a"].pid
+        );
+        assert!(record.is_alive());
+
+        orchestrator.shutdown().await.unwrap();
+        assert!(!path.exists());
+        assert!(!orchestrator.persist_state().await);
+        assert!(!path.exists());
+    }
 }
@@ -0,0 +1,23 @@
+// Generates krill.h from the exported functions of src/lib.rs into OUT_DIR
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("invalid cbindgen.toml");
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    // The committed include/krill.h, which C/C++ consumers use without a
+    // Rust toolchain, is checked against this one by the tests
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate C header")
+        .write_to_file(out_dir.join("krill.h"));
+}
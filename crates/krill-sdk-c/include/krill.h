@@ -0,0 +1,76 @@
+/* Krill C SDK - generated by cbindgen from crates/krill-sdk-c, do not edit */
+
+#ifndef KRILL_H
+#define KRILL_H
+
+// Outcome of a Krill SDK call
+typedef enum {
+  KRILL_RESULT_OK = 0,
+  // A null client or string, or a string that is not valid UTF-8
+  KRILL_RESULT_INVALID_ARGUMENT = 1,
+  // The daemon could not be reached
+  KRILL_RESULT_CONNECTION = 2,
+  KRILL_RESULT_IO = 3,
+  KRILL_RESULT_SERIALIZATION = 4,
+  // The daemon rejected a request or did not answer it
+  KRILL_RESULT_REQUEST = 5,
+} KrillResult;
+
+// Connection to the daemon. Opaque to C: create it with `krill_client_new`
+// or `krill_client_connect` and release it with `krill_client_free`.
+typedef struct KrillClient KrillClient;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Connect to the daemon for `service_name`, using the socket the daemon
+// exported in `KRILL_SOCKET` (or `/tmp/krill.sock` outside the daemon).
+// Returns NULL if the daemon cannot be reached.
+//
+// # Safety
+// `service_name` must be null or a valid NUL-terminated string.
+KrillClient *krill_client_new(const char *service_name);
+
+// Connect to the daemon for `service_name` through the socket at
+// `socket_path`. Returns NULL if the daemon cannot be reached.
+//
+// # Safety
+// `service_name` and `socket_path` must be null or valid NUL-terminated
+// strings.
+KrillClient *krill_client_connect(const char *service_name, const char *socket_path);
+
+// Close the connection and release the client. NULL is ignored.
+//
+// # Safety
+// `client` must be null or a client returned by `krill_client_new` or
+// `krill_client_connect` that has not been freed yet.
+void krill_client_free(KrillClient *client);
+
+// Send a heartbeat. While the daemon is unreachable heartbeats are queued
+// and `KRILL_RESULT_OK` is returned.
+//
+// # Safety
+// `client` must be null or a live client; calls on one client must not
+// overlap.
+KrillResult krill_heartbeat(KrillClient *client);
+
+// Report the service as degraded, with a human-readable `reason`
+//
+// # Safety
+// `client` must be null or a live client and `reason` null or a valid
+// NUL-terminated string; calls on one client must not overlap.
+KrillResult krill_report_degraded(KrillClient *client, const char *reason);
+
+// Report the service as healthy again
+//
+// # Safety
+// `client` must be null or a live client; calls on one client must not
+// overlap.
+KrillResult krill_report_healthy(KrillClient *client);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KRILL_H */
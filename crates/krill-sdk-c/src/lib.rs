@@ -0,0 +1,199 @@
+// Krill C SDK - C ABI over the Rust SDK for sending heartbeats to krill daemon
+
+use std::ffi::{c_char, CStr};
+use std::path::PathBuf;
+use tokio::runtime::{Builder, Runtime};
+
+/// Outcome of a Krill SDK call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KrillResult {
+    Ok = 0,
+    /// A null client or string, or a string that is not valid UTF-8
+    InvalidArgument = 1,
+    /// The daemon could not be reached
+    Connection = 2,
+    Io = 3,
+    Serialization = 4,
+    /// The daemon rejected a request or did not answer it
+    Request = 5,
+}
+
+impl From<krill_sdk_rust::KrillError> for KrillResult {
+    fn from(err: krill_sdk_rust::KrillError) -> Self {
+        match err {
+            krill_sdk_rust::KrillError::Connection(_) => KrillResult::Connection,
+            krill_sdk_rust::KrillError::Io(_) => KrillResult::Io,
+            krill_sdk_rust::KrillError::Serialization(_) => KrillResult::Serialization,
+            krill_sdk_rust::KrillError::Daemon(_)
+            | krill_sdk_rust::KrillError::Timeout(_)
+            | krill_sdk_rust::KrillError::UnexpectedReply(_)
+            | krill_sdk_rust::KrillError::UnknownService(_) => KrillResult::Request,
+        }
+    }
+}
+
+/// Connection to the daemon. Opaque to C: create it with `krill_client_new`
+/// or `krill_client_connect` and release it with `krill_client_free`.
+pub struct KrillClient {
+    runtime: Runtime,
+    client: krill_sdk_rust::KrillClient,
+}
+
+impl KrillClient {
+    fn open(
+        connect: impl std::future::Future<
+            Output = Result<krill_sdk_rust::KrillClient, krill_sdk_rust::KrillError>,
+        >,
+    ) -> Option<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build().ok()?;
+        let client = runtime.block_on(connect).ok()?;
+        Some(Self { runtime, client })
+    }
+}
+
+/// Borrow a C string as UTF-8, or `None` if it is null or not UTF-8
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+fn into_result(result: Result<(), krill_sdk_rust::KrillError>) -> KrillResult {
+    match result {
+        Ok(()) => KrillResult::Ok,
+        Err(e) => e.into(),
+    }
+}
+
+/// Connect to the daemon for `service_name`, using the socket the daemon
+/// exported in `KRILL_SOCKET` (or `/tmp/krill.sock` outside the daemon).
+/// Returns NULL if the daemon cannot be reached.
+///
+/// # Safety
+/// `service_name` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn krill_client_new(service_name: *const c_char) -> *mut KrillClient {
+    let Some(service_name) = to_str(service_name) else {
+        return std::ptr::null_mut();
+    };
+    match KrillClient::open(krill_sdk_rust::KrillClient::new(service_name)) {
+        Some(client) => Box::into_raw(Box::new(client)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Connect to the daemon for `service_name` through the socket at
+/// `socket_path`. Returns NULL if the daemon cannot be reached.
+///
+/// # Safety
+/// `service_name` and `socket_path` must be null or valid NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn krill_client_connect(
+    service_name: *const c_char,
+    socket_path: *const c_char,
+) -> *mut KrillClient {
+    let (Some(service_name), Some(socket_path)) = (to_str(service_name), to_str(socket_path))
+    else {
+        return std::ptr::null_mut();
+    };
+    let connect = krill_sdk_rust::KrillClient::connect(service_name, PathBuf::from(socket_path));
+    match KrillClient::open(connect) {
+        Some(client) => Box::into_raw(Box::new(client)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Close the connection and release the client. NULL is ignored.
+///
+/// # Safety
+/// `client` must be null or a client returned by `krill_client_new` or
+/// `krill_client_connect` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn krill_client_free(client: *mut KrillClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Send a heartbeat. While the daemon is unreachable heartbeats are queued
+/// and `KRILL_RESULT_OK` is returned.
+///
+/// # Safety
+/// `client` must be null or a live client; calls on one client must not
+/// overlap.
+#[no_mangle]
+pub unsafe extern "C" fn krill_heartbeat(client: *mut KrillClient) -> KrillResult {
+    let Some(client) = client.as_ref() else {
+        return KrillResult::InvalidArgument;
+    };
+    into_result(client.runtime.block_on(client.client.heartbeat()))
+}
+
+/// Report the service as degraded, with a human-readable `reason`
+///
+/// # Safety
+/// `client` must be null or a live client and `reason` null or a valid
+/// NUL-terminated string; calls on one client must not overlap.
+#[no_mangle]
+pub unsafe extern "C" fn krill_report_degraded(
+    client: *mut KrillClient,
+    reason: *const c_char,
+) -> KrillResult {
+    let (Some(client), Some(reason)) = (client.as_ref(), to_str(reason)) else {
+        return KrillResult::InvalidArgument;
+    };
+    into_result(
+        client
+            .runtime
+            .block_on(client.client.report_degraded(reason)),
+    )
+}
+
+/// Report the service as healthy again
+///
+/// # Safety
+/// `client` must be null or a live client; calls on one client must not
+/// overlap.
+#[no_mangle]
+pub unsafe extern "C" fn krill_report_healthy(client: *mut KrillClient) -> KrillResult {
+    let Some(client) = client.as_ref() else {
+        return KrillResult::InvalidArgument;
+    };
+    into_result(client.runtime.block_on(client.client.report_healthy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_arguments_are_rejected() {
+        unsafe {
+            assert!(krill_client_new(std::ptr::null()).is_null());
+            assert!(krill_client_connect(c"svc".as_ptr(), std::ptr::null()).is_null());
+            assert_eq!(
+                krill_heartbeat(std::ptr::null_mut()),
+                KrillResult::InvalidArgument
+            );
+            assert_eq!(
+                krill_report_degraded(std::ptr::null_mut(), c"slow".as_ptr()),
+                KrillResult::InvalidArgument
+            );
+            krill_client_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_unreachable_daemon_returns_null() {
+        let client = unsafe {
+            krill_client_connect(
+                c"svc".as_ptr(),
+                c"/tmp/krill_c_sdk_socket_that_does_not_exist.sock".as_ptr(),
+            )
+        };
+        assert!(client.is_null());
+    }
+}
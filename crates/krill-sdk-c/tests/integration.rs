@@ -0,0 +1,75 @@
+// These tests stand in for the daemon with a Unix socket listener
+#![cfg(unix)]
+
+use krill_common::{ClientMessage, ServiceStatus};
+use krill_sdk_c::*;
+use std::ffi::CString;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixListener;
+use tempfile::TempDir;
+
+#[test]
+fn committed_header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/krill.h"));
+    let committed = include_str!("../include/krill.h");
+    assert!(
+        generated == committed,
+        "include/krill.h is stale; copy the generated header over it:\n{}",
+        generated
+    );
+}
+
+#[test]
+fn heartbeats_and_degraded_reports_reach_the_socket() {
+    let tmp_dir = TempDir::new().unwrap();
+    let socket_path = tmp_dir.path().join("krill_test.sock");
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let service = CString::new("firmware").unwrap();
+    let path = CString::new(socket_path.to_str().unwrap()).unwrap();
+    let reason = CString::new("motor driver hot").unwrap();
+
+    let client = unsafe { krill_client_connect(service.as_ptr(), path.as_ptr()) };
+    assert!(!client.is_null());
+    let (stream, _) = listener.accept().unwrap();
+
+    unsafe {
+        assert_eq!(krill_heartbeat(client), KrillResult::Ok);
+        assert_eq!(
+            krill_report_degraded(client, reason.as_ptr()),
+            KrillResult::Ok
+        );
+        assert_eq!(krill_report_healthy(client), KrillResult::Ok);
+        krill_client_free(client);
+    }
+
+    let messages: Vec<ClientMessage> = BufReader::new(stream)
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect();
+    let statuses: Vec<_> = messages
+        .into_iter()
+        .map(|message| match message {
+            ClientMessage::Heartbeat {
+                service,
+                status,
+                metadata,
+            } => {
+                assert_eq!(service, "firmware");
+                (status, metadata.get("reason").cloned())
+            }
+            other => panic!("expected a heartbeat, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (ServiceStatus::Healthy, None),
+            (
+                ServiceStatus::Degraded,
+                Some("motor driver hot".to_string())
+            ),
+            (ServiceStatus::Healthy, None),
+        ]
+    );
+}
@@ -1,34 +1,347 @@
 // Krill Rust SDK - Client library for sending heartbeats to krill daemon
+// and querying it about the other services
 
-use krill_common::{ClientMessage, ServiceStatus};
-use std::collections::HashMap;
+use krill_common::{
+    ClientMessage, ClientRequest, ServerMessage, ServerReply, ServiceSnapshot, ServiceStatus,
+};
+use std::collections::{HashMap, VecDeque};
 use std::io;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use tokio::io::AsyncWriteExt;
-use tokio::net::UnixStream;
-use tokio::sync::Mutex;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Messages kept while disconnected before the oldest are dropped
+pub const DEFAULT_BUFFER_CAPACITY: usize = 100;
+
+/// Delay before the first reconnection attempt after losing the daemon
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// Upper bound of the doubling delay between reconnection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long a request waits for the daemon's reply unless set otherwise
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shortest interval between automatic heartbeats; shorter ones are raised
+/// to it
+pub const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_millis(10);
+
+trait Duplex: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Duplex for T {}
+
+type Stream = Box<dyn Duplex>;
+type Writer = tokio::io::WriteHalf<Stream>;
+type DisconnectCallback = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+/// Where the daemon was reached, so the connection can be re-established
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Socket(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl Endpoint {
+    async fn open(&self) -> io::Result<Stream> {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Socket(path) => Ok(Box::new(tokio::net::UnixStream::from_std(
+                krill_common::connect_unix_socket(path)?,
+            )?)),
+            #[cfg(windows)]
+            Endpoint::Socket(path) => Ok(Box::new(
+                tokio::net::windows::named_pipe::ClientOptions::new()
+                    .open(krill_common::pipe_name(path))?,
+            )),
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr).await?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+}
+
+/// A service changed status, as pushed by the daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEvent {
+    pub service: String,
+    pub status: ServiceStatus,
+}
+
+/// Status changes of all services, from [`KrillClient::subscribe_events`].
+/// Outlives reconnections; ends when the client is dropped.
+pub struct EventStream {
+    events: mpsc::UnboundedReceiver<StatusEvent>,
+}
+
+impl EventStream {
+    /// The next status change
+    pub async fn recv(&mut self) -> Option<StatusEvent> {
+        self.events.recv().await
+    }
+}
+
+impl futures::Stream for EventStream {
+    type Item = StatusEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StatusEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// What an [`AutoHeartbeat`] reports with each heartbeat
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Beat {
+    status: ServiceStatus,
+    metadata: HashMap<String, String>,
+}
+
+/// Heartbeats sent in the background by [`KrillClient::start_auto_heartbeat`]
+/// until this handle is dropped, [`stop`](Self::stop) is called or the client
+/// is dropped
+pub struct AutoHeartbeat {
+    beat: watch::Sender<Beat>,
+    task: JoinHandle<()>,
+}
+
+impl AutoHeartbeat {
+    /// Report `status` and `metadata` from now on. Both are replaced at
+    /// once, and a heartbeat carrying them is sent right away.
+    pub fn update(&self, status: ServiceStatus, metadata: HashMap<String, String>) {
+        self.beat.send_replace(Beat { status, metadata });
+    }
+
+    /// Report degraded status with `reason` from now on
+    pub fn report_degraded(&self, reason: &str) {
+        let mut metadata = HashMap::new();
+        metadata.insert("reason".to_string(), reason.to_string());
+        self.update(ServiceStatus::Degraded, metadata);
+    }
+
+    /// Report healthy status, without metadata, from now on
+    pub fn report_healthy(&self) {
+        self.update(ServiceStatus::Healthy, HashMap::new());
+    }
+
+    /// The status currently reported
+    pub fn status(&self) -> ServiceStatus {
+        self.beat.borrow().status.clone()
+    }
+
+    /// Stop sending heartbeats
+    pub fn stop(self) {}
+}
+
+impl Drop for AutoHeartbeat {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Send the current beat every `interval`, and whenever it changes
+async fn send_heartbeats(
+    client: Weak<KrillClient>,
+    mut beat: watch::Receiver<Beat>,
+    interval: Duration,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            changed = beat.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                ticks.reset();
+            }
+        }
+        let Some(client) = client.upgrade() else {
+            return;
+        };
+        let Beat { status, metadata } = beat.borrow_and_update().clone();
+        // Only serialization can fail; unreachable daemons queue the beat
+        let _ = client.send_heartbeat(status, metadata).await;
+    }
+}
+
+/// What the task reading the daemon's messages hands them to
+#[derive(Default)]
+struct Dispatch {
+    /// Requests awaiting their reply, by request id
+    replies: std::sync::Mutex<HashMap<String, oneshot::Sender<ServerMessage>>>,
+    events: std::sync::Mutex<Vec<mpsc::UnboundedSender<StatusEvent>>>,
+}
+
+impl Dispatch {
+    fn deliver(&self, reply: ServerReply) {
+        if let Some(request_id) = reply.request_id {
+            if let Some(reply_tx) = self.replies.lock().unwrap().remove(&request_id) {
+                let _ = reply_tx.send(reply.message);
+            }
+            return;
+        }
+
+        // Logs, metrics and the like are of no interest to services
+        if let ServerMessage::StatusUpdate { service, status } = reply.message {
+            let event = StatusEvent { service, status };
+            self.events
+                .lock()
+                .unwrap()
+                .retain(|events| events.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// Read the daemon's messages until the connection closes, then fail the
+/// requests still waiting: their replies can no longer arrive
+async fn read_messages(reader: tokio::io::ReadHalf<Stream>, dispatch: Arc<Dispatch>) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Ok(reply) = serde_json::from_str::<ServerReply>(line.trim()) {
+            dispatch.deliver(reply);
+        }
+    }
+    dispatch.replies.lock().unwrap().clear();
+}
+
+/// Connection state: the live stream (if any) and messages awaiting delivery
+struct Connection {
+    stream: Option<Writer>,
+    /// Task reading the live stream
+    reader: Option<JoinHandle<()>>,
+    pending: VecDeque<String>,
+    reconnect_delay: Duration,
+    next_attempt: Instant,
+}
+
+impl Connection {
+    fn attach(&mut self, stream: Stream, dispatch: &Arc<Dispatch>) {
+        let (reader, writer) = tokio::io::split(stream);
+        self.detach();
+        self.stream = Some(writer);
+        self.reader = Some(tokio::spawn(read_messages(reader, Arc::clone(dispatch))));
+    }
+
+    fn detach(&mut self) {
+        self.stream = None;
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.detach();
+    }
+}
 
 pub struct KrillClient {
     service_name: String,
-    stream: Mutex<UnixStream>,
+    endpoint: Endpoint,
+    connection: Mutex<Connection>,
+    dispatch: Arc<Dispatch>,
+    next_request_id: AtomicU64,
+    buffer_capacity: usize,
+    request_timeout: Duration,
+    on_disconnect: Option<DisconnectCallback>,
 }
 
 impl KrillClient {
-    /// Create a new Krill client
+    /// Create a new Krill client, connecting to the socket the daemon
+    /// exported in `KRILL_SOCKET` (or `/tmp/krill.sock` outside the daemon)
     pub async fn new(service_name: &str) -> Result<Self, KrillError> {
-        Self::connect(service_name, PathBuf::from("/tmp/krill.sock")).await
+        let socket_path = std::env::var_os(krill_common::SOCKET_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(krill_common::LEGACY_SOCKET_PATH));
+        Self::connect(service_name, socket_path).await
     }
 
     /// Create a new Krill client with custom socket path
+    /// (on Windows, the daemon's named pipe for that path)
     pub async fn connect(service_name: &str, socket_path: PathBuf) -> Result<Self, KrillError> {
-        let stream = UnixStream::connect(&socket_path)
+        Self::open(service_name, Endpoint::Socket(socket_path)).await
+    }
+
+    /// Create a new Krill client connected over TCP (daemon started with `--listen`)
+    pub async fn connect_tcp(
+        service_name: &str,
+        addr: impl ToSocketAddrs,
+    ) -> Result<Self, KrillError> {
+        let stream = TcpStream::connect(addr)
             .await
             .map_err(|e| KrillError::Connection(e.to_string()))?;
+        let peer = stream.peer_addr().map_err(KrillError::Io)?;
+        stream.set_nodelay(true).map_err(KrillError::Io)?;
+
+        Ok(Self::with_stream(
+            service_name,
+            Endpoint::Tcp(peer),
+            Box::new(stream),
+        ))
+    }
+
+    async fn open(service_name: &str, endpoint: Endpoint) -> Result<Self, KrillError> {
+        let stream = endpoint
+            .open()
+            .await
+            .map_err(|e| KrillError::Connection(e.to_string()))?;
+        Ok(Self::with_stream(service_name, endpoint, stream))
+    }
+
+    fn with_stream(service_name: &str, endpoint: Endpoint, stream: Stream) -> Self {
+        let dispatch = Arc::new(Dispatch::default());
+        let mut connection = Connection {
+            stream: None,
+            reader: None,
+            pending: VecDeque::new(),
+            reconnect_delay: INITIAL_RECONNECT_DELAY,
+            next_attempt: Instant::now(),
+        };
+        connection.attach(stream, &dispatch);
 
-        Ok(Self {
+        Self {
             service_name: service_name.to_string(),
-            stream: Mutex::new(stream),
-        })
+            endpoint,
+            connection: Mutex::new(connection),
+            dispatch,
+            next_request_id: AtomicU64::new(1),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            on_disconnect: None,
+        }
+    }
+
+    /// Call `callback` whenever the connection to the daemon is lost. The
+    /// client keeps reconnecting in the background of later sends
+    pub fn on_disconnect(&mut self, callback: impl Fn(&io::Error) + Send + Sync + 'static) {
+        self.on_disconnect = Some(Box::new(callback));
+    }
+
+    /// Set how many messages are kept while disconnected (oldest dropped first)
+    pub fn set_buffer_capacity(&mut self, capacity: usize) {
+        self.buffer_capacity = capacity.max(1);
+    }
+
+    /// Set how long requests wait for the daemon's reply
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = timeout;
+    }
+
+    /// Whether the last send reached the daemon
+    pub async fn is_connected(&self) -> bool {
+        self.connection.lock().await.stream.is_some()
     }
 
     /// Send a heartbeat to the daemon
@@ -58,6 +371,8 @@ impl KrillClient {
             .await
     }
 
+    /// Queue a heartbeat and deliver everything pending. While the daemon is
+    /// unreachable the message stays queued and `Ok` is returned
     async fn send_heartbeat(
         &self,
         status: ServiceStatus,
@@ -72,14 +387,159 @@ impl KrillClient {
         let json = serde_json::to_string(&message)
             .map_err(|e| KrillError::Serialization(e.to_string()))?;
 
-        let mut stream = self.stream.lock().await;
-        stream
-            .write_all(format!("{}\n", json).as_bytes())
-            .await
-            .map_err(KrillError::Io)?;
+        let mut connection = self.connection.lock().await;
+        if connection.pending.len() >= self.buffer_capacity {
+            connection.pending.pop_front();
+        }
+        connection.pending.push_back(format!("{}\n", json));
+        self.deliver(&mut connection).await;
 
         Ok(())
     }
+
+    /// Snapshot of every service the daemon runs
+    pub async fn get_status(&self) -> Result<HashMap<String, ServiceSnapshot>, KrillError> {
+        match self.request(ClientMessage::GetSnapshot).await? {
+            ServerMessage::Snapshot { services, .. } => Ok(services),
+            other => Err(KrillError::UnexpectedReply(format!("{:?}", other))),
+        }
+    }
+
+    /// Current status of `service`, e.g. to wait for a dependency to be
+    /// healthy before using it
+    pub async fn get_service_status(&self, service: &str) -> Result<ServiceStatus, KrillError> {
+        self.get_status()
+            .await?
+            .remove(service)
+            .map(|snapshot| snapshot.status)
+            .ok_or_else(|| KrillError::UnknownService(service.to_string()))
+    }
+
+    /// Receive the status changes of all services from now on
+    pub fn subscribe_events(&self) -> EventStream {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        self.dispatch.events.lock().unwrap().push(events_tx);
+        EventStream { events }
+    }
+
+    /// Send a healthy heartbeat every `interval` from a background task, for
+    /// services that just need to tell krill they are alive. The returned
+    /// handle changes what is reported and stops the heartbeats when dropped.
+    /// `interval` is at least [`MIN_HEARTBEAT_INTERVAL`]. Must be called
+    /// within a Tokio runtime.
+    pub fn start_auto_heartbeat(self: &Arc<Self>, interval: Duration) -> AutoHeartbeat {
+        let interval = interval.max(MIN_HEARTBEAT_INTERVAL);
+        let (beat, beat_rx) = watch::channel(Beat {
+            status: ServiceStatus::Healthy,
+            metadata: HashMap::new(),
+        });
+        let task = tokio::spawn(send_heartbeats(Arc::downgrade(self), beat_rx, interval));
+        AutoHeartbeat { beat, task }
+    }
+
+    /// Send `message` and wait for the daemon's reply to it. Unlike
+    /// heartbeats, requests are not queued while the daemon is unreachable.
+    async fn request(&self, message: ClientMessage) -> Result<ServerMessage, KrillError> {
+        let request_id = self
+            .next_request_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        let request = ClientRequest {
+            request_id: Some(request_id.clone()),
+            message,
+        };
+        let json = serde_json::to_string(&request)
+            .map_err(|e| KrillError::Serialization(e.to_string()))?;
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.dispatch
+            .replies
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), reply_tx);
+
+        {
+            let mut connection = self.connection.lock().await;
+            connection.pending.push_back(format!("{}\n", json));
+            self.deliver(&mut connection).await;
+            if connection.stream.is_none() {
+                connection.pending.pop_back();
+                self.dispatch.replies.lock().unwrap().remove(&request_id);
+                return Err(KrillError::Connection(
+                    "not connected to the daemon".to_string(),
+                ));
+            }
+        }
+
+        let reply = match tokio::time::timeout(self.request_timeout, reply_rx).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => {
+                return Err(KrillError::Connection(
+                    "connection lost before the daemon replied".to_string(),
+                ))
+            }
+            Err(_) => {
+                self.dispatch.replies.lock().unwrap().remove(&request_id);
+                return Err(KrillError::Timeout(self.request_timeout));
+            }
+        };
+        match reply {
+            ServerMessage::Error { message, .. } => Err(KrillError::Daemon(message)),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Write everything pending, reconnecting as needed. While the daemon is
+    /// unreachable the messages stay queued
+    async fn deliver(&self, connection: &mut Connection) {
+        if connection.stream.is_none() {
+            self.reconnect(connection).await;
+        }
+        if let Err(e) = Self::flush(connection).await {
+            connection.detach();
+            if let Some(ref callback) = self.on_disconnect {
+                callback(&e);
+            }
+            // The daemon may simply have restarted - try again right away
+            connection.next_attempt = Instant::now();
+            self.reconnect(connection).await;
+            if Self::flush(connection).await.is_err() {
+                connection.detach();
+            }
+        }
+    }
+
+    /// Re-open the connection if the backoff delay has passed
+    async fn reconnect(&self, connection: &mut Connection) {
+        let now = Instant::now();
+        if now < connection.next_attempt {
+            return;
+        }
+
+        match self.endpoint.open().await {
+            Ok(stream) => {
+                connection.attach(stream, &self.dispatch);
+                connection.reconnect_delay = INITIAL_RECONNECT_DELAY;
+            }
+            Err(_) => {
+                connection.next_attempt = now + connection.reconnect_delay;
+                connection.reconnect_delay =
+                    (connection.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+
+    /// Write pending messages in order, keeping any that could not be sent
+    async fn flush(connection: &mut Connection) -> io::Result<()> {
+        let Some(stream) = connection.stream.as_mut() else {
+            return Ok(());
+        };
+        while let Some(line) = connection.pending.front() {
+            stream.write_all(line.as_bytes()).await?;
+            connection.pending.pop_front();
+        }
+        stream.flush().await
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -92,6 +552,18 @@ pub enum KrillError {
 
     #[error("Serialization error: {0}")]
     Serialization(String),
+
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+
+    #[error("No reply from the daemon within {0:?}")]
+    Timeout(Duration),
+
+    #[error("Unexpected reply from the daemon: {0}")]
+    UnexpectedReply(String),
+
+    #[error("Unknown service: {0}")]
+    UnknownService(String),
 }
 
 #[cfg(test)]
@@ -105,6 +577,27 @@ mod tests {
         let _client_future = KrillClient::new("test-service");
     }
 
+    #[tokio::test]
+    async fn test_tcp_heartbeat_is_line_delimited_json() {
+        use tokio::io::{AsyncBufReadExt, BufReader};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = KrillClient::connect_tcp("tcp-service", addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        client.heartbeat().await.unwrap();
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        let message: ClientMessage = serde_json::from_str(line.trim()).unwrap();
+        assert!(matches!(
+            message,
+            ClientMessage::Heartbeat { ref service, .. } if service == "tcp-service"
+        ));
+    }
+
     #[test]
     fn test_heartbeat_message_format() {
         let message = ClientMessage::Heartbeat {
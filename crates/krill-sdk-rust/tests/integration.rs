@@ -1,3 +1,6 @@
+// These tests stand in for the daemon with a Unix socket listener
+#![cfg(unix)]
+
 use krill_common::{ClientMessage, ServiceStatus};
 use krill_sdk_rust::{KrillClient, KrillError};
 use std::collections::HashMap;
@@ -257,3 +260,173 @@ async fn krill_error_display_is_descriptive() {
         display,
     );
 }
+
+// ---------------------------------------------------------------------------
+// 7. Heartbeats sent while the daemon is down are delivered after it returns
+// ---------------------------------------------------------------------------
+#[tokio::test]
+async fn reconnects_and_flushes_buffered_heartbeats() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let tmp_dir = TempDir::new().expect("failed to create temp dir");
+    let socket_path = tmp_dir.path().join("krill_test.sock");
+    let listener = UnixListener::bind(&socket_path).expect("failed to bind unix listener");
+
+    let mut client = KrillClient::connect("my-service", socket_path.clone())
+        .await
+        .expect("failed to connect");
+    let disconnected = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&disconnected);
+    client.on_disconnect(move |_| flag.store(true, Ordering::SeqCst));
+
+    // Daemon goes away
+    let (stream, _) = listener.accept().await.unwrap();
+    drop(stream);
+    drop(listener);
+    std::fs::remove_file(&socket_path).unwrap();
+
+    for _ in 0..50 {
+        client
+            .heartbeat()
+            .await
+            .expect("heartbeat should be queued");
+        if disconnected.load(Ordering::SeqCst) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(
+        disconnected.load(Ordering::SeqCst),
+        "disconnect not reported"
+    );
+    assert!(!client.is_connected().await);
+
+    client
+        .report_degraded("daemon offline")
+        .await
+        .expect("report should be queued while disconnected");
+
+    // Daemon comes back on the same socket
+    let listener = UnixListener::bind(&socket_path).expect("failed to rebind unix listener");
+    let stream = loop {
+        client.heartbeat().await.unwrap();
+        if let Ok(Ok((stream, _))) =
+            tokio::time::timeout(Duration::from_millis(50), listener.accept()).await
+        {
+            break stream;
+        }
+    };
+
+    // Heartbeats queued before the report are flushed ahead of it
+    let mut lines = BufReader::new(stream).lines();
+    let degraded = loop {
+        let line = lines.next_line().await.unwrap().expect("no line received");
+        let message: ClientMessage = serde_json::from_str(&line).unwrap();
+        if let ClientMessage::Heartbeat {
+            status: ServiceStatus::Degraded,
+            metadata,
+            ..
+        } = message
+        {
+            break metadata;
+        }
+    };
+    assert_eq!(degraded.get("reason").unwrap(), "daemon offline");
+    assert!(client.is_connected().await);
+}
+
+// ---------------------------------------------------------------------------
+// 8. Requests are matched to their replies among pushed events
+// ---------------------------------------------------------------------------
+#[tokio::test]
+async fn requests_receive_their_replies_and_events_stream() {
+    use futures::StreamExt;
+    use krill_common::{ClientRequest, ServerMessage, ServerReply};
+    use krill_sdk_rust::StatusEvent;
+    use tokio::io::AsyncWriteExt;
+
+    let tmp_dir = TempDir::new().expect("failed to create temp dir");
+    let socket_path = tmp_dir.path().join("krill_test.sock");
+    let listener = UnixListener::bind(&socket_path).expect("failed to bind unix listener");
+
+    let client = KrillClient::connect("planner", socket_path.clone())
+        .await
+        .expect("failed to connect client");
+    let mut events = client.subscribe_events();
+
+    // A daemon that pushes a status change ahead of each reply
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let request: ClientRequest = serde_json::from_str(&line).unwrap();
+            let update = ServerMessage::StatusUpdate {
+                service: "lidar".to_string(),
+                status: ServiceStatus::Healthy,
+            };
+            let reply = match request.message {
+                ClientMessage::GetSnapshot => ServerMessage::Snapshot {
+                    services: HashMap::new(),
+                    seq: None,
+                },
+                _ => continue,
+            };
+            for message in [
+                ServerReply::new(None, update),
+                ServerReply::new(request.request_id, reply),
+            ] {
+                let json = serde_json::to_string(&message).unwrap();
+                writer
+                    .write_all(format!("{}\n", json).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        }
+    });
+
+    let services = client.get_status().await.expect("get_status failed");
+    assert!(services.is_empty());
+    match client.get_service_status("lidar").await {
+        Err(KrillError::UnknownService(service)) => assert_eq!(service, "lidar"),
+        other => panic!("Expected KrillError::UnknownService, got: {:?}", other),
+    }
+
+    for _ in 0..2 {
+        let event = tokio::time::timeout(std::time::Duration::from_secs(2), events.next())
+            .await
+            .expect("no event received");
+        assert_eq!(
+            event,
+            Some(StatusEvent {
+                service: "lidar".to_string(),
+                status: ServiceStatus::Healthy,
+            })
+        );
+    }
+}
+
+// ---------------------------------------------------------------------------
+// 9. A request without a reply times out
+// ---------------------------------------------------------------------------
+#[tokio::test]
+async fn request_without_reply_times_out() {
+    use std::time::Duration;
+
+    let tmp_dir = TempDir::new().expect("failed to create temp dir");
+    let socket_path = tmp_dir.path().join("krill_test.sock");
+    let listener = UnixListener::bind(&socket_path).expect("failed to bind unix listener");
+
+    let mut client = KrillClient::connect("planner", socket_path.clone())
+        .await
+        .expect("failed to connect client");
+    client.set_request_timeout(Duration::from_millis(100));
+    let _stream = listener.accept().await.unwrap();
+
+    match client.get_status().await {
+        Err(KrillError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(100)),
+        other => panic!("Expected KrillError::Timeout, got: {:?}", other),
+    }
+}
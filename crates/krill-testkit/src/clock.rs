@@ -0,0 +1,29 @@
+// Fake clock - Time of the mock daemon, moved only by the test
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Time since the mock daemon started, as far as it is concerned. It stands
+/// still until advanced, so heartbeat timeouts expire exactly when a test
+/// says so rather than when a slow CI machine gets around to it.
+#[derive(Debug, Clone, Default)]
+pub struct FakeClock {
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Move the clock forward by `by`, returning the new time
+    pub fn advance(&self, by: Duration) -> Duration {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed += by;
+        *elapsed
+    }
+}
@@ -0,0 +1,562 @@
+// Mock daemon - Serves the IPC protocol from state the test controls
+
+use crate::clock::FakeClock;
+use krill_common::{
+    AvailabilityStats, ClientMessage, ClientRequest, KrillError, LabelSelector, ProtocolVersion,
+    ServerMessage, ServerReply, ServiceConfigSummary, ServiceSnapshot, ServiceStatus,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, watch};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// How long the `wait_for_*` helpers wait before failing the test
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Messages pushed to clients that read them too slowly are dropped beyond this
+const EVENT_CAPACITY: usize = 1024;
+
+/// A heartbeat the mock daemon received
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    pub service: String,
+    pub status: ServiceStatus,
+    pub metadata: HashMap<String, String>,
+    /// Fake-clock time it arrived at
+    pub at: Duration,
+}
+
+type Matcher = Box<dyn Fn(&ClientMessage) -> bool + Send + Sync>;
+
+/// Scripted reply to the messages `matches` accepts; `None` leaves them unanswered
+struct Rule {
+    matches: Matcher,
+    reply: Option<ServerMessage>,
+}
+
+struct MockService {
+    snapshot: ServiceSnapshot,
+    /// Heartbeats are expected at least this often
+    heartbeat_timeout: Option<Duration>,
+    /// Fake-clock time heartbeats were first expected at
+    expected_since: Duration,
+    /// Fake-clock time of the last heartbeat
+    last_heartbeat: Option<Duration>,
+}
+
+impl MockService {
+    fn new(snapshot: ServiceSnapshot) -> Self {
+        Self {
+            snapshot,
+            heartbeat_timeout: None,
+            expected_since: Duration::ZERO,
+            last_heartbeat: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    services: BTreeMap<String, MockService>,
+    rules: Vec<Rule>,
+    received: Vec<ClientMessage>,
+    heartbeats: Vec<Heartbeat>,
+    commands: Vec<ClientMessage>,
+}
+
+impl State {
+    fn snapshot(&self, now: Duration) -> HashMap<String, ServiceSnapshot> {
+        self.services
+            .iter()
+            .map(|(name, service)| {
+                let mut snapshot = service.snapshot.clone();
+                snapshot.heartbeat_age = service.last_heartbeat.map(|at| now.saturating_sub(at));
+                (name.clone(), snapshot)
+            })
+            .collect()
+    }
+
+    /// Record a heartbeat and, like the daemon, report the service's status
+    /// after it. Heartbeats of unknown services are recorded but change nothing.
+    fn heartbeat(&mut self, heartbeat: Heartbeat) -> Option<ServerMessage> {
+        self.heartbeats.push(heartbeat.clone());
+        let service = self.services.get_mut(&heartbeat.service)?;
+        service.last_heartbeat = Some(heartbeat.at);
+        service.snapshot.missed_heartbeats = 0;
+        service.snapshot.heartbeat_metadata = heartbeat.metadata;
+        service.snapshot.status = match heartbeat.status {
+            ServiceStatus::Healthy | ServiceStatus::Running => ServiceStatus::Healthy,
+            _ => ServiceStatus::Degraded,
+        };
+        Some(ServerMessage::StatusUpdate {
+            service: heartbeat.service,
+            status: service.snapshot.status.clone(),
+        })
+    }
+
+    /// Count the heartbeats overdue at `now`, degrading the services that
+    /// newly missed one
+    fn expire_heartbeats(&mut self, now: Duration) -> Vec<ServerMessage> {
+        let mut updates = Vec::new();
+        for (name, service) in &mut self.services {
+            let Some(timeout) = service.heartbeat_timeout else {
+                continue;
+            };
+            let since = service.last_heartbeat.unwrap_or(service.expected_since);
+            let missed = (now.saturating_sub(since).as_nanos() / timeout.as_nanos().max(1)) as u32;
+            if missed <= service.snapshot.missed_heartbeats {
+                continue;
+            }
+            service.snapshot.missed_heartbeats = missed;
+            if service.snapshot.status != ServiceStatus::Degraded {
+                service.snapshot.status = ServiceStatus::Degraded;
+                updates.push(ServerMessage::StatusUpdate {
+                    service: name.clone(),
+                    status: ServiceStatus::Degraded,
+                });
+            }
+        }
+        updates
+    }
+}
+
+/// What the mock daemon and the connections it serves share
+struct Shared {
+    state: Mutex<State>,
+    clock: FakeClock,
+    /// Messages pushed to every client
+    events: broadcast::Sender<ServerMessage>,
+    /// Clients connected
+    clients: watch::Sender<usize>,
+    /// Messages received so far, to wake up tests waiting for one
+    received: watch::Sender<usize>,
+}
+
+impl Shared {
+    /// Answer `message` as scripted, else the way the daemon would; `None`
+    /// where the daemon sends no reply
+    fn handle(&self, message: ClientMessage) -> Option<ServerMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.received.push(message.clone());
+        self.received.send_modify(|count| *count += 1);
+
+        if let Some(rule) = state
+            .rules
+            .iter()
+            .rev()
+            .find(|rule| (rule.matches)(&message))
+        {
+            return rule.reply.clone();
+        }
+
+        let now = self.clock.now();
+        match message {
+            ClientMessage::Hello {
+                protocol_version, ..
+            } => {
+                let server = ProtocolVersion::CURRENT;
+                Some(match server.negotiate(&protocol_version) {
+                    Some(negotiated) => ServerMessage::Welcome {
+                        protocol_version: negotiated,
+                    },
+                    None => KrillError::IncompatibleProtocol {
+                        client: protocol_version,
+                        server,
+                    }
+                    .into(),
+                })
+            }
+            ClientMessage::Heartbeat {
+                service,
+                status,
+                metadata,
+            } => {
+                let update = state.heartbeat(Heartbeat {
+                    service,
+                    status,
+                    metadata,
+                    at: now,
+                });
+                drop(state);
+                if let Some(update) = update {
+                    let _ = self.events.send(update);
+                }
+                None
+            }
+            message @ (ClientMessage::Command { .. }
+            | ClientMessage::BatchCommand { .. }
+            | ClientMessage::ScheduleCommand { .. }) => {
+                state.commands.push(message);
+                Some(ServerMessage::Ack { request_id: None })
+            }
+            // Every client receives the pushed messages
+            ClientMessage::Subscribe { .. } => None,
+            ClientMessage::GetSnapshot => Some(ServerMessage::Snapshot {
+                services: state.snapshot(now),
+                seq: None,
+            }),
+            ClientMessage::ListServices { selector } => {
+                let selector = match selector.as_deref().map(str::parse::<LabelSelector>) {
+                    Some(Err(e)) => return Some(KrillError::InvalidRequest(e).into()),
+                    Some(Ok(selector)) => Some(selector),
+                    None => None,
+                };
+                let mut services = state.snapshot(now);
+                if let Some(selector) = selector {
+                    services.retain(|_, snapshot| selector.matches(&snapshot.labels));
+                }
+                Some(ServerMessage::Snapshot {
+                    services,
+                    seq: None,
+                })
+            }
+            // No deltas follow: changes are pushed as status updates
+            ClientMessage::SubscribeSnapshots => Some(ServerMessage::Snapshot {
+                services: state.snapshot(now),
+                seq: Some(0),
+            }),
+            other => {
+                let kind = serde_json::to_value(&other)
+                    .ok()
+                    .and_then(|value| value["type"].as_str().map(String::from))
+                    .unwrap_or_default();
+                Some(KrillError::Unsupported(format!("`{}`", kind)).into())
+            }
+        }
+    }
+}
+
+/// Serve one client until it disconnects: reply to its requests and push
+/// the daemon's messages to it
+async fn serve<S>(stream: S, shared: Arc<Shared>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let mut events = shared.events.subscribe();
+    shared.clients.send_modify(|clients| *clients += 1);
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let reply = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => match serde_json::from_str::<ClientRequest>(line.trim()) {
+                    Ok(request) => shared
+                        .handle(request.message)
+                        .map(|reply| ServerReply::new(request.request_id, reply)),
+                    Err(e) => Some(ServerReply::new(
+                        None,
+                        KrillError::InvalidRequest(e.to_string()).into(),
+                    )),
+                },
+                Ok(None) | Err(_) => break,
+            },
+            event = events.recv() => match event {
+                Ok(message) => Some(ServerReply::new(None, message)),
+                Err(broadcast::error::RecvError::Lagged(_)) => None,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+
+        let Some(reply) = reply else { continue };
+        let Ok(json) = serde_json::to_string(&reply) else {
+            continue;
+        };
+        if writer
+            .write_all(format!("{}\n", json).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    shared.clients.send_modify(|clients| *clients -= 1);
+}
+
+/// An in-process stand-in for `krill-daemon`, listening on a Unix socket (on
+/// Unix) and on a local TCP port. It keeps a table of services the test sets
+/// up, records the heartbeats and commands clients send, and pushes the
+/// messages the test injects. Time only passes through [`advance`](Self::advance).
+///
+/// Must be started within a tokio runtime; dropping it disconnects all clients.
+pub struct MockDaemon {
+    shared: Arc<Shared>,
+    tcp_addr: SocketAddr,
+    #[cfg(unix)]
+    socket_path: PathBuf,
+    /// Holds the socket
+    _dir: TempDir,
+    listeners: Vec<JoinHandle<()>>,
+    /// Received messages already returned by `next_message`
+    taken: Mutex<usize>,
+}
+
+impl MockDaemon {
+    /// Start listening, with no services
+    pub async fn start() -> io::Result<Self> {
+        let dir = TempDir::new()?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            clock: FakeClock::new(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            clients: watch::channel(0).0,
+            received: watch::channel(0).0,
+        });
+
+        let tcp = TcpListener::bind("127.0.0.1:0").await?;
+        let tcp_addr = tcp.local_addr()?;
+        let mut listeners = vec![tokio::spawn({
+            let shared = Arc::clone(&shared);
+            async move {
+                // Dropping the set when the listener is aborted closes the connections
+                let mut connections = JoinSet::new();
+                while let Ok((stream, _)) = tcp.accept().await {
+                    let _ = stream.set_nodelay(true);
+                    while connections.try_join_next().is_some() {}
+                    connections.spawn(serve(stream, Arc::clone(&shared)));
+                }
+            }
+        })];
+
+        #[cfg(unix)]
+        let socket_path = dir.path().join("krill-mock.sock");
+        #[cfg(unix)]
+        {
+            let unix = tokio::net::UnixListener::bind(&socket_path)?;
+            let shared = Arc::clone(&shared);
+            listeners.push(tokio::spawn(async move {
+                let mut connections = JoinSet::new();
+                while let Ok((stream, _)) = unix.accept().await {
+                    while connections.try_join_next().is_some() {}
+                    connections.spawn(serve(stream, Arc::clone(&shared)));
+                }
+            }));
+        }
+
+        Ok(Self {
+            shared,
+            tcp_addr,
+            #[cfg(unix)]
+            socket_path,
+            _dir: dir,
+            listeners,
+            taken: Mutex::new(0),
+        })
+    }
+
+    /// Unix socket to connect to, e.g. with `KrillClient::connect`
+    #[cfg(unix)]
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// TCP address to connect to, like a daemon started with `--listen`
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.tcp_addr
+    }
+
+    pub fn clock(&self) -> &FakeClock {
+        &self.shared.clock
+    }
+
+    /// Add a service, or replace one, with `status` and nothing else known
+    /// about it
+    pub fn add_service(&self, name: &str, status: ServiceStatus) {
+        self.add_snapshot(name, snapshot(status));
+    }
+
+    /// Add a service, or replace one, as `snapshot` describes it
+    pub fn add_snapshot(&self, name: &str, snapshot: ServiceSnapshot) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .services
+            .insert(name.to_string(), MockService::new(snapshot));
+    }
+
+    /// Degrade `service` once it goes `timeout` without a heartbeat, counting
+    /// from now. Only [`advance`](Self::advance) lets heartbeats go overdue.
+    pub fn expect_heartbeats(&self, service: &str, timeout: Duration) {
+        let now = self.shared.clock.now();
+        let mut state = self.shared.state.lock().unwrap();
+        let service = state
+            .services
+            .entry(service.to_string())
+            .or_insert_with(|| MockService::new(snapshot(ServiceStatus::Running)));
+        service.heartbeat_timeout = Some(timeout);
+        service.expected_since = now;
+    }
+
+    /// Change the status of `service`, adding it if unknown, and push the
+    /// change to all clients
+    pub fn set_status(&self, service: &str, status: ServiceStatus) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .services
+            .entry(service.to_string())
+            .or_insert_with(|| MockService::new(snapshot(status.clone())))
+            .snapshot
+            .status = status.clone();
+        self.inject(ServerMessage::StatusUpdate {
+            service: service.to_string(),
+            status,
+        });
+    }
+
+    /// Push `message` to all connected clients, as the daemon pushes status
+    /// updates, log lines and metrics
+    pub fn inject(&self, message: ServerMessage) {
+        let _ = self.shared.events.send(message);
+    }
+
+    /// Reply `reply` to every message `matches` accepts, instead of what the
+    /// mock would. Of the scripts matching a message, the latest added wins.
+    pub fn respond(
+        &self,
+        matches: impl Fn(&ClientMessage) -> bool + Send + Sync + 'static,
+        reply: ServerMessage,
+    ) {
+        self.script(Box::new(matches), Some(reply));
+    }
+
+    /// Leave every message `matches` accepts unanswered, like a daemon that
+    /// hangs
+    pub fn ignore(&self, matches: impl Fn(&ClientMessage) -> bool + Send + Sync + 'static) {
+        self.script(Box::new(matches), None);
+    }
+
+    fn script(&self, matches: Matcher, reply: Option<ServerMessage>) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .rules
+            .push(Rule { matches, reply });
+    }
+
+    /// Move the fake clock forward, degrading the services whose heartbeats
+    /// became overdue and pushing those changes to all clients
+    pub fn advance(&self, by: Duration) {
+        let now = self.shared.clock.advance(by);
+        let updates = self.shared.state.lock().unwrap().expire_heartbeats(now);
+        for update in updates {
+            self.inject(update);
+        }
+    }
+
+    /// The services as a client's `get_snapshot` would see them now
+    pub fn snapshot(&self) -> HashMap<String, ServiceSnapshot> {
+        let now = self.shared.clock.now();
+        self.shared.state.lock().unwrap().snapshot(now)
+    }
+
+    /// Heartbeats received so far, oldest first
+    pub fn heartbeats(&self) -> Vec<Heartbeat> {
+        self.shared.state.lock().unwrap().heartbeats.clone()
+    }
+
+    /// Commands received so far, oldest first
+    pub fn commands(&self) -> Vec<ClientMessage> {
+        self.shared.state.lock().unwrap().commands.clone()
+    }
+
+    /// Wait until at least `count` clients are connected, e.g. before
+    /// injecting messages they must all receive
+    pub async fn wait_for_clients(&self, count: usize) {
+        let mut clients = self.shared.clients.subscribe();
+        let connected = tokio::time::timeout(WAIT_TIMEOUT, clients.wait_for(|&n| n >= count));
+        if !matches!(connected.await, Ok(Ok(_))) {
+            panic!("timed out waiting for {} clients to connect", count);
+        }
+    }
+
+    /// Wait until at least `count` heartbeats arrived, returning all of them
+    pub async fn wait_for_heartbeats(&self, count: usize) -> Vec<Heartbeat> {
+        self.wait_until(&format!("{} heartbeats", count), |state| {
+            (state.heartbeats.len() >= count).then(|| state.heartbeats.clone())
+        })
+        .await
+    }
+
+    /// Wait for the next message a client sends, of any kind; each is
+    /// returned once, in the order received
+    pub async fn next_message(&self) -> ClientMessage {
+        let taken = *self.taken.lock().unwrap();
+        let message = self
+            .wait_until("a client message", |state| {
+                state.received.get(taken).cloned()
+            })
+            .await;
+        *self.taken.lock().unwrap() = taken + 1;
+        message
+    }
+
+    async fn wait_until<T>(&self, what: &str, mut check: impl FnMut(&State) -> Option<T>) -> T {
+        let mut received = self.shared.received.subscribe();
+        let wait = async {
+            loop {
+                let found = check(&self.shared.state.lock().unwrap());
+                if let Some(found) = found {
+                    return found;
+                }
+                if received.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        match tokio::time::timeout(WAIT_TIMEOUT, wait).await {
+            Ok(found) => found,
+            Err(_) => panic!("timed out waiting for {}", what),
+        }
+    }
+}
+
+impl Drop for MockDaemon {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            listener.abort();
+        }
+    }
+}
+
+/// Snapshot of a service of which only the status is known
+fn snapshot(status: ServiceStatus) -> ServiceSnapshot {
+    ServiceSnapshot {
+        status,
+        pid: None,
+        uid: String::new(),
+        uptime: None,
+        restart_count: 0,
+        last_error: None,
+        namespace: "mock".to_string(),
+        config: ServiceConfigSummary::default(),
+        protected: false,
+        legacy_config: Default::default(),
+        labels: BTreeMap::new(),
+        safety_stopped: false,
+        restarts_paused: false,
+        metrics: None,
+        heartbeat_metadata: HashMap::new(),
+        heartbeat_age: None,
+        missed_heartbeats: 0,
+        total_restarts: 0,
+        last_exit_code: None,
+        state_history: Vec::new(),
+        state_age: None,
+        availability: AvailabilityStats::default(),
+        maintenance: Vec::new(),
+    }
+}
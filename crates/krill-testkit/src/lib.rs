@@ -0,0 +1,8 @@
+// Krill Testkit - An in-process mock daemon speaking the IPC protocol, for
+// integration tests of services using the SDK and of clients like the TUI
+
+pub mod clock;
+pub mod daemon;
+
+pub use clock::FakeClock;
+pub use daemon::{Heartbeat, MockDaemon};
@@ -0,0 +1,253 @@
+// These tests drive the Rust SDK against the mock daemon, the way a service
+// would test its heartbeat and degraded reporting
+#![cfg(unix)]
+
+use krill_common::{ClientMessage, CommandAction, ServerMessage, ServiceStatus};
+use krill_sdk_rust::{KrillClient, KrillError, StatusEvent};
+use krill_testkit::MockDaemon;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn test_heartbeats_and_degraded_reports_are_recorded() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("planner", ServiceStatus::Running);
+    let client = KrillClient::connect("planner", mock.socket_path().to_path_buf())
+        .await
+        .unwrap();
+
+    client.heartbeat().await.unwrap();
+    client.report_degraded("map not loaded").await.unwrap();
+
+    let heartbeats = mock.wait_for_heartbeats(2).await;
+    assert_eq!(heartbeats[0].service, "planner");
+    assert_eq!(heartbeats[0].status, ServiceStatus::Healthy);
+    assert_eq!(heartbeats[1].status, ServiceStatus::Degraded);
+    assert_eq!(heartbeats[1].metadata["reason"], "map not loaded");
+
+    let planner = &mock.snapshot()["planner"];
+    assert_eq!(planner.status, ServiceStatus::Degraded);
+    assert_eq!(planner.heartbeat_metadata["reason"], "map not loaded");
+}
+
+#[tokio::test]
+async fn test_auto_heartbeat_runs_until_stopped() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("planner", ServiceStatus::Running);
+    let client = Arc::new(
+        KrillClient::connect("planner", mock.socket_path().to_path_buf())
+            .await
+            .unwrap(),
+    );
+
+    let heartbeat = client.start_auto_heartbeat(Duration::from_millis(20));
+    let heartbeats = mock.wait_for_heartbeats(3).await;
+    assert!(heartbeats
+        .iter()
+        .all(|heartbeat| heartbeat.status == ServiceStatus::Healthy));
+
+    // Status and metadata change together, and are reported from then on
+    heartbeat.report_degraded("map not loaded");
+    assert_eq!(heartbeat.status(), ServiceStatus::Degraded);
+    let degraded = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let heartbeats = mock.wait_for_heartbeats(mock.heartbeats().len() + 1).await;
+            let last = heartbeats.last().unwrap().clone();
+            if last.status == ServiceStatus::Degraded {
+                return last;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(degraded.metadata["reason"], "map not loaded");
+
+    heartbeat.stop();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let sent = mock.heartbeats().len();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(mock.heartbeats().len(), sent);
+}
+
+#[tokio::test]
+async fn test_auto_heartbeat_with_zero_interval_keeps_beating() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("planner", ServiceStatus::Running);
+    let client = Arc::new(
+        KrillClient::connect("planner", mock.socket_path().to_path_buf())
+            .await
+            .unwrap(),
+    );
+
+    let _heartbeat = client.start_auto_heartbeat(Duration::ZERO);
+    let heartbeats = mock.wait_for_heartbeats(3).await;
+    assert!(heartbeats
+        .iter()
+        .all(|heartbeat| heartbeat.status == ServiceStatus::Healthy));
+}
+
+#[tokio::test]
+async fn test_status_queries_see_the_mock_services() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Healthy);
+    mock.add_service("planner", ServiceStatus::Starting);
+    let client = KrillClient::connect_tcp("planner", mock.tcp_addr())
+        .await
+        .unwrap();
+
+    let services = client.get_status().await.unwrap();
+    assert_eq!(services.len(), 2);
+    assert_eq!(
+        client.get_service_status("lidar").await.unwrap(),
+        ServiceStatus::Healthy
+    );
+    assert!(matches!(
+        client.get_service_status("camera").await,
+        Err(KrillError::UnknownService(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_injected_events_reach_subscribers() {
+    let mock = MockDaemon::start().await.unwrap();
+    let client = KrillClient::connect("planner", mock.socket_path().to_path_buf())
+        .await
+        .unwrap();
+    let mut events = client.subscribe_events();
+    mock.wait_for_clients(1).await;
+
+    mock.set_status("lidar", ServiceStatus::Failed);
+    // Messages other than status updates are of no interest to services
+    mock.inject(ServerMessage::LogLine {
+        service: "lidar".to_string(),
+        line: "disconnected".to_string(),
+        level: None,
+        timestamp: None,
+        stream: None,
+    });
+    mock.inject(ServerMessage::StatusUpdate {
+        service: "lidar".to_string(),
+        status: ServiceStatus::Running,
+    });
+
+    let received = tokio::time::timeout(Duration::from_secs(5), async {
+        vec![events.recv().await.unwrap(), events.recv().await.unwrap()]
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        received,
+        [
+            StatusEvent {
+                service: "lidar".to_string(),
+                status: ServiceStatus::Failed,
+            },
+            StatusEvent {
+                service: "lidar".to_string(),
+                status: ServiceStatus::Running,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_fake_clock_degrades_services_missing_heartbeats() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("planner", ServiceStatus::Running);
+    mock.expect_heartbeats("planner", Duration::from_secs(2));
+    let client = KrillClient::connect("planner", mock.socket_path().to_path_buf())
+        .await
+        .unwrap();
+    let mut events = client.subscribe_events();
+    mock.wait_for_clients(1).await;
+
+    client.heartbeat().await.unwrap();
+    mock.wait_for_heartbeats(1).await;
+    assert_eq!(events.recv().await.unwrap().status, ServiceStatus::Healthy);
+
+    // Time stands still until the test moves it
+    mock.advance(Duration::from_millis(1500));
+    assert_eq!(mock.snapshot()["planner"].status, ServiceStatus::Healthy);
+    assert_eq!(
+        mock.snapshot()["planner"].heartbeat_age,
+        Some(Duration::from_millis(1500))
+    );
+
+    mock.advance(Duration::from_secs(3));
+    let planner = &mock.snapshot()["planner"];
+    assert_eq!(planner.status, ServiceStatus::Degraded);
+    assert_eq!(planner.missed_heartbeats, 2);
+    assert_eq!(
+        events.recv().await.unwrap(),
+        StatusEvent {
+            service: "planner".to_string(),
+            status: ServiceStatus::Degraded,
+        }
+    );
+
+    client.heartbeat().await.unwrap();
+    let heartbeats = mock.wait_for_heartbeats(2).await;
+    assert_eq!(heartbeats[1].at, Duration::from_millis(4500));
+    assert_eq!(mock.snapshot()["planner"].missed_heartbeats, 0);
+}
+
+#[tokio::test]
+async fn test_scripted_replies_override_the_mock() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("planner", ServiceStatus::Healthy);
+    mock.respond(
+        |message| matches!(message, ClientMessage::GetSnapshot),
+        ServerMessage::Error {
+            message: "daemon is busy".to_string(),
+            code: None,
+        },
+    );
+    let mut client = KrillClient::connect("planner", mock.socket_path().to_path_buf())
+        .await
+        .unwrap();
+
+    match client.get_status().await {
+        Err(KrillError::Daemon(message)) => assert_eq!(message, "daemon is busy"),
+        other => panic!("expected the scripted error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(mock.next_message().await, ClientMessage::GetSnapshot);
+
+    // A daemon that never answers lets the request time out
+    mock.ignore(|_| true);
+    client.set_request_timeout(Duration::from_millis(100));
+    assert!(matches!(
+        client.get_status().await,
+        Err(KrillError::Timeout(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_commands_are_recorded_and_acknowledged() {
+    let mock = MockDaemon::start().await.unwrap();
+    let mut stream = TcpStream::connect(mock.tcp_addr()).await.unwrap();
+    let (reader, mut writer) = stream.split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let request = r#"{"request_id":"7","type":"command","action":"restart","target":"lidar"}"#;
+    writer
+        .write_all(format!("{}\n", request).as_bytes())
+        .await
+        .unwrap();
+    let reply = lines.next_line().await.unwrap().unwrap();
+    assert_eq!(
+        serde_json::from_str::<ServerMessage>(&reply).unwrap(),
+        ServerMessage::Ack {
+            request_id: Some("7".to_string())
+        }
+    );
+
+    match &mock.commands()[..] {
+        [ClientMessage::Command { action, target, .. }] => {
+            assert_eq!(*action, CommandAction::Restart);
+            assert_eq!(target.as_deref(), Some("lidar"));
+        }
+        other => panic!("expected one command, got {:?}", other),
+    }
+}
@@ -1,15 +1,126 @@
 // TUI Application State
 
-use krill_common::{ClientMessage, CommandAction, ServerMessage, ServiceStatus};
-use std::collections::HashMap;
+use crate::prefs::{LogDisplay, Preferences};
+use crate::replay::ReplayStatus;
+use chrono::{DateTime, Utc};
+use krill_common::{
+    parse_log_level, ClientMessage, CommandAction, DependencyCondition, LogLevel, LogStream,
+    MaintenanceAction, MaintenanceEvent, ServerMessage, ServiceConfigSummary, ServiceMetrics,
+    ServiceSnapshot, ServiceStatus, SnapshotFields, StateTransition, DEFAULT_LOG_PAGE_LINES,
+};
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Columns the logs view moves per horizontal scroll step
+const HORIZONTAL_SCROLL_STEP: usize = 8;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum View {
     List,
     Logs(String),   // service name
     Detail(String), // service name
+    Tree,           // dependency tree
+    AllLogs,        // logs of every listed service, interleaved
+}
+
+/// Action awaiting an answer in the confirmation dialog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmAction {
+    StopDaemon,
+    EmergencyStop,
+    /// Kill the marked services, or the one under the cursor
+    Kill,
+    /// Start failed services again, which may put them back in a crash loop
+    StartFailed,
+    /// Stop, restart or kill targets that include protected services,
+    /// once the confirmation phrase is typed
+    Protected(CommandAction),
+}
+
+/// Pattern entered with `/`: a case-insensitive regex, or a plain substring
+/// when the text is not a valid regex
+#[derive(Debug, Clone)]
+pub struct SearchPattern {
+    pub text: String,
+    regex: Regex,
+}
+
+impl SearchPattern {
+    pub fn new(text: &str) -> Option<Self> {
+        if text.is_empty() {
+            return None;
+        }
+        let regex = RegexBuilder::new(text)
+            .case_insensitive(true)
+            .build()
+            .or_else(|_| {
+                RegexBuilder::new(&regex::escape(text))
+                    .case_insensitive(true)
+                    .build()
+            })
+            .ok()?;
+        Some(Self {
+            text: text.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Byte ranges of the non-empty matches in `text`, for highlighting
+    pub fn matches(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .map(|m| m.range())
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    /// Services are matched by name or status
+    fn matches_service(&self, service: &ServiceState) -> bool {
+        self.is_match(&service.name) || self.is_match(&format!("{:?}", service.status))
+    }
+}
+
+/// A service in the dependency tree, below the services it depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub service: String,
+    pub depth: usize,
+    /// What the service waits for on the service of its parent row
+    pub condition: Option<DependencyCondition>,
+    pub has_children: bool,
+    pub collapsed: bool,
+}
+
+/// A line of service output and its severity, if it has a recognizable one
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub line: String,
+    pub level: Option<LogLevel>,
+    /// Known for lines that arrived live from a daemon sending it
+    pub timestamp: Option<DateTime<Utc>>,
+    pub stream: Option<LogStream>,
+}
+
+impl LogEntry {
+    /// Entry for a history line, which arrives without its level, time
+    /// and stream
+    fn parse(line: String) -> Self {
+        Self {
+            level: parse_log_level(&line),
+            line,
+            timestamp: None,
+            stream: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -20,14 +131,48 @@ pub struct ServiceState {
     pub uid: String,
     pub restart_count: u32,
     pub namespace: String,
-    pub executor_type: String,
     pub uptime: Option<std::time::Duration>,
-    pub dependencies: Vec<String>,
-    pub uses_gpu: bool,
-    pub critical: bool,
-    pub restart_policy: String,
-    pub max_restarts: u32,
+    /// Dependencies, executor, restart policy and flags from the recipe
+    pub config: ServiceConfigSummary,
+    pub protected: bool,
     pub last_error: Option<String>,
+    pub metrics: Option<ServiceMetrics>,
+    pub heartbeat_metadata: HashMap<String, String>,
+    pub missed_heartbeats: u32,
+    pub total_restarts: u32,
+    pub last_exit_code: Option<i32>,
+    pub state_history: Vec<StateTransition>,
+    pub state_age: Option<std::time::Duration>,
+    /// Upcoming maintenance actions, soonest first
+    pub maintenance: Vec<MaintenanceAction>,
+    /// A restart storm paused the automatic restarts of the workspace
+    pub restarts_paused: bool,
+}
+
+impl ServiceState {
+    fn from_snapshot(name: String, snapshot: ServiceSnapshot) -> Self {
+        Self {
+            name,
+            status: snapshot.status,
+            pid: snapshot.pid,
+            uid: snapshot.uid,
+            restart_count: snapshot.restart_count,
+            namespace: snapshot.namespace,
+            uptime: snapshot.uptime,
+            config: snapshot.config,
+            protected: snapshot.protected,
+            last_error: snapshot.last_error,
+            metrics: snapshot.metrics,
+            heartbeat_metadata: snapshot.heartbeat_metadata,
+            missed_heartbeats: snapshot.missed_heartbeats,
+            total_restarts: snapshot.total_restarts,
+            last_exit_code: snapshot.last_exit_code,
+            state_history: snapshot.state_history,
+            state_age: snapshot.state_age,
+            maintenance: snapshot.maintenance,
+            restarts_paused: snapshot.restarts_paused,
+        }
+    }
 }
 
 pub struct App {
@@ -35,12 +180,46 @@ pub struct App {
     pub services: HashMap<String, ServiceState>,
     pub selected_index: usize,
     pub service_list: Vec<String>,
-    pub logs: HashMap<String, Vec<String>>, // per-service logs
-    pub log_scroll: usize,                  // scroll offset from bottom (0 = at bottom)
-    pub auto_scroll: bool,                  // auto-scroll to new logs
+    /// Services marked with space; bulk actions apply to these
+    pub marked: BTreeSet<String>,
+    /// Snapshot the daemon's `snapshot_delta` messages are applied to
+    snapshots: HashMap<String, ServiceSnapshot>,
+    /// Sequence number of the last applied delta; `None` while waiting
+    /// for a snapshot to apply deltas to
+    snapshot_seq: Option<u64>,
+    /// Text typed after `/` while the search box is open
+    pub search_input: Option<String>,
+    /// Only services matching this are listed
+    pub service_filter: Option<SearchPattern>,
+    /// Log lines matching this are highlighted and reachable with n/N
+    pub log_search: Option<SearchPattern>,
+    pub logs: HashMap<String, Vec<LogEntry>>, // per-service logs
+    /// Only log lines at this severity or above are shown
+    pub log_level: Option<LogLevel>,
+    /// Per service with loaded history: the cursor of its oldest loaded
+    /// line, `None` once everything back to the first line is loaded
+    pub log_cursors: HashMap<String, Option<u64>>,
+    /// A page of older log lines was requested and has not arrived yet
+    pub log_page_pending: bool,
+    /// Services whose dependents are hidden in the tree view
+    pub tree_collapsed: HashSet<String>,
+    pub tree_index: usize,
+    pub log_scroll: usize, // scroll offset from bottom (0 = at bottom)
+    pub auto_scroll: bool, // auto-scroll to new logs
+    /// Columns of the log lines scrolled out on the left, when not wrapping
+    pub log_hscroll: usize,
+    pub log_display: LogDisplay,
+    /// Where toggled display options are saved
+    preferences_path: Option<PathBuf>,
     pub should_quit: bool,
+    /// Keys of the current view shown over it, until any key is pressed
+    pub show_help: bool,
     pub show_confirmation: bool,
     pub confirmation_message: String,
+    pub confirmation_action: ConfirmAction,
+    /// Text to type instead of answering Y/N, for protected services
+    pub confirmation_phrase: Option<String>,
+    pub confirmation_input: String,
     pub message_tx: mpsc::UnboundedSender<ClientMessage>,
     pub uptime_start: std::time::Instant,
     pub cpu_usage: f32,
@@ -48,6 +227,9 @@ pub struct App {
     pub memory_total_mb: u64,
     pub disk_usage_gb: f32,
     pub disk_total_gb: f32,
+    /// Playback position when replaying a recorded journal instead of
+    /// talking to a daemon
+    pub replay: Option<ReplayStatus>,
 }
 
 impl App {
@@ -57,12 +239,30 @@ impl App {
             services: HashMap::new(),
             selected_index: 0,
             service_list: Vec::new(),
+            marked: BTreeSet::new(),
+            snapshots: HashMap::new(),
+            snapshot_seq: None,
+            search_input: None,
+            service_filter: None,
+            log_search: None,
             logs: HashMap::new(),
+            log_level: None,
+            log_cursors: HashMap::new(),
+            log_page_pending: false,
+            tree_collapsed: HashSet::new(),
+            tree_index: 0,
             log_scroll: 0,
             auto_scroll: true,
+            log_hscroll: 0,
+            log_display: LogDisplay::default(),
+            preferences_path: None,
             should_quit: false,
+            show_help: false,
             show_confirmation: false,
             confirmation_message: String::new(),
+            confirmation_action: ConfirmAction::StopDaemon,
+            confirmation_phrase: None,
+            confirmation_input: String::new(),
             message_tx,
             uptime_start: std::time::Instant::now(),
             cpu_usage: 0.0,
@@ -70,6 +270,7 @@ impl App {
             memory_total_mb: 0,
             disk_usage_gb: 0.0,
             disk_total_gb: 0.0,
+            replay: None,
         }
     }
 
@@ -86,60 +287,100 @@ impl App {
                         uid: String::new(),
                         restart_count: 0,
                         namespace: String::new(),
-                        executor_type: String::new(),
                         uptime: None,
-                        dependencies: Vec::new(),
-                        uses_gpu: false,
-                        critical: false,
-                        restart_policy: String::new(),
-                        max_restarts: 0,
+                        config: ServiceConfigSummary::default(),
+                        protected: false,
                         last_error: None,
+                        metrics: None,
+                        heartbeat_metadata: HashMap::new(),
+                        missed_heartbeats: 0,
+                        total_restarts: 0,
+                        last_exit_code: None,
+                        state_history: Vec::new(),
+                        state_age: None,
+                        maintenance: Vec::new(),
+                        restarts_paused: false,
                     });
 
                 // Update service list
                 self.update_service_list();
             }
-            ServerMessage::LogLine { service, line } => {
+            ServerMessage::LogLine {
+                service,
+                line,
+                level,
+                timestamp,
+                stream,
+            } => {
                 // Store logs per-service
                 let service_logs = self.logs.entry(service.clone()).or_default();
-                service_logs.push(line);
+                service_logs.push(LogEntry {
+                    line,
+                    level,
+                    timestamp,
+                    stream,
+                });
 
                 // Keep only last 2000 lines per service
                 if service_logs.len() > 2000 {
                     service_logs.drain(0..1000);
+                    if let Some(cursor) = self.log_cursors.get_mut(&service) {
+                        *cursor = Some(cursor.unwrap_or(0) + 1000);
+                    }
                 }
 
                 // If viewing this service's logs and auto_scroll is on, stay at bottom
-                if let View::Logs(current_service) = &self.current_view {
-                    if current_service == &service && self.auto_scroll {
-                        self.log_scroll = 0;
-                    }
+                let viewing = match &self.current_view {
+                    View::Logs(current_service) => current_service == &service,
+                    View::AllLogs => true,
+                    _ => false,
+                };
+                if viewing && self.auto_scroll {
+                    self.log_scroll = 0;
                 }
             }
-            ServerMessage::Snapshot { services } => {
+            ServerMessage::Snapshot { services, seq } => {
+                if seq.is_some() {
+                    // Base for the deltas that follow
+                    self.services.retain(|name, _| services.contains_key(name));
+                    self.snapshots = services.clone();
+                    self.snapshot_seq = seq;
+                }
                 for (name, snapshot) in services {
-                    self.services.insert(
-                        name.clone(),
-                        ServiceState {
-                            name: name.clone(),
-                            status: snapshot.status,
-                            pid: snapshot.pid,
-                            uid: snapshot.uid,
-                            restart_count: snapshot.restart_count,
-                            namespace: snapshot.namespace,
-                            executor_type: snapshot.executor_type,
-                            uptime: snapshot.uptime,
-                            dependencies: snapshot.dependencies,
-                            uses_gpu: snapshot.uses_gpu,
-                            critical: snapshot.critical,
-                            restart_policy: snapshot.restart_policy,
-                            max_restarts: snapshot.max_restarts,
-                            last_error: snapshot.last_error,
-                        },
-                    );
+                    self.services
+                        .insert(name.clone(), ServiceState::from_snapshot(name, snapshot));
                 }
                 self.update_service_list();
             }
+            ServerMessage::SnapshotDelta {
+                seq,
+                changed,
+                removed,
+            } => self.apply_snapshot_delta(seq, changed, removed),
+            ServerMessage::Maintenance {
+                service,
+                event,
+                maintenance,
+            } => {
+                if let Some(svc) = self.services.get_mut(&service) {
+                    // A cron schedule has a single upcoming run
+                    svc.maintenance.retain(|planned| {
+                        *planned != maintenance
+                            && (planned.cron.is_none()
+                                || planned.cron != maintenance.cron
+                                || planned.action != maintenance.action)
+                    });
+                    if event == MaintenanceEvent::Scheduled {
+                        svc.maintenance.push(maintenance);
+                        svc.maintenance.sort_by_key(|planned| planned.at);
+                    }
+                }
+            }
+            ServerMessage::ServiceMetrics { services } => {
+                for service in self.services.values_mut() {
+                    service.metrics = services.get(&service.name).copied();
+                }
+            }
             ServerMessage::SystemStats {
                 cpu_usage,
                 memory_used_mb,
@@ -153,26 +394,111 @@ impl App {
                 self.disk_usage_gb = disk_usage_gb;
                 self.disk_total_gb = disk_total_gb;
             }
+            ServerMessage::LogPage {
+                service,
+                lines,
+                cursor,
+            } => {
+                // Older lines go in front of those already loaded
+                let service_logs = self.logs.entry(service.clone()).or_default();
+                let mut new_logs: Vec<LogEntry> = lines.into_iter().map(LogEntry::parse).collect();
+                new_logs.append(service_logs);
+                *service_logs = new_logs;
+                self.log_cursors.insert(service, cursor);
+                self.log_page_pending = false;
+            }
             ServerMessage::LogHistory { service, lines } => {
                 // Prepend history to existing logs
                 if let Some(svc) = service {
                     let service_logs = self.logs.entry(svc).or_default();
                     // Insert history at the beginning
-                    let mut new_logs = lines;
+                    let mut new_logs: Vec<LogEntry> =
+                        lines.into_iter().map(LogEntry::parse).collect();
                     new_logs.append(service_logs);
                     *service_logs = new_logs;
                 } else {
                     // All logs - store under special key
-                    self.logs.insert("__all__".to_string(), lines);
+                    self.logs.insert(
+                        "__all__".to_string(),
+                        lines.into_iter().map(LogEntry::parse).collect(),
+                    );
                 }
             }
             _ => {}
         }
     }
 
+    /// Apply a delta following the last one, or ask for a fresh snapshot
+    /// when one was missed
+    fn apply_snapshot_delta(
+        &mut self,
+        seq: u64,
+        changed: HashMap<String, SnapshotFields>,
+        removed: Vec<String>,
+    ) {
+        let Some(last) = self.snapshot_seq else {
+            return;
+        };
+        if seq <= last {
+            return;
+        }
+        if seq > last + 1
+            || krill_common::apply_snapshot_delta(&mut self.snapshots, &changed, &removed).is_err()
+        {
+            self.snapshot_seq = None;
+            let _ = self.subscribe_snapshots();
+            return;
+        }
+        self.snapshot_seq = Some(seq);
+
+        for name in &removed {
+            self.services.remove(name);
+        }
+        for name in changed.keys() {
+            if let Some(snapshot) = self.snapshots.get(name) {
+                self.services.insert(
+                    name.clone(),
+                    ServiceState::from_snapshot(name.clone(), snapshot.clone()),
+                );
+            }
+        }
+        self.update_service_list();
+    }
+
+    /// Forget all services and their logs, e.g. before a replay starts over
+    pub fn reset(&mut self) {
+        self.services.clear();
+        self.snapshots.clear();
+        self.snapshot_seq = None;
+        self.logs.clear();
+        self.log_cursors.clear();
+        self.update_service_list();
+    }
+
     fn update_service_list(&mut self) {
-        self.service_list = self.services.keys().cloned().collect();
+        let selected = self.selected_service().map(String::from);
+        self.service_list = self
+            .services
+            .values()
+            .filter(|s| {
+                self.service_filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches_service(s))
+            })
+            .map(|s| s.name.clone())
+            .collect();
         self.service_list.sort();
+
+        // Keep the cursor on the same service when it is still listed
+        if let Some(index) =
+            selected.and_then(|name| self.service_list.iter().position(|s| *s == name))
+        {
+            self.selected_index = index;
+        }
+        self.selected_index = self
+            .selected_index
+            .min(self.service_list.len().saturating_sub(1));
+        self.marked.retain(|name| self.services.contains_key(name));
     }
 
     pub fn selected_service(&self) -> Option<&str> {
@@ -198,57 +524,249 @@ impl App {
             let service_name = service.to_string();
             self.current_view = View::Logs(service_name.clone());
             self.log_scroll = 0;
+            self.log_hscroll = 0;
             self.auto_scroll = true;
+            self.log_search = None;
 
-            // Request log history first
-            let get_logs_msg = ClientMessage::GetLogs {
-                service: Some(service_name.clone()),
-            };
-            let _ = self.message_tx.send(get_logs_msg);
+            // A replay has no daemon to ask; its lines are all collected already
+            if self.replay.is_some() {
+                return;
+            }
+
+            // Request the newest page of history first; it replaces the
+            // lines collected so far
+            self.logs.remove(&service_name);
+            self.log_cursors.remove(&service_name);
+            self.request_log_page(&service_name, None);
 
             // Subscribe to this service's logs
             let subscribe_msg = ClientMessage::Subscribe {
                 events: true,
                 logs: Some(service_name),
+                raw_logs: false,
             };
             let _ = self.message_tx.send(subscribe_msg);
         }
     }
 
+    /// Show the logs of every listed service, oldest first, following the
+    /// newest line
+    pub fn enter_all_logs(&mut self) {
+        self.current_view = View::AllLogs;
+        self.log_scroll = 0;
+        self.log_hscroll = 0;
+        self.auto_scroll = true;
+        self.log_search = None;
+
+        if self.replay.is_none() {
+            let _ = self.message_tx.send(ClientMessage::Subscribe {
+                events: true,
+                logs: None,
+                raw_logs: false,
+            });
+        }
+    }
+
     pub fn enter_detail(&mut self) {
         if let Some(service) = self.selected_service() {
             self.current_view = View::Detail(service.to_string());
         }
     }
 
+    pub fn enter_tree(&mut self) {
+        self.current_view = View::Tree;
+        self.tree_index = 0;
+    }
+
+    /// Open the detail view of the service under the tree cursor
+    pub fn enter_tree_detail(&mut self) {
+        if let Some(row) = self.tree_rows().get(self.tree_index) {
+            self.current_view = View::Detail(row.service.clone());
+        }
+    }
+
+    /// Rows of the dependency tree: services without dependencies at the
+    /// top, each followed by the services depending on it. A service with
+    /// several dependencies appears below each of them.
+    pub fn tree_rows(&self) -> Vec<TreeRow> {
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut roots = Vec::new();
+        for service in self.services.values() {
+            let mut has_parent = false;
+            for dependency in service.config.dependency_names() {
+                if self.services.contains_key(dependency) {
+                    dependents
+                        .entry(dependency)
+                        .or_default()
+                        .push(service.name.as_str());
+                    has_parent = true;
+                }
+            }
+            if !has_parent {
+                roots.push(service.name.as_str());
+            }
+        }
+        roots.sort();
+        for children in dependents.values_mut() {
+            children.sort();
+        }
+
+        let mut rows = Vec::new();
+        let mut path = Vec::new();
+        for root in roots {
+            self.push_tree_rows(root, None, &dependents, &mut path, &mut rows);
+        }
+        rows
+    }
+
+    fn push_tree_rows<'a>(
+        &self,
+        service: &'a str,
+        parent: Option<&str>,
+        dependents: &HashMap<&str, Vec<&'a str>>,
+        path: &mut Vec<&'a str>,
+        rows: &mut Vec<TreeRow>,
+    ) {
+        // The daemon rejects cycles, but a tree must not recurse forever
+        if path.contains(&service) {
+            return;
+        }
+        let children = dependents.get(service).map(Vec::as_slice).unwrap_or(&[]);
+        let collapsed = self.tree_collapsed.contains(service);
+        let condition = parent.and_then(|parent| {
+            self.services
+                .get(service)
+                .and_then(|s| {
+                    s.config
+                        .dependencies
+                        .iter()
+                        .find(|dependency| dependency.service == parent)
+                })
+                .map(|dependency| dependency.condition)
+        });
+        rows.push(TreeRow {
+            service: service.to_string(),
+            depth: path.len(),
+            condition,
+            has_children: !children.is_empty(),
+            collapsed,
+        });
+
+        if collapsed {
+            return;
+        }
+        path.push(service);
+        for child in children {
+            self.push_tree_rows(child, Some(service), dependents, path, rows);
+        }
+        path.pop();
+    }
+
+    pub fn tree_move_up(&mut self) {
+        self.tree_index = self.tree_index.saturating_sub(1);
+    }
+
+    pub fn tree_move_down(&mut self) {
+        let last = self.tree_rows().len().saturating_sub(1);
+        self.tree_index = (self.tree_index + 1).min(last);
+    }
+
+    /// Collapse or expand the dependents of the service under the cursor
+    pub fn toggle_tree_node(&mut self) {
+        let Some(row) = self.tree_rows().into_iter().nth(self.tree_index) else {
+            return;
+        };
+        if !row.has_children {
+            return;
+        }
+        if !self.tree_collapsed.remove(&row.service) {
+            self.tree_collapsed.insert(row.service);
+        }
+    }
+
     pub fn back_to_list(&mut self) {
         self.current_view = View::List;
         // Re-subscribe to all logs
         let subscribe_msg = ClientMessage::Subscribe {
             events: true,
             logs: None,
+            raw_logs: false,
         };
         let _ = self.message_tx.send(subscribe_msg);
     }
 
-    /// Get logs for the current service being viewed
-    pub fn current_logs(&self) -> &[String] {
-        if let View::Logs(service) = &self.current_view {
-            self.logs.get(service).map(|v| v.as_slice()).unwrap_or(&[])
-        } else {
-            &[]
+    /// Get logs for the current service being viewed, at the selected level
+    /// or above, each with the service it came from. The all-services view
+    /// merges the listed services' lines by time; history lines, which
+    /// carry none, come first.
+    pub fn current_logs(&self) -> Vec<(&str, &LogEntry)> {
+        let services: Vec<&String> = match &self.current_view {
+            View::Logs(service) => vec![service],
+            View::AllLogs => self.service_list.iter().collect(),
+            View::List | View::Detail(_) | View::Tree => return Vec::new(),
+        };
+        let mut logs: Vec<(&str, &LogEntry)> = services
+            .into_iter()
+            .filter_map(|service| Some((service.as_str(), self.logs.get(service)?)))
+            .flat_map(|(service, logs)| logs.iter().map(move |entry| (service, entry)))
+            .filter(|(_, entry)| {
+                self.log_level
+                    .is_none_or(|min| entry.level.is_some_and(|level| level >= min))
+            })
+            .collect();
+        if self.current_view == View::AllLogs {
+            logs.sort_by_key(|(_, entry)| entry.timestamp);
+        }
+        logs
+    }
+
+    /// Cycle the minimum level of shown log lines: all, debug, info, warn, error
+    pub fn cycle_log_level(&mut self) {
+        self.log_level = match self.log_level {
+            None => Some(LogLevel::Debug),
+            Some(LogLevel::Trace) | Some(LogLevel::Debug) => Some(LogLevel::Info),
+            Some(LogLevel::Info) => Some(LogLevel::Warn),
+            Some(LogLevel::Warn) => Some(LogLevel::Error),
+            Some(LogLevel::Error) => None,
+        };
+        self.scroll_logs_to_bottom();
+    }
+
+    fn request_log_page(&mut self, service: &str, before: Option<u64>) {
+        self.log_page_pending = true;
+        let _ = self.message_tx.send(ClientMessage::GetLogsPage {
+            service: service.to_string(),
+            before,
+            lines: DEFAULT_LOG_PAGE_LINES,
+            level: None,
+        });
+    }
+
+    /// Load the next older page of the viewed service's logs once the view
+    /// reached the oldest loaded line
+    fn load_older_logs(&mut self) {
+        let View::Logs(service) = &self.current_view else {
+            return;
+        };
+        if self.log_page_pending || self.log_scroll + 1 < self.current_logs().len() {
+            return;
+        }
+        if let Some(Some(cursor)) = self.log_cursors.get(service).copied() {
+            let service = service.clone();
+            self.request_log_page(&service, Some(cursor));
         }
     }
 
     /// Scroll logs up (older)
     pub fn scroll_logs_up(&mut self, amount: usize) {
-        if let View::Logs(service) = &self.current_view {
-            let total_logs = self.logs.get(service).map(|v| v.len()).unwrap_or(0);
+        if let View::Logs(_) | View::AllLogs = &self.current_view {
+            let total_logs = self.current_logs().len();
             self.log_scroll = self
                 .log_scroll
                 .saturating_add(amount)
                 .min(total_logs.saturating_sub(1));
             self.auto_scroll = false;
+            self.load_older_logs();
         }
     }
 
@@ -262,10 +780,11 @@ impl App {
 
     /// Scroll to top (oldest logs)
     pub fn scroll_logs_to_top(&mut self) {
-        if let View::Logs(service) = &self.current_view {
-            let total_logs = self.logs.get(service).map(|v| v.len()).unwrap_or(0);
+        if let View::Logs(_) | View::AllLogs = &self.current_view {
+            let total_logs = self.current_logs().len();
             self.log_scroll = total_logs.saturating_sub(1);
             self.auto_scroll = false;
+            self.load_older_logs();
         }
     }
 
@@ -275,6 +794,61 @@ impl App {
         self.auto_scroll = true;
     }
 
+    /// Load the display options saved at `path`, saving toggled ones there
+    pub fn use_preferences(&mut self, path: PathBuf) {
+        self.log_display = Preferences::load(&path).logs;
+        self.preferences_path = Some(path);
+    }
+
+    fn save_preferences(&self) {
+        let Some(path) = &self.preferences_path else {
+            return;
+        };
+        let preferences = Preferences {
+            logs: self.log_display,
+        };
+        if let Err(e) = preferences.save(path) {
+            warn!("Failed to save preferences to {:?}: {}", path, e);
+        }
+    }
+
+    pub fn toggle_log_timestamps(&mut self) {
+        self.log_display.timestamps = !self.log_display.timestamps;
+        self.save_preferences();
+    }
+
+    pub fn toggle_log_wrap(&mut self) {
+        self.log_display.wrap = !self.log_display.wrap;
+        self.log_hscroll = 0;
+        self.save_preferences();
+    }
+
+    pub fn toggle_log_stream(&mut self) {
+        self.log_display.stream = !self.log_display.stream;
+        self.save_preferences();
+    }
+
+    /// Scroll long log lines sideways; lines are wrapped instead when
+    /// wrapping is on
+    pub fn scroll_logs_left(&mut self) {
+        self.log_hscroll = self.log_hscroll.saturating_sub(HORIZONTAL_SCROLL_STEP);
+    }
+
+    pub fn scroll_logs_right(&mut self) {
+        if self.log_display.wrap {
+            return;
+        }
+        let longest = self
+            .current_logs()
+            .iter()
+            .map(|(_, entry)| entry.line.chars().count())
+            .max()
+            .unwrap_or(0);
+        if self.log_hscroll + HORIZONTAL_SCROLL_STEP < longest {
+            self.log_hscroll += HORIZONTAL_SCROLL_STEP;
+        }
+    }
+
     /// Toggle auto-scroll mode
     pub fn toggle_auto_scroll(&mut self) {
         self.auto_scroll = !self.auto_scroll;
@@ -283,41 +857,368 @@ impl App {
         }
     }
 
-    pub fn restart_selected(&mut self) -> io::Result<()> {
-        if let Some(service) = self.selected_service() {
-            let msg = ClientMessage::Command {
-                action: CommandAction::Restart,
-                target: Some(service.to_string()),
-            };
-            self.message_tx
-                .send(msg)
-                .map_err(|e| io::Error::other(e.to_string()))?;
+    /// Open the search box, pre-filled with the current view's pattern
+    pub fn open_search(&mut self) {
+        let current = match self.current_view {
+            View::List => self.service_filter.as_ref(),
+            View::Logs(_) | View::AllLogs => self.log_search.as_ref(),
+            View::Detail(_) | View::Tree => return,
+        };
+        self.search_input = Some(current.map(|p| p.text.clone()).unwrap_or_default());
+    }
+
+    pub fn search_push(&mut self, c: char) {
+        if let Some(ref mut input) = self.search_input {
+            input.push(c);
         }
-        Ok(())
+        self.preview_search();
+    }
+
+    pub fn search_pop(&mut self) {
+        if let Some(ref mut input) = self.search_input {
+            input.pop();
+        }
+        self.preview_search();
+    }
+
+    /// The service list narrows while typing; log matches wait for Enter
+    fn preview_search(&mut self) {
+        if self.current_view == View::List {
+            let text = self.search_input.as_deref().unwrap_or_default();
+            self.service_filter = SearchPattern::new(text);
+            self.update_service_list();
+        }
+    }
+
+    /// Close the search box and apply what was typed
+    pub fn apply_search(&mut self) {
+        let Some(text) = self.search_input.take() else {
+            return;
+        };
+        match self.current_view {
+            View::List => {
+                self.service_filter = SearchPattern::new(&text);
+                self.update_service_list();
+            }
+            View::Logs(_) | View::AllLogs => {
+                self.log_search = SearchPattern::new(&text);
+                // Start from the newest match at or above the bottom line
+                self.jump_to_match(|cursor, i| i <= cursor, true);
+            }
+            View::Detail(_) | View::Tree => {}
+        }
+    }
+
+    /// Close the search box and drop the current view's pattern
+    pub fn cancel_search(&mut self) {
+        self.search_input = None;
+        self.clear_search();
+    }
+
+    pub fn clear_search(&mut self) {
+        match self.current_view {
+            View::List => {
+                self.service_filter = None;
+                self.update_service_list();
+            }
+            View::Logs(_) | View::AllLogs => self.log_search = None,
+            View::Detail(_) | View::Tree => {}
+        }
+    }
+
+    /// Whether the current view has a search pattern applied
+    pub fn has_search(&self) -> bool {
+        match self.current_view {
+            View::List => self.service_filter.is_some(),
+            View::Logs(_) | View::AllLogs => self.log_search.is_some(),
+            View::Detail(_) | View::Tree => false,
+        }
+    }
+
+    /// Move to the next match: the next listed service, or the previous
+    /// (older) matching log line
+    pub fn next_match(&mut self) {
+        match self.current_view {
+            View::List => {
+                if !self.service_list.is_empty() {
+                    self.selected_index = (self.selected_index + 1) % self.service_list.len();
+                }
+            }
+            View::Logs(_) | View::AllLogs => self.jump_to_match(|cursor, i| i < cursor, true),
+            View::Detail(_) | View::Tree => {}
+        }
+    }
+
+    /// Move to the previous match: the previous listed service, or the
+    /// next (newer) matching log line
+    pub fn previous_match(&mut self) {
+        match self.current_view {
+            View::List => {
+                let len = self.service_list.len();
+                if len > 0 {
+                    self.selected_index = (self.selected_index + len - 1) % len;
+                }
+            }
+            View::Logs(_) | View::AllLogs => self.jump_to_match(|cursor, i| i > cursor, false),
+            View::Detail(_) | View::Tree => {}
+        }
+    }
+
+    /// Indices of the current view's log lines matching the log search
+    pub fn log_matches(&self) -> Vec<usize> {
+        let Some(ref pattern) = self.log_search else {
+            return Vec::new();
+        };
+        self.current_logs()
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, entry))| pattern.is_match(&entry.line))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Scroll so the matching line closest to the bottom line (in the
+    /// direction given by `accept`) becomes the bottom line
+    fn jump_to_match(&mut self, accept: impl Fn(usize, usize) -> bool, backwards: bool) {
+        let total = self.current_logs().len();
+        if total == 0 {
+            return;
+        }
+        let cursor = total - 1 - self.log_scroll.min(total - 1);
+        let matches = self.log_matches();
+        let found = if backwards {
+            matches.into_iter().rev().find(|&i| accept(cursor, i))
+        } else {
+            matches.into_iter().find(|&i| accept(cursor, i))
+        };
+        if let Some(line) = found {
+            self.log_scroll = total - 1 - line;
+            self.auto_scroll = self.log_scroll == 0;
+        }
+    }
+
+    /// Mark or unmark the service under the cursor
+    pub fn toggle_mark(&mut self) {
+        if let Some(service) = self.selected_service().map(String::from) {
+            if !self.marked.remove(&service) {
+                self.marked.insert(service);
+            }
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+
+    pub fn restart_selected(&mut self) -> io::Result<()> {
+        self.send_to_selected(CommandAction::Restart)
+    }
+
+    /// Signal the service to reload its configuration without a restart
+    pub fn reload_selected(&mut self) -> io::Result<()> {
+        self.send_to_selected(CommandAction::Reload)
+    }
+
+    pub fn start_selected(&mut self) -> io::Result<()> {
+        self.send_to_selected(CommandAction::Start)
     }
 
     pub fn stop_selected(&mut self) -> io::Result<()> {
-        if let Some(service) = self.selected_service() {
-            let msg = ClientMessage::Command {
-                action: CommandAction::Stop,
-                target: Some(service.to_string()),
-            };
-            self.message_tx
-                .send(msg)
-                .map_err(|e| io::Error::other(e.to_string()))?;
+        self.send_to_selected(CommandAction::Stop)
+    }
+
+    /// Start the marked services (or the one under the cursor) when none of
+    /// them runs, i.e. all are stopped, failed, completed or blocked;
+    /// otherwise stop them. Starting a failed service asks first, as it may
+    /// be crash looping.
+    pub fn start_or_stop_selected(&mut self) -> io::Result<()> {
+        let selection: Vec<&str> = if self.marked.is_empty() {
+            self.selected_service().into_iter().collect()
+        } else {
+            self.marked.iter().map(String::as_str).collect()
+        };
+        let all_down = selection.iter().all(|name| {
+            self.services.get(*name).is_some_and(|service| {
+                matches!(
+                    service.status,
+                    ServiceStatus::Stopped
+                        | ServiceStatus::Failed
+                        | ServiceStatus::Completed
+                        | ServiceStatus::Blocked
+                )
+            })
+        });
+        if selection.is_empty() || !all_down {
+            return self.stop_selected();
         }
+
+        let failed: Vec<&ServiceState> = selection
+            .iter()
+            .filter_map(|name| self.services.get(*name))
+            .filter(|service| service.status == ServiceStatus::Failed)
+            .collect();
+        let message = match &failed[..] {
+            [] => return self.start_selected(),
+            [service] => format!(
+                "Start '{}' again? It failed{}. (Y/N)",
+                service.name,
+                service
+                    .last_error
+                    .as_ref()
+                    .map(|error| format!(": {}", error))
+                    .unwrap_or_default()
+            ),
+            failed => format!("Start {} failed services again? (Y/N)", failed.len()),
+        };
+        self.show_confirmation = true;
+        self.confirmation_action = ConfirmAction::StartFailed;
+        self.confirmation_message = message;
         Ok(())
     }
 
+    /// Send `action` for the marked services as one batch, or for the
+    /// service under the cursor when nothing is marked. Stopping,
+    /// restarting or killing protected services asks for confirmation first.
+    fn send_to_selected(&mut self, action: CommandAction) -> io::Result<()> {
+        if matches!(
+            action,
+            CommandAction::Stop | CommandAction::Restart | CommandAction::Kill
+        ) && self.show_protected_confirmation(action)
+        {
+            return Ok(());
+        }
+        self.send_command(action, false)
+    }
+
+    fn send_command(&mut self, action: CommandAction, force: bool) -> io::Result<()> {
+        let msg = if !self.marked.is_empty() {
+            ClientMessage::BatchCommand {
+                action,
+                targets: self.marked.iter().cloned().collect(),
+                force,
+            }
+        } else if let Some(service) = self.selected_service() {
+            ClientMessage::Command {
+                action,
+                target: Some(service.to_string()),
+                force,
+            }
+        } else {
+            return Ok(());
+        };
+
+        self.message_tx
+            .send(msg)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
     pub fn show_stop_daemon_confirmation(&mut self) {
         self.show_confirmation = true;
+        self.confirmation_action = ConfirmAction::StopDaemon;
         self.confirmation_message = "Stop daemon? All services will be stopped. (Y/N)".to_string();
     }
 
+    pub fn show_emergency_stop_confirmation(&mut self) {
+        self.show_confirmation = true;
+        self.confirmation_action = ConfirmAction::EmergencyStop;
+        self.confirmation_message =
+            "EMERGENCY STOP? All services stop until cleared with 'krill estop --clear'. (Y/N)"
+                .to_string();
+    }
+
+    /// Ask for the protected services among the targets to be typed out
+    /// before `action` is sent; false if none of them is protected
+    fn show_protected_confirmation(&mut self, action: CommandAction) -> bool {
+        let targets: Vec<&str> = if !self.marked.is_empty() {
+            self.marked.iter().map(String::as_str).collect()
+        } else {
+            self.selected_service().into_iter().collect()
+        };
+        let protected: Vec<&str> = targets
+            .into_iter()
+            .filter(|name| self.services.get(*name).is_some_and(|svc| svc.protected))
+            .collect();
+
+        let verb = format!("{:?}", action).to_lowercase();
+        let (phrase, message) = match protected.as_slice() {
+            [] => return false,
+            [service] => (
+                service.to_string(),
+                format!(
+                    "'{}' is protected. Type its name and press Enter to {} it.",
+                    service, verb
+                ),
+            ),
+            several => (
+                "all".to_string(),
+                format!(
+                    "{} of the marked services are protected. Type 'all' and press Enter to {} them.",
+                    several.len(),
+                    verb
+                ),
+            ),
+        };
+        self.show_confirmation = true;
+        self.confirmation_action = ConfirmAction::Protected(action);
+        self.confirmation_phrase = Some(phrase);
+        self.confirmation_input.clear();
+        self.confirmation_message = message;
+        true
+    }
+
+    pub fn confirmation_push(&mut self, c: char) {
+        self.confirmation_input.push(c);
+    }
+
+    pub fn confirmation_pop(&mut self) {
+        self.confirmation_input.pop();
+    }
+
+    pub fn show_kill_confirmation(&mut self) {
+        if self.show_protected_confirmation(CommandAction::Kill) {
+            return;
+        }
+        let target = if !self.marked.is_empty() {
+            format!("{} marked services", self.marked.len())
+        } else if let Some(service) = self.selected_service() {
+            format!("'{}'", service)
+        } else {
+            return;
+        };
+        self.show_confirmation = true;
+        self.confirmation_action = ConfirmAction::Kill;
+        self.confirmation_message = format!(
+            "Kill {} with SIGKILL? No stop command or grace period. (Y/N)",
+            target
+        );
+    }
+
+    /// Carry out the action the confirmation dialog was opened for; a
+    /// mistyped phrase clears the input and keeps the dialog open
+    pub fn confirm(&mut self) -> io::Result<()> {
+        if self
+            .confirmation_phrase
+            .as_ref()
+            .is_some_and(|phrase| *phrase != self.confirmation_input)
+        {
+            self.confirmation_input.clear();
+            return Ok(());
+        }
+        self.cancel_confirmation();
+        match self.confirmation_action {
+            ConfirmAction::StopDaemon => self.confirm_stop_daemon(),
+            ConfirmAction::EmergencyStop => self.confirm_emergency_stop(),
+            ConfirmAction::Kill => self.send_command(CommandAction::Kill, false),
+            ConfirmAction::StartFailed => self.send_command(CommandAction::Start, false),
+            ConfirmAction::Protected(action) => self.send_command(action, true),
+        }
+    }
+
     pub fn confirm_stop_daemon(&mut self) -> io::Result<()> {
         let msg = ClientMessage::Command {
             action: CommandAction::StopDaemon,
             target: None,
+            force: false,
         };
         self.message_tx
             .send(msg)
@@ -326,9 +1227,29 @@ impl App {
         Ok(())
     }
 
+    pub fn confirm_emergency_stop(&mut self) -> io::Result<()> {
+        let msg = ClientMessage::Command {
+            action: CommandAction::EmergencyStop,
+            target: None,
+            force: false,
+        };
+        self.message_tx
+            .send(msg)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
     pub fn cancel_confirmation(&mut self) {
         self.show_confirmation = false;
         self.confirmation_message.clear();
+        self.confirmation_phrase = None;
+        self.confirmation_input.clear();
+    }
+
+    /// Receive a snapshot, then only what changes (protocol 1.1 and later)
+    pub fn subscribe_snapshots(&mut self) -> io::Result<()> {
+        self.message_tx
+            .send(ClientMessage::SubscribeSnapshots)
+            .map_err(|e| io::Error::other(e.to_string()))
     }
 
     pub fn request_snapshot(&mut self) -> io::Result<()> {
@@ -0,0 +1,370 @@
+// Keymap - The keys of every view and what they do. Input handling and the
+// `?` help overlay both read these tables, so the help cannot drift from
+// the handlers.
+
+use crate::app::View;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What a key does; `handle_input` maps each to a call on the app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Help,
+    /// Clear the search, else leave the view (or clear the marks in the list)
+    Back,
+    MoveUp,
+    MoveDown,
+    OpenLogs,
+    Describe,
+    OpenTree,
+    AllLogs,
+    Mark,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    Restart,
+    Reload,
+    /// Start the selection if it is stopped, failed, completed or blocked,
+    /// else stop it
+    StartStop,
+    Start,
+    Kill,
+    StopDaemon,
+    EmergencyStop,
+    CycleLevel,
+    ScrollUp(usize),
+    ScrollDown(usize),
+    ScrollTop,
+    ScrollBottom,
+    Follow,
+    Timestamps,
+    Wrap,
+    Stream,
+    PanLeft,
+    PanRight,
+    ToggleNode,
+}
+
+/// A key with the modifiers it needs; Shift is implied by upper-case letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    const fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    const fn char(c: char) -> Self {
+        Self::new(KeyCode::Char(c))
+    }
+
+    const fn ctrl(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::CONTROL,
+        }
+    }
+
+    const fn shift(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::SHIFT,
+        }
+    }
+
+    /// Whether `event` is this key. Control must match exactly, so `Ctrl-u`
+    /// does not also trigger `u`
+    fn matches(&self, event: &KeyEvent) -> bool {
+        event.code == self.code
+            && event.modifiers.contains(self.modifiers)
+            && event.modifiers.contains(KeyModifiers::CONTROL)
+                == self.modifiers.contains(KeyModifiers::CONTROL)
+    }
+
+    /// How the help overlay shows the key
+    pub fn label(&self) -> String {
+        let name = match self.code {
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            other => format!("{:?}", other),
+        };
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            format!("Ctrl-{}", name)
+        } else if self.modifiers.contains(KeyModifiers::SHIFT) {
+            format!("Shift-{}", name)
+        } else {
+            name
+        }
+    }
+}
+
+/// Keys bound to an action, with the text the help overlay shows for it
+#[derive(Debug)]
+pub struct Binding {
+    pub keys: &'static [Key],
+    pub action: Action,
+    pub description: &'static str,
+}
+
+const fn bind(keys: &'static [Key], action: Action, description: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        description,
+    }
+}
+
+const LIST: &[Binding] = &[
+    bind(
+        &[Key::new(KeyCode::Up), Key::char('k')],
+        Action::MoveUp,
+        "Previous service",
+    ),
+    bind(
+        &[Key::new(KeyCode::Down), Key::char('j')],
+        Action::MoveDown,
+        "Next service",
+    ),
+    bind(&[Key::new(KeyCode::Enter)], Action::OpenLogs, "Logs"),
+    bind(&[Key::char('d')], Action::Describe, "Describe"),
+    bind(&[Key::char('t')], Action::OpenTree, "Dependency tree"),
+    bind(
+        &[Key::char('a')],
+        Action::AllLogs,
+        "Logs of all listed services",
+    ),
+    bind(&[Key::char(' ')], Action::Mark, "Mark for a batch action"),
+    bind(&[Key::char('/')], Action::Search, "Filter services"),
+    bind(&[Key::char('n')], Action::NextMatch, "Next match"),
+    bind(&[Key::char('N')], Action::PreviousMatch, "Previous match"),
+    bind(
+        &[Key::new(KeyCode::Esc)],
+        Action::Back,
+        "Clear the filter, else the marks",
+    ),
+    bind(&[Key::char('r')], Action::Restart, "Restart"),
+    bind(&[Key::char('R')], Action::Reload, "Send the reload signal"),
+    bind(
+        &[Key::char('s')],
+        Action::StartStop,
+        "Start if stopped or failed, else stop",
+    ),
+    bind(&[Key::char('u')], Action::Start, "Start"),
+    bind(&[Key::char('x')], Action::Kill, "Kill"),
+    bind(&[Key::char('S')], Action::StopDaemon, "Stop the daemon"),
+    bind(&[Key::char('E')], Action::EmergencyStop, "Emergency stop"),
+    bind(&[Key::char('?')], Action::Help, "Help"),
+    bind(&[Key::char('q')], Action::Quit, "Quit"),
+];
+
+const LOGS: &[Binding] = &[
+    bind(
+        &[Key::new(KeyCode::Up), Key::char('k')],
+        Action::ScrollUp(1),
+        "Scroll up",
+    ),
+    bind(
+        &[Key::new(KeyCode::Down), Key::char('j')],
+        Action::ScrollDown(1),
+        "Scroll down",
+    ),
+    bind(&[Key::char('K')], Action::ScrollUp(5), "Scroll up 5 lines"),
+    bind(
+        &[Key::char('J')],
+        Action::ScrollDown(5),
+        "Scroll down 5 lines",
+    ),
+    bind(&[Key::ctrl('u')], Action::ScrollUp(10), "Half page up"),
+    bind(&[Key::ctrl('d')], Action::ScrollDown(10), "Half page down"),
+    bind(
+        &[Key::new(KeyCode::PageUp)],
+        Action::ScrollUp(20),
+        "Page up",
+    ),
+    bind(
+        &[Key::new(KeyCode::PageDown)],
+        Action::ScrollDown(20),
+        "Page down",
+    ),
+    // Shift-g before g, for terminals reporting it that way
+    bind(
+        &[Key::char('G'), Key::shift('g'), Key::new(KeyCode::End)],
+        Action::ScrollBottom,
+        "Bottom",
+    ),
+    bind(
+        &[Key::char('g'), Key::new(KeyCode::Home)],
+        Action::ScrollTop,
+        "Top",
+    ),
+    bind(
+        &[Key::char('f'), Key::char('F')],
+        Action::Follow,
+        "Follow new lines",
+    ),
+    bind(&[Key::char('/')], Action::Search, "Search"),
+    bind(&[Key::char('n')], Action::NextMatch, "Next match"),
+    bind(&[Key::char('N')], Action::PreviousMatch, "Previous match"),
+    bind(&[Key::char('l')], Action::CycleLevel, "Minimum level"),
+    bind(&[Key::char('t')], Action::Timestamps, "Timestamps"),
+    bind(&[Key::char('o')], Action::Stream, "stdout/stderr markers"),
+    bind(&[Key::char('w')], Action::Wrap, "Wrap long lines"),
+    bind(&[Key::new(KeyCode::Left)], Action::PanLeft, "Scroll left"),
+    bind(
+        &[Key::new(KeyCode::Right)],
+        Action::PanRight,
+        "Scroll right",
+    ),
+    bind(
+        &[Key::new(KeyCode::Esc)],
+        Action::Back,
+        "Clear the search, else back",
+    ),
+    bind(&[Key::char('?')], Action::Help, "Help"),
+    bind(&[Key::char('q')], Action::Quit, "Quit"),
+];
+
+const DETAIL: &[Binding] = &[
+    bind(&[Key::new(KeyCode::Esc)], Action::Back, "Back"),
+    bind(&[Key::char('?')], Action::Help, "Help"),
+    bind(&[Key::char('q')], Action::Quit, "Quit"),
+];
+
+const TREE: &[Binding] = &[
+    bind(
+        &[Key::new(KeyCode::Up), Key::char('k')],
+        Action::MoveUp,
+        "Previous service",
+    ),
+    bind(
+        &[Key::new(KeyCode::Down), Key::char('j')],
+        Action::MoveDown,
+        "Next service",
+    ),
+    bind(
+        &[Key::new(KeyCode::Enter), Key::char(' ')],
+        Action::ToggleNode,
+        "Collapse/expand",
+    ),
+    bind(&[Key::char('d')], Action::Describe, "Describe"),
+    bind(&[Key::new(KeyCode::Esc)], Action::Back, "Back"),
+    bind(&[Key::char('?')], Action::Help, "Help"),
+    bind(&[Key::char('q')], Action::Quit, "Quit"),
+];
+
+/// The keys of `view`, in the order the help overlay lists them
+pub fn bindings(view: &View) -> &'static [Binding] {
+    match view {
+        View::List => LIST,
+        View::Logs(_) | View::AllLogs => LOGS,
+        View::Detail(_) => DETAIL,
+        View::Tree => TREE,
+    }
+}
+
+/// Title of the help overlay for `view`
+pub fn title(view: &View) -> &'static str {
+    match view {
+        View::List => "Services",
+        View::Logs(_) | View::AllLogs => "Logs",
+        View::Detail(_) => "Describe",
+        View::Tree => "Dependency tree",
+    }
+}
+
+/// The action `event` triggers in `view`, if any
+pub fn action(view: &View, event: &KeyEvent) -> Option<Action> {
+    bindings(view)
+        .iter()
+        .find(|binding| binding.keys.iter().any(|key| key.matches(event)))
+        .map(|binding| binding.action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_key_is_bound_twice() {
+        let views = [
+            View::List,
+            View::AllLogs,
+            View::Detail(String::new()),
+            View::Tree,
+        ];
+        for view in &views {
+            let keys: Vec<&Key> = bindings(view)
+                .iter()
+                .flat_map(|binding| binding.keys)
+                .collect();
+            for (i, key) in keys.iter().enumerate() {
+                assert!(
+                    !keys[i + 1..].contains(key),
+                    "{} is bound twice in {}",
+                    key.label(),
+                    title(view)
+                );
+            }
+            // Help is reachable from every view
+            assert!(bindings(view)
+                .iter()
+                .any(|binding| binding.action == Action::Help));
+        }
+    }
+
+    #[test]
+    fn test_action_for_key() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            action(&View::List, &key(KeyCode::Char('s'), KeyModifiers::NONE)),
+            Some(Action::StartStop)
+        );
+        assert_eq!(
+            action(
+                &View::AllLogs,
+                &key(KeyCode::Char('u'), KeyModifiers::CONTROL)
+            ),
+            Some(Action::ScrollUp(10))
+        );
+        // Plain `u` and `d` do nothing in the logs
+        assert_eq!(
+            action(&View::AllLogs, &key(KeyCode::Char('u'), KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(
+            action(
+                &View::AllLogs,
+                &key(KeyCode::Char('g'), KeyModifiers::SHIFT)
+            ),
+            Some(Action::ScrollBottom)
+        );
+        assert_eq!(
+            action(
+                &View::AllLogs,
+                &key(KeyCode::Char('G'), KeyModifiers::SHIFT)
+            ),
+            Some(Action::ScrollBottom)
+        );
+        assert_eq!(
+            action(&View::Tree, &key(KeyCode::Char('s'), KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(Key::ctrl('d').label(), "Ctrl-d");
+    }
+}
@@ -1,31 +1,180 @@
 // Krill TUI Library
 
 pub mod app;
+pub mod keymap;
+pub mod prefs;
+pub mod replay;
 pub mod ui;
 
 pub use app::App;
 
-use anyhow::{Context, Result};
+use keymap::Action;
+
+use anyhow::{bail, Context, Result};
 use crossterm::{
-    event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
-    },
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use krill_common::{ClientMessage, ServerMessage};
+use krill_common::{ClientMessage, ProtocolVersion, ServerMessage, ERROR_INCOMPATIBLE_PROTOCOL};
 use ratatui::{backend::CrosstermBackend, Terminal};
+use std::collections::VecDeque;
 use std::io;
 use std::path::PathBuf;
+use std::sync::Arc;
 use sysinfo::{Disks, System};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Debug, Clone)]
 pub struct TuiConfig {
     pub socket: PathBuf,
+    /// Connect over TCP (`host:port`) instead of the Unix socket
+    pub connect: Option<String>,
+    /// Talk to the daemon over the stdin and stdout of this command instead,
+    /// such as an `ssh` running `krill ps --stdio` on a robot
+    pub tunnel: Option<Vec<String>>,
+}
+
+trait DaemonStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> DaemonStream for T {}
+
+/// Lines of a tunnel command's stderr kept to report, the last ones
+const TUNNEL_STDERR_LINES: usize = 20;
+
+/// How long a failed connection waits for the tunnel command to exit, so
+/// its last words can be reported
+const TUNNEL_EXIT_WAIT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// What a tunnel command writes to stderr, such as SSH's connection errors.
+/// Kept instead of letting it scribble over the TUI, and reported once the
+/// TUI is gone.
+struct TunnelStderr {
+    output: Arc<std::sync::Mutex<VecDeque<String>>>,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl TunnelStderr {
+    fn capture(stderr: tokio::process::ChildStderr) -> Self {
+        let output = Arc::new(std::sync::Mutex::new(VecDeque::new()));
+        let reader = tokio::spawn({
+            let output = Arc::clone(&output);
+            async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let mut output = output.lock().unwrap();
+                    if output.len() == TUNNEL_STDERR_LINES {
+                        output.pop_front();
+                    }
+                    output.push_back(line);
+                }
+            }
+        });
+        Self { output, reader }
+    }
+
+    /// The output so far, after waiting up to `wait` for the command to close
+    /// its stderr; `None` if it wrote nothing
+    async fn output(&mut self, wait: std::time::Duration) -> Option<String> {
+        let _ = tokio::time::timeout(wait, &mut self.reader).await;
+        let output = Vec::from(self.output.lock().unwrap().clone()).join("\n");
+        let output = output.trim();
+        (!output.is_empty()).then(|| output.to_string())
+    }
+}
+
+async fn connect(config: &TuiConfig) -> io::Result<(Box<dyn DaemonStream>, Option<TunnelStderr>)> {
+    if let Some((program, args)) = config.tunnel.as_ref().and_then(|argv| argv.split_first()) {
+        // SSH asks for passwords and host key confirmations on the terminal
+        // itself, not on stderr; the command exits once the TUI closes its stdin
+        let mut child = tokio::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout), Some(stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(io::Error::other("tunnel command has no stdio"));
+        };
+        let stderr = TunnelStderr::capture(stderr);
+        return Ok((Box::new(tokio::io::join(stdout, stdin)), Some(stderr)));
+    }
+
+    let stream: Box<dyn DaemonStream> = match &config.connect {
+        Some(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Box::new(stream)
+        }
+        #[cfg(unix)]
+        None => Box::new(tokio::net::UnixStream::from_std(
+            krill_common::connect_unix_socket(&config.socket)?,
+        )?),
+        #[cfg(windows)]
+        None => {
+            use tokio::net::windows::named_pipe::ClientOptions;
+            let pipe = krill_common::pipe_name(&config.socket);
+            Box::new(ClientOptions::new().open(pipe)?)
+        }
+    };
+    Ok((stream, None))
+}
+
+/// How long to wait for the daemon's reply to `hello`
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Announce our protocol version and wait for the daemon to accept it,
+/// returning the negotiated version. Daemons predating the handshake never
+/// reply, so a timeout is not fatal.
+async fn handshake<R, W>(reader: &mut R, writer: &mut W) -> Result<Option<ProtocolVersion>>
+where
+    R: AsyncBufReadExt + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let hello = ClientMessage::Hello {
+        protocol_version: ProtocolVersion::CURRENT,
+        client: Some(format!("krill-tui {}", env!("CARGO_PKG_VERSION"))),
+    };
+    writer
+        .write_all(format!("{}\n", serde_json::to_string(&hello)?).as_bytes())
+        .await?;
+
+    let reply = async {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                bail!("Daemon closed the connection during handshake");
+            }
+
+            // Broadcast events may arrive before the reply; the snapshot requested later covers them
+            match serde_json::from_str::<ServerMessage>(line.trim()) {
+                Ok(ServerMessage::Welcome { protocol_version }) => return Ok(protocol_version),
+                Ok(ServerMessage::Error {
+                    message,
+                    code: Some(ERROR_INCOMPATIBLE_PROTOCOL),
+                }) => bail!("{}", message),
+                _ => {}
+            }
+        }
+    };
+
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, reply).await {
+        Ok(Ok(version)) => {
+            info!("Connected to daemon with protocol {}", version);
+            Ok(Some(version))
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => {
+            warn!("Daemon did not answer hello; it may predate protocol versioning");
+            Ok(None)
+        }
+    }
 }
 
 /// Run the TUI application
@@ -33,13 +182,27 @@ pub async fn run(config: TuiConfig) -> Result<()> {
     info!("Starting krill-tui");
 
     // Connect to daemon
-    let stream = UnixStream::connect(&config.socket)
+    let (stream, mut tunnel_stderr) = connect(&config)
         .await
         .context("Failed to connect to daemon. Is krill-daemon running?")?;
 
     let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
 
+    let protocol_version = match handshake(&mut reader, &mut writer).await {
+        Ok(version) => version,
+        Err(e) => {
+            let e = e.context("Protocol handshake with daemon failed");
+            return Err(match tunnel_stderr.as_mut() {
+                Some(stderr) => match stderr.output(TUNNEL_EXIT_WAIT).await {
+                    Some(output) => e.context(output),
+                    None => e,
+                },
+                None => e,
+            });
+        }
+    };
+
     // Create channels
     let (message_tx, mut message_rx) = mpsc::unbounded_channel::<ClientMessage>();
     let (server_tx, mut server_rx) = mpsc::unbounded_channel::<ServerMessage>();
@@ -81,21 +244,25 @@ pub async fn run(config: TuiConfig) -> Result<()> {
         }
     });
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let mut terminal = setup_terminal()?;
 
     // Create app
     let mut app = App::new(message_tx);
+    if let Some(path) = prefs::Preferences::default_path() {
+        app.use_preferences(path);
+    }
 
-    // Subscribe to events
-    let _ = app.request_snapshot();
+    // Subscribe to events; daemons since protocol 1.1 send snapshot deltas
+    let deltas = ProtocolVersion { major: 1, minor: 1 };
+    if protocol_version.is_some_and(|version| version >= deltas) {
+        let _ = app.subscribe_snapshots();
+    } else {
+        let _ = app.request_snapshot();
+    }
     let subscribe_msg = ClientMessage::Subscribe {
         events: true,
         logs: None,
+        raw_logs: false,
     };
     if app.message_tx.send(subscribe_msg).is_err() {
         error!("Failed to subscribe to events");
@@ -104,7 +271,32 @@ pub async fn run(config: TuiConfig) -> Result<()> {
     // Main loop
     let result = run_app(&mut terminal, &mut app, &mut server_rx).await;
 
-    // Restore terminal
+    restore_terminal(&mut terminal)?;
+
+    if let Some(output) = match tunnel_stderr.as_mut() {
+        Some(stderr) => stderr.output(std::time::Duration::ZERO).await,
+        None => None,
+    } {
+        eprintln!("{}", output);
+    }
+    if let Err(err) = result {
+        eprintln!("Error: {:?}", err);
+    }
+
+    Ok(())
+}
+
+/// Switch the terminal to raw mode on the alternate screen
+fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    Ok(Terminal::new(backend)?)
+}
+
+/// Give the terminal back to the shell
+fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -112,11 +304,6 @@ pub async fn run(config: TuiConfig) -> Result<()> {
         DisableMouseCapture
     )?;
     terminal.show_cursor()?;
-
-    if let Err(err) = result {
-        eprintln!("Error: {:?}", err);
-    }
-
     Ok(())
 }
 
@@ -210,13 +397,24 @@ async fn run_app(
 }
 
 fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool> {
-    // Handle confirmation dialog
+    // Handle confirmation dialog; protected services need their phrase typed
+    if app.show_confirmation && app.confirmation_phrase.is_some() {
+        match key.code {
+            KeyCode::Enter => app.confirm()?,
+            KeyCode::Esc => app.cancel_confirmation(),
+            KeyCode::Backspace => app.confirmation_pop(),
+            KeyCode::Char(c) => app.confirmation_push(c),
+            _ => {}
+        }
+        return Ok(true);
+    }
     if app.show_confirmation {
         match key.code {
             KeyCode::Char('y') | KeyCode::Char('Y') => {
-                app.cancel_confirmation();
-                app.confirm_stop_daemon()?;
-                return Ok(false); // Quit
+                app.confirm()?;
+                if app.should_quit {
+                    return Ok(false); // Quit
+                }
             }
             KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
                 app.cancel_confirmation();
@@ -226,58 +424,67 @@ fn handle_input(app: &mut App, key: KeyEvent) -> Result<bool> {
         return Ok(true);
     }
 
-    // Handle different views
-    match &app.current_view {
-        app::View::List => match key.code {
-            KeyCode::Char('q') => return Ok(false),
-            KeyCode::Up | KeyCode::Char('k') => app.move_up(),
-            KeyCode::Down | KeyCode::Char('j') => app.move_down(),
-            KeyCode::Enter => app.enter_logs(),
-            KeyCode::Char('d') => app.enter_detail(),
-            KeyCode::Char('r') => app.restart_selected()?,
-            KeyCode::Char('s') => app.stop_selected()?,
-            KeyCode::Char('S') => app.show_stop_daemon_confirmation(),
-            _ => {}
-        },
-        app::View::Logs(_) => match key.code {
-            KeyCode::Char('q') => return Ok(false),
-            KeyCode::Esc => app.back_to_list(),
-            // Single line scrolling
-            KeyCode::Up | KeyCode::Char('k') => app.scroll_logs_up(1),
-            KeyCode::Down | KeyCode::Char('j') => app.scroll_logs_down(1),
-            // Page scrolling
-            KeyCode::PageUp => app.scroll_logs_up(20),
-            KeyCode::PageDown => app.scroll_logs_down(20),
-            // Half-page scrolling (vim style)
-            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                app.scroll_logs_up(10)
-            }
-            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                app.scroll_logs_down(10)
-            }
-            // Fast scrolling (5 lines)
-            KeyCode::Char('K') => app.scroll_logs_up(5),
-            KeyCode::Char('J') => app.scroll_logs_down(5),
-            // Jump to top/bottom
-            KeyCode::Char('g') => {
-                if key.modifiers.contains(KeyModifiers::SHIFT) {
-                    app.scroll_logs_to_bottom();
-                } else {
-                    app.scroll_logs_to_top();
-                }
-            }
-            KeyCode::Char('G') => app.scroll_logs_to_bottom(),
-            KeyCode::Home => app.scroll_logs_to_top(),
-            KeyCode::End => app.scroll_logs_to_bottom(),
-            // Toggle auto-scroll
-            KeyCode::Char('f') | KeyCode::Char('F') => app.toggle_auto_scroll(),
-            _ => {}
-        },
-        app::View::Detail(_) => match key.code {
-            KeyCode::Char('q') => return Ok(false),
-            KeyCode::Esc => app.back_to_list(),
+    // Handle the search box
+    if app.search_input.is_some() {
+        match key.code {
+            KeyCode::Enter => app.apply_search(),
+            KeyCode::Esc => app.cancel_search(),
+            KeyCode::Backspace => app.search_pop(),
+            KeyCode::Char(c) => app.search_push(c),
             _ => {}
-        },
+        }
+        return Ok(true);
+    }
+
+    // Any key closes the help overlay
+    if app.show_help {
+        app.show_help = false;
+        return Ok(true);
+    }
+
+    let Some(action) = keymap::action(&app.current_view, &key) else {
+        return Ok(true);
+    };
+    let in_list = app.current_view == app::View::List;
+    let in_tree = app.current_view == app::View::Tree;
+    match action {
+        Action::Quit => return Ok(false),
+        Action::Help => app.show_help = true,
+        Action::Back if app.has_search() => app.clear_search(),
+        Action::Back if in_list => app.clear_marks(),
+        Action::Back => app.back_to_list(),
+        Action::MoveUp if in_tree => app.tree_move_up(),
+        Action::MoveUp => app.move_up(),
+        Action::MoveDown if in_tree => app.tree_move_down(),
+        Action::MoveDown => app.move_down(),
+        Action::OpenLogs => app.enter_logs(),
+        Action::Describe if in_tree => app.enter_tree_detail(),
+        Action::Describe => app.enter_detail(),
+        Action::OpenTree => app.enter_tree(),
+        Action::AllLogs => app.enter_all_logs(),
+        Action::Mark => app.toggle_mark(),
+        Action::Search => app.open_search(),
+        Action::NextMatch => app.next_match(),
+        Action::PreviousMatch => app.previous_match(),
+        Action::Restart => app.restart_selected()?,
+        Action::Reload => app.reload_selected()?,
+        Action::StartStop => app.start_or_stop_selected()?,
+        Action::Start => app.start_selected()?,
+        Action::Kill => app.show_kill_confirmation(),
+        Action::StopDaemon => app.show_stop_daemon_confirmation(),
+        Action::EmergencyStop => app.show_emergency_stop_confirmation(),
+        Action::CycleLevel => app.cycle_log_level(),
+        Action::ScrollUp(lines) => app.scroll_logs_up(lines),
+        Action::ScrollDown(lines) => app.scroll_logs_down(lines),
+        Action::ScrollTop => app.scroll_logs_to_top(),
+        Action::ScrollBottom => app.scroll_logs_to_bottom(),
+        Action::Follow => app.toggle_auto_scroll(),
+        Action::Timestamps => app.toggle_log_timestamps(),
+        Action::Wrap => app.toggle_log_wrap(),
+        Action::Stream => app.toggle_log_stream(),
+        Action::PanLeft => app.scroll_logs_left(),
+        Action::PanRight => app.scroll_logs_right(),
+        Action::ToggleNode => app.toggle_tree_node(),
     }
 
     Ok(true)
@@ -9,9 +9,17 @@ use tracing_subscriber::{fmt, EnvFilter};
 #[command(name = "krill-tui")]
 #[command(about = "Krill Terminal UI", long_about = None)]
 struct Args {
-    /// IPC socket path
-    #[arg(long, default_value = "/tmp/krill.sock")]
-    socket: PathBuf,
+    /// IPC socket path, or @name for an abstract socket
+    #[arg(long, conflicts_with = "workspace")]
+    socket: Option<PathBuf>,
+
+    /// Attach to the daemon running this workspace
+    #[arg(short, long, value_name = "NAME")]
+    workspace: Option<String>,
+
+    /// Connect to a daemon over TCP instead (e.g. 192.168.1.10:7777)
+    #[arg(long, value_name = "HOST:PORT")]
+    connect: Option<String>,
 
     /// Verbose logging
     #[arg(short, long)]
@@ -36,9 +44,19 @@ async fn main() -> Result<()> {
         .with_writer(io::stderr)
         .init();
 
+    let socket = match (args.socket, args.workspace) {
+        (Some(socket), _) => socket,
+        (None, Some(name)) => krill_common::workspace_socket_path(&name)?,
+        (None, None) => std::env::var_os(krill_common::SOCKET_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(krill_common::LEGACY_SOCKET_PATH)),
+    };
+
     // Run TUI
     let config = krill_tui::TuiConfig {
-        socket: args.socket,
+        socket,
+        connect: args.connect,
+        tunnel: None,
     };
 
     krill_tui::run(config).await
@@ -0,0 +1,47 @@
+// TUI Preferences - Display options kept across sessions
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the logs view shows lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogDisplay {
+    /// Prefix lines with the time the daemon captured them
+    pub timestamps: bool,
+    /// Wrap long lines instead of scrolling horizontally
+    pub wrap: bool,
+    /// Mark whether a line came from stdout or stderr
+    pub stream: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub logs: LogDisplay,
+}
+
+impl Preferences {
+    /// `~/.krill/tui.json`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".krill").join("tui.json"))
+    }
+
+    /// Preferences saved at `path`, or the defaults when there are none
+    /// or they cannot be read
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
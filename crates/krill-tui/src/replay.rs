@@ -0,0 +1,492 @@
+// Replay - Run the TUI against a recorded event journal
+
+use crate::app::{App, View};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use krill_common::{EventRecord, LogLevel, ServerMessage};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+
+/// Journal of status changes inside a log session directory
+const EVENTS_FILE: &str = "events.jsonl";
+
+/// Journal of log lines inside a log session directory
+const TIMELINE_FILE: &str = "timeline.jsonl";
+
+/// Distance of a short seek (`[`/`]`)
+const SHORT_SEEK_SECS: i64 = 10;
+
+/// Distance of a long seek (`{`/`}`)
+const LONG_SEEK_SECS: i64 = 60;
+
+/// How often the clock advances while playing
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the key reader waits for input before checking whether the
+/// player is still there
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 64.0;
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    /// A log session directory, or a single `events.jsonl`/`timeline.jsonl`
+    pub path: PathBuf,
+    /// Initial playback speed, 1.0 being real time
+    pub speed: f64,
+}
+
+/// A line of a recorded journal: a status change or a line of output
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JournalLine {
+    Event(EventRecord),
+    Log {
+        timestamp: DateTime<Utc>,
+        service: String,
+        level: LogLevel,
+        message: String,
+    },
+}
+
+impl JournalLine {
+    fn into_entry(self) -> JournalEntry {
+        match self {
+            JournalLine::Event(event) => JournalEntry {
+                timestamp: event.timestamp,
+                message: ServerMessage::StatusUpdate {
+                    service: event.service,
+                    status: event.status,
+                },
+            },
+            JournalLine::Log {
+                timestamp,
+                service,
+                level,
+                message,
+            } => JournalEntry {
+                timestamp,
+                message: ServerMessage::LogLine {
+                    service,
+                    line: message,
+                    level: Some(level),
+                    timestamp: Some(timestamp),
+                    stream: None,
+                },
+            },
+        }
+    }
+}
+
+/// A recorded message and when the daemon sent it
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    pub message: ServerMessage,
+}
+
+/// Recorded status changes and log lines, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Load the event and log journals of a session directory, or a
+    /// single journal file
+    pub fn load(path: &Path) -> Result<Self> {
+        let files = if path.is_dir() {
+            let files: Vec<PathBuf> = [EVENTS_FILE, TIMELINE_FILE]
+                .iter()
+                .map(|name| path.join(name))
+                .filter(|file| file.exists())
+                .collect();
+            if files.is_empty() {
+                bail!(
+                    "{:?} has neither {} nor {}; is it a log session directory?",
+                    path,
+                    EVENTS_FILE,
+                    TIMELINE_FILE
+                );
+            }
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut journal = Self::default();
+        for file in files {
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {:?}", file))?;
+            journal.extend(&text);
+        }
+        if journal.entries.is_empty() {
+            bail!("No events or log lines in {:?}", path);
+        }
+        Ok(journal)
+    }
+
+    /// Add the entries of JSON lines, skipping lines that are neither events
+    /// nor log lines (e.g. one cut short by a power loss)
+    pub fn extend(&mut self, text: &str) {
+        self.entries.extend(
+            text.lines()
+                .filter_map(|line| serde_json::from_str::<JournalLine>(line).ok())
+                .map(JournalLine::into_entry),
+        );
+        // Stable, so lines of the same instant keep their recorded order
+        self.entries.sort_by_key(|entry| entry.timestamp);
+    }
+}
+
+/// Where playback is, shown in the TUI header
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayStatus {
+    pub clock: DateTime<Utc>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub playing: bool,
+    pub speed: f64,
+    /// Entries applied so far, out of `total`
+    pub position: usize,
+    pub total: usize,
+}
+
+/// Plays a journal back on a clock that can be paused, sped up and moved
+pub struct Player {
+    journal: Journal,
+    /// Index of the next entry to apply
+    position: usize,
+    clock: DateTime<Utc>,
+    playing: bool,
+    speed: f64,
+}
+
+impl Player {
+    /// Player at the first entry, playing
+    pub fn new(journal: Journal, speed: f64) -> Self {
+        let clock = journal
+            .entries
+            .first()
+            .map(|entry| entry.timestamp)
+            .unwrap_or_else(Utc::now);
+        Self {
+            journal,
+            position: 0,
+            clock,
+            playing: true,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+        }
+    }
+
+    fn start(&self) -> DateTime<Utc> {
+        self.journal
+            .entries
+            .first()
+            .map(|entry| entry.timestamp)
+            .unwrap_or(self.clock)
+    }
+
+    fn end(&self) -> DateTime<Utc> {
+        self.journal
+            .entries
+            .last()
+            .map(|entry| entry.timestamp)
+            .unwrap_or(self.clock)
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            clock: self.clock,
+            start: self.start(),
+            end: self.end(),
+            playing: self.playing,
+            speed: self.speed,
+            position: self.position,
+            total: self.journal.entries.len(),
+        }
+    }
+
+    /// Move the clock by `elapsed` wall time, scaled by the speed; playback
+    /// pauses at the end of the journal
+    pub fn advance(&mut self, elapsed: std::time::Duration) {
+        if !self.playing {
+            return;
+        }
+        let step = ChronoDuration::from_std(elapsed.mul_f64(self.speed))
+            .unwrap_or_else(|_| ChronoDuration::zero());
+        self.clock = (self.clock + step).min(self.end());
+        if self.clock >= self.end() {
+            self.playing = false;
+        }
+    }
+
+    /// Messages recorded up to the clock that were not applied yet
+    pub fn due(&mut self) -> Vec<ServerMessage> {
+        let due = self.journal.entries[self.position..]
+            .iter()
+            .take_while(|entry| entry.timestamp <= self.clock)
+            .map(|entry| entry.message.clone())
+            .collect::<Vec<_>>();
+        self.position += due.len();
+        due
+    }
+
+    /// Move the clock by `offset`, within the journal. Returns true when it
+    /// moved backwards, so the state built so far must be discarded and the
+    /// journal applied again from its start.
+    pub fn seek(&mut self, offset: ChronoDuration) -> bool {
+        let clock = (self.clock + offset).clamp(self.start(), self.end());
+        let rewound = clock < self.clock;
+        self.clock = clock;
+        if rewound {
+            self.position = 0;
+        }
+        rewound
+    }
+
+    /// Pause or resume. Returns true when playing from the end started over,
+    /// which, like seeking backwards, needs the state built so far discarded.
+    pub fn toggle_playing(&mut self) -> bool {
+        self.playing = !self.playing;
+        if self.playing && self.clock >= self.end() {
+            self.clock = self.start();
+            self.position = 0;
+            return true;
+        }
+        false
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+}
+
+/// Run the TUI against a recorded journal instead of a daemon
+pub async fn run(config: ReplayConfig) -> Result<()> {
+    let journal = Journal::load(&config.path)?;
+    info!(
+        "Replaying {} entries from {:?}",
+        journal.entries.len(),
+        config.path
+    );
+
+    // Nothing reads the requests; keeping the receiver lets the app send them
+    let (message_tx, _message_rx) = mpsc::unbounded_channel();
+    let mut app = App::new(message_tx);
+    if let Some(path) = crate::prefs::Preferences::default_path() {
+        app.use_preferences(path);
+    }
+    let mut player = Player::new(journal, config.speed);
+
+    let mut terminal = crate::setup_terminal()?;
+    let result = run_player(&mut terminal, &mut app, &mut player).await;
+    crate::restore_terminal(&mut terminal)?;
+
+    if let Err(err) = result {
+        eprintln!("Error: {:?}", err);
+    }
+
+    Ok(())
+}
+
+async fn run_player(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    player: &mut Player,
+) -> Result<()> {
+    let mut keys = read_keys();
+    let mut tick_interval = tokio::time::interval(TICK_INTERVAL);
+    tick_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_tick = Instant::now();
+
+    loop {
+        for message in player.due() {
+            app.handle_server_message(message);
+        }
+        let status = player.status();
+        let playing = status.playing;
+        app.replay = Some(status);
+        terminal.draw(|f| crate::ui::render(f, app))?;
+
+        // Paused, nothing changes until a key is pressed
+        tokio::select! {
+            key = keys.recv() => {
+                let Some(key) = key else {
+                    break;
+                };
+                if !handle_replay_input(app, player, key?)? {
+                    break;
+                }
+                if !playing {
+                    last_tick = Instant::now();
+                }
+            }
+
+            _ = tick_interval.tick(), if playing => {
+                let now = Instant::now();
+                player.advance(now - last_tick);
+                last_tick = now;
+            }
+        }
+
+        if app.should_quit {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Key presses, read on a blocking thread so the player can wait for them.
+/// The thread ends shortly after the receiver is dropped.
+fn read_keys() -> mpsc::UnboundedReceiver<io::Result<KeyEvent>> {
+    let (key_tx, key_rx) = mpsc::unbounded_channel();
+    tokio::task::spawn_blocking(move || {
+        while !key_tx.is_closed() {
+            let key = match event::poll(KEY_POLL_INTERVAL) {
+                Ok(false) => continue,
+                Ok(true) => match event::read() {
+                    Ok(Event::Key(key)) => Ok(key),
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            let failed = key.is_err();
+            if key_tx.send(key).is_err() || failed {
+                break;
+            }
+        }
+    });
+    key_rx
+}
+
+/// Playback controls, then the TUI's own keys minus the ones controlling
+/// services, which a recording has none of
+fn handle_replay_input(app: &mut App, player: &mut Player, key: KeyEvent) -> Result<bool> {
+    if app.search_input.is_none() {
+        let seek = match key.code {
+            KeyCode::Char('p') => {
+                if player.toggle_playing() {
+                    app.reset();
+                }
+                return Ok(true);
+            }
+            KeyCode::Char('>') => {
+                player.faster();
+                return Ok(true);
+            }
+            KeyCode::Char('<') => {
+                player.slower();
+                return Ok(true);
+            }
+            KeyCode::Char('[') => -SHORT_SEEK_SECS,
+            KeyCode::Char(']') => SHORT_SEEK_SECS,
+            KeyCode::Char('{') => -LONG_SEEK_SECS,
+            KeyCode::Char('}') => LONG_SEEK_SECS,
+            KeyCode::Char('r' | 'R' | 's' | 'u' | 'x' | 'S' | 'E' | ' ')
+                if app.current_view == View::List =>
+            {
+                return Ok(true)
+            }
+            _ => return crate::handle_input(app, key),
+        };
+        if player.seek(ChronoDuration::seconds(seek)) {
+            app.reset();
+        }
+        return Ok(true);
+    }
+
+    crate::handle_input(app, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use krill_common::ServiceStatus;
+
+    const JOURNAL: &str = r#"{"timestamp":"2026-10-16T08:00:00Z","service":"lidar","status":"starting"}
+{"timestamp":"2026-10-16T08:00:02Z","service":"lidar","level":"error","message":"no device"}
+not json
+{"timestamp":"2026-10-16T08:00:01Z","service":"lidar","status":"running"}
+{"timestamp":"2026-10-16T08:00:03Z","service":"lidar","status":"failed"}
+"#;
+
+    fn journal() -> Journal {
+        let mut journal = Journal::default();
+        journal.extend(JOURNAL);
+        journal
+    }
+
+    #[test]
+    fn test_journal_sorts_events_and_log_lines() {
+        let journal = journal();
+        assert_eq!(journal.entries.len(), 4);
+        assert!(journal
+            .entries
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        assert!(matches!(
+            journal.entries[2].message,
+            ServerMessage::LogLine {
+                level: Some(LogLevel::Error),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_player_applies_entries_as_the_clock_advances() {
+        let mut player = Player::new(journal(), 1.0);
+        assert_eq!(player.due().len(), 1);
+
+        player.advance(std::time::Duration::from_millis(1500));
+        assert!(matches!(
+            player.due()[..],
+            [ServerMessage::StatusUpdate {
+                status: ServiceStatus::Running,
+                ..
+            }]
+        ));
+
+        player.toggle_playing();
+        player.advance(std::time::Duration::from_secs(10));
+        assert!(player.due().is_empty());
+
+        player.toggle_playing();
+        player.advance(std::time::Duration::from_secs(10));
+        assert_eq!(player.due().len(), 2);
+        assert!(!player.status().playing);
+        assert_eq!(player.status().position, 4);
+
+        assert!(player.toggle_playing());
+        assert_eq!(player.due().len(), 1);
+    }
+
+    #[test]
+    fn test_seeking_backwards_replays_from_the_start() {
+        let mut player = Player::new(journal(), 4.0);
+        assert!(!player.seek(ChronoDuration::seconds(60)));
+        assert_eq!(player.due().len(), 4);
+
+        assert!(player.seek(ChronoDuration::seconds(-2)));
+        assert_eq!(
+            player.status().clock,
+            player.status().start + ChronoDuration::seconds(1)
+        );
+        assert_eq!(player.due().len(), 2);
+    }
+}
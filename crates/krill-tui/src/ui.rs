@@ -1,12 +1,15 @@
 // TUI Rendering
 
-use crate::app::{App, View};
-use krill_common::ServiceStatus;
+use crate::app::{App, LogEntry, SearchPattern, View};
+use crate::keymap;
+use crate::prefs::LogDisplay;
+use chrono::Local;
+use krill_common::{LogLevel, LogStream, ServiceMetrics, ServiceStatus};
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
@@ -18,6 +21,8 @@ const SELECTED_FG: Color = Color::White;
 const TABLE_HEADER_FG: Color = Color::Rgb(100, 150, 200);
 const BORDER_COLOR: Color = Color::Rgb(60, 70, 90);
 const DIM_FG: Color = Color::Rgb(120, 120, 120);
+const MATCH_BG: Color = Color::Rgb(220, 180, 50);
+const MATCH_FG: Color = Color::Black;
 
 // Status colors
 const STATUS_HEALTHY: Color = Color::Rgb(80, 200, 120);
@@ -26,12 +31,29 @@ const STATUS_STARTING: Color = Color::Rgb(100, 180, 220);
 const STATUS_DEGRADED: Color = Color::Rgb(200, 100, 200);
 const STATUS_STOPPED: Color = Color::Rgb(100, 100, 100);
 const STATUS_FAILED: Color = Color::Rgb(220, 80, 80);
+const STATUS_PAUSED: Color = Color::Rgb(120, 140, 220);
+
+// Service name colors in the all-services logs view
+const SERVICE_COLORS: [Color; 6] = [
+    Color::Rgb(100, 180, 220),
+    Color::Rgb(80, 200, 120),
+    Color::Rgb(200, 100, 200),
+    Color::Rgb(220, 140, 60),
+    Color::Rgb(100, 150, 200),
+    Color::Rgb(180, 200, 80),
+];
 
 pub fn render(frame: &mut Frame, app: &App) {
     match &app.current_view {
         View::List => render_list_view(frame, app),
-        View::Logs(service) => render_logs_view(frame, app, service),
+        View::Logs(service) => render_logs_view(frame, app, Some(service)),
+        View::AllLogs => render_logs_view(frame, app, None),
         View::Detail(service) => render_detail_view(frame, app, service),
+        View::Tree => render_tree_view(frame, app),
+    }
+
+    if app.show_help {
+        render_help(frame, app);
     }
 
     // Render confirmation dialog if shown
@@ -56,8 +78,12 @@ fn render_list_view(frame: &mut Frame, app: &App) {
     // Service list
     render_service_list(frame, app, chunks[1]);
 
-    // Footer with keybindings (compact)
-    render_footer(frame, chunks[2]);
+    // Footer with keybindings (compact), or the search box while typing
+    match app.search_input {
+        Some(ref input) => render_search_box(frame, input, chunks[2]),
+        None if app.replay.is_some() => render_replay_footer(frame, chunks[2]),
+        None => render_footer(frame, chunks[2]),
+    }
 }
 
 fn render_header(frame: &mut Frame, app: &App, area: Rect) {
@@ -113,14 +139,66 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
         STATUS_HEALTHY
     };
 
-    // Line 1: Krill branding
-    let line1 = Line::from(vec![Span::styled(
+    // Line 1: Krill branding, and the playback position of a replay
+    let mut line1 = Line::from(vec![Span::styled(
         " Krill",
         Style::default()
             .fg(Color::Black)
             .bg(STATUS_HEALTHY)
             .add_modifier(Modifier::BOLD),
     )]);
+    if let Some(ref replay) = app.replay {
+        line1.spans.push(Span::raw(" "));
+        line1.spans.push(Span::styled(
+            if replay.playing {
+                " ▶ REPLAY "
+            } else {
+                " ⏸ REPLAY "
+            },
+            Style::default()
+                .fg(Color::Black)
+                .bg(STATUS_STARTING)
+                .add_modifier(Modifier::BOLD),
+        ));
+        line1.spans.push(Span::styled(
+            format!(
+                " {} ",
+                replay
+                    .clock
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            ),
+            Style::default().fg(HEADER_FG).add_modifier(Modifier::BOLD),
+        ));
+        let elapsed = (replay.clock - replay.start).to_std().unwrap_or_default();
+        let length = (replay.end - replay.start).to_std().unwrap_or_default();
+        line1.spans.push(Span::styled(
+            format!(
+                "{} / {} │ {}x │ {}/{} events",
+                format_duration(elapsed),
+                format_duration(length),
+                replay.speed,
+                replay.position,
+                replay.total
+            ),
+            Style::default().fg(DIM_FG),
+        ));
+    }
+
+    if app.services.values().any(|s| s.restarts_paused) {
+        line1.spans.push(Span::raw(" "));
+        line1.spans.push(Span::styled(
+            " RESTARTS PAUSED ",
+            Style::default()
+                .fg(Color::Black)
+                .bg(STATUS_FAILED)
+                .add_modifier(Modifier::BOLD),
+        ));
+        line1.spans.push(Span::styled(
+            " restart storm, resume with 'krill resume'",
+            Style::default().fg(DIM_FG),
+        ));
+    }
 
     // Line 2: Recipe and services
     let line2 = Line::from(vec![
@@ -146,6 +224,23 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
             Style::default().fg(STATUS_STOPPED),
         ),
     ]);
+    let line2 = match app.service_filter {
+        Some(ref filter) => {
+            let mut spans = line2.spans;
+            spans.push(Span::styled(" │ ", Style::default().fg(DIM_FG)));
+            spans.push(Span::styled("filter: ", Style::default().fg(DIM_FG)));
+            spans.push(Span::styled(
+                format!("/{}", filter.text),
+                Style::default().fg(MATCH_BG),
+            ));
+            spans.push(Span::styled(
+                format!(" ({} shown)", app.service_list.len()),
+                Style::default().fg(DIM_FG),
+            ));
+            Line::from(spans)
+        }
+        None => line2,
+    };
 
     // Line 3: CPU, Memory, Disk
     let line3 = Line::from(vec![
@@ -169,7 +264,13 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
         ),
     ]);
 
-    let header = Paragraph::new(vec![line1, line2, line3])
+    // A replay has no live machine to measure
+    let lines = if app.replay.is_some() {
+        vec![line1, line2]
+    } else {
+        vec![line1, line2, line3]
+    };
+    let header = Paragraph::new(lines)
         .style(Style::default().bg(HEADER_BG))
         .block(
             Block::default()
@@ -192,7 +293,8 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
         Span::styled(format!("{:<22}", "NAMESPACE (UID)"), header_style),
         Span::styled(format!("{:<10}", "UPTIME"), header_style),
         Span::styled(format!("{:<10}", "EXECUTOR"), header_style),
-        Span::styled(format!("{:<8}", "RESTARTS"), header_style),
+        Span::styled(format!("{:<10}", "RESTARTS"), header_style),
+        Span::styled(format!("{:<20}", "RESOURCES"), header_style),
     ]);
     items.push(ListItem::new(header));
 
@@ -200,17 +302,10 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
     for (i, name) in app.service_list.iter().enumerate() {
         let service = app.services.get(name).unwrap();
 
-        let (status_symbol, status_color) = match service.status {
-            ServiceStatus::Healthy => ("●", STATUS_HEALTHY),
-            ServiceStatus::Running => ("●", STATUS_RUNNING),
-            ServiceStatus::Degraded => ("◐", STATUS_DEGRADED),
-            ServiceStatus::Starting => ("◐", STATUS_STARTING),
-            ServiceStatus::Stopping => ("◌", STATUS_STOPPED),
-            ServiceStatus::Stopped => ("○", STATUS_STOPPED),
-            ServiceStatus::Failed => ("✗", STATUS_FAILED),
-        };
+        let (status_symbol, status_color) = status_style(&service.status);
 
         let is_selected = i == app.selected_index;
+        let is_marked = app.marked.contains(name);
         let row_style = if is_selected {
             Style::default().bg(SELECTED_BG).fg(SELECTED_FG)
         } else {
@@ -238,15 +333,21 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
             "-".to_string()
         };
 
-        let mut spans = vec![
-            Span::styled(
-                format!(" {:<20}", name),
-                row_style.add_modifier(if is_selected {
-                    Modifier::BOLD
-                } else {
-                    Modifier::empty()
-                }),
-            ),
+        let mut spans = vec![Span::styled(
+            if is_marked { "▶" } else { " " },
+            row_style.fg(STATUS_RUNNING),
+        )];
+        let name_style = row_style.add_modifier(if is_selected {
+            Modifier::BOLD
+        } else {
+            Modifier::empty()
+        });
+        spans.extend(highlight(name, app.service_filter.as_ref(), name_style));
+        spans.push(Span::styled(
+            " ".repeat(20usize.saturating_sub(name.chars().count())),
+            name_style,
+        ));
+        spans.extend([
             Span::styled(format!("{} ", status_symbol), row_style.fg(status_color)),
             Span::styled(format!("{:<10}", status_text), row_style.fg(status_color)),
             Span::styled(
@@ -262,7 +363,7 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
                 }),
             ),
             Span::styled(
-                format!("{:<10}", service.executor_type),
+                format!("{:<10}", service.config.executor),
                 row_style.fg(if is_selected {
                     Color::LightCyan
                 } else {
@@ -270,13 +371,24 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
                 }),
             ),
             Span::styled(
-                format!("{:<8}", service.restart_count),
+                format!("{:<10}", service.restart_count),
                 row_style.fg(if is_selected { SELECTED_FG } else { DIM_FG }),
             ),
-        ];
+            Span::styled(
+                format!("{:<20}", format_resources(service.metrics.as_ref())),
+                row_style.fg(if is_selected {
+                    SELECTED_FG
+                } else {
+                    Color::LightBlue
+                }),
+            ),
+        ]);
 
-        // Append error snippet for failed services
-        if service.status == ServiceStatus::Failed {
+        // Append error snippet for failed and blocked services
+        if matches!(
+            service.status,
+            ServiceStatus::Failed | ServiceStatus::Blocked
+        ) {
             if let Some(ref error) = service.last_error {
                 let truncated: String = error.chars().take(60).collect();
                 spans.push(Span::styled(truncated, row_style.fg(STATUS_FAILED)));
@@ -295,6 +407,96 @@ fn render_service_list(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(list, area);
 }
 
+/// Symbol and color showing a service status
+fn status_style(status: &ServiceStatus) -> (&'static str, Color) {
+    match status {
+        ServiceStatus::Healthy => ("●", STATUS_HEALTHY),
+        ServiceStatus::Running => ("●", STATUS_RUNNING),
+        ServiceStatus::Degraded => ("◐", STATUS_DEGRADED),
+        ServiceStatus::Starting => ("◐", STATUS_STARTING),
+        ServiceStatus::Stopping => ("◌", STATUS_STOPPED),
+        ServiceStatus::Stopped => ("○", STATUS_STOPPED),
+        ServiceStatus::Failed => ("✗", STATUS_FAILED),
+        ServiceStatus::Completed => ("✓", STATUS_HEALTHY),
+        ServiceStatus::Blocked => ("⊘", STATUS_DEGRADED),
+        ServiceStatus::Paused => ("⏸", STATUS_PAUSED),
+    }
+}
+
+/// Format a duration for the detail view, e.g. `3m 12s` or `2d 4h 10m`
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    } else {
+        format!(
+            "{}d {}h {}m",
+            secs / 86400,
+            (secs % 86400) / 3600,
+            (secs % 3600) / 60
+        )
+    }
+}
+
+/// Format per-service usage as `CPU% RSS THREADS`, e.g. `12.5% 64M 4t`
+fn format_resources(metrics: Option<&ServiceMetrics>) -> String {
+    let Some(metrics) = metrics else {
+        return "-".to_string();
+    };
+
+    let mb = metrics.memory_bytes as f64 / (1024.0 * 1024.0);
+    let memory = if mb >= 1024.0 {
+        format!("{:.1}G", mb / 1024.0)
+    } else {
+        format!("{:.0}M", mb)
+    };
+
+    format!(
+        "{:.1}% {} {}t",
+        metrics.cpu_percent, memory, metrics.threads
+    )
+}
+
+/// Split `text` into spans with the matches of `pattern` highlighted
+fn highlight<'a>(text: &'a str, pattern: Option<&SearchPattern>, style: Style) -> Vec<Span<'a>> {
+    let Some(pattern) = pattern else {
+        return vec![Span::styled(text, style)];
+    };
+
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for range in pattern.matches(text) {
+        if range.start > last {
+            spans.push(Span::styled(&text[last..range.start], style));
+        }
+        spans.push(Span::styled(
+            &text[range.clone()],
+            style.bg(MATCH_BG).fg(MATCH_FG),
+        ));
+        last = range.end;
+    }
+    if last < text.len() || spans.is_empty() {
+        spans.push(Span::styled(&text[last..], style));
+    }
+    spans
+}
+
+/// One-line input shown in place of the footer while typing a search
+fn render_search_box(frame: &mut Frame, input: &str, area: Rect) {
+    let search = Paragraph::new(Line::from(vec![
+        Span::styled(" /", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled(input, Style::default().fg(HEADER_FG)),
+        Span::styled("█", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("  <enter>Apply <esc>Clear", Style::default().fg(DIM_FG)),
+    ]))
+    .style(Style::default().bg(HEADER_BG));
+    frame.render_widget(search, area);
+}
+
 fn render_footer(frame: &mut Frame, area: Rect) {
     let footer = Paragraph::new(Line::from(vec![
         Span::styled(" <↑↓>", Style::default().fg(STATUS_HEALTHY)),
@@ -303,10 +505,50 @@ fn render_footer(frame: &mut Frame, area: Rect) {
         Span::styled("Logs ", Style::default().fg(DIM_FG)),
         Span::styled("<d>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Describe ", Style::default().fg(DIM_FG)),
+        Span::styled("<t>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Tree ", Style::default().fg(DIM_FG)),
+        Span::styled("<a>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("All logs ", Style::default().fg(DIM_FG)),
+        Span::styled("<space>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Mark ", Style::default().fg(DIM_FG)),
+        Span::styled("</>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Filter ", Style::default().fg(DIM_FG)),
         Span::styled("<r>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Restart ", Style::default().fg(DIM_FG)),
+        Span::styled("<R>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Reload ", Style::default().fg(DIM_FG)),
         Span::styled("<s>", Style::default().fg(STATUS_HEALTHY)),
-        Span::styled("Stop ", Style::default().fg(DIM_FG)),
+        Span::styled("Start/Stop ", Style::default().fg(DIM_FG)),
+        Span::styled("<x>", Style::default().fg(STATUS_FAILED)),
+        Span::styled("Kill ", Style::default().fg(DIM_FG)),
+        Span::styled("<E>", Style::default().fg(STATUS_FAILED)),
+        Span::styled("E-Stop ", Style::default().fg(DIM_FG)),
+        Span::styled("<?>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Help ", Style::default().fg(DIM_FG)),
+        Span::styled("<q>", Style::default().fg(STATUS_FAILED)),
+        Span::styled("Quit ", Style::default().fg(DIM_FG)),
+    ]))
+    .style(Style::default().bg(HEADER_BG));
+    frame.render_widget(footer, area);
+}
+
+/// Footer of a replay: playback controls instead of service actions
+fn render_replay_footer(frame: &mut Frame, area: Rect) {
+    let footer = Paragraph::new(Line::from(vec![
+        Span::styled(" <↑↓>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Navigate ", Style::default().fg(DIM_FG)),
+        Span::styled("<enter>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Logs ", Style::default().fg(DIM_FG)),
+        Span::styled("<d>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Describe ", Style::default().fg(DIM_FG)),
+        Span::styled("<p>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Play/Pause ", Style::default().fg(DIM_FG)),
+        Span::styled("<[]>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Seek 10s ", Style::default().fg(DIM_FG)),
+        Span::styled("<{}>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Seek 1m ", Style::default().fg(DIM_FG)),
+        Span::styled("<<>>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Speed ", Style::default().fg(DIM_FG)),
         Span::styled("<q>", Style::default().fg(STATUS_FAILED)),
         Span::styled("Quit ", Style::default().fg(DIM_FG)),
     ]))
@@ -314,7 +556,8 @@ fn render_footer(frame: &mut Frame, area: Rect) {
     frame.render_widget(footer, area);
 }
 
-fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
+/// Logs of one service, or of all listed services when `service` is `None`
+fn render_logs_view(frame: &mut Frame, app: &App, service: Option<&str>) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -345,6 +588,25 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
         String::new()
     };
 
+    let matches = app.log_matches();
+    let search_info = match app.log_search {
+        Some(ref pattern) => format!(" /{} ({} matches)", pattern.text, matches.len()),
+        None => String::new(),
+    };
+
+    let level_info = match app.log_level {
+        Some(level) => format!(" [{:?}+]", level).to_uppercase(),
+        None => String::new(),
+    };
+
+    let display_info = if app.log_display.wrap {
+        " [WRAP]".to_string()
+    } else if app.log_hscroll > 0 {
+        format!(" [→{}]", app.log_hscroll)
+    } else {
+        String::new()
+    };
+
     let auto_scroll_indicator = if app.auto_scroll {
         Span::styled(
             " [FOLLOW]",
@@ -372,11 +634,14 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
         Span::raw(" "),
         Span::styled("Logs: ", Style::default().fg(DIM_FG)),
         Span::styled(
-            service,
+            service.unwrap_or("all services"),
             Style::default().fg(HEADER_FG).add_modifier(Modifier::BOLD),
         ),
         Span::styled(scroll_info, Style::default().fg(DIM_FG)),
         auto_scroll_indicator,
+        Span::styled(level_info, Style::default().fg(STATUS_RUNNING)),
+        Span::styled(display_info, Style::default().fg(DIM_FG)),
+        Span::styled(search_info, Style::default().fg(MATCH_BG)),
     ]))
     .style(Style::default().bg(HEADER_BG))
     .block(
@@ -404,29 +669,42 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
             Style::default().fg(DIM_FG),
         ))]
     } else {
-        let end_idx = total_logs.saturating_sub(app.log_scroll);
-        let start_idx = end_idx.saturating_sub(visible_height);
-
-        logs[start_idx..end_idx]
-            .iter()
-            .enumerate()
-            .map(|(i, line)| {
-                // Add line numbers for easier reference
-                let line_num = start_idx + i + 1;
-                // Color code based on content
-                let style = if line.contains("ERROR") || line.contains("error") {
-                    Style::default().fg(STATUS_FAILED)
-                } else if line.contains("WARN") || line.contains("warn") {
-                    Style::default().fg(STATUS_RUNNING)
-                } else {
-                    Style::default().fg(HEADER_FG)
-                };
-                Line::from(vec![
-                    Span::styled(format!("{:4} ", line_num), Style::default().fg(DIM_FG)),
-                    Span::styled(line.as_str(), style),
-                ])
-            })
-            .collect()
+        // Take lines from the bottom until the area is full; wrapped lines
+        // take several rows
+        let width = logs_chunks[0].width as usize;
+        // Service names line up in a column in the all-services view
+        let name_width = match service {
+            Some(_) => None,
+            None => logs.iter().map(|(name, _)| name.chars().count()).max(),
+        };
+        let mut rows = Vec::new();
+        let mut index = total_logs.saturating_sub(app.log_scroll);
+        while index > 0 && rows.len() < visible_height {
+            index -= 1;
+            let (name, entry) = logs[index];
+            // Color code based on the parsed level
+            let style = match entry.level {
+                Some(LogLevel::Error) => Style::default().fg(STATUS_FAILED),
+                Some(LogLevel::Warn) => Style::default().fg(STATUS_RUNNING),
+                Some(LogLevel::Debug) | Some(LogLevel::Trace) => Style::default().fg(DIM_FG),
+                _ => Style::default().fg(HEADER_FG),
+            };
+            // Line numbers for easier reference
+            let mut prefix = log_prefix(entry, index + 1, app.log_display);
+            if let Some(name_width) = name_width {
+                prefix.push(Span::styled(
+                    format!("{:<width$} ", name, width = name_width),
+                    Style::default().fg(service_color(name)),
+                ));
+            }
+            let message = highlight(&entry.line, app.log_search.as_ref(), style);
+            rows.splice(
+                0..0,
+                log_rows(prefix, &message, width, app.log_display, app.log_hscroll),
+            );
+        }
+        let hidden = rows.len().saturating_sub(visible_height);
+        rows.split_off(hidden)
     };
 
     let logs_widget = Paragraph::new(log_lines);
@@ -441,7 +719,11 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
         app.log_scroll,
     );
 
-    // Footer with scroll keybindings
+    // Footer with scroll keybindings, or the search box while typing
+    if let Some(ref input) = app.search_input {
+        render_search_box(frame, input, chunks[2]);
+        return;
+    }
     let footer = Paragraph::new(Line::from(vec![
         Span::styled(" <j/k>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Scroll ", Style::default().fg(DIM_FG)),
@@ -451,8 +733,22 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
         Span::styled("Top/Bot ", Style::default().fg(DIM_FG)),
         Span::styled("<f>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Follow ", Style::default().fg(DIM_FG)),
+        Span::styled("</>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Search ", Style::default().fg(DIM_FG)),
+        Span::styled("<n/N>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Prev/Next ", Style::default().fg(DIM_FG)),
+        Span::styled("<l>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Level ", Style::default().fg(DIM_FG)),
+        Span::styled("<t/o>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Time/Stream ", Style::default().fg(DIM_FG)),
+        Span::styled("<w>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Wrap ", Style::default().fg(DIM_FG)),
+        Span::styled("<←/→>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Pan ", Style::default().fg(DIM_FG)),
         Span::styled("<esc>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Back ", Style::default().fg(DIM_FG)),
+        Span::styled("<?>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Help ", Style::default().fg(DIM_FG)),
         Span::styled("<q>", Style::default().fg(STATUS_FAILED)),
         Span::styled("Quit", Style::default().fg(DIM_FG)),
     ]))
@@ -460,6 +756,96 @@ fn render_logs_view(frame: &mut Frame, app: &App, service: &str) {
     frame.render_widget(footer, chunks[2]);
 }
 
+/// Color of a service's name, the same on every run
+fn service_color(service: &str) -> Color {
+    let hash = service.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte.into())
+    });
+    SERVICE_COLORS[hash % SERVICE_COLORS.len()]
+}
+
+/// Number of a log line, followed by its local time and stream when shown.
+/// History lines carry neither and get blank columns.
+fn log_prefix(entry: &LogEntry, line_num: usize, display: LogDisplay) -> Vec<Span<'static>> {
+    let dim = Style::default().fg(DIM_FG);
+    let mut spans = vec![Span::styled(format!("{:4} ", line_num), dim)];
+    if display.timestamps {
+        let time = match entry.timestamp {
+            Some(timestamp) => timestamp
+                .with_timezone(&Local)
+                .format("%H:%M:%S%.3f ")
+                .to_string(),
+            None => " ".repeat(13),
+        };
+        spans.push(Span::styled(time, dim));
+    }
+    if display.stream {
+        spans.push(match entry.stream {
+            Some(LogStream::Stdout) => Span::styled("out ", dim),
+            Some(LogStream::Stderr) => Span::styled("err ", Style::default().fg(STATUS_FAILED)),
+            None => Span::raw("    "),
+        });
+    }
+    spans
+}
+
+/// Screen rows of a log line `width` columns wide: the message cut after
+/// `hscroll` columns, or wrapped and indented below the prefix
+fn log_rows<'a>(
+    prefix: Vec<Span<'a>>,
+    message: &[Span<'a>],
+    width: usize,
+    display: LogDisplay,
+    hscroll: usize,
+) -> Vec<Line<'a>> {
+    let prefix_width: usize = prefix.iter().map(|span| span.content.chars().count()).sum();
+    let text_width = width.saturating_sub(prefix_width).max(1);
+    if !display.wrap {
+        let mut spans = prefix;
+        spans.extend(slice_spans(message, hscroll, text_width));
+        return vec![Line::from(spans)];
+    }
+
+    let length: usize = message
+        .iter()
+        .map(|span| span.content.chars().count())
+        .sum();
+    let mut prefix = Some(prefix);
+    let mut rows = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut spans = prefix
+            .take()
+            .unwrap_or_else(|| vec![Span::raw(" ".repeat(prefix_width))]);
+        spans.extend(slice_spans(message, start, text_width));
+        rows.push(Line::from(spans));
+        start += text_width;
+        if start >= length {
+            return rows;
+        }
+    }
+}
+
+/// Up to `width` characters of `spans`, starting `skip` characters in
+fn slice_spans<'a>(spans: &[Span<'a>], mut skip: usize, mut width: usize) -> Vec<Span<'a>> {
+    let mut sliced = Vec::new();
+    for span in spans {
+        if width == 0 {
+            break;
+        }
+        let length = span.content.chars().count();
+        if skip >= length {
+            skip -= length;
+            continue;
+        }
+        let text: String = span.content.chars().skip(skip).take(width).collect();
+        width -= text.chars().count();
+        skip = 0;
+        sliced.push(Span::styled(text, span.style));
+    }
+    sliced
+}
+
 /// Render a visual scroll bar
 fn render_scroll_bar(
     frame: &mut Frame,
@@ -484,6 +870,7 @@ fn render_scroll_bar(
     // Calculate thumb position and size
     let thumb_size = ((visible_lines * height) / total_lines).max(1).min(height);
     let position = scrollable.saturating_sub(scroll_offset);
+    #[allow(clippy::manual_checked_ops)]
     let thumb_pos = if scrollable > 0 {
         ((position * (height - thumb_size)) / scrollable).min(height - thumb_size)
     } else {
@@ -546,15 +933,7 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
     // Details
     let mut details = vec![];
     if let Some(svc) = app.services.get(service) {
-        let (status_symbol, status_color) = match svc.status {
-            ServiceStatus::Healthy => ("●", STATUS_HEALTHY),
-            ServiceStatus::Running => ("●", STATUS_RUNNING),
-            ServiceStatus::Degraded => ("◐", STATUS_DEGRADED),
-            ServiceStatus::Starting => ("◐", STATUS_STARTING),
-            ServiceStatus::Stopping => ("◌", STATUS_STOPPED),
-            ServiceStatus::Stopped => ("○", STATUS_STOPPED),
-            ServiceStatus::Failed => ("✗", STATUS_FAILED),
-        };
+        let (status_symbol, status_color) = status_style(&svc.status);
 
         // Basic info section
         details.push(Line::from(Span::styled(
@@ -573,7 +952,7 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
         ]));
         details.push(Line::from(vec![
             Span::styled("Executor:     ", Style::default().fg(TABLE_HEADER_FG)),
-            Span::styled(&svc.executor_type, Style::default().fg(Color::Cyan)),
+            Span::styled(&svc.config.executor, Style::default().fg(Color::Cyan)),
         ]));
         details.push(Line::from(vec![
             Span::styled("Status:       ", Style::default().fg(TABLE_HEADER_FG)),
@@ -592,30 +971,21 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
             ),
         ]));
 
-        // Uptime
-        let uptime_str = if let Some(uptime) = svc.uptime {
-            let secs = uptime.as_secs();
-            if secs < 60 {
-                format!("{}s", secs)
-            } else if secs < 3600 {
-                format!("{}m {}s", secs / 60, secs % 60)
-            } else if secs < 86400 {
-                format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
-            } else {
-                format!(
-                    "{}d {}h {}m",
-                    secs / 86400,
-                    (secs % 86400) / 3600,
-                    (secs % 3600) / 60
-                )
-            }
-        } else {
-            "N/A".to_string()
-        };
         details.push(Line::from(vec![
             Span::styled("Uptime:       ", Style::default().fg(TABLE_HEADER_FG)),
-            Span::styled(uptime_str, Style::default().fg(Color::LightBlue)),
+            Span::styled(
+                svc.uptime
+                    .map(format_duration)
+                    .unwrap_or_else(|| "N/A".to_string()),
+                Style::default().fg(Color::LightBlue),
+            ),
         ]));
+        if let Some(age) = svc.state_age {
+            details.push(Line::from(vec![
+                Span::styled("In State:     ", Style::default().fg(TABLE_HEADER_FG)),
+                Span::styled(format_duration(age), Style::default().fg(Color::LightBlue)),
+            ]));
+        }
 
         details.push(Line::from(""));
 
@@ -626,16 +996,20 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
                 .fg(TABLE_HEADER_FG)
                 .add_modifier(Modifier::BOLD),
         )));
-        if svc.dependencies.is_empty() {
+        if svc.config.dependencies.is_empty() {
             details.push(Line::from(Span::styled(
                 "None",
                 Style::default().fg(DIM_FG),
             )));
         } else {
-            for dep in &svc.dependencies {
+            for dep in &svc.config.dependencies {
                 details.push(Line::from(vec![
                     Span::styled("  • ", Style::default().fg(STATUS_HEALTHY)),
-                    Span::styled(dep, Style::default().fg(HEADER_FG)),
+                    Span::styled(&dep.service, Style::default().fg(HEADER_FG)),
+                    Span::styled(
+                        format!("  ({:?})", dep.condition).to_lowercase(),
+                        Style::default().fg(DIM_FG),
+                    ),
                 ]));
             }
         }
@@ -652,15 +1026,34 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
         details.push(Line::from(vec![
             Span::styled("GPU Required: ", Style::default().fg(TABLE_HEADER_FG)),
             Span::styled(
-                if svc.uses_gpu { "Yes" } else { "No" },
-                Style::default().fg(if svc.uses_gpu { STATUS_RUNNING } else { DIM_FG }),
+                if svc.config.uses_gpu { "Yes" } else { "No" },
+                Style::default().fg(if svc.config.uses_gpu {
+                    STATUS_RUNNING
+                } else {
+                    DIM_FG
+                }),
             ),
         ]));
         details.push(Line::from(vec![
             Span::styled("Critical:     ", Style::default().fg(TABLE_HEADER_FG)),
             Span::styled(
-                if svc.critical { "Yes" } else { "No" },
-                Style::default().fg(if svc.critical { STATUS_FAILED } else { DIM_FG }),
+                if svc.config.critical { "Yes" } else { "No" },
+                Style::default().fg(if svc.config.critical {
+                    STATUS_FAILED
+                } else {
+                    DIM_FG
+                }),
+            ),
+        ]));
+        details.push(Line::from(vec![
+            Span::styled("Protected:    ", Style::default().fg(TABLE_HEADER_FG)),
+            Span::styled(
+                if svc.protected { "Yes" } else { "No" },
+                Style::default().fg(if svc.protected {
+                    STATUS_DEGRADED
+                } else {
+                    DIM_FG
+                }),
             ),
         ]));
 
@@ -675,15 +1068,18 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
         )));
         details.push(Line::from(vec![
             Span::styled("Policy:       ", Style::default().fg(TABLE_HEADER_FG)),
-            Span::styled(&svc.restart_policy, Style::default().fg(Color::Cyan)),
+            Span::styled(
+                format!("{:?}", svc.config.restart_policy),
+                Style::default().fg(Color::Cyan),
+            ),
         ]));
         details.push(Line::from(vec![
             Span::styled("Max Restarts: ", Style::default().fg(TABLE_HEADER_FG)),
             Span::styled(
-                if svc.max_restarts == 0 {
+                if svc.config.max_restarts == 0 {
                     "Unlimited".to_string()
                 } else {
-                    svc.max_restarts.to_string()
+                    svc.config.max_restarts.to_string()
                 },
                 Style::default().fg(HEADER_FG),
             ),
@@ -699,6 +1095,117 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
                 }),
             ),
         ]));
+        details.push(Line::from(vec![
+            Span::styled("Total:        ", Style::default().fg(TABLE_HEADER_FG)),
+            Span::styled(
+                svc.total_restarts.to_string(),
+                Style::default().fg(HEADER_FG),
+            ),
+        ]));
+        if let Some(code) = svc.last_exit_code {
+            details.push(Line::from(vec![
+                Span::styled("Last Exit:    ", Style::default().fg(TABLE_HEADER_FG)),
+                Span::styled(
+                    code.to_string(),
+                    Style::default().fg(if code == 0 { HEADER_FG } else { STATUS_FAILED }),
+                ),
+            ]));
+        }
+
+        // Heartbeat section: missed beats and metadata, e.g. why the service
+        // reported degraded
+        if !svc.heartbeat_metadata.is_empty() || svc.missed_heartbeats > 0 {
+            details.push(Line::from(""));
+            details.push(Line::from(Span::styled(
+                "═══ Heartbeat ═══",
+                Style::default()
+                    .fg(TABLE_HEADER_FG)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            if svc.missed_heartbeats > 0 {
+                details.push(Line::from(vec![
+                    Span::styled("Missed:       ", Style::default().fg(TABLE_HEADER_FG)),
+                    Span::styled(
+                        svc.missed_heartbeats.to_string(),
+                        Style::default().fg(STATUS_RUNNING),
+                    ),
+                ]));
+            }
+            let mut metadata: Vec<_> = svc.heartbeat_metadata.iter().collect();
+            metadata.sort();
+            for (key, value) in metadata {
+                details.push(Line::from(vec![
+                    Span::styled(
+                        format!("{:<14}", format!("{}:", key)),
+                        Style::default().fg(TABLE_HEADER_FG),
+                    ),
+                    Span::styled(value, Style::default().fg(HEADER_FG)),
+                ]));
+            }
+        }
+
+        // Maintenance section: scheduled actions, soonest first
+        if !svc.maintenance.is_empty() {
+            details.push(Line::from(""));
+            details.push(Line::from(Span::styled(
+                "═══ Maintenance ═══",
+                Style::default()
+                    .fg(TABLE_HEADER_FG)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            for planned in &svc.maintenance {
+                details.push(Line::from(vec![
+                    Span::styled(
+                        planned
+                            .at
+                            .with_timezone(&Local)
+                            .format("  %Y-%m-%d %H:%M  ")
+                            .to_string(),
+                        Style::default().fg(DIM_FG),
+                    ),
+                    Span::styled(
+                        format!("{:?}", planned.action).to_lowercase(),
+                        Style::default().fg(HEADER_FG),
+                    ),
+                    Span::styled(
+                        planned
+                            .cron
+                            .as_ref()
+                            .map(|cron| format!("  ({})", cron))
+                            .unwrap_or_default(),
+                        Style::default().fg(DIM_FG),
+                    ),
+                ]));
+            }
+        }
+
+        // Timeline section: recent status changes, newest first
+        if !svc.state_history.is_empty() {
+            details.push(Line::from(""));
+            details.push(Line::from(Span::styled(
+                "═══ Timeline ═══",
+                Style::default()
+                    .fg(TABLE_HEADER_FG)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            for transition in svc.state_history.iter().rev() {
+                let (symbol, color) = status_style(&transition.status);
+                details.push(Line::from(vec![
+                    Span::styled(
+                        transition
+                            .timestamp
+                            .with_timezone(&Local)
+                            .format("  %H:%M:%S  ")
+                            .to_string(),
+                        Style::default().fg(DIM_FG),
+                    ),
+                    Span::styled(
+                        format!("{} {:?}", symbol, transition.status),
+                        Style::default().fg(color),
+                    ),
+                ]));
+            }
+        }
 
         // Last error section
         if let Some(ref error) = svc.last_error {
@@ -725,6 +1232,91 @@ fn render_detail_view(frame: &mut Frame, app: &App, service: &str) {
     let footer = Paragraph::new(Line::from(vec![
         Span::styled(" <esc>", Style::default().fg(STATUS_HEALTHY)),
         Span::styled("Back ", Style::default().fg(DIM_FG)),
+        Span::styled("<?>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Help ", Style::default().fg(DIM_FG)),
+        Span::styled("<q>", Style::default().fg(STATUS_FAILED)),
+        Span::styled("Quit ", Style::default().fg(DIM_FG)),
+    ]))
+    .style(Style::default().bg(HEADER_BG));
+    frame.render_widget(footer, chunks[2]);
+}
+
+fn render_tree_view(frame: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4), // Header
+            Constraint::Min(0),    // Tree
+            Constraint::Length(1), // Footer
+        ])
+        .split(frame.area());
+
+    render_header(frame, app, chunks[0]);
+
+    let rows = app.tree_rows();
+    let selected = app.tree_index.min(rows.len().saturating_sub(1));
+    let mut items = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let Some(service) = app.services.get(&row.service) else {
+            continue;
+        };
+        let (status_symbol, status_color) = status_style(&service.status);
+        let row_style = if i == selected {
+            Style::default().bg(SELECTED_BG).fg(SELECTED_FG)
+        } else {
+            Style::default()
+        };
+
+        let marker = match (row.has_children, row.collapsed) {
+            (false, _) => "  ",
+            (true, false) => "▾ ",
+            (true, true) => "▸ ",
+        };
+        let mut spans = vec![
+            Span::styled(
+                format!(" {}{}", "  ".repeat(row.depth), marker),
+                row_style.fg(DIM_FG),
+            ),
+            Span::styled(format!("{} ", status_symbol), row_style.fg(status_color)),
+            Span::styled(
+                row.service.as_str(),
+                row_style.fg(status_color).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(format!(" {:?}", service.status), row_style.fg(status_color)),
+        ];
+        if let Some(condition) = row.condition {
+            spans.push(Span::styled(
+                format!("  (needs {:?})", condition).to_lowercase(),
+                row_style.fg(DIM_FG),
+            ));
+        }
+        if matches!(
+            service.status,
+            ServiceStatus::Failed | ServiceStatus::Blocked
+        ) {
+            if let Some(ref error) = service.last_error {
+                let truncated: String = error.chars().take(60).collect();
+                spans.push(Span::styled(
+                    format!("  {}", truncated),
+                    row_style.fg(STATUS_FAILED),
+                ));
+            }
+        }
+        items.push(ListItem::new(Line::from(spans)).style(row_style));
+    }
+    frame.render_widget(List::new(items), chunks[1]);
+
+    let footer = Paragraph::new(Line::from(vec![
+        Span::styled(" <↑↓>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Navigate ", Style::default().fg(DIM_FG)),
+        Span::styled("<enter>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Collapse/Expand ", Style::default().fg(DIM_FG)),
+        Span::styled("<d>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Describe ", Style::default().fg(DIM_FG)),
+        Span::styled("<esc>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Back ", Style::default().fg(DIM_FG)),
+        Span::styled("<?>", Style::default().fg(STATUS_HEALTHY)),
+        Span::styled("Help ", Style::default().fg(DIM_FG)),
         Span::styled("<q>", Style::default().fg(STATUS_FAILED)),
         Span::styled("Quit ", Style::default().fg(DIM_FG)),
     ]))
@@ -750,6 +1342,22 @@ fn render_confirmation(frame: &mut Frame, app: &App) {
                 .add_modifier(Modifier::BOLD),
         ));
 
+    let answer = match app.confirmation_phrase {
+        Some(_) => Line::from(vec![
+            Span::styled("> ", Style::default().fg(DIM_FG)),
+            Span::styled(&app.confirmation_input, Style::default().fg(HEADER_FG)),
+            Span::styled("_  ", Style::default().fg(DIM_FG)),
+            Span::styled("<esc>", Style::default().fg(STATUS_FAILED)),
+            Span::styled(" cancel", Style::default().fg(DIM_FG)),
+        ]),
+        None => Line::from(vec![
+            Span::styled("<y>", Style::default().fg(STATUS_HEALTHY)),
+            Span::styled("es  ", Style::default().fg(DIM_FG)),
+            Span::styled("<n>", Style::default().fg(STATUS_FAILED)),
+            Span::styled("o", Style::default().fg(DIM_FG)),
+        ]),
+    };
+
     let text = Paragraph::new(vec![
         Line::from(""),
         Line::from(Span::styled(
@@ -757,19 +1365,70 @@ fn render_confirmation(frame: &mut Frame, app: &App) {
             Style::default().fg(HEADER_FG),
         )),
         Line::from(""),
-        Line::from(vec![
-            Span::styled("<y>", Style::default().fg(STATUS_HEALTHY)),
-            Span::styled("es  ", Style::default().fg(DIM_FG)),
-            Span::styled("<n>", Style::default().fg(STATUS_FAILED)),
-            Span::styled("o", Style::default().fg(DIM_FG)),
-        ]),
+        answer,
     ])
     .block(block)
+    .wrap(Wrap { trim: true })
     .alignment(Alignment::Center);
 
     frame.render_widget(text, area);
 }
 
+/// Every key of the current view, from the keymap the input handling uses
+fn render_help(frame: &mut Frame, app: &App) {
+    let area = centered_rect(60, 80, frame.area());
+
+    // Clear background
+    let clear = Block::default().style(Style::default().bg(Color::Black));
+    frame.render_widget(clear, area);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(BORDER_COLOR))
+        .style(Style::default().bg(HEADER_BG))
+        .title(Span::styled(
+            format!(" Keys: {} ", keymap::title(&app.current_view)),
+            Style::default()
+                .fg(TABLE_HEADER_FG)
+                .add_modifier(Modifier::BOLD),
+        ));
+
+    let bindings = keymap::bindings(&app.current_view);
+    let labels: Vec<String> = bindings
+        .iter()
+        .map(|binding| {
+            binding
+                .keys
+                .iter()
+                .map(|key| key.label())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect();
+    let width = labels.iter().map(|label| label.chars().count()).max();
+
+    let mut lines: Vec<Line> = bindings
+        .iter()
+        .zip(&labels)
+        .map(|(binding, label)| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {:<width$}  ", label, width = width.unwrap_or(0)),
+                    Style::default().fg(STATUS_HEALTHY),
+                ),
+                Span::styled(binding.description, Style::default().fg(HEADER_FG)),
+            ])
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        " Press any key to close",
+        Style::default().fg(DIM_FG),
+    )));
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -0,0 +1,230 @@
+// These tests connect the TUI's app state to the mock daemon and render it
+// to a test backend, so screens can be checked without a terminal or daemon
+
+use chrono::{Duration, Utc};
+use krill_common::{ClientMessage, CommandAction, ServerMessage, ServiceStatus};
+use krill_testkit::MockDaemon;
+use krill_tui::{ui, App};
+use ratatui::{backend::TestBackend, Terminal};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// The TUI's side of a connection to the mock daemon
+struct Session {
+    app: App,
+    outgoing: mpsc::UnboundedReceiver<ClientMessage>,
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Session {
+    async fn connect(mock: &MockDaemon) -> Self {
+        let stream = TcpStream::connect(mock.tcp_addr()).await.unwrap();
+        let (reader, writer) = stream.into_split();
+        let (message_tx, outgoing) = mpsc::unbounded_channel();
+        Self {
+            app: App::new(message_tx),
+            outgoing,
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    /// Send what the app queued for the daemon
+    async fn send(&mut self) {
+        while let Ok(message) = self.outgoing.try_recv() {
+            let json = serde_json::to_string(&message).unwrap();
+            self.writer
+                .write_all(format!("{}\n", json).as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Hand the daemon's next message to the app
+    async fn receive(&mut self) {
+        let line = self.lines.next_line().await.unwrap().unwrap();
+        let message: ServerMessage = serde_json::from_str(&line).unwrap();
+        self.app.handle_server_message(message);
+    }
+
+    fn render(&self) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| ui::render(frame, &self.app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn row(&self, service: &str) -> String {
+        self.render()
+            .into_iter()
+            .find(|row| row.contains(service))
+            .unwrap_or_else(|| panic!("'{}' is not on screen", service))
+    }
+}
+
+#[tokio::test]
+async fn test_service_list_follows_the_daemon() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Healthy);
+    mock.add_service("planner", ServiceStatus::Starting);
+
+    let mut session = Session::connect(&mock).await;
+    session.app.subscribe_snapshots().unwrap();
+    session.send().await;
+    session.receive().await;
+    assert!(session.row("lidar").contains('●'));
+    assert!(session.row("planner").contains('◐'));
+
+    mock.set_status("lidar", ServiceStatus::Failed);
+    session.receive().await;
+    assert!(session.row("lidar").contains('✗'));
+}
+
+#[tokio::test]
+async fn test_restart_reaches_the_daemon() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Healthy);
+
+    let mut session = Session::connect(&mock).await;
+    session.app.request_snapshot().unwrap();
+    session.send().await;
+    session.receive().await;
+
+    session.app.restart_selected().unwrap();
+    session.send().await;
+    session.receive().await;
+    match &mock.commands()[..] {
+        [ClientMessage::Command { action, target, .. }] => {
+            assert_eq!(*action, CommandAction::Restart);
+            assert_eq!(target.as_deref(), Some("lidar"));
+        }
+        other => panic!("expected one command, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_start_of_a_failed_service_is_confirmed() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Failed);
+
+    let mut session = Session::connect(&mock).await;
+    session.app.request_snapshot().unwrap();
+    session.send().await;
+    session.receive().await;
+
+    // Nothing is sent until the start is confirmed
+    session.app.start_or_stop_selected().unwrap();
+    assert!(session.app.show_confirmation);
+    assert!(session.app.confirmation_message.contains("'lidar'"));
+    assert!(session.outgoing.try_recv().is_err());
+
+    session.app.confirm().unwrap();
+    session.send().await;
+    session.receive().await;
+    match &mock.commands()[..] {
+        [ClientMessage::Command { action, target, .. }] => {
+            assert_eq!(*action, CommandAction::Start);
+            assert_eq!(target.as_deref(), Some("lidar"));
+        }
+        other => panic!("expected one command, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_stopped_service_starts_without_confirmation() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Stopped);
+
+    let mut session = Session::connect(&mock).await;
+    session.app.request_snapshot().unwrap();
+    session.send().await;
+    session.receive().await;
+
+    session.app.start_or_stop_selected().unwrap();
+    assert!(!session.app.show_confirmation);
+    session.send().await;
+    session.receive().await;
+    assert!(matches!(
+        &mock.commands()[..],
+        [ClientMessage::Command {
+            action: CommandAction::Start,
+            ..
+        }]
+    ));
+}
+
+#[tokio::test]
+async fn test_all_logs_interleave_services_by_time() {
+    let mock = MockDaemon::start().await.unwrap();
+    mock.add_service("lidar", ServiceStatus::Healthy);
+    mock.add_service("planner", ServiceStatus::Healthy);
+
+    let mut session = Session::connect(&mock).await;
+    session.app.request_snapshot().unwrap();
+    session.send().await;
+    session.receive().await;
+
+    session.app.enter_all_logs();
+    assert!(matches!(
+        session.outgoing.try_recv(),
+        Ok(ClientMessage::Subscribe { logs: None, .. })
+    ));
+
+    let start = Utc::now();
+    for (service, line, seconds) in [
+        ("planner", "planning route", 2),
+        ("lidar", "scan started", 1),
+        ("planner", "route blocked", 3),
+    ] {
+        mock.inject(ServerMessage::LogLine {
+            service: service.to_string(),
+            line: line.to_string(),
+            level: None,
+            timestamp: Some(start + Duration::seconds(seconds)),
+            stream: None,
+        });
+        session.receive().await;
+    }
+
+    let logs: Vec<(&str, &str)> = session
+        .app
+        .current_logs()
+        .into_iter()
+        .map(|(service, entry)| (service, entry.line.as_str()))
+        .collect();
+    assert_eq!(
+        logs,
+        [
+            ("lidar", "scan started"),
+            ("planner", "planning route"),
+            ("planner", "route blocked"),
+        ]
+    );
+    let screen = session.render();
+    assert!(screen[0].contains("Logs: all services"));
+    assert!(screen.iter().any(|row| row.contains("scan started")));
+    assert!(screen.iter().any(|row| row.contains("route blocked")));
+
+    // Search and scrolling work across services
+    session.app.open_search();
+    "planning".chars().for_each(|c| session.app.search_push(c));
+    session.app.apply_search();
+    assert_eq!(session.app.log_matches(), [1]);
+    assert_eq!(session.app.log_scroll, 1);
+    assert!(!session.app.auto_scroll);
+
+    session.app.scroll_logs_to_top();
+    assert_eq!(session.app.log_scroll, 2);
+    session.app.scroll_logs_to_bottom();
+    assert_eq!(session.app.log_scroll, 0);
+    assert!(session.app.auto_scroll);
+}
@@ -0,0 +1,33 @@
+// A TUI attached through a tunnel command (`krill ps --remote`) reports why
+// the command failed, since its stderr is kept off the screen
+
+#![cfg(unix)]
+
+use krill_tui::TuiConfig;
+use std::path::PathBuf;
+
+#[tokio::test]
+async fn test_failed_tunnel_reports_its_stderr() {
+    let config = TuiConfig {
+        socket: PathBuf::new(),
+        connect: None,
+        tunnel: Some(
+            [
+                "sh",
+                "-c",
+                "echo 'ssh: connect to host robot port 22: Connection refused' >&2; exit 255",
+            ]
+            .map(String::from)
+            .to_vec(),
+        ),
+    };
+
+    let error = krill_tui::run(config).await.unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(
+        message.contains("ssh: connect to host robot port 22: Connection refused"),
+        "{}",
+        message
+    );
+    assert!(message.contains("handshake"), "{}", message);
+}
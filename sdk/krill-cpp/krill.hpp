@@ -8,6 +8,8 @@
 #include <map>
 #include <stdexcept>
 #include <sstream>
+#include <cstddef>
+#include <cstdlib>
 #include <cstring>
 
 #ifdef _WIN32
@@ -29,11 +31,17 @@ public:
 class Client {
 public:
     explicit Client(const std::string& service_name,
-                   const std::string& socket_path = "/tmp/krill.sock")
+                   const std::string& socket_path = default_socket_path())
         : service_name_(service_name), socket_fd_(-1) {
         connect(socket_path);
     }
 
+    /// Socket exported by the daemon in KRILL_SOCKET, else /tmp/krill.sock
+    static std::string default_socket_path() {
+        const char* path = std::getenv("KRILL_SOCKET");
+        return path ? path : "/tmp/krill.sock";
+    }
+
     ~Client() {
         if (socket_fd_ >= 0) {
             close(socket_fd_);
@@ -85,9 +93,16 @@ private:
             throw KrillError("Socket path too long");
         }
         
-        std::strncpy(addr.sun_path, socket_path.c_str(), sizeof(addr.sun_path) - 1);
+        // "@name" is a Linux abstract socket: a leading NUL, no terminator
+        socklen_t addr_len = sizeof(addr);
+        if (socket_path.size() > 1 && socket_path[0] == '@') {
+            std::memcpy(addr.sun_path + 1, socket_path.data() + 1, socket_path.size() - 1);
+            addr_len = offsetof(struct sockaddr_un, sun_path) + socket_path.size();
+        } else {
+            std::strncpy(addr.sun_path, socket_path.c_str(), sizeof(addr.sun_path) - 1);
+        }
 
-        if (::connect(socket_fd_, (struct sockaddr*)&addr, sizeof(addr)) < 0) {
+        if (::connect(socket_fd_, (struct sockaddr*)&addr, addr_len) < 0) {
             close(socket_fd_);
             throw KrillError("Failed to connect to daemon: " + std::string(std::strerror(errno)));
         }
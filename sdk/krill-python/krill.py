@@ -4,7 +4,8 @@
 """
 Krill Python SDK
 
-Lightweight client for communicating with the Krill daemon over Unix sockets.
+Lightweight client for communicating with the Krill daemon over Unix sockets,
+or over TCP when the daemon is started with ``--listen``.
 Zero external dependencies - uses only the Python standard library.
 
 Supports both synchronous and asynchronous (asyncio) usage.
@@ -26,13 +27,22 @@ from __future__ import annotations
 
 import asyncio
 import json
+import os
 import socket
 import threading
-from typing import Dict, Optional
+from typing import Dict, Optional, Tuple
 
 __all__ = ["KrillClient", "AsyncKrillClient", "KrillError"]
 
-DEFAULT_SOCKET_PATH = "/tmp/krill.sock"
+# The daemon exports its socket to the services it launches
+DEFAULT_SOCKET_PATH = os.environ.get("KRILL_SOCKET", "/tmp/krill.sock")
+
+
+def _unix_address(socket_path: str) -> str:
+    """Address to connect to; ``@name`` is a Linux abstract socket."""
+    if socket_path.startswith("@") and len(socket_path) > 1:
+        return "\0" + socket_path[1:]
+    return socket_path
 
 
 class KrillError(Exception):
@@ -61,19 +71,34 @@ class KrillClient:
     Args:
         service_name: The name of the service this client represents.
         socket_path: Path to the Krill daemon Unix socket.
+        tcp_address: ``(host, port)`` of a daemon TCP listener. When set,
+            ``socket_path`` is ignored.
     """
 
     def __init__(
         self,
         service_name: str,
         socket_path: str = DEFAULT_SOCKET_PATH,
+        tcp_address: Optional[Tuple[str, int]] = None,
     ) -> None:
         self._service_name = service_name
         self._socket_path = socket_path
+        self._tcp_address = tcp_address
         self._lock = threading.Lock()
         self._sock: Optional[socket.socket] = None
         self._connect()
 
+    @classmethod
+    def connect_tcp(cls, service_name: str, host: str, port: int) -> KrillClient:
+        """Connect to a daemon TCP listener (``krill up --listen``)."""
+        return cls(service_name, tcp_address=(host, port))
+
+    def _endpoint(self) -> str:
+        if self._tcp_address is not None:
+            host, port = self._tcp_address
+            return f"{host}:{port}"
+        return self._socket_path
+
     def _connect(self) -> None:
         import time
 
@@ -82,9 +107,15 @@ class KrillClient:
 
         for attempt in range(max_retries):
             try:
-                sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+                if self._tcp_address is not None:
+                    sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+                    sock.setsockopt(socket.IPPROTO_TCP, socket.TCP_NODELAY, 1)
+                    target = self._tcp_address
+                else:
+                    sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+                    target = _unix_address(self._socket_path)
                 sock.settimeout(2.0)  # 2 second connection timeout
-                sock.connect(self._socket_path)
+                sock.connect(target)
                 sock.settimeout(None)  # Remove timeout after connection
                 self._sock = sock
                 return
@@ -92,13 +123,13 @@ class KrillClient:
                 if attempt == max_retries - 1:
                     # Last attempt failed
                     raise ConnectionError(
-                        f"Failed to connect to daemon at {self._socket_path} after {max_retries} attempts: {exc}"
+                        f"Failed to connect to daemon at {self._endpoint()} after {max_retries} attempts: {exc}"
                     ) from exc
                 # Retry after delay
                 time.sleep(retry_delay)
             except Exception as exc:
                 raise ConnectionError(
-                    f"Unexpected error connecting to daemon at {self._socket_path}: {exc}"
+                    f"Unexpected error connecting to daemon at {self._endpoint()}: {exc}"
                 ) from exc
 
     def heartbeat(self) -> None:
@@ -195,13 +226,35 @@ class AsyncKrillClient:
             ConnectionError: If the connection fails.
         """
         try:
-            reader, writer = await asyncio.open_unix_connection(socket_path)
+            reader, writer = await asyncio.open_unix_connection(
+                _unix_address(socket_path)
+            )
         except OSError as exc:
             raise ConnectionError(
                 f"Failed to connect to daemon at {socket_path}: {exc}"
             ) from exc
         return cls(service_name, reader, writer)
 
+    @classmethod
+    async def connect_tcp(
+        cls,
+        service_name: str,
+        host: str,
+        port: int,
+    ) -> AsyncKrillClient:
+        """Connect to a daemon TCP listener (``krill up --listen``).
+
+        Raises:
+            ConnectionError: If the connection fails.
+        """
+        try:
+            reader, writer = await asyncio.open_connection(host, port)
+        except OSError as exc:
+            raise ConnectionError(
+                f"Failed to connect to daemon at {host}:{port}: {exc}"
+            ) from exc
+        return cls(service_name, reader, writer)
+
     async def heartbeat(self) -> None:
         """Send a healthy heartbeat to the daemon."""
         await self._send_heartbeat("healthy", {})
@@ -159,6 +159,26 @@ class TestKrillClient(unittest.TestCase):
         lines = all_data.strip().split("\n")
         self.assertEqual(len(lines), 3)
 
+    def test_connect_tcp_heartbeat(self):
+        """Test that connect_tcp() sends heartbeats over TCP."""
+        server_sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+        server_sock.bind(("127.0.0.1", 0))
+        server_sock.listen(1)
+        port = server_sock.getsockname()[1]
+
+        client = krill.KrillClient.connect_tcp("tcp-service", "127.0.0.1", port)
+        conn, _ = server_sock.accept()
+        client.heartbeat()
+        client.close()
+
+        data = conn.recv(4096).decode("utf-8")
+        conn.close()
+        server_sock.close()
+
+        message = json.loads(data)
+        self.assertEqual(message["type"], "heartbeat")
+        self.assertEqual(message["service"], "tcp-service")
+
 
 class TestAsyncKrillClient(unittest.TestCase):
     """Tests for asynchronous AsyncKrillClient."""
@@ -290,6 +310,34 @@ class TestAsyncKrillClient(unittest.TestCase):
         asyncio.run(test())
 
 
+    def test_async_connect_tcp_heartbeat(self):
+        """Test async connect_tcp sends heartbeats over TCP."""
+
+        async def test():
+            received = []
+
+            async def handle_client(reader, writer):
+                received.append((await reader.readline()).decode("utf-8"))
+                writer.close()
+
+            server = await asyncio.start_server(handle_client, "127.0.0.1", 0)
+            port = server.sockets[0].getsockname()[1]
+
+            client = await krill.AsyncKrillClient.connect_tcp(
+                "tcp-async", "127.0.0.1", port
+            )
+            await client.heartbeat()
+            await asyncio.sleep(0.1)
+            await client.close()
+            server.close()
+            await server.wait_closed()
+
+            message = json.loads(received[0])
+            self.assertEqual(message["service"], "tcp-async")
+
+        asyncio.run(test())
+
+
 class TestErrorClasses(unittest.TestCase):
     """Test error classes."""
 